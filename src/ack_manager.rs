@@ -18,12 +18,17 @@
 use error::RoutingError;
 use maidsafe_utilities::serialisation;
 use message_filter::MessageFilter;
-use messages::RoutingMessage;
+use messages::{MessageContent, RoutingMessage};
+use payload_pool::{MIN_POOLED_PAYLOAD_LEN, PayloadPool};
+use routing_table::Authority;
 use sha3;
 use std::collections::BTreeMap;
 use std::fmt;
+use std::mem;
+use std::rc::Rc;
 use std::time::Duration;
 use tiny_keccak::sha3_256;
+use xor_name::XorName;
 
 /// Time (in seconds) after which a message is resent due to being unacknowledged by recipient.
 pub const ACK_TIMEOUT_SECS: u64 = 20;
@@ -31,16 +36,50 @@ pub const ACK_TIMEOUT_SECS: u64 = 20;
 const EXPIRY_DURATION_SECS: u64 = 4 * 60;
 
 /// A copy of a message which has been sent and is pending the ack from the recipient.
-#[derive(Clone, Debug)]
+///
+/// The payload of a large `UserMessagePart` is taken out of the message and shared via the
+/// manager's `PayloadPool` instead, so that identical parts sent to several recipients are only
+/// held once. It is put back by `into_routing_msg` when the message is resent.
+#[derive(Clone)]
 pub struct UnacknowledgedMessage {
-    pub routing_msg: RoutingMessage,
+    routing_msg: RoutingMessage,
+    payload: Option<Rc<Vec<u8>>>,
     pub route: u8,
     pub timer_token: u64,
 }
 
+impl UnacknowledgedMessage {
+    /// Returns the source authority of the message.
+    pub fn src(&self) -> &Authority<XorName> {
+        &self.routing_msg.src
+    }
+
+    /// Returns the complete message.
+    pub fn into_routing_msg(self) -> RoutingMessage {
+        let mut routing_msg = self.routing_msg;
+        if let Some(shared) = self.payload {
+            if let MessageContent::UserMessagePart { ref mut payload, .. } = routing_msg.content {
+                *payload = Rc::try_unwrap(shared).unwrap_or_else(|shared| (*shared).clone());
+            }
+        }
+        routing_msg
+    }
+}
+
+impl fmt::Debug for UnacknowledgedMessage {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter,
+               "UnacknowledgedMessage {{ routing_msg: {:?}, route: {}, timer_token: {} }}",
+               self.routing_msg,
+               self.route,
+               self.timer_token)
+    }
+}
+
 pub struct AckManager {
     pending: BTreeMap<Ack, UnacknowledgedMessage>,
     received: MessageFilter<Ack>,
+    payload_pool: PayloadPool,
 }
 
 /// An identifier for a waiting-to-be-acknowledged message (a hash of the message).
@@ -57,6 +96,7 @@ impl AckManager {
         AckManager {
             pending: BTreeMap::new(),
             received: MessageFilter::with_expiry_duration(expiry_duration),
+            payload_pool: PayloadPool::new(),
         }
     }
 
@@ -77,11 +117,48 @@ impl AckManager {
     /// this is removed and returned.
     pub fn add_to_pending(&mut self,
                           ack: Ack,
-                          unacked_msg: UnacknowledgedMessage)
+                          mut routing_msg: RoutingMessage,
+                          route: u8,
+                          timer_token: u64)
                           -> Option<UnacknowledgedMessage> {
+        let payload = match routing_msg.content {
+            MessageContent::UserMessagePart { ref mut payload, .. } => {
+                if payload.len() >= MIN_POOLED_PAYLOAD_LEN {
+                    Some(self.payload_pool.share(mem::replace(payload, Vec::new())))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        let unacked_msg = UnacknowledgedMessage {
+            routing_msg: routing_msg,
+            payload: payload,
+            route: route,
+            timer_token: timer_token,
+        };
         self.pending.insert(ack, unacked_msg)
     }
 
+    /// Returns the number of distinct payloads held by pending messages, and their total size in
+    /// bytes.
+    pub fn pooled_payloads(&self) -> (usize, usize) {
+        (self.payload_pool.payload_count(), self.payload_pool.stored_bytes())
+    }
+
+    /// Returns whether any pending message satisfies the given predicate.
+    pub fn has_pending<F>(&self, predicate: F) -> bool
+        where F: Fn(&UnacknowledgedMessage) -> bool
+    {
+        self.pending.values().any(predicate)
+    }
+
+    /// Returns the number of messages pending acknowledgement.
+    #[cfg(feature = "use-mock-crust")]
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
     // Find a timed out unacknowledged message corresponding to the given timer token.
     // If such message exists, returns it with the corresponding ack hash. Otherwise
     // returns None.
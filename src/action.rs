@@ -21,7 +21,11 @@ use messages::{Request, UserMessage};
 use messages::DirectMessage;
 use routing_table::Authority;
 use std::fmt::{self, Debug, Formatter};
+use stats::Diagnostics;
 use std::sync::mpsc::Sender;
+use std::time::Duration;
+use tunables::{ConnectionQuotas, EffectiveConfig, PartialConfig, ProxyStrategy};
+use types::MessageId;
 use xor_name::XorName;
 
 /// An Action initiates a message flow < A | B > where we are (a part of) A.
@@ -38,6 +42,13 @@ pub enum Action {
         dst: Authority<XorName>,
         content: UserMessage,
         priority: u8,
+        retry_safe: bool,
+        result_tx: Sender<Result<(), InterfaceError>>,
+    },
+    NodeSendBatch {
+        src: Authority<XorName>,
+        messages: Vec<(Authority<XorName>, UserMessage, u8)>,
+        batch_id: u64,
         result_tx: Sender<Result<(), InterfaceError>>,
     },
     ClientSendRequest {
@@ -46,12 +57,77 @@ pub enum Action {
         priority: u8,
         result_tx: Sender<Result<(), InterfaceError>>,
     },
+    CancelRequest {
+        msg_id: MessageId,
+        result_tx: Sender<Result<(), InterfaceError>>,
+    },
     Id { result_tx: Sender<PublicId> },
+    GetStats { result_tx: Sender<Diagnostics> },
+    DisconnectPeer {
+        name: XorName,
+        result_tx: Sender<Result<(), InterfaceError>>,
+    },
+    BanPeer {
+        name: XorName,
+        duration: Duration,
+        result_tx: Sender<Result<(), InterfaceError>>,
+    },
+    SetConnectionQuotas {
+        quotas: ConnectionQuotas,
+        result_tx: Sender<Result<(), InterfaceError>>,
+    },
+    SetProxyStrategy {
+        strategy: ProxyStrategy,
+        result_tx: Sender<Result<(), InterfaceError>>,
+    },
+    DumpDecisionLog { result_tx: Sender<Result<Vec<u8>, InterfaceError>> },
+    GetEffectiveConfig { result_tx: Sender<Result<EffectiveConfig, InterfaceError>> },
+    UpdateConfig {
+        update: PartialConfig,
+        result_tx: Sender<Result<(), InterfaceError>>,
+    },
+    PingPeer {
+        name: XorName,
+        reply_tx: Sender<Duration>,
+        result_tx: Sender<Result<(), InterfaceError>>,
+    },
     Timeout(u64),
     ResourceProofResult(PublicId, Vec<DirectMessage>),
     Terminate,
 }
 
+impl Action {
+    /// Answers the action with `InterfaceError::InvalidState`, as it can't be handled any more.
+    /// Actions without a result are dropped instead, which disconnects their receivers.
+    pub fn refuse(self) {
+        match self {
+            Action::NodeSendMessage { result_tx, .. } |
+            Action::NodeSendBatch { result_tx, .. } |
+            Action::ClientSendRequest { result_tx, .. } |
+            Action::CancelRequest { result_tx, .. } |
+            Action::DisconnectPeer { result_tx, .. } |
+            Action::BanPeer { result_tx, .. } |
+            Action::SetConnectionQuotas { result_tx, .. } |
+            Action::SetProxyStrategy { result_tx, .. } |
+            Action::UpdateConfig { result_tx, .. } |
+            Action::PingPeer { result_tx, .. } => {
+                let _ = result_tx.send(Err(InterfaceError::InvalidState));
+            }
+            Action::DumpDecisionLog { result_tx } => {
+                let _ = result_tx.send(Err(InterfaceError::InvalidState));
+            }
+            Action::GetEffectiveConfig { result_tx } => {
+                let _ = result_tx.send(Err(InterfaceError::InvalidState));
+            }
+            Action::Id { .. } |
+            Action::GetStats { .. } |
+            Action::Timeout(_) |
+            Action::ResourceProofResult(..) |
+            Action::Terminate => (),
+        }
+    }
+}
+
 impl Debug for Action {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         match *self {
@@ -60,6 +136,16 @@ impl Debug for Action {
                        "Action::NodeSendMessage {{ {:?}, result_tx }}",
                        content)
             }
+            Action::NodeSendBatch {
+                ref messages,
+                batch_id,
+                ..
+            } => {
+                write!(formatter,
+                       "Action::NodeSendBatch {{ id: {}, {} messages, result_tx }}",
+                       batch_id,
+                       messages.len())
+            }
             Action::ClientSendRequest {
                 ref content,
                 ref dst,
@@ -70,7 +156,31 @@ impl Debug for Action {
                        content,
                        dst)
             }
+            Action::CancelRequest { ref msg_id, .. } => {
+                write!(formatter, "Action::CancelRequest({:?})", msg_id)
+            }
             Action::Id { .. } => write!(formatter, "Action::Id"),
+            Action::GetStats { .. } => write!(formatter, "Action::GetStats"),
+            Action::DisconnectPeer { ref name, .. } => {
+                write!(formatter, "Action::DisconnectPeer({:?})", name)
+            }
+            Action::BanPeer {
+                ref name,
+                ref duration,
+                ..
+            } => write!(formatter, "Action::BanPeer({:?}, {:?})", name, duration),
+            Action::SetConnectionQuotas { ref quotas, .. } => {
+                write!(formatter, "Action::SetConnectionQuotas({:?})", quotas)
+            }
+            Action::SetProxyStrategy { strategy, .. } => {
+                write!(formatter, "Action::SetProxyStrategy({:?})", strategy)
+            }
+            Action::DumpDecisionLog { .. } => write!(formatter, "Action::DumpDecisionLog"),
+            Action::GetEffectiveConfig { .. } => write!(formatter, "Action::GetEffectiveConfig"),
+            Action::UpdateConfig { ref update, .. } => {
+                write!(formatter, "Action::UpdateConfig({:?})", update)
+            }
+            Action::PingPeer { ref name, .. } => write!(formatter, "Action::PingPeer({:?})", name),
             Action::Timeout(token) => write!(formatter, "Action::Timeout({})", token),
             Action::ResourceProofResult(pub_id, _) => {
                 write!(formatter, "Action::ResourceProofResult({:?}, ...)", pub_id)
@@ -17,24 +17,27 @@
 
 use action::Action;
 use cache::NullCache;
+use crypto;
 use data::{AppendWrapper, Data, DataIdentifier};
 use error::{InterfaceError, RoutingError};
 use event::Event;
+#[cfg(not(feature = "use-mock-crust"))]
+use event_sink::EventSink;
 use id::{FullId, PublicId};
 #[cfg(not(feature = "use-mock-crust"))]
 use maidsafe_utilities::thread::{self, Joiner};
 use messages::{CLIENT_GET_PRIORITY, DEFAULT_PRIORITY, Request};
 use outbox::{EventBox, EventBuf};
 use routing_table::Authority;
-#[cfg(not(feature = "use-mock-crust"))]
-use rust_sodium;
 use state_machine::{State, StateMachine};
 use states::{Bootstrapping, BootstrappingTargetState};
+use stats::Diagnostics;
 #[cfg(feature = "use-mock-crust")]
 use std::cell::RefCell;
 use std::sync::mpsc::{Receiver, Sender, channel};
 #[cfg(feature = "use-mock-crust")]
 use std::sync::mpsc::TryRecvError;
+use tunables::{ProxyStrategy, Tunables};
 use types::MessageId;
 use types::RoutingActionSender;
 use xor_name::XorName;
@@ -70,11 +73,16 @@ impl Client {
     /// Keys will be exchanged with the `ClientAuthority` so that communication with the network is
     /// cryptographically secure and uses section consensus. The restriction for the client name
     /// exists to ensure that the client cannot choose its `ClientAuthority`.
+    ///
+    /// Events are delivered to `event_sender`, which is usually a `Sender<Event>`, but can be any
+    /// `EventSink`, e.g. a `RingBufferSink`.
     #[cfg(not(feature = "use-mock-crust"))]
-    pub fn new(event_sender: Sender<Event>, keys: Option<FullId>) -> Result<Client, RoutingError> {
+    pub fn new<S>(event_sender: S, keys: Option<FullId>) -> Result<Client, RoutingError>
+        where S: EventSink + 'static
+    {
         // TODO - replace this hard-coded value
         let min_section_size = 8;
-        rust_sodium::init(); // enable shared global (i.e. safe to multithread now)
+        crypto::init()?; // enable shared global (i.e. safe to multithread now)
 
         let (tx, rx) = channel();
         let (get_action_sender_tx, get_action_sender_rx) = channel();
@@ -85,12 +93,15 @@ impl Client {
             let (action_sender, mut machine) =
                 Self::make_state_machine(keys, min_section_size, &mut event_buffer);
 
+            unwrap!(get_action_sender_tx.send(action_sender));
+
             for ev in event_buffer.take_all() {
-                unwrap!(event_sender.send(ev));
+                // If sending the event fails, terminate this thread.
+                if event_sender.send(ev).is_err() {
+                    return;
+                }
             }
 
-            unwrap!(get_action_sender_tx.send(action_sender));
-
             // Gather events from the state machine's event loop and proxy them over the
             // event_sender channel.
             while Ok(()) == machine.step(&mut event_buffer) {
@@ -101,6 +112,10 @@ impl Client {
                     }
                 }
             }
+            // Forward any events raised by the final step, e.g. `Event::Terminate`.
+            for ev in event_buffer.take_all() {
+                let _ = event_sender.send(ev);
+            }
             // When there are no more events to process, terminate this thread.
         });
 
@@ -124,14 +139,18 @@ impl Client {
         StateMachine::new(move |action_sender, crust_service, timer, _outbox2| {
             Bootstrapping::new(action_sender,
                                Box::new(NullCache),
+                               None,
+                               None,
                                BootstrappingTargetState::Client,
                                crust_service,
                                full_id,
                                min_section_size,
-                               timer)
+                               timer,
+                               Tunables::default())
                     .map_or(State::Terminated, State::Bootstrapping)
         },
                           pub_id,
+                          Vec::new(),
                           outbox)
     }
 
@@ -191,6 +210,23 @@ impl Client {
                          CLIENT_GET_PRIORITY)
     }
 
+    /// Send the given request to `dst`, with the priority the method for its type uses.
+    pub fn send_request(&self,
+                        dst: Authority<XorName>,
+                        request: Request)
+                        -> Result<(), InterfaceError> {
+        let priority = match request {
+            Request::Get(..) |
+            Request::GetAccountInfo(..) => CLIENT_GET_PRIORITY,
+            Request::Refresh(..) |
+            Request::Put(..) |
+            Request::Post(..) |
+            Request::Delete(..) |
+            Request::Append(..) => DEFAULT_PRIORITY,
+        };
+        self.send_action(request, dst, priority)
+    }
+
     /// Returns the `PublicId` of this client.
     pub fn id(&self) -> Result<PublicId, InterfaceError> {
         let (result_tx, result_rx) = channel();
@@ -200,6 +236,52 @@ impl Client {
         self.receive_action_result(&result_rx)
     }
 
+    /// Returns the current sizes and counters of this client's message filters.
+    pub fn diagnostics(&self) -> Result<Diagnostics, InterfaceError> {
+        let (result_tx, result_rx) = channel();
+        self.action_sender
+            .send(Action::GetStats { result_tx: result_tx })?;
+
+        self.receive_action_result(&result_rx)
+    }
+
+    /// Sets the strategy choosing which of our proxies carries each request. It applies to all
+    /// subsequent requests, and can be set while still bootstrapping.
+    pub fn set_proxy_strategy(&self, strategy: ProxyStrategy) -> Result<(), InterfaceError> {
+        let action = Action::SetProxyStrategy {
+            strategy: strategy,
+            result_tx: self.interface_result_tx.clone(),
+        };
+
+        self.action_sender.send(action)?;
+        self.receive_action_result(&self.interface_result_rx)?
+    }
+
+    /// Cancels the request with the given ID, which this client sent recently: any late response
+    /// to it is dropped, and its destination is asked to stop handling it. That notice is
+    /// best-effort, and the destination may already have responded.
+    ///
+    /// Returns `InterfaceError::UnknownRequest` if no such request was sent within the last
+    /// few minutes, or it was cancelled already.
+    pub fn cancel_request(&self, message_id: MessageId) -> Result<(), InterfaceError> {
+        let action = Action::CancelRequest {
+            msg_id: message_id,
+            result_tx: self.interface_result_tx.clone(),
+        };
+
+        self.action_sender.send(action)?;
+        self.receive_action_result(&self.interface_result_rx)?
+    }
+
+    /// Disconnects from our proxies and shuts the client down. `Event::Terminate` is the last
+    /// event raised.
+    pub fn terminate(&self) -> Result<(), InterfaceError> {
+        self.action_sender.send(Action::Terminate)?;
+        #[cfg(feature = "use-mock-crust")]
+        let _ = self.poll();
+        Ok(())
+    }
+
     fn send_action(&self,
                    content: Request,
                    dst: Authority<XorName>,
@@ -230,6 +312,8 @@ impl Client {
 impl Client {
     /// Create a new `Client` for unit testing.
     pub fn new(keys: Option<FullId>, min_section_size: usize) -> Result<Client, RoutingError> {
+        crypto::init()?;
+
         // start the handler for routing with a restriction to become a full node
         let mut event_buffer = EventBuf::new();
 
@@ -270,6 +354,15 @@ impl Client {
         result
     }
 
+    /// Sets a proxy name to be claimed in the source authority of subsequently sent messages,
+    /// instead of the one of the actual proxy node.
+    pub fn set_claimed_proxy_name(&self, name: XorName) {
+        self.machine
+            .borrow_mut()
+            .current_mut()
+            .set_claimed_proxy_name(Some(name))
+    }
+
     /// Step the underlying state machine if there are any events for it to process.
     fn try_step(&self) -> Result<(), TryRecvError> {
         self.machine
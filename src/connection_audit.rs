@@ -0,0 +1,116 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+#[cfg(feature="use-mock-crust")]
+use fake_clock::FakeClock as Instant;
+use id::PublicId;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Duration;
+#[cfg(not(feature="use-mock-crust"))]
+use std::time::Instant;
+
+/// Tracks the discrepancies between our peers and Crust's live connections found by periodic
+/// audits, so that each is only repaired once it has outlasted the grace period.
+pub struct ConnectionAudit {
+    interval: Duration,
+    grace: Duration,
+    /// Peers Crust reported a connection to or a message from, and which haven't been lost since.
+    live: BTreeSet<PublicId>,
+    /// The current discrepancies, with the time each was first found.
+    suspects: HashMap<PublicId, Instant>,
+}
+
+impl ConnectionAudit {
+    /// Returns a new instance for audits every `interval`, which repair discrepancies only after
+    /// they have persisted for `grace`.
+    pub fn new(interval: Duration, grace: Duration) -> ConnectionAudit {
+        ConnectionAudit {
+            interval: interval,
+            grace: grace,
+            live: BTreeSet::new(),
+            suspects: HashMap::new(),
+        }
+    }
+
+    /// The time between two audits.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Records that Crust has a live connection to the peer.
+    pub fn connected(&mut self, pub_id: PublicId) {
+        let _ = self.live.insert(pub_id);
+    }
+
+    /// Records that the connection to the peer is gone.
+    pub fn disconnected(&mut self, pub_id: &PublicId) {
+        let _ = self.live.remove(pub_id);
+    }
+
+    /// Returns the peers Crust is believed to have a live connection to.
+    pub fn live_peers(&self) -> Vec<PublicId> {
+        self.live.iter().cloned().collect()
+    }
+
+    /// Takes the discrepancies found by the current audit, and returns those which have outlasted
+    /// the grace period and should be repaired now. Previous discrepancies not found again are
+    /// considered resolved.
+    pub fn due<I: IntoIterator<Item = PublicId>>(&mut self, found: I) -> BTreeSet<PublicId> {
+        let now = Instant::now();
+        let found: BTreeSet<PublicId> = found.into_iter().collect();
+        self.suspects.retain(|pub_id, _| found.contains(pub_id));
+        let mut due = BTreeSet::new();
+        for pub_id in found {
+            let first_found = *self.suspects.entry(pub_id).or_insert(now);
+            if first_found.elapsed() >= self.grace {
+                let _ = self.suspects.remove(&pub_id);
+                let _ = due.insert(pub_id);
+            }
+        }
+        due
+    }
+}
+
+#[cfg(all(test, feature = "use-mock-crust"))]
+mod tests {
+    use super::*;
+    use fake_clock::FakeClock;
+    use id::FullId;
+
+    #[test]
+    fn discrepancies_due_after_grace_period() {
+        let mut audit = ConnectionAudit::new(Duration::from_secs(10), Duration::from_secs(15));
+        let pub_id_0 = *FullId::new().public_id();
+        let pub_id_1 = *FullId::new().public_id();
+
+        assert!(audit.due(vec![pub_id_0]).is_empty());
+        FakeClock::advance_time(10 * 1000);
+        assert!(audit.due(vec![pub_id_0, pub_id_1]).is_empty());
+        FakeClock::advance_time(10 * 1000);
+        assert_eq!(audit.due(vec![pub_id_0, pub_id_1]),
+                   vec![pub_id_0].into_iter().collect());
+
+        // A discrepancy which resolves itself in between starts its grace period afresh.
+        assert!(audit.due(vec![]).is_empty());
+        FakeClock::advance_time(10 * 1000);
+        assert!(audit.due(vec![pub_id_1]).is_empty());
+        FakeClock::advance_time(10 * 1000);
+        assert!(audit.due(vec![pub_id_1]).is_empty());
+        FakeClock::advance_time(10 * 1000);
+        assert_eq!(audit.due(vec![pub_id_1]), vec![pub_id_1].into_iter().collect());
+    }
+}
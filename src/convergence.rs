@@ -0,0 +1,219 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use routing_table::{RoutingTable, Xorable};
+use std::cmp;
+use std::collections::{BTreeSet, VecDeque};
+use std::time::Duration;
+use xor_name::{XOR_NAME_LEN, XorName};
+
+/// The minimum number of snapshots kept in the history.
+const MIN_HISTORY_LEN: usize = 10;
+
+/// Metrics of how far a routing table has converged, at one point in time.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ConvergenceSnapshot {
+    /// The XOR distance from our name to the furthest member of our close group, or `None` if the
+    /// table doesn't hold a full close group yet.
+    pub close_group_distance: Option<XorName>,
+    /// The number of distinct buckets the table's entries fall into, i.e. of distinct lengths of
+    /// their common prefix with our name.
+    pub buckets: usize,
+    /// The number of entries in the table, excluding our own name.
+    pub table_size: usize,
+}
+
+impl ConvergenceSnapshot {
+    /// Returns the metrics of the given routing table.
+    pub fn of(table: &RoutingTable<XorName>) -> ConvergenceSnapshot {
+        let our_name = table.our_name();
+        let group_size = table.min_section_size();
+        let close_group_distance = table
+            .closest_names(our_name, group_size)
+            .and_then(|names| if names.len() < group_size {
+                          None
+                      } else {
+                          names.last().map(|name| distance(our_name, name))
+                      });
+        let buckets = table
+            .iter()
+            .map(|name| our_name.common_prefix(name))
+            .collect::<BTreeSet<_>>()
+            .len();
+        ConvergenceSnapshot {
+            close_group_distance: close_group_distance,
+            buckets: buckets,
+            table_size: table.len(),
+        }
+    }
+}
+
+/// The convergence of a node's routing table, as reported in `Diagnostics`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConvergenceMetrics {
+    /// The metrics as of the last change to the routing table.
+    pub current: ConvergenceSnapshot,
+    /// The snapshots taken periodically, oldest first.
+    pub history: Vec<ConvergenceSnapshot>,
+    /// Whether the table is considered converged: its close group distance and size didn't change
+    /// over the required number of snapshots, and it has at least the required size.
+    pub converged: bool,
+}
+
+/// Tracks the convergence metrics of our routing table, updated on every change to it, and takes
+/// a snapshot of them periodically.
+pub struct ConvergenceTracker {
+    interval: Duration,
+    stable_snapshots: usize,
+    min_table_size: usize,
+    current: ConvergenceSnapshot,
+    history: VecDeque<ConvergenceSnapshot>,
+    history_len: usize,
+    /// The number of snapshots taken since the close group distance or table size last changed.
+    unchanged_snapshots: usize,
+    converged: bool,
+}
+
+impl ConvergenceTracker {
+    /// Returns a new tracker taking a snapshot every `interval`, which considers the table
+    /// converged once it has `min_table_size` entries and hasn't changed over `stable_snapshots`
+    /// snapshots.
+    pub fn new(interval: Duration,
+               stable_snapshots: usize,
+               min_table_size: usize)
+               -> ConvergenceTracker {
+        let history_len = cmp::max(stable_snapshots, MIN_HISTORY_LEN);
+        ConvergenceTracker {
+            interval: interval,
+            stable_snapshots: stable_snapshots,
+            min_table_size: min_table_size,
+            current: ConvergenceSnapshot::default(),
+            history: VecDeque::with_capacity(history_len),
+            history_len: history_len,
+            unchanged_snapshots: 0,
+            converged: false,
+        }
+    }
+
+    /// The time between two snapshots.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Updates the current metrics after a change to the routing table. If its close group
+    /// distance or size changed, the table is no longer considered converged.
+    pub fn update(&mut self, table: &RoutingTable<XorName>) {
+        let snapshot = ConvergenceSnapshot::of(table);
+        if snapshot.close_group_distance != self.current.close_group_distance ||
+           snapshot.table_size != self.current.table_size {
+            self.unchanged_snapshots = 0;
+            self.converged = false;
+        }
+        self.current = snapshot;
+    }
+
+    /// Adds a snapshot of the current metrics to the history. Returns `true` if the table is now
+    /// considered converged, but wasn't before.
+    pub fn take_snapshot(&mut self) -> bool {
+        if self.history.len() == self.history_len {
+            let _ = self.history.pop_front();
+        }
+        self.history.push_back(self.current);
+        self.unchanged_snapshots += 1;
+        if self.converged || self.unchanged_snapshots < self.stable_snapshots ||
+           self.current.table_size < self.min_table_size {
+            return false;
+        }
+        self.converged = true;
+        true
+    }
+
+    /// Returns the current metrics and the history of snapshots.
+    pub fn metrics(&self) -> ConvergenceMetrics {
+        ConvergenceMetrics {
+            current: self.current,
+            history: self.history.iter().cloned().collect(),
+            converged: self.converged,
+        }
+    }
+}
+
+// Returns the XOR distance between the two names.
+fn distance(lhs: &XorName, rhs: &XorName) -> XorName {
+    let mut result = [0; XOR_NAME_LEN];
+    for (byte, (lhs_byte, rhs_byte)) in result.iter_mut().zip(lhs.0.iter().zip(rhs.0.iter())) {
+        *byte = lhs_byte ^ rhs_byte;
+    }
+    XorName(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn name(first_byte: u8) -> XorName {
+        let mut bytes = [0; XOR_NAME_LEN];
+        bytes[0] = first_byte;
+        XorName(bytes)
+    }
+
+    #[test]
+    fn snapshot_of_table() {
+        let mut table = RoutingTable::new(name(0), 3);
+        assert_eq!(ConvergenceSnapshot::of(&table), ConvergenceSnapshot::default());
+
+        unwrap!(table.add(name(0b1000_0000)));
+        unwrap!(table.add(name(0b0100_0000)));
+        let snapshot = ConvergenceSnapshot::of(&table);
+        assert_eq!(snapshot.close_group_distance, Some(name(0b1000_0000)));
+        assert_eq!(snapshot.buckets, 2);
+        assert_eq!(snapshot.table_size, 2);
+
+        // A closer name pushes the furthest one out of the close group.
+        unwrap!(table.add(name(0b0100_0001)));
+        let snapshot = ConvergenceSnapshot::of(&table);
+        assert_eq!(snapshot.close_group_distance, Some(name(0b0100_0001)));
+        assert_eq!(snapshot.buckets, 2);
+        assert_eq!(snapshot.table_size, 3);
+    }
+
+    #[test]
+    fn converges_once_per_episode() {
+        let mut table = RoutingTable::new(name(0), 2);
+        let mut tracker = ConvergenceTracker::new(Duration::from_secs(1), 2, 2);
+        unwrap!(table.add(name(1)));
+        tracker.update(&table);
+        assert!(!tracker.take_snapshot());
+        assert!(!tracker.take_snapshot());
+
+        // Large enough, and unchanged over two snapshots.
+        unwrap!(table.add(name(2)));
+        tracker.update(&table);
+        assert!(!tracker.take_snapshot());
+        assert!(tracker.take_snapshot());
+        assert!(tracker.metrics().converged);
+        assert!(!tracker.take_snapshot());
+
+        // A change starts a new episode.
+        unwrap!(table.add(name(3)));
+        tracker.update(&table);
+        assert!(!tracker.metrics().converged);
+        assert!(!tracker.take_snapshot());
+        assert!(tracker.take_snapshot());
+        assert_eq!(tracker.metrics().history.len(), 7);
+    }
+}
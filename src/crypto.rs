@@ -0,0 +1,176 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Initialisation of the crypto library, and signing and verification of routing messages.
+//!
+//! With mock Crust, any of these operations can be scripted to fail via the functions in
+//! `mock_crust`, such as `fail_signing`, to exercise the code handling such failures.
+
+#[cfg(not(feature = "use-mock-crust"))]
+use rust_sodium;
+use rust_sodium::crypto::sign::{self, PublicKey, SecretKey, Signature};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+
+/// An operation of the crypto library which failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CryptoError {
+    /// The library couldn't be initialised.
+    InitFailed,
+    /// Data couldn't be signed.
+    SigningFailed,
+}
+
+impl Display for CryptoError {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        write!(formatter, "{}", self.description())
+    }
+}
+
+impl Error for CryptoError {
+    fn description(&self) -> &str {
+        match *self {
+            CryptoError::InitFailed => "Failed to initialise the crypto library",
+            CryptoError::SigningFailed => "Failed to sign data",
+        }
+    }
+}
+
+/// Something which can sign data.
+pub trait Signer {
+    /// Returns the signature of `data`.
+    fn sign(&self, data: &[u8]) -> Result<Signature, CryptoError>;
+}
+
+/// Something which can verify signatures.
+pub trait Verifier {
+    /// Returns whether `signature` is a valid signature of `data`. A failure to verify is treated
+    /// like an invalid signature.
+    fn verify(&self, signature: &Signature, data: &[u8]) -> bool;
+}
+
+impl Signer for SecretKey {
+    fn sign(&self, data: &[u8]) -> Result<Signature, CryptoError> {
+        if script::fails(Operation::Signing) {
+            return Err(CryptoError::SigningFailed);
+        }
+        Ok(sign::sign_detached(data, self))
+    }
+}
+
+impl Verifier for PublicKey {
+    fn verify(&self, signature: &Signature, data: &[u8]) -> bool {
+        !script::fails(Operation::Verification) && sign::verify_detached(signature, data, self)
+    }
+}
+
+/// Initialises the crypto library. This needs to succeed before any keys are generated.
+pub fn init() -> Result<(), CryptoError> {
+    if script::fails(Operation::Init) || !init_sodium() {
+        Err(CryptoError::InitFailed)
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "use-mock-crust"))]
+fn init_sodium() -> bool {
+    rust_sodium::init()
+}
+
+// With mock Crust, this has already been done with a seeded RNG by `mock_crust::Network::new`.
+#[cfg(feature = "use-mock-crust")]
+fn init_sodium() -> bool {
+    true
+}
+
+/// A crypto operation which can be scripted to fail.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Operation {
+    Init,
+    Signing,
+    Verification,
+}
+
+#[cfg(not(feature = "use-mock-crust"))]
+mod script {
+    use super::Operation;
+
+    pub fn fails(_operation: Operation) -> bool {
+        false
+    }
+}
+
+/// Scripted failures of crypto operations on the current thread.
+#[cfg(feature = "use-mock-crust")]
+pub mod script {
+    use super::Operation;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    thread_local! {
+        // For each operation, how many more of them succeed before the next one fails.
+        static COUNTDOWNS: RefCell<HashMap<Operation, usize>> = RefCell::new(HashMap::new());
+    }
+
+    /// Makes the `nth` initialisation of the crypto library on this thread from now on fail,
+    /// counting from 1.
+    pub fn fail_crypto_init(nth: usize) {
+        schedule(Operation::Init, nth);
+    }
+
+    /// Makes the `nth` signing operation on this thread from now on fail, counting from 1.
+    pub fn fail_signing(nth: usize) {
+        schedule(Operation::Signing, nth);
+    }
+
+    /// Makes the `nth` signature verification on this thread from now on fail, counting from 1.
+    /// The signature is treated as invalid.
+    pub fn fail_verification(nth: usize) {
+        schedule(Operation::Verification, nth);
+    }
+
+    /// Cancels all scripted failures on this thread.
+    pub fn clear_crypto_failures() {
+        COUNTDOWNS.with(|countdowns| countdowns.borrow_mut().clear());
+    }
+
+    fn schedule(operation: Operation, nth: usize) {
+        assert!(nth > 0, "Failures are counted from 1.");
+        COUNTDOWNS.with(|countdowns| {
+                            let _ = countdowns.borrow_mut().insert(operation, nth - 1);
+                        });
+    }
+
+    /// Returns whether this occurrence of `operation` is scripted to fail.
+    pub fn fails(operation: Operation) -> bool {
+        COUNTDOWNS.with(|countdowns| {
+            let mut countdowns = countdowns.borrow_mut();
+            match countdowns.get(&operation).cloned() {
+                None => false,
+                Some(0) => {
+                    let _ = countdowns.remove(&operation);
+                    true
+                }
+                Some(remaining) => {
+                    let _ = countdowns.insert(operation, remaining - 1);
+                    false
+                }
+            }
+        })
+    }
+}
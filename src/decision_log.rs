@@ -0,0 +1,325 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+#[cfg(feature="use-mock-crust")]
+use fake_clock::FakeClock as Instant;
+use messages::RoutingMessage;
+use routing_table::Authority;
+use std::cmp;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+#[cfg(not(feature="use-mock-crust"))]
+use std::time::Instant;
+use xor_name::XorName;
+
+/// The format version written as the first byte of a dumped decision log.
+const DECISION_LOG_VERSION: u8 = 1;
+/// The size of a single encoded record, in bytes.
+const RECORD_LEN: usize = 28;
+/// The number of bytes of a peer's name stored in a record.
+const PEER_PREFIX_LEN: usize = 8;
+
+/// Returns the hash identifying `msg` in decision log records. It is the same on every node, so
+/// the records of a message can be matched across the logs of several nodes.
+pub fn message_hash(msg: &RoutingMessage) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    msg.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// The outcome of checking an incoming message against the filter of messages already seen.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FilterOutcome {
+    /// The message hasn't been seen before.
+    New,
+    /// The message has been seen before, but via a different route.
+    KnownMessage,
+    /// The message has been seen before via the same route.
+    KnownMessageAndRoute,
+}
+
+/// The kind of a message's destination authority.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AuthorityKind {
+    /// `Authority::ClientManager`.
+    ClientManager,
+    /// `Authority::NaeManager`.
+    NaeManager,
+    /// `Authority::NodeManager`.
+    NodeManager,
+    /// `Authority::Section`.
+    Section,
+    /// `Authority::PrefixSection`.
+    PrefixSection,
+    /// `Authority::ManagedNode`.
+    ManagedNode,
+    /// `Authority::Client`.
+    Client,
+}
+
+impl<'a> From<&'a Authority<XorName>> for AuthorityKind {
+    fn from(authority: &'a Authority<XorName>) -> AuthorityKind {
+        match *authority {
+            Authority::ClientManager(_) => AuthorityKind::ClientManager,
+            Authority::NaeManager(_) => AuthorityKind::NaeManager,
+            Authority::NodeManager(_) => AuthorityKind::NodeManager,
+            Authority::Section(_) => AuthorityKind::Section,
+            Authority::PrefixSection(_) => AuthorityKind::PrefixSection,
+            Authority::ManagedNode(_) => AuthorityKind::ManagedNode,
+            Authority::Client { .. } => AuthorityKind::Client,
+        }
+    }
+}
+
+impl AuthorityKind {
+    fn to_code(self) -> u8 {
+        match self {
+            AuthorityKind::ClientManager => 0,
+            AuthorityKind::NaeManager => 1,
+            AuthorityKind::NodeManager => 2,
+            AuthorityKind::Section => 3,
+            AuthorityKind::PrefixSection => 4,
+            AuthorityKind::ManagedNode => 5,
+            AuthorityKind::Client => 6,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<AuthorityKind> {
+        Some(match code {
+                 0 => AuthorityKind::ClientManager,
+                 1 => AuthorityKind::NaeManager,
+                 2 => AuthorityKind::NodeManager,
+                 3 => AuthorityKind::Section,
+                 4 => AuthorityKind::PrefixSection,
+                 5 => AuthorityKind::ManagedNode,
+                 6 => AuthorityKind::Client,
+                 _ => return None,
+             })
+    }
+}
+
+/// A routing decision taken for a message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Decision {
+    /// The message was received from the record's peer. If the peer is this node, the message
+    /// originated here.
+    Received,
+    /// The message was checked against the filter of messages already seen.
+    Filtered(FilterOutcome),
+    /// We are in the message's destination authority and handled it, passing user messages on to
+    /// the user.
+    Delivered,
+    /// The message was sent on to the given number of peers. Messages relayed to a client we are
+    /// the proxy of are not recorded.
+    Forwarded(u8),
+    /// The message exceeded the maximum hop count and was dropped.
+    HopLimitExceeded,
+}
+
+impl Decision {
+    fn to_codes(self) -> (u8, u8) {
+        match self {
+            Decision::Received => (0, 0),
+            Decision::Filtered(FilterOutcome::New) => (1, 0),
+            Decision::Filtered(FilterOutcome::KnownMessage) => (1, 1),
+            Decision::Filtered(FilterOutcome::KnownMessageAndRoute) => (1, 2),
+            Decision::Delivered => (2, 0),
+            Decision::Forwarded(count) => (3, count),
+            Decision::HopLimitExceeded => (4, 0),
+        }
+    }
+
+    fn from_codes(code: u8, detail: u8) -> Option<Decision> {
+        Some(match (code, detail) {
+                 (0, 0) => Decision::Received,
+                 (1, 0) => Decision::Filtered(FilterOutcome::New),
+                 (1, 1) => Decision::Filtered(FilterOutcome::KnownMessage),
+                 (1, 2) => Decision::Filtered(FilterOutcome::KnownMessageAndRoute),
+                 (2, 0) => Decision::Delivered,
+                 (3, count) => Decision::Forwarded(count),
+                 (4, 0) => Decision::HopLimitExceeded,
+                 _ => return None,
+             })
+    }
+}
+
+/// A decoded entry of a decision log.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DecisionRecord {
+    /// The hash of the message, as returned by `message_hash`.
+    pub msg_hash: u64,
+    /// The time since the log was created.
+    pub elapsed: Duration,
+    /// The first bytes of the name of the peer the message was received from, or of our own name
+    /// for messages we originated.
+    pub peer: [u8; PEER_PREFIX_LEN],
+    /// The decision taken.
+    pub decision: Decision,
+    /// The route the message was received or sent on.
+    pub route: u8,
+    /// The kind of the message's destination authority.
+    pub dst: AuthorityKind,
+}
+
+impl DecisionRecord {
+    /// Returns whether the record's peer has the given name.
+    pub fn peer_is(&self, name: &XorName) -> bool {
+        self.peer[..] == name.0[..PEER_PREFIX_LEN]
+    }
+}
+
+/// Decodes a decision log dumped via `Node::decision_log`, oldest record first. Returns `None` if
+/// the bytes are not a valid log.
+pub fn decode_decision_log(bytes: &[u8]) -> Option<Vec<DecisionRecord>> {
+    if bytes.first() != Some(&DECISION_LOG_VERSION) || (bytes.len() - 1) % RECORD_LEN != 0 {
+        return None;
+    }
+    let mut records = Vec::with_capacity((bytes.len() - 1) / RECORD_LEN);
+    for chunk in bytes[1..].chunks(RECORD_LEN) {
+        let decision = match Decision::from_codes(chunk[24], chunk[25]) {
+            Some(decision) => decision,
+            None => return None,
+        };
+        let dst = match AuthorityKind::from_code(chunk[27]) {
+            Some(dst) => dst,
+            None => return None,
+        };
+        let mut peer = [0; PEER_PREFIX_LEN];
+        peer.copy_from_slice(&chunk[16..24]);
+        let millis = read_u64(&chunk[8..16]);
+        records.push(DecisionRecord {
+                         msg_hash: read_u64(&chunk[0..8]),
+                         elapsed: Duration::from_millis(millis),
+                         peer: peer,
+                         decision: decision,
+                         route: chunk[26],
+                         dst: dst,
+                     });
+    }
+    Some(records)
+}
+
+/// A fixed-size ring buffer of compact binary records of the routing decisions taken for each
+/// message. Once full, each new record overwrites the oldest one. The buffer is allocated up front,
+/// so recording doesn't allocate.
+pub struct DecisionLog {
+    start: Instant,
+    records: Vec<[u8; RECORD_LEN]>,
+    /// The index of the slot the next record is written to.
+    next: usize,
+    /// Whether the buffer has wrapped around, i.e. all slots hold a record.
+    full: bool,
+}
+
+impl DecisionLog {
+    /// Returns a new log holding up to `capacity` records.
+    pub fn new(capacity: usize) -> DecisionLog {
+        DecisionLog {
+            start: Instant::now(),
+            records: vec![[0; RECORD_LEN]; cmp::max(capacity, 1)],
+            next: 0,
+            full: false,
+        }
+    }
+
+    /// Records a decision taken for the message with the given hash, received from or sent to
+    /// `peer` on `route`.
+    pub fn record(&mut self,
+                  msg_hash: u64,
+                  peer: &XorName,
+                  route: u8,
+                  dst: &Authority<XorName>,
+                  decision: Decision) {
+        let elapsed = self.start.elapsed();
+        let millis = elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_nanos() / 1_000_000);
+        let (code, detail) = decision.to_codes();
+        let slot = &mut self.records[self.next];
+        write_u64(&mut slot[0..8], msg_hash);
+        write_u64(&mut slot[8..16], millis);
+        slot[16..24].copy_from_slice(&peer.0[..PEER_PREFIX_LEN]);
+        slot[24] = code;
+        slot[25] = detail;
+        slot[26] = route;
+        slot[27] = AuthorityKind::from(dst).to_code();
+        self.next = (self.next + 1) % self.records.len();
+        self.full = self.full || self.next == 0;
+    }
+
+    /// Returns the serialised log, oldest record first, to be decoded by `decode_decision_log`.
+    pub fn dump(&self) -> Vec<u8> {
+        let (older, newer) = if self.full {
+            (&self.records[self.next..], &self.records[..self.next])
+        } else {
+            (&self.records[..0], &self.records[..self.next])
+        };
+        let mut bytes = Vec::with_capacity(1 + (older.len() + newer.len()) * RECORD_LEN);
+        bytes.push(DECISION_LOG_VERSION);
+        for record in older.iter().chain(newer) {
+            bytes.extend_from_slice(record);
+        }
+        bytes
+    }
+}
+
+fn write_u64(bytes: &mut [u8], value: u64) {
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = (value >> (8 * i)) as u8;
+    }
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .enumerate()
+        .fold(0, |value, (i, byte)| value | (u64::from(*byte) << (8 * i)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand;
+
+    #[test]
+    fn records_decoded_oldest_first_after_wrapping() {
+        let mut log = DecisionLog::new(3);
+        let peer: XorName = rand::random();
+        let dst = Authority::NaeManager(rand::random());
+        let decisions = [Decision::Received,
+                         Decision::Filtered(FilterOutcome::KnownMessage),
+                         Decision::Delivered,
+                         Decision::Forwarded(7),
+                         Decision::HopLimitExceeded];
+        for (i, decision) in decisions.iter().enumerate() {
+            log.record(u64::max_value() - i as u64, &peer, i as u8, &dst, *decision);
+        }
+
+        let records = unwrap!(decode_decision_log(&log.dump()));
+        assert_eq!(records.len(), 3);
+        for (record, (i, decision)) in records.iter().zip(decisions.iter().enumerate().skip(2)) {
+            assert_eq!(record.msg_hash, u64::max_value() - i as u64);
+            assert_eq!(record.decision, *decision);
+            assert_eq!(record.route, i as u8);
+            assert_eq!(record.dst, AuthorityKind::NaeManager);
+            assert!(record.peer_is(&peer));
+        }
+
+        assert!(decode_decision_log(&[]).is_none());
+        assert!(decode_decision_log(&log.dump()[..RECORD_LEN]).is_none());
+        assert_eq!(Some(vec![]), decode_decision_log(&DecisionLog::new(3).dump()));
+    }
+}
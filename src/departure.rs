@@ -0,0 +1,153 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use super::{QUORUM_DENOMINATOR, QUORUM_NUMERATOR};
+use expiring_cache::{CacheStats, ExpiringCache};
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+use xor_name::XorName;
+
+/// Time (in seconds) for which reports of losing a section member are kept. If a quorum of the
+/// section hasn't reported it by then, the reports are dropped.
+pub const DEPARTURE_OBSERVATION_TIMEOUT_SECS: u64 = 60;
+/// Time (in seconds) for which a departed member is remembered, so that late reports of losing it
+/// don't declare it departed again.
+const DEPARTED_MEMBER_EXPIRY_SECS: u64 = 600;
+
+/// Collects our section members' reports of losing their connection to another member, and
+/// decides when enough of them have lost it to consider it departed from the network.
+///
+/// A single lost connection can be a local failure; only a quorum of the remaining members
+/// reporting the loss independently makes the departure agreed.
+pub struct DepartureConsensus {
+    /// The names of the members which reported losing each lost member.
+    observations: ExpiringCache<XorName, BTreeSet<XorName>>,
+    /// The members we recently declared departed.
+    departed: ExpiringCache<XorName, ()>,
+}
+
+impl DepartureConsensus {
+    pub fn new() -> DepartureConsensus {
+        DepartureConsensus {
+            observations:
+                ExpiringCache::new("departure_observations",
+                                   Duration::from_secs(DEPARTURE_OBSERVATION_TIMEOUT_SECS)),
+            departed: ExpiringCache::new("departed_members",
+                                         Duration::from_secs(DEPARTED_MEMBER_EXPIRY_SECS)),
+        }
+    }
+
+    /// Records that `observer` lost its connection to `lost`. `group` is our section without
+    /// `lost`, and only its members' reports count. Returns `true` if this completes a quorum of
+    /// `group`, unless `lost` was already declared departed.
+    pub fn observe(&mut self,
+                   lost: XorName,
+                   observer: XorName,
+                   group: &BTreeSet<XorName>)
+                   -> bool {
+        if self.departed.contains_key(&lost) || !group.contains(&observer) {
+            return false;
+        }
+        if !self.observations.contains_key(&lost) {
+            let _ = self.observations.insert(lost, BTreeSet::new());
+        }
+        let quorum = match self.observations.get_mut(&lost) {
+            Some(observers) => {
+                let _ = observers.insert(observer);
+                let count = observers.intersection(group).count();
+                count * QUORUM_DENOMINATOR > group.len() * QUORUM_NUMERATOR
+            }
+            None => false,
+        };
+        if quorum {
+            let _ = self.observations.remove(&lost);
+            let _ = self.departed.insert(lost, ());
+        }
+        quorum
+    }
+
+    /// Returns whether `name` was recently declared departed.
+    pub fn has_departed(&self, name: &XorName) -> bool {
+        self.departed.contains_key(name)
+    }
+
+    /// Forgets any reports of losing `name`, and that it departed, e.g. because it is in our
+    /// routing table again. Its next departure will need a new quorum of reports.
+    pub fn rejoined(&mut self, name: &XorName) {
+        let _ = self.observations.remove(name);
+        let _ = self.departed.remove(name);
+    }
+
+    /// Adds the stats of the reports and departed members to `caches`.
+    pub fn report(&mut self, caches: &mut BTreeMap<String, CacheStats>) {
+        self.observations.report(caches);
+        self.departed.report(caches);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand;
+
+    fn group(size: usize) -> BTreeSet<XorName> {
+        (0..size).map(|_| rand::random()).collect()
+    }
+
+    #[test]
+    fn quorum_of_observers_required() {
+        let group = group(7);
+        let lost = rand::random();
+        let mut departures = DepartureConsensus::new();
+
+        // Reports from outsiders, or repeated ones, don't count.
+        let mut observers = group.iter();
+        assert!(!departures.observe(lost, rand::random(), &group));
+        let first = *unwrap!(observers.next());
+        for _ in 0..3 {
+            assert!(!departures.observe(lost, first, &group));
+        }
+        for _ in 0..2 {
+            assert!(!departures.observe(lost, *unwrap!(observers.next()), &group));
+        }
+        assert!(!departures.has_departed(&lost));
+
+        // The fourth of seven members is a quorum, and later reports are ignored.
+        assert!(departures.observe(lost, *unwrap!(observers.next()), &group));
+        assert!(departures.has_departed(&lost));
+        for observer in observers {
+            assert!(!departures.observe(lost, *observer, &group));
+        }
+    }
+
+    #[test]
+    fn rejoined_member_can_depart_again() {
+        let group = group(3);
+        let observers: Vec<XorName> = group.iter().cloned().collect();
+        let lost = rand::random();
+        let mut departures = DepartureConsensus::new();
+        assert!(!departures.observe(lost, observers[0], &group));
+        assert!(departures.observe(lost, observers[1], &group));
+        assert!(!departures.observe(lost, observers[2], &group));
+
+        // Once the member is back, its next departure needs a new quorum.
+        departures.rejoined(&lost);
+        assert!(!departures.has_departed(&lost));
+        assert!(!departures.observe(lost, observers[2], &group));
+        assert!(departures.observe(lost, observers[0], &group));
+    }
+}
@@ -0,0 +1,377 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Sharing a `Node` between multiple application threads.
+//!
+//! A `RoutingDispatcher` is pumped on the thread owning the node or client. Other threads send
+//! requests via cloned `DispatcherHandle`s and each receive the responses to their own requests
+//! only, while all other events are broadcast to the subscribers interested in them.
+
+use client::Client;
+use error::InterfaceError;
+use event::{CoalescingKey, Event};
+use event_stream::EventStream;
+use messages::{Request, Response};
+use node::Node;
+use routing_table::Authority;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, RecvError, Sender};
+use types::MessageId;
+use xor_name::XorName;
+
+const CHURN: u8 = 0b0001;
+const REQUESTS: u8 = 0b0010;
+const UNMATCHED_RESPONSES: u8 = 0b0100;
+const STATUS: u8 = 0b1000;
+const JOIN_PROGRESS: u8 = 0b1_0000;
+
+/// Selects the kinds of events a subscriber of a `RoutingDispatcher` receives.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct EventMask(u8);
+
+impl EventMask {
+    /// Changes to the routing table and our section: `NodeAdded`, `NodeLost`, `NodeDeparted`,
+    /// `SectionSplit` and `SectionMerge`.
+    pub fn churn() -> EventMask {
+        EventMask(CHURN)
+    }
+
+    /// Incoming requests, including `Refresh` requests, and their cancellations.
+    pub fn requests() -> EventMask {
+        EventMask(REQUESTS)
+    }
+
+    /// Responses which no `RequestHandle` is waiting for.
+    pub fn unmatched_responses() -> EventMask {
+        EventMask(UNMATCHED_RESPONSES)
+    }
+
+    /// All other events, which concern the node's connection status, e.g. `Connected`,
+    /// `RestartRequired` or `Tick`.
+    pub fn status() -> EventMask {
+        EventMask(STATUS)
+    }
+
+    /// The `JoinProgress` steps enabled via `NodeBuilder::join_progress_events`.
+    pub fn join_progress() -> EventMask {
+        EventMask(JOIN_PROGRESS)
+    }
+
+    /// All events not received via a `RequestHandle`.
+    pub fn all() -> EventMask {
+        EventMask(CHURN | REQUESTS | UNMATCHED_RESPONSES | STATUS | JOIN_PROGRESS)
+    }
+
+    /// Returns the mask selecting the events selected by either `self` or `other`.
+    pub fn with(self, other: EventMask) -> EventMask {
+        EventMask(self.0 | other.0)
+    }
+
+    /// Returns whether the given event is selected by this mask.
+    pub fn matches(&self, event: &Event) -> bool {
+        let kind = match *event {
+            Event::NodeAdded(..) |
+            Event::NodeLost(..) |
+            Event::NodeDeparted(..) |
+            Event::SectionSplit(..) |
+            Event::SectionMerge(..) |
+            Event::Coalesced { key: CoalescingKey::Node(_), .. } => CHURN,
+            Event::Request { .. } |
+            Event::RequestCancelled { .. } => REQUESTS,
+            Event::Response { .. } => UNMATCHED_RESPONSES,
+            Event::JoinProgress(_) => JOIN_PROGRESS,
+            _ => STATUS,
+        };
+        self.0 & kind != 0
+    }
+}
+
+/// Distributes the events raised by a `Node` between multiple application threads.
+///
+/// Responses are delivered to the `RequestHandle` returned when the request with the same
+/// `MessageId` was sent via a `DispatcherHandle`. All other events are sent to each subscriber
+/// whose `EventMask` selects them.
+pub struct RoutingDispatcher {
+    shared: Arc<Mutex<Shared>>,
+    request_tx: Sender<QueuedRequest>,
+    request_rx: Receiver<QueuedRequest>,
+}
+
+impl RoutingDispatcher {
+    /// Creates a dispatcher without any pending requests or subscribers.
+    pub fn new() -> RoutingDispatcher {
+        let (request_tx, request_rx) = mpsc::channel();
+        RoutingDispatcher {
+            shared: Arc::new(Mutex::new(Shared::default())),
+            request_tx: request_tx,
+            request_rx: request_rx,
+        }
+    }
+
+    /// Returns a new handle to send requests via this dispatcher, e.g. from another thread.
+    pub fn handle(&self) -> DispatcherHandle {
+        DispatcherHandle {
+            shared: self.shared.clone(),
+            request_tx: self.request_tx.clone(),
+        }
+    }
+
+    /// Returns a receiver of all events selected by `mask` which are dispatched from now on.
+    pub fn subscribe(&self, mask: EventMask) -> Receiver<Event> {
+        subscribe(&self.shared, mask)
+    }
+
+    /// Sends all requests queued via the handles using `routing`, then dispatches all events it
+    /// has raised so far. Returns whether there were any requests or events.
+    ///
+    /// If a request can't be sent, its `RequestHandle` won't receive any response.
+    pub fn pump<T: Dispatchable>(&self, routing: &mut T) -> bool {
+        let mut result = false;
+        while let Ok(queued) = self.request_rx.try_recv() {
+            result = true;
+            let message_id = queued.request.message_id();
+            if let Err(error) = routing.send_request(queued.src, queued.dst, queued.request) {
+                debug!("Failed to send request {:?}: {:?}", message_id, error);
+                let _ = unwrap!(self.shared.lock()).waiters.remove(&message_id);
+            }
+        }
+        while let Some(event) = routing.try_next_event() {
+            result = true;
+            self.dispatch(event);
+        }
+        result
+    }
+
+    /// Delivers the event to the `RequestHandle` waiting for it, or else to the subscribers whose
+    /// masks select it.
+    pub fn dispatch(&self, event: Event) {
+        let mut shared = unwrap!(self.shared.lock());
+        if let Event::Response { ref response, .. } = event {
+            if let Some(waiter) = shared.waiters.remove(&response.message_id()) {
+                let _ = waiter.send(response.clone());
+                return;
+            }
+        }
+        // Subscribers whose receivers have been dropped are removed.
+        shared
+            .subscribers
+            .retain(|&(ref mask, ref event_tx)| {
+                        !mask.matches(&event) || event_tx.send(event.clone()).is_ok()
+                    });
+    }
+}
+
+impl Default for RoutingDispatcher {
+    fn default() -> RoutingDispatcher {
+        RoutingDispatcher::new()
+    }
+}
+
+/// A `Node` or `Client` which a `RoutingDispatcher` sends requests with and takes events from.
+pub trait Dispatchable {
+    /// Sends `request` from `src` to `dst`. A client ignores `src`, as it always sends as itself.
+    fn send_request(&mut self,
+                    src: Authority<XorName>,
+                    dst: Authority<XorName>,
+                    request: Request)
+                    -> Result<(), InterfaceError>;
+
+    /// Returns the next event raised, if any. A client which delivers its events to an
+    /// `EventSink` never returns any here: pass them to `RoutingDispatcher::dispatch` instead.
+    fn try_next_event(&mut self) -> Option<Event>;
+}
+
+impl Dispatchable for Node {
+    fn send_request(&mut self,
+                    src: Authority<XorName>,
+                    dst: Authority<XorName>,
+                    request: Request)
+                    -> Result<(), InterfaceError> {
+        Node::send_request(self, src, dst, request)
+    }
+
+    fn try_next_event(&mut self) -> Option<Event> {
+        self.try_next_ev().ok()
+    }
+}
+
+impl Dispatchable for Client {
+    fn send_request(&mut self,
+                    _src: Authority<XorName>,
+                    dst: Authority<XorName>,
+                    request: Request)
+                    -> Result<(), InterfaceError> {
+        Client::send_request(self, dst, request)
+    }
+
+    #[cfg(feature = "use-mock-crust")]
+    fn try_next_event(&mut self) -> Option<Event> {
+        self.try_next_ev().ok()
+    }
+
+    #[cfg(not(feature = "use-mock-crust"))]
+    fn try_next_event(&mut self) -> Option<Event> {
+        None
+    }
+}
+
+/// A cheaply cloneable handle to send requests via a `RoutingDispatcher`.
+#[derive(Clone)]
+pub struct DispatcherHandle {
+    shared: Arc<Mutex<Shared>>,
+    request_tx: Sender<QueuedRequest>,
+}
+
+impl DispatcherHandle {
+    /// Queues the request to be sent from `src` to `dst` the next time the dispatcher is pumped,
+    /// and returns a handle to receive the response with the request's `MessageId`.
+    pub fn send_request(&self,
+                        src: Authority<XorName>,
+                        dst: Authority<XorName>,
+                        request: Request)
+                        -> RequestHandle {
+        let message_id = request.message_id();
+        let (response_tx, response_rx) = mpsc::channel();
+        let _ = unwrap!(self.shared.lock())
+            .waiters
+            .insert(message_id, response_tx);
+        let _ = self.request_tx
+            .send(QueuedRequest {
+                      src: src,
+                      dst: dst,
+                      request: request,
+                  });
+        RequestHandle {
+            message_id: message_id,
+            response_rx: response_rx,
+        }
+    }
+
+    /// Returns a receiver of all events selected by `mask` which are dispatched from now on.
+    pub fn subscribe(&self, mask: EventMask) -> Receiver<Event> {
+        subscribe(&self.shared, mask)
+    }
+}
+
+/// The pending response to a request sent via a `DispatcherHandle`.
+pub struct RequestHandle {
+    message_id: MessageId,
+    response_rx: Receiver<Response>,
+}
+
+impl RequestHandle {
+    /// Returns the `MessageId` of the request.
+    pub fn message_id(&self) -> MessageId {
+        self.message_id
+    }
+
+    /// Blocks until the response has been dispatched. Returns an error if it never will be,
+    /// because the request couldn't be sent or the dispatcher has been dropped.
+    pub fn wait(self) -> Result<Response, RecvError> {
+        self.response_rx.recv()
+    }
+
+    /// Returns the response if it has been dispatched already.
+    pub fn try_response(&self) -> Option<Response> {
+        self.response_rx.try_recv().ok()
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    waiters: HashMap<MessageId, Sender<Response>>,
+    subscribers: Vec<(EventMask, Sender<Event>)>,
+}
+
+struct QueuedRequest {
+    src: Authority<XorName>,
+    dst: Authority<XorName>,
+    request: Request,
+}
+
+fn subscribe(shared: &Mutex<Shared>, mask: EventMask) -> Receiver<Event> {
+    let (event_tx, event_rx) = mpsc::channel();
+    unwrap!(shared.lock()).subscribers.push((mask, event_tx));
+    event_rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data::DataIdentifier;
+    use rand;
+
+    fn response_event(response: Response) -> Event {
+        Event::Response {
+            response: response,
+            src: Authority::NaeManager(rand::random()),
+            dst: Authority::ManagedNode(rand::random()),
+        }
+    }
+
+    #[test]
+    fn responses_reach_their_request_handles() {
+        let dispatcher = RoutingDispatcher::new();
+        let handle = dispatcher.handle();
+        let event_rx = dispatcher.subscribe(EventMask::all());
+        let src = Authority::ManagedNode(rand::random());
+        let dst = Authority::NaeManager(rand::random());
+        let data_id = DataIdentifier::Immutable(rand::random());
+        let request_handles = (0..2)
+            .map(|_| handle.send_request(src, dst, Request::Get(data_id, MessageId::new())))
+            .collect::<Vec<_>>();
+
+        let failure = |id| {
+            Response::GetFailure {
+                id: id,
+                data_id: data_id,
+                external_error_indicator: vec![],
+            }
+        };
+        dispatcher.dispatch(response_event(failure(request_handles[1].message_id())));
+        assert_eq!(request_handles[0].try_response(), None);
+        assert_eq!(request_handles[1].try_response(),
+                   Some(failure(request_handles[1].message_id())));
+
+        // A second response with the same ID, or one without a request, goes to the subscribers.
+        let unmatched = response_event(failure(request_handles[1].message_id()));
+        dispatcher.dispatch(unmatched.clone());
+        assert_eq!(event_rx.try_recv(), Ok(unmatched));
+    }
+
+    #[test]
+    fn subscribers_only_receive_selected_events() {
+        let dispatcher = RoutingDispatcher::new();
+        let churn_rx = dispatcher.subscribe(EventMask::churn());
+        let status_rx = dispatcher
+            .handle()
+            .subscribe(EventMask::status().with(EventMask::requests()));
+        let prefix = Default::default();
+
+        dispatcher.dispatch(Event::Tick);
+        dispatcher.dispatch(Event::SectionSplit(prefix));
+        assert_eq!(churn_rx.try_iter().collect::<Vec<_>>(),
+                   vec![Event::SectionSplit(prefix)]);
+        assert_eq!(status_rx.try_iter().collect::<Vec<_>>(), vec![Event::Tick]);
+
+        // Dropped subscribers are removed.
+        drop(churn_rx);
+        dispatcher.dispatch(Event::SectionMerge(prefix));
+        assert_eq!(unwrap!(dispatcher.shared.lock()).subscribers.len(), 1);
+    }
+}
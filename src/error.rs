@@ -18,6 +18,7 @@
 use super::routing_table::Error as RoutingTableError;
 use action::Action;
 use crust::CrustError;
+use crypto::CryptoError;
 use event::Event;
 use id::PublicId;
 use maidsafe_utilities::event_sender::{EventSenderError, MaidSafeEventCategory};
@@ -37,6 +38,8 @@ pub enum InterfaceError {
     ChannelRxError(RecvError),
     /// Error while trying to transmit an event via a channel
     EventSenderError(EventSenderError<MaidSafeEventCategory, Action>),
+    /// No request with the given `MessageId` was sent recently, or it was cancelled already.
+    UnknownRequest,
 }
 
 impl From<EventSenderError<MaidSafeEventCategory, Action>> for InterfaceError {
@@ -128,6 +131,13 @@ pub enum RoutingError {
     InvalidMessage,
     /// Invalid Peer
     InvalidPeer,
+    /// Received a connection info response which doesn't match any request we sent, or connection
+    /// info from a peer which shouldn't be connecting to us as a node
+    UnexpectedConnectionInfo,
+    /// A configured parameter is out of its valid range
+    InvalidConfig,
+    /// The crypto library failed to initialise or to sign
+    Crypto(CryptoError),
 }
 
 impl From<RoutingTableError> for RoutingError {
@@ -160,6 +170,12 @@ impl From<CrustError> for RoutingError {
     }
 }
 
+impl From<CryptoError> for RoutingError {
+    fn from(error: CryptoError) -> RoutingError {
+        RoutingError::Crypto(error)
+    }
+}
+
 impl From<SendError<Event>> for RoutingError {
     fn from(error: SendError<Event>) -> RoutingError {
         RoutingError::SendEventError(error)
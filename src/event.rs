@@ -15,10 +15,16 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
+use convergence::ConvergenceSnapshot;
+use id::PublicId;
 use messages::{Request, Response};
+use processing_stats::SlowMessageReport;
 use routing_table::{Prefix, RoutingTable};
 use routing_table::Authority;
 use std::fmt::{self, Debug, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use tunables::EffectiveConfig;
+use types::MessageId;
 use xor_name::XorName;
 
 /// An Event raised by a `Node` or `Client` via its event sender.
@@ -50,26 +56,334 @@ pub enum Event {
         /// The destination authority that receives the response.
         dst: Authority<XorName>,
     },
+    /// The client which sent us the request with the given ID has cancelled it, so there is no
+    /// need to handle it any further or to respond.
+    RequestCancelled {
+        /// The ID of the cancelled request.
+        message_id: MessageId,
+    },
+    /// A user message we sent to the given client authority couldn't be delivered, because the
+    /// client is no longer connected to its proxy node. Reported by the proxy node, possibly more
+    /// than once if the message was sent via several routes.
+    ClientUnreachable(Authority<XorName>),
     /// A node has connected to us.
     NodeAdded(XorName, RoutingTable<XorName>),
     /// A node has disconnected from us.
     NodeLost(XorName, RoutingTable<XorName>),
+    /// A quorum of our section lost their connections to the given member, so it is considered
+    /// to have left the network. Only raised if enabled via `NodeBuilder::departure_consensus`,
+    /// and at most once per departure.
+    NodeDeparted(XorName),
     /// Our own section has been split, resulting in the included `Prefix` for our new section.
     SectionSplit(Prefix<XorName>),
     /// Our own section requires merged with others, resulting in the included `Prefix` for our new
     /// section.
     SectionMerge(Prefix<XorName>),
+    /// Received a message from the given peer which couldn't be deserialised. Contains the
+    /// message's length in bytes.
+    MalformedMessage(PublicId, usize),
+    /// The node has made progress towards joining the network. Only raised for the kinds of steps
+    /// selected via `NodeBuilder::join_progress_events`.
+    JoinProgress(JoinProgress),
+    /// The health of our connectivity to our own section has changed. Only raised once approved,
+    /// and only if enabled via `NodeBuilder::health_events`.
+    HealthChanged {
+        /// The new health state.
+        health: Health,
+        /// The number of members of our section we are connected to, including ourselves.
+        connected: usize,
+        /// The number of section members expected for full health, i.e. the min section size.
+        expected: usize,
+    },
+    /// Refused a direct connection to the given peer, e.g. because it would exceed a limit or
+    /// quota. Refused nodes won't be added to our routing table, and refused clients can't use us
+    /// as their proxy.
+    PeerRefused(XorName, RefusalReason),
+    /// A periodic audit found discrepancies between our routing table and Crust's live
+    /// connections. Only raised if enabled via `NodeBuilder::connection_audit`, and if anything
+    /// was found.
+    ConnectionAudit(AuditReport),
+    /// Processing a single received message took longer than the threshold set via
+    /// `NodeBuilder::slow_message_reports`. At most one is raised every ten seconds; slow messages
+    /// in between are counted in the next report.
+    SlowMessage(SlowMessageReport),
+    /// Signing a message we were asked to send failed, so it was dropped without sending any of
+    /// it.
+    SigningFailed {
+        /// The source authority of the message.
+        src: Authority<XorName>,
+        /// The destination authority of the message.
+        dst: Authority<XorName>,
+    },
+    /// A batch of messages passed to `Node::send_request_batch` has been handed to the network.
+    BatchSent {
+        /// The ID the batch was submitted with.
+        batch_id: u64,
+        /// The destinations the messages were sent to.
+        succeeded: Vec<Authority<XorName>>,
+        /// The destinations the messages couldn't be sent to.
+        failed: Vec<Authority<XorName>>,
+    },
+    /// A message with an ID assigned by `Node::message_id_for` has been handed to the network.
+    MessageSent {
+        /// The message's ID.
+        msg_id: MessageId,
+        /// The destination the message was sent to.
+        dst: Authority<XorName>,
+        /// Whether this was a retry, and the wire messages of the first send were resent.
+        resent: bool,
+    },
+    /// Settings passed to `Node::update_config` have been applied. Contains all settings now in
+    /// effect.
+    ConfigUpdated(EffectiveConfig),
+    /// Some of the settings passed to `Node::update_config` were refused and left as they were.
+    /// This is followed by `Event::ConfigUpdated` with the ones which were applied.
+    ConfigRefused(Vec<RefusedSetting>),
+    /// Our routing table has converged, with the given metrics. Raised once per episode: after the
+    /// next change to the table, it is raised again once the table has converged anew. Only raised
+    /// if enabled via `NodeBuilder::convergence_tracking`.
+    TableConverged(ConvergenceSnapshot),
+    /// All the other members of our section use the given wire format version, the newest one we
+    /// accept, so support for older versions can be dropped. Raised again if some member uses an
+    /// older version and later stops. Only raised if older versions are accepted too, as set via
+    /// `NodeBuilder::wire_versions`.
+    WireUpgraded(u8),
+    /// The node started from the snapshot loaded from the store set via
+    /// `NodeBuilder::state_persistence`, using the keys saved in it if they were included.
+    /// Contains the peers it had been connected to.
+    StateRestored(Vec<PublicId>),
+    /// The snapshot loaded from the store set via `NodeBuilder::state_persistence` was discarded
+    /// for the given reason, and the node started afresh.
+    StateDiscarded(SnapshotRejection),
+    /// Events with the given key were withheld because they repeated within the window set via
+    /// `NodeBuilder::event_coalescing`. Raised once the window has closed, with their number. If
+    /// the last of them changed the state reported before the window opened, it is raised right
+    /// before this.
+    Coalesced {
+        /// What the withheld events were about.
+        key: CoalescingKey,
+        /// The number of withheld events.
+        count: usize,
+    },
     /// The client has successfully connected to a proxy node on the network.
     Connected,
+    /// The node has enough routing table entries and has disconnected from its proxy node.
+    ProxyDropped,
+    /// Failed to bootstrap off any of our contacts, for the given reasons. This is followed by
+    /// `Event::Terminate`.
+    BootstrapFailed(Vec<(SocketAddr, BootstrapFailure)>),
+    /// The node lost all its routing connections and is trying to rejoin its section. Only raised
+    /// if enabled via `NodeBuilder::disconnected_recovery`; otherwise `Event::RestartRequired`
+    /// is raised instead.
+    Disconnected,
+    /// The node has rejoined its section after `Event::Disconnected`, and sent the user messages
+    /// queued in the meantime.
+    Reconnected,
     /// Disconnected or failed to connect - restart required.
     RestartRequired,
-    /// Startup failed - terminate.
+    /// The node has shut down, either because startup failed or because it was asked to via
+    /// `terminate`. No further events are raised.
     Terminate,
     // TODO: Find a better solution for periodic tasks.
     /// This event is sent periodically every time Routing sends the `Heartbeat` messages.
     Tick,
 }
 
+/// A step a node goes through while joining the network, in the order they normally occur. Each
+/// is raised once per transition: the bootstrap steps once before and once after relocation, and
+/// `BootstrapContactAttempt` again for each retry.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum JoinProgress {
+    /// A bootstrap contact accepted our connection, and we are trying to join through it.
+    BootstrapContactAttempt {
+        /// The contact's address.
+        contact: SocketAddr,
+        /// The number of the attempt, starting at 1 and counting up whenever a contact fails and
+        /// we retry with another one.
+        attempt: u32,
+    },
+    /// The contact identified itself and is now our proxy node, with the given name.
+    BootstrapConnected(XorName),
+    /// Sent a request to the network to be relocated to a new section.
+    RelocationRequested,
+    /// Received our relocated name, and are about to bootstrap again using it.
+    RelocationReceived(XorName),
+    /// Connected to another peer while awaiting approval by our new section.
+    CloseGroupConnecting {
+        /// The number of peers we are connected to.
+        connected: usize,
+        /// The number of members of our new section, which we need to connect to.
+        required: usize,
+    },
+    /// Approved by our new section. This is followed by `Event::Connected`.
+    JoinComplete,
+}
+
+const BOOTSTRAP_STEPS: u8 = 0b0001;
+const RELOCATION_STEPS: u8 = 0b0010;
+const CLOSE_GROUP_STEPS: u8 = 0b0100;
+const COMPLETION_STEPS: u8 = 0b1000;
+
+/// Selects the kinds of `JoinProgress` steps raised, as set via
+/// `NodeBuilder::join_progress_events`. The default selects none.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct JoinProgressMask(u8);
+
+impl JoinProgressMask {
+    /// `BootstrapContactAttempt` and `BootstrapConnected`.
+    pub fn bootstrap() -> JoinProgressMask {
+        JoinProgressMask(BOOTSTRAP_STEPS)
+    }
+
+    /// `RelocationRequested` and `RelocationReceived`.
+    pub fn relocation() -> JoinProgressMask {
+        JoinProgressMask(RELOCATION_STEPS)
+    }
+
+    /// `CloseGroupConnecting`.
+    pub fn close_group() -> JoinProgressMask {
+        JoinProgressMask(CLOSE_GROUP_STEPS)
+    }
+
+    /// `JoinComplete`.
+    pub fn completion() -> JoinProgressMask {
+        JoinProgressMask(COMPLETION_STEPS)
+    }
+
+    /// All steps.
+    pub fn all() -> JoinProgressMask {
+        JoinProgressMask(BOOTSTRAP_STEPS | RELOCATION_STEPS | CLOSE_GROUP_STEPS | COMPLETION_STEPS)
+    }
+
+    /// Returns the mask selecting the steps selected by either `self` or `other`.
+    pub fn with(self, other: JoinProgressMask) -> JoinProgressMask {
+        JoinProgressMask(self.0 | other.0)
+    }
+
+    /// Returns whether no steps are selected.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns whether the given step is selected by this mask.
+    pub fn matches(&self, step: &JoinProgress) -> bool {
+        let bit = match *step {
+            JoinProgress::BootstrapContactAttempt { .. } |
+            JoinProgress::BootstrapConnected(_) => BOOTSTRAP_STEPS,
+            JoinProgress::RelocationRequested |
+            JoinProgress::RelocationReceived(_) => RELOCATION_STEPS,
+            JoinProgress::CloseGroupConnecting { .. } => CLOSE_GROUP_STEPS,
+            JoinProgress::JoinComplete => COMPLETION_STEPS,
+        };
+        self.0 & bit != 0
+    }
+}
+
+/// Why bootstrapping off a contact failed, as reported in `Event::BootstrapFailed`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BootstrapFailure {
+    /// The contact isn't accepting connections.
+    NotListening,
+    /// The contact doesn't accept our kind of peer.
+    Refused,
+    /// The contact couldn't be reached.
+    Unreachable,
+    /// The connection to the contact was lost before it identified itself.
+    Lost,
+    /// The contact doesn't have enough routing table entries to accept clients yet.
+    Denied,
+    /// The contact didn't identify itself in time.
+    Timeout,
+}
+
+/// How reliably a node is connected to its own section, as reported in `Event::HealthChanged`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Health {
+    /// Connected to at least min section size members of our section, including ourselves.
+    Healthy,
+    /// Connected to a quorum of min section size members of our section, but not to all of them.
+    Degraded,
+    /// Connected to less than a quorum of min section size members of our section.
+    Critical,
+}
+
+/// The reason for refusing a connection to a peer, as reported in `Event::PeerRefused`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum RefusalReason {
+    /// Too many of our routing table entries are connected from the peer's IP address, which is
+    /// included.
+    IpLimit(IpAddr),
+    /// Too many of our routing table entries are connected from the peer's subnet. Contains the
+    /// peer's IP address.
+    SubnetLimit(IpAddr),
+    /// We already act as a proxy for as many clients as the quota allows.
+    ClientQuota,
+    /// We already hold as many connections to unidentified peers as the quota allows.
+    UnidentifiedQuota,
+    /// We already have as many routing table entries outside our section as the quota allows.
+    FarContactQuota,
+}
+
+/// What repetitive events are coalesced by, as reported in `Event::Coalesced`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum CoalescingKey {
+    /// `NodeAdded` and `NodeLost` events for the named peer.
+    Node(XorName),
+    /// `HealthChanged` events.
+    Health,
+}
+
+/// Why a saved snapshot was discarded, as reported in `Event::StateDiscarded`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SnapshotRejection {
+    /// The store failed to load the snapshot, with the given error.
+    Unreadable(String),
+    /// The snapshot couldn't be parsed.
+    Corrupt,
+    /// The snapshot was taken by a node started with different settings.
+    ConfigMismatch,
+    /// The saved public keys don't belong to the saved secret keys.
+    KeyMismatch,
+}
+
+/// A setting passed to `Node::update_config` which was refused, as reported in
+/// `Event::ConfigRefused`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RefusedSetting {
+    /// The name of the setting, as in `EffectiveConfig`.
+    pub field: &'static str,
+    /// Why the setting was refused.
+    pub reason: ConfigRefusal,
+}
+
+/// Why a setting passed to `Node::update_config` was refused.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ConfigRefusal {
+    /// The setting is only read when the node starts.
+    NotLive,
+    /// The value is out of range or conflicts with other settings, as described.
+    Invalid(String),
+}
+
+/// The discrepancies found by a connection audit, as reported in `Event::ConnectionAudit`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct AuditReport {
+    /// Routing table entries without a live connection, which have been dropped.
+    pub dropped_entries: Vec<XorName>,
+    /// Live connections to peers we didn't know about, which have been closed.
+    pub closed_connections: Vec<XorName>,
+    /// Discrepancies which haven't outlasted the grace period yet, and are left as they are.
+    pub pending: Vec<XorName>,
+}
+
+impl AuditReport {
+    /// Returns whether the audit found no discrepancies at all.
+    pub fn is_empty(&self) -> bool {
+        self.dropped_entries.is_empty() && self.closed_connections.is_empty() &&
+        self.pending.is_empty()
+    }
+}
+
 impl Debug for Event {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         match *self {
@@ -95,6 +409,14 @@ impl Debug for Event {
                        src,
                        dst)
             }
+            Event::RequestCancelled { ref message_id } => {
+                write!(formatter,
+                       "Event::RequestCancelled {{ message_id: {:?} }}",
+                       message_id)
+            }
+            Event::ClientUnreachable(ref client) => {
+                write!(formatter, "Event::ClientUnreachable({:?})", client)
+            }
             Event::NodeAdded(ref node_name, _) => {
                 write!(formatter,
                        "Event::NodeAdded({:?}, routing_table)",
@@ -103,13 +425,96 @@ impl Debug for Event {
             Event::NodeLost(ref node_name, _) => {
                 write!(formatter, "Event::NodeLost({:?}, routing_table)", node_name)
             }
+            Event::NodeDeparted(ref node_name) => {
+                write!(formatter, "Event::NodeDeparted({:?})", node_name)
+            }
             Event::SectionSplit(ref prefix) => {
                 write!(formatter, "Event::SectionSplit({:?})", prefix)
             }
             Event::SectionMerge(ref prefix) => {
                 write!(formatter, "Event::SectionMerge({:?})", prefix)
             }
+            Event::MalformedMessage(ref pub_id, len) => {
+                write!(formatter, "Event::MalformedMessage({:?}, {})", pub_id, len)
+            }
+            Event::JoinProgress(ref progress) => {
+                write!(formatter, "Event::JoinProgress({:?})", progress)
+            }
+            Event::HealthChanged {
+                health,
+                connected,
+                expected,
+            } => {
+                write!(formatter,
+                       "Event::HealthChanged {{ health: {:?}, connected: {}, expected: {} }}",
+                       health,
+                       connected,
+                       expected)
+            }
+            Event::PeerRefused(ref name, ref reason) => {
+                write!(formatter, "Event::PeerRefused({:?}, {:?})", name, reason)
+            }
+            Event::ConnectionAudit(ref report) => {
+                write!(formatter, "Event::ConnectionAudit({:?})", report)
+            }
+            Event::SlowMessage(ref report) => write!(formatter, "Event::SlowMessage({:?})", report),
+            Event::SigningFailed { ref src, ref dst } => {
+                write!(formatter,
+                       "Event::SigningFailed {{ src: {:?}, dst: {:?} }}",
+                       src,
+                       dst)
+            }
+            Event::BatchSent {
+                batch_id,
+                ref succeeded,
+                ref failed,
+            } => {
+                write!(formatter,
+                       "Event::BatchSent {{ batch_id: {}, succeeded: {:?}, failed: {:?} }}",
+                       batch_id,
+                       succeeded,
+                       failed)
+            }
+            Event::MessageSent {
+                msg_id,
+                ref dst,
+                resent,
+            } => {
+                write!(formatter,
+                       "Event::MessageSent {{ msg_id: {:?}, dst: {:?}, resent: {} }}",
+                       msg_id,
+                       dst,
+                       resent)
+            }
+            Event::ConfigUpdated(ref config) => {
+                write!(formatter, "Event::ConfigUpdated({:?})", config)
+            }
+            Event::ConfigRefused(ref refused) => {
+                write!(formatter, "Event::ConfigRefused({:?})", refused)
+            }
+            Event::TableConverged(ref snapshot) => {
+                write!(formatter, "Event::TableConverged({:?})", snapshot)
+            }
+            Event::WireUpgraded(version) => write!(formatter, "Event::WireUpgraded({})", version),
+            Event::StateRestored(ref contacts) => {
+                write!(formatter, "Event::StateRestored({:?})", contacts)
+            }
+            Event::StateDiscarded(ref reason) => {
+                write!(formatter, "Event::StateDiscarded({:?})", reason)
+            }
+            Event::Coalesced { key, count } => {
+                write!(formatter,
+                       "Event::Coalesced {{ key: {:?}, count: {} }}",
+                       key,
+                       count)
+            }
             Event::Connected => write!(formatter, "Event::Connected"),
+            Event::ProxyDropped => write!(formatter, "Event::ProxyDropped"),
+            Event::BootstrapFailed(ref failures) => {
+                write!(formatter, "Event::BootstrapFailed({:?})", failures)
+            }
+            Event::Disconnected => write!(formatter, "Event::Disconnected"),
+            Event::Reconnected => write!(formatter, "Event::Reconnected"),
             Event::RestartRequired => write!(formatter, "Event::RestartRequired"),
             Event::Terminate => write!(formatter, "Event::Terminate"),
             Event::Tick => write!(formatter, "Event::Tick"),
@@ -0,0 +1,269 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Coalescing bursts of repetitive events, as enabled via `NodeBuilder::event_coalescing`.
+
+use event::{CoalescingKey, Event};
+#[cfg(feature="use-mock-crust")]
+use fake_clock::FakeClock as Instant;
+use routing_table::RoutingTable;
+use std::collections::BTreeMap;
+use std::time::Duration;
+#[cfg(not(feature="use-mock-crust"))]
+use std::time::Instant;
+use timer::Timer;
+use xor_name::XorName;
+
+/// The events with one key since the first of them was passed on.
+struct Window {
+    opened: Instant,
+    /// The event which opened the window.
+    first: Event,
+    /// The last event withheld since, if any.
+    last: Option<Event>,
+    /// The number of events withheld since.
+    withheld: usize,
+}
+
+/// Passes on the first event with each key, and withholds further ones with the same key until
+/// the window has elapsed. Then their number is reported via `Event::Coalesced`, preceded by the
+/// last of them if it changed the state reported by the first one. Events without a key are
+/// always passed on straight away.
+///
+/// A timeout is scheduled on the state machine's timer for each window opened, so that the
+/// machine wakes up to close it even if nothing else happens meanwhile.
+pub struct EventCoalescer {
+    window: Duration,
+    windows: BTreeMap<CoalescingKey, Window>,
+    timer: Timer,
+    /// The routing table reported by the latest `NodeAdded` or `NodeLost` event, withheld or not.
+    table: Option<RoutingTable<XorName>>,
+}
+
+impl EventCoalescer {
+    pub fn new(window: Duration, timer: Timer) -> EventCoalescer {
+        EventCoalescer {
+            window: window,
+            windows: BTreeMap::new(),
+            timer: timer,
+            table: None,
+        }
+    }
+
+    /// Returns the events to pass on now: the summaries of the windows which have closed, and
+    /// the given event unless it is withheld.
+    pub fn admit(&mut self, event: Event) -> Vec<Event> {
+        let mut events = self.close_expired();
+        let key = match coalescing_key(&event) {
+            Some(key) => key,
+            None => {
+                events.push(event);
+                return events;
+            }
+        };
+        match event {
+            Event::NodeAdded(_, ref table) |
+            Event::NodeLost(_, ref table) => self.table = Some(table.clone()),
+            _ => (),
+        }
+        if let Some(window) = self.windows.get_mut(&key) {
+            window.last = Some(event);
+            window.withheld += 1;
+            return events;
+        }
+        let _ = self.windows.insert(key,
+                                    Window {
+                                        opened: Instant::now(),
+                                        first: event.clone(),
+                                        last: None,
+                                        withheld: 0,
+                                    });
+        let _ = self.timer.schedule(self.window);
+        events.push(event);
+        events
+    }
+
+    /// Closes the windows which have elapsed, and returns the events summarising them. A withheld
+    /// `NodeAdded` or `NodeLost` event is passed on with the latest routing table, as the one it
+    /// carried may have been superseded by events passed on since.
+    pub fn close_expired(&mut self) -> Vec<Event> {
+        let duration = self.window;
+        let expired: Vec<CoalescingKey> = self.windows
+            .iter()
+            .filter(|&(_, window)| window.opened.elapsed() >= duration)
+            .map(|(key, _)| *key)
+            .collect();
+        let mut events = Vec::new();
+        for key in expired {
+            let window = match self.windows.remove(&key) {
+                Some(window) => window,
+                None => continue,
+            };
+            if let Some(last) = window.last {
+                if !same_state(&window.first, &last) {
+                    events.push(self.with_latest_table(last));
+                }
+                events.push(Event::Coalesced {
+                                key: key,
+                                count: window.withheld,
+                            });
+            }
+        }
+        events
+    }
+
+    fn with_latest_table(&self, event: Event) -> Event {
+        let table = match self.table {
+            Some(ref table) => table.clone(),
+            None => return event,
+        };
+        match event {
+            Event::NodeAdded(name, _) => Event::NodeAdded(name, table),
+            Event::NodeLost(name, _) => Event::NodeLost(name, table),
+            event => event,
+        }
+    }
+}
+
+/// Returns the key by which the event is coalesced, or `None` if it is never withheld.
+fn coalescing_key(event: &Event) -> Option<CoalescingKey> {
+    match *event {
+        Event::NodeAdded(name, _) |
+        Event::NodeLost(name, _) => Some(CoalescingKey::Node(name)),
+        Event::HealthChanged { .. } => Some(CoalescingKey::Health),
+        _ => None,
+    }
+}
+
+/// Returns whether the second event reports the same state as the first one, e.g. a peer being
+/// added again, or our health returning to where it was.
+fn same_state(first: &Event, second: &Event) -> bool {
+    match (first, second) {
+        (&Event::NodeAdded(..), &Event::NodeAdded(..)) |
+        (&Event::NodeLost(..), &Event::NodeLost(..)) => true,
+        (&Event::HealthChanged { health, .. }, &Event::HealthChanged { health: other, .. }) => {
+            health == other
+        }
+        _ => false,
+    }
+}
+
+#[cfg(all(test, feature = "use-mock-crust"))]
+mod tests {
+    use super::*;
+    use data::DataIdentifier;
+    use event::Health;
+    use fake_clock::FakeClock;
+    use maidsafe_utilities::event_sender::MaidSafeEventCategory;
+    use messages::Request;
+    use rand;
+    use routing_table::Authority;
+    use std::sync::mpsc;
+    use types::{MessageId, RoutingActionSender};
+
+    fn new_timer() -> Timer {
+        let (action_tx, _) = mpsc::channel();
+        let (category_tx, _) = mpsc::channel();
+        Timer::new(RoutingActionSender::new(action_tx, MaidSafeEventCategory::Routing, category_tx))
+    }
+
+    fn health_changed(health: Health, connected: usize) -> Event {
+        Event::HealthChanged {
+            health: health,
+            connected: connected,
+            expected: 8,
+        }
+    }
+
+    #[test]
+    fn flapping_peer_coalesced() {
+        let mut coalescer = EventCoalescer::new(Duration::from_secs(10), new_timer());
+        let name: XorName = rand::random();
+        let table = RoutingTable::new(rand::random(), 8);
+        let lost = Event::NodeLost(name, table.clone());
+        let added = Event::NodeAdded(name, table);
+        assert_eq!(coalescer.admit(lost.clone()), vec![lost.clone()]);
+        assert!(coalescer.timer.has_deadline_within(Duration::from_secs(10)));
+        for _ in 0..3 {
+            assert!(coalescer.admit(added.clone()).is_empty());
+            assert!(coalescer.admit(lost.clone()).is_empty());
+        }
+        assert!(coalescer.admit(added.clone()).is_empty());
+
+        // Requests are never withheld.
+        let request = Event::Request {
+            request: Request::Get(DataIdentifier::Immutable(rand::random()), MessageId::new()),
+            src: Authority::ManagedNode(rand::random()),
+            dst: Authority::ManagedNode(rand::random()),
+        };
+        assert_eq!(coalescer.admit(request.clone()), vec![request]);
+        assert!(coalescer.close_expired().is_empty());
+
+        // Once the window closes, the final state and the count are reported.
+        FakeClock::advance_time(10 * 1000);
+        assert_eq!(coalescer.close_expired(),
+                   vec![added,
+                        Event::Coalesced {
+                            key: CoalescingKey::Node(name),
+                            count: 7,
+                        }]);
+        assert_eq!(coalescer.admit(lost.clone()), vec![lost]);
+    }
+
+    #[test]
+    fn health_returning_to_prior_state_coalesced() {
+        let mut coalescer = EventCoalescer::new(Duration::from_secs(10), new_timer());
+        let degraded = health_changed(Health::Degraded, 6);
+        assert_eq!(coalescer.admit(degraded.clone()), vec![degraded]);
+        assert!(coalescer.admit(health_changed(Health::Healthy, 7)).is_empty());
+        assert!(coalescer.admit(health_changed(Health::Degraded, 6)).is_empty());
+
+        // An unrelated event closes the elapsed window on its way through.
+        FakeClock::advance_time(10 * 1000);
+        assert_eq!(coalescer.admit(Event::Tick),
+                   vec![Event::Coalesced {
+                            key: CoalescingKey::Health,
+                            count: 2,
+                        },
+                        Event::Tick]);
+    }
+
+    #[test]
+    fn withheld_event_raised_with_latest_table() {
+        let mut coalescer = EventCoalescer::new(Duration::from_secs(10), new_timer());
+        let name: XorName = rand::random();
+        let other_name: XorName = rand::random();
+        let stale_table = RoutingTable::new(rand::random(), 8);
+        let latest_table = RoutingTable::new(rand::random(), 8);
+        let lost = Event::NodeLost(name, stale_table.clone());
+        assert_eq!(coalescer.admit(lost.clone()), vec![lost]);
+        assert!(coalescer
+                    .admit(Event::NodeAdded(name, stale_table))
+                    .is_empty());
+        let other_added = Event::NodeAdded(other_name, latest_table.clone());
+        assert_eq!(coalescer.admit(other_added.clone()), vec![other_added]);
+
+        // The withheld event reports the table as of the latest event passed on.
+        FakeClock::advance_time(10 * 1000);
+        assert_eq!(coalescer.close_expired(),
+                   vec![Event::NodeAdded(name, latest_table),
+                        Event::Coalesced {
+                            key: CoalescingKey::Node(name),
+                            count: 1,
+                        }]);
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Pluggable delivery of the events raised by a `Node` or `Client`.
+
+use event::Event;
+use std::collections::VecDeque;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+
+/// Returned by an `EventSink` which can't accept any more events, e.g. because the receiving end
+/// has been dropped.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SinkClosed;
+
+/// A destination for events, e.g. a channel to another thread or a queue polled by the
+/// application.
+pub trait EventSink: Send {
+    /// Delivers the event, or returns `SinkClosed` if no more events can be delivered.
+    fn send(&self, event: Event) -> Result<(), SinkClosed>;
+}
+
+impl EventSink for Sender<Event> {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        Sender::send(self, event).map_err(|_| SinkClosed)
+    }
+}
+
+/// An `EventSink` which stores events in a bounded queue, to be drained by polling it.
+///
+/// Clones share the same queue, so one can be given to a `NodeBuilder` and the other be polled.
+/// Once the queue is full, each new event displaces the oldest one, and the number of events lost
+/// this way is counted.
+#[derive(Clone)]
+pub struct RingBufferSink {
+    inner: Arc<Mutex<RingBuffer>>,
+}
+
+struct RingBuffer {
+    events: VecDeque<Event>,
+    capacity: usize,
+    dropped: u64,
+}
+
+impl RingBufferSink {
+    /// Returns a new sink holding at most `capacity` undrained events.
+    pub fn new(capacity: usize) -> RingBufferSink {
+        RingBufferSink {
+            inner: Arc::new(Mutex::new(RingBuffer {
+                                           events: VecDeque::with_capacity(capacity),
+                                           capacity: capacity,
+                                           dropped: 0,
+                                       })),
+        }
+    }
+
+    /// Removes and returns all events received so far, oldest first.
+    pub fn try_drain(&self) -> Vec<Event> {
+        unwrap!(self.inner.lock()).events.drain(..).collect()
+    }
+
+    /// Returns the number of events that were dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        unwrap!(self.inner.lock()).dropped
+    }
+}
+
+impl EventSink for RingBufferSink {
+    fn send(&self, event: Event) -> Result<(), SinkClosed> {
+        let mut buffer = unwrap!(self.inner.lock());
+        if buffer.capacity == 0 {
+            buffer.dropped += 1;
+            return Ok(());
+        }
+        if buffer.events.len() >= buffer.capacity {
+            let _ = buffer.events.pop_front();
+            buffer.dropped += 1;
+        }
+        buffer.events.push_back(event);
+        Ok(())
+    }
+}
+
+impl Debug for RingBufferSink {
+    fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
+        let buffer = unwrap!(self.inner.lock());
+        write!(formatter,
+               "RingBufferSink {{ {}/{} events, {} dropped }}",
+               buffer.events.len(),
+               buffer.capacity,
+               buffer.dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_drops_oldest() {
+        let sink = RingBufferSink::new(2);
+        let events = vec![Event::Connected, Event::Tick, Event::ProxyDropped];
+        for event in events {
+            unwrap!(EventSink::send(&sink, event));
+        }
+        assert_eq!(sink.dropped(), 1);
+        let drained = sink.try_drain();
+        assert_eq!(drained.len(), 2);
+        match (&drained[0], &drained[1]) {
+            (&Event::Tick, &Event::ProxyDropped) => (),
+            _ => panic!("Unexpected events {:?}", drained),
+        }
+        assert!(sink.try_drain().is_empty());
+    }
+}
@@ -0,0 +1,331 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! A map whose entries expire, for the node's short-lived bookkeeping, which reports its activity
+//! in the node's diagnostics.
+
+#[cfg(feature="use-mock-crust")]
+use fake_clock::FakeClock as Instant;
+use std::collections::BTreeMap;
+use std::time::Duration;
+#[cfg(not(feature="use-mock-crust"))]
+use std::time::Instant;
+
+/// The size and activity of an `ExpiringCache`, as reported in `Diagnostics::caches`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CacheStats {
+    /// The number of entries which haven't expired yet.
+    pub len: usize,
+    /// The number of entries inserted, including replacements of existing keys.
+    pub insertions: usize,
+    /// The number of entries removed because they expired.
+    pub expirations: usize,
+    /// The number of entries evicted to stay within capacity.
+    pub evictions: usize,
+}
+
+struct Entry<V> {
+    value: V,
+    expiry: Instant,
+    /// The number of insertions into the cache up to and including this entry's.
+    seq: u64,
+}
+
+/// A map whose entries expire a fixed time after they were inserted, unless inserted with their
+/// own time to live. If it has a capacity, expired entries and then the least recently inserted
+/// ones are evicted to make room. Looking an entry up neither extends its life nor protects it from
+/// eviction; only inserting it again does.
+///
+/// Expired entries are never returned. They are removed lazily, when accessed or by `sweep`. The
+/// time is only ever compared, never subtracted, so a clock which goes backwards just delays
+/// expiry instead of panicking.
+pub struct ExpiringCache<K, V> {
+    label: &'static str,
+    ttl: Duration,
+    capacity: Option<usize>,
+    entries: BTreeMap<K, Entry<V>>,
+    /// The keys by their entry's `seq`, i.e. in insertion order.
+    by_seq: BTreeMap<u64, K>,
+    next_seq: u64,
+    stats: CacheStats,
+}
+
+impl<K: Clone + Ord, V> ExpiringCache<K, V> {
+    /// Returns an empty cache without a capacity, reporting its stats under `label`.
+    pub fn new(label: &'static str, ttl: Duration) -> ExpiringCache<K, V> {
+        ExpiringCache {
+            label: label,
+            ttl: ttl,
+            capacity: None,
+            entries: BTreeMap::new(),
+            by_seq: BTreeMap::new(),
+            next_seq: 0,
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Returns an empty cache holding at most `capacity` entries, reporting its stats under
+    /// `label`.
+    pub fn with_capacity(label: &'static str,
+                         ttl: Duration,
+                         capacity: usize)
+                         -> ExpiringCache<K, V> {
+        ExpiringCache {
+            capacity: Some(capacity),
+            ..Self::new(label, ttl)
+        }
+    }
+
+    /// Inserts the entry with the cache's time to live, returning the unexpired value it replaces.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let ttl = self.ttl;
+        self.insert_at(key, value, ttl, Instant::now())
+    }
+
+    /// Inserts the entry with the given time to live, returning the unexpired value it replaces.
+    pub fn insert_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.insert_at(key, value, ttl, Instant::now())
+    }
+
+    /// Returns the value if it hasn't expired.
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.get_at(key, Instant::now())
+    }
+
+    /// Returns a mutable reference to the value if it hasn't expired.
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let now = Instant::now();
+        self.entries
+            .get_mut(key)
+            .and_then(|entry| if now < entry.expiry {
+                          Some(&mut entry.value)
+                      } else {
+                          None
+                      })
+    }
+
+    /// Returns whether there is an unexpired entry for the key.
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Removes the entry, returning its value if it hadn't expired.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.remove_at(key, Instant::now())
+    }
+
+    /// Removes the expired entries and returns the number of remaining ones.
+    pub fn len(&mut self) -> usize {
+        let _ = self.sweep_at(Instant::now());
+        self.entries.len()
+    }
+
+    /// Removes the expired entries, returning how many there were. Call this periodically, e.g.
+    /// from a timer, if entries are rarely accessed.
+    pub fn sweep(&mut self) -> usize {
+        self.sweep_at(Instant::now())
+    }
+
+    /// Returns the cache's size and activity.
+    pub fn stats(&self) -> CacheStats {
+        self.stats_at(Instant::now())
+    }
+
+    /// Removes the expired entries and adds the cache's stats to `caches`, under its label.
+    pub fn report(&mut self, caches: &mut BTreeMap<String, CacheStats>) {
+        let _ = self.sweep();
+        let _ = caches.insert(self.label.to_string(), self.stats());
+    }
+
+    fn insert_at(&mut self, key: K, value: V, ttl: Duration, now: Instant) -> Option<V> {
+        let old_value = self.remove_at(&key, now);
+        if let Some(capacity) = self.capacity {
+            if self.entries.len() >= capacity {
+                let _ = self.sweep_at(now);
+            }
+            while !self.entries.is_empty() && self.entries.len() >= capacity {
+                self.evict_oldest();
+            }
+            if capacity == 0 {
+                return old_value;
+            }
+        }
+        self.next_seq += 1;
+        let seq = self.next_seq;
+        let _ = self.by_seq.insert(seq, key.clone());
+        let _ = self.entries.insert(key,
+                                    Entry {
+                                        value: value,
+                                        expiry: now + ttl,
+                                        seq: seq,
+                                    });
+        self.stats.insertions += 1;
+        old_value
+    }
+
+    fn get_at(&self, key: &K, now: Instant) -> Option<&V> {
+        self.entries
+            .get(key)
+            .and_then(|entry| if now < entry.expiry {
+                          Some(&entry.value)
+                      } else {
+                          None
+                      })
+    }
+
+    fn remove_at(&mut self, key: &K, now: Instant) -> Option<V> {
+        let entry = match self.entries.remove(key) {
+            Some(entry) => entry,
+            None => return None,
+        };
+        let _ = self.by_seq.remove(&entry.seq);
+        if now < entry.expiry {
+            Some(entry.value)
+        } else {
+            self.stats.expirations += 1;
+            None
+        }
+    }
+
+    fn sweep_at(&mut self, now: Instant) -> usize {
+        let expired: Vec<K> = self.entries
+            .iter()
+            .filter(|&(_, entry)| now >= entry.expiry)
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &expired {
+            let _ = self.remove_at(key, now);
+        }
+        expired.len()
+    }
+
+    fn stats_at(&self, now: Instant) -> CacheStats {
+        CacheStats {
+            len: self.entries
+                .values()
+                .filter(|entry| now < entry.expiry)
+                .count(),
+            ..self.stats
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        let key = match self.by_seq.iter().next() {
+            Some((_, key)) => key.clone(),
+            None => return,
+        };
+        if let Some(entry) = self.entries.remove(&key) {
+            let _ = self.by_seq.remove(&entry.seq);
+            self.stats.evictions += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL_SECS: u64 = 10;
+
+    #[test]
+    fn entries_expire_after_ttl() {
+        let ttl = Duration::from_secs(TTL_SECS);
+        let mut cache = ExpiringCache::new("test", ttl);
+        let start = Instant::now();
+        assert_eq!(cache.insert_at(1, "a", ttl, start), None);
+        assert_eq!(cache.insert_at(2, "b", ttl * 3 / 2, start), None);
+
+        // An entry is alive until just before its expiry, and gone at it.
+        let almost = start + ttl - Duration::from_millis(1);
+        assert_eq!(cache.get_at(&1, almost), Some(&"a"));
+        assert_eq!(cache.get_at(&1, start + ttl), None);
+        assert_eq!(cache.get_at(&2, start + ttl), Some(&"b"));
+
+        // Inserting an expired key doesn't return the old value, but replacing a live one does.
+        assert_eq!(cache.insert_at(1, "c", ttl, start + ttl), None);
+        assert_eq!(cache.insert_at(1, "d", ttl, start + ttl), Some("c"));
+
+        let later = start + ttl * 3 / 2;
+        assert_eq!(cache.sweep_at(later), 1);
+        assert_eq!(cache.get_at(&1, later), Some(&"d"));
+        assert_eq!(cache.remove_at(&1, start + ttl * 2), None);
+
+        let stats = cache.stats_at(later);
+        assert_eq!(stats.len, 0);
+        assert_eq!(stats.insertions, 4);
+        assert_eq!(stats.expirations, 3);
+        assert_eq!(stats.evictions, 0);
+    }
+
+    #[test]
+    fn oldest_entries_evicted() {
+        let ttl = Duration::from_secs(TTL_SECS);
+        let mut cache = ExpiringCache::with_capacity("test", ttl, 3);
+        let now = Instant::now();
+        for key in 0..3 {
+            let _ = cache.insert_at(key, key, ttl, now);
+        }
+
+        // Reinserting entry 0 makes entry 1 the oldest one.
+        assert_eq!(cache.insert_at(0, 0, ttl, now), Some(0));
+        let _ = cache.insert_at(3, 3, ttl, now);
+        assert_eq!(cache.get_at(&1, now), None);
+        let _ = cache.insert_at(4, 4, ttl, now);
+        assert_eq!(cache.get_at(&2, now), None);
+        for key in &[0, 3, 4] {
+            assert_eq!(cache.get_at(key, now), Some(key));
+        }
+        assert_eq!(cache.stats_at(now).evictions, 2);
+
+        // Expired entries make room before anything is evicted.
+        let _ = cache.insert_at(5, 5, ttl * 2, now);
+        let later = now + ttl;
+        let _ = cache.insert_at(6, 6, ttl, later);
+        assert_eq!(cache.get_at(&5, later), Some(&5));
+        assert_eq!(cache.get_at(&6, later), Some(&6));
+        let stats = cache.stats_at(later);
+        assert_eq!(stats.len, 2);
+        assert_eq!(stats.evictions, 3);
+        assert_eq!(stats.expirations, 2);
+    }
+
+    #[test]
+    fn clock_regression_tolerated() {
+        let ttl = Duration::from_secs(TTL_SECS);
+        let mut cache = ExpiringCache::new("test", ttl);
+        let earlier = Instant::now();
+        let later = earlier + ttl;
+        let _ = cache.insert_at(1, "a", ttl, later);
+
+        // Looking at the entry from before it was inserted finds it alive.
+        assert_eq!(cache.get_at(&1, earlier), Some(&"a"));
+        assert_eq!(cache.sweep_at(earlier), 0);
+        assert_eq!(cache.stats_at(earlier).len, 1);
+        assert_eq!(cache.remove_at(&1, earlier), Some("a"));
+    }
+
+    #[test]
+    fn stats_reported_under_label() {
+        let mut cache = ExpiringCache::new("label", Duration::from_secs(TTL_SECS));
+        let _ = cache.insert(1, ());
+        let mut caches = BTreeMap::new();
+        cache.report(&mut caches);
+        assert_eq!(caches.len(), 1);
+        assert_eq!(caches["label"].len, 1);
+        assert_eq!(caches["label"].insertions, 1);
+    }
+}
@@ -0,0 +1,62 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+
+//! Reporting the peers a node chooses to send each routing message to, e.g. to drive an external
+//! network simulator.
+
+use id::PublicId;
+use routing_table::Authority;
+use std::sync::mpsc::Sender;
+use xor_name::XorName;
+
+/// Why a routing message is sent to the chosen hops.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ForwardingReason {
+    /// The message was sent by us, e.g. a user request or an ack.
+    Originated,
+    /// The message was received from another peer and is passed on towards its destination.
+    Forwarded,
+    /// The message is delivered to a client or joining node we are the proxy of.
+    RelayedToClient,
+}
+
+/// The peers a node chose to send a routing message to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ForwardingDecision {
+    /// The hash identifying the message, the same on every node. See `message_hash`.
+    pub message_hash: u64,
+    /// The message's destination authority.
+    pub destination: Authority<XorName>,
+    /// The name of each chosen target, and the peer the message is sent to for it: the target
+    /// itself, or the tunnel node if it is only reachable via a tunnel.
+    pub chosen_hops: Vec<(XorName, PublicId)>,
+    /// Why the message is sent.
+    pub reason: ForwardingReason,
+}
+
+/// A destination for forwarding decisions, set via `NodeBuilder::forwarding_sink`.
+pub trait ForwardingSink: Send {
+    /// Receives the decision taken for a single routing message.
+    fn record(&self, decision: ForwardingDecision);
+}
+
+impl ForwardingSink for Sender<ForwardingDecision> {
+    fn record(&self, decision: ForwardingDecision) {
+        let _ = self.send(decision);
+    }
+}
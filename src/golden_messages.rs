@@ -0,0 +1,339 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Wire compatibility tests: canonical messages, built from fixed keys and field values, are
+//! checked byte-for-byte against the encodings in `tests/golden`.
+//!
+//! A failure means a change to the wire format, which breaks networks of mixed versions. If the
+//! change is deliberate, rewrite the fixtures by running the tests with the environment variable
+//! `ROUTING_UPDATE_GOLDENS` set, and commit them together with the change.
+
+use ack_manager::Ack;
+use data::{Data, DataIdentifier, ImmutableData};
+use hex::{FromHex, ToHex};
+use id::{FullId, PublicId};
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use messages::{DirectMessage, HopMessage, Message, MessageContent, Request, Response,
+               RoutingMessage, SectionList, SignedMessage, UserMessage};
+use peer_manager::SectionMap;
+use routing_table::{Authority, Prefix};
+use rust_sodium::crypto::{box_, sign};
+use rust_sodium::crypto::hash::sha256;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+use types::MessageId;
+use xor_name::XorName;
+
+/// If set, the fixtures are rewritten from the current encodings instead of being checked.
+const UPDATE_GOLDENS_VAR: &str = "ROUTING_UPDATE_GOLDENS";
+
+fn fixture_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests");
+    path.push("golden");
+    path.push(format!("{}.hex", name));
+    path
+}
+
+/// Returns the encoding of `msg` after checking it against the fixture `name`, or writing it to
+/// the fixture if `ROUTING_UPDATE_GOLDENS` is set.
+fn check_encoding<T: Serialize>(name: &str, msg: &T) -> Vec<u8> {
+    let encoded = unwrap!(serialise(msg));
+    let path = fixture_path(name);
+    if env::var_os(UPDATE_GOLDENS_VAR).is_some() {
+        unwrap!(fs::create_dir_all(unwrap!(path.parent())));
+        let mut file = unwrap!(File::create(&path));
+        unwrap!(writeln!(file, "{}", encoded.to_hex()));
+        return encoded;
+    }
+    let mut contents = String::new();
+    match File::open(&path) {
+        Ok(mut file) => {
+            let _ = unwrap!(file.read_to_string(&mut contents));
+        }
+        Err(error) => {
+            panic!("Failed to open {}: {}. Run the tests with {} set to create it.",
+                   path.display(),
+                   error,
+                   UPDATE_GOLDENS_VAR)
+        }
+    }
+    let golden: Vec<u8> = unwrap!(FromHex::from_hex(contents.trim()));
+    assert!(golden == encoded,
+            "The encoding of {} differs from {}. If the wire format change is deliberate, run \
+             the tests with {} set to update it.",
+            name,
+            path.display(),
+            UPDATE_GOLDENS_VAR);
+    golden
+}
+
+/// Checks the encoding of `msg`, and that the golden decodes to an equal value.
+fn check<T: Serialize + DeserializeOwned + Debug + Eq>(name: &str, msg: &T) {
+    let golden = check_encoding(name, msg);
+    let decoded: T = unwrap!(deserialise(&golden));
+    assert_eq!(decoded, *msg);
+}
+
+/// Like `check`, for types without `Eq`: the decoded golden must match `msg` in its `Debug`
+/// output, and encode to the golden again.
+fn check_without_eq<T: Serialize + DeserializeOwned + Debug>(name: &str, msg: &T) {
+    let golden = check_encoding(name, msg);
+    let decoded: T = unwrap!(deserialise(&golden));
+    assert_eq!(format!("{:?}", decoded), format!("{:?}", msg));
+    assert_eq!(unwrap!(serialise(&decoded)), golden);
+}
+
+/// Returns a `FullId` whose keys are derived from `seed`. The encryption keys don't form a valid
+/// pair, since none of the fixtures are encrypted.
+fn full_id(seed: u8) -> FullId {
+    let sign_keys = sign::keypair_from_seed(&sign::Seed([seed; sign::SEEDBYTES]));
+    let encrypt_keys = (box_::PublicKey([seed; box_::PUBLICKEYBYTES]),
+                        box_::SecretKey([seed; box_::SECRETKEYBYTES]));
+    FullId::with_keys(encrypt_keys, sign_keys)
+}
+
+fn pub_id(seed: u8) -> PublicId {
+    *full_id(seed).public_id()
+}
+
+fn pub_ids(seeds: &[u8]) -> BTreeSet<PublicId> {
+    seeds.iter().map(|&seed| pub_id(seed)).collect()
+}
+
+fn name(byte: u8) -> XorName {
+    XorName([byte; 32])
+}
+
+fn msg_id(byte: u8) -> MessageId {
+    MessageId::from_name(name(byte))
+}
+
+fn signature(byte: u8) -> sign::Signature {
+    sign::Signature([byte; sign::SIGNATUREBYTES])
+}
+
+fn client_auth() -> Authority<XorName> {
+    Authority::Client {
+        client_id: pub_id(1),
+        proxy_node_name: name(2),
+    }
+}
+
+fn section_map() -> SectionMap {
+    let prefix = Prefix::new(1, name(0));
+    vec![(prefix.with_version(3), pub_ids(&[1, 2])),
+         (prefix.sibling().with_version(4), pub_ids(&[3]))]
+        .into_iter()
+        .collect()
+}
+
+fn routing_msg(content: MessageContent) -> RoutingMessage {
+    RoutingMessage {
+        src: Authority::ManagedNode(*pub_id(1).name()),
+        dst: Authority::Section(name(5)),
+        content: content,
+    }
+}
+
+fn signed_msg() -> SignedMessage {
+    let content = MessageContent::SectionSplit(Prefix::new(1, name(0)).with_version(2), name(7));
+    let src_sections = vec![SectionList::from(Prefix::new(1, name(0)), pub_ids(&[1, 2]))];
+    unwrap!(SignedMessage::new(routing_msg(content), &full_id(1), src_sections))
+}
+
+fn contents() -> Vec<(&'static str, MessageContent)> {
+    vec![("content_relocate", MessageContent::Relocate { message_id: msg_id(1) }),
+         ("content_expect_candidate",
+          MessageContent::ExpectCandidate {
+              old_public_id: pub_id(1),
+              old_client_auth: client_auth(),
+              message_id: msg_id(2),
+          }),
+         ("content_connection_info_request",
+          MessageContent::ConnectionInfoRequest {
+              encrypted_conn_info: vec![1, 2, 3],
+              nonce: [4; box_::NONCEBYTES],
+              pub_id: pub_id(1),
+              msg_id: msg_id(3),
+              generation: 5,
+          }),
+         ("content_connection_info_response",
+          MessageContent::ConnectionInfoResponse {
+              encrypted_conn_info: vec![3, 2, 1],
+              nonce: [6; box_::NONCEBYTES],
+              pub_id: pub_id(2),
+              msg_id: msg_id(4),
+              generation: 7,
+          }),
+         ("content_relocate_response",
+          MessageContent::RelocateResponse {
+              target_interval: (name(1), name(2)),
+              section: (Prefix::new(2, name(1)), pub_ids(&[1, 2, 3])),
+              message_id: msg_id(5),
+          }),
+         ("content_section_update",
+          MessageContent::SectionUpdate {
+              versioned_prefix: Prefix::new(3, name(3)).with_version(8),
+              members: pub_ids(&[2, 3]),
+          }),
+         ("content_section_split",
+          MessageContent::SectionSplit(Prefix::new(1, name(0)).with_version(9), name(4))),
+         ("content_own_section_merge", MessageContent::OwnSectionMerge(section_map())),
+         ("content_other_section_merge",
+          MessageContent::OtherSectionMerge(pub_ids(&[1, 3]), 10)),
+         ("content_ack",
+          MessageContent::Ack(unwrap!(Ack::compute(&routing_msg(MessageContent::Relocate {
+                                                                    message_id: msg_id(6),
+                                                                }))),
+                              2)),
+         ("content_user_message_part",
+          MessageContent::UserMessagePart {
+              hash: [8; 32],
+              part_count: 2,
+              part_index: 1,
+              priority: 3,
+              cacheable: true,
+              payload: vec![9; 10],
+          }),
+         ("content_accept_as_candidate",
+          MessageContent::AcceptAsCandidate {
+              old_public_id: pub_id(3),
+              old_client_auth: client_auth(),
+              target_interval: (name(3), name(4)),
+              message_id: msg_id(7),
+          }),
+         ("content_candidate_approval",
+          MessageContent::CandidateApproval {
+              new_public_id: pub_id(4),
+              new_client_auth: client_auth(),
+              sections: section_map(),
+          }),
+         ("content_node_approval", MessageContent::NodeApproval { sections: section_map() }),
+         ("content_client_relay", MessageContent::ClientRelay(pub_id(2))),
+         ("content_redirect_to_client", MessageContent::RedirectToClient(Box::new(signed_msg()))),
+         ("content_client_unreachable", MessageContent::ClientUnreachable(pub_id(2))),
+         ("content_cancel_request",
+          MessageContent::CancelRequest {
+              msg_id: msg_id(8),
+              signature: signature(8),
+          })]
+}
+
+fn direct_msgs() -> Vec<(&'static str, DirectMessage)> {
+    let old_id = pub_id(1);
+    let new_id = pub_id(2);
+    vec![("direct_message_signature",
+          DirectMessage::MessageSignature(sha256::Digest([1; sha256::DIGESTBYTES]), signature(2))),
+         ("direct_section_list_signature",
+          DirectMessage::SectionListSignature(SectionList::from(Prefix::new(1, name(0)),
+                                                                pub_ids(&[1, 2])),
+                                              signature(3))),
+         ("direct_bootstrap_identify", DirectMessage::BootstrapIdentify),
+         ("direct_bootstrap_deny", DirectMessage::BootstrapDeny),
+         ("direct_client_identify",
+          DirectMessage::ClientIdentify {
+              serialised_public_id: unwrap!(serialise(&old_id)),
+              signature: signature(4),
+              client_restriction: true,
+          }),
+         ("direct_candidate_identify",
+          DirectMessage::CandidateIdentify {
+              old_public_id: old_id,
+              new_public_id: new_id,
+              signature_using_old: signature(5),
+              signature_using_new: signature(6),
+              new_client_auth: client_auth(),
+          }),
+         ("direct_tunnel_request", DirectMessage::TunnelRequest(old_id)),
+         ("direct_tunnel_success", DirectMessage::TunnelSuccess(old_id)),
+         ("direct_tunnel_select", DirectMessage::TunnelSelect(old_id)),
+         ("direct_tunnel_closed", DirectMessage::TunnelClosed(old_id)),
+         ("direct_tunnel_disconnect", DirectMessage::TunnelDisconnect(old_id)),
+         ("direct_resource_proof",
+          DirectMessage::ResourceProof {
+              seed: vec![7; 8],
+              target_size: 1024,
+              difficulty: 2,
+          }),
+         ("direct_resource_proof_response",
+          DirectMessage::ResourceProofResponse {
+              part_index: 1,
+              part_count: 3,
+              proof: vec![8; 16],
+              leading_zero_bytes: 4,
+          }),
+         ("direct_resource_proof_response_receipt", DirectMessage::ResourceProofResponseReceipt),
+         ("direct_identify_challenge", DirectMessage::IdentifyChallenge(0x0102_0304_0506_0708)),
+         ("direct_table_sample", DirectMessage::TableSample(vec![name(1), name(2)])),
+         ("direct_peer_ids_request", DirectMessage::PeerIdsRequest(vec![name(3)])),
+         ("direct_peer_ids", DirectMessage::PeerIds(vec![pub_id(4)])),
+         ("direct_peer_lost", DirectMessage::PeerLost(name(5)))]
+}
+
+#[test]
+fn message_contents() {
+    for (name, content) in contents() {
+        check(name, &routing_msg(content));
+    }
+}
+
+#[test]
+fn direct_messages() {
+    for (name, msg) in direct_msgs() {
+        check_without_eq(name, &msg);
+    }
+}
+
+#[test]
+fn signed_message() {
+    check("signed_message", &signed_msg());
+}
+
+#[test]
+fn envelopes() {
+    let hop_msg = unwrap!(HopMessage::new(signed_msg(),
+                                          1,
+                                          vec![name(1), name(2)].into_iter().collect(),
+                                          3,
+                                          full_id(2).signing_private_key()));
+    check_without_eq("message_hop", &Message::Hop(hop_msg));
+    check_without_eq("message_direct", &Message::Direct(DirectMessage::BootstrapIdentify));
+    check_without_eq("message_tunnel_direct",
+                     &Message::TunnelDirect {
+                         content: DirectMessage::TunnelClosed(pub_id(3)),
+                         src: pub_id(1),
+                         dst: pub_id(2),
+                     });
+    check_without_eq("message_ping", &Message::Ping(0x0102_0304_0506_0708));
+    check_without_eq("message_pong", &Message::Pong(0x0102_0304_0506_0708));
+}
+
+#[test]
+fn user_messages() {
+    let data = Data::Immutable(ImmutableData::new(vec![1, 2, 3]));
+    check("user_request",
+          &UserMessage::Request(Request::Get(DataIdentifier::Immutable(name(1)), msg_id(1))));
+    check("user_response",
+          &UserMessage::Response(Response::GetSuccess(data, msg_id(2))));
+}
@@ -0,0 +1,420 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Recording the inputs a node consumes, to replay them against a fresh node with the same keys
+//! and configuration and find the step at which the two diverge.
+
+use {CrustEvent, PrivConnectionInfo};
+use action::Action;
+use crust::{BootstrapFailureReason, ConnectionInfoResult, CrustError, CrustUser};
+use fake_clock::FakeClock as Instant;
+use id::PublicId;
+use maidsafe_utilities::serialisation;
+use messages::{DirectMessage, UserMessage};
+use mock_crust::Endpoint;
+use routing_table::Authority;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::time::Duration;
+use xor_name::XorName;
+
+/// A Crust event or action consumed by a node, as recorded in an `InputLog`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum RecordedInput {
+    /// `crust::Event::BootstrapAccept`.
+    BootstrapAccept(PublicId, CrustUser),
+    /// `crust::Event::BootstrapConnect`.
+    BootstrapConnect(PublicId, SocketAddr),
+    /// `crust::Event::BootstrapFailed`.
+    BootstrapFailed,
+    /// `crust::Event::BootstrapRefused`.
+    BootstrapRefused(SocketAddr, CrustUser),
+    /// `crust::Event::BootstrapAttemptFailed`.
+    BootstrapAttemptFailed(SocketAddr, BootstrapFailureReason),
+    /// `crust::Event::ListenerStarted`.
+    ListenerStarted(u16),
+    /// `crust::Event::ListenerFailed`.
+    ListenerFailed,
+    /// `crust::Event::ConnectionInfoPrepared`, with the prepared info's ID and endpoint if
+    /// successful.
+    ConnectionInfoPrepared {
+        /// The token that was passed to `prepare_connection_info`.
+        result_token: u32,
+        /// The ID and endpoint of the prepared connection info, if any.
+        info: Option<(PublicId, Endpoint)>,
+    },
+    /// `crust::Event::ConnectSuccess`.
+    ConnectSuccess(PublicId),
+    /// `crust::Event::ConnectFailure`.
+    ConnectFailure(PublicId),
+    /// `crust::Event::LostPeer`.
+    LostPeer(PublicId),
+    /// `crust::Event::NewMessage`.
+    NewMessage(PublicId, Vec<u8>),
+    /// `crust::Event::WriteMsgSizeProhibitive`.
+    WriteMsgSizeProhibitive(PublicId, Vec<u8>),
+    /// `crust::Event::MessageDelivered`.
+    MessageDelivered(PublicId, u64),
+    /// `crust::Event::MessageFailed`.
+    MessageFailed(PublicId, u64),
+    /// A user message sent via `Node`.
+    NodeSendMessage {
+        /// The source authority.
+        src: Authority<XorName>,
+        /// The destination authority.
+        dst: Authority<XorName>,
+        /// The message.
+        content: UserMessage,
+        /// The priority it is sent with.
+        priority: u8,
+        /// Whether its ID was assigned by `Node::message_id_for`.
+        retry_safe: bool,
+    },
+    /// A batch of user messages sent via `Node::send_request_batch`.
+    NodeSendBatch {
+        /// The source authority.
+        src: Authority<XorName>,
+        /// The messages with their destinations and the priorities they are sent with.
+        messages: Vec<(Authority<XorName>, UserMessage, u8)>,
+        /// The ID of the batch.
+        batch_id: u64,
+    },
+    /// `Node::disconnect_peer`.
+    DisconnectPeer(XorName),
+    /// `Node::ban_peer`.
+    BanPeer(XorName, Duration),
+    /// The timer with the given token fired.
+    Timeout(u64),
+    /// A resource proof was computed for the given peer, with the serialised messages to send it.
+    ResourceProofResult(PublicId, Vec<u8>),
+    /// The node was asked to terminate.
+    Terminate,
+    /// An action which can't be rebuilt from the log, by its debug output. It is skipped when
+    /// replaying.
+    Unreplayable(String),
+}
+
+impl RecordedInput {
+    /// Returns the record of the given Crust event.
+    pub fn from_crust_event(event: &CrustEvent<PublicId>) -> RecordedInput {
+        match *event {
+            CrustEvent::BootstrapAccept(pub_id, kind) => {
+                RecordedInput::BootstrapAccept(pub_id, kind)
+            }
+            CrustEvent::BootstrapConnect(pub_id, addr) => {
+                RecordedInput::BootstrapConnect(pub_id, addr)
+            }
+            CrustEvent::BootstrapFailed => RecordedInput::BootstrapFailed,
+            CrustEvent::BootstrapRefused(addr, kind) => RecordedInput::BootstrapRefused(addr, kind),
+            CrustEvent::BootstrapAttemptFailed(addr, reason) => {
+                RecordedInput::BootstrapAttemptFailed(addr, reason)
+            }
+            CrustEvent::ListenerStarted(port) => RecordedInput::ListenerStarted(port),
+            CrustEvent::ListenerFailed => RecordedInput::ListenerFailed,
+            CrustEvent::ConnectionInfoPrepared(ConnectionInfoResult {
+                                                   result_token,
+                                                   ref result,
+                                               }) => {
+                RecordedInput::ConnectionInfoPrepared {
+                    result_token: result_token,
+                    info: result.as_ref().ok().map(|info| (info.id, info.endpoint)),
+                }
+            }
+            CrustEvent::ConnectSuccess(pub_id) => RecordedInput::ConnectSuccess(pub_id),
+            CrustEvent::ConnectFailure(pub_id) => RecordedInput::ConnectFailure(pub_id),
+            CrustEvent::LostPeer(pub_id) => RecordedInput::LostPeer(pub_id),
+            CrustEvent::NewMessage(pub_id, ref bytes) => {
+                RecordedInput::NewMessage(pub_id, bytes.clone())
+            }
+            CrustEvent::WriteMsgSizeProhibitive(pub_id, ref bytes) => {
+                RecordedInput::WriteMsgSizeProhibitive(pub_id, bytes.clone())
+            }
+            CrustEvent::MessageDelivered(pub_id, id) => RecordedInput::MessageDelivered(pub_id, id),
+            CrustEvent::MessageFailed(pub_id, id) => RecordedInput::MessageFailed(pub_id, id),
+        }
+    }
+
+    /// Returns the record of the given action, or `None` if it only queries the node's state.
+    pub fn from_action(action: &Action) -> Option<RecordedInput> {
+        Some(match *action {
+                 Action::Id { .. } |
+                 Action::GetStats { .. } |
+                 Action::DumpDecisionLog { .. } |
+                 Action::GetEffectiveConfig { .. } => return None,
+                 Action::NodeSendMessage {
+                     src,
+                     dst,
+                     ref content,
+                     priority,
+                     retry_safe,
+                     ..
+                 } => {
+                     RecordedInput::NodeSendMessage {
+                         src: src,
+                         dst: dst,
+                         content: content.clone(),
+                         priority: priority,
+                         retry_safe: retry_safe,
+                     }
+                 }
+                 Action::NodeSendBatch {
+                     src,
+                     ref messages,
+                     batch_id,
+                     ..
+                 } => {
+                     RecordedInput::NodeSendBatch {
+                         src: src,
+                         messages: messages.clone(),
+                         batch_id: batch_id,
+                     }
+                 }
+                 Action::DisconnectPeer { name, .. } => RecordedInput::DisconnectPeer(name),
+                 Action::BanPeer { name, duration, .. } => RecordedInput::BanPeer(name, duration),
+                 Action::Timeout(token) => RecordedInput::Timeout(token),
+                 Action::ResourceProofResult(pub_id, ref messages) => {
+                     match serialisation::serialise(messages) {
+                         Ok(bytes) => RecordedInput::ResourceProofResult(pub_id, bytes),
+                         Err(_) => RecordedInput::Unreplayable(format!("{:?}", action)),
+                     }
+                 }
+                 Action::Terminate => RecordedInput::Terminate,
+                 Action::ClientSendRequest { .. } |
+                 Action::CancelRequest { .. } |
+                 Action::SetConnectionQuotas { .. } |
+                 Action::SetProxyStrategy { .. } |
+                 Action::UpdateConfig { .. } |
+                 Action::PingPeer { .. } => RecordedInput::Unreplayable(format!("{:?}", action)),
+             })
+    }
+
+    /// Rebuilds the Crust event, if this is the record of one.
+    pub fn to_crust_event(&self) -> Option<CrustEvent<PublicId>> {
+        Some(match *self {
+                 RecordedInput::BootstrapAccept(pub_id, kind) => {
+                     CrustEvent::BootstrapAccept(pub_id, kind)
+                 }
+                 RecordedInput::BootstrapConnect(pub_id, addr) => {
+                     CrustEvent::BootstrapConnect(pub_id, addr)
+                 }
+                 RecordedInput::BootstrapFailed => CrustEvent::BootstrapFailed,
+                 RecordedInput::BootstrapRefused(addr, kind) => {
+                     CrustEvent::BootstrapRefused(addr, kind)
+                 }
+                 RecordedInput::BootstrapAttemptFailed(addr, reason) => {
+                     CrustEvent::BootstrapAttemptFailed(addr, reason)
+                 }
+                 RecordedInput::ListenerStarted(port) => CrustEvent::ListenerStarted(port),
+                 RecordedInput::ListenerFailed => CrustEvent::ListenerFailed,
+                 RecordedInput::ConnectionInfoPrepared { result_token, info } => {
+                     let result = info.map_or(Err(CrustError), |(id, endpoint)| {
+                         Ok(PrivConnectionInfo {
+                                id: id,
+                                endpoint: endpoint,
+                            })
+                     });
+                     CrustEvent::ConnectionInfoPrepared(ConnectionInfoResult {
+                                                            result_token: result_token,
+                                                            result: result,
+                                                        })
+                 }
+                 RecordedInput::ConnectSuccess(pub_id) => CrustEvent::ConnectSuccess(pub_id),
+                 RecordedInput::ConnectFailure(pub_id) => CrustEvent::ConnectFailure(pub_id),
+                 RecordedInput::LostPeer(pub_id) => CrustEvent::LostPeer(pub_id),
+                 RecordedInput::NewMessage(pub_id, ref bytes) => {
+                     CrustEvent::NewMessage(pub_id, bytes.clone())
+                 }
+                 RecordedInput::WriteMsgSizeProhibitive(pub_id, ref bytes) => {
+                     CrustEvent::WriteMsgSizeProhibitive(pub_id, bytes.clone())
+                 }
+                 RecordedInput::MessageDelivered(pub_id, id) => {
+                     CrustEvent::MessageDelivered(pub_id, id)
+                 }
+                 RecordedInput::MessageFailed(pub_id, id) => CrustEvent::MessageFailed(pub_id, id),
+                 _ => return None,
+             })
+    }
+
+    /// Rebuilds the action, if this is the record of one which can be replayed. Its result is
+    /// discarded.
+    pub fn to_action(&self) -> Option<Action> {
+        let (result_tx, _) = mpsc::channel();
+        Some(match *self {
+                 RecordedInput::NodeSendMessage {
+                     src,
+                     dst,
+                     ref content,
+                     priority,
+                     retry_safe,
+                 } => {
+                     Action::NodeSendMessage {
+                         src: src,
+                         dst: dst,
+                         content: content.clone(),
+                         priority: priority,
+                         retry_safe: retry_safe,
+                         result_tx: result_tx,
+                     }
+                 }
+                 RecordedInput::NodeSendBatch {
+                     src,
+                     ref messages,
+                     batch_id,
+                 } => {
+                     Action::NodeSendBatch {
+                         src: src,
+                         messages: messages.clone(),
+                         batch_id: batch_id,
+                         result_tx: result_tx,
+                     }
+                 }
+                 RecordedInput::DisconnectPeer(name) => {
+                     Action::DisconnectPeer {
+                         name: name,
+                         result_tx: result_tx,
+                     }
+                 }
+                 RecordedInput::BanPeer(name, duration) => {
+                     Action::BanPeer {
+                         name: name,
+                         duration: duration,
+                         result_tx: result_tx,
+                     }
+                 }
+                 RecordedInput::Timeout(token) => Action::Timeout(token),
+                 RecordedInput::ResourceProofResult(pub_id, ref bytes) => {
+                     let messages: Vec<DirectMessage> = match serialisation::deserialise(bytes) {
+                         Ok(messages) => messages,
+                         Err(_) => return None,
+                     };
+                     Action::ResourceProofResult(pub_id, messages)
+                 }
+                 RecordedInput::Terminate => Action::Terminate,
+                 _ => return None,
+             })
+    }
+}
+
+/// An input consumed by a node, in an `InputLog`.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct InputRecord {
+    /// The time since recording started at which the input was consumed.
+    pub elapsed: Duration,
+    /// The input.
+    pub input: RecordedInput,
+    /// The digest of the node's decision log after handling the input.
+    pub decisions: u64,
+}
+
+/// The inputs a node consumed, in order, as recorded via `NodeBuilder::record_inputs` and
+/// replayed via `Node::replay_input_log`.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct InputLog {
+    /// The recorded inputs, oldest first.
+    pub records: Vec<InputRecord>,
+}
+
+/// The first step of a replay after which the node's decision log differs from the recorded one.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ReplayDivergence {
+    /// The index of the record in the log.
+    pub step: usize,
+    /// The recorded digest of the decision log.
+    pub expected: u64,
+    /// The digest of the replaying node's decision log.
+    pub actual: u64,
+}
+
+/// Returns the digest of a dumped decision log, as stored in `InputRecord::decisions`.
+pub fn decisions_digest(dump: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(dump);
+    hasher.finish()
+}
+
+/// Appends the inputs a node consumes to an `InputLog`.
+pub struct InputRecorder {
+    start: Instant,
+    log: InputLog,
+}
+
+impl InputRecorder {
+    pub fn new() -> InputRecorder {
+        InputRecorder {
+            start: Instant::now(),
+            log: InputLog::default(),
+        }
+    }
+
+    pub fn record(&mut self, input: RecordedInput, decisions: u64) {
+        self.log
+            .records
+            .push(InputRecord {
+                      elapsed: self.start.elapsed(),
+                      input: input,
+                      decisions: decisions,
+                  });
+    }
+
+    pub fn into_log(self) -> InputLog {
+        self.log
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use id::FullId;
+    use maidsafe_utilities::serialisation::{deserialise, serialise};
+    use messages::Request;
+    use rand;
+    use types::MessageId;
+
+    #[test]
+    fn inputs_survive_serialisation() {
+        let pub_id = *FullId::new().public_id();
+        let content = UserMessage::Request(Request::Refresh(vec![1, 2, 3], MessageId::new()));
+        let mut recorder = InputRecorder::new();
+        recorder.record(RecordedInput::NewMessage(pub_id, vec![4, 5, 6]), 1);
+        recorder.record(RecordedInput::Timeout(7), 2);
+        recorder.record(RecordedInput::NodeSendMessage {
+                            src: Authority::ManagedNode(rand::random()),
+                            dst: Authority::NaeManager(rand::random()),
+                            content: content,
+                            priority: 0,
+                            retry_safe: false,
+                        },
+                        3);
+        let log = recorder.into_log();
+        assert_eq!(log.records.len(), 3);
+        assert_eq!(unwrap!(deserialise::<InputLog>(&unwrap!(serialise(&log)))), log);
+
+        for record in &log.records {
+            let event = record.input.to_crust_event();
+            let action = record.input.to_action();
+            assert!(event.is_some() != action.is_some());
+            if let Some(event) = event {
+                assert_eq!(RecordedInput::from_crust_event(&event), record.input);
+            }
+            if let Some(action) = action {
+                assert_eq!(RecordedInput::from_action(&action), Some(record.input.clone()));
+            }
+        }
+    }
+}
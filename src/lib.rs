@@ -156,33 +156,63 @@ mod action;
 mod cache;
 mod client;
 mod common_types;
+mod connection_audit;
+mod convergence;
+mod crypto;
 mod data;
+mod decision_log;
+mod departure;
+mod dispatcher;
 mod error;
 mod event;
+mod event_coalescer;
+mod event_sink;
 mod event_stream;
+mod expiring_cache;
+mod forwarding;
+#[cfg(test)]
+mod golden_messages;
 mod section_list_cache;
 mod id;
+#[cfg(feature = "use-mock-crust")]
+mod input_log;
 mod message_filter;
 mod messages;
 mod node;
 mod outbox;
+mod payload_pool;
+mod peer_generations;
 mod peer_manager;
+mod peer_score_book;
+mod ping;
+mod processing_stats;
+mod proxy_selector;
+mod recovery;
 mod resource_prover;
 mod routing_message_filter;
 mod routing_table;
 mod signature_accumulator;
+mod startup_queue;
 mod state_machine;
 mod states;
+mod state_store;
 mod stats;
+mod table_gossip;
 mod timer;
+mod tunables;
 mod tunnels;
 mod types;
 mod utils;
+mod wire_kind;
+mod wire_version;
 mod xor_name;
 
 /// Mock crust
 #[cfg(feature = "use-mock-crust")]
 pub mod mock_crust;
+/// Builders for crafted messages to inject into a node in tests.
+#[cfg(feature = "use-mock-crust")]
+pub mod test_messages;
 
 /// SHA-3 type alias.
 pub mod sha3;
@@ -207,26 +237,50 @@ pub const QUORUM_DENOMINATOR: usize = 2;
 pub use cache::{Cache, NullCache};
 pub use client::Client;
 pub use common_types::AccountPacket;
+pub use convergence::{ConvergenceMetrics, ConvergenceSnapshot};
+pub use crypto::CryptoError;
 pub use data::{AppendWrapper, AppendedData, Data, DataIdentifier, Filter, ImmutableData,
                MAX_IMMUTABLE_DATA_SIZE_IN_BYTES, MAX_PRIV_APPENDABLE_DATA_SIZE_IN_BYTES,
                MAX_PUB_APPENDABLE_DATA_SIZE_IN_BYTES, MAX_STRUCTURED_DATA_SIZE_IN_BYTES,
                NO_OWNER_PUB_KEY, PrivAppendableData, PrivAppendedData, PubAppendableData,
                StructuredData};
+pub use decision_log::{AuthorityKind, Decision, DecisionRecord, FilterOutcome,
+                       decode_decision_log, message_hash};
+pub use dispatcher::{Dispatchable, DispatcherHandle, EventMask, RequestHandle, RoutingDispatcher};
 pub use error::{InterfaceError, RoutingError};
-pub use event::Event;
+pub use event::{AuditReport, BootstrapFailure, CoalescingKey, ConfigRefusal, Event, Health,
+                JoinProgress, JoinProgressMask, RefusalReason, RefusedSetting, SnapshotRejection};
+pub use event_sink::{EventSink, RingBufferSink, SinkClosed};
 pub use event_stream::EventStream;
+pub use expiring_cache::CacheStats;
+pub use forwarding::{ForwardingDecision, ForwardingReason, ForwardingSink};
 pub use id::{FullId, PublicId};
+#[cfg(feature = "use-mock-crust")]
+pub use input_log::{InputLog, InputRecord, RecordedInput, ReplayDivergence};
+#[cfg(feature = "use-mock-crust")]
+pub use messages::signed_message_encodings;
 pub use messages::{Request, Response};
 #[cfg(feature = "use-mock-crust")]
 pub use mock_crust::crust;
 pub use node::{Node, NodeBuilder};
 #[cfg(feature = "use-mock-crust")]
 pub use peer_manager::test_consts;
+#[cfg(feature = "use-mock-crust")]
+pub use processing_stats::inject_phase_cost;
+pub use processing_stats::{HISTOGRAM_BUCKETS, ProcessingHistograms, ProcessingPhase,
+                           SlowMessageReport};
 pub use routing_table::{Authority, Prefix, RoutingTable, Xorable};
 pub use routing_table::Error as RoutingTableError;
 #[cfg(any(test, feature = "use-mock-crust"))]
 pub use routing_table::verify_network_invariant;
+pub use state_store::{FileStateStore, MemoryStateStore, StateStore};
+pub use stats::Diagnostics;
+#[cfg(feature = "use-mock-crust")]
+pub use stats::PendingWork;
+pub use tunables::{ConnectionQuotas, EffectiveConfig, LiveConfig, PartialConfig, ProxyStrategy,
+                   ScoreRule, StartupConfig};
 pub use types::MessageId;
+pub use wire_kind::WireKind;
 pub use xor_name::{XOR_NAME_BITS, XOR_NAME_LEN, XorName, XorNameFromHexError};
 
 type Service = crust::Service<PublicId>;
@@ -32,14 +32,22 @@ fn hash<T: Hash>(t: &T) -> u64 {
 }
 
 /// A time based message filter that takes any generic type as a key and will drop keys after a
-/// time period (LRU Cache pattern).
+/// time period (LRU Cache pattern). Optionally, the number of entries can be bounded too, in which
+/// case the least recently inserted entries are evicted first.
 pub struct MessageFilter<Message> {
-    /// The number of times each message has been received so far, and the expiry timestamp.
-    count: HashMap<u64, (usize, Instant)>,
+    /// The number of times each message has been received so far, the expiry timestamp, and the
+    /// number of its insertions still held in `timeout_queue`.
+    count: HashMap<u64, (usize, Instant, usize)>,
     /// A record of message hashes and the expiry timestamps of all insertions, ordered
     /// chronologically. The timestamps are out of date if the same hash has been inserted again.
     timeout_queue: VecDeque<(u64, Instant)>,
     time_to_live: Duration,
+    /// The maximum number of insertions held in `timeout_queue`, if bounded.
+    capacity: Option<usize>,
+    /// The number of entries removed to stay within `capacity`.
+    evictions: usize,
+    /// The number of times an already known message was inserted or looked up.
+    hits: usize,
     phantom: PhantomData<Message>,
 }
 
@@ -50,10 +58,23 @@ impl<Message: Hash> MessageFilter<Message> {
             count: HashMap::new(),
             timeout_queue: VecDeque::new(),
             time_to_live: time_to_live,
+            capacity: None,
+            evictions: 0,
+            hits: 0,
             phantom: PhantomData,
         }
     }
 
+    /// Constructor for time based `MessageFilter` which holds at most `capacity` entries.
+    pub fn with_expiry_duration_and_capacity(time_to_live: Duration,
+                                             capacity: usize)
+                                             -> MessageFilter<Message> {
+        MessageFilter {
+            capacity: Some(capacity),
+            ..Self::with_expiry_duration(time_to_live)
+        }
+    }
+
     /// Adds a message to the filter.
     ///
     /// Removes any expired messages, then adds `message`, then removes enough older messages until
@@ -68,15 +89,19 @@ impl<Message: Hash> MessageFilter<Message> {
         let hash_code = hash(message);
         let expiry = Instant::now() + self.time_to_live;
         self.timeout_queue.push_back((hash_code, expiry));
-        match self.count.entry(hash_code) {
+        let count = match self.count.entry(hash_code) {
             Entry::Occupied(entry) => {
-                let &mut (ref mut c, ref mut t) = entry.into_mut();
+                self.hits += 1;
+                let &mut (ref mut c, ref mut t, ref mut q) = entry.into_mut();
                 *t = expiry;
                 *c += 1;
+                *q += 1;
                 *c
             }
-            Entry::Vacant(entry) => entry.insert((1, expiry)).0,
-        }
+            Entry::Vacant(entry) => entry.insert((1, expiry, 1)).0,
+        };
+        self.remove_excess();
+        count
     }
 
     /// Returns the number of times this message has already been inserted.
@@ -85,18 +110,41 @@ impl<Message: Hash> MessageFilter<Message> {
         let hash_code = hash(message);
         self.count
             .get(&hash_code)
-            .map_or(0, |&(count, _)| count)
+            .map_or(0, |&(count, _, _)| count)
     }
 
     /// Removes any expired messages, then returns whether `message` exists in the filter or not.
     pub fn contains(&mut self, message: &Message) -> bool {
         self.remove_expired();
-        self.count.contains_key(&hash(message))
+        let found = self.count.contains_key(&hash(message));
+        if found {
+            self.hits += 1;
+        }
+        found
+    }
+
+    /// Returns the number of distinct messages currently held by the filter.
+    pub fn len(&self) -> usize {
+        self.count.len()
+    }
+
+    /// Returns the number of messages evicted to keep the filter within its capacity.
+    pub fn evictions(&self) -> usize {
+        self.evictions
+    }
+
+    /// Returns the number of times an already known message was inserted or looked up.
+    pub fn hits(&self) -> usize {
+        self.hits
     }
 
     /// Remove the entry for `message`, regardless of how many times it was previously inserted.
     pub fn remove(&mut self, message: &Message) {
-        let _old_val = self.count.remove(&hash(message));
+        let hash_code = hash(message);
+        if self.count.remove(&hash_code).is_some() {
+            // Otherwise its stale insertions would count against it if it is inserted again.
+            self.timeout_queue.retain(|&(queued, _)| queued != hash_code);
+        }
     }
 
     fn remove_expired(&mut self) {
@@ -105,9 +153,30 @@ impl<Message: Hash> MessageFilter<Message> {
                   .front()
                   .map_or(false, |&(_, ref t)| *t <= now) {
             let (hash_code, _) = unwrap!(self.timeout_queue.pop_front());
-            if let Entry::Occupied(entry) = self.count.entry(hash_code) {
+            if let Entry::Occupied(mut entry) = self.count.entry(hash_code) {
                 if entry.get().1 <= now {
                     let _removed_pair = entry.remove_entry();
+                } else {
+                    entry.get_mut().2 -= 1;
+                }
+            }
+        }
+    }
+
+    fn remove_excess(&mut self) {
+        let capacity = match self.capacity {
+            Some(capacity) => capacity,
+            None => return,
+        };
+        while self.timeout_queue.len() > capacity {
+            let (hash_code, _) = unwrap!(self.timeout_queue.pop_front());
+            // Only evict the message if this was its most recent insertion.
+            if let Entry::Occupied(mut entry) = self.count.entry(hash_code) {
+                if entry.get().2 <= 1 {
+                    let _removed_pair = entry.remove_entry();
+                    self.evictions += 1;
+                } else {
+                    entry.get_mut().2 -= 1;
                 }
             }
         }
@@ -218,6 +287,61 @@ mod tests {
         assert_eq!(2, msg_filter.count(&0));
     }
 
+    #[test]
+    fn capacity() {
+        let capacity = 10;
+        let time_to_live = Duration::from_secs(99);
+        let mut msg_filter =
+            MessageFilter::<usize>::with_expiry_duration_and_capacity(time_to_live, capacity);
+
+        // Add more unique messages than the capacity - only the most recent ones should be kept.
+        for i in 0..(3 * capacity) {
+            assert_eq!(1, msg_filter.insert(&i));
+            assert!(msg_filter.len() <= capacity);
+        }
+        assert_eq!(capacity, msg_filter.len());
+        assert_eq!(2 * capacity, msg_filter.evictions());
+        assert!((0..(2 * capacity)).all(|index| !msg_filter.contains(&index)));
+        assert!(((2 * capacity)..(3 * capacity)).all(|index| msg_filter.contains(&index)));
+
+        // Recent duplicates are still filtered, and re-inserting one protects it from eviction.
+        let last = 3 * capacity - 1;
+        let first_kept = 2 * capacity;
+        assert_eq!(2, msg_filter.insert(&first_kept));
+        assert_eq!(1, msg_filter.insert(&(last + 1)));
+        assert!(msg_filter.contains(&first_kept));
+        assert!(!msg_filter.contains(&(first_kept + 1)));
+        assert!(msg_filter.contains(&last));
+        assert!(msg_filter.hits() > 0);
+    }
+
+    #[test]
+    fn remove_and_reinsert() {
+        let capacity = 3;
+        let time_to_live = Duration::from_secs(99);
+        let mut msg_filter =
+            MessageFilter::<usize>::with_expiry_duration_and_capacity(time_to_live, capacity);
+
+        // A removed message counts from scratch when inserted again.
+        assert_eq!(1, msg_filter.insert(&0));
+        assert_eq!(2, msg_filter.insert(&0));
+        msg_filter.remove(&0);
+        assert!(!msg_filter.contains(&0));
+        assert_eq!(1, msg_filter.insert(&0));
+
+        // Its insertions from before the removal don't take up capacity.
+        assert_eq!(1, msg_filter.insert(&1));
+        assert_eq!(1, msg_filter.insert(&2));
+        assert!((0..capacity).all(|index| msg_filter.contains(&index)));
+        assert_eq!(0, msg_filter.evictions());
+
+        // Once over capacity, it is evicted as the oldest message.
+        assert_eq!(1, msg_filter.insert(&3));
+        assert!(!msg_filter.contains(&0));
+        assert!((1..(capacity + 1)).all(|index| msg_filter.contains(&index)));
+        assert_eq!(1, msg_filter.evictions());
+    }
+
     #[test]
     fn insert_resets_timeout() {
         // Check re-adding a message to a filter alters its expiry time.
@@ -18,21 +18,28 @@
 use super::{QUORUM_DENOMINATOR, QUORUM_NUMERATOR};
 use ack_manager::Ack;
 use data::{AppendWrapper, Data, DataIdentifier};
+use crypto::{Signer, Verifier};
 use error::RoutingError;
 use event::Event;
 use id::{FullId, PublicId};
 use itertools::Itertools;
 use lru_time_cache::LruCache;
-use maidsafe_utilities::serialisation::{deserialise, serialise};
+use maidsafe_utilities::serialisation::{SerialisationError, deserialise, serialise};
 use peer_manager::SectionMap;
 use routing_table::{Prefix, VersionedPrefix, Xorable};
 use routing_table::Authority;
 use rust_sodium::crypto::{box_, sign};
 use rust_sodium::crypto::hash::sha256;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de, ser};
 use sha3;
+#[cfg(feature = "use-mock-crust")]
+use std::cell::Cell;
+use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::fmt::{self, Debug, Formatter};
+use std::hash::{Hash, Hasher};
 use std::iter;
+use std::sync::Arc;
 use std::time::Duration;
 use tiny_keccak::sha3_256;
 use types::MessageId;
@@ -51,6 +58,13 @@ pub const DEFAULT_PRIORITY: u8 = 2;
 /// needs to prioritise maintaining its structure, data and consensus.
 pub const CLIENT_GET_PRIORITY: u8 = 3;
 
+/// Returns the bytes a peer signs to identify itself on a connection: `identity` followed by the
+/// `nonce` the other side challenged it with on that connection.
+pub fn identify_signed_bytes(mut identity: Vec<u8>, nonce: u64) -> Result<Vec<u8>, RoutingError> {
+    identity.extend_from_slice(&serialise(&nonce)?);
+    Ok(identity)
+}
+
 /// Wrapper of all messages.
 ///
 /// This is the only type allowed to be sent / received on the network.
@@ -80,6 +94,11 @@ pub enum Message {
         /// The receiver
         dst: PublicId,
     },
+    /// An unsigned diagnostic ping between directly connected peers, to be answered with a `Pong`
+    /// carrying the same nonce.
+    Ping(u64),
+    /// The reply to a `Ping`.
+    Pong(u64),
 }
 
 impl Message {
@@ -89,6 +108,7 @@ impl Message {
             Message::TunnelDirect { ref content, .. } => content.priority(),
             Message::Hop(ref content) |
             Message::TunnelHop { ref content, .. } => content.content.content.priority(),
+            Message::Ping(_) | Message::Pong(_) => 0,
         }
     }
 }
@@ -171,6 +191,22 @@ pub enum DirectMessage {
     },
     /// Receipt of a part of a ResourceProofResponse
     ResourceProofResponseReceipt,
+    /// Sent by a node to a newly connected peer which needs to identify itself. The peer's
+    /// `ClientIdentify` or `CandidateIdentify` must sign the nonce, so that an identification
+    /// captured from another connection can't be replayed on this one.
+    IdentifyChallenge(u64),
+    /// A random sample of the names in the sender's routing table, sent periodically to a few
+    /// connected peers so that they can discover nodes they are missing.
+    TableSample(Vec<XorName>),
+    /// Sent in response to a `TableSample`, requesting the `PublicId`s of the given names the
+    /// recipient included in it.
+    PeerIdsRequest(Vec<XorName>),
+    /// Sent in response to a `PeerIdsRequest`, with the IDs of those requested names which are in
+    /// the sender's routing table.
+    PeerIds(Vec<PublicId>),
+    /// Sent to the other members of our section when we lost our connection to the given member.
+    /// Only once a quorum of them lost it, it is considered departed.
+    PeerLost(XorName),
 }
 
 impl DirectMessage {
@@ -188,42 +224,75 @@ impl DirectMessage {
 /// To relay a `SignedMessage` via another node, the `SignedMessage` is wrapped in a `HopMessage`.
 /// The `signature` is from the node that sends this directly to a node in its routing table. To
 /// prevent Man-in-the-middle attacks, the `content` is signed by the original sender.
+///
+/// The `hop_count` can't be part of the `content`, since that would invalidate the original
+/// sender's signature at every hop. Instead it is covered by the `signature`, which each hop
+/// creates anew anyway.
 #[derive(Serialize, Deserialize)]
 pub struct HopMessage {
-    /// Wrapped signed message.
+    /// Wrapped signed message. It is sent as a byte string holding its encoding, which is kept
+    /// when it is received, so that relaying it doesn't encode it again.
+    #[serde(serialize_with = "serialise_encoded", deserialize_with = "deserialise_encoded")]
     pub content: SignedMessage,
     /// Route number; corresponds to the index of the peer in the section of target peers being
     /// considered for the next hop.
     pub route: u8,
     /// Every node this has already been sent to.
     pub sent_to: BTreeSet<XorName>,
+    /// The number of times the message has been relayed between nodes so far.
+    pub hop_count: u8,
     /// Signature to be validated against the neighbouring sender's public key.
     signature: sign::Signature,
 }
 
 impl HopMessage {
     /// Wrap `content` for transmission to the next hop and sign it.
-    pub fn new(content: SignedMessage,
+    pub fn new(mut content: SignedMessage,
                route: u8,
                sent_to: BTreeSet<XorName>,
+               hop_count: u8,
                signing_key: &sign::SecretKey)
                -> Result<HopMessage, RoutingError> {
-        let bytes_to_sign = serialise(&content)?;
+        let encoding = content.encoding()?;
+        let signature = signing_key.sign(&serialise(&(&encoding[..], hop_count))?)?;
+        content.encoding = Encoding(Some(encoding));
         Ok(HopMessage {
                content: content,
                route: route,
                sent_to: sent_to,
-               signature: sign::sign_detached(&bytes_to_sign, signing_key),
+               hop_count: hop_count,
+               signature: signature,
            })
     }
 
+    /// Wrap `content` like `new`, but with a placeholder signature of the correct length. This is
+    /// used to compute the size of a message without signing it.
+    fn with_placeholder_signature(content: SignedMessage,
+                                  route: u8,
+                                  sent_to: BTreeSet<XorName>,
+                                  hop_count: u8)
+                                  -> HopMessage {
+        HopMessage {
+            content: content,
+            route: route,
+            sent_to: sent_to,
+            hop_count: hop_count,
+            signature: placeholder_signature(),
+        }
+    }
+
+    /// Wrap this in a `Message::Hop` and serialise it, ready to be sent to a peer.
+    pub fn into_bytes(self) -> Result<Vec<u8>, RoutingError> {
+        Ok(serialise(&Message::Hop(self))?)
+    }
+
     /// Validate that the message is signed by `verification_key` contained in message.
     ///
     /// This does not imply that the message came from a known node. That requires a check against
     /// the routing table to identify the name associated with the `verification_key`.
     pub fn verify(&self, verification_key: &sign::PublicKey) -> Result<(), RoutingError> {
-        let signed_bytes = serialise(&self.content)?;
-        if sign::verify_detached(&self.signature, &signed_bytes, verification_key) {
+        let signed_bytes = serialise(&(&self.content.encoding()?[..], self.hop_count))?;
+        if verification_key.verify(&self.signature, &signed_bytes) {
             Ok(())
         } else {
             Err(RoutingError::FailedSignature)
@@ -255,7 +324,7 @@ impl SectionList {
 }
 
 /// Wrapper around a routing message, signed by the originator of the message.
-#[derive(Ord, PartialOrd, Eq, PartialEq, Clone, Hash, Serialize, Deserialize)]
+#[derive(Ord, PartialOrd, Eq, PartialEq, Clone, Hash, Deserialize)]
 pub struct SignedMessage {
     /// A request or response type message.
     content: RoutingMessage,
@@ -265,6 +334,9 @@ pub struct SignedMessage {
     // TODO: implement (MAID-1677): sec_lists: Vec<SectionList>,
     /// The IDs and signatures of the source authority's members.
     signatures: BTreeMap<PublicId, sign::Signature>,
+    /// The encoding this was received or last sent in, if it hasn't been modified since.
+    #[serde(skip_deserializing)]
+    encoding: Encoding,
 }
 
 impl SignedMessage {
@@ -276,14 +348,40 @@ impl SignedMessage {
                mut src_sections: Vec<SectionList>)
                -> Result<SignedMessage, RoutingError> {
         src_sections.sort_by_key(|list| list.prefix);
-        let sig = sign::sign_detached(&serialise(&content)?, full_id.signing_private_key());
+        let sig = full_id.signing_private_key().sign(&serialise(&content)?)?;
         Ok(SignedMessage {
                content: content,
                src_sections: src_sections,
                signatures: iter::once((*full_id.public_id(), sig)).collect(),
+               encoding: Encoding::default(),
            })
     }
 
+    /// Creates a `SignedMessage` like `new`, but with placeholder signatures of the correct length.
+    /// If the source is a section, every member of `src_sections` is assumed to have signed it,
+    /// otherwise only `sender`.
+    fn with_placeholder_signatures(content: RoutingMessage,
+                                   sender: &PublicId,
+                                   mut src_sections: Vec<SectionList>)
+                                   -> SignedMessage {
+        src_sections.sort_by_key(|list| list.prefix);
+        let signatures = if content.src.is_multiple() {
+            src_sections
+                .iter()
+                .flat_map(|list| list.pub_ids.iter())
+                .map(|pub_id| (*pub_id, placeholder_signature()))
+                .collect()
+        } else {
+            iter::once((*sender, placeholder_signature())).collect()
+        };
+        SignedMessage {
+            content: content,
+            src_sections: src_sections,
+            signatures: signatures,
+            encoding: Encoding::default(),
+        }
+    }
+
     /// Confirms the signatures.
     // TODO (MAID-1677): verify the sending SectionLists via each hop's signed lists
     pub fn check_integrity(&self, min_section_size: usize) -> Result<(), RoutingError> {
@@ -316,6 +414,7 @@ impl SignedMessage {
     pub fn add_signature(&mut self, pub_id: PublicId, sig: sign::Signature) {
         if self.content.src.is_multiple() && self.is_sender(&pub_id) {
             let _ = self.signatures.insert(pub_id, sig);
+            self.encoding = Encoding::default();
         }
     }
 
@@ -323,6 +422,7 @@ impl SignedMessage {
     pub fn add_signatures(&mut self, msg: SignedMessage) {
         if self.content.src.is_multiple() {
             self.signatures.extend(msg.signatures);
+            self.encoding = Encoding::default();
         }
     }
 
@@ -341,6 +441,15 @@ impl SignedMessage {
         self.content.priority()
     }
 
+    // Returns the encoding this was received or last sent in, or encodes it if it was modified
+    // since.
+    fn encoding(&self) -> Result<Arc<Vec<u8>>, SerialisationError> {
+        match self.encoding.0 {
+            Some(ref bytes) => Ok(bytes.clone()),
+            None => Ok(Arc::new(serialise(self)?)),
+        }
+    }
+
     /// Returns whether there are enough signatures from the sender.
     pub fn check_fully_signed(&mut self, min_section_size: usize) -> bool {
         if !self.has_enough_sigs(min_section_size) {
@@ -364,6 +473,7 @@ impl SignedMessage {
         };
         for invalid_signature in &self.find_invalid_sigs(signed_bytes) {
             let _ = self.signatures.remove(invalid_signature);
+            self.encoding = Encoding::default();
         }
 
         self.has_enough_sigs(min_section_size)
@@ -385,10 +495,9 @@ impl SignedMessage {
                 // Remove if not in sending nodes or signature is invalid:
                 let is_valid = if let Authority::Client { ref client_id, .. } = self.content.src {
                     client_id == pub_id &&
-                    sign::verify_detached(sig, &signed_bytes, client_id.signing_public_key())
+                    client_id.signing_public_key().verify(sig, &signed_bytes)
                 } else {
-                    self.is_sender(pub_id) &&
-                    sign::verify_detached(sig, &signed_bytes, pub_id.signing_public_key())
+                    self.is_sender(pub_id) && pub_id.signing_public_key().verify(sig, &signed_bytes)
                 };
                 if is_valid { None } else { Some(*pub_id) }
             })
@@ -409,7 +518,7 @@ impl SignedMessage {
                 let valid_names: HashSet<_> = self.src_sections
                     .iter()
                     .flat_map(|list| list.pub_ids.iter().map(PublicId::name))
-                    .sorted_by(|lhs, rhs| self.content.src.name().cmp_distance(lhs, rhs))
+                    .sorted_by(|lhs, rhs| self.content.src.name().cmp_closeness(lhs, rhs))
                     .into_iter()
                     .take(min_section_size)
                     .collect();
@@ -480,7 +589,7 @@ impl RoutingMessage {
                         -> Result<DirectMessage, RoutingError> {
         let serialised_msg = serialise(self)?;
         let hash = sha256::hash(&serialised_msg);
-        let sig = sign::sign_detached(&serialised_msg, signing_key);
+        let sig = signing_key.sign(&serialised_msg)?;
         Ok(DirectMessage::MessageSignature(hash, sig))
     }
 }
@@ -496,9 +605,10 @@ impl RoutingMessage {
 /// any node B of the network via Crust. When successful, i. e. when receiving an `OnConnect` event,
 /// it moves to the `Bootstrapping` state.
 ///
-/// A now sends a `ClientIdentify` message to B, containing A's signed public ID. B verifies the
-/// signature and responds with a `BootstrapIdentify`, containing B's public ID. Once it receives
-/// that, A goes into the `Client` state and uses B as its proxy to the network.
+/// B sends A an `IdentifyChallenge` with a random nonce. A responds with a `ClientIdentify`
+/// message, containing A's public ID, signed together with the nonce. B verifies the signature and
+/// responds with a `BootstrapIdentify`, containing B's public ID. Once it receives that, A goes
+/// into the `Client` state and uses B as its proxy to the network.
 ///
 /// A can now exchange messages with any `Authority`. This completes the bootstrap process for
 /// clients.
@@ -534,17 +644,18 @@ impl RoutingMessage {
 /// to A and also attempts to connect to A via Crust. A does the same, once it receives the
 /// `ConnectionInfo`.
 ///
-/// Once the connection between A and Z is established and a Crust `OnConnect` event is raised,
-/// they exchange `NodeIdentify` messages.
+/// Once the connection between A and Z is established and a Crust `OnConnect` event is raised, Z
+/// sends A an `IdentifyChallenge` with a random nonce, and A responds with a `CandidateIdentify`
+/// whose signatures cover the nonce.
 ///
 ///
 /// ### Resource Proof Evaluation to approve
-/// When nodes Z of section Y receive `NodeIdentify` from A, they respond with a `ResourceProof`
-/// request. Node A needs to answer these requests (resolving a hashing challenge) with
-/// `ResourceProofResponse`. Members of Y will send out `CandidateApproval` messages to vote for the
-/// approval in their section. Once the vote succeeds, the members of Y send `NodeApproval` to A and
-/// add it into their routing table. When A receives the `NodeApproval` message, it adds the members
-/// of Y to its routing table.
+/// When nodes Z of section Y receive `CandidateIdentify` from A, they respond with a
+/// `ResourceProof` request. Node A needs to answer these requests (resolving a hashing challenge)
+/// with `ResourceProofResponse`. Members of Y will send out `CandidateApproval` messages to vote
+/// for the approval in their section. Once the vote succeeds, the members of Y send `NodeApproval`
+/// to A and add it into their routing table. When A receives the `NodeApproval` message, it adds
+/// the members of Y to its routing table.
 ///
 #[derive(Ord, PartialOrd, Eq, PartialEq, Clone, Hash, Serialize, Deserialize)]
 // FIXME - See https://maidsafe.atlassian.net/browse/MAID-2026 for info on removing this exclusion.
@@ -579,6 +690,9 @@ pub enum MessageContent {
         pub_id: PublicId,
         /// The message's unique identifier.
         msg_id: MessageId,
+        /// The sender's churn generation, i.e. the number of changes to its section it had seen
+        /// when the message was created.
+        generation: u64,
     },
     /// Respond to a `ConnectionInfoRequest` with our Crust connection info encrypted to the
     /// requester.
@@ -591,6 +705,9 @@ pub enum MessageContent {
         pub_id: PublicId,
         /// The message's unique identifier.
         msg_id: MessageId,
+        /// The sender's churn generation, i.e. the number of changes to its section it had seen
+        /// when the message was created.
+        generation: u64,
     },
     /// Reply with the address range into which the joining node should move.
     RelocateResponse {
@@ -672,6 +789,33 @@ pub enum MessageContent {
         /// contacts.
         sections: SectionMap,
     },
+    /// Announces that the sender is the proxy node of the given client.
+    ///
+    /// Sent from the proxy node to the client's `ClientManager`s when the client connects, and
+    /// repeated periodically while it stays connected.
+    ClientRelay(PublicId),
+    /// A message for a client whose proxy node is unknown, so that it can still be delivered if the
+    /// client has connected to a new proxy node.
+    ///
+    /// Sent to the client's `ClientManager`s, which pass it on to the proxy node announced via
+    /// `ClientRelay`.
+    RedirectToClient(Box<SignedMessage>),
+    /// Reports that a user message for the given client couldn't be relayed, because the client
+    /// is no longer connected to its proxy node.
+    ///
+    /// Sent from the proxy node to the source of the message.
+    ClientUnreachable(PublicId),
+    /// Asks the destination of a request to stop handling it, because the requester no longer
+    /// needs the response. `signature` is the requesting client's signature of the serialised
+    /// `msg_id`.
+    ///
+    /// Sent from the client to the destination of its request.
+    CancelRequest {
+        /// The ID of the cancelled request.
+        msg_id: MessageId,
+        /// The client's signature of the serialised `msg_id`.
+        signature: sign::Signature,
+    },
 }
 
 impl MessageContent {
@@ -737,6 +881,11 @@ impl Debug for DirectMessage {
                        leading_zero_bytes)
             }
             ResourceProofResponseReceipt => write!(formatter, "ResourceProofResponseReceipt"),
+            IdentifyChallenge(nonce) => write!(formatter, "IdentifyChallenge({})", nonce),
+            TableSample(ref names) => write!(formatter, "TableSample({} names)", names.len()),
+            PeerIdsRequest(ref names) => write!(formatter, "PeerIdsRequest({:?})", names),
+            PeerIds(ref pub_ids) => write!(formatter, "PeerIds({:?})", pub_ids),
+            PeerLost(ref name) => write!(formatter, "PeerLost({:?})", name),
         }
     }
 }
@@ -750,6 +899,72 @@ impl Debug for HopMessage {
     }
 }
 
+impl Serialize for SignedMessage {
+    fn serialize<S: Serializer>(&self, serialiser: S) -> Result<S::Ok, S::Error> {
+        #[cfg(feature = "use-mock-crust")]
+        ENCODINGS.with(|encodings| encodings.set(encodings.get() + 1));
+        (&self.content, &self.src_sections, &self.signatures).serialize(serialiser)
+    }
+}
+
+// Writes the `content` of a `HopMessage` as a byte string holding its encoding.
+fn serialise_encoded<S: Serializer>(content: &SignedMessage,
+                                    serialiser: S)
+                                    -> Result<S::Ok, S::Error> {
+    let encoding = content.encoding().map_err(ser::Error::custom)?;
+    (*encoding).serialize(serialiser)
+}
+
+// Reads the `content` of a `HopMessage` from a byte string, and keeps that as its encoding.
+fn deserialise_encoded<'de, D: Deserializer<'de>>(deserialiser: D)
+                                                  -> Result<SignedMessage, D::Error> {
+    let bytes = Vec::<u8>::deserialize(deserialiser)?;
+    let mut content: SignedMessage = deserialise(&bytes).map_err(de::Error::custom)?;
+    content.encoding = Encoding(Some(Arc::new(bytes)));
+    Ok(content)
+}
+
+/// The encoding of a `SignedMessage` as received or last sent. It is ignored by comparisons and
+/// hashing, so a received message is equal to the same message built locally.
+#[derive(Clone, Default)]
+struct Encoding(Option<Arc<Vec<u8>>>);
+
+impl PartialEq for Encoding {
+    fn eq(&self, _other: &Encoding) -> bool {
+        true
+    }
+}
+
+impl Eq for Encoding {}
+
+impl PartialOrd for Encoding {
+    fn partial_cmp(&self, other: &Encoding) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Encoding {
+    fn cmp(&self, _other: &Encoding) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+impl Hash for Encoding {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+#[cfg(feature = "use-mock-crust")]
+thread_local! {
+    static ENCODINGS: Cell<usize> = Cell::new(0);
+}
+
+/// Returns the number of times a `SignedMessage` has been encoded on this thread. Relaying a
+/// received message reuses its received encoding and isn't counted.
+#[cfg(feature = "use-mock-crust")]
+pub fn signed_message_encodings() -> usize {
+    ENCODINGS.with(|encodings| encodings.get())
+}
+
 impl Debug for SignedMessage {
     fn fmt(&self, formatter: &mut Formatter) -> fmt::Result {
         write!(formatter,
@@ -868,6 +1083,14 @@ impl Debug for MessageContent {
                        sections)
             }
             NodeApproval { ref sections } => write!(formatter, "NodeApproval {{ {:?} }}", sections),
+            ClientRelay(ref client_id) => write!(formatter, "ClientRelay({:?})", client_id),
+            RedirectToClient(ref signed_msg) => {
+                write!(formatter, "RedirectToClient({:?})", signed_msg)
+            }
+            ClientUnreachable(ref client_id) => {
+                write!(formatter, "ClientUnreachable({:?})", client_id)
+            }
+            CancelRequest { ref msg_id, .. } => write!(formatter, "CancelRequest({:?})", msg_id),
         }
     }
 }
@@ -882,6 +1105,14 @@ pub enum UserMessage {
 }
 
 impl UserMessage {
+    /// The ID of the request, or of the request the response answers.
+    pub fn message_id(&self) -> MessageId {
+        match *self {
+            UserMessage::Request(ref request) => request.message_id(),
+            UserMessage::Response(ref response) => response.message_id(),
+        }
+    }
+
     /// Splits up the message into smaller `MessageContent` parts, which can individually be sent
     /// and routed, and then be put back together by the receiver.
     pub fn to_parts(&self, priority: u8) -> Result<Vec<MessageContent>, RoutingError> {
@@ -904,6 +1135,37 @@ impl UserMessage {
                .collect())
     }
 
+    /// Returns the total number of bytes of the `Hop` messages which carry the parts of this
+    /// message from `src` to `dst` on their first hop, if sent by `sender`, whose section is
+    /// described by `src_sections`. Nothing is signed: placeholder signatures are used instead.
+    ///
+    /// This is exact for messages from a single node. For section sources, a signature from every
+    /// member of `src_sections` is counted, so the result is an upper bound. Messages relayed
+    /// within the destination section additionally list the names they were already sent to, at
+    /// `XOR_NAME_LEN` bytes each, and messages sent through a tunnel carry two more public IDs.
+    pub fn wire_size(&self,
+                     src: Authority<XorName>,
+                     dst: Authority<XorName>,
+                     sender: &PublicId,
+                     src_sections: &[SectionList])
+                     -> Result<usize, RoutingError> {
+        let mut size = 0;
+        // The priority doesn't affect the size, since it is always serialised as one byte.
+        for part in self.to_parts(DEFAULT_PRIORITY)? {
+            let routing_msg = RoutingMessage {
+                src: src,
+                dst: dst,
+                content: part,
+            };
+            let signed_msg = SignedMessage::with_placeholder_signatures(routing_msg,
+                                                                        sender,
+                                                                        src_sections.to_vec());
+            let hop_msg = HopMessage::with_placeholder_signature(signed_msg, 0, BTreeSet::new(), 0);
+            size += hop_msg.into_bytes()?.len();
+        }
+        Ok(size)
+    }
+
     /// Puts the given parts of a serialised message together and verifies that it matches the
     /// given hash code. If it does, returns the `UserMessage`.
     pub fn from_parts<'a, I: Iterator<Item = &'a Vec<u8>>>(hash: sha3::Digest256,
@@ -1077,6 +1339,19 @@ impl Request {
             false
         }
     }
+
+    /// The ID of this request, which is repeated in the response to it.
+    pub fn message_id(&self) -> MessageId {
+        match *self {
+            Request::Refresh(_, id) |
+            Request::Get(_, id) |
+            Request::Put(_, id) |
+            Request::Post(_, id) |
+            Request::Delete(_, id) |
+            Request::Append(_, id) |
+            Request::GetAccountInfo(id) => id,
+        }
+    }
 }
 
 impl Response {
@@ -1111,6 +1386,24 @@ impl Response {
             false
         }
     }
+
+    /// The ID of the request this is a response to.
+    pub fn message_id(&self) -> MessageId {
+        match *self {
+            Response::GetSuccess(_, id) |
+            Response::PutSuccess(_, id) |
+            Response::PostSuccess(_, id) |
+            Response::DeleteSuccess(_, id) |
+            Response::AppendSuccess(_, id) |
+            Response::GetAccountInfoSuccess { id, .. } |
+            Response::GetFailure { id, .. } |
+            Response::PutFailure { id, .. } |
+            Response::PostFailure { id, .. } |
+            Response::DeleteFailure { id, .. } |
+            Response::AppendFailure { id, .. } |
+            Response::GetAccountInfoFailure { id, .. } => id,
+        }
+    }
 }
 
 impl Debug for Request {
@@ -1240,10 +1533,14 @@ impl UserMessageCache {
     }
 }
 
+fn placeholder_signature() -> sign::Signature {
+    sign::Signature([0; sign::SIGNATUREBYTES])
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use data::{Data, ImmutableData};
+    use data::{Data, DataIdentifier, ImmutableData};
     use id::FullId;
     use maidsafe_utilities::serialisation::serialise;
     use rand;
@@ -1384,14 +1681,20 @@ mod tests {
         let hop_message_result = HopMessage::new(signed_message.clone(),
                                                  0,
                                                  BTreeSet::new(),
+                                                 3,
                                                  &secret_signing_key);
 
-        let hop_message = unwrap!(hop_message_result);
+        let mut hop_message = unwrap!(hop_message_result);
 
         assert_eq!(signed_message, hop_message.content);
 
         assert!(hop_message.verify(&public_signing_key).is_ok());
 
+        // The hop count is covered by the signature.
+        hop_message.hop_count = 0;
+        assert!(hop_message.verify(&public_signing_key).is_err());
+        hop_message.hop_count = 3;
+
         let (public_signing_key, _) = sign::gen_keypair();
         assert!(hop_message.verify(&public_signing_key).is_err());
     }
@@ -1429,4 +1732,118 @@ mod tests {
         let deserialised_user_msg = unwrap!(UserMessage::from_parts(msg_hash, payloads.iter()));
         assert_eq!(user_msg, deserialised_user_msg);
     }
+
+    #[test]
+    fn user_message_wire_size() {
+        let full_ids: Vec<FullId> = (0..3).map(|_| FullId::new()).collect();
+        let sender = &full_ids[0];
+        let pub_ids = full_ids.iter().map(FullId::public_id).cloned();
+        let src_sections = vec![SectionList::from(Prefix::new(0, *sender.public_id().name()),
+                                                  pub_ids)];
+        let small_msg = UserMessage::Request(Request::Get(DataIdentifier::Immutable(rand::random()),
+                                                          MessageId::new()));
+        let data_bytes: Vec<u8> = (0..(MAX_PART_LEN * 5 / 2)).map(|i| i as u8).collect();
+        let data = Data::Immutable(ImmutableData::new(data_bytes));
+        let large_msg = UserMessage::Request(Request::Put(data, MessageId::new()));
+        let srcs = [Authority::ManagedNode(*sender.public_id().name()),
+                    Authority::NaeManager(rand::random())];
+        let dst = Authority::NodeManager(rand::random());
+
+        for user_msg in &[small_msg, large_msg] {
+            for &src in &srcs {
+                // Build and serialise the messages the way they are actually sent, with all
+                // section members' signatures if the source is a section.
+                let mut sent_len = 0;
+                for part in unwrap!(user_msg.to_parts(DEFAULT_PRIORITY)) {
+                    let routing_msg = RoutingMessage {
+                        src: src,
+                        dst: dst,
+                        content: part,
+                    };
+                    let signed_bytes = unwrap!(serialise(&routing_msg));
+                    let mut signed_msg =
+                        unwrap!(SignedMessage::new(routing_msg, sender, src_sections.clone()));
+                    if src.is_multiple() {
+                        for full_id in &full_ids[1..] {
+                            let sig = sign::sign_detached(&signed_bytes,
+                                                          full_id.signing_private_key());
+                            signed_msg.add_signature(*full_id.public_id(), sig);
+                        }
+                    }
+                    let hop_msg = unwrap!(HopMessage::new(signed_msg,
+                                                          0,
+                                                          BTreeSet::new(),
+                                                          0,
+                                                          sender.signing_private_key()));
+                    sent_len += unwrap!(hop_msg.into_bytes()).len();
+                }
+                let estimate =
+                    unwrap!(user_msg.wire_size(src, dst, sender.public_id(), &src_sections));
+                assert_eq!(sent_len, estimate);
+            }
+        }
+    }
+
+    #[test]
+    fn relayed_signed_message_keeps_received_bytes() {
+        let sender = FullId::new();
+        let relay = FullId::new();
+        let data = Data::Immutable(ImmutableData::new(vec![7; MAX_PART_LEN / 2]));
+        let mut parts = unwrap!(UserMessage::Request(Request::Put(data, MessageId::new()))
+                                    .to_parts(DEFAULT_PRIORITY));
+        let routing_msg = RoutingMessage {
+            src: Authority::ManagedNode(*sender.public_id().name()),
+            dst: Authority::NaeManager(rand::random()),
+            content: parts.remove(0),
+        };
+        let signed_msg = unwrap!(SignedMessage::new(routing_msg, &sender, vec![]));
+        let signed_bytes = unwrap!(serialise(&signed_msg));
+        let sent_to: BTreeSet<_> = iter::once(*sender.public_id().name()).collect();
+        let hop_msg = unwrap!(HopMessage::new(signed_msg,
+                                              1,
+                                              sent_to,
+                                              3,
+                                              sender.signing_private_key()));
+        let tunnel_hop_msg = unwrap!(HopMessage::new(hop_msg.content.clone(),
+                                                     1,
+                                                     BTreeSet::new(),
+                                                     3,
+                                                     sender.signing_private_key()));
+        let tunnel_bytes = unwrap!(serialise(&Message::TunnelHop {
+                                                  content: tunnel_hop_msg,
+                                                  src: *sender.public_id(),
+                                                  dst: *relay.public_id(),
+                                              }));
+
+        for bytes in vec![unwrap!(hop_msg.into_bytes()), tunnel_bytes] {
+            let received = match unwrap!(deserialise::<Message>(&bytes)) {
+                Message::Hop(hop_msg) |
+                Message::TunnelHop { content: hop_msg, .. } => hop_msg,
+                msg => panic!("Unexpected message {:?}", msg),
+            };
+            unwrap!(received.verify(sender.signing_public_key()));
+            assert_eq!(received
+                           .content
+                           .encoding
+                           .0
+                           .as_ref()
+                           .map(|bytes| &bytes[..]),
+                       Some(&signed_bytes[..]));
+
+            // Relaying the message embeds the received bytes instead of encoding it again.
+            #[cfg(feature = "use-mock-crust")]
+            let encodings = signed_message_encodings();
+            let relayed = unwrap!(HopMessage::new(received.content,
+                                                  2,
+                                                  BTreeSet::new(),
+                                                  4,
+                                                  relay.signing_private_key()));
+            let relayed_bytes = unwrap!(relayed.into_bytes());
+            #[cfg(feature = "use-mock-crust")]
+            assert_eq!(signed_message_encodings(), encodings);
+            assert!(relayed_bytes
+                        .windows(signed_bytes.len())
+                        .any(|window| window == &signed_bytes[..]));
+        }
+    }
 }
@@ -0,0 +1,231 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+
+use super::support::Endpoint;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The connections of a mock service, as pairs of peer UID and endpoint, indexed by both.
+///
+/// A UID may be connected at several endpoints and an endpoint may host several UIDs, e.g. while
+/// a restarted service's old connection hasn't been removed yet. Lookups then return the lowest
+/// match. All enumerations are sorted.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Connections<UID: Copy + Ord> {
+    by_uid: BTreeMap<UID, BTreeSet<Endpoint>>,
+    by_endpoint: BTreeMap<Endpoint, BTreeSet<UID>>,
+    len: usize,
+}
+
+impl<UID: Copy + Ord> Connections<UID> {
+    pub fn new() -> Self {
+        Connections {
+            by_uid: BTreeMap::new(),
+            by_endpoint: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of connections.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns whether `uid` is connected at `endpoint`.
+    pub fn contains(&self, uid: &UID, endpoint: &Endpoint) -> bool {
+        self.by_uid
+            .get(uid)
+            .map_or(false, |endpoints| endpoints.contains(endpoint))
+    }
+
+    /// Adds the connection. Returns `false` if it already existed.
+    pub fn insert(&mut self, uid: UID, endpoint: Endpoint) -> bool {
+        if !self.by_uid
+                .entry(uid)
+                .or_insert_with(BTreeSet::new)
+                .insert(endpoint) {
+            return false;
+        }
+        let _ = self.by_endpoint
+            .entry(endpoint)
+            .or_insert_with(BTreeSet::new)
+            .insert(uid);
+        self.len += 1;
+        true
+    }
+
+    /// Returns the endpoint `uid` is connected at.
+    pub fn endpoint_of(&self, uid: &UID) -> Option<Endpoint> {
+        self.by_uid
+            .get(uid)
+            .and_then(|endpoints| endpoints.iter().next().cloned())
+    }
+
+    /// Returns the UID connected at `endpoint`.
+    pub fn uid_at(&self, endpoint: &Endpoint) -> Option<UID> {
+        self.by_endpoint
+            .get(endpoint)
+            .and_then(|uids| uids.iter().next().cloned())
+    }
+
+    /// Removes a connection to `uid` and returns its endpoint.
+    pub fn remove_uid(&mut self, uid: &UID) -> Option<Endpoint> {
+        let endpoint = match self.endpoint_of(uid) {
+            Some(endpoint) => endpoint,
+            None => return None,
+        };
+        self.remove(uid, &endpoint);
+        Some(endpoint)
+    }
+
+    /// Removes a connection at `endpoint` and returns its UID.
+    pub fn remove_endpoint(&mut self, endpoint: &Endpoint) -> Option<UID> {
+        let uid = match self.uid_at(endpoint) {
+            Some(uid) => uid,
+            None => return None,
+        };
+        self.remove(&uid, endpoint);
+        Some(uid)
+    }
+
+    /// Returns all connections, sorted by UID and then endpoint.
+    pub fn pairs(&self) -> Vec<(UID, Endpoint)> {
+        self.by_uid
+            .iter()
+            .flat_map(|(uid, endpoints)| endpoints.iter().map(move |endpoint| (*uid, *endpoint)))
+            .collect()
+    }
+
+    /// Returns the UID of each connection, sorted.
+    pub fn uids(&self) -> Vec<UID> {
+        self.pairs().into_iter().map(|(uid, _)| uid).collect()
+    }
+
+    /// Returns the endpoint of each connection, sorted.
+    pub fn endpoints(&self) -> Vec<Endpoint> {
+        self.by_endpoint
+            .iter()
+            .flat_map(|(endpoint, uids)| uids.iter().map(move |_| *endpoint))
+            .collect()
+    }
+
+    /// Removes all connections.
+    pub fn clear(&mut self) {
+        self.by_uid.clear();
+        self.by_endpoint.clear();
+        self.len = 0;
+    }
+
+    fn remove(&mut self, uid: &UID, endpoint: &Endpoint) {
+        let uid_empty = self.by_uid.get_mut(uid).map_or(false, |endpoints| {
+            let _ = endpoints.remove(endpoint);
+            endpoints.is_empty()
+        });
+        if uid_empty {
+            let _ = self.by_uid.remove(uid);
+        }
+        let endpoint_empty = self.by_endpoint.get_mut(endpoint).map_or(false, |uids| {
+            let _ = uids.remove(uid);
+            uids.is_empty()
+        });
+        if endpoint_empty {
+            let _ = self.by_endpoint.remove(endpoint);
+        }
+        self.len -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn indexes_kept_consistent() {
+        let mut connections = Connections::new();
+        assert!(connections.insert(2u64, Endpoint(20)));
+        assert!(connections.insert(1u64, Endpoint(10)));
+        assert!(!connections.insert(1u64, Endpoint(10)));
+        // The same UID at another endpoint, and another UID at the same endpoint.
+        assert!(connections.insert(1u64, Endpoint(11)));
+        assert!(connections.insert(3u64, Endpoint(10)));
+        assert_eq!(connections.len(), 4);
+
+        assert_eq!(connections.pairs(),
+                   vec![(1, Endpoint(10)), (1, Endpoint(11)), (2, Endpoint(20)),
+                        (3, Endpoint(10))]);
+        assert_eq!(connections.uids(), vec![1, 1, 2, 3]);
+        assert_eq!(connections.endpoints(),
+                   vec![Endpoint(10), Endpoint(10), Endpoint(11), Endpoint(20)]);
+        assert_eq!(connections.endpoint_of(&1), Some(Endpoint(10)));
+        assert_eq!(connections.uid_at(&Endpoint(10)), Some(1));
+
+        assert_eq!(connections.remove_uid(&1), Some(Endpoint(10)));
+        assert_eq!(connections.endpoint_of(&1), Some(Endpoint(11)));
+        assert_eq!(connections.uid_at(&Endpoint(10)), Some(3));
+        assert_eq!(connections.remove_endpoint(&Endpoint(10)), Some(3));
+        assert_eq!(connections.remove_endpoint(&Endpoint(10)), None);
+        assert_eq!(connections.remove_uid(&3), None);
+        assert!(connections.contains(&1, &Endpoint(11)));
+        assert!(!connections.contains(&1, &Endpoint(10)));
+        assert_eq!(connections.len(), 2);
+
+        connections.clear();
+        assert!(connections.is_empty());
+        assert_eq!(connections.endpoint_of(&2), None);
+        assert_eq!(connections.uid_at(&Endpoint(20)), None);
+    }
+
+    // Compares looking up each of a few thousand connections in both directions against the
+    // linear scans of a plain list of pairs.
+    #[test]
+    fn lookups_faster_than_linear_scan() {
+        let count = 4000;
+        let list: Vec<(u64, Endpoint)> = (0..count)
+            .map(|i| (i as u64 * 7919 % count as u64, Endpoint(i)))
+            .collect();
+        let mut connections = Connections::new();
+        for &(uid, endpoint) in &list {
+            assert!(connections.insert(uid, endpoint));
+        }
+
+        let started = Instant::now();
+        for &(uid, endpoint) in &list {
+            assert_eq!(list.iter().find(|&&(id, _)| id == uid).map(|&(_, ep)| ep),
+                       Some(endpoint));
+            assert_eq!(list.iter().find(|&&(_, ep)| ep == endpoint).map(|&(id, _)| id),
+                       Some(uid));
+        }
+        let linear = started.elapsed();
+
+        let started = Instant::now();
+        for &(uid, endpoint) in &list {
+            assert_eq!(connections.endpoint_of(&uid), Some(endpoint));
+            assert_eq!(connections.uid_at(&endpoint), Some(uid));
+        }
+        let indexed = started.elapsed();
+
+        assert!(indexed < linear || linear == Duration::from_secs(0),
+                "Indexed lookups took {:?}, linear scans {:?}.",
+                indexed,
+                linear);
+    }
+}
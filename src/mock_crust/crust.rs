@@ -23,7 +23,7 @@ use std::{fmt, io, thread};
 use std::cell::{RefCell, RefMut};
 use std::collections::HashSet;
 use std::hash::Hash;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::rc::Rc;
 
 /// TCP listener port
@@ -124,11 +124,23 @@ impl<UID: Uid> Service<UID> {
         self.lock_and_poll(|imp| imp.disconnect(&uid))
     }
 
+    /// Raises a `NewMessage` event with `data` as if it had been received from the connected peer
+    /// at `endpoint`. Returns `false` if we aren't connected to it. Only exists in mock Crust.
+    pub fn inject_message(&self, endpoint: Endpoint, data: Vec<u8>) -> bool {
+        self.lock().inject_message(endpoint, data)
+    }
+
     /// Send message to the given peer.
     // TODO: Implement tests that drop low-priority messages.
-    pub fn send(&self, id: UID, data: Vec<u8>, _priority: u8) -> io::Result<()> {
-        if self.lock_and_poll(|imp| imp.send_message(&id, data)) {
-            Ok(())
+    pub fn send(&self, id: UID, data: Vec<u8>, priority: u8) -> io::Result<()> {
+        self.send_with_id(id, data, priority).map(|_| ())
+    }
+
+    /// Send message to the given peer and return the message ID which is reported in the
+    /// `MessageDelivered` or `MessageFailed` event, if the network has send confirmations enabled.
+    pub fn send_with_id(&self, id: UID, data: Vec<u8>, _priority: u8) -> io::Result<u64> {
+        if let Some(msg_id) = self.lock_and_poll(|imp| imp.send_message(&id, data)) {
+            Ok(msg_id)
         } else {
             let msg = format!("No connection to peer {:?}", id);
             Err(io::Error::new(io::ErrorKind::Other, msg))
@@ -140,6 +152,16 @@ impl<UID: Uid> Service<UID> {
         self.lock_and_poll(|imp| imp.is_peer_connected(uid))
     }
 
+    /// Returns the IP address of the given connected peer.
+    pub fn get_peer_ip_addr(&self, uid: &UID) -> Result<IpAddr, CrustError> {
+        self.lock().peer_ip_addr(uid).ok_or(CrustError)
+    }
+
+    /// Returns the socket address of the given connected peer.
+    pub fn get_peer_socket_addr(&self, uid: &UID) -> Result<SocketAddr, CrustError> {
+        self.lock().peer_socket_addr(uid).ok_or(CrustError)
+    }
+
     /// Adds the peer to the whitelist, allowing them to connect to us.
     pub fn whitelist_peer(&self, endpoint: Endpoint) {
         self.lock().whitelist_peer(endpoint);
@@ -170,9 +192,15 @@ impl<UID: Uid> Service<UID> {
 
 impl<UID: Uid> Drop for Service<UID> {
     fn drop(&mut self) {
-        if !thread::panicking() {
-            self.lock_and_poll(|imp| imp.disconnect_all());
+        if thread::panicking() {
+            return;
+        }
+        // The service may be dropped from within one of its own callbacks, in which case the
+        // `ServiceImpl` drops its connections itself once its last handle is gone.
+        if let Ok(mut imp) = self.0.try_borrow_mut() {
+            imp.disconnect_all();
         }
+        self.1.poll();
     }
 }
 
@@ -185,6 +213,11 @@ pub enum Event<UID: Uid> {
     BootstrapConnect(UID, SocketAddr),
     /// Invoked when we failed to connect to all bootstrap contacts.
     BootstrapFailed,
+    /// Invoked when a bootstrap contact refused us because it doesn't accept our kind of peer.
+    BootstrapRefused(SocketAddr, CrustUser),
+    /// Invoked for each bootstrap contact we failed to bootstrap off, with the reason. Only exists
+    /// in mock Crust.
+    BootstrapAttemptFailed(SocketAddr, BootstrapFailureReason),
     /// Invoked when we are ready to listen for incomming connection. Contains
     /// the listening port.
     ListenerStarted(u16),
@@ -202,6 +235,12 @@ pub enum Event<UID: Uid> {
     NewMessage(UID, Vec<u8>),
     /// Invoked when trying to sending a too large data.
     WriteMsgSizeProhibitive(UID, Vec<u8>),
+    /// Invoked when a message with the given ID has been received by the peer. Only raised if
+    /// send confirmations are enabled in the mock network.
+    MessageDelivered(UID, u64),
+    /// Invoked when a message with the given ID has been dropped before reaching the peer. Only
+    /// raised if send confirmations are enabled in the mock network.
+    MessageFailed(UID, u64),
 }
 
 /// Mock version of `CrustEventSender`.
@@ -262,10 +301,26 @@ pub struct ConnectionInfoResult<UID: Uid> {
 #[derive(Debug)]
 pub struct CrustError;
 
+/// Why an attempt to bootstrap off a single contact failed, as reported in
+/// `Event::BootstrapAttemptFailed`.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum BootstrapFailureReason {
+    /// The contact isn't listening for incoming connections.
+    NotListening,
+    /// The contact doesn't accept our kind of peer.
+    Refused(CrustUser),
+    /// The connection to the contact is blocked in the mock network.
+    Blocked,
+    /// The contact has a whitelist which doesn't include us.
+    NotWhitelisted,
+    /// The contact, or we ourselves, already have the maximum number of connections.
+    ConnectionLimit,
+}
+
 /// Specify crust user. Behaviour (for example in bootstrap phase) will be different for different
 /// variants. Node will request the Bootstrapee to connect back to this crust failing which it
 /// would mean it's not reachable from outside and hence should be rejected bootstrap attempts.
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum CrustUser {
     /// Crust user is a Node and should not be allowed to bootstrap if it's not reachable from
     /// outside.
@@ -15,6 +15,7 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
+mod connections;
 /// Mock crust main module. This module provides mock version of all public
 /// types and methods of crust.
 pub mod crust;
@@ -23,4 +24,10 @@ mod support;
 #[cfg(test)]
 mod tests;
 
-pub use self::support::{Config, Endpoint, Network, ServiceHandle, get_current, make_current};
+pub use crypto::script::{clear_crypto_failures, fail_crypto_init, fail_signing,
+                         fail_verification};
+pub use self::support::{BootstrapPolicy, CONTROL_PACKET_SIZE, Config, ConnectionInfoBehaviour,
+                        DEFAULT_POLL_LIMIT, Delivery, Endpoint, EndpointRange, IdFactory,
+                        LinkImpairment, MockError, Network, NetworkSnapshot, ObservedPacket,
+                        PacketKind, PacketKindMask, ServiceHandle, TraceEntry, get_current,
+                        make_current};
@@ -15,57 +15,315 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
-use super::crust::{ConnectionInfoResult, CrustEventSender, CrustUser, Event, PrivConnectionInfo,
-                   PubConnectionInfo, Uid};
+use super::connections::Connections;
+use super::crust::{BootstrapFailureReason, ConnectionInfoResult, CrustError, CrustEventSender,
+                   CrustUser, Event, PrivConnectionInfo, PubConnectionInfo, Uid};
 use CrustEvent;
-use id::PublicId;
+use crypto::{self, CryptoError};
+use id::{FullId, PublicId};
 use maidsafe_utilities::SeededRng;
 use rand::Rng;
+use routing_table::Prefix;
 use rust_sodium;
-use std::cell::RefCell;
-use std::cmp;
-use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use rust_sodium::crypto::{box_, sign};
+use std::cell::{Cell, RefCell};
+use std::{cmp, error, fmt, iter, mem};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::collections::btree_map::Entry;
+use std::io;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::rc::{Rc, Weak};
+use wire_kind::WireKind;
+use xor_name::XorName;
+
+/// The number of bytes each packet other than a message counts with in the traffic statistics.
+pub const CONTROL_PACKET_SIZE: u64 = 32;
+/// The default maximum number of packets a single `Network::poll` processes before it panics.
+pub const DEFAULT_POLL_LIMIT: usize = 1_000_000;
+/// The number of busiest routes listed when `Network::poll` exceeds its limit.
+const POLL_LIMIT_DUMP_ROUTES: usize = 10;
 
 /// Mock network. Create one before testing with mocks. Use it to create `ServiceHandle`s.
+///
+/// Services only hold weak handles to the network, so once all clones of the `Network` have been
+/// dropped it is closed: its queues are cleared, and the services still alive no longer send any
+/// packets nor poll it. This lets a test tear down the network and services in any order.
 #[derive(Clone)]
-pub struct Network<UID: Uid>(Rc<RefCell<NetworkImpl<UID>>>);
+pub struct Network<UID: Uid>(Rc<RefCell<NetworkImpl<UID>>>, Option<Rc<NetworkOwner<UID>>>);
+
+// Closes the network when the last `Network` created by `Network::new`, or cloned from it, is
+// dropped. The handles held by services and by the network's own helpers don't own one.
+struct NetworkOwner<UID: Uid>(Weak<RefCell<NetworkImpl<UID>>>);
+
+impl<UID: Uid> Drop for NetworkOwner<UID> {
+    fn drop(&mut self) {
+        let network_impl = match self.0.upgrade() {
+            Some(network_impl) => network_impl,
+            None => return,
+        };
+        match network_impl.try_borrow_mut() {
+            Ok(mut network_impl) => network_impl.close(),
+            Err(_) => debug!("Network dropped while in use. Leaving it open."),
+        };
+    }
+}
 
 pub struct NetworkImpl<UID: Uid> {
     services: HashMap<Endpoint, Weak<RefCell<ServiceImpl<UID>>>>,
     min_section_size: usize,
     next_endpoint: usize,
-    queue: BTreeMap<(Endpoint, Endpoint), VecDeque<Packet<UID>>>,
-    blocked_connections: HashSet<(Endpoint, Endpoint)>,
+    /// Endpoints which were explicitly requested, and are never generated automatically.
+    reserved_endpoints: BTreeSet<Endpoint>,
+    /// Ranges reserved via `reserve_range`, whose endpoints are never generated automatically.
+    endpoint_ranges: Vec<ReservedRange>,
+    /// The incarnation of the service last registered at each endpoint, counting from 1.
+    incarnations: HashMap<Endpoint, u64>,
+    /// Number of packets dropped because they were addressed to an earlier incarnation.
+    stale_packets: usize,
+    /// Whether stale packets are answered with the corresponding failure instead of vanishing.
+    stale_packet_failures: bool,
+    queue: BTreeMap<(Endpoint, Endpoint), VecDeque<QueuedPacket<UID>>>,
+    blocked_connections: HashMap<(Endpoint, Endpoint), PacketKindMask>,
+    /// Per route, the kinds of messages blocked via `block_wire_kind`.
+    blocked_wire_kinds: HashMap<(Endpoint, Endpoint), BTreeSet<WireKind>>,
     delayed_connections: HashSet<(Endpoint, Endpoint)>,
+    held_connections: HashSet<(Endpoint, Endpoint)>,
+    blackholed_connections: HashSet<(Endpoint, Endpoint)>,
+    /// Routes cut by `partition`, in both directions.
+    partitioned_connections: HashSet<(Endpoint, Endpoint)>,
+    /// Per route, the threshold below which a random `u32` means a packet is lost.
+    packet_loss: HashMap<(Endpoint, Endpoint), u64>,
+    /// Per route, the threshold below which a random `u32` means a message is delivered twice.
+    duplication: HashMap<(Endpoint, Endpoint), u64>,
+    /// Routes on which messages can overtake each other.
+    reordering_connections: HashSet<(Endpoint, Endpoint)>,
+    pending_connection_infos: Vec<PendingConnectionInfo>,
+    /// Number of polls without any packets after which a connection is dropped.
+    idle_timeout: Option<usize>,
+    /// Number of polls each connection has been idle for, keyed by its endpoints in ascending order.
+    idle_polls: HashMap<(Endpoint, Endpoint), usize>,
+    /// Connections which carried packets during the current poll.
+    active_connections: HashSet<(Endpoint, Endpoint)>,
+    /// IP addresses explicitly assigned to endpoints.
+    ip_addrs: HashMap<Endpoint, IpAddr>,
     rng: SeededRng,
+    /// Seed from which the IDs produced by `id_factory` are derived.
+    id_seed: [u32; 4],
+    message_sent: bool,
+    send_confirmations: bool,
+    next_msg_id: u64,
+    /// Networks whose services can be reached from this one.
+    bridged: Vec<Weak<RefCell<NetworkImpl<UID>>>>,
+    /// Bytes sent and received by each endpoint, counting only packets delivered to a service.
+    traffic: HashMap<Endpoint, (u64, u64)>,
+    /// Messages delivered to a service, by kind.
+    wire_kind_counts: BTreeMap<WireKind, usize>,
+    /// Called for each packet processed, whether it is delivered or not.
+    packet_observer: Option<Rc<RefCell<Box<FnMut(&ObservedPacket)>>>>,
+    /// The packets taken off the queue since `Network::start_recording`, if recording.
+    trace: Option<Vec<TraceEntry>>,
+    /// The rest of the trace passed to `Network::replay`, which determines the next packets to
+    /// be taken off the queue.
+    replay: VecDeque<TraceEntry>,
+    /// Set once all owning `Network` handles are dropped. No more packets are queued afterwards.
+    closed: bool,
+    /// The maximum number of packets a single `Network::poll` processes, if limited.
+    poll_limit: Option<usize>,
+}
+
+impl<UID: Uid> NetworkImpl<UID> {
+    fn close(&mut self) {
+        self.closed = true;
+        self.queue.clear();
+        self.pending_connection_infos.clear();
+    }
+
+    // Returns the incarnation of the service last registered at `endpoint` on this or a bridged
+    // network, or 0 if there never was one.
+    // Returns the reserved range containing the endpoint, if any.
+    fn range_of(&self, endpoint: Endpoint) -> Option<&ReservedRange> {
+        self.endpoint_ranges
+            .iter()
+            .find(|range| range.start <= endpoint.0 && endpoint.0 < range.end)
+    }
+
+    fn incarnation(&self, endpoint: Endpoint) -> u64 {
+        if let Some(&incarnation) = self.incarnations.get(&endpoint) {
+            return incarnation;
+        }
+        self.bridged
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter_map(|other| {
+                            let incarnation = other.borrow().incarnations.get(&endpoint).cloned();
+                            incarnation
+                        })
+            .next()
+            .unwrap_or(0)
+    }
+}
+
+// The packets processed by a single `Network::poll`, by route and kind.
+#[derive(Default)]
+struct PollStats {
+    processed: usize,
+    routes: BTreeMap<(Endpoint, Endpoint), BTreeMap<PacketKind, usize>>,
+    kinds: BTreeMap<PacketKind, usize>,
+}
+
+impl PollStats {
+    fn record(&mut self, sender: Endpoint, receiver: Endpoint, kind: PacketKind) {
+        self.processed += 1;
+        *self.routes
+             .entry((sender, receiver))
+             .or_insert_with(BTreeMap::new)
+             .entry(kind)
+             .or_insert(0) += 1;
+        *self.kinds.entry(kind).or_insert(0) += 1;
+    }
+}
+
+// A queued packet, stamped with the incarnation of the receiving service known when it was sent.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct QueuedPacket<UID: Uid> {
+    packet: Packet<UID>,
+    incarnation: u64,
+    /// Set on the second copy of a duplicated message, so that it isn't duplicated again.
+    duplicate: bool,
+}
+
+// A `prepare_connection_info` call whose result is withheld until enough network polls elapsed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct PendingConnectionInfo {
+    endpoint: Endpoint,
+    result_token: u32,
+    polls_remaining: usize,
+    /// Whether the result is an error once it is delivered.
+    failed: bool,
+}
+
+/// The network-level state of a `Network`, as captured by `Network::snapshot`.
+///
+/// This covers the packet queues, the blocked, delayed, held, blackholed, partitioned, lossy,
+/// duplicating and reordering connections, the endpoint, incarnation and message counters, the
+/// reserved endpoint ranges with their labels, the random number generator and the connections
+/// and flags of each live service. It doesn't cover
+/// the routing state of the nodes driving the services or their event channels, nor any networks
+/// bridged with this one. So restoring a snapshot only reproduces a run if the nodes are rebuilt
+/// deterministically as well, e.g. from the same seed.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NetworkSnapshot<UID: Uid> {
+    next_endpoint: usize,
+    endpoint_ranges: Vec<ReservedRange>,
+    incarnations: HashMap<Endpoint, u64>,
+    stale_packets: usize,
+    stale_packet_failures: bool,
+    queue: BTreeMap<(Endpoint, Endpoint), VecDeque<QueuedPacket<UID>>>,
+    blocked_connections: HashMap<(Endpoint, Endpoint), PacketKindMask>,
+    blocked_wire_kinds: HashMap<(Endpoint, Endpoint), BTreeSet<WireKind>>,
+    delayed_connections: HashSet<(Endpoint, Endpoint)>,
+    held_connections: HashSet<(Endpoint, Endpoint)>,
+    blackholed_connections: HashSet<(Endpoint, Endpoint)>,
+    partitioned_connections: HashSet<(Endpoint, Endpoint)>,
+    packet_loss: HashMap<(Endpoint, Endpoint), u64>,
+    duplication: HashMap<(Endpoint, Endpoint), u64>,
+    reordering_connections: HashSet<(Endpoint, Endpoint)>,
+    pending_connection_infos: Vec<PendingConnectionInfo>,
+    idle_timeout: Option<usize>,
+    idle_polls: HashMap<(Endpoint, Endpoint), usize>,
+    rng_seed: [u32; 4],
     message_sent: bool,
+    send_confirmations: bool,
+    next_msg_id: u64,
+    services: BTreeMap<Endpoint, ServiceSnapshot<UID>>,
+}
+
+// A range of endpoints reserved via `Network::reserve_range`, and the next one to hand out.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ReservedRange {
+    label: &'static str,
+    start: usize,
+    end: usize,
+    next: usize,
+}
+
+// The state of a single service captured in a `NetworkSnapshot`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ServiceSnapshot<UID: Uid> {
+    listening_tcp: bool,
+    pending_bootstraps: u64,
+    connections: Connections<UID>,
+    whitelist: HashSet<Endpoint>,
+    connection_info_behaviour: ConnectionInfoBehaviour,
+    connection_info_failures: usize,
+    accept_bootstrap: BootstrapPolicy,
+    keepalive_interval: Option<usize>,
+    polls_since_keepalive: usize,
 }
 
 impl<UID: Uid> Network<UID> {
-    /// Create new mock Network.
+    /// Create new mock Network. Panics if the crypto library fails to initialise.
     pub fn new(min_section_size: usize, optional_seed: Option<[u32; 4]>) -> Self {
+        unwrap!(Self::try_new(min_section_size, optional_seed))
+    }
+
+    /// Create new mock Network, or return an error if the crypto library fails to initialise.
+    pub fn try_new(min_section_size: usize,
+                   optional_seed: Option<[u32; 4]>)
+                   -> Result<Self, CryptoError> {
         let mut rng = if let Some(seed) = optional_seed {
             SeededRng::from_seed(seed)
         } else {
             SeededRng::new()
         };
-        unwrap!(rust_sodium::init_with_rng(&mut rng));
-        Network(Rc::new(RefCell::new(NetworkImpl {
+        if rust_sodium::init_with_rng(&mut rng).is_err() {
+            return Err(CryptoError::InitFailed);
+        }
+        crypto::init()?;
+        let id_seed = rng.gen();
+        let network_impl = Rc::new(RefCell::new(NetworkImpl {
                                          services: HashMap::new(),
                                          min_section_size: min_section_size,
                                          next_endpoint: 0,
+                                         reserved_endpoints: BTreeSet::new(),
+                                         endpoint_ranges: Vec::new(),
+                                         incarnations: HashMap::new(),
+                                         stale_packets: 0,
+                                         stale_packet_failures: false,
                                          queue: BTreeMap::new(),
-                                         blocked_connections: HashSet::new(),
+                                         blocked_connections: HashMap::new(),
+                                         blocked_wire_kinds: HashMap::new(),
                                          delayed_connections: HashSet::new(),
+                                         held_connections: HashSet::new(),
+                                         blackholed_connections: HashSet::new(),
+                                         partitioned_connections: HashSet::new(),
+                                         packet_loss: HashMap::new(),
+                                         duplication: HashMap::new(),
+                                         reordering_connections: HashSet::new(),
+                                         pending_connection_infos: Vec::new(),
+                                         idle_timeout: None,
+                                         idle_polls: HashMap::new(),
+                                         active_connections: HashSet::new(),
+                                         ip_addrs: HashMap::new(),
                                          // Use `SeededRng::new()` here rather than passing in `rng`
                                          // so that a fresh one is used in every test, i.e. it will
                                          // not have been affected by initialising rust_sodium.
                                          rng: SeededRng::new(),
+                                         id_seed: id_seed,
                                          message_sent: false,
-                                     })))
+                                         send_confirmations: false,
+                                         next_msg_id: 0,
+                                         bridged: Vec::new(),
+                                         traffic: HashMap::new(),
+                                         wire_kind_counts: BTreeMap::new(),
+                                         packet_observer: None,
+                                         trace: None,
+                                         replay: VecDeque::new(),
+                                         closed: false,
+                                         poll_limit: Some(DEFAULT_POLL_LIMIT),
+                                     }));
+        let owner = NetworkOwner(Rc::downgrade(&network_impl));
+        Ok(Network(network_impl, Some(Rc::new(owner))))
     }
 
     /// Create new ServiceHandle.
@@ -73,55 +331,538 @@ impl<UID: Uid> Network<UID> {
                               opt_config: Option<Config>,
                               opt_endpoint: Option<Endpoint>)
                               -> ServiceHandle<UID> {
-        let config = opt_config.unwrap_or_else(Config::new);
         let endpoint = self.gen_endpoint(opt_endpoint);
+        self.add_service(opt_config, endpoint)
+    }
 
-        let handle = ServiceHandle::new(self.clone(), config, endpoint);
-        if self.0
+    /// Reserves the `len` endpoints starting at `start` under `label`, and returns a handle which
+    /// hands them out in order. Automatically generated endpoints, on this network and the ones
+    /// bridged with it, never fall into a reserved range. This lets a test give each group of
+    /// services its own recognisable endpoints, e.g. 100 to 199 for one section's nodes.
+    ///
+    /// The label is shown with the range's endpoints in the network's diagnostics and snapshots.
+    /// Panics if the range overlaps one reserved before on this or a bridged network.
+    pub fn reserve_range(&self,
+                         start: usize,
+                         len: usize,
+                         label: &'static str)
+                         -> EndpointRange<UID> {
+        let end = start + len;
+        for network in self.with_bridged() {
+            for range in &network.0.borrow().endpoint_ranges {
+                assert!(end <= range.start || range.end <= start,
+                        "Endpoint range {} overlaps the reserved range {}.",
+                        label,
+                        range.label);
+            }
+        }
+        let mut imp = self.0.borrow_mut();
+        imp.endpoint_ranges
+            .push(ReservedRange {
+                      label: label,
+                      start: start,
+                      end: end,
+                      next: start,
+                  });
+        EndpointRange {
+            network: self.unowned(),
+            index: imp.endpoint_ranges.len() - 1,
+        }
+    }
+
+    /// Returns the label of the reserved range containing the endpoint, if any.
+    pub fn endpoint_label(&self, endpoint: Endpoint) -> Option<&'static str> {
+        self.with_bridged()
+            .iter()
+            .filter_map(|network| network.0.borrow().range_of(endpoint).map(|range| range.label))
+            .next()
+    }
+
+    // Registers a new service at the endpoint, which must have been allocated already.
+    fn add_service(&self, opt_config: Option<Config>, endpoint: Endpoint) -> ServiceHandle<UID> {
+        let config = opt_config.unwrap_or_else(Config::new);
+        let handle = ServiceHandle::new(self.unowned(), config, endpoint);
+        // An endpoint whose service has been dropped can be reused, e.g. to simulate a restart.
+        if let Some(old_service) = self.0
                .borrow_mut()
                .services
-               .insert(endpoint, Rc::downgrade(&handle.0))
-               .is_some() {
-            debug!("Tried to insert duplicate service handle ");
+               .insert(endpoint, Rc::downgrade(&handle.0)) {
+            assert!(old_service.upgrade().is_none(),
+                    "Tried to register a second service on {:?}.",
+                    endpoint);
         }
+        handle.0.borrow_mut().incarnation = self.next_incarnation(endpoint);
 
         handle
     }
 
+    /// Sets whether packets addressed to an earlier incarnation of the service at their receiving
+    /// endpoint are answered with the corresponding failure, as over a blocked connection. By
+    /// default they silently vanish. Either way they are never delivered to the new incarnation.
+    pub fn set_stale_packet_failures(&self, enable: bool) {
+        self.0.borrow_mut().stale_packet_failures = enable;
+    }
+
+    /// Returns the number of packets dropped because they were sent before the service at their
+    /// receiving endpoint was restarted. Networks bridged with this one are included.
+    pub fn stale_packet_count(&self) -> usize {
+        self.with_bridged()
+            .iter()
+            .map(|network| network.0.borrow().stale_packets)
+            .sum()
+    }
+
+    /// Returns whether the network has been closed, i.e. all `Network` handles to it have been
+    /// dropped and only services are left.
+    pub fn is_closed(&self) -> bool {
+        self.0.borrow().closed
+    }
+
     /// Get min_section_size
     pub fn min_section_size(&self) -> usize {
         self.0.borrow().min_section_size
     }
 
     /// Generate unique Endpoint
+    ///
+    /// Generated endpoints are unique across this network and all networks bridged with it, and
+    /// skip any endpoint which was requested explicitly before or lies in a reserved range. An
+    /// explicitly requested endpoint must not be in use by a live service on this or a bridged
+    /// network, or this panics. The endpoints of dropped services can be reused.
     pub fn gen_endpoint(&self, opt_endpoint: Option<Endpoint>) -> Endpoint {
-        let mut imp = self.0.borrow_mut();
+        let networks = self.with_bridged();
         let endpoint = if let Some(endpoint) = opt_endpoint {
+            if self.find_local_service(endpoint).is_some() {
+                panic!("{:?} is already in use by a live service on this network.",
+                       endpoint);
+            }
+            if let Some(network) = networks[1..]
+                   .iter()
+                   .find(|network| network.find_local_service(endpoint).is_some()) {
+                panic!("{:?} is already in use on bridged network {:?}.",
+                       endpoint,
+                       network);
+            }
+            let _ = self.0.borrow_mut().reserved_endpoints.insert(endpoint);
             endpoint
         } else {
-            Endpoint(imp.next_endpoint)
+            let mut endpoint = Endpoint(networks
+                                            .iter()
+                                            .map(|network| network.0.borrow().next_endpoint)
+                                            .max()
+                                            .unwrap_or(0));
+            while networks
+                      .iter()
+                      .any(|network| network.endpoint_taken(endpoint)) {
+                endpoint.0 += 1;
+            }
+            endpoint
         };
-        imp.next_endpoint = cmp::max(imp.next_endpoint, endpoint.0 + 1);
+        for network in &networks {
+            let mut imp = network.0.borrow_mut();
+            imp.next_endpoint = cmp::max(imp.next_endpoint, endpoint.0 + 1);
+        }
         endpoint
     }
 
-    /// Poll and process all queued Packets.
+    /// Poll and process all queued Packets, and release any delayed connection infos which are
+    /// due. Keep-alives are sent and idle connections dropped as configured. Networks bridged with
+    /// this one are polled too.
+    ///
+    /// Panics if more packets than the limit set via `set_poll_limit` are processed, which
+    /// usually means that services keep responding to each other's packets forever.
     pub fn poll(&self) {
-        while let Some((sender, receiver, packet)) = self.pop_packet() {
-            self.process_packet(sender, receiver, packet);
+        let limit = self.0.borrow().poll_limit;
+        let _ = self.poll_with_limit(limit.unwrap_or(::std::usize::MAX));
+    }
+
+    /// Polls like `poll`, but panics once more than `max_packets` packets have been processed. The
+    /// panic message lists the routes which carried the most packets, and the kinds of packets
+    /// processed, to point out which services are looping. Returns the number of packets
+    /// processed.
+    pub fn poll_with_limit(&self, max_packets: usize) -> usize {
+        if !self.is_available() {
+            return 0;
+        }
+        let networks = self.with_bridged();
+        for network in &networks {
+            network.send_keepalives();
+        }
+        // Packets processed on one network can queue replies on another, so keep going until all
+        // queues are empty.
+        let mut stats = PollStats::default();
+        while let Some((sender, receiver, kind)) = self.poll_packet() {
+            stats.record(sender, receiver, kind);
+            if stats.processed > max_packets {
+                panic!("{}", self.poll_limit_dump(max_packets, &stats));
+            }
+        }
+        for network in &networks {
+            network.release_connection_infos();
+            network.expire_idle_connections();
         }
+        stats.processed
     }
 
-    /// Causes all packets from `sender` to `receiver` to fail.
-    pub fn block_connection(&self, sender: Endpoint, receiver: Endpoint) {
+    /// Sets the maximum number of packets a single `poll` processes before it panics. `None`
+    /// removes the limit, e.g. for soak tests which legitimately exchange huge numbers of packets.
+    /// The default is `DEFAULT_POLL_LIMIT`.
+    pub fn set_poll_limit(&self, limit: Option<usize>) {
+        self.0.borrow_mut().poll_limit = limit;
+    }
+
+    /// Processes a single queued packet, picked the same way as by `poll`, so that tests can make
+    /// assertions between individual deliveries. Packets queued on this network are processed
+    /// before those on networks bridged with it. Returns `false` if there were no packets left.
+    ///
+    /// Unlike `poll`, this doesn't send keep-alives, release delayed connection infos or expire
+    /// idle connections.
+    pub fn poll_once(&self) -> bool {
+        self.is_available() && self.poll_packet().is_some()
+    }
+
+    /// Processes up to `n` queued packets one at a time, as by `poll_once`. Returns the number of
+    /// packets processed.
+    pub fn poll_n(&self, n: usize) -> usize {
+        (0..n).take_while(|_| self.poll_once()).count()
+    }
+
+    /// Drops connections which didn't carry any packets for the given number of polls, as if torn
+    /// down by a NAT or firewall: both ends receive a `LostPeer` event. `None` disables this.
+    pub fn set_idle_timeout(&self, polls: Option<usize>) {
         let mut imp = self.0.borrow_mut();
-        imp.blocked_connections.insert((sender, receiver));
+        imp.idle_timeout = polls;
+        imp.idle_polls.clear();
+        imp.active_connections.clear();
+    }
+
+    /// Assigns the given IP address to `endpoint`, to be reported in its socket addresses.
+    /// Endpoints without an assigned address all share the same default IP.
+    pub fn assign_ip(&self, endpoint: Endpoint, ip: IpAddr) {
+        let _ = self.0.borrow_mut().ip_addrs.insert(endpoint, ip);
+    }
+
+    /// Links this network with `other`, so that the services of either can connect and send
+    /// packets to the services of the other. Bridging is not transitive.
+    ///
+    /// Panics if both networks have a live service on the same endpoint.
+    pub fn bridge(&self, other: &Network<UID>) {
+        assert!(!Rc::ptr_eq(&self.0, &other.0),
+                "Cannot bridge a network with itself.");
+        for endpoint in self.local_endpoints() {
+            assert!(other.find_local_service(endpoint).is_none(),
+                    "Cannot bridge networks: {:?} is in use on both.",
+                    endpoint);
+        }
+        if self.with_bridged()
+               .iter()
+               .any(|network| Rc::ptr_eq(&network.0, &other.0)) {
+            return;
+        }
+
+        let next_endpoint = cmp::max(self.0.borrow().next_endpoint,
+                                     other.0.borrow().next_endpoint);
+        self.0.borrow_mut().next_endpoint = next_endpoint;
+        other.0.borrow_mut().next_endpoint = next_endpoint;
+        self.0.borrow_mut().bridged.push(Rc::downgrade(&other.0));
+        other.0.borrow_mut().bridged.push(Rc::downgrade(&self.0));
+    }
+
+    /// Removes the link between this network and `other`. Pending packets between them are
+    /// dropped and services connected across the two networks receive `LostPeer` events.
+    pub fn unbridge(&self, other: &Network<UID>) {
+        for (local, remote) in self.cross_connections(other) {
+            self.drop_pending(local, remote);
+            other.drop_pending(remote, local);
+            let _ = self.lost_connection(local, remote);
+        }
+        for (local, remote) in other.cross_connections(self) {
+            other.drop_pending(local, remote);
+            self.drop_pending(remote, local);
+            let _ = other.lost_connection(local, remote);
+        }
+
+        self.0
+            .borrow_mut()
+            .bridged
+            .retain(|network| network.upgrade().map_or(false, |imp| !Rc::ptr_eq(&imp, &other.0)));
+        other
+            .0
+            .borrow_mut()
+            .bridged
+            .retain(|network| network.upgrade().map_or(false, |imp| !Rc::ptr_eq(&imp, &self.0)));
+    }
+
+    /// Causes all packets from `sender` to `receiver` to fail. This is equivalent to blocking
+    /// `PacketKindMask::all()`.
+    pub fn block_connection(&self, sender: Endpoint, receiver: Endpoint) {
+        self.block_packet_kind(sender, receiver, PacketKindMask::all());
     }
 
     /// Make all packets from `sender` to `receiver` succeed.
     pub fn unblock_connection(&self, sender: Endpoint, receiver: Endpoint) {
+        self.unblock_packet_kind(sender, receiver, PacketKindMask::all());
+    }
+
+    /// Causes the packets of the kinds selected by `kinds` from `sender` to `receiver` to fail,
+    /// in addition to any already blocked. Other packets are delivered normally.
+    ///
+    /// Blocked requests are answered with the corresponding failure, while disconnects and
+    /// keep-alives are dropped. Blocked messages are only dropped if send confirmations are
+    /// enabled, and are then reported as failed. Responses to requests which were not blocked are
+    /// always delivered.
+    pub fn block_packet_kind(&self, sender: Endpoint, receiver: Endpoint, kinds: PacketKindMask) {
+        let mut imp = self.0.borrow_mut();
+        let blocked = imp.blocked_connections
+            .entry((sender, receiver))
+            .or_insert_with(PacketKindMask::default);
+        *blocked = blocked.with(kinds);
+    }
+
+    /// Lets the packets of the kinds selected by `kinds` from `sender` to `receiver` succeed
+    /// again.
+    pub fn unblock_packet_kind(&self,
+                               sender: Endpoint,
+                               receiver: Endpoint,
+                               kinds: PacketKindMask) {
+        let mut imp = self.0.borrow_mut();
+        let unblocked = match imp.blocked_connections.get_mut(&(sender, receiver)) {
+            Some(blocked) => {
+                *blocked = blocked.without(kinds);
+                blocked.is_empty()
+            }
+            None => false,
+        };
+        if unblocked {
+            let _ = imp.blocked_connections.remove(&(sender, receiver));
+        }
+    }
+
+    /// Causes the messages of the given kind from `sender` to `receiver` to fail, as told by the
+    /// first byte of their payload. Other messages and packets are delivered normally.
+    /// Unlike with `block_packet_kind`, blocked messages are always dropped, and reported as
+    /// failed if send confirmations are enabled.
+    pub fn block_wire_kind(&self, sender: Endpoint, receiver: Endpoint, kind: WireKind) {
+        let mut imp = self.0.borrow_mut();
+        let _ = imp.blocked_wire_kinds
+            .entry((sender, receiver))
+            .or_insert_with(BTreeSet::new)
+            .insert(kind);
+    }
+
+    /// Lets the messages of the given kind from `sender` to `receiver` succeed again.
+    pub fn unblock_wire_kind(&self, sender: Endpoint, receiver: Endpoint, kind: WireKind) {
+        let mut imp = self.0.borrow_mut();
+        let unblocked = match imp.blocked_wire_kinds.get_mut(&(sender, receiver)) {
+            Some(blocked) => {
+                let _ = blocked.remove(&kind);
+                blocked.is_empty()
+            }
+            None => false,
+        };
+        if unblocked {
+            let _ = imp.blocked_wire_kinds.remove(&(sender, receiver));
+        }
+    }
+
+    /// Silently drops all packets from `sender` to `receiver`. Unlike with `block_connection`, no
+    /// failures are reported for any kind of packet, so e.g. connection attempts don't fail fast
+    /// but are left to time out. If send confirmations are enabled, messages are confirmed as sent.
+    pub fn blackhole_connection(&self, sender: Endpoint, receiver: Endpoint) {
+        let mut imp = self.0.borrow_mut();
+        imp.blackholed_connections.insert((sender, receiver));
+    }
+
+    /// Delivers packets from `sender` to `receiver` again.
+    pub fn unblackhole_connection(&self, sender: Endpoint, receiver: Endpoint) {
         let mut imp = self.0.borrow_mut();
-        let _ = imp.blocked_connections.remove(&(sender, receiver));
+        let _ = imp.blackholed_connections.remove(&(sender, receiver));
+    }
+
+    /// Installs a callback which is invoked for each packet taken off the queue, before it is
+    /// delivered, blocked or dropped. It replaces any previously installed one. The network isn't
+    /// borrowed while the callback runs, so it may query the network. A callback holding a clone
+    /// of the network keeps it open until it is removed.
+    pub fn set_packet_observer<F>(&self, observer: F)
+        where F: FnMut(&ObservedPacket) + 'static
+    {
+        self.0.borrow_mut().packet_observer = Some(Rc::new(RefCell::new(Box::new(observer))));
+    }
+
+    /// Removes the callback installed via `set_packet_observer`.
+    pub fn clear_packet_observer(&self) {
+        self.0.borrow_mut().packet_observer = None;
+    }
+
+    /// Starts recording the order in which this network takes packets off its queue, whether
+    /// they are then delivered or not. Any previous recording is discarded.
+    pub fn start_recording(&self) {
+        self.0.borrow_mut().trace = Some(Vec::new());
+    }
+
+    /// Stops recording and returns the packets taken off the queue since `start_recording`.
+    pub fn take_trace(&self) -> Vec<TraceEntry> {
+        self.0.borrow_mut().trace.take().unwrap_or_else(Vec::new)
+    }
+
+    /// Makes this network take packets off its queue in the order given by `trace`, instead of
+    /// picking them at random. Random numbers are still drawn as if picking, so that a network
+    /// created with the same seed as the recorded one stays in step with it. Once the trace is
+    /// used up, packets are picked at random again.
+    ///
+    /// Polling panics if the next packet in the trace isn't ready to be taken off the queue, as
+    /// that means the run has diverged from the recorded one.
+    pub fn replay(&self, trace: Vec<TraceEntry>) {
+        self.0.borrow_mut().replay = trace.into_iter().collect();
+    }
+
+    /// Returns the number of bytes sent by `endpoint` which were delivered to their receiver.
+    /// Messages count with the length of their payload, all other packets with
+    /// `CONTROL_PACKET_SIZE`. Networks bridged with this one are included.
+    pub fn bytes_sent(&self, endpoint: Endpoint) -> u64 {
+        self.with_bridged()
+            .iter()
+            .filter_map(|network| network.0.borrow().traffic.get(&endpoint).map(|&(sent, _)| sent))
+            .sum()
+    }
+
+    /// Returns the number of bytes received by `endpoint`, counted as by `bytes_sent`.
+    pub fn bytes_received(&self, endpoint: Endpoint) -> u64 {
+        self.with_bridged()
+            .iter()
+            .filter_map(|network| {
+                            network
+                                .0
+                                .borrow()
+                                .traffic
+                                .get(&endpoint)
+                                .map(|&(_, received)| received)
+                        })
+            .sum()
+    }
+
+    /// Returns the total number of bytes sent and received by all endpoints, counted as by
+    /// `bytes_sent`. Networks bridged with this one are included.
+    pub fn total_traffic(&self) -> (u64, u64) {
+        self.with_bridged()
+            .iter()
+            .flat_map(|network| network.0.borrow().traffic.values().cloned().collect::<Vec<_>>())
+            .fold((0, 0), |(total_sent, total_received), (sent, received)| {
+                (total_sent + sent, total_received + received)
+            })
+    }
+
+    /// Returns the number of messages of each kind delivered to their receiver, as told by
+    /// `WireKind::of`. Networks bridged with this one are included.
+    pub fn wire_kind_counts(&self) -> BTreeMap<WireKind, usize> {
+        let mut counts = BTreeMap::new();
+        for network in self.with_bridged() {
+            for (&kind, &count) in &network.0.borrow().wire_kind_counts {
+                *counts.entry(kind).or_insert(0) += count;
+            }
+        }
+        counts
+    }
+
+    /// Resets all traffic counters to zero, including the counts of messages by kind.
+    pub fn reset_traffic_stats(&self) {
+        for network in self.with_bridged() {
+            let mut imp = network.0.borrow_mut();
+            imp.traffic.clear();
+            imp.wire_kind_counts.clear();
+        }
+    }
+
+    /// Cuts all routes between the two groups of endpoints, in both directions, and drops the
+    /// packets already queued on them.
+    ///
+    /// Requests across the cut are answered with the corresponding failure, as over a blocked
+    /// connection. All other packets are dropped, including messages, which are reported as
+    /// failed if send confirmations are enabled. Existing connections are not closed, but can be
+    /// dropped with `lost_connection` or by an idle timeout.
+    pub fn partition(&self, group_a: &[Endpoint], group_b: &[Endpoint]) {
+        for &a in group_a {
+            for &b in group_b {
+                {
+                    let mut imp = self.0.borrow_mut();
+                    let _ = imp.partitioned_connections.insert((a, b));
+                    let _ = imp.partitioned_connections.insert((b, a));
+                }
+                self.drop_pending(a, b);
+                self.drop_pending(b, a);
+            }
+        }
+    }
+
+    /// Restores all routes between the two groups of endpoints cut by `partition`.
+    pub fn heal_partition(&self, group_a: &[Endpoint], group_b: &[Endpoint]) {
+        let mut imp = self.0.borrow_mut();
+        for &a in group_a {
+            for &b in group_b {
+                let _ = imp.partitioned_connections.remove(&(a, b));
+                let _ = imp.partitioned_connections.remove(&(b, a));
+            }
+        }
+    }
+
+    /// Returns whether the routes between the two endpoints are cut by `partition`.
+    pub fn is_partitioned(&self, endpoint_a: Endpoint, endpoint_b: Endpoint) -> bool {
+        self.0
+            .borrow()
+            .partitioned_connections
+            .contains(&(endpoint_a, endpoint_b))
+    }
+
+    /// Makes each packet from `sender` to `receiver` get lost with the given probability, which
+    /// must be between 0 and 1. The network's random number generator decides which ones, so runs
+    /// are reproducible from the same seed.
+    ///
+    /// Lost requests are answered with the corresponding failure, as if the connection was
+    /// blocked. All other packets, including messages, vanish without a trace, but still count as
+    /// sent for `reset_message_sent`.
+    pub fn set_packet_loss(&self, sender: Endpoint, receiver: Endpoint, probability: f64) {
+        assert!(probability >= 0.0 && probability <= 1.0,
+                "Invalid packet loss probability {}.",
+                probability);
+        let threshold = (probability * (u64::from(u32::max_value()) + 1) as f64) as u64;
+        let _ = self.0.borrow_mut().packet_loss.insert((sender, receiver), threshold);
+    }
+
+    /// Stops losing packets from `sender` to `receiver`.
+    pub fn clear_packet_loss(&self, sender: Endpoint, receiver: Endpoint) {
+        let _ = self.0.borrow_mut().packet_loss.remove(&(sender, receiver));
+    }
+
+    /// Makes each message from `sender` to `receiver` get delivered twice with the given
+    /// probability: after it has been delivered, a copy is queued again behind the other packets
+    /// on the route. The copy isn't duplicated again, and other kinds of packets never are. As
+    /// for `set_packet_loss`, the decision is drawn from the network's random number generator.
+    ///
+    /// Panics if `probability` is not between 0 and 1.
+    pub fn set_duplication(&self, sender: Endpoint, receiver: Endpoint, probability: f64) {
+        assert!(probability >= 0.0 && probability <= 1.0,
+                "Invalid duplication probability {}.",
+                probability);
+        let threshold = (probability * (u64::from(u32::max_value()) + 1) as f64) as u64;
+        let _ = self.0.borrow_mut().duplication.insert((sender, receiver), threshold);
+    }
+
+    /// Stops duplicating messages from `sender` to `receiver`.
+    pub fn clear_duplication(&self, sender: Endpoint, receiver: Endpoint) {
+        let _ = self.0.borrow_mut().duplication.remove(&(sender, receiver));
+    }
+
+    /// Sets whether messages from `sender` to `receiver` can overtake each other. If enabled, the
+    /// next packet taken off the route is picked at random from the messages at its front, using
+    /// the network's random number generator. Other kinds of packets are still delivered in order,
+    /// and messages never overtake them, so that handshakes keep working.
+    pub fn set_reordering(&self, sender: Endpoint, receiver: Endpoint, enabled: bool) {
+        let mut imp = self.0.borrow_mut();
+        if enabled {
+            let _ = imp.reordering_connections.insert((sender, receiver));
+        } else {
+            let _ = imp.reordering_connections.remove(&(sender, receiver));
+        }
     }
 
     /// Delay the processing of packets from `sender` to `receiver`.
@@ -130,20 +871,130 @@ impl<UID: Uid> Network<UID> {
         imp.delayed_connections.insert((sender, receiver));
     }
 
-    /// Simulates the loss of a connection.
-    pub fn lost_connection(&self, node_1: Endpoint, node_2: Endpoint) {
-        let service_1 = unwrap!(self.find_service(node_1),
-                                "Cannot fetch service of {:?}.",
-                                node_1);
+    /// Keeps all packets from `sender` to `receiver` queued until `release_connection` is called,
+    /// so that they can be inspected with `pending_packets` or removed with `drain_matching`.
+    pub fn hold_connection(&self, sender: Endpoint, receiver: Endpoint) {
+        let mut imp = self.0.borrow_mut();
+        imp.held_connections.insert((sender, receiver));
+    }
+
+    /// Lets packets from `sender` to `receiver` be processed again by the next poll.
+    pub fn release_connection(&self, sender: Endpoint, receiver: Endpoint) {
+        let mut imp = self.0.borrow_mut();
+        let _ = imp.held_connections.remove(&(sender, receiver));
+    }
+
+    /// Returns the kinds of the packets currently queued from `sender` to `receiver`, in the order
+    /// they will be processed. Networks bridged with this one are included.
+    pub fn pending_packets(&self, sender: Endpoint, receiver: Endpoint) -> Vec<PacketKind> {
+        self.with_bridged()
+            .iter()
+            .flat_map(|network| {
+                network
+                    .0
+                    .borrow()
+                    .queue
+                    .get(&(sender, receiver))
+                    .map_or_else(Vec::new, |packets| {
+                        packets.iter().map(|queued| queued.packet.kind()).collect()
+                    })
+            })
+            .collect()
+    }
+
+    /// Returns the total number of queued packets. Networks bridged with this one are included.
+    pub fn pending_count(&self) -> usize {
+        self.with_bridged()
+            .iter()
+            .map(|network| network.0.borrow().queue.values().map(VecDeque::len).sum::<usize>())
+            .sum()
+    }
+
+    /// Returns whether any packets are queued between `node_1` and `node_2`, in either direction.
+    pub fn has_pending_between(&self, node_1: Endpoint, node_2: Endpoint) -> bool {
+        self.with_bridged()
+            .iter()
+            .any(|network| {
+                     let imp = network.0.borrow();
+                     [(node_1, node_2), (node_2, node_1)]
+                         .iter()
+                         .any(|key| imp.queue.get(key).map_or(false, |packets| !packets.is_empty()))
+                 })
+    }
+
+    /// Returns the routes on this network which don't deliver packets normally, due to being
+    /// blocked, delayed, held, blackholed or partitioned, sorted. A route can be listed more than
+    /// once with different impairments.
+    pub fn impaired_links(&self) -> Vec<(Endpoint, Endpoint, LinkImpairment)> {
+        let imp = self.0.borrow();
+        let mut links = Vec::new();
+        for (&(sender, receiver), &kinds) in &imp.blocked_connections {
+            if kinds != PacketKindMask::default() {
+                links.push((sender, receiver, LinkImpairment::Blocked(kinds)));
+            }
+        }
+        for (&(sender, receiver), kinds) in &imp.blocked_wire_kinds {
+            links.extend(kinds
+                             .iter()
+                             .map(|&kind| (sender, receiver, LinkImpairment::BlockedWire(kind))));
+        }
+        let sets = [(&imp.delayed_connections, LinkImpairment::Delayed),
+                    (&imp.held_connections, LinkImpairment::Held),
+                    (&imp.blackholed_connections, LinkImpairment::Blackholed),
+                    (&imp.partitioned_connections, LinkImpairment::Partitioned)];
+        for &(routes, impairment) in &sets {
+            links.extend(routes
+                             .iter()
+                             .map(|&(sender, receiver)| (sender, receiver, impairment)));
+        }
+        links.sort_by_key(|&(sender, receiver, _)| (sender, receiver));
+        links
+    }
+
+    /// Silently removes all queued packets for which `predicate(sender, receiver, kind)` returns
+    /// `true`, without notifying either side. Returns the number of removed packets. Networks
+    /// bridged with this one are included.
+    pub fn drain_matching<F>(&self, mut predicate: F) -> usize
+        where F: FnMut(Endpoint, Endpoint, PacketKind) -> bool
+    {
+        let mut removed = 0;
+        for network in self.with_bridged() {
+            let mut imp = network.0.borrow_mut();
+            for (&(sender, receiver), packets) in &mut imp.queue {
+                let old_len = packets.len();
+                packets.retain(|queued| !predicate(sender, receiver, queued.packet.kind()));
+                removed += old_len - packets.len();
+            }
+            let emptied: Vec<_> = imp.queue
+                .iter()
+                .filter(|&(_, packets)| packets.is_empty())
+                .map(|(key, _)| *key)
+                .collect();
+            for key in emptied {
+                let _ = imp.queue.remove(&key);
+            }
+        }
+        removed
+    }
+
+    /// Enables or disables send confirmations. While enabled, the sender of each message is
+    /// notified with `MessageDelivered` once the receiver has processed it, or with
+    /// `MessageFailed` if the message was dropped due to a blocked connection or a missing
+    /// receiver.
+    pub fn enable_send_confirmations(&self, enable: bool) {
+        self.0.borrow_mut().send_confirmations = enable;
+    }
+
+    /// Simulates the loss of a connection. Both ends receive a `LostPeer` event.
+    pub fn lost_connection(&self, node_1: Endpoint, node_2: Endpoint) -> Result<(), MockError> {
+        let service_1 = self.find_service(node_1).ok_or(MockError::ServiceNotFound(node_1))?;
+        let service_2 = self.find_service(node_2).ok_or(MockError::ServiceNotFound(node_2))?;
         if service_1
                .borrow_mut()
                .remove_connection_by_endpoint(node_2)
                .is_none() {
-            return;
+            return Err(MockError::NotConnected(node_1, node_2));
         }
-        let service_2 = unwrap!(self.find_service(node_2),
-                                "Cannot fetch service of {:?}.",
-                                node_2);
         let _ = service_2
             .borrow_mut()
             .remove_connection_by_endpoint(node_1);
@@ -154,14 +1005,99 @@ impl<UID: Uid> Network<UID> {
         service_2
             .borrow_mut()
             .send_event(CrustEvent::LostPeer(unwrap!(service_1.borrow().uid)));
+        Ok(())
+    }
+
+    /// Like `lost_connection`, but panics if either service is gone or they are not connected.
+    pub fn lost_connection_or_panic(&self, node_1: Endpoint, node_2: Endpoint) {
+        if let Err(error) = self.lost_connection(node_1, node_2) {
+            panic!("Cannot drop the connection: {}", error);
+        }
+    }
+
+    /// Removes the connection between the two nodes without notifying either of them, as if Crust
+    /// had lost track of it.
+    pub fn forget_connection(&self, node_1: Endpoint, node_2: Endpoint) {
+        for &(node, peer) in &[(node_1, node_2), (node_2, node_1)] {
+            if let Some(service) = self.find_service(node) {
+                let _ = service.borrow_mut().remove_connection_by_endpoint(peer);
+            }
+            self.drop_pending(node, peer);
+        }
     }
 
     /// Simulates a crust event being sent to the node.
-    pub fn send_crust_event(&self, node: Endpoint, crust_event: CrustEvent<UID>) {
-        let service = unwrap!(self.find_service(node),
-                              "Cannot fetch service of {:?}.",
-                              node);
+    pub fn send_crust_event(&self,
+                            node: Endpoint,
+                            crust_event: CrustEvent<UID>)
+                            -> Result<(), MockError> {
+        let service = self.find_service(node).ok_or(MockError::ServiceNotFound(node))?;
         service.borrow_mut().send_event(crust_event);
+        Ok(())
+    }
+
+    /// Like `send_crust_event`, but panics if the service is gone.
+    pub fn send_crust_event_or_panic(&self, node: Endpoint, crust_event: CrustEvent<UID>) {
+        if let Err(error) = self.send_crust_event(node, crust_event) {
+            panic!("Cannot send crust event: {}", error);
+        }
+    }
+
+    /// Returns the connections between all live services as undirected edges, each with the lower
+    /// endpoint first, sorted. A connection is included if either side has registered it. Networks
+    /// bridged with this one are included.
+    pub fn connection_graph(&self) -> Vec<(Endpoint, Endpoint)> {
+        let mut edges = BTreeSet::new();
+        for network in self.with_bridged() {
+            for endpoint in network.local_endpoints() {
+                let service = match network.find_local_service(endpoint) {
+                    Some(service) => service,
+                    None => continue,
+                };
+                let peer_endpoints = service.borrow().connected_endpoints();
+                for peer_endpoint in peer_endpoints {
+                    if self.find_service(peer_endpoint).is_some() {
+                        let _ = edges.insert((cmp::min(endpoint, peer_endpoint),
+                                              cmp::max(endpoint, peer_endpoint)));
+                    }
+                }
+            }
+        }
+        edges.into_iter().collect()
+    }
+
+    /// Returns the endpoints of all live services on this network, sorted. Services on bridged
+    /// networks are not included. Entries of services which have been dropped are pruned.
+    pub fn endpoints(&self) -> Vec<Endpoint> {
+        let mut endpoints = self.local_endpoints();
+        endpoints.sort();
+        endpoints
+    }
+
+    /// Returns a handle to the live service with the given endpoint on this network or a network
+    /// bridged with it.
+    pub fn find_handle(&self, endpoint: Endpoint) -> Option<ServiceHandle<UID>> {
+        self.find_service(endpoint).map(ServiceHandle)
+    }
+
+    /// Panics if a live service on this network lists another one as connected, but the other one
+    /// doesn't list it in turn, or is gone.
+    pub fn assert_connectivity_symmetric(&self) {
+        for endpoint in self.endpoints() {
+            let handle = match self.find_handle(endpoint) {
+                Some(handle) => handle,
+                None => continue,
+            };
+            for peer_endpoint in handle.connected_endpoints() {
+                let symmetric = self.find_handle(peer_endpoint)
+                    .map_or(false, |peer| peer.is_connected_to_endpoint(endpoint));
+                if !symmetric {
+                    panic!("{:?} is connected to {:?}, but not vice versa.",
+                           endpoint,
+                           peer_endpoint);
+                }
+            }
+        }
     }
 
     /// Construct a new [`SeededRng`][1] using a seed generated from random data provided by `self`.
@@ -170,57 +1106,539 @@ impl<UID: Uid> Network<UID> {
         self.0.borrow_mut().rng.new_rng()
     }
 
-    /// Return whether sent any message since previous query and reset the flag.
+    /// Captures the network-level state of this network, so that it can be reset to this point
+    /// later using `restore`. See `NetworkSnapshot` for what is and isn't included.
+    ///
+    /// `SeededRng` doesn't expose its state, so the network's RNG is reseeded from itself and the
+    /// new seed is recorded. The random numbers drawn after this call are therefore different
+    /// from the ones which would have been drawn without taking the snapshot.
+    pub fn snapshot(&self) -> NetworkSnapshot<UID> {
+        let mut imp = self.0.borrow_mut();
+        let rng_seed: [u32; 4] = imp.rng.gen();
+        imp.rng = SeededRng::from_seed(rng_seed);
+        let services = imp.services
+            .iter()
+            .filter_map(|(endpoint, service)| {
+                            service
+                                .upgrade()
+                                .map(|service| (*endpoint, service.borrow().snapshot()))
+                        })
+            .collect();
+        NetworkSnapshot {
+            next_endpoint: imp.next_endpoint,
+            endpoint_ranges: imp.endpoint_ranges.clone(),
+            incarnations: imp.incarnations.clone(),
+            stale_packets: imp.stale_packets,
+            stale_packet_failures: imp.stale_packet_failures,
+            queue: imp.queue.clone(),
+            blocked_connections: imp.blocked_connections.clone(),
+            blocked_wire_kinds: imp.blocked_wire_kinds.clone(),
+            delayed_connections: imp.delayed_connections.clone(),
+            held_connections: imp.held_connections.clone(),
+            blackholed_connections: imp.blackholed_connections.clone(),
+            partitioned_connections: imp.partitioned_connections.clone(),
+            packet_loss: imp.packet_loss.clone(),
+            duplication: imp.duplication.clone(),
+            reordering_connections: imp.reordering_connections.clone(),
+            pending_connection_infos: imp.pending_connection_infos.clone(),
+            idle_timeout: imp.idle_timeout,
+            idle_polls: imp.idle_polls.clone(),
+            rng_seed: rng_seed,
+            message_sent: imp.message_sent,
+            send_confirmations: imp.send_confirmations,
+            next_msg_id: imp.next_msg_id,
+            services: services,
+        }
+    }
+
+    /// Resets the network-level state of this network to the given snapshot. Services which were
+    /// not alive when the snapshot was taken are left unchanged.
+    pub fn restore(&self, snapshot: &NetworkSnapshot<UID>) {
+        let services = {
+            let mut imp = self.0.borrow_mut();
+            imp.next_endpoint = snapshot.next_endpoint;
+            // Ranges reserved since the snapshot stay, as `EndpointRange`s may still refer to them.
+            for (range, saved) in imp.endpoint_ranges
+                    .iter_mut()
+                    .zip(&snapshot.endpoint_ranges) {
+                range.next = saved.next;
+            }
+            imp.incarnations = snapshot.incarnations.clone();
+            imp.stale_packets = snapshot.stale_packets;
+            imp.stale_packet_failures = snapshot.stale_packet_failures;
+            imp.queue = snapshot.queue.clone();
+            imp.blocked_connections = snapshot.blocked_connections.clone();
+            imp.blocked_wire_kinds = snapshot.blocked_wire_kinds.clone();
+            imp.delayed_connections = snapshot.delayed_connections.clone();
+            imp.held_connections = snapshot.held_connections.clone();
+            imp.blackholed_connections = snapshot.blackholed_connections.clone();
+            imp.partitioned_connections = snapshot.partitioned_connections.clone();
+            imp.packet_loss = snapshot.packet_loss.clone();
+            imp.duplication = snapshot.duplication.clone();
+            imp.reordering_connections = snapshot.reordering_connections.clone();
+            imp.pending_connection_infos = snapshot.pending_connection_infos.clone();
+            imp.idle_timeout = snapshot.idle_timeout;
+            imp.idle_polls = snapshot.idle_polls.clone();
+            imp.rng = SeededRng::from_seed(snapshot.rng_seed);
+            imp.message_sent = snapshot.message_sent;
+            imp.send_confirmations = snapshot.send_confirmations;
+            imp.next_msg_id = snapshot.next_msg_id;
+            imp.services
+                .iter()
+                .filter_map(|(endpoint, service)| {
+                                service.upgrade().map(|service| (*endpoint, service))
+                            })
+                .collect::<Vec<_>>()
+        };
+        for (endpoint, service) in services {
+            if let Some(service_snapshot) = snapshot.services.get(&endpoint) {
+                service.borrow_mut().restore(service_snapshot);
+            }
+        }
+    }
+
+    /// Return whether sent any message since previous query and reset the flag. Networks bridged
+    /// with this one are included.
+    ///
+    /// Delayed connection info preparations count as pending traffic: if there are any, the
+    /// network is polled once and `true` is returned, so that test loops keep running until they
+    /// have all been delivered.
     pub fn reset_message_sent(&self) -> bool {
+        self.with_bridged()
+            .iter()
+            .fold(false, |result, network| network.reset_local_message_sent() || result)
+    }
+
+    fn reset_local_message_sent(&self) -> bool {
+        let pending_infos = !self.0.borrow().pending_connection_infos.is_empty();
+        if pending_infos {
+            let _ = self.process_packets();
+            self.release_connection_infos();
+        }
         let message_sent = self.0.borrow().message_sent;
         self.0.borrow_mut().message_sent = false;
-        message_sent
+        message_sent || pending_infos
+    }
+
+    // Processes a single packet queued on this network or, if there are none, on a bridged one.
+    // Returns its route and kind.
+    fn poll_packet(&self) -> Option<(Endpoint, Endpoint, PacketKind)> {
+        for network in self.with_bridged() {
+            if let Some((sender, receiver, queued)) = network.pop_packet() {
+                let kind = queued.packet.kind();
+                network.process_packet(sender, receiver, queued);
+                return Some((sender, receiver, kind));
+            }
+        }
+        None
+    }
+
+    // Describes the packets processed by a `poll` which exceeded its limit, and those still
+    // queued.
+    fn poll_limit_dump(&self, max_packets: usize, stats: &PollStats) -> String {
+        let mut routes: Vec<_> = stats.routes.iter().collect();
+        routes.sort_by(|&(route_a, kinds_a), &(route_b, kinds_b)| {
+                           let total_a: usize = kinds_a.values().sum();
+                           let total_b: usize = kinds_b.values().sum();
+                           total_b.cmp(&total_a).then(route_a.cmp(route_b))
+                       });
+        let mut dump = format!("Polling processed more than {} packets. Busiest routes:\n",
+                               max_packets);
+        for &(&(sender, receiver), kinds) in routes.iter().take(POLL_LIMIT_DUMP_ROUTES) {
+            let total: usize = kinds.values().sum();
+            dump.push_str(&format!("    {} -> {}: {} packets {:?}\n",
+                                   self.describe_endpoint(sender),
+                                   self.describe_endpoint(receiver),
+                                   total,
+                                   kinds));
+        }
+        dump.push_str(&format!("Packets processed by kind: {:?}\nStill queued:\n", stats.kinds));
+        for network in self.with_bridged() {
+            for (&(sender, receiver), queue) in &network.0.borrow().queue {
+                if !queue.is_empty() {
+                    dump.push_str(&format!("    {} -> {}: {} packets\n",
+                                           self.describe_endpoint(sender),
+                                           self.describe_endpoint(receiver),
+                                           queue.len()));
+                }
+            }
+        }
+        dump
+    }
+
+    // Processes all packets queued on this network. Returns whether there were any.
+    fn process_packets(&self) -> bool {
+        let mut processed = false;
+        while let Some((sender, receiver, queued)) = self.pop_packet() {
+            self.process_packet(sender, receiver, queued);
+            processed = true;
+        }
+        processed
+    }
+
+    // Registers a new incarnation of the service at `endpoint` and returns its number.
+    fn next_incarnation(&self, endpoint: Endpoint) -> u64 {
+        let mut imp = self.0.borrow_mut();
+        let incarnation = imp.incarnation(endpoint) + 1;
+        let _ = imp.incarnations.insert(endpoint, incarnation);
+        incarnation
+    }
+
+    // Returns this network followed by all networks bridged with it.
+    fn with_bridged(&self) -> Vec<Network<UID>> {
+        let bridged = self.0
+            .borrow()
+            .bridged
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|network_impl| Network(network_impl, None))
+            .collect::<Vec<_>>();
+        iter::once(self.unowned()).chain(bridged).collect()
+    }
+
+    // Returns a handle to this network which doesn't keep it open.
+    fn unowned(&self) -> Network<UID> {
+        Network(self.0.clone(), None)
+    }
+
+    // Returns whether the network is open and not currently in use further up the stack, e.g.
+    // because a service is being dropped during a poll.
+    fn is_available(&self) -> bool {
+        self.0
+            .try_borrow()
+            .map(|network_impl| !network_impl.closed)
+            .unwrap_or(false)
+    }
+
+    // Returns the socket address of `endpoint`, using the IP assigned to it on this or a bridged
+    // network. The endpoint is used as the port, so that endpoints and addresses can be easily
+    // mapped to each other during testing.
+    fn socket_addr(&self, endpoint: &Endpoint) -> SocketAddr {
+        let ip = self.with_bridged()
+            .iter()
+            .filter_map(|network| network.0.borrow().ip_addrs.get(endpoint).cloned())
+            .next()
+            .unwrap_or_else(|| IpAddr::V4(Ipv4Addr::new(123, 123, 255, 255)));
+        SocketAddr::new(ip, endpoint.0 as u16)
+    }
+
+    // Returns the endpoints of all live services on this network.
+    // Returns the endpoints of the live local services, and prunes the entries of dropped ones.
+    fn local_endpoints(&self) -> Vec<Endpoint> {
+        let mut network_impl = self.0.borrow_mut();
+        let dead_endpoints = network_impl
+            .services
+            .iter()
+            .filter(|&(_, service)| service.upgrade().is_none())
+            .map(|(endpoint, _)| *endpoint)
+            .collect::<Vec<_>>();
+        for endpoint in dead_endpoints {
+            let _ = network_impl.services.remove(&endpoint);
+        }
+        network_impl.services.keys().cloned().collect()
+    }
+
+    // Returns all pairs of our endpoints and `other`'s endpoints which are connected.
+    fn cross_connections(&self, other: &Network<UID>) -> Vec<(Endpoint, Endpoint)> {
+        let mut result = Vec::new();
+        for endpoint in self.local_endpoints() {
+            if let Some(service) = self.find_local_service(endpoint) {
+                for remote in service.borrow().connections.endpoints() {
+                    if other.find_local_service(remote).is_some() {
+                        result.push((endpoint, remote));
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn delay_connection_info(&self,
+                             endpoint: Endpoint,
+                             result_token: u32,
+                             polls: usize,
+                             failed: bool) {
+        self.0
+            .borrow_mut()
+            .pending_connection_infos
+            .push(PendingConnectionInfo {
+                      endpoint: endpoint,
+                      result_token: result_token,
+                      polls_remaining: polls,
+                      failed: failed,
+                  });
+    }
+
+    // Counts down all pending connection info preparations and delivers those which are due.
+    fn release_connection_infos(&self) {
+        let due = {
+            let mut network_impl = self.0.borrow_mut();
+            let mut due = Vec::new();
+            network_impl
+                .pending_connection_infos
+                .retain(|pending| if pending.polls_remaining <= 1 {
+                            due.push((pending.endpoint, pending.result_token, pending.failed));
+                            false
+                        } else {
+                            true
+                        });
+            for pending in &mut network_impl.pending_connection_infos {
+                pending.polls_remaining -= 1;
+            }
+            due
+        };
+
+        for (endpoint, result_token, failed) in due {
+            if let Some(service) = self.find_service(endpoint) {
+                service
+                    .borrow()
+                    .send_connection_info_prepared(result_token, failed);
+            }
+        }
+    }
+
+    // Lets every live service with keep-alives enabled send them, if due.
+    fn send_keepalives(&self) {
+        for endpoint in self.local_endpoints() {
+            if let Some(service) = self.find_local_service(endpoint) {
+                service.borrow_mut().poll_keepalive();
+            }
+        }
+    }
+
+    // Marks the connection between the two endpoints as active during the current poll.
+    fn record_activity(&self, sender: Endpoint, receiver: Endpoint) {
+        let key = (cmp::min(sender, receiver), cmp::max(sender, receiver));
+        // The connection is accounted for by the network hosting the lower endpoint, which may be
+        // a bridged one.
+        let host = self.with_bridged()
+            .into_iter()
+            .find(|network| network.find_local_service(key.0).is_some());
+        if let Some(network) = host {
+            let mut imp = network.0.borrow_mut();
+            if imp.idle_timeout.is_some() {
+                let _ = imp.active_connections.insert(key);
+            }
+        }
+    }
+
+    // Counts the polls each connection has been idle for, and drops those which reached the idle
+    // timeout.
+    fn expire_idle_connections(&self) {
+        let timeout = match self.0.borrow().idle_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let mut connections = BTreeSet::new();
+        for endpoint in self.local_endpoints() {
+            if let Some(service) = self.find_local_service(endpoint) {
+                for peer_endpoint in service.borrow().connected_endpoints() {
+                    if endpoint < peer_endpoint {
+                        let _ = connections.insert((endpoint, peer_endpoint));
+                    }
+                }
+            }
+        }
+
+        let expired = {
+            let mut imp = self.0.borrow_mut();
+            let active = mem::replace(&mut imp.active_connections, HashSet::new());
+            let mut idle_polls = HashMap::new();
+            let mut expired = Vec::new();
+            for key in connections {
+                let polls = if active.contains(&key) {
+                    0
+                } else {
+                    imp.idle_polls.get(&key).map_or(1, |polls| polls + 1)
+                };
+                if polls >= timeout {
+                    expired.push(key);
+                } else {
+                    let _ = idle_polls.insert(key, polls);
+                }
+            }
+            imp.idle_polls = idle_polls;
+            expired
+        };
+
+        for (endpoint_1, endpoint_2) in expired {
+            debug!("Dropping idle connection between {:?} and {:?}.",
+                   endpoint_1,
+                   endpoint_2);
+            let _ = self.lost_connection(endpoint_1, endpoint_2);
+        }
     }
 
-    fn connection_blocked(&self, sender: Endpoint, receiver: Endpoint) -> bool {
+    fn gen_msg_id(&self) -> u64 {
+        let mut network_impl = self.0.borrow_mut();
+        let msg_id = network_impl.next_msg_id;
+        network_impl.next_msg_id += 1;
+        msg_id
+    }
+
+    fn send_confirmations(&self) -> bool {
+        self.0.borrow().send_confirmations
+    }
+
+    // Notifies the sender of a message whether it was delivered, if send confirmations are
+    // enabled.
+    fn confirm_message(&self, sender: Endpoint, receiver_uid: UID, msg_id: u64, delivered: bool) {
+        if !self.send_confirmations() {
+            return;
+        }
+        if let Some(service) = self.find_service(sender) {
+            let event = if delivered {
+                CrustEvent::MessageDelivered(receiver_uid, msg_id)
+            } else {
+                CrustEvent::MessageFailed(receiver_uid, msg_id)
+            };
+            service.borrow().send_event(event);
+        }
+    }
+
+    fn packet_blocked(&self, sender: Endpoint, receiver: Endpoint, kind: PacketKind) -> bool {
         self.0
             .borrow()
             .blocked_connections
-            .contains(&(sender, receiver))
+            .get(&(sender, receiver))
+            .map_or(false, |blocked| blocked.contains(kind))
+    }
+
+    fn wire_kind_blocked(&self, sender: Endpoint, receiver: Endpoint, kind: WireKind) -> bool {
+        self.0
+            .borrow()
+            .blocked_wire_kinds
+            .get(&(sender, receiver))
+            .map_or(false, |blocked| blocked.contains(&kind))
+    }
+
+    fn record_traffic(&self, sender: Endpoint, receiver: Endpoint, packet: &Packet<UID>) {
+        let mut imp = self.0.borrow_mut();
+        let size = packet.size();
+        imp.traffic.entry(sender).or_insert((0, 0)).0 += size;
+        imp.traffic.entry(receiver).or_insert((0, 0)).1 += size;
+        if let Some(kind) = packet.wire_kind() {
+            *imp.wire_kind_counts.entry(kind).or_insert(0) += 1;
+        }
+    }
+
+    // Decides whether the next packet from `sender` to `receiver` gets lost. The random number
+    // generator is only used for lossy routes, so that configuring them doesn't affect the others.
+    fn packet_lost(&self, sender: Endpoint, receiver: Endpoint) -> bool {
+        let mut imp = self.0.borrow_mut();
+        let threshold = match imp.packet_loss.get(&(sender, receiver)) {
+            Some(&threshold) => threshold,
+            None => return false,
+        };
+        u64::from(imp.rng.gen::<u32>()) < threshold
+    }
+
+    // Decides whether a message from `sender` to `receiver` gets delivered twice. As with losses,
+    // the random number generator is only used for routes configured to duplicate.
+    fn message_duplicated(&self, sender: Endpoint, receiver: Endpoint) -> bool {
+        let mut imp = self.0.borrow_mut();
+        let threshold = match imp.duplication.get(&(sender, receiver)) {
+            Some(&threshold) => threshold,
+            None => return false,
+        };
+        u64::from(imp.rng.gen::<u32>()) < threshold
+    }
+
+    // Queues `queued` again behind the other packets from `sender` to `receiver`.
+    fn requeue(&self, sender: Endpoint, receiver: Endpoint, queued: QueuedPacket<UID>) {
+        let mut imp = self.0.borrow_mut();
+        if imp.closed {
+            return;
+        }
+        imp.message_sent = true;
+        imp.queue
+            .entry((sender, receiver))
+            .or_insert_with(VecDeque::new)
+            .push_back(queued);
     }
 
     fn send(&self, sender: Endpoint, receiver: Endpoint, packet: Packet<UID>) {
-        let mut network_impl = self.0.borrow_mut();
-        network_impl.message_sent = true;
+        let mut network_impl = match self.0.try_borrow_mut() {
+            Ok(ref network_impl) if network_impl.closed => return,
+            Ok(network_impl) => network_impl,
+            Err(_) => {
+                debug!("Network in use. Discarding {:?} packet from {:?} to {:?}.",
+                       packet.kind(),
+                       sender,
+                       receiver);
+                return;
+            }
+        };
+        // Keep-alives don't count as traffic, so that test loops still terminate.
+        if packet.kind() != PacketKind::KeepAlive {
+            network_impl.message_sent = true;
+        }
+        let incarnation = network_impl.incarnation(receiver);
         network_impl
             .queue
             .entry((sender, receiver))
             .or_insert_with(VecDeque::new)
-            .push_back(packet);
+            .push_back(QueuedPacket {
+                           packet: packet,
+                           incarnation: incarnation,
+                           duplicate: false,
+                       });
     }
 
     // Drops any pending messages on a specific route (does not automatically
     // drop packets going the other way).
     fn drop_pending(&self, sender: Endpoint, receiver: Endpoint) {
-        if let Some(deque) = self.0.borrow_mut().queue.get_mut(&(sender, receiver)) {
-            deque.clear();
+        if let Ok(mut network_impl) = self.0.try_borrow_mut() {
+            if let Some(deque) = network_impl.queue.get_mut(&(sender, receiver)) {
+                deque.clear();
+            }
+        }
+    }
+
+    // Unregisters a crashed service, and drops all pending packets to and from it.
+    fn remove_service(&self, endpoint: Endpoint) {
+        if !self.is_available() {
+            return;
+        }
+        let _ = self.0.borrow_mut().services.remove(&endpoint);
+        let _ = self.drain_matching(|sender, receiver, _| {
+                                        sender == endpoint || receiver == endpoint
+                                    });
+    }
+
+    // Makes the sender of a message to a vanished service notice that the connection is gone.
+    fn peer_unreachable(&self, sender: Endpoint, receiver: Endpoint) {
+        if let Some(service) = self.find_service(sender) {
+            let mut service = service.borrow_mut();
+            if let Some(uid) = service.remove_connection_by_endpoint(receiver) {
+                service.send_event(CrustEvent::LostPeer(uid));
+            }
         }
     }
 
     // Drops all pending messages across the entire network.
     fn drop_all_pending(&self) {
-        self.0.borrow_mut().queue.clear();
+        if let Ok(mut network_impl) = self.0.try_borrow_mut() {
+            network_impl.queue.clear();
+        }
     }
 
-    fn pop_packet(&self) -> Option<(Endpoint, Endpoint, Packet<UID>)> {
+    fn pop_packet(&self) -> Option<(Endpoint, Endpoint, QueuedPacket<UID>)> {
         let mut network_impl = self.0.borrow_mut();
-        let keys: Vec<_> = if
-            network_impl
-                .queue
-                .keys()
-                .all(|&(ref s, ref r)| network_impl.delayed_connections.contains(&(*s, *r))) {
-            network_impl.queue.keys().cloned().collect()
+        let ready: Vec<_> = network_impl
+            .queue
+            .keys()
+            .filter(|key| !network_impl.held_connections.contains(*key))
+            .cloned()
+            .collect();
+        let keys: Vec<_> = if ready
+               .iter()
+               .all(|key| network_impl.delayed_connections.contains(key)) {
+            ready
         } else {
-            network_impl
-                .queue
-                .keys()
-                .filter(|&&(ref s, ref r)| !network_impl.delayed_connections.contains(&(*s, *r)))
-                .cloned()
+            ready
+                .into_iter()
+                .filter(|key| !network_impl.delayed_connections.contains(key))
                 .collect()
         };
 
@@ -229,41 +1647,260 @@ impl<UID: Uid> Network<UID> {
         } else {
             return None;
         };
+        let expected = network_impl.replay.pop_front();
+        let (sender, receiver) = match expected {
+            Some(ref entry) => {
+                if !keys.contains(&(entry.sender, entry.receiver)) {
+                    panic!("Replay diverged: no packet from {:?} to {:?} is ready to be \
+                            processed.",
+                           entry.sender,
+                           entry.receiver);
+                }
+                (entry.sender, entry.receiver)
+            }
+            None => (sender, receiver),
+        };
+        let index = if network_impl
+               .reordering_connections
+               .contains(&(sender, receiver)) {
+            // Only the messages queued ahead of any other kind of packet can be picked.
+            let movable = network_impl.queue.get(&(sender, receiver)).map_or(0, |packets| {
+                packets
+                    .iter()
+                    .take_while(|queued| queued.packet.kind() == PacketKind::Message)
+                    .count()
+            });
+            if movable > 1 {
+                network_impl.rng.gen_range(0, movable)
+            } else {
+                0
+            }
+        } else {
+            0
+        };
         let result = network_impl
             .queue
             .get_mut(&(sender, receiver))
             .and_then(|packets| {
                           packets
-                              .pop_front()
+                              .remove(index)
                               .map(|packet| (sender, receiver, packet))
                       });
-        if result.is_some() {
+        if let Some((_, _, ref queued)) = result {
             if let Entry::Occupied(entry) = network_impl.queue.entry((sender, receiver)) {
                 if entry.get().is_empty() {
                     let (_key, _value) = entry.remove_entry();
                 }
             }
+            let kind = queued.packet.kind();
+            if let Some(entry) = expected {
+                if entry.kind != kind {
+                    panic!("Replay diverged: expected a {:?} packet from {:?} to {:?}, but the \
+                            next one is a {:?} packet.",
+                           entry.kind,
+                           sender,
+                           receiver,
+                           kind);
+                }
+            }
+            if let Some(ref mut trace) = network_impl.trace {
+                trace.push(TraceEntry {
+                               sender: sender,
+                               receiver: receiver,
+                               kind: kind,
+                               wire_kind: queued.packet.wire_kind(),
+                           });
+            }
+        }
+        result
+    }
+
+    fn process_packet(&self, sender: Endpoint, receiver: Endpoint, queued: QueuedPacket<UID>) {
+        let QueuedPacket {
+            packet,
+            incarnation,
+            duplicate,
+        } = queued;
+        let delivery = self.delivery(sender, receiver, &packet, incarnation);
+        self.observe(sender, receiver, &packet, delivery);
+        match delivery {
+            Delivery::Delivered => {
+                // The sender is only told about the first delivery of a duplicated message.
+                let confirmation = match packet {
+                    Packet::Message(_, receiver_uid, msg_id) if !duplicate => {
+                        Some((receiver_uid, msg_id))
+                    }
+                    _ => None,
+                };
+                let copy = if confirmation.is_some() &&
+                              self.message_duplicated(sender, receiver) {
+                    Some(QueuedPacket {
+                             packet: packet.clone(),
+                             incarnation: incarnation,
+                             duplicate: true,
+                         })
+                } else {
+                    None
+                };
+                if let Some(service) = self.find_service(receiver) {
+                    self.record_activity(sender, receiver);
+                    self.record_traffic(sender, receiver, &packet);
+                    service.borrow_mut().receive_packet(sender, packet);
+                    if let Some((receiver_uid, msg_id)) = confirmation {
+                        self.confirm_message(sender, receiver_uid, msg_id, true);
+                    }
+                    if let Some(copy) = copy {
+                        self.requeue(sender, receiver, copy);
+                    }
+                } else {
+                    // The observer dropped the receiver.
+                    self.reject(sender, receiver, packet);
+                }
+            }
+            Delivery::Blackholed => {
+                if let Packet::Message(_, receiver_uid, msg_id) = packet {
+                    self.confirm_message(sender, receiver_uid, msg_id, true);
+                }
+            }
+            Delivery::Lost => {
+                if let Some(failure) = packet.to_failure() {
+                    self.send(receiver, sender, failure);
+                }
+            }
+            Delivery::Unreachable => {
+                if let Packet::Message(..) = packet {
+                    self.peer_unreachable(sender, receiver);
+                }
+                self.reject(sender, receiver, packet);
+            }
+            Delivery::Stale => {
+                debug!("Dropping {:?} packet from {:?} to an earlier incarnation of {:?}.",
+                       packet.kind(),
+                       sender,
+                       receiver);
+                self.0.borrow_mut().stale_packets += 1;
+                if self.0.borrow().stale_packet_failures {
+                    self.reject(sender, receiver, packet);
+                }
+            }
+            Delivery::Partitioned |
+            Delivery::Blocked => self.reject(sender, receiver, packet),
         }
-        result
     }
 
-    fn process_packet(&self, sender: Endpoint, receiver: Endpoint, packet: Packet<UID>) {
-        if self.connection_blocked(sender, receiver) {
-            if let Some(failure) = packet.to_failure() {
-                self.send(receiver, sender, failure);
-                return;
+    // Decides what happens to the packet. This draws the random number deciding whether it is lost.
+    fn delivery(&self,
+                sender: Endpoint,
+                receiver: Endpoint,
+                packet: &Packet<UID>,
+                incarnation: u64)
+                -> Delivery {
+        if self.0
+               .borrow()
+               .blackholed_connections
+               .contains(&(sender, receiver)) {
+            return Delivery::Blackholed;
+        }
+        if self.0
+               .borrow()
+               .partitioned_connections
+               .contains(&(sender, receiver)) {
+            return Delivery::Partitioned;
+        }
+        if self.packet_lost(sender, receiver) {
+            return Delivery::Lost;
+        }
+        if self.packet_blocked(sender, receiver, packet.kind()) {
+            let dropped = match *packet {
+                Packet::KeepAlive |
+                Packet::Disconnect => true,
+                // Messages over blocked connections are only dropped when confirmations are
+                // enabled, to preserve the behaviour existing tests rely on.
+                Packet::Message(..) => self.send_confirmations(),
+                _ => packet.to_failure().is_some(),
+            };
+            if dropped {
+                return Delivery::Blocked;
             }
         }
+        if let Packet::Message(ref data, ..) = *packet {
+            if self.wire_kind_blocked(sender, receiver, WireKind::of(data)) {
+                return Delivery::Blocked;
+            }
+        }
+        if self.find_service(receiver).is_none() {
+            Delivery::Unreachable
+        } else if self.0.borrow().incarnation(receiver) != incarnation {
+            Delivery::Stale
+        } else {
+            Delivery::Delivered
+        }
+    }
 
-        if let Some(service) = self.find_service(receiver) {
-            service.borrow_mut().receive_packet(sender, packet);
-        } else if let Some(failure) = packet.to_failure() {
-            // Packet was sent to a non-existing receiver.
+    // Answers a packet which isn't delivered with a failure, or a failed message confirmation.
+    fn reject(&self, sender: Endpoint, receiver: Endpoint, packet: Packet<UID>) {
+        if let Some(failure) = packet.to_failure() {
             self.send(receiver, sender, failure);
+        } else if let Packet::Message(_, receiver_uid, msg_id) = packet {
+            self.confirm_message(sender, receiver_uid, msg_id, false);
+        }
+    }
+
+    // Passes the packet to the observer installed via `set_packet_observer`, if any.
+    fn observe(&self,
+               sender: Endpoint,
+               receiver: Endpoint,
+               packet: &Packet<UID>,
+               delivery: Delivery) {
+        let observer = match self.0.borrow().packet_observer {
+            Some(ref observer) => observer.clone(),
+            None => return,
+        };
+        let observed = ObservedPacket {
+            sender: sender,
+            receiver: receiver,
+            kind: packet.kind(),
+            wire_kind: packet.wire_kind(),
+            payload: match *packet {
+                Packet::Message(ref data, ..) => Some(&data[..]),
+                _ => None,
+            },
+            delivery: delivery,
+        };
+        // The observer can't be re-entered, e.g. if it polls the network itself.
+        if let Ok(mut observer) = observer.try_borrow_mut() {
+            (&mut **observer)(&observed);
         }
     }
 
     fn find_service(&self, endpoint: Endpoint) -> Option<Rc<RefCell<ServiceImpl<UID>>>> {
+        self.with_bridged()
+            .iter()
+            .filter_map(|network| network.find_local_service(endpoint))
+            .next()
+    }
+
+    // Returns whether the endpoint was reserved explicitly or as part of a range, or is used by a
+    // live local service.
+    fn endpoint_taken(&self, endpoint: Endpoint) -> bool {
+        self.0.borrow().range_of(endpoint).is_some() || self.endpoint_used(endpoint)
+    }
+
+    // Returns whether the endpoint was requested explicitly, or is used by a live local service.
+    fn endpoint_used(&self, endpoint: Endpoint) -> bool {
+        self.0.borrow().reserved_endpoints.contains(&endpoint) ||
+        self.find_local_service(endpoint).is_some()
+    }
+
+    // Formats the endpoint with the label of its reserved range, if any.
+    fn describe_endpoint(&self, endpoint: Endpoint) -> String {
+        match self.endpoint_label(endpoint) {
+            Some(label) => format!("{:?}[{}]", endpoint, label),
+            None => format!("{:?}", endpoint),
+        }
+    }
+
+    fn find_local_service(&self, endpoint: Endpoint) -> Option<Rc<RefCell<ServiceImpl<UID>>>> {
         self.0
             .borrow()
             .services
@@ -272,6 +1909,111 @@ impl<UID: Uid> Network<UID> {
     }
 }
 
+impl Network<PublicId> {
+    /// Returns a factory of IDs derived from the seed this network was created with, so that
+    /// nodes can be given names in controlled parts of the address space, identically in re-runs.
+    pub fn id_factory(&self) -> IdFactory {
+        IdFactory { seed: self.0.borrow().id_seed }
+    }
+}
+
+impl<UID: Uid> fmt::Debug for Network<UID> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "Network({:p})", self.0.as_ptr())
+    }
+}
+
+/// A range of endpoints reserved via `Network::reserve_range`, which hands them out in order.
+pub struct EndpointRange<UID: Uid> {
+    network: Network<UID>,
+    index: usize,
+}
+
+impl<UID: Uid> EndpointRange<UID> {
+    /// Returns the range's label.
+    pub fn label(&self) -> &'static str {
+        self.network.0.borrow().endpoint_ranges[self.index].label
+    }
+
+    /// Returns the next endpoint of the range, skipping ones requested explicitly or used by a
+    /// live service, or an error if the range is exhausted. Endpoints are never handed out twice,
+    /// even if their services have been dropped.
+    pub fn gen_endpoint(&self) -> Result<Endpoint, MockError> {
+        let networks = self.network.with_bridged();
+        loop {
+            let endpoint = {
+                let mut imp = self.network.0.borrow_mut();
+                let range = &mut imp.endpoint_ranges[self.index];
+                if range.next >= range.end {
+                    return Err(MockError::RangeExhausted(range.label));
+                }
+                range.next += 1;
+                Endpoint(range.next - 1)
+            };
+            if !networks
+                    .iter()
+                    .any(|network| network.endpoint_used(endpoint)) {
+                return Ok(endpoint);
+            }
+        }
+    }
+
+    /// Creates a new `ServiceHandle` at the next endpoint of the range, or returns an error if the
+    /// range is exhausted.
+    pub fn new_service_handle(&self,
+                              opt_config: Option<Config>)
+                              -> Result<ServiceHandle<UID>, MockError> {
+        let endpoint = self.gen_endpoint()?;
+        Ok(self.network.add_service(opt_config, endpoint))
+    }
+}
+
+impl<UID: Uid> fmt::Debug for EndpointRange<UID> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let imp = self.network.0.borrow();
+        let range = &imp.endpoint_ranges[self.index];
+        write!(formatter,
+               "EndpointRange({}: {}..{}, next {})",
+               range.label,
+               range.start,
+               range.end,
+               range.next)
+    }
+}
+
+/// Produces `FullId`s whose names lie within requested prefixes. Each ID is derived from the
+/// network's seed and an index only, so the same index and prefix always yield the same ID.
+#[derive(Clone, Copy, Debug)]
+pub struct IdFactory {
+    seed: [u32; 4],
+}
+
+impl IdFactory {
+    /// Returns the `index`th ID whose name matches `prefix`.
+    ///
+    /// Signing keys are generated from the derived random seeds until one yields a matching name,
+    /// so this takes about `2^prefix.bit_count()` attempts. The encryption keys are not derived
+    /// from the index, as they don't affect the name.
+    pub fn full_id(&self, index: u32, prefix: &Prefix<XorName>) -> FullId {
+        let mut rng = SeededRng::from_seed([self.seed[0],
+                                            self.seed[1],
+                                            self.seed[2],
+                                            self.seed[3] ^ index]);
+        loop {
+            let sign_keys = sign::keypair_from_seed(&sign::Seed(rng.gen()));
+            let full_id = FullId::with_keys(box_::gen_keypair(), sign_keys);
+            if prefix.matches(full_id.public_id().name()) {
+                return full_id;
+            }
+        }
+    }
+
+    /// Returns the public part of the `index`th ID whose name matches `prefix`.
+    pub fn public_id(&self, index: u32, prefix: &Prefix<XorName>) -> PublicId {
+        *self.full_id(index, prefix).public_id()
+    }
+}
+
 /// `ServiceHandle` is associated with the mock `Service` and allows to configure
 /// and instrument it.
 #[derive(Clone)]
@@ -287,17 +2029,160 @@ impl<UID: Uid> ServiceHandle<UID> {
         self.0.borrow().endpoint
     }
 
-    /// Returns `true` if this service is connected to the given one.
+    /// Incarnation of the `Service` bound to this handle: 1 for the first service registered at
+    /// its endpoint, incremented with every replacement or restart.
+    pub fn incarnation(&self) -> u64 {
+        self.0.borrow().incarnation
+    }
+
+    /// Returns `true` if this service is connected to the given one. Returns `false` if no
+    /// `Service` has been started on the given handle yet.
     pub fn is_connected(&self, handle: &Self) -> bool {
-        self.0
-            .borrow()
-            .is_peer_connected(&unwrap!(handle.0.borrow().uid))
+        let uid = match handle.0.borrow().uid {
+            Some(uid) => uid,
+            None => return false,
+        };
+        self.0.borrow().is_peer_connected(&uid)
+    }
+
+    /// Returns the IDs of all peers this service is connected to.
+    pub fn connected_uids(&self) -> Vec<UID> {
+        self.0.borrow().connections.uids()
+    }
+
+    /// Returns the endpoints of all peers this service is connected to.
+    pub fn connected_endpoints(&self) -> Vec<Endpoint> {
+        self.0.borrow().connected_endpoints()
+    }
+
+    /// Returns the number of peers this service is connected to.
+    pub fn connection_count(&self) -> usize {
+        self.0.borrow().connections.len()
+    }
+
+    /// Returns `true` if this service is connected to the service at the given endpoint.
+    pub fn is_connected_to_endpoint(&self, endpoint: Endpoint) -> bool {
+        self.0.borrow().find_uid_by_endpoint(&endpoint).is_some()
     }
 
     /// Returns whether sent any message across the network since previous query and reset the flag.
     pub fn reset_message_sent(&self) -> bool {
         self.0.borrow().network.reset_message_sent()
     }
+
+    /// Sets how the `Service` responds to subsequent `prepare_connection_info` calls.
+    pub fn set_connection_info_behaviour(&self, behaviour: ConnectionInfoBehaviour) {
+        self.0.borrow_mut().connection_info_behaviour = behaviour;
+    }
+
+    /// Makes the next `count` `prepare_connection_info` calls of the `Service` result in an
+    /// error. The results are still delivered according to the configured behaviour, so they are
+    /// withheld if it is `Delayed`.
+    pub fn set_connection_info_failures(&self, count: usize) {
+        self.0.borrow_mut().connection_info_failures = count;
+    }
+
+    /// If `enable` is `true`, the results of subsequent `prepare_connection_info` calls of the
+    /// `Service` are only delivered once the network is polled after the call returned. Otherwise
+    /// they are delivered straight away.
+    pub fn set_connection_info_async(&self, enable: bool) {
+        // Preparing connection info polls the network itself, so one more poll is needed.
+        let behaviour = if enable {
+            ConnectionInfoBehaviour::Delayed(2)
+        } else {
+            ConnectionInfoBehaviour::Immediate
+        };
+        self.set_connection_info_behaviour(behaviour);
+    }
+
+    /// Adds the peer at `endpoint` to the whitelist of the `Service`. Once the whitelist isn't
+    /// empty, bootstrap and connect requests from peers not on it are rejected.
+    pub fn whitelist_peer(&self, endpoint: Endpoint) {
+        self.0.borrow_mut().whitelist_peer(endpoint);
+    }
+
+    /// Returns the endpoints on the whitelist of the `Service`, sorted. An empty whitelist allows
+    /// all peers.
+    pub fn whitelisted_endpoints(&self) -> Vec<Endpoint> {
+        let mut endpoints: Vec<_> = self.0.borrow().whitelist.iter().cloned().collect();
+        endpoints.sort();
+        endpoints
+    }
+
+    /// Sets which kinds of peers the `Service` accepts bootstrap requests from.
+    pub fn set_accept_bootstrap(&self, policy: BootstrapPolicy) {
+        self.0.borrow_mut().accept_bootstrap = policy;
+    }
+
+    /// Makes the `Service` send a keep-alive packet to all its peers every `interval` network
+    /// polls. Keep-alives prevent idle connections from being dropped, but don't raise any events.
+    pub fn enable_keepalive(&self, interval: usize) {
+        let mut imp = self.0.borrow_mut();
+        imp.keepalive_interval = Some(interval);
+        imp.polls_since_keepalive = 0;
+    }
+
+    /// Stops the `Service` from sending keep-alive packets.
+    pub fn disable_keepalive(&self) {
+        self.0.borrow_mut().keepalive_interval = None;
+    }
+
+    /// Simulates an abrupt crash of the `Service`: it vanishes from the network without sending
+    /// `Disconnect` to its peers, and all packets to and from it are dropped. Peers only notice
+    /// when they next try to reach it, or via `Network::lost_connection`.
+    pub fn crash(&self) {
+        self.0.borrow_mut().crash();
+    }
+}
+
+/// Determines which kinds of peers a listening mock `Service` accepts as bootstrappers. Refused
+/// peers receive a `BootstrapRefused` event.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BootstrapPolicy {
+    /// Both nodes and clients are accepted. This is the default.
+    All,
+    /// Only nodes are accepted.
+    NodesOnly,
+    /// Only clients are accepted.
+    ClientsOnly,
+    /// No bootstrappers are accepted.
+    None,
+}
+
+impl BootstrapPolicy {
+    fn accepts(&self, kind: CrustUser) -> bool {
+        match (*self, kind) {
+            (BootstrapPolicy::All, _) |
+            (BootstrapPolicy::NodesOnly, CrustUser::Node) |
+            (BootstrapPolicy::ClientsOnly, CrustUser::Client) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Default for BootstrapPolicy {
+    fn default() -> BootstrapPolicy {
+        BootstrapPolicy::All
+    }
+}
+
+/// Determines how a mock `Service` answers `prepare_connection_info` calls.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConnectionInfoBehaviour {
+    /// The `ConnectionInfoPrepared` event is sent straight away. This is the default.
+    Immediate,
+    /// The `ConnectionInfoPrepared` event is sent once the given number of network polls have
+    /// elapsed.
+    Delayed(usize),
+    /// The `ConnectionInfoPrepared` event is sent straight away, but contains an error. The
+    /// error kind is only used for logging.
+    Fail(io::ErrorKind),
+}
+
+impl Default for ConnectionInfoBehaviour {
+    fn default() -> ConnectionInfoBehaviour {
+        ConnectionInfoBehaviour::Immediate
+    }
 }
 
 pub struct ServiceImpl<UID: Uid> {
@@ -307,9 +2192,21 @@ pub struct ServiceImpl<UID: Uid> {
     config: Config,
     pub listening_tcp: bool,
     event_sender: Option<CrustEventSender<UID>>,
+    /// Set once sending an event failed, i.e. the receiving end of `event_sender` is gone.
+    receiver_gone: Cell<bool>,
     pending_bootstraps: u64,
-    connections: Vec<(UID, Endpoint)>,
+    connections: Connections<UID>,
     whitelist: HashSet<Endpoint>,
+    connection_info_behaviour: ConnectionInfoBehaviour,
+    /// Number of subsequent `prepare_connection_info` calls that fail.
+    connection_info_failures: usize,
+    accept_bootstrap: BootstrapPolicy,
+    keepalive_interval: Option<usize>,
+    polls_since_keepalive: usize,
+    /// Set once the service has crashed. It then doesn't send any packets anymore.
+    crashed: bool,
+    /// Distinguishes this service from earlier ones registered at the same endpoint.
+    incarnation: u64,
 }
 
 impl<UID: Uid> ServiceImpl<UID> {
@@ -321,21 +2218,57 @@ impl<UID: Uid> ServiceImpl<UID> {
             config: config,
             listening_tcp: false,
             event_sender: None,
+            receiver_gone: Cell::new(false),
             pending_bootstraps: 0,
-            connections: Vec::new(),
+            connections: Connections::new(),
             whitelist: HashSet::new(),
+            connection_info_behaviour: ConnectionInfoBehaviour::default(),
+            connection_info_failures: 0,
+            accept_bootstrap: BootstrapPolicy::default(),
+            keepalive_interval: None,
+            polls_since_keepalive: 0,
+            crashed: false,
+            incarnation: 0,
         }
     }
 
     pub fn start(&mut self, event_sender: CrustEventSender<UID>, uid: UID) {
         self.uid = Some(uid);
         self.event_sender = Some(event_sender);
+        self.receiver_gone.set(false);
+    }
+
+    fn snapshot(&self) -> ServiceSnapshot<UID> {
+        ServiceSnapshot {
+            listening_tcp: self.listening_tcp,
+            pending_bootstraps: self.pending_bootstraps,
+            connections: self.connections.clone(),
+            whitelist: self.whitelist.clone(),
+            connection_info_behaviour: self.connection_info_behaviour,
+            connection_info_failures: self.connection_info_failures,
+            accept_bootstrap: self.accept_bootstrap,
+            keepalive_interval: self.keepalive_interval,
+            polls_since_keepalive: self.polls_since_keepalive,
+        }
+    }
+
+    fn restore(&mut self, snapshot: &ServiceSnapshot<UID>) {
+        self.listening_tcp = snapshot.listening_tcp;
+        self.pending_bootstraps = snapshot.pending_bootstraps;
+        self.connections = snapshot.connections.clone();
+        self.whitelist = snapshot.whitelist.clone();
+        self.connection_info_behaviour = snapshot.connection_info_behaviour;
+        self.connection_info_failures = snapshot.connection_info_failures;
+        self.accept_bootstrap = snapshot.accept_bootstrap;
+        self.keepalive_interval = snapshot.keepalive_interval;
+        self.polls_since_keepalive = snapshot.polls_since_keepalive;
     }
 
     pub fn restart(&mut self, event_sender: CrustEventSender<UID>, uid: UID) {
         trace!("{:?} restart", self.endpoint);
 
         self.disconnect_all();
+        self.incarnation = self.network.next_incarnation(self.endpoint);
 
         self.uid = Some(uid);
         self.listening_tcp = false;
@@ -347,7 +2280,8 @@ impl<UID: Uid> ServiceImpl<UID> {
         let mut pending_bootstraps = 0;
 
         for endpoint in &self.config.hard_coded_contacts {
-            if *endpoint != self.endpoint && !blacklist.contains(&to_socket_addr(endpoint)) {
+            if *endpoint != self.endpoint &&
+               !blacklist.contains(&self.network.socket_addr(endpoint)) {
                 self.send_packet(*endpoint, Packet::BootstrapRequest(unwrap!(self.uid), kind));
                 pending_bootstraps += 1;
             }
@@ -356,18 +2290,20 @@ impl<UID: Uid> ServiceImpl<UID> {
         // If we have no contacts in the config, we can fire BootstrapFailed
         // immediately.
         if pending_bootstraps == 0 {
-            unwrap!(unwrap!(self.event_sender.as_ref()).send(Event::BootstrapFailed));
+            self.send_event(Event::BootstrapFailed);
         }
 
         self.pending_bootstraps = pending_bootstraps;
     }
 
-    pub fn send_message(&self, uid: &UID, data: Vec<u8>) -> bool {
+    /// Sends `data` to the peer and returns the message's ID, or `None` if not connected to them.
+    pub fn send_message(&self, uid: &UID, data: Vec<u8>) -> Option<u64> {
         if let Some(endpoint) = self.find_endpoint_by_uid(uid) {
-            self.send_packet(endpoint, Packet::Message(data));
-            true
+            let msg_id = self.network.gen_msg_id();
+            self.send_packet(endpoint, Packet::Message(data, *uid, msg_id));
+            Some(msg_id)
         } else {
-            false
+            None
         }
     }
 
@@ -375,6 +2311,26 @@ impl<UID: Uid> ServiceImpl<UID> {
         self.find_endpoint_by_uid(uid).is_some()
     }
 
+    pub fn peer_ip_addr(&self, uid: &UID) -> Option<IpAddr> {
+        self.peer_socket_addr(uid).map(|addr| addr.ip())
+    }
+
+    pub fn peer_socket_addr(&self, uid: &UID) -> Option<SocketAddr> {
+        self.find_endpoint_by_uid(uid)
+            .map(|endpoint| self.network.socket_addr(&endpoint))
+    }
+
+    /// Adds the services at the given addresses to the contacts to bootstrap off, after the
+    /// configured ones.
+    pub fn add_bootstrap_contacts(&mut self, addrs: &[SocketAddr]) {
+        for addr in addrs {
+            let endpoint = Endpoint(addr.port() as usize);
+            if !self.config.hard_coded_contacts.contains(&endpoint) {
+                self.config.hard_coded_contacts.push(endpoint);
+            }
+        }
+    }
+
     pub fn whitelist_peer(&mut self, endpoint: Endpoint) {
         if !self.whitelist.insert(endpoint) {
             debug!("Duplicate insert attempt whitelist for peer : {:?}",
@@ -383,21 +2339,50 @@ impl<UID: Uid> ServiceImpl<UID> {
     }
 
     pub fn is_peer_whitelisted(&self, id: &UID) -> bool {
-        self.whitelist.is_empty() ||
         self.find_endpoint_by_uid(id)
-            .map_or(false, |endpoint| self.whitelist.contains(&endpoint))
+            .map_or(self.whitelist.is_empty(),
+                    |endpoint| self.is_endpoint_whitelisted(&endpoint))
+    }
+
+    // An empty whitelist allows everyone.
+    fn is_endpoint_whitelisted(&self, endpoint: &Endpoint) -> bool {
+        self.whitelist.is_empty() || self.whitelist.contains(endpoint)
     }
 
-    pub fn prepare_connection_info(&self, result_token: u32) {
-        // TODO: should we also simulate failure here?
-        // TODO: should we simulate asynchrony here?
+    pub fn prepare_connection_info(&mut self, result_token: u32) {
+        let failed = self.connection_info_failures > 0;
+        if failed {
+            trace!("{:?} simulating failure of prepare_connection_info", self.endpoint);
+            self.connection_info_failures -= 1;
+        }
+        match self.connection_info_behaviour {
+            ConnectionInfoBehaviour::Immediate => {
+                self.send_connection_info_prepared(result_token, failed)
+            }
+            ConnectionInfoBehaviour::Delayed(polls) => {
+                self.network
+                    .delay_connection_info(self.endpoint, result_token, polls, failed)
+            }
+            ConnectionInfoBehaviour::Fail(kind) => {
+                trace!("{:?} simulating {:?} failure of prepare_connection_info",
+                       self.endpoint,
+                       kind);
+                self.send_connection_info_prepared(result_token, true);
+            }
+        }
+    }
 
+    fn send_connection_info_prepared(&self, result_token: u32, failed: bool) {
         let result = ConnectionInfoResult {
             result_token: result_token,
-            result: Ok(PrivConnectionInfo {
-                           id: unwrap!(self.uid),
-                           endpoint: self.endpoint,
-                       }),
+            result: if failed {
+                Err(CrustError)
+            } else {
+                Ok(PrivConnectionInfo {
+                       id: unwrap!(self.uid),
+                       endpoint: self.endpoint,
+                   })
+            },
         };
 
         self.send_event(CrustEvent::ConnectionInfoPrepared(result));
@@ -414,6 +2399,9 @@ impl<UID: Uid> ServiceImpl<UID> {
     }
 
     fn send_packet(&self, receiver: Endpoint, packet: Packet<UID>) {
+        if self.crashed {
+            return;
+        }
         self.network.send(self.endpoint, receiver, packet);
     }
 
@@ -421,36 +2409,72 @@ impl<UID: Uid> ServiceImpl<UID> {
         match packet {
             Packet::BootstrapRequest(uid, kind) => self.handle_bootstrap_request(sender, uid, kind),
             Packet::BootstrapSuccess(uid) => self.handle_bootstrap_success(sender, uid),
-            Packet::BootstrapFailure => self.handle_bootstrap_failure(sender),
+            Packet::BootstrapFailure(reason) => self.handle_bootstrap_failure(sender, reason),
             Packet::ConnectRequest(their_id, _) => self.handle_connect_request(sender, their_id),
             Packet::ConnectSuccess(their_id, _) => self.handle_connect_success(sender, their_id),
             Packet::ConnectFailure(their_id, _) => self.handle_connect_failure(sender, their_id),
-            Packet::Message(data) => self.handle_message(sender, data),
+            Packet::Message(data, ..) => self.handle_message(sender, data),
             Packet::Disconnect => self.handle_disconnect(sender),
+            Packet::KeepAlive => (),
         }
     }
 
-    fn handle_bootstrap_request(&mut self, peer_endpoint: Endpoint, uid: UID, kind: CrustUser) {
-        if self.is_listening() {
-            self.handle_bootstrap_accept(peer_endpoint, uid, kind);
-            self.send_packet(peer_endpoint, Packet::BootstrapSuccess(unwrap!(self.uid)));
-        } else {
-            self.send_packet(peer_endpoint, Packet::BootstrapFailure);
+    // Sends a keep-alive to all peers if keep-alives are enabled and one is due.
+    fn poll_keepalive(&mut self) {
+        let interval = match self.keepalive_interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        self.polls_since_keepalive += 1;
+        if self.polls_since_keepalive < interval {
+            return;
+        }
+        self.polls_since_keepalive = 0;
+        for endpoint in self.connected_endpoints() {
+            self.send_packet(endpoint, Packet::KeepAlive);
         }
     }
 
-    fn handle_bootstrap_accept(&mut self, peer_endpoint: Endpoint, uid: UID, kind: CrustUser) {
-        self.add_connection(uid, peer_endpoint);
-        self.send_event(CrustEvent::BootstrapAccept(uid, kind));
+    fn handle_bootstrap_request(&mut self, peer_endpoint: Endpoint, uid: UID, kind: CrustUser) {
+        if !self.is_listening() {
+            let reason = BootstrapFailureReason::NotListening;
+            self.send_packet(peer_endpoint, Packet::BootstrapFailure(reason));
+        } else if !self.accept_bootstrap.accepts(kind) {
+            let reason = BootstrapFailureReason::Refused(kind);
+            self.send_packet(peer_endpoint, Packet::BootstrapFailure(reason));
+        } else if !self.is_endpoint_whitelisted(&peer_endpoint) {
+            let reason = BootstrapFailureReason::NotWhitelisted;
+            self.send_packet(peer_endpoint, Packet::BootstrapFailure(reason));
+        } else if !self.add_connection(uid, peer_endpoint) {
+            let reason = BootstrapFailureReason::ConnectionLimit;
+            self.send_packet(peer_endpoint, Packet::BootstrapFailure(reason));
+        } else {
+            self.send_event(CrustEvent::BootstrapAccept(uid, kind));
+            self.send_packet(peer_endpoint, Packet::BootstrapSuccess(unwrap!(self.uid)));
+        }
     }
 
     fn handle_bootstrap_success(&mut self, peer_endpoint: Endpoint, uid: UID) {
-        self.add_connection(uid, peer_endpoint);
-        self.send_event(CrustEvent::BootstrapConnect(uid, to_socket_addr(&peer_endpoint)));
+        let addr = self.network.socket_addr(&peer_endpoint);
+        if self.add_connection(uid, peer_endpoint) {
+            self.send_event(CrustEvent::BootstrapConnect(uid, addr));
+        } else {
+            // We reached our own limit while the request was in flight.
+            self.send_packet(peer_endpoint, Packet::Disconnect);
+            let reason = BootstrapFailureReason::ConnectionLimit;
+            self.send_event(CrustEvent::BootstrapAttemptFailed(addr, reason));
+        }
         self.decrement_pending_bootstraps();
     }
 
-    fn handle_bootstrap_failure(&mut self, _peer_endpoint: Endpoint) {
+    fn handle_bootstrap_failure(&mut self,
+                                peer_endpoint: Endpoint,
+                                reason: BootstrapFailureReason) {
+        let addr = self.network.socket_addr(&peer_endpoint);
+        if let BootstrapFailureReason::Refused(kind) = reason {
+            self.send_event(CrustEvent::BootstrapRefused(addr, kind));
+        }
+        self.send_event(CrustEvent::BootstrapAttemptFailed(addr, reason));
         self.decrement_pending_bootstraps();
     }
 
@@ -458,14 +2482,26 @@ impl<UID: Uid> ServiceImpl<UID> {
         if self.is_connected(&peer_endpoint, &their_id) {
             return;
         }
+        if !self.is_endpoint_whitelisted(&peer_endpoint) ||
+           !self.add_connection(their_id, peer_endpoint) {
+            self.send_packet(peer_endpoint,
+                             Packet::ConnectFailure(unwrap!(self.uid), their_id));
+            return;
+        }
 
-        self.add_rendezvous_connection(their_id, peer_endpoint);
+        self.send_event(CrustEvent::ConnectSuccess(their_id));
         self.send_packet(peer_endpoint,
                          Packet::ConnectSuccess(unwrap!(self.uid), their_id));
     }
 
     fn handle_connect_success(&mut self, peer_endpoint: Endpoint, their_id: UID) {
-        self.add_rendezvous_connection(their_id, peer_endpoint);
+        if self.add_connection(their_id, peer_endpoint) {
+            self.send_event(CrustEvent::ConnectSuccess(their_id));
+        } else {
+            // We reached our own limit while the request was in flight.
+            self.send_packet(peer_endpoint, Packet::Disconnect);
+            self.send_event(CrustEvent::ConnectFailure(their_id));
+        }
     }
 
     fn handle_connect_failure(&self, _peer_endpoint: Endpoint, their_id: UID) {
@@ -480,15 +2516,40 @@ impl<UID: Uid> ServiceImpl<UID> {
         }
     }
 
+    pub fn inject_message(&self, peer_endpoint: Endpoint, data: Vec<u8>) -> bool {
+        if let Some(uid) = self.find_uid_by_endpoint(&peer_endpoint) {
+            self.send_event(CrustEvent::NewMessage(uid, data));
+            true
+        } else {
+            false
+        }
+    }
+
     fn handle_disconnect(&mut self, peer_endpoint: Endpoint) {
         if let Some(uid) = self.remove_connection_by_endpoint(peer_endpoint) {
             self.send_event(CrustEvent::LostPeer(uid));
         }
     }
 
+    // Sends the event to the owner of this service. If the receiver has been dropped, the event
+    // and all subsequent ones are silently discarded, so that tearing down a network in any order
+    // doesn't panic.
     fn send_event(&self, event: CrustEvent<UID>) {
-        let sender = unwrap!(self.event_sender.as_ref(), "Could not get event sender.");
-        unwrap!(sender.send(event));
+        if self.receiver_gone.get() {
+            return;
+        }
+        let sender = match self.event_sender.as_ref() {
+            Some(sender) => sender,
+            None => {
+                debug!("{:?} Service not started. Discarding {:?}.", self.endpoint, event);
+                return;
+            }
+        };
+        if sender.send(event).is_err() {
+            debug!("{:?} Event receiver gone. Discarding further events.",
+                   self.endpoint);
+            self.receiver_gone.set(true);
+        }
     }
 
     fn is_listening(&self) -> bool {
@@ -507,61 +2568,51 @@ impl<UID: Uid> ServiceImpl<UID> {
         }
     }
 
+    // Returns `false` if the connection is refused because we already have the maximum number of
+    // connections allowed by our config. Adding an existing connection again succeeds.
     fn add_connection(&mut self, uid: UID, peer_endpoint: Endpoint) -> bool {
-        if self.connections
-               .iter()
-               .any(|&(id, ep)| id == uid && ep == peer_endpoint) {
-            // Connection already exists
+        if self.connections.contains(&uid, &peer_endpoint) {
+            return true;
+        }
+        if self.config
+               .max_connections
+               .map_or(false, |max| self.connections.len() >= max) {
+            debug!("{:?} Refusing connection to {:?}: limit reached.",
+                   self.endpoint,
+                   peer_endpoint);
             return false;
         }
 
-        self.connections.push((uid, peer_endpoint));
+        let _ = self.connections.insert(uid, peer_endpoint);
         true
     }
 
-    fn add_rendezvous_connection(&mut self, uid: UID, peer_endpoint: Endpoint) {
-        self.add_connection(uid, peer_endpoint);
-        self.send_event(CrustEvent::ConnectSuccess(uid));
-    }
-
     // Remove connected peer with the given uid and return its endpoint,
     // or None if no such peer exists.
     fn remove_connection_by_uid(&mut self, uid: &UID) -> Option<Endpoint> {
-        if let Some(i) = self.connections.iter().position(|&(id, _)| id == *uid) {
-            Some(self.connections.swap_remove(i).1)
-        } else {
-            None
-        }
+        self.connections.remove_uid(uid)
     }
 
-    fn remove_connection_by_endpoint(&mut self, endpoint: Endpoint) -> Option<UID> {
-        if let Some(i) = self.connections
-               .iter()
-               .position(|&(_, ep)| ep == endpoint) {
-            Some(self.connections.swap_remove(i).0)
-        } else {
-            None
-        }
+    /// Forgets the connection to `endpoint` on this side only, without notifying either service.
+    /// Returns the peer's UID if it was connected.
+    pub fn remove_connection_by_endpoint(&mut self, endpoint: Endpoint) -> Option<UID> {
+        self.connections.remove_endpoint(&endpoint)
+    }
+
+    fn connected_endpoints(&self) -> Vec<Endpoint> {
+        self.connections.endpoints()
     }
 
     fn find_endpoint_by_uid(&self, uid: &UID) -> Option<Endpoint> {
-        self.connections
-            .iter()
-            .find(|&&(id, _)| id == *uid)
-            .map(|&(_, ep)| ep)
+        self.connections.endpoint_of(uid)
     }
 
     fn find_uid_by_endpoint(&self, endpoint: &Endpoint) -> Option<UID> {
-        self.connections
-            .iter()
-            .find(|&&(_, ep)| ep == *endpoint)
-            .map(|&(id, _)| id)
+        self.connections.uid_at(endpoint)
     }
 
     fn is_connected(&self, endpoint: &Endpoint, uid: &UID) -> bool {
-        self.connections
-            .iter()
-            .any(|&conn| conn == (*uid, *endpoint))
+        self.connections.contains(uid, endpoint)
     }
 
     pub fn disconnect(&mut self, uid: &UID) -> bool {
@@ -581,13 +2632,31 @@ impl<UID: Uid> ServiceImpl<UID> {
         }
     }
 
+    /// Removes the service from the network without notifying its peers. See
+    /// `ServiceHandle::crash`.
+    pub fn crash(&mut self) {
+        if self.crashed {
+            return;
+        }
+        self.crashed = true;
+        self.connections.clear();
+        self.pending_bootstraps = 0;
+        self.network.remove_service(self.endpoint);
+    }
+
     pub fn disconnect_all(&mut self) {
-        self.network.drop_all_pending();
-        let endpoints = self.connections
-            .drain(..)
-            .map(|(_, ep)| ep)
-            .collect::<Vec<_>>();
+        // A crashed service doesn't tell anyone, even when it is dropped later.
+        if self.crashed {
+            return;
+        }
+        let endpoints = self.connections.endpoints();
+        self.connections.clear();
+        // Once the network is closed there is nobody left to tell.
+        if !self.network.is_available() {
+            return;
+        }
 
+        self.network.drop_all_pending();
         for endpoint in endpoints {
             self.send_packet(endpoint, Packet::Disconnect);
         }
@@ -600,11 +2669,41 @@ impl<UID: Uid> Drop for ServiceImpl<UID> {
     }
 }
 
-/// Creates a `SocketAddr` with the endpoint as its port, so that endpoints and addresses can be
-/// easily mapped to each other during testing.
-fn to_socket_addr(endpoint: &Endpoint) -> SocketAddr {
-    SocketAddr::new(IpAddr::V4(Ipv4Addr::new(123, 123, 255, 255)),
-                    endpoint.0 as u16)
+/// Errors returned by the `Network` operations on individual services.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MockError {
+    /// No live service is bound to the endpoint, e.g. because it has been dropped.
+    ServiceNotFound(Endpoint),
+    /// The service at the first endpoint is not connected to the one at the second.
+    NotConnected(Endpoint, Endpoint),
+    /// All endpoints of the reserved range with this label have been handed out.
+    RangeExhausted(&'static str),
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MockError::ServiceNotFound(endpoint) => {
+                write!(formatter, "No service found at {:?}", endpoint)
+            }
+            MockError::NotConnected(endpoint_1, endpoint_2) => {
+                write!(formatter, "{:?} is not connected to {:?}", endpoint_1, endpoint_2)
+            }
+            MockError::RangeExhausted(label) => {
+                write!(formatter, "Endpoint range {} is exhausted", label)
+            }
+        }
+    }
+}
+
+impl error::Error for MockError {
+    fn description(&self) -> &str {
+        match *self {
+            MockError::ServiceNotFound(_) => "Service not found",
+            MockError::NotConnected(..) => "Services not connected",
+            MockError::RangeExhausted(_) => "Endpoint range exhausted",
+        }
+    }
 }
 
 /// Simulated crust config file.
@@ -612,6 +2711,9 @@ fn to_socket_addr(endpoint: &Endpoint) -> SocketAddr {
 pub struct Config {
     /// Contacts to bootstrap against.
     pub hard_coded_contacts: Vec<Endpoint>,
+    /// The maximum number of simultaneous connections. Further bootstrap and connect requests are
+    /// refused. `None` means no limit.
+    pub max_connections: Option<usize>,
 }
 
 impl Config {
@@ -622,7 +2724,15 @@ impl Config {
 
     /// Create `Config` with the given hardcoded contacts.
     pub fn with_contacts(contacts: &[Endpoint]) -> Self {
-        Config { hard_coded_contacts: contacts.into_iter().cloned().collect() }
+        Config {
+            hard_coded_contacts: contacts.into_iter().cloned().collect(),
+            max_connections: None,
+        }
+    }
+
+    /// Limits the number of simultaneous connections to `max`.
+    pub fn with_max_connections(self, max: usize) -> Self {
+        Config { max_connections: Some(max), ..self }
     }
 }
 
@@ -637,25 +2747,210 @@ impl Default for Config {
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize, Deserialize, PartialOrd, Ord)]
 pub struct Endpoint(pub usize);
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 enum Packet<UID: Uid> {
     BootstrapRequest(UID, CrustUser),
     BootstrapSuccess(UID),
-    BootstrapFailure,
+    BootstrapFailure(BootstrapFailureReason),
 
     ConnectRequest(UID, UID),
     ConnectSuccess(UID, UID),
     ConnectFailure(UID, UID),
 
-    Message(Vec<u8>),
+    Message(Vec<u8>, UID, u64),
     Disconnect,
+    KeepAlive,
+}
+
+/// A packet taken off the queue, as recorded by `Network::start_recording` and replayed by
+/// `Network::replay`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub sender: Endpoint,
+    pub receiver: Endpoint,
+    pub kind: PacketKind,
+    /// The kind of the payload, if the packet is a message.
+    pub wire_kind: Option<WireKind>,
+}
+
+/// What happened to a packet passed to the observer installed via `Network::set_packet_observer`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Delivery {
+    /// The packet is handed to its receiver.
+    Delivered,
+    /// The packet is dropped by a blocked connection.
+    Blocked,
+    /// The packet silently vanishes in a blackholed connection.
+    Blackholed,
+    /// The packet is dropped because its sender and receiver are partitioned.
+    Partitioned,
+    /// The packet is lost by a lossy connection.
+    Lost,
+    /// There is no service at the receiving endpoint.
+    Unreachable,
+    /// The packet was sent before the service at the receiving endpoint was restarted.
+    Stale,
+}
+
+/// Why a route doesn't deliver packets normally, as listed by `Network::impaired_links`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LinkImpairment {
+    /// The packets of the given kinds are blocked via `Network::block_packet_kind`.
+    Blocked(PacketKindMask),
+    /// The messages of the given kind are blocked via `Network::block_wire_kind`.
+    BlockedWire(WireKind),
+    /// Packets are delayed via `Network::delay_connection`.
+    Delayed,
+    /// Packets are held back via `Network::hold_connection`.
+    Held,
+    /// Packets vanish via `Network::blackhole_connection`.
+    Blackholed,
+    /// The route is cut via `Network::partition`.
+    Partitioned,
+}
+
+/// A packet as seen by the observer installed via `Network::set_packet_observer`.
+#[derive(Debug)]
+pub struct ObservedPacket<'a> {
+    pub sender: Endpoint,
+    pub receiver: Endpoint,
+    pub kind: PacketKind,
+    /// The kind of the payload, if the packet is a message.
+    pub wire_kind: Option<WireKind>,
+    /// The payload, if the packet is a message.
+    pub payload: Option<&'a [u8]>,
+    pub delivery: Delivery,
+}
+
+/// The kind of a queued packet, without its payload.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize, PartialOrd, Ord)]
+pub enum PacketKind {
+    /// A request to bootstrap off the receiver.
+    BootstrapRequest,
+    /// The receiver's bootstrap request has been accepted.
+    BootstrapSuccess,
+    /// The receiver's bootstrap request has been rejected.
+    BootstrapFailure,
+    /// A request to connect to the receiver.
+    ConnectRequest,
+    /// The receiver's connect request has been accepted.
+    ConnectSuccess,
+    /// The receiver's connect request has failed.
+    ConnectFailure,
+    /// A user message.
+    Message,
+    /// The sender has disconnected from the receiver.
+    Disconnect,
+    /// A keep-alive, which doesn't raise any event at the receiver.
+    KeepAlive,
+}
+
+const BOOTSTRAP_PACKETS: u8 = 0b0_0001;
+const CONNECT_PACKETS: u8 = 0b0_0010;
+const MESSAGE_PACKETS: u8 = 0b0_0100;
+const DISCONNECT_PACKETS: u8 = 0b0_1000;
+const KEEPALIVE_PACKETS: u8 = 0b1_0000;
+
+/// Selects the kinds of packets blocked by `Network::block_packet_kind`.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PacketKindMask(u8);
+
+impl PacketKindMask {
+    /// Bootstrap requests and their responses.
+    pub fn bootstrap() -> PacketKindMask {
+        PacketKindMask(BOOTSTRAP_PACKETS)
+    }
+
+    /// Connect requests and their responses.
+    pub fn connect() -> PacketKindMask {
+        PacketKindMask(CONNECT_PACKETS)
+    }
+
+    /// User messages.
+    pub fn message() -> PacketKindMask {
+        PacketKindMask(MESSAGE_PACKETS)
+    }
+
+    /// Disconnect notifications.
+    pub fn disconnect() -> PacketKindMask {
+        PacketKindMask(DISCONNECT_PACKETS)
+    }
+
+    /// All packets, including keep-alives.
+    pub fn all() -> PacketKindMask {
+        PacketKindMask(BOOTSTRAP_PACKETS | CONNECT_PACKETS | MESSAGE_PACKETS | DISCONNECT_PACKETS |
+                       KEEPALIVE_PACKETS)
+    }
+
+    /// Returns the mask selecting the packets selected by either `self` or `other`.
+    pub fn with(self, other: PacketKindMask) -> PacketKindMask {
+        PacketKindMask(self.0 | other.0)
+    }
+
+    /// Returns the mask selecting the packets selected by `self` but not by `other`.
+    pub fn without(self, other: PacketKindMask) -> PacketKindMask {
+        PacketKindMask(self.0 & !other.0)
+    }
+
+    /// Returns whether no packets are selected.
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns whether packets of the given kind are selected.
+    pub fn contains(&self, kind: PacketKind) -> bool {
+        let bit = match kind {
+            PacketKind::BootstrapRequest |
+            PacketKind::BootstrapSuccess |
+            PacketKind::BootstrapFailure => BOOTSTRAP_PACKETS,
+            PacketKind::ConnectRequest |
+            PacketKind::ConnectSuccess |
+            PacketKind::ConnectFailure => CONNECT_PACKETS,
+            PacketKind::Message => MESSAGE_PACKETS,
+            PacketKind::Disconnect => DISCONNECT_PACKETS,
+            PacketKind::KeepAlive => KEEPALIVE_PACKETS,
+        };
+        self.0 & bit != 0
+    }
 }
 
 impl<UID: Uid> Packet<UID> {
+    fn kind(&self) -> PacketKind {
+        match *self {
+            Packet::BootstrapRequest(..) => PacketKind::BootstrapRequest,
+            Packet::BootstrapSuccess(..) => PacketKind::BootstrapSuccess,
+            Packet::BootstrapFailure(..) => PacketKind::BootstrapFailure,
+            Packet::ConnectRequest(..) => PacketKind::ConnectRequest,
+            Packet::ConnectSuccess(..) => PacketKind::ConnectSuccess,
+            Packet::ConnectFailure(..) => PacketKind::ConnectFailure,
+            Packet::Message(..) => PacketKind::Message,
+            Packet::Disconnect => PacketKind::Disconnect,
+            Packet::KeepAlive => PacketKind::KeepAlive,
+        }
+    }
+
+    // The kind of the payload, if the packet is a message.
+    fn wire_kind(&self) -> Option<WireKind> {
+        match *self {
+            Packet::Message(ref data, ..) => Some(WireKind::of(data)),
+            _ => None,
+        }
+    }
+
+    // The number of bytes the packet counts with in the traffic statistics.
+    fn size(&self) -> u64 {
+        match *self {
+            Packet::Message(ref data, ..) => data.len() as u64,
+            _ => CONTROL_PACKET_SIZE,
+        }
+    }
+
     // Given a request packet, returns the corresponding failure packet.
     fn to_failure(&self) -> Option<Packet<UID>> {
         match *self {
-            Packet::BootstrapRequest(..) => Some(Packet::BootstrapFailure),
+            Packet::BootstrapRequest(..) => {
+                Some(Packet::BootstrapFailure(BootstrapFailureReason::Blocked))
+            }
             Packet::ConnectRequest(our_id, their_id) => {
                 Some(Packet::ConnectFailure(their_id, our_id))
             }
@@ -17,13 +17,25 @@
 
 // These tests are almost straight up copied from crust::service::tests
 
-use super::crust::{CrustEventSender, CrustUser, Service};
-use super::support::{Config, Network};
+use super::crust::{BootstrapFailureReason, CrustEventSender, CrustUser, Service};
+use super::support::{BootstrapPolicy, CONTROL_PACKET_SIZE, Config, ConnectionInfoBehaviour,
+                     Delivery, Endpoint, LinkImpairment, MockError, Network, ObservedPacket,
+                     PacketKind, PacketKindMask, ServiceHandle, TraceEntry};
+use rand::Rng;
 use CrustEvent;
+use WireKind;
 use id::{FullId, PublicId};
 use maidsafe_utilities::event_sender::{MaidSafeEventCategory, MaidSafeObserver};
-use std::collections::HashSet;
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use messages::{DirectMessage, Message};
+use routing_table::Authority;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::rc::Rc;
 use std::sync::mpsc::{self, Receiver};
+use test_messages::TestMessage;
 
 fn get_event_sender
     ()
@@ -180,6 +192,1333 @@ fn start_two_services_rendezvous_connect() {
     assert_eq!(pub_id, id_1);
 }
 
+#[test]
+fn send_confirmations() {
+    const PREPARE_CI_TOKEN: u32 = 1;
+
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    network.enable_send_confirmations(true);
+    let handle0 = network.new_service_handle(None, None);
+    let handle1 = network.new_service_handle(None, None);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let service_0 = unwrap!(Service::with_handle(&handle0, event_tx_0, *FullId::new().public_id()));
+    let service_1 = unwrap!(Service::with_handle(&handle1, event_tx_1, *FullId::new().public_id()));
+
+    service_0.prepare_connection_info(PREPARE_CI_TOKEN);
+    let our_ci_0 = expect_event!(event_rx_0,
+                                 CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        unwrap!(cir.result)
+    });
+
+    service_1.prepare_connection_info(PREPARE_CI_TOKEN);
+    let our_ci_1 = expect_event!(event_rx_1,
+                                 CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        unwrap!(cir.result)
+    });
+
+    let their_ci_1 = our_ci_1.to_pub_connection_info();
+    unwrap!(service_0.connect(our_ci_0, their_ci_1));
+
+    let id_1 = expect_event!(event_rx_0, CrustEvent::ConnectSuccess::<PublicId>(id) => id);
+    expect_event!(event_rx_1, CrustEvent::ConnectSuccess::<PublicId>(_));
+
+    // A message which reaches the receiver is confirmed as delivered.
+    let msg_id = unwrap!(service_0.send_with_id(id_1, vec![0, 1, 2], 0));
+    expect_event!(event_rx_1, CrustEvent::NewMessage::<PublicId>(..));
+    expect_event!(event_rx_0, CrustEvent::MessageDelivered::<PublicId>(id, delivered_id) => {
+        assert_eq!(id, id_1);
+        assert_eq!(delivered_id, msg_id);
+    });
+
+    // A message over a blocked connection is dropped and reported as failed.
+    network.block_connection(handle0.endpoint(), handle1.endpoint());
+    let msg_id = unwrap!(service_0.send_with_id(id_1, vec![3, 4, 5], 0));
+    assert!(event_rx_1.try_recv().is_err());
+    expect_event!(event_rx_0, CrustEvent::MessageFailed::<PublicId>(id, failed_id) => {
+        assert_eq!(id, id_1);
+        assert_eq!(failed_id, msg_id);
+    });
+}
+
+#[test]
+fn bridged_networks() {
+    let min_section_size = 8;
+    let network_a = Network::new(min_section_size, None);
+    let network_b = Network::new(min_section_size, None);
+
+    let handle_a = network_a.new_service_handle(None, None);
+    network_a.bridge(&network_b);
+
+    // Endpoints generated after bridging don't collide with the other network's.
+    let config = Config::with_contacts(&[handle_a.endpoint()]);
+    let handle_b = network_b.new_service_handle(Some(config), None);
+    assert_ne!(handle_a.endpoint(), handle_b.endpoint());
+
+    let (event_tx_a, _category_rx_a, event_rx_a) = get_event_sender();
+    let (event_tx_b, _category_rx_b, event_rx_b) = get_event_sender();
+
+    let mut service_a =
+        unwrap!(Service::with_handle(&handle_a, event_tx_a, *FullId::new().public_id()));
+    unwrap!(service_a.start_listening_tcp());
+    expect_event!(event_rx_a, CrustEvent::ListenerStarted::<PublicId>(..));
+
+    let mut service_b =
+        unwrap!(Service::with_handle(&handle_b, event_tx_b, *FullId::new().public_id()));
+    unwrap!(service_b.start_bootstrap(HashSet::new(), CrustUser::Node));
+    let id_a = expect_event!(event_rx_b, CrustEvent::BootstrapConnect::<PublicId>(id, _) => id);
+    let id_b = expect_event!(event_rx_a,
+        CrustEvent::BootstrapAccept::<PublicId>(id, CrustUser::Node) => id);
+
+    // Messages flow across the bridge.
+    let data_sent = vec![0, 1, 255, 254, 222, 1];
+    unwrap!(service_b.send(id_a, data_sent.clone(), 0));
+    let data_recvd =
+        expect_event!(event_rx_a, CrustEvent::NewMessage::<PublicId>(_, msg) => msg);
+    assert_eq!(data_recvd, data_sent);
+
+    // Unbridging severs the connection.
+    network_a.unbridge(&network_b);
+    expect_event!(event_rx_a, CrustEvent::LostPeer::<PublicId>(id) => assert_eq!(id, id_b));
+    expect_event!(event_rx_b, CrustEvent::LostPeer::<PublicId>(id) => assert_eq!(id, id_a));
+    assert!(!handle_a.is_connected(&handle_b));
+}
+
+#[test]
+#[should_panic(expected = "is in use on both")]
+fn bridge_with_colliding_endpoints() {
+    let min_section_size = 8;
+    let network_a = Network::new(min_section_size, None);
+    let network_b = Network::new(min_section_size, None);
+    let _handle_a = network_a.new_service_handle(None, Some(Endpoint(0)));
+    let _handle_b = network_b.new_service_handle(None, Some(Endpoint(0)));
+    network_a.bridge(&network_b);
+}
+
+#[test]
+#[should_panic(expected = "is already in use on bridged network")]
+fn gen_endpoint_colliding_across_bridge() {
+    let min_section_size = 8;
+    let network_a = Network::new(min_section_size, None);
+    let network_b = Network::new(min_section_size, None);
+    network_a.bridge(&network_b);
+    let _handle_a = network_a.new_service_handle(None, Some(Endpoint(0)));
+    let _handle_b = network_b.new_service_handle(None, Some(Endpoint(0)));
+}
+
+#[test]
+#[should_panic(expected = "is already in use by a live service")]
+fn explicit_endpoint_colliding_with_live_service() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let _handle_0 = network.new_service_handle(None, Some(Endpoint(0)));
+    let _handle_1 = network.new_service_handle(None, Some(Endpoint(0)));
+}
+
+#[test]
+fn explicit_endpoint_reused_after_drop() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle = network.new_service_handle(None, Some(Endpoint(0)));
+    drop(handle);
+    let handle = network.new_service_handle(None, Some(Endpoint(0)));
+    assert_eq!(handle.endpoint(), Endpoint(0));
+}
+
+#[test]
+fn generated_endpoints_skip_reserved_ones() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let snapshot = network.snapshot();
+    let _handle = network.new_service_handle(None, Some(Endpoint(1)));
+    assert_eq!(network.gen_endpoint(Some(Endpoint(3))), Endpoint(3));
+
+    // Even once the endpoint counter is reset, reserved endpoints are not generated again.
+    network.restore(&snapshot);
+    assert_eq!(network.gen_endpoint(None), Endpoint(0));
+    assert_eq!(network.gen_endpoint(None), Endpoint(2));
+    assert_eq!(network.gen_endpoint(None), Endpoint(4));
+}
+
+#[test]
+fn endpoints_allocated_from_reserved_ranges() {
+    let min_section_size = 8;
+    let network = Network::<PublicId>::new(min_section_size, None);
+    let section_a = network.reserve_range(100, 3, "section_a");
+    let section_b = network.reserve_range(2, 2, "section_b");
+    let clients = network.reserve_range(900, 2, "clients");
+
+    // Automatically generated endpoints skip the reserved ranges.
+    let mut handles = Vec::new();
+    for _ in 0..4 {
+        handles.push(network.new_service_handle(None, None));
+    }
+    let auto_endpoints: Vec<_> = handles.iter().map(ServiceHandle::endpoint).collect();
+    assert_eq!(auto_endpoints,
+               vec![Endpoint(0), Endpoint(1), Endpoint(4), Endpoint(5)]);
+
+    // Each range hands out its own endpoints until it is exhausted.
+    for range in &[&section_a, &section_b, &clients] {
+        while let Ok(handle) = range.new_service_handle(None) {
+            handles.push(handle);
+        }
+        assert_eq!(range.gen_endpoint(),
+                   Err(MockError::RangeExhausted(range.label())));
+    }
+    let endpoints: BTreeSet<_> = handles.iter().map(ServiceHandle::endpoint).collect();
+    assert_eq!(endpoints.len(), 4 + 3 + 2 + 2);
+    assert_eq!(endpoints.into_iter().collect::<Vec<_>>(),
+               vec![0, 1, 2, 3, 4, 5, 100, 101, 102, 900, 901]
+                   .into_iter()
+                   .map(Endpoint)
+                   .collect::<Vec<_>>());
+    assert_eq!(network.gen_endpoint(None), Endpoint(6));
+
+    // The labels show up in diagnostics.
+    assert_eq!(network.endpoint_label(Endpoint(101)), Some("section_a"));
+    assert_eq!(network.endpoint_label(Endpoint(900)), Some("clients"));
+    assert_eq!(network.endpoint_label(Endpoint(4)), None);
+    assert_eq!(format!("{:?}", section_a),
+               "EndpointRange(section_a: 100..103, next 103)");
+    let snapshot = format!("{:?}", network.snapshot());
+    for label in &["section_a", "section_b", "clients"] {
+        assert!(snapshot.contains(label));
+    }
+}
+
+#[test]
+#[should_panic(expected = "Endpoint range b overlaps the reserved range a.")]
+fn overlapping_endpoint_ranges() {
+    let min_section_size = 8;
+    let network = Network::<PublicId>::new(min_section_size, None);
+    let _range_a = network.reserve_range(100, 10, "a");
+    let _range_b = network.reserve_range(105, 10, "b");
+}
+
+#[test]
+fn inspect_pending_packets() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let endpoint_0 = network.gen_endpoint(None);
+    let endpoint_1 = network.gen_endpoint(None);
+    let config = Config::with_contacts(&[endpoint_0]);
+
+    let handle_0 = network.new_service_handle(None, Some(endpoint_0));
+    let handle_1 = network.new_service_handle(Some(config), Some(endpoint_1));
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+
+    // Hold both directions, so the handshake can be observed step by step.
+    network.hold_connection(endpoint_1, endpoint_0);
+    network.hold_connection(endpoint_0, endpoint_1);
+    assert_eq!(network.pending_count(), 0);
+
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    assert_eq!(network.pending_packets(endpoint_1, endpoint_0),
+               vec![PacketKind::BootstrapRequest]);
+    assert!(network.pending_packets(endpoint_0, endpoint_1).is_empty());
+    assert!(network.has_pending_between(endpoint_0, endpoint_1));
+    assert_eq!(network.pending_count(), 1);
+
+    // The blocked request is answered with a failure, which stays queued on the held route.
+    network.block_connection(endpoint_1, endpoint_0);
+    network.release_connection(endpoint_1, endpoint_0);
+    network.poll();
+    assert!(network.pending_packets(endpoint_1, endpoint_0).is_empty());
+    assert_eq!(network.pending_packets(endpoint_0, endpoint_1),
+               vec![PacketKind::BootstrapFailure]);
+    assert!(event_rx_0.try_recv().is_err());
+
+    network.release_connection(endpoint_0, endpoint_1);
+    network.poll();
+    assert!(!network.has_pending_between(endpoint_0, endpoint_1));
+    expect_event!(event_rx_1,
+                  CrustEvent::BootstrapAttemptFailed::<PublicId>(_,
+                                                                 BootstrapFailureReason::Blocked));
+    expect_event!(event_rx_1, CrustEvent::BootstrapFailed::<PublicId>);
+}
+
+#[test]
+fn drain_matching_packets() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let endpoint_0 = network.gen_endpoint(None);
+    let endpoint_1 = network.gen_endpoint(None);
+    let config = Config::with_contacts(&[endpoint_0]);
+
+    let handle_0 = network.new_service_handle(None, Some(endpoint_0));
+    let handle_1 = network.new_service_handle(Some(config), Some(endpoint_1));
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    let id_0 = expect_event!(event_rx_1, CrustEvent::BootstrapConnect::<PublicId>(id, _) => id);
+    let id_1 = expect_event!(event_rx_0,
+        CrustEvent::BootstrapAccept::<PublicId>(id, CrustUser::Node) => id);
+
+    // Queue a message in each direction, then drop only the one sent by service_1.
+    network.hold_connection(endpoint_1, endpoint_0);
+    network.hold_connection(endpoint_0, endpoint_1);
+    unwrap!(service_1.send(id_0, vec![1], 0));
+    unwrap!(service_0.send(id_1, vec![2], 0));
+    assert_eq!(network.pending_packets(endpoint_1, endpoint_0),
+               vec![PacketKind::Message]);
+    assert_eq!(network.pending_packets(endpoint_0, endpoint_1),
+               vec![PacketKind::Message]);
+
+    let removed = network.drain_matching(|sender, _, kind| {
+                                             sender == endpoint_1 && kind == PacketKind::Message
+                                         });
+    assert_eq!(removed, 1);
+    assert_eq!(network.pending_count(), 1);
+
+    network.release_connection(endpoint_1, endpoint_0);
+    network.release_connection(endpoint_0, endpoint_1);
+    network.poll();
+    assert_eq!(network.pending_count(), 0);
+    let data = expect_event!(event_rx_1, CrustEvent::NewMessage::<PublicId>(_, data) => data);
+    assert_eq!(data, vec![2]);
+    assert!(event_rx_0.try_recv().is_err());
+}
+
+#[test]
+fn teardown_with_dropped_receivers() {
+    let min_section_size = 8;
+
+    // Each order lists the indices of the services whose event receivers are dropped, in that
+    // order. The services themselves are then dropped in the reverse order.
+    for drop_order in &[[0, 1, 2, 3], [3, 2, 1, 0], [1, 3, 0, 2]] {
+        let network = Network::new(min_section_size, None);
+        let handle_0 = network.new_service_handle(None, None);
+        let config = Config::with_contacts(&[handle_0.endpoint()]);
+
+        let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+        let mut service_0 =
+            unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+        unwrap!(service_0.start_listening_tcp());
+
+        let mut services = vec![Some(service_0)];
+        let mut receivers = vec![Some(event_rx_0)];
+        for _ in 1..4 {
+            let handle = network.new_service_handle(Some(config.clone()), None);
+            let (event_tx, _category_rx, event_rx) = get_event_sender();
+            let mut service =
+                unwrap!(Service::with_handle(&handle, event_tx, *FullId::new().public_id()));
+            unwrap!(service.start_bootstrap(HashSet::new(), CrustUser::Node));
+            services.push(Some(service));
+            receivers.push(Some(event_rx));
+        }
+
+        // Events keep flowing to services whose receivers are gone, without panicking.
+        let id_0 = unwrap!(services[0].as_ref()).id();
+        for &index in drop_order.iter() {
+            receivers[index] = None;
+            if index != 0 {
+                assert!(unwrap!(services[index].as_ref()).disconnect(id_0));
+            }
+        }
+
+        for &index in drop_order.iter().rev() {
+            services[index] = None;
+        }
+    }
+}
+
+#[test]
+fn teardown_in_any_order() {
+    let min_section_size = 8;
+
+    // Indices 0 and 1 stand for the two services, index 2 for the network.
+    for drop_order in &[[2, 0, 1], [2, 1, 0], [0, 1, 2], [1, 0, 2], [0, 2, 1], [1, 2, 0]] {
+        let network = Network::new(min_section_size, None);
+        let handle_0 = network.new_service_handle(None, None);
+        let config = Config::with_contacts(&[handle_0.endpoint()]);
+        let handle_1 = network.new_service_handle(Some(config), None);
+        let endpoint_0 = handle_0.endpoint();
+        let endpoint_1 = handle_1.endpoint();
+
+        let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+        let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+        let mut service_0 =
+            unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+        unwrap!(service_0.start_listening_tcp());
+        let mut service_1 =
+            unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+        unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+        let id_0 =
+            expect_event!(event_rx_1, CrustEvent::BootstrapConnect::<PublicId>(id, _) => id);
+        let id_1 =
+            expect_event!(event_rx_0, CrustEvent::BootstrapAccept::<PublicId>(id, _) => id);
+
+        // Leave packets pending in both directions.
+        network.hold_connection(endpoint_0, endpoint_1);
+        network.hold_connection(endpoint_1, endpoint_0);
+        unwrap!(service_0.send(id_1, vec![0], 0));
+        unwrap!(service_1.send(id_0, vec![1], 0));
+        assert_eq!(network.pending_packets(endpoint_0, endpoint_1).len(), 1);
+
+        let weak_services = [Rc::downgrade(&handle_0.0), Rc::downgrade(&handle_1.0)];
+        let mut services = vec![Some((service_0, handle_0)), Some((service_1, handle_1))];
+        let mut network = Some(network);
+        for &index in drop_order.iter() {
+            if index == 2 {
+                let _ = network.take();
+                for &(_, ref handle) in services.iter().filter_map(Option::as_ref) {
+                    assert!(handle.0.borrow().network.is_closed());
+                }
+            } else {
+                services[index] = None;
+                assert!(weak_services[index].upgrade().is_none());
+            }
+        }
+    }
+}
+
+#[test]
+fn bootstrap_refused_by_policy() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let endpoint_0 = network.gen_endpoint(None);
+    let config = Config::with_contacts(&[endpoint_0]);
+
+    let handle_0 = network.new_service_handle(None, Some(endpoint_0));
+    handle_0.set_accept_bootstrap(BootstrapPolicy::NodesOnly);
+    let handle_1 = network.new_service_handle(Some(config.clone()), None);
+    let handle_2 = network.new_service_handle(Some(config), None);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+    let (event_tx_2, _category_rx_2, event_rx_2) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+
+    // A client is refused, and told why.
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Client));
+    expect_event!(event_rx_1,
+                  CrustEvent::BootstrapRefused::<PublicId>(_, CrustUser::Client));
+    expect_event!(event_rx_1,
+                  CrustEvent::BootstrapAttemptFailed::<PublicId>(
+                      _, BootstrapFailureReason::Refused(CrustUser::Client)));
+    expect_event!(event_rx_1, CrustEvent::BootstrapFailed::<PublicId>);
+    assert!(event_rx_0.try_recv().is_err());
+
+    // A node is accepted.
+    let mut service_2 =
+        unwrap!(Service::with_handle(&handle_2, event_tx_2, *FullId::new().public_id()));
+    unwrap!(service_2.start_bootstrap(HashSet::new(), CrustUser::Node));
+    expect_event!(event_rx_2, CrustEvent::BootstrapConnect::<PublicId>(..));
+    expect_event!(event_rx_0,
+                  CrustEvent::BootstrapAccept::<PublicId>(_, CrustUser::Node));
+}
+
+#[test]
+fn whitelist_rejects_other_peers() {
+    const PREPARE_CI_TOKEN: u32 = 1;
+
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let endpoint_0 = network.gen_endpoint(None);
+    let config = Config::with_contacts(&[endpoint_0]);
+
+    let handle_0 = network.new_service_handle(None, Some(endpoint_0));
+    let handle_1 = network.new_service_handle(Some(config.clone()), None);
+    let handle_2 = network.new_service_handle(Some(config), None);
+    let handle_3 = network.new_service_handle(None, None);
+    assert!(handle_0.whitelisted_endpoints().is_empty());
+    handle_0.whitelist_peer(handle_2.endpoint());
+    assert_eq!(handle_0.whitelisted_endpoints(), vec![handle_2.endpoint()]);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+    let (event_tx_2, _category_rx_2, event_rx_2) = get_event_sender();
+    let (event_tx_3, _category_rx_3, event_rx_3) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+
+    // A peer which isn't whitelisted can't bootstrap.
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    expect_event!(event_rx_1,
+                  CrustEvent::BootstrapAttemptFailed::<PublicId>(
+                      _, BootstrapFailureReason::NotWhitelisted));
+    expect_event!(event_rx_1, CrustEvent::BootstrapFailed::<PublicId>);
+    assert!(event_rx_0.try_recv().is_err());
+
+    // Nor connect.
+    let service_3 =
+        unwrap!(Service::with_handle(&handle_3, event_tx_3, *FullId::new().public_id()));
+    service_0.prepare_connection_info(PREPARE_CI_TOKEN);
+    let our_ci_0 = expect_event!(event_rx_0,
+                                 CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        unwrap!(cir.result)
+    });
+    service_3.prepare_connection_info(PREPARE_CI_TOKEN);
+    let our_ci_3 = expect_event!(event_rx_3,
+                                 CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        unwrap!(cir.result)
+    });
+    unwrap!(service_3.connect(our_ci_3, our_ci_0.to_pub_connection_info()));
+    expect_event!(event_rx_3, CrustEvent::ConnectFailure::<PublicId>(_));
+    assert!(event_rx_0.try_recv().is_err());
+    assert!(!handle_3.is_connected(&handle_0));
+
+    // A whitelisted peer is accepted.
+    let mut service_2 =
+        unwrap!(Service::with_handle(&handle_2, event_tx_2, *FullId::new().public_id()));
+    unwrap!(service_2.start_bootstrap(HashSet::new(), CrustUser::Node));
+    expect_event!(event_rx_2, CrustEvent::BootstrapConnect::<PublicId>(..));
+    expect_event!(event_rx_0,
+                  CrustEvent::BootstrapAccept::<PublicId>(_, CrustUser::Node));
+}
+
+#[test]
+fn empty_whitelist_allows_all() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let endpoint_0 = network.gen_endpoint(None);
+    let config = Config::with_contacts(&[endpoint_0]);
+
+    let handle_0 = network.new_service_handle(None, Some(endpoint_0));
+    let handle_1 = network.new_service_handle(Some(config), None);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+
+    let uid_1 = *FullId::new().public_id();
+    let mut service_1 = unwrap!(Service::with_handle(&handle_1, event_tx_1, uid_1));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    expect_event!(event_rx_1, CrustEvent::BootstrapConnect::<PublicId>(..));
+    expect_event!(event_rx_0,
+                  CrustEvent::BootstrapAccept::<PublicId>(_, CrustUser::Node));
+    assert!(service_0.is_peer_whitelisted(&uid_1));
+}
+
+#[test]
+fn connection_limit() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let endpoint_0 = network.gen_endpoint(None);
+    let config = Config::with_contacts(&[endpoint_0]);
+
+    let handle_0 = network.new_service_handle(Some(Config::new().with_max_connections(2)),
+                                              Some(endpoint_0));
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+
+    let mut handles = Vec::new();
+    let mut services = Vec::new();
+    let mut event_rxs = Vec::new();
+    for _ in 0..3 {
+        let handle = network.new_service_handle(Some(config.clone()), None);
+        let (event_tx, _category_rx, event_rx) = get_event_sender();
+        let mut service =
+            unwrap!(Service::with_handle(&handle, event_tx, *FullId::new().public_id()));
+        unwrap!(service.start_bootstrap(HashSet::new(), CrustUser::Node));
+        handles.push(handle);
+        services.push(service);
+        event_rxs.push(event_rx);
+    }
+
+    // The first two peers are accepted.
+    for event_rx in &event_rxs[..2] {
+        expect_event!(event_rx, CrustEvent::BootstrapConnect::<PublicId>(..));
+    }
+    for _ in 0..2 {
+        expect_event!(event_rx_0,
+                      CrustEvent::BootstrapAccept::<PublicId>(_, CrustUser::Node));
+    }
+
+    // The third one is refused.
+    expect_event!(event_rxs[2],
+                  CrustEvent::BootstrapAttemptFailed::<PublicId>(
+                      _, BootstrapFailureReason::ConnectionLimit));
+    expect_event!(event_rxs[2], CrustEvent::BootstrapFailed::<PublicId>);
+    assert!(event_rx_0.try_recv().is_err());
+
+    assert_eq!(handle_0.connection_count(), 2);
+    assert!(handle_0.is_connected(&handles[0]));
+    assert!(handle_0.is_connected(&handles[1]));
+    assert!(!handle_0.is_connected(&handles[2]));
+}
+
+#[test]
+fn connection_graph() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let endpoint_0 = network.gen_endpoint(None);
+    let config = Config::with_contacts(&[endpoint_0]);
+
+    let handle_0 = network.new_service_handle(None, Some(endpoint_0));
+    let handle_1 = network.new_service_handle(Some(config.clone()), None);
+    let handle_2 = network.new_service_handle(Some(config), None);
+    let endpoint_1 = handle_1.endpoint();
+    let endpoint_2 = handle_2.endpoint();
+
+    let (event_tx_0, _category_rx_0, _event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+    let (event_tx_2, _category_rx_2, event_rx_2) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    assert!(network.connection_graph().is_empty());
+
+    // Both bootstrap off service_0.
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    let mut service_2 =
+        unwrap!(Service::with_handle(&handle_2, event_tx_2, *FullId::new().public_id()));
+    unwrap!(service_2.start_bootstrap(HashSet::new(), CrustUser::Node));
+
+    assert_eq!(network.connection_graph(),
+               vec![(endpoint_0, endpoint_1), (endpoint_0, endpoint_2)]);
+    assert_eq!(handle_0.connection_count(), 2);
+    assert_eq!(handle_1.connected_uids(), vec![service_0.id()]);
+    assert_eq!(handle_1.connected_endpoints(), vec![endpoint_0]);
+    assert!(handle_2.is_connected_to_endpoint(endpoint_0));
+    assert!(!handle_2.is_connected_to_endpoint(endpoint_1));
+
+    // Connect service_1 and service_2 directly.
+    while event_rx_1.try_recv().is_ok() {}
+    while event_rx_2.try_recv().is_ok() {}
+    service_1.prepare_connection_info(0);
+    let our_ci_1 = expect_event!(event_rx_1,
+                                 CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        unwrap!(cir.result)
+    });
+    service_2.prepare_connection_info(0);
+    let our_ci_2 = expect_event!(event_rx_2,
+                                 CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        unwrap!(cir.result)
+    });
+    let their_ci_1 = our_ci_1.to_pub_connection_info();
+    let their_ci_2 = our_ci_2.to_pub_connection_info();
+    unwrap!(service_1.connect(our_ci_1, their_ci_2));
+    unwrap!(service_2.connect(our_ci_2, their_ci_1));
+    assert_eq!(network.connection_graph(),
+               vec![(endpoint_0, endpoint_1), (endpoint_0, endpoint_2), (endpoint_1, endpoint_2)]);
+
+    // Disconnect service_1 from service_0.
+    assert!(service_1.disconnect(service_0.id()));
+    assert_eq!(network.connection_graph(),
+               vec![(endpoint_0, endpoint_2), (endpoint_1, endpoint_2)]);
+
+    // Dead services are excluded.
+    drop(service_2);
+    drop(handle_2);
+    assert!(network.connection_graph().is_empty());
+    assert_eq!(handle_0.connection_count(), 0);
+}
+
+// Starts two services, with the second one bootstrapping off the first.
+fn start_two_connected_services(network: &Network<PublicId>)
+                                -> (ServiceHandle<PublicId>,
+                                    Service<PublicId>,
+                                    ServiceHandle<PublicId>,
+                                    Service<PublicId>) {
+    let endpoint_0 = network.gen_endpoint(None);
+    let config = Config::with_contacts(&[endpoint_0]);
+    let handle_0 = network.new_service_handle(None, Some(endpoint_0));
+    let handle_1 = network.new_service_handle(Some(config), None);
+
+    let (event_tx_0, _category_rx_0, _event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, _event_rx_1) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    (handle_0, service_0, handle_1, service_1)
+}
+
+#[test]
+fn list_and_find_services() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    assert!(network.endpoints().is_empty());
+
+    let (handle_0, _service_0, handle_1, service_1) = start_two_connected_services(&network);
+    let endpoint_0 = handle_0.endpoint();
+    let endpoint_1 = handle_1.endpoint();
+    assert_eq!(network.endpoints(), vec![endpoint_0, endpoint_1]);
+    assert_eq!(unwrap!(network.find_handle(endpoint_1)).endpoint(), endpoint_1);
+    assert!(unwrap!(network.find_handle(endpoint_0)).is_connected(&handle_1));
+    network.assert_connectivity_symmetric();
+
+    // Dead services are pruned.
+    drop(service_1);
+    drop(handle_1);
+    assert_eq!(network.endpoints(), vec![endpoint_0]);
+    assert!(network.find_handle(endpoint_1).is_none());
+    network.assert_connectivity_symmetric();
+}
+
+#[test]
+fn unstarted_peer_is_not_connected() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let handle_1 = network.new_service_handle(None, None);
+    assert!(!handle_0.is_connected(&handle_1));
+    assert!(handle_0.connected_uids().is_empty());
+    assert!(handle_0.connected_endpoints().is_empty());
+    assert_eq!(handle_0.connection_count(), 0);
+
+    // Only the peer has been started.
+    let (event_tx_1, _category_rx_1, _event_rx_1) = get_event_sender();
+    let _service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    assert!(!handle_0.is_connected(&handle_1));
+    assert!(!handle_1.is_connected(&handle_0));
+}
+
+#[test]
+#[should_panic(expected = "Endpoint(1) is connected to Endpoint(0), but not vice versa.")]
+fn asymmetric_connectivity() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let (handle_0, _service_0, handle_1, _service_1) = start_two_connected_services(&network);
+    network.assert_connectivity_symmetric();
+
+    let _ = handle_0.0.borrow_mut().remove_connection_by_endpoint(handle_1.endpoint());
+    network.assert_connectivity_symmetric();
+}
+
+#[test]
+fn snapshot_and_restore() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let endpoint_0 = network.gen_endpoint(None);
+    let config = Config::with_contacts(&[endpoint_0]);
+
+    let handle_0 = network.new_service_handle(None, Some(endpoint_0));
+    let handle_1 = network.new_service_handle(Some(config), None);
+    let endpoint_1 = handle_1.endpoint();
+
+    let (event_tx_0, _category_rx_0, _event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, _event_rx_1) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+
+    // Leave a message queued and a connection blocked.
+    network.hold_connection(endpoint_1, endpoint_0);
+    unwrap!(service_1.send(service_0.id(), vec![1, 2, 3], 0));
+    network.block_connection(endpoint_0, endpoint_1);
+
+    let snapshot = network.snapshot();
+    // Taking a snapshot reseeds the RNG, so take a second one to compare against after restoring.
+    let expected = network.snapshot();
+    network.restore(&snapshot);
+    let expected_random: u64 = network.new_rng().gen();
+
+    // Change the queue, the connection states and the services' connections.
+    network.restore(&snapshot);
+    unwrap!(service_1.send(service_0.id(), vec![4, 5, 6], 0));
+    network.unblock_connection(endpoint_0, endpoint_1);
+    network.release_connection(endpoint_1, endpoint_0);
+    network.delay_connection(endpoint_1, endpoint_0);
+    network.poll();
+    assert!(service_1.disconnect(service_0.id()));
+    assert!(!handle_1.is_connected_to_endpoint(endpoint_0));
+
+    network.restore(&snapshot);
+    assert_eq!(network.pending_packets(endpoint_1, endpoint_0),
+               vec![PacketKind::Message]);
+    assert!(handle_1.is_connected_to_endpoint(endpoint_0));
+    assert_eq!(network.new_rng().gen::<u64>(), expected_random);
+
+    network.restore(&snapshot);
+    assert_eq!(network.snapshot(), expected);
+}
+
+#[test]
+fn idle_connections_expire() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let config = Config::with_contacts(&[handle_0.endpoint()]);
+    let handle_1 = network.new_service_handle(Some(config), None);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    assert!(handle_0.is_connected(&handle_1));
+    while event_rx_0.try_recv().is_ok() {}
+    while event_rx_1.try_recv().is_ok() {}
+
+    let idle_timeout = 3;
+    network.set_idle_timeout(Some(idle_timeout));
+    for _ in 1..idle_timeout {
+        network.poll();
+    }
+    assert!(handle_0.is_connected(&handle_1));
+
+    // Traffic resets the idle count.
+    unwrap!(service_1.send(service_0.id(), vec![1, 2, 3], 0));
+    expect_event!(event_rx_0, CrustEvent::NewMessage::<PublicId>(..));
+    for _ in 1..idle_timeout {
+        network.poll();
+    }
+    assert!(handle_0.is_connected(&handle_1));
+
+    network.poll();
+    assert!(!handle_0.is_connected(&handle_1));
+    assert!(!handle_1.is_connected(&handle_0));
+    expect_event!(event_rx_0, CrustEvent::LostPeer::<PublicId>(id) => {
+        assert_eq!(id, service_1.id())
+    });
+    expect_event!(event_rx_1, CrustEvent::LostPeer::<PublicId>(id) => {
+        assert_eq!(id, service_0.id())
+    });
+}
+
+#[test]
+fn keepalives_prevent_idle_expiry() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let config = Config::with_contacts(&[handle_0.endpoint()]);
+    let handle_1 = network.new_service_handle(Some(config), None);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    while event_rx_0.try_recv().is_ok() {}
+    while event_rx_1.try_recv().is_ok() {}
+
+    let idle_timeout = 3;
+    network.set_idle_timeout(Some(idle_timeout));
+    handle_1.enable_keepalive(idle_timeout - 1);
+    let _ = network.reset_message_sent();
+    for _ in 0..10 * idle_timeout {
+        network.poll();
+    }
+    assert!(handle_0.is_connected(&handle_1));
+    // Keep-alives neither raise events nor count as sent messages.
+    assert!(event_rx_0.try_recv().is_err());
+    assert!(event_rx_1.try_recv().is_err());
+    assert!(!network.reset_message_sent());
+
+    handle_1.disable_keepalive();
+    for _ in 0..idle_timeout {
+        network.poll();
+    }
+    assert!(!handle_0.is_connected(&handle_1));
+}
+
+#[test]
+fn blocked_disconnect_packets() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let config = Config::with_contacts(&[handle_0.endpoint()]);
+    let handle_1 = network.new_service_handle(Some(config), None);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    while event_rx_0.try_recv().is_ok() {}
+    while event_rx_1.try_recv().is_ok() {}
+
+    // The disconnect never arrives, so the far side still believes the connection is alive.
+    network.block_packet_kind(handle_1.endpoint(),
+                              handle_0.endpoint(),
+                              PacketKindMask::disconnect());
+    assert!(service_1.disconnect(service_0.id()));
+    assert!(!handle_1.is_connected(&handle_0));
+    assert!(handle_0.is_connected(&handle_1));
+    assert!(event_rx_0.try_recv().is_err());
+
+    // Until the idle connection is dropped.
+    let idle_timeout = 3;
+    network.set_idle_timeout(Some(idle_timeout));
+    for _ in 1..idle_timeout {
+        network.poll();
+    }
+    assert!(handle_0.is_connected(&handle_1));
+    network.poll();
+    assert!(!handle_0.is_connected(&handle_1));
+    expect_event!(event_rx_0, CrustEvent::LostPeer::<PublicId>(id) => {
+        assert_eq!(id, service_1.id())
+    });
+}
+
+#[test]
+fn blocked_message_packets() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    network.enable_send_confirmations(true);
+    let handle_0 = network.new_service_handle(None, None);
+    let config = Config::with_contacts(&[handle_0.endpoint()]);
+    let handle_1 = network.new_service_handle(Some(config), None);
+    network.block_packet_kind(handle_1.endpoint(),
+                              handle_0.endpoint(),
+                              PacketKindMask::message());
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    // The handshake still completes.
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    assert!(handle_0.is_connected(&handle_1));
+    assert!(handle_1.is_connected(&handle_0));
+    while event_rx_0.try_recv().is_ok() {}
+    while event_rx_1.try_recv().is_ok() {}
+
+    // But messages in the blocked direction fail.
+    let msg_id = unwrap!(service_1.send_with_id(service_0.id(), vec![0, 1, 2], 0));
+    assert!(event_rx_0.try_recv().is_err());
+    expect_event!(event_rx_1, CrustEvent::MessageFailed::<PublicId>(id, failed_id) => {
+        assert_eq!(id, service_0.id());
+        assert_eq!(failed_id, msg_id);
+    });
+
+    // While those in the other direction, and after unblocking, are delivered.
+    unwrap!(service_0.send(service_1.id(), vec![3, 4, 5], 0));
+    expect_event!(event_rx_1, CrustEvent::NewMessage::<PublicId>(..));
+    expect_event!(event_rx_0, CrustEvent::MessageDelivered::<PublicId>(..));
+    network.unblock_packet_kind(handle_1.endpoint(),
+                                handle_0.endpoint(),
+                                PacketKindMask::message());
+    unwrap!(service_1.send(service_0.id(), vec![6, 7, 8], 0));
+    expect_event!(event_rx_0, CrustEvent::NewMessage::<PublicId>(..));
+}
+
+#[test]
+fn lossy_connections() {
+    let min_section_size = 8;
+
+    // With a loss probability of 1, a bootstrap attempt fails just like over a blocked route.
+    let bootstrap_events = |lose: bool| {
+        let network = Network::new(min_section_size, None);
+        let handle_0 = network.new_service_handle(None, None);
+        let config = Config::with_contacts(&[handle_0.endpoint()]);
+        let handle_1 = network.new_service_handle(Some(config), None);
+        if lose {
+            network.set_packet_loss(handle_1.endpoint(), handle_0.endpoint(), 1.0);
+        } else {
+            network.block_connection(handle_1.endpoint(), handle_0.endpoint());
+        }
+
+        let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+        let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+        let mut service_0 =
+            unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+        unwrap!(service_0.start_listening_tcp());
+        let mut service_1 =
+            unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+        unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+        assert!(!handle_0.is_connected(&handle_1));
+        event_rx_0
+            .try_iter()
+            .chain(event_rx_1.try_iter())
+            .map(|event| format!("{:?}", event))
+            .collect::<Vec<_>>()
+    };
+    let lost_events = bootstrap_events(true);
+    assert!(lost_events
+                .iter()
+                .any(|event| event.contains("BootstrapAttemptFailed")));
+    assert_eq!(lost_events, bootstrap_events(false));
+
+    // Messages are lost without a trace, but still count as sent.
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let config = Config::with_contacts(&[handle_0.endpoint()]);
+    let handle_1 = network.new_service_handle(Some(config), None);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    while event_rx_0.try_recv().is_ok() {}
+    while event_rx_1.try_recv().is_ok() {}
+    let _ = network.reset_message_sent();
+
+    network.set_packet_loss(handle_1.endpoint(), handle_0.endpoint(), 1.0);
+    unwrap!(service_1.send(service_0.id(), vec![0, 1, 2], 0));
+    assert!(network.reset_message_sent());
+    assert!(event_rx_0.try_recv().is_err());
+    assert!(event_rx_1.try_recv().is_err());
+
+    // With a lower probability, only some of them are lost.
+    network.set_packet_loss(handle_1.endpoint(), handle_0.endpoint(), 0.5);
+    for i in 0..100 {
+        unwrap!(service_1.send(service_0.id(), vec![i], 0));
+    }
+    let delivered = event_rx_0.try_iter().count();
+    assert!(delivered > 0 && delivered < 100);
+
+    network.clear_packet_loss(handle_1.endpoint(), handle_0.endpoint());
+    unwrap!(service_1.send(service_0.id(), vec![3, 4, 5], 0));
+    expect_event!(event_rx_0, CrustEvent::NewMessage::<PublicId>(..));
+}
+
+#[test]
+fn duplicated_messages() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let config = Config::with_contacts(&[handle_0.endpoint()]);
+    let handle_1 = network.new_service_handle(Some(config), None);
+    network.set_duplication(handle_1.endpoint(), handle_0.endpoint(), 1.0);
+
+    // The handshake isn't duplicated.
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    expect_event!(event_rx_0, CrustEvent::BootstrapAccept::<PublicId>(..));
+    expect_event!(event_rx_1, CrustEvent::BootstrapConnect::<PublicId>(..));
+    assert!(event_rx_0.try_recv().is_err());
+
+    // A message arrives twice, but not more often.
+    unwrap!(service_1.send(service_0.id(), vec![0, 1, 2], 0));
+    network.poll();
+    for _ in 0..2 {
+        expect_event!(event_rx_0, CrustEvent::NewMessage::<PublicId>(id, msg) => {
+            assert_eq!(id, service_1.id());
+            assert_eq!(msg, vec![0, 1, 2]);
+        });
+    }
+    assert!(event_rx_0.try_recv().is_err());
+
+    // Only messages on the configured route are duplicated.
+    unwrap!(service_0.send(service_1.id(), vec![3], 0));
+    expect_event!(event_rx_1, CrustEvent::NewMessage::<PublicId>(..));
+    assert!(event_rx_1.try_recv().is_err());
+
+    // With a lower probability, only some of them are.
+    network.set_duplication(handle_1.endpoint(), handle_0.endpoint(), 0.5);
+    for i in 0..100 {
+        unwrap!(service_1.send(service_0.id(), vec![i], 0));
+    }
+    let delivered = event_rx_0.try_iter().count();
+    assert!(delivered > 100 && delivered < 200);
+
+    network.clear_duplication(handle_1.endpoint(), handle_0.endpoint());
+    unwrap!(service_1.send(service_0.id(), vec![4, 5, 6], 0));
+    expect_event!(event_rx_0, CrustEvent::NewMessage::<PublicId>(..));
+    assert!(event_rx_0.try_recv().is_err());
+}
+
+#[test]
+fn reordered_messages() {
+    let min_section_size = 8;
+    let sent = vec![vec![0], vec![1], vec![2]];
+
+    // Returns the order in which the messages arrive over a reordering route, with the network
+    // seeded with `seed`.
+    let received = |seed: u32, reorder: bool| {
+        let network = Network::new(min_section_size, Some([seed, 1, 2, 3]));
+        let handle_0 = network.new_service_handle(None, None);
+        let config = Config::with_contacts(&[handle_0.endpoint()]);
+        let handle_1 = network.new_service_handle(Some(config), None);
+        // The handshake still works, as only messages are reordered.
+        network.set_reordering(handle_1.endpoint(), handle_0.endpoint(), reorder);
+
+        let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+        let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+        let mut service_0 =
+            unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+        unwrap!(service_0.start_listening_tcp());
+        expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+        let mut service_1 =
+            unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+        unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+        expect_event!(event_rx_0, CrustEvent::BootstrapAccept::<PublicId>(..));
+        expect_event!(event_rx_1, CrustEvent::BootstrapConnect::<PublicId>(..));
+
+        network.hold_connection(handle_1.endpoint(), handle_0.endpoint());
+        for data in &sent {
+            unwrap!(service_1.send(service_0.id(), data.clone(), 0));
+        }
+        network.release_connection(handle_1.endpoint(), handle_0.endpoint());
+        network.poll();
+        event_rx_0
+            .try_iter()
+            .map(|event| match event {
+                     CrustEvent::NewMessage(_, data) => data,
+                     event => panic!("Unexpected event {:?}", event),
+                 })
+            .collect::<Vec<_>>()
+    };
+
+    // Each message arrives once, and for some seeds not in the order they were sent.
+    let mut reordered = false;
+    for seed in 0..8 {
+        let mut order = received(seed, true);
+        reordered = reordered || order != sent;
+        order.sort();
+        assert_eq!(order, sent);
+        assert_eq!(received(seed, false), sent);
+    }
+    assert!(reordered);
+}
+
+#[test]
+fn partitioned_network() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let config = Config::with_contacts(&[handle_0.endpoint()]);
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+
+    // Five more services, all connected to the first one.
+    let mut handles = vec![handle_0];
+    let mut services = Vec::new();
+    let mut receivers = vec![event_rx_0];
+    for _ in 1..6 {
+        let handle = network.new_service_handle(Some(config.clone()), None);
+        let (event_tx, _category_rx, event_rx) = get_event_sender();
+        let mut service =
+            unwrap!(Service::with_handle(&handle, event_tx, *FullId::new().public_id()));
+        unwrap!(service.start_bootstrap(HashSet::new(), CrustUser::Node));
+        assert!(handle.is_connected(&handles[0]));
+        handles.push(handle);
+        services.push(service);
+        receivers.push(event_rx);
+    }
+    for event_rx in &receivers {
+        while event_rx.try_recv().is_ok() {}
+    }
+
+    let endpoints: Vec<_> = handles.iter().map(ServiceHandle::endpoint).collect();
+    let (group_a, group_b) = endpoints.split_at(3);
+
+    // Packets already on their way across the cut are dropped.
+    network.hold_connection(endpoints[0], endpoints[3]);
+    unwrap!(service_0.send(services[2].id(), vec![0], 0));
+    network.partition(group_a, group_b);
+    assert!(network.is_partitioned(endpoints[0], endpoints[3]));
+    assert!(network.is_partitioned(endpoints[5], endpoints[2]));
+    assert!(!network.is_partitioned(endpoints[0], endpoints[1]));
+    assert!(network.pending_packets(endpoints[0], endpoints[3]).is_empty());
+    network.release_connection(endpoints[0], endpoints[3]);
+
+    // Messages within a group are delivered, those across the cut aren't.
+    unwrap!(service_0.send(services[0].id(), vec![1], 0));
+    expect_event!(receivers[1], CrustEvent::NewMessage::<PublicId>(..));
+    unwrap!(service_0.send(services[2].id(), vec![2], 0));
+    unwrap!(services[3].send(service_0.id(), vec![3], 0));
+    assert!(receivers[3].try_recv().is_err());
+    assert!(receivers[0].try_recv().is_err());
+
+    network.heal_partition(group_a, group_b);
+    assert!(!network.is_partitioned(endpoints[0], endpoints[3]));
+    unwrap!(service_0.send(services[2].id(), vec![4], 0));
+    expect_event!(receivers[3], CrustEvent::NewMessage::<PublicId>(_, data) => {
+        assert_eq!(data, vec![4])
+    });
+    unwrap!(services[3].send(service_0.id(), vec![5], 0));
+    expect_event!(receivers[0], CrustEvent::NewMessage::<PublicId>(..));
+}
+
+#[test]
+fn traffic_accounting() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let config = Config::with_contacts(&[handle_0.endpoint()]);
+    let handle_1 = network.new_service_handle(Some(config), None);
+    let (endpoint_0, endpoint_1) = (handle_0.endpoint(), handle_1.endpoint());
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    while event_rx_0.try_recv().is_ok() {}
+    while event_rx_1.try_recv().is_ok() {}
+
+    // The handshake consists of control packets only.
+    let (sent, received) = network.total_traffic();
+    assert_eq!(sent, received);
+    assert!(sent > 0);
+    assert_eq!(sent % CONTROL_PACKET_SIZE, 0);
+
+    network.reset_traffic_stats();
+    assert_eq!(network.total_traffic(), (0, 0));
+
+    // Messages count with the length of their payload.
+    unwrap!(service_1.send(service_0.id(), vec![0; 1000], 0));
+    expect_event!(event_rx_0, CrustEvent::NewMessage::<PublicId>(..));
+    assert_eq!(network.bytes_sent(endpoint_1), 1000);
+    assert_eq!(network.bytes_received(endpoint_0), 1000);
+    assert_eq!(network.bytes_sent(endpoint_0), 0);
+    assert_eq!(network.bytes_received(endpoint_1), 0);
+    assert_eq!(network.total_traffic(), (1000, 1000));
+
+    // Packets which never reach their receiver aren't counted.
+    network.partition(&[endpoint_0], &[endpoint_1]);
+    unwrap!(service_1.send(service_0.id(), vec![0; 500], 0));
+    assert!(event_rx_0.try_recv().is_err());
+    assert_eq!(network.total_traffic(), (1000, 1000));
+}
+
+#[test]
+fn packet_observer() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let config = Config::with_contacts(&[handle_0.endpoint()]);
+    let handle_1 = network.new_service_handle(Some(config.clone()), None);
+    let handle_2 = network.new_service_handle(Some(config), None);
+    let (endpoint_0, endpoint_1, endpoint_2) =
+        (handle_0.endpoint(), handle_1.endpoint(), handle_2.endpoint());
+
+    // The observer may query the network while it runs.
+    let observed = Rc::new(RefCell::new(Vec::new()));
+    let observed_clone = observed.clone();
+    let network_clone = network.clone();
+    network.set_packet_observer(move |packet: &ObservedPacket| {
+        assert_eq!(network_clone.min_section_size(), min_section_size);
+        observed_clone
+            .borrow_mut()
+            .push((packet.sender,
+                   packet.receiver,
+                   packet.kind,
+                   packet.payload.map(|payload| payload.to_vec()),
+                   packet.delivery));
+    });
+
+    let (event_tx_0, _category_rx_0, _event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, _event_rx_1) = get_event_sender();
+    let (event_tx_2, _category_rx_2, event_rx_2) = get_event_sender();
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    unwrap!(service_1.send(service_0.id(), vec![1, 2, 3], 0));
+    network.poll();
+
+    // A bootstrap attempt over a blocked connection is seen as well.
+    network.block_connection(endpoint_2, endpoint_0);
+    let mut service_2 =
+        unwrap!(Service::with_handle(&handle_2, event_tx_2, *FullId::new().public_id()));
+    unwrap!(service_2.start_bootstrap(HashSet::new(), CrustUser::Node));
+    expect_event!(event_rx_2, CrustEvent::BootstrapFailed::<PublicId>);
+
+    assert_eq!(*observed.borrow(),
+               vec![(endpoint_1,
+                     endpoint_0,
+                     PacketKind::BootstrapRequest,
+                     None,
+                     Delivery::Delivered),
+                    (endpoint_0,
+                     endpoint_1,
+                     PacketKind::BootstrapSuccess,
+                     None,
+                     Delivery::Delivered),
+                    (endpoint_1,
+                     endpoint_0,
+                     PacketKind::Message,
+                     Some(vec![1, 2, 3]),
+                     Delivery::Delivered),
+                    (endpoint_2,
+                     endpoint_0,
+                     PacketKind::BootstrapRequest,
+                     None,
+                     Delivery::Blocked),
+                    (endpoint_0,
+                     endpoint_2,
+                     PacketKind::BootstrapFailure,
+                     None,
+                     Delivery::Delivered)]);
+
+    // Once removed, the observer sees nothing more and no longer keeps the network open.
+    network.clear_packet_observer();
+    unwrap!(service_1.send(service_0.id(), vec![4], 0));
+    network.poll();
+    assert_eq!(observed.borrow().len(), 5);
+}
+
 #[test]
 fn unidirectional_rendezvous_connect() {
     const PREPARE_CI_TOKEN: u32 = 1;
@@ -187,32 +1526,311 @@ fn unidirectional_rendezvous_connect() {
     let min_section_size = 8;
     let network = Network::new(min_section_size, None);
     let handle0 = network.new_service_handle(None, None);
-    let handle1 = network.new_service_handle(None, None);
+    let handle1 = network.new_service_handle(None, None);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let service_0 = unwrap!(Service::with_handle(&handle0, event_tx_0, *FullId::new().public_id()));
+    let service_1 = unwrap!(Service::with_handle(&handle1, event_tx_1, *FullId::new().public_id()));
+
+    service_0.prepare_connection_info(PREPARE_CI_TOKEN);
+    let our_ci_0 = expect_event!(event_rx_0,
+                                 CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        unwrap!(cir.result)
+    });
+
+    service_1.prepare_connection_info(PREPARE_CI_TOKEN);
+    let our_ci_1 = expect_event!(event_rx_1,
+                                 CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        unwrap!(cir.result)
+    });
+
+    let their_ci_1 = our_ci_1.to_pub_connection_info();
+
+    unwrap!(service_0.connect(our_ci_0, their_ci_1));
+
+    expect_event!(event_rx_0, CrustEvent::ConnectSuccess::<PublicId>(_));
+    expect_event!(event_rx_1, CrustEvent::ConnectSuccess::<PublicId>(_));
+}
+
+#[test]
+fn single_step_polling() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let config = Config::with_contacts(&[handle_0.endpoint()]);
+    let handle_1 = network.new_service_handle(Some(config), None);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+
+    // Keep the bootstrap request queued until we are ready to step through the handshake.
+    network.hold_connection(handle_1.endpoint(), handle_0.endpoint());
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    network.release_connection(handle_1.endpoint(), handle_0.endpoint());
+    assert_eq!(network.pending_packets(handle_1.endpoint(), handle_0.endpoint()),
+               vec![PacketKind::BootstrapRequest]);
+
+    // The request is accepted, but the response hasn't arrived yet.
+    assert!(network.poll_once());
+    assert!(handle_0.is_connected_to_endpoint(handle_1.endpoint()));
+    assert!(!handle_1.is_connected_to_endpoint(handle_0.endpoint()));
+    expect_event!(event_rx_0, CrustEvent::BootstrapAccept::<PublicId>(..));
+    assert!(event_rx_1.try_recv().is_err());
+    assert_eq!(network.pending_packets(handle_0.endpoint(), handle_1.endpoint()),
+               vec![PacketKind::BootstrapSuccess]);
+
+    assert_eq!(network.poll_n(5), 1);
+    assert!(handle_1.is_connected_to_endpoint(handle_0.endpoint()));
+    expect_event!(event_rx_1, CrustEvent::BootstrapConnect::<PublicId>(..));
+    assert!(!network.poll_once());
+    assert_eq!(network.poll_n(5), 0);
+}
+
+#[test]
+#[should_panic(expected = "Endpoint(1) -> Endpoint(0): 51 packets")]
+fn poll_limit_names_looping_route() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let config = Config::with_contacts(&[handle_0.endpoint()]);
+    let handle_1 = network.new_service_handle(Some(config), None);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    expect_event!(event_rx_0, CrustEvent::BootstrapAccept::<PublicId>(..));
+    expect_event!(event_rx_1, CrustEvent::BootstrapConnect::<PublicId>(..));
+
+    // Polls which finish within the limit return the number of packets processed.
+    unwrap!(service_1.send(service_0.id(), vec![0], 0));
+    assert_eq!(network.poll_with_limit(1), 1);
+
+    // Each service echoes every message it receives, so the two never stop.
+    unwrap!(service_1.send(service_0.id(), vec![1], 0));
+    let service_0 = Rc::new(service_0);
+    let service_1 = Rc::new(service_1);
+    let (endpoint_0, endpoint_1) = (handle_0.endpoint(), handle_1.endpoint());
+    network.set_packet_observer(move |packet: &ObservedPacket| {
+        if packet.kind != PacketKind::Message {
+            return;
+        }
+        let payload = unwrap!(packet.payload).to_vec();
+        if packet.receiver == endpoint_0 {
+            unwrap!(service_0.send(service_1.id(), payload, 0));
+        } else if packet.receiver == endpoint_1 {
+            unwrap!(service_1.send(service_0.id(), payload, 0));
+        }
+    });
+    let _ = network.poll_with_limit(100);
+}
+
+#[test]
+fn blackholed_connect_attempt() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let handle_1 = network.new_service_handle(None, None);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    let service_1 =
+        unwrap!(Service::with_handle(&handle_1, event_tx_1, *FullId::new().public_id()));
+
+    let connect = |token| {
+        service_0.prepare_connection_info(token);
+        let our_ci_0 = expect_event!(event_rx_0,
+                                     CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+            unwrap!(cir.result)
+        });
+        service_1.prepare_connection_info(token);
+        let our_ci_1 = expect_event!(event_rx_1,
+                                     CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+            unwrap!(cir.result)
+        });
+        unwrap!(service_0.connect(our_ci_0, our_ci_1.to_pub_connection_info()));
+        network.poll();
+    };
+
+    // Over a blackholed route, the attempt vanishes without a trace.
+    network.blackhole_connection(handle_0.endpoint(), handle_1.endpoint());
+    connect(1);
+    assert!(event_rx_0.try_recv().is_err());
+    assert!(event_rx_1.try_recv().is_err());
+    assert!(!handle_0.is_connected(&handle_1));
+
+    // Over a blocked one, it fails fast.
+    network.unblackhole_connection(handle_0.endpoint(), handle_1.endpoint());
+    network.block_connection(handle_0.endpoint(), handle_1.endpoint());
+    connect(2);
+    expect_event!(event_rx_0, CrustEvent::ConnectFailure::<PublicId>(id) => {
+        assert_eq!(id, service_1.id())
+    });
+    assert!(event_rx_1.try_recv().is_err());
+}
+
+#[test]
+fn delayed_connection_info() {
+    const PREPARE_CI_TOKEN: u32 = 1;
+
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle0 = network.new_service_handle(None, None);
+    handle0.set_connection_info_behaviour(ConnectionInfoBehaviour::Delayed(3));
 
     let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
-    let (event_tx_1, _category_rx_1, event_rx_1) = get_event_sender();
+    let service_0 = unwrap!(Service::with_handle(&handle0, event_tx_0, *FullId::new().public_id()));
+
+    // Preparing polls the network once, so two more polls are needed to release the event.
+    service_0.prepare_connection_info(PREPARE_CI_TOKEN);
+    network.poll();
+    assert!(event_rx_0.try_recv().is_err());
 
+    network.poll();
+    expect_event!(event_rx_0, CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        assert_eq!(cir.result_token, PREPARE_CI_TOKEN);
+        assert!(cir.result.is_ok());
+    });
+}
+
+#[test]
+fn failed_connection_info() {
+    const PREPARE_CI_TOKEN: u32 = 1;
+
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle0 = network.new_service_handle(None, None);
+    handle0.set_connection_info_behaviour(ConnectionInfoBehaviour::Fail(io::ErrorKind::Other));
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
     let service_0 = unwrap!(Service::with_handle(&handle0, event_tx_0, *FullId::new().public_id()));
-    let service_1 = unwrap!(Service::with_handle(&handle1, event_tx_1, *FullId::new().public_id()));
 
     service_0.prepare_connection_info(PREPARE_CI_TOKEN);
-    let our_ci_0 = expect_event!(event_rx_0,
-                                 CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
-        unwrap!(cir.result)
+    expect_event!(event_rx_0, CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        assert_eq!(cir.result_token, PREPARE_CI_TOKEN);
+        assert!(cir.result.is_err());
     });
+}
 
-    service_1.prepare_connection_info(PREPARE_CI_TOKEN);
-    let our_ci_1 = expect_event!(event_rx_1,
-                                 CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
-        unwrap!(cir.result)
+#[test]
+fn connection_info_failures() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle0 = network.new_service_handle(None, None);
+    handle0.set_connection_info_failures(2);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let service_0 = unwrap!(Service::with_handle(&handle0, event_tx_0, *FullId::new().public_id()));
+
+    // The first two calls fail, then preparing succeeds again. Each result keeps its token.
+    for token in 1..4 {
+        service_0.prepare_connection_info(token);
+        expect_event!(event_rx_0, CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+            assert_eq!(cir.result_token, token);
+            assert_eq!(cir.result.is_err(), token < 3);
+        });
+    }
+}
+
+#[test]
+fn async_connection_info() {
+    const PREPARE_CI_TOKEN: u32 = 1;
+
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle0 = network.new_service_handle(None, None);
+    handle0.set_connection_info_async(true);
+    handle0.set_connection_info_failures(1);
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let service_0 = unwrap!(Service::with_handle(&handle0, event_tx_0, *FullId::new().public_id()));
+
+    // Failures are deferred as well.
+    service_0.prepare_connection_info(PREPARE_CI_TOKEN);
+    assert!(event_rx_0.try_recv().is_err());
+    network.poll();
+    expect_event!(event_rx_0, CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        assert_eq!(cir.result_token, PREPARE_CI_TOKEN);
+        assert!(cir.result.is_err());
     });
 
-    let their_ci_1 = our_ci_1.to_pub_connection_info();
+    service_0.prepare_connection_info(PREPARE_CI_TOKEN + 1);
+    assert!(event_rx_0.try_recv().is_err());
+    network.poll();
+    expect_event!(event_rx_0, CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        assert_eq!(cir.result_token, PREPARE_CI_TOKEN + 1);
+        assert!(cir.result.is_ok());
+    });
 
-    unwrap!(service_0.connect(our_ci_0, their_ci_1));
+    // Switching back makes the results synchronous again.
+    handle0.set_connection_info_async(false);
+    service_0.prepare_connection_info(PREPARE_CI_TOKEN + 2);
+    expect_event!(event_rx_0, CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+        assert_eq!(cir.result_token, PREPARE_CI_TOKEN + 2);
+        assert!(cir.result.is_ok());
+    });
+}
 
-    expect_event!(event_rx_0, CrustEvent::ConnectSuccess::<PublicId>(_));
-    expect_event!(event_rx_1, CrustEvent::ConnectSuccess::<PublicId>(_));
+#[test]
+fn operations_on_missing_services() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let handle_1 = network.new_service_handle(None, None);
+    let endpoint_0 = handle_0.endpoint();
+    let endpoint_1 = handle_1.endpoint();
+
+    let (event_tx_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let _service_0 =
+        unwrap!(Service::with_handle(&handle_0, event_tx_0, *FullId::new().public_id()));
+    assert_eq!(network.lost_connection(endpoint_0, endpoint_1),
+               Err(MockError::NotConnected(endpoint_0, endpoint_1)));
+
+    ::std::mem::drop(handle_1);
+    assert_eq!(network.lost_connection(endpoint_0, endpoint_1),
+               Err(MockError::ServiceNotFound(endpoint_1)));
+    assert_eq!(network.lost_connection(endpoint_1, endpoint_0),
+               Err(MockError::ServiceNotFound(endpoint_1)));
+    assert_eq!(network.send_crust_event(endpoint_1, CrustEvent::ListenerStarted(0)),
+               Err(MockError::ServiceNotFound(endpoint_1)));
+    assert_eq!(network.send_crust_event(endpoint_0, CrustEvent::ListenerStarted(0)),
+               Ok(()));
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(0));
+}
+
+#[test]
+#[should_panic(expected = "No service found at Endpoint(1)")]
+fn lost_connection_or_panic_on_missing_service() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle_0 = network.new_service_handle(None, None);
+    let endpoint_1 = network.new_service_handle(None, None).endpoint();
+    network.lost_connection_or_panic(handle_0.endpoint(), endpoint_1);
+}
+
+#[test]
+#[should_panic(expected = "No service found at Endpoint(0)")]
+fn send_crust_event_or_panic_on_missing_service() {
+    let min_section_size = 8;
+    let network = Network::<PublicId>::new(min_section_size, None);
+    let endpoint = network.new_service_handle(None, None).endpoint();
+    network.send_crust_event_or_panic(endpoint, CrustEvent::ListenerStarted(0));
 }
 
 #[test]
@@ -246,3 +1864,349 @@ fn drop() {
     mem::drop(service_0);
     expect_event!(event_rx_1, CrustEvent::LostPeer::<PublicId>(id) => assert_eq!(id, id_0));
 }
+
+#[test]
+fn crash() {
+    use std::mem;
+
+    const PREPARE_CI_TOKEN: u32 = 1;
+
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle0 = network.new_service_handle(None, None);
+    let handle1 = network.new_service_handle(None, None);
+    let handle2 = network.new_service_handle(None, None);
+
+    let (event_sender_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_sender_1, _category_rx_1, event_rx_1) = get_event_sender();
+    let (event_sender_2, _category_rx_2, event_rx_2) = get_event_sender();
+
+    let service_0 =
+        unwrap!(Service::with_handle(&handle0, event_sender_0, *FullId::new().public_id()));
+    let service_1 =
+        unwrap!(Service::with_handle(&handle1, event_sender_1, *FullId::new().public_id()));
+    let service_2 =
+        unwrap!(Service::with_handle(&handle2, event_sender_2, *FullId::new().public_id()));
+
+    let prepare = |service: &Service<PublicId>, event_rx: &Receiver<CrustEvent<PublicId>>| {
+        service.prepare_connection_info(PREPARE_CI_TOKEN);
+        expect_event!(event_rx, CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+            unwrap!(cir.result)
+        })
+    };
+    let our_ci_0 = prepare(&service_0, &event_rx_0);
+    let our_ci_1 = prepare(&service_1, &event_rx_1);
+    let our_ci_2 = prepare(&service_2, &event_rx_2);
+    let their_ci_0 = our_ci_0.to_pub_connection_info();
+    let their_ci_1 = our_ci_1.to_pub_connection_info();
+
+    unwrap!(service_0.connect(our_ci_0, their_ci_1.clone()));
+    unwrap!(service_1.connect(our_ci_1, their_ci_0));
+    let id_1 = expect_event!(event_rx_0, CrustEvent::ConnectSuccess::<PublicId>(id) => id);
+    expect_event!(event_rx_1, CrustEvent::ConnectSuccess::<PublicId>(_));
+
+    // Crash 1 while a message to it is still in flight. Neither the message nor anything else
+    // reaches it, and 0 isn't told.
+    network.hold_connection(handle0.endpoint(), handle1.endpoint());
+    unwrap!(service_0.send(id_1, vec![1, 2, 3], 0));
+    handle1.crash();
+    assert!(network.pending_packets(handle0.endpoint(), handle1.endpoint()).is_empty());
+    network.release_connection(handle0.endpoint(), handle1.endpoint());
+    assert!(event_rx_0.try_recv().is_err());
+    assert!(event_rx_1.try_recv().is_err());
+    assert!(handle0.is_connected_to_endpoint(handle1.endpoint()));
+    assert_eq!(handle1.connection_count(), 0);
+
+    // 0 only notices once it tries to send another message.
+    unwrap!(service_0.send(id_1, vec![4, 5, 6], 0));
+    expect_event!(event_rx_0, CrustEvent::LostPeer::<PublicId>(id) => assert_eq!(id, id_1));
+    assert!(!handle0.is_connected_to_endpoint(handle1.endpoint()));
+
+    // A connection attempt fails.
+    unwrap!(service_2.connect(our_ci_2, their_ci_1));
+    expect_event!(event_rx_2,
+                  CrustEvent::ConnectFailure::<PublicId>(id) => assert_eq!(id, id_1));
+
+    // Dropping the crashed service doesn't send anything either.
+    mem::drop(service_1);
+    mem::drop(handle1);
+    assert!(event_rx_0.try_recv().is_err());
+    assert!(event_rx_2.try_recv().is_err());
+}
+
+#[test]
+fn stale_packets_after_restart() {
+    use std::mem;
+
+    const PREPARE_CI_TOKEN: u32 = 1;
+
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let handle0 = network.new_service_handle(None, None);
+    let handle1 = network.new_service_handle(None, None);
+    let endpoint0 = handle0.endpoint();
+    let endpoint1 = handle1.endpoint();
+    assert_eq!(handle1.incarnation(), 1);
+
+    let (event_sender_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_sender_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let service_0 =
+        unwrap!(Service::with_handle(&handle0, event_sender_0, *FullId::new().public_id()));
+    let service_1 =
+        unwrap!(Service::with_handle(&handle1, event_sender_1, *FullId::new().public_id()));
+
+    let prepare = |service: &Service<PublicId>, event_rx: &Receiver<CrustEvent<PublicId>>| {
+        service.prepare_connection_info(PREPARE_CI_TOKEN);
+        expect_event!(event_rx, CrustEvent::ConnectionInfoPrepared::<PublicId>(cir) => {
+            unwrap!(cir.result)
+        })
+    };
+    let connect = |service_a: &Service<PublicId>,
+                   event_rx_a: &Receiver<CrustEvent<PublicId>>,
+                   service_b: &Service<PublicId>,
+                   event_rx_b: &Receiver<CrustEvent<PublicId>>| {
+        let our_ci_a = prepare(service_a, event_rx_a);
+        let our_ci_b = prepare(service_b, event_rx_b);
+        let their_ci_a = our_ci_a.to_pub_connection_info();
+        let their_ci_b = our_ci_b.to_pub_connection_info();
+        unwrap!(service_a.connect(our_ci_a, their_ci_b));
+        unwrap!(service_b.connect(our_ci_b, their_ci_a));
+        expect_event!(event_rx_b, CrustEvent::ConnectSuccess::<PublicId>(_));
+        expect_event!(event_rx_a, CrustEvent::ConnectSuccess::<PublicId>(id) => id)
+    };
+    let id_1 = connect(&service_0, &event_rx_0, &service_1, &event_rx_1);
+
+    // Crash 1 and queue messages to it while its endpoint is vacant.
+    network.hold_connection(endpoint0, endpoint1);
+    handle1.crash();
+    mem::drop(service_1);
+    mem::drop(handle1);
+    unwrap!(service_0.send(id_1, vec![1, 2, 3], 0));
+    unwrap!(service_0.send(id_1, vec![4, 5, 6], 0));
+    assert_eq!(network.pending_packets(endpoint0, endpoint1).len(), 2);
+
+    // Restart it at the same endpoint. The queued messages aren't delivered to the new
+    // incarnation, and by default the sender isn't told.
+    let handle1 = network.new_service_handle(None, Some(endpoint1));
+    assert_eq!(handle1.incarnation(), 2);
+    let (event_sender_1, _category_rx_1, event_rx_1) = get_event_sender();
+    let service_1 =
+        unwrap!(Service::with_handle(&handle1, event_sender_1, *FullId::new().public_id()));
+    network.release_connection(endpoint0, endpoint1);
+    network.poll();
+    assert_eq!(network.stale_packet_count(), 2);
+    assert!(event_rx_0.try_recv().is_err());
+    assert!(event_rx_1.try_recv().is_err());
+
+    // New traffic reaches the new incarnation normally.
+    let id_1 = connect(&service_0, &event_rx_0, &service_1, &event_rx_1);
+    unwrap!(service_0.send(id_1, vec![7, 8, 9], 0));
+    expect_event!(event_rx_1, CrustEvent::NewMessage::<PublicId>(_, msg) => {
+        assert_eq!(msg, vec![7, 8, 9])
+    });
+    assert_eq!(network.stale_packet_count(), 2);
+}
+
+// Bootstraps three services off a fourth over held routes, then releases them all at once, so
+// that the network decides the order of delivery. Returns the indices of the three services in
+// the order in which the fourth accepted them.
+fn bootstrap_concurrently(network: &Network<PublicId>) -> Vec<usize> {
+    let endpoint0 = network.gen_endpoint(None);
+    let config = Config::with_contacts(&[endpoint0]);
+    let handle0 = network.new_service_handle(None, Some(endpoint0));
+    let (event_sender_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle0, event_sender_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+
+    let mut ids = Vec::new();
+    let mut services = Vec::new();
+    for _ in 0..3 {
+        let handle = network.new_service_handle(Some(config.clone()), None);
+        network.hold_connection(handle.endpoint(), endpoint0);
+        let (event_sender, _category_rx, event_rx) = get_event_sender();
+        let id = *FullId::new().public_id();
+        let mut service = unwrap!(Service::with_handle(&handle, event_sender, id));
+        unwrap!(service.start_bootstrap(HashSet::new(), CrustUser::Node));
+        ids.push(id);
+        services.push((handle, service, event_rx));
+    }
+    for &(ref handle, ..) in &services {
+        network.release_connection(handle.endpoint(), endpoint0);
+    }
+    network.poll();
+
+    (0..3)
+        .map(|_| {
+            let id = expect_event!(event_rx_0,
+                CrustEvent::BootstrapAccept::<PublicId>(id, CrustUser::Node) => id);
+            unwrap!(ids.iter().position(|other| *other == id))
+        })
+        .collect()
+}
+
+#[test]
+fn record_and_replay() {
+    let min_section_size = 8;
+    let seed = Some([1, 2, 3, 4]);
+    let network = Network::new(min_section_size, seed);
+    network.start_recording();
+    let order = bootstrap_concurrently(&network);
+    let trace = network.take_trace();
+    assert_eq!(trace
+                   .iter()
+                   .filter(|entry| entry.kind == PacketKind::BootstrapRequest)
+                   .count(),
+               3);
+    assert!(network.take_trace().is_empty());
+
+    // The trace survives serialisation.
+    let trace: Vec<TraceEntry> = unwrap!(deserialise(&unwrap!(serialise(&trace))));
+
+    // Replaying reproduces the run exactly, on a network rebuilt from the same seed as well as on
+    // one whose random numbers would pick packets in a different order.
+    for seed in &[seed, Some([5, 6, 7, 8])] {
+        let network = Network::new(min_section_size, *seed);
+        network.replay(trace.clone());
+        network.start_recording();
+        assert_eq!(bootstrap_concurrently(&network), order);
+        assert_eq!(network.take_trace(), trace);
+    }
+}
+
+#[test]
+#[should_panic(expected = "Replay diverged")]
+fn replay_divergence() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    // The bootstrap requests go the other way, so this packet is never queued.
+    network.replay(vec![TraceEntry {
+                            sender: Endpoint(0),
+                            receiver: Endpoint(1),
+                            kind: PacketKind::BootstrapRequest,
+                            wire_kind: None,
+                        }]);
+    let _ = bootstrap_concurrently(&network);
+}
+
+#[test]
+fn assigned_ip_addresses() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let endpoint0 = network.gen_endpoint(None);
+    let endpoint1 = network.gen_endpoint(None);
+    let ip0 = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    network.assign_ip(endpoint0, ip0);
+    let config = Config::with_contacts(&[endpoint0]);
+
+    let handle0 = network.new_service_handle(None, Some(endpoint0));
+    let handle1 = network.new_service_handle(Some(config), Some(endpoint1));
+
+    let (event_sender_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_sender_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle0, event_sender_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle1, event_sender_1, *FullId::new().public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    let (id_0, addr_0) = expect_event!(event_rx_1,
+        CrustEvent::BootstrapConnect::<PublicId>(id, addr) => (id, addr));
+    let id_1 = expect_event!(event_rx_0,
+        CrustEvent::BootstrapAccept::<PublicId>(id, CrustUser::Node) => id);
+
+    // The assigned address is reported to the peer, while unassigned endpoints use the default.
+    assert_eq!(addr_0.ip(), ip0);
+    assert_eq!(unwrap!(service_1.get_peer_ip_addr(&id_0)), ip0);
+    assert_ne!(unwrap!(service_0.get_peer_ip_addr(&id_1)), ip0);
+    assert!(service_0.get_peer_ip_addr(&id_0).is_err());
+}
+
+#[test]
+fn messages_told_apart_by_wire_kind() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let endpoint0 = network.gen_endpoint(None);
+    let endpoint1 = network.gen_endpoint(None);
+    let config = Config::with_contacts(&[endpoint0]);
+
+    let handle0 = network.new_service_handle(None, Some(endpoint0));
+    let handle1 = network.new_service_handle(Some(config), Some(endpoint1));
+
+    let (event_sender_0, _category_rx_0, event_rx_0) = get_event_sender();
+    let (event_sender_1, _category_rx_1, event_rx_1) = get_event_sender();
+
+    let mut service_0 =
+        unwrap!(Service::with_handle(&handle0, event_sender_0, *FullId::new().public_id()));
+    unwrap!(service_0.start_listening_tcp());
+    expect_event!(event_rx_0, CrustEvent::ListenerStarted::<PublicId>(..));
+
+    let full_id_1 = FullId::new();
+    let mut service_1 =
+        unwrap!(Service::with_handle(&handle1, event_sender_1, *full_id_1.public_id()));
+    unwrap!(service_1.start_bootstrap(HashSet::new(), CrustUser::Node));
+    let id_0 = expect_event!(event_rx_1, CrustEvent::BootstrapConnect::<PublicId>(id, _) => id);
+    expect_event!(event_rx_0, CrustEvent::BootstrapAccept::<PublicId>(..));
+
+    let observed = Rc::new(RefCell::new(Vec::new()));
+    let observed_clone = observed.clone();
+    network.set_packet_observer(move |packet: &ObservedPacket| {
+        if let Some(kind) = packet.wire_kind {
+            observed_clone.borrow_mut().push((kind, packet.delivery));
+        }
+    });
+
+    let hello = unwrap!(serialise(&Message::Direct(DirectMessage::BootstrapIdentify)));
+    let authority = Authority::ManagedNode(*full_id_1.public_id().name());
+    let signed = TestMessage::connection_info_request(authority, authority, *full_id_1.public_id())
+        .to_bytes(&full_id_1, &full_id_1);
+    let send_both = || {
+        unwrap!(service_1.send(id_0, hello.clone(), 0));
+        unwrap!(service_1.send(id_0, signed.clone(), 0));
+    };
+
+    send_both();
+    expect_event!(event_rx_0, CrustEvent::NewMessage::<PublicId>(_, data) => {
+        assert_eq!(data, hello)
+    });
+    expect_event!(event_rx_0, CrustEvent::NewMessage::<PublicId>(_, data) => {
+        assert_eq!(data, signed)
+    });
+    let expected_counts: BTreeMap<_, _> = vec![(WireKind::Direct, 1), (WireKind::Hop, 1)]
+        .into_iter()
+        .collect();
+    assert_eq!(network.wire_kind_counts(), expected_counts);
+
+    // Blocking signed messages leaves the others flowing.
+    network.block_wire_kind(endpoint1, endpoint0, WireKind::Hop);
+    assert_eq!(network.impaired_links(),
+               vec![(endpoint1, endpoint0, LinkImpairment::BlockedWire(WireKind::Hop))]);
+    send_both();
+    expect_event!(event_rx_0, CrustEvent::NewMessage::<PublicId>(_, data) => {
+        assert_eq!(data, hello)
+    });
+    assert!(event_rx_0.try_recv().is_err());
+    let expected_counts: BTreeMap<_, _> = vec![(WireKind::Direct, 2), (WireKind::Hop, 1)]
+        .into_iter()
+        .collect();
+    assert_eq!(network.wire_kind_counts(), expected_counts);
+
+    network.unblock_wire_kind(endpoint1, endpoint0, WireKind::Hop);
+    assert!(network.impaired_links().is_empty());
+    unwrap!(service_1.send(id_0, signed.clone(), 0));
+    expect_event!(event_rx_0, CrustEvent::NewMessage::<PublicId>(_, data) => {
+        assert_eq!(data, signed)
+    });
+
+    assert_eq!(*observed.borrow(),
+               vec![(WireKind::Direct, Delivery::Delivered),
+                    (WireKind::Hop, Delivery::Delivered),
+                    (WireKind::Direct, Delivery::Delivered),
+                    (WireKind::Hop, Delivery::Blocked),
+                    (WireKind::Hop, Delivery::Delivered)]);
+}
@@ -17,28 +17,44 @@
 
 use action::Action;
 use cache::{Cache, NullCache};
+use crypto;
 use data::{Data, DataIdentifier};
 use error::{InterfaceError, RoutingError};
-use event::Event;
+use event::{Event, JoinProgressMask};
+use event_sink::EventSink;
 use event_stream::{EventStepper, EventStream};
+use expiring_cache::ExpiringCache;
+use forwarding::ForwardingSink;
 use id::{FullId, PublicId};
+#[cfg(feature = "use-mock-crust")]
+use input_log::{InputLog, ReplayDivergence};
 use messages::{CLIENT_GET_PRIORITY, DEFAULT_PRIORITY, RELOCATE_PRIORITY, Request, Response,
                UserMessage};
+#[cfg(feature = "use-mock-crust")]
+use mock_crust::Endpoint;
 use outbox::{EventBox, EventBuf};
 use routing_table::{Authority, RoutingTable};
 #[cfg(feature = "use-mock-crust")]
 use routing_table::Prefix;
-#[cfg(not(feature = "use-mock-crust"))]
-use rust_sodium;
 #[cfg(feature = "use-mock-crust")]
 use rust_sodium::crypto::sign;
+use sha3::Digest256;
 use state_machine::{State, StateMachine};
+use state_store::{StatePersistence, StateStore};
 use states::{self, Bootstrapping, BootstrappingTargetState};
+use stats::Diagnostics;
+#[cfg(feature = "use-mock-crust")]
+use stats::PendingWork;
 #[cfg(feature = "use-mock-crust")]
 use std::collections::BTreeMap;
 #[cfg(feature = "use-mock-crust")]
 use std::fmt::{self, Debug, Formatter};
+use std::net::SocketAddr;
 use std::sync::mpsc::{Receiver, RecvError, Sender, TryRecvError, channel};
+use std::time::Duration;
+use tiny_keccak::sha3_256;
+use tunables::{ConnectionQuotas, EffectiveConfig, PartialConfig, ScoreRule, Tunables,
+               group_quorum};
 use types::{MessageId, RoutingActionSender};
 use xor_name::XorName;
 
@@ -47,6 +63,14 @@ pub struct NodeBuilder {
     cache: Box<Cache>,
     first: bool,
     deny_other_local_nodes: bool,
+    tunables: Tunables,
+    event_sink: Option<Box<EventSink>>,
+    forwarding_sink: Option<Box<ForwardingSink>>,
+    state_store: Option<(Box<StateStore>, Duration, bool)>,
+    #[cfg(feature = "use-mock-crust")]
+    full_id: Option<FullId>,
+    #[cfg(feature = "use-mock-crust")]
+    record_inputs: bool,
 }
 
 impl NodeBuilder {
@@ -74,22 +98,381 @@ impl NodeBuilder {
         }
     }
 
+    /// Sets for how long received messages are remembered to filter out duplicates, and how
+    /// many of them are remembered at most.
+    pub fn message_filter(mut self, expiry: Duration, capacity: usize) -> NodeBuilder {
+        self.tunables.filter_expiry = expiry;
+        self.tunables.filter_capacity = capacity;
+        self
+    }
+
+    /// Sets for how long the kinds of bootstrapping peers are remembered, and how many of them are
+    /// remembered at most.
+    pub fn bootstrapper_cache(mut self, duration: Duration, capacity: usize) -> NodeBuilder {
+        self.tunables.bootstrapper_cache_duration = duration;
+        self.tunables.bootstrapper_cache_capacity = capacity;
+        self
+    }
+
+    /// Sets for how long the relocated names assigned to joining nodes are remembered, and how
+    /// many of them are remembered at most.
+    pub fn relocation_cache(mut self, duration: Duration, capacity: usize) -> NodeBuilder {
+        self.tunables.relocation_cache_duration = duration;
+        self.tunables.relocation_cache_capacity = capacity;
+        self
+    }
+
+    /// Limits how many routing table entries may be directly connected from the same IP address.
+    /// Connections exceeding the limit are refused and reported via `Event::PeerRefused`.
+    pub fn max_peers_per_ip(mut self, max: usize) -> NodeBuilder {
+        self.tunables.max_peers_per_ip = Some(max);
+        self
+    }
+
+    /// Limits how many routing table entries may be directly connected from the same /24 IPv4 or
+    /// /64 IPv6 subnet. Connections exceeding the limit are refused and reported via
+    /// `Event::PeerRefused`.
+    pub fn max_peers_per_subnet(mut self, max: usize) -> NodeBuilder {
+        self.tunables.max_peers_per_subnet = Some(max);
+        self
+    }
+
+    /// Paces outgoing connection attempts: at most `max_in_flight` of them are made at a time, and
+    /// at least `spacing` passes between initiating two of them. Further attempts are queued until
+    /// both allow them.
+    pub fn connect_pacing(mut self, max_in_flight: usize, spacing: Duration) -> NodeBuilder {
+        self.tunables.max_connects_in_flight = Some(max_in_flight);
+        self.tunables.connect_spacing = spacing;
+        self
+    }
+
+    /// Limits how many connections of each kind the node holds. Connections exceeding a quota are
+    /// refused and reported via `Event::PeerRefused`. The quotas can be adjusted later via
+    /// `Node::set_connection_quotas`.
+    pub fn connection_quotas(mut self, quotas: ConnectionQuotas) -> NodeBuilder {
+        self.tunables.connection_quotas = quotas;
+        self
+    }
+
+    /// Keeps a log of the routing decisions taken for the last `capacity` messages, to be retrieved
+    /// via `Node::decision_log`. Each entry is a small fixed-size record; the log's memory is
+    /// allocated up front.
+    pub fn decision_log(mut self, capacity: usize) -> NodeBuilder {
+        self.tunables.decision_log_capacity = Some(capacity);
+        self
+    }
+
+    /// Raises `Event::SlowMessage` whenever handling a single received message takes `threshold`
+    /// or longer, naming the phase of processing which dominated.
+    pub fn slow_message_reports(mut self, threshold: Duration) -> NodeBuilder {
+        self.tunables.slow_message_threshold = Some(threshold);
+        self
+    }
+
+    /// Audits our routing table against Crust's live connections every `interval`. Routing table
+    /// entries without a connection, and connections to peers we don't know, which persist for
+    /// `grace` are dropped. Discrepancies are reported via `Event::ConnectionAudit`.
+    pub fn connection_audit(mut self, interval: Duration, grace: Duration) -> NodeBuilder {
+        self.tunables.audit_interval = Some(interval);
+        self.tunables.audit_grace = grace;
+        self
+    }
+
+    /// Every `interval`, sends `fanout` random peers a sample of up to `sample_size` names from our
+    /// routing table, so that nodes learn about and connect to peers they are missing. Gossip is
+    /// suspended while the routing table has fewer than `min_section_size` entries.
+    pub fn table_gossip(mut self,
+                        interval: Duration,
+                        fanout: usize,
+                        sample_size: usize)
+                        -> NodeBuilder {
+        self.tunables.gossip_interval = Some(interval);
+        self.tunables.gossip_fanout = fanout;
+        self.tunables.gossip_sample_size = sample_size;
+        self
+    }
+
+    /// If the node loses all its routing connections, it raises `Event::Disconnected` and tries to
+    /// rejoin its section via a new bootstrap connection, instead of raising
+    /// `Event::RestartRequired`. Until `Event::Reconnected`, up to `queue_limit` user messages are
+    /// queued and sent once it has rejoined.
+    pub fn disconnected_recovery(mut self, queue_limit: usize) -> NodeBuilder {
+        self.tunables.disconnected_queue_limit = Some(queue_limit);
+        self
+    }
+
+    /// Sets the maximum number of peers to which the node sends a message bound for a close group
+    /// (a `ClientManager`, `NaeManager` or `NodeManager`) it belongs to. It picks the members
+    /// closest to the destination. By default, this is a quorum plus two. `create` fails if the
+    /// maximum is less than a quorum.
+    pub fn group_fanout(mut self, max: usize) -> NodeBuilder {
+        self.tunables.group_fanout = Some(max);
+        self
+    }
+
+    /// Sets the number of routing table entries at which a newly joined node disconnects from the
+    /// proxy node it bootstrapped off, unless the proxy is one of them. By default, this is
+    /// `min_section_size - 1`.
+    pub fn proxy_drop_threshold(mut self, entries: usize) -> NodeBuilder {
+        self.tunables.proxy_drop_threshold = Some(entries);
+        self
+    }
+
+    /// Sets the number of times a message can be relayed between nodes before it is dropped as
+    /// caught in a routing loop. By default, this is 40, which comfortably exceeds the number of
+    /// hops needed to reach any section.
+    pub fn max_hop_count(mut self, hops: u8) -> NodeBuilder {
+        self.tunables.max_hop_count = hops;
+        self
+    }
+
+    /// Tracks how far the routing table has converged: the XOR distance to the furthest member of
+    /// our close group, the number of buckets covered and the table size, as reported in
+    /// `Diagnostics`. A snapshot of them is taken every `interval`. Once they have been unchanged
+    /// over `stable_snapshots` snapshots and the table has at least `min_table_size` entries,
+    /// `Event::TableConverged` is raised. It is raised again after the next change.
+    pub fn convergence_tracking(mut self,
+                                interval: Duration,
+                                stable_snapshots: usize,
+                                min_table_size: usize)
+                                -> NodeBuilder {
+        self.tunables.convergence_interval = Some(interval);
+        self.tunables.convergence_stable_snapshots = stable_snapshots;
+        self.tunables.convergence_min_table_size = min_table_size;
+        self
+    }
+
+    /// Sets the maximum number of Crust events parked while the node is still joining, e.g.
+    /// messages from peers other than its proxy. They are handled once it has joined the network
+    /// under its relocated name. If more arrive, the oldest ones are dropped, as counted in
+    /// `Diagnostics::startup_queue_drops`.
+    pub fn startup_queue_capacity(mut self, capacity: usize) -> NodeBuilder {
+        self.tunables.startup_queue_capacity = capacity;
+        self
+    }
+
+    /// Sets the range of wire format versions the node accepts, e.g. the previous and the current
+    /// one while a network is being upgraded. Each peer is sent messages in the version it was
+    /// last seen using, or in `oldest` if we haven't heard from it yet. The versions used by our
+    /// peers are reported in `Diagnostics::peer_wire_versions`, and `Event::WireUpgraded` is
+    /// raised once all the other members of our section use `newest`. Only applies once the node
+    /// has joined; while joining, it uses the base version.
+    pub fn wire_versions(mut self, oldest: u8, newest: u8) -> NodeBuilder {
+        self.tunables.oldest_wire_version = oldest;
+        self.tunables.newest_wire_version = newest;
+        self
+    }
+
+    /// Sets when peers are disconnected for committing protocol violations and for sending
+    /// malformed messages respectively, how quickly that is forgiven, and whether they are also
+    /// banned. By default, a peer is disconnected after 3 of either within 10 or 5 minutes of each
+    /// other respectively, and not banned.
+    pub fn misbehaviour_rules(mut self,
+                              protocol_violation: ScoreRule,
+                              malformed_msg: ScoreRule)
+                              -> NodeBuilder {
+        self.tunables.protocol_violation_rule = protocol_violation;
+        self.tunables.malformed_msg_rule = malformed_msg;
+        self
+    }
+
+    /// Sets by how many churn events a connection info message may lag behind our knowledge of its
+    /// sender's section. Older ones were created before the section changed and are dropped.
+    pub fn churn_generation_slack(mut self, slack: u64) -> NodeBuilder {
+        self.tunables.churn_generation_slack = slack;
+        self
+    }
+
+    /// Sets for how long retries of a request are assigned the original `MessageId` by
+    /// `Node::message_id_for`.
+    pub fn message_id_retry_window(mut self, window: Duration) -> NodeBuilder {
+        self.tunables.message_id_retry_window = window;
+        self
+    }
+
+    /// Sets the maximum number of retry-safe messages remembered within the retry window. Once
+    /// exceeded, the oldest are forgotten, and a retry of one of them is sent as a new message.
+    pub fn message_id_retry_capacity(mut self, capacity: usize) -> NodeBuilder {
+        self.tunables.message_id_retry_capacity = capacity;
+        self
+    }
+
+    /// Enables `Event::JoinProgress` events for the kinds of steps selected by `mask`, reporting
+    /// the node's progress in joining the network.
+    pub fn join_progress_events(mut self, mask: JoinProgressMask) -> NodeBuilder {
+        self.tunables.join_progress_events = mask;
+        self
+    }
+
+    /// Enables `Event::HealthChanged` events, reporting changes in the connectivity to our section.
+    pub fn health_events(mut self) -> NodeBuilder {
+        self.tunables.health_events = true;
+        self
+    }
+
+    /// Enables `Event::NodeDeparted` events. When the node loses its connection to a member of
+    /// its section, it reports that to the other members. Once a quorum of the section reported
+    /// losing the same member, it is considered to have left the network and the event is raised.
+    /// A connection lost by fewer members, e.g. due to a local failure, only raises
+    /// `Event::NodeLost`, and the node keeps trying to reconnect.
+    pub fn departure_consensus(mut self) -> NodeBuilder {
+        self.tunables.departure_consensus = true;
+        self
+    }
+
+    /// Hands all events to the given sink as soon as they are raised, instead of buffering them
+    /// to be read via `EventStream`.
+    pub fn event_sink(self, sink: Box<EventSink>) -> NodeBuilder {
+        NodeBuilder {
+            event_sink: Some(sink),
+            ..self
+        }
+    }
+
+    /// Coalesces bursts of repetitive events: after a `NodeAdded` or `NodeLost` event for a peer,
+    /// or a `HealthChanged` event, further ones for the same peer or about our health are withheld
+    /// for `window`. Then their number is reported via `Event::Coalesced`, preceded by the last of
+    /// them if it changed the state reported before, carrying the current routing table. A timeout
+    /// is scheduled for each window, so it is closed once elapsed even if the node is otherwise
+    /// idle. Requests, responses and all other events are never
+    /// withheld.
+    pub fn event_coalescing(mut self, window: Duration) -> NodeBuilder {
+        self.tunables.event_coalescing_window = Some(window);
+        self
+    }
+
+    /// Hands the peers chosen for each routing message the node sends, forwards or relays to a
+    /// client to the given sink. Messages passed on via tunnels and direct messages between peers
+    /// are not reported.
+    pub fn forwarding_sink(self, sink: Box<ForwardingSink>) -> NodeBuilder {
+        NodeBuilder {
+            forwarding_sink: Some(sink),
+            ..self
+        }
+    }
+
+    /// Saves a snapshot of the node's state to `store` every `interval` once it has joined, and
+    /// when it is terminated: the peers it was connected to within the last day, and its keys if
+    /// `include_keys` is set. On creation, the node is restored from the stored snapshot as
+    /// reported via `Event::StateRestored`, unless it was taken with different settings, can't be
+    /// read or holds mismatched keys, which is reported via `Event::StateDiscarded`. The restored
+    /// peers are then bootstrapped off as well as the configured contacts.
+    pub fn state_persistence(mut self,
+                             store: Box<StateStore>,
+                             interval: Duration,
+                             include_keys: bool)
+                             -> NodeBuilder {
+        self.state_store = Some((store, interval, include_keys));
+        self
+    }
+
+    /// Only reports routing messages to the sink set via `forwarding_sink` instead of sending
+    /// them. Filters, accumulators and the routing table are still updated by received messages,
+    /// e.g. ones injected via `Node::inject_message_for_test`. Direct messages between peers are
+    /// still sent. Can be changed at runtime via `Node::update_config`.
+    pub fn decisions_only(mut self) -> NodeBuilder {
+        self.tunables.decisions_only = true;
+        self
+    }
+
+    /// Starts the node with the given ID instead of newly generated keys. Unless the node is the
+    /// first one, it will still be relocated to a new name when joining.
+    #[cfg(feature = "use-mock-crust")]
+    pub fn full_id(self, full_id: FullId) -> NodeBuilder {
+        NodeBuilder {
+            full_id: Some(full_id),
+            ..self
+        }
+    }
+
+    /// Seeds the RNG the node draws its nonces and message IDs from, so that replaying its inputs
+    /// via `Node::replay_input_log` reproduces them.
+    #[cfg(feature = "use-mock-crust")]
+    pub fn rng_seed(mut self, seed: [u32; 4]) -> NodeBuilder {
+        self.tunables.rng_seed = Some(seed);
+        self
+    }
+
+    /// Records every Crust event and action the node consumes, to be retrieved via
+    /// `Node::take_input_log`.
+    #[cfg(feature = "use-mock-crust")]
+    pub fn record_inputs(self) -> NodeBuilder {
+        NodeBuilder {
+            record_inputs: true,
+            ..self
+        }
+    }
+
     /// Creates new `Node`.
     ///
     /// It will automatically connect to the network in the same way a client does, but then
     /// request a new name and integrate itself into the network using the new name.
     ///
     /// The initial `Node` object will have newly generated keys.
-    pub fn create(self, min_section_size: usize) -> Result<Node, RoutingError> {
+    pub fn create(mut self, min_section_size: usize) -> Result<Node, RoutingError> {
         // If we're not in a test environment where we might want to manually seed the crypto RNG
         // then seed randomly.
-        #[cfg(not(feature = "use-mock-crust"))]
-        rust_sodium::init();
+        crypto::init()?;
+
+        if let Some(fanout) = self.tunables.group_fanout {
+            if fanout < group_quorum(min_section_size) {
+                return Err(RoutingError::InvalidConfig);
+            }
+        }
+
+        let mut ev_buffer = self.event_sink
+            .take()
+            .map_or_else(EventBuf::new, EventBuf::with_sink);
+        let message_ids = ExpiringCache::with_capacity("message_ids",
+                                                       self.tunables.message_id_retry_window,
+                                                       self.tunables.message_id_retry_capacity);
+        let retry_safe_ids = ExpiringCache::with_capacity("retry_safe_ids",
+                                                          self.tunables.message_id_retry_window,
+                                                          self.tunables.message_id_retry_capacity);
+
+        let (state_persistence, restored_id, bootstrap_contacts) = match self.state_store.take() {
+            Some((store, interval, include_keys)) => {
+                let mut state_persistence = StatePersistence::new(store,
+                                                                  interval,
+                                                                  include_keys,
+                                                                  min_section_size,
+                                                                  &self.tunables);
+                let (restored_id, bootstrap_contacts) = match state_persistence.load() {
+                    Ok(Some(restored)) => {
+                        ev_buffer.send_event(Event::StateRestored(restored.contacts));
+                        (restored.full_id, restored.bootstrap_addrs)
+                    }
+                    Ok(None) => (None, Vec::new()),
+                    Err(reason) => {
+                        warn!("Discarding the saved state: {:?}", reason);
+                        ev_buffer.send_event(Event::StateDiscarded(reason));
+                        (None, Vec::new())
+                    }
+                };
+                (Some(state_persistence), restored_id, bootstrap_contacts)
+            }
+            None => (None, None, Vec::new()),
+        };
 
-        let mut ev_buffer = EventBuf::new();
+        #[cfg(feature = "use-mock-crust")]
+        let record_inputs = self.record_inputs;
+        let coalescing_window = self.tunables.event_coalescing_window;
 
         // start the handler for routing without a restriction to become a full node
-        let (_, machine) = self.make_state_machine(min_section_size, &mut ev_buffer);
+        let (_, machine) = self.make_state_machine(min_section_size,
+                                                   state_persistence,
+                                                   restored_id,
+                                                   bootstrap_contacts,
+                                                   &mut ev_buffer);
+        if let Some(window) = coalescing_window {
+            ev_buffer.enable_coalescing(window, machine.timer());
+        }
+        #[cfg(feature = "use-mock-crust")]
+        let machine = if record_inputs {
+            machine.with_input_log()
+        } else {
+            machine
+        };
 
         let (tx, rx) = channel();
 
@@ -98,22 +481,36 @@ impl NodeBuilder {
                interface_result_rx: rx,
                machine: machine,
                event_buffer: ev_buffer,
+               message_ids: message_ids,
+               retry_safe_ids: retry_safe_ids,
            })
     }
 
     fn make_state_machine(self,
                           min_section_size: usize,
+                          state_persistence: Option<StatePersistence>,
+                          restored_id: Option<FullId>,
+                          bootstrap_contacts: Vec<SocketAddr>,
                           outbox: &mut EventBox)
                           -> (RoutingActionSender, StateMachine) {
-        let full_id = FullId::new();
+        #[cfg(feature = "use-mock-crust")]
+        let full_id = self.full_id
+            .clone()
+            .or(restored_id)
+            .unwrap_or_else(FullId::new);
+        #[cfg(not(feature = "use-mock-crust"))]
+        let full_id = restored_id.unwrap_or_else(FullId::new);
         let pub_id = *full_id.public_id();
         StateMachine::new(move |action_sender, crust_service, timer, outbox2| if self.first {
                               if let Some(state) = states::Node::first(action_sender,
                                                                        self.cache,
+                                                                       self.forwarding_sink,
+                                                                       state_persistence,
                                                                        crust_service,
                                                                        full_id,
                                                                        min_section_size,
-                                                                       timer) {
+                                                                       timer,
+                                                                       self.tunables) {
                                   State::Node(state)
                               } else {
                                   State::Terminated
@@ -126,14 +523,18 @@ impl NodeBuilder {
         } else {
             Bootstrapping::new(action_sender,
                                self.cache,
+                               self.forwarding_sink,
+                               state_persistence,
                                BootstrappingTargetState::JoiningNode,
                                crust_service,
                                full_id,
                                min_section_size,
-                               timer)
+                               timer,
+                               self.tunables)
                     .map_or(State::Terminated, State::Bootstrapping)
         },
                           pub_id,
+                          bootstrap_contacts,
                           outbox)
     }
 }
@@ -150,6 +551,10 @@ pub struct Node {
     interface_result_rx: Receiver<Result<(), InterfaceError>>,
     machine: StateMachine,
     event_buffer: EventBuf,
+    /// `MessageId`s recently assigned by `message_id_for`, by content hash and correlation ID.
+    message_ids: ExpiringCache<(Digest256, Option<u64>), MessageId>,
+    /// The `MessageId`s in `message_ids`, to recognise messages sent with them as retry-safe.
+    retry_safe_ids: ExpiringCache<MessageId, ()>,
 }
 
 impl Node {
@@ -159,9 +564,38 @@ impl Node {
             cache: Box::new(NullCache),
             first: false,
             deny_other_local_nodes: false,
+            tunables: Tunables::default(),
+            event_sink: None,
+            forwarding_sink: None,
+            state_store: None,
+            #[cfg(feature = "use-mock-crust")]
+            full_id: None,
+            #[cfg(feature = "use-mock-crust")]
+            record_inputs: false,
         }
     }
 
+    /// Returns the `MessageId` to use for a request with the given content and optional
+    /// correlation ID.
+    ///
+    /// Calls with the same arguments within the retry window of the previous one return the same
+    /// ID, so that a retried request is deduplicated by the network instead of being handled as a
+    /// new one. Once the window has elapsed, a new ID is assigned.
+    ///
+    /// A message sent with such an ID is acknowledged with `Event::MessageSent`. If the same
+    /// message is sent again within the window, the wire messages of the first send are resent
+    /// unchanged instead of signing it anew.
+    pub fn message_id_for(&mut self, content: &[u8], correlation_id: Option<u64>) -> MessageId {
+        let key = (sha3_256(content), correlation_id);
+        let msg_id = match self.message_ids.get(&key) {
+            Some(msg_id) => *msg_id,
+            None => MessageId::new(),
+        };
+        let _ = self.message_ids.insert(key, msg_id);
+        let _ = self.retry_safe_ids.insert(msg_id, ());
+        msg_id
+    }
+
     /// Send a `Get` request to `dst` to retrieve data from the network.
     pub fn send_get_request(&mut self,
                             src: Authority<XorName>,
@@ -381,6 +815,48 @@ impl Node {
         self.send_action(src, dst, user_msg, CLIENT_GET_PRIORITY)
     }
 
+    /// Send the given request from `src` to `dst`, with the priority the method for its type
+    /// uses.
+    pub fn send_request(&mut self,
+                        src: Authority<XorName>,
+                        dst: Authority<XorName>,
+                        request: Request)
+                        -> Result<(), InterfaceError> {
+        let priority = request_priority(&request);
+        self.send_action(src, dst, UserMessage::Request(request), priority)
+    }
+
+    /// Send each of the given requests from `src` to its destination. A failure to send one of
+    /// them doesn't affect the others. Each is sent with the priority the method for its type
+    /// uses. Once all have been handed to the network, a single `Event::BatchSent` with the given
+    /// `batch_id` reports which destinations succeeded.
+    pub fn send_request_batch(&mut self,
+                              src: Authority<XorName>,
+                              requests: Vec<(Authority<XorName>, Request)>,
+                              batch_id: u64)
+                              -> Result<(), InterfaceError> {
+        // Make sure the state machine has processed any outstanding crust events.
+        self.poll();
+
+        let messages = requests
+            .into_iter()
+            .map(|(dst, request)| {
+                     let priority = request_priority(&request);
+                     (dst, UserMessage::Request(request), priority)
+                 })
+            .collect();
+        let action = Action::NodeSendBatch {
+            src: src,
+            messages: messages,
+            batch_id: batch_id,
+            result_tx: self.interface_result_tx.clone(),
+        };
+
+        self.machine.handle_action(action, &mut self.event_buffer);
+
+        self.receive_action_result(&self.interface_result_rx)?
+    }
+
     /// Send a `Refresh` request from `src` to `dst` to trigger churn.
     pub fn send_refresh_request(&mut self,
                                 src: Authority<XorName>,
@@ -393,7 +869,8 @@ impl Node {
     }
 
     /// Returns the first `count` names of the nodes in the routing table which are closest
-    /// to the given one.
+    /// to the given one, in the order of `Xorable::cmp_closeness`, or `None` if we are not among
+    /// them.
     pub fn close_group(&self, name: XorName, count: usize) -> Option<Vec<XorName>> {
         self.machine.close_group(name, count)
     }
@@ -403,6 +880,150 @@ impl Node {
         self.machine.id().ok_or(RoutingError::Terminated)
     }
 
+    /// Returns the current sizes and counters of this node's message filters and caches.
+    pub fn diagnostics(&mut self) -> Result<Diagnostics, InterfaceError> {
+        let (result_tx, result_rx) = channel();
+        let action = Action::GetStats { result_tx: result_tx };
+
+        self.machine.handle_action(action, &mut self.event_buffer);
+
+        let mut diagnostics = self.receive_action_result(&result_rx)?;
+        self.message_ids.report(&mut diagnostics.caches);
+        self.retry_safe_ids.report(&mut diagnostics.caches);
+        Ok(diagnostics)
+    }
+
+    /// Disconnects from the peer with the given name and removes it from the routing table.
+    pub fn disconnect_peer(&mut self, name: XorName) -> Result<(), InterfaceError> {
+        let (result_tx, result_rx) = channel();
+        let action = Action::DisconnectPeer {
+            name: name,
+            result_tx: result_tx,
+        };
+
+        self.machine.handle_action(action, &mut self.event_buffer);
+
+        self.receive_action_result(&result_rx)?
+    }
+
+    /// Disconnects from the peer with the given name, and refuses connections to and messages
+    /// from it until `duration` has elapsed. Then we reconnect to it if it was a routing table
+    /// entry and the table still needs it.
+    pub fn ban_peer(&mut self, name: XorName, duration: Duration) -> Result<(), InterfaceError> {
+        let (result_tx, result_rx) = channel();
+        let action = Action::BanPeer {
+            name: name,
+            duration: duration,
+            result_tx: result_tx,
+        };
+
+        self.machine.handle_action(action, &mut self.event_buffer);
+
+        self.receive_action_result(&result_rx)?
+    }
+
+    /// Disconnects from all peers and shuts the node down. `Event::Terminate` is the last event
+    /// raised; any actions requested afterwards fail.
+    pub fn terminate(&mut self) {
+        self.machine.handle_action(Action::Terminate, &mut self.event_buffer);
+    }
+
+    /// Returns the serialised decision log, to be decoded via `decode_decision_log`. The log is
+    /// empty unless enabled via `NodeBuilder::decision_log`.
+    pub fn decision_log(&mut self) -> Result<Vec<u8>, InterfaceError> {
+        let (result_tx, result_rx) = channel();
+        let action = Action::DumpDecisionLog { result_tx: result_tx };
+
+        self.machine.handle_action(action, &mut self.event_buffer);
+
+        self.receive_action_result(&result_rx)?
+    }
+
+    /// Sends an unsigned diagnostic ping to the directly connected peer with the given name. It
+    /// bypasses signatures and message filters, and is answered as soon as the peer receives it.
+    /// Returns a receiver for the round-trip time, which is disconnected instead if no reply
+    /// arrives within 30 seconds.
+    pub fn ping_peer(&mut self, name: XorName) -> Result<Receiver<Duration>, InterfaceError> {
+        let (reply_tx, reply_rx) = channel();
+        let (result_tx, result_rx) = channel();
+        let action = Action::PingPeer {
+            name: name,
+            reply_tx: reply_tx,
+            result_tx: result_tx,
+        };
+
+        self.machine.handle_action(action, &mut self.event_buffer);
+
+        self.receive_action_result(&result_rx)??;
+        Ok(reply_rx)
+    }
+
+    /// Replaces the connection quotas. Existing connections beyond a tightened quota are kept, and
+    /// only new ones are refused, except for unidentified connections: the oldest of them are
+    /// dropped immediately until the new quota is met.
+    pub fn set_connection_quotas(&mut self,
+                                 quotas: ConnectionQuotas)
+                                 -> Result<(), InterfaceError> {
+        let (result_tx, result_rx) = channel();
+        let action = Action::SetConnectionQuotas {
+            quotas: quotas,
+            result_tx: result_tx,
+        };
+
+        self.machine.handle_action(action, &mut self.event_buffer);
+
+        self.receive_action_result(&result_rx)?
+    }
+
+    /// Returns all settings of the node, including those changed since it started. Fails if the
+    /// node hasn't started joining the network yet, or is a client.
+    pub fn effective_config(&mut self) -> Result<EffectiveConfig, InterfaceError> {
+        let (result_tx, result_rx) = channel();
+        let action = Action::GetEffectiveConfig { result_tx: result_tx };
+
+        self.machine.handle_action(action, &mut self.event_buffer);
+
+        self.receive_action_result(&result_rx)?
+    }
+
+    /// Changes the given live settings of a joined node. Valid ones are applied at once; invalid
+    /// ones, and any startup settings, are refused and reported via `Event::ConfigRefused`. Then
+    /// `Event::ConfigUpdated` reports the settings now in effect.
+    pub fn update_config(&mut self, update: PartialConfig) -> Result<(), InterfaceError> {
+        let (result_tx, result_rx) = channel();
+        let action = Action::UpdateConfig {
+            update: update,
+            result_tx: result_tx,
+        };
+
+        self.machine.handle_action(action, &mut self.event_buffer);
+
+        self.receive_action_result(&result_rx)?
+    }
+
+    /// Returns the number of bytes that sending `request` from `src` to `dst` would put on the
+    /// wire for the first hop, including all the parts a large request is split into. Nothing is
+    /// sent or signed. For section sources, the signatures of all section members are counted.
+    pub fn estimate_request_size(&self,
+                                 src: Authority<XorName>,
+                                 dst: Authority<XorName>,
+                                 request: &Request)
+                                 -> Result<usize, RoutingError> {
+        self.machine
+            .estimate_wire_size(src, dst, &UserMessage::Request(request.clone()))
+    }
+
+    /// Returns the number of bytes that sending `response` from `src` to `dst` would put on the
+    /// wire for the first hop. See `estimate_request_size`.
+    pub fn estimate_response_size(&self,
+                                  src: Authority<XorName>,
+                                  dst: Authority<XorName>,
+                                  response: &Response)
+                                  -> Result<usize, RoutingError> {
+        self.machine
+            .estimate_wire_size(src, dst, &UserMessage::Response(response.clone()))
+    }
+
     /// Returns the routing table of this node.
     pub fn routing_table(&self) -> Result<&RoutingTable<XorName>, RoutingError> {
         self.machine
@@ -419,19 +1040,18 @@ impl Node {
         // Make sure the state machine has processed any outstanding crust events.
         self.poll();
 
+        let retry_safe = self.retry_safe_ids
+            .contains_key(&user_msg.message_id());
         let action = Action::NodeSendMessage {
             src: src,
             dst: dst,
             content: user_msg,
             priority: priority,
+            retry_safe: retry_safe,
             result_tx: self.interface_result_tx.clone(),
         };
 
-        let transition = self.machine
-            .current_mut()
-            .handle_action(action, &mut self.event_buffer);
-        self.machine
-            .apply_transition(transition, &mut self.event_buffer);
+        self.machine.handle_action(action, &mut self.event_buffer);
 
         self.receive_action_result(&self.interface_result_rx)?
     }
@@ -445,11 +1065,15 @@ impl EventStepper for Node {
     type Item = Event;
 
     fn produce_events(&mut self) -> Result<(), RecvError> {
-        self.machine.step(&mut self.event_buffer)
+        let result = self.machine.step(&mut self.event_buffer);
+        self.event_buffer.close_expired_windows();
+        result
     }
 
     fn try_produce_events(&mut self) -> Result<(), TryRecvError> {
-        self.machine.try_step(&mut self.event_buffer)
+        let result = self.machine.try_step(&mut self.event_buffer);
+        self.event_buffer.close_expired_windows();
+        result
     }
 
     fn pop_item(&mut self) -> Option<Event> {
@@ -507,6 +1131,74 @@ impl Node {
     pub fn clear_next_relocation_dst(&mut self) {
         self.machine.current_mut().set_next_relocation_dst(None)
     }
+
+    /// Feeds `bytes` to this node as a message from the connected peer at `endpoint`. It takes the
+    /// same path as a message received via Crust, and is handled on the next poll. See
+    /// `test_messages` for building messages with arbitrary authorities and signatures.
+    pub fn inject_message_for_test(&mut self,
+                                   endpoint: Endpoint,
+                                   bytes: Vec<u8>)
+                                   -> Result<(), InterfaceError> {
+        self.machine.current_mut().inject_message(endpoint, bytes)
+    }
+
+    /// Returns the errors this node encountered handling received messages since the last call,
+    /// oldest first.
+    pub fn take_message_errors(&mut self) -> Vec<RoutingError> {
+        self.machine.current_mut().take_message_errors()
+    }
+
+    /// Returns whether this node lost all its routing connections and is trying to rejoin its
+    /// section.
+    pub fn is_disconnected(&self) -> bool {
+        self.machine.current().is_disconnected()
+    }
+
+    /// Returns whether this node currently acts as, or as a member of, the given authority.
+    pub fn in_authority(&self, auth: &Authority<XorName>) -> bool {
+        self.machine.current().in_authority(auth)
+    }
+
+    /// Removes the given peer from the peer manager and routing table, but keeps the connection
+    /// to it, as if our state had drifted apart from Crust's.
+    pub fn forget_peer(&mut self, name: &XorName) {
+        self.machine.current_mut().forget_peer(name)
+    }
+
+    /// Returns the work this node still has to do, counting timeouts due within `horizon` from now.
+    pub fn pending_work(&self, horizon: Duration) -> PendingWork {
+        self.machine.pending_work(horizon)
+    }
+
+    /// Returns whether this node has no pending work, and no timeout due within `horizon`.
+    pub fn is_idle(&self, horizon: Duration) -> bool {
+        self.pending_work(horizon).is_idle()
+    }
+
+    /// Stops recording and returns the inputs this node consumed since it was created, if it was
+    /// built with `NodeBuilder::record_inputs`.
+    pub fn take_input_log(&mut self) -> InputLog {
+        self.machine.take_input_log()
+    }
+
+    /// Feeds the inputs recorded in `log` to this node, which must be freshly created with the
+    /// same keys, RNG seed and configuration as the recorded one, and must not be polled. Returns
+    /// the first step after which this node's decision log differs from the recorded one.
+    ///
+    /// Crust queries, such as whether a peer is connected, are still answered by this node's own
+    /// service, so only runs which don't depend on them replay exactly.
+    pub fn replay_input_log(&mut self, log: &InputLog) -> Result<(), ReplayDivergence> {
+        self.machine
+            .replay_input_log(log, &mut self.event_buffer)
+    }
+
+    /// Sends a connection info request to `dst` which claims to be from `claimed_id`, as a
+    /// misbehaving node would.
+    pub fn send_forged_connection_info_request(&mut self, claimed_id: PublicId, dst: XorName) {
+        self.machine
+            .current_mut()
+            .send_forged_connection_info_request(claimed_id, dst)
+    }
 }
 
 #[cfg(feature = "use-mock-crust")]
@@ -524,3 +1216,16 @@ impl Drop for Node {
         let _ = self.event_buffer.take_all();
     }
 }
+
+// The priority to send `request` with, as chosen by the methods for the individual request types.
+fn request_priority(request: &Request) -> u8 {
+    match *request {
+        Request::Get(..) |
+        Request::Refresh(..) => RELOCATE_PRIORITY,
+        Request::GetAccountInfo(..) => CLIENT_GET_PRIORITY,
+        Request::Put(..) |
+        Request::Post(..) |
+        Request::Delete(..) |
+        Request::Append(..) => DEFAULT_PRIORITY,
+    }
+}
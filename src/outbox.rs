@@ -21,9 +21,13 @@
 //! object handling the appropriate types of message.
 
 use event::Event;
+use event_coalescer::EventCoalescer;
+use event_sink::EventSink;
 use std::collections::VecDeque;
 use std::default::Default;
 use std::mem;
+use std::time::Duration;
+use timer::Timer;
 
 
 /// An event dispatcher. Collects things to deliver and "sends".
@@ -35,15 +39,24 @@ pub trait EventBox {
     fn send_event(&mut self, event: Event);
 }
 
-/// Implementor of `EventBox`; stores its events in a `VecDeque`.
+/// Implementor of `EventBox`; stores its events in a `VecDeque`, or hands them to an `EventSink`
+/// if it has one. Repetitive events are coalesced first, if enabled.
 #[derive(Default)]
 pub struct EventBuf {
     events: VecDeque<Event>,
+    sink: Option<Box<EventSink>>,
+    coalescer: Option<EventCoalescer>,
 }
 
 impl EventBox for EventBuf {
     fn send_event(&mut self, event: Event) {
-        self.events.push_back(event)
+        let events = match self.coalescer.as_mut() {
+            Some(coalescer) => coalescer.admit(event),
+            None => vec![event],
+        };
+        for event in events {
+            self.deliver(event);
+        }
     }
 }
 
@@ -53,6 +66,32 @@ impl EventBuf {
         Default::default()
     }
 
+    /// Create a box which hands all events to the given sink.
+    pub fn with_sink(sink: Box<EventSink>) -> Self {
+        EventBuf {
+            events: VecDeque::new(),
+            sink: Some(sink),
+            coalescer: None,
+        }
+    }
+
+    /// Withholds events repeating within `window` from now on, as described for
+    /// `NodeBuilder::event_coalescing`. The windows' timeouts are scheduled on `timer`.
+    pub fn enable_coalescing(&mut self, window: Duration, timer: Timer) {
+        self.coalescer = Some(EventCoalescer::new(window, timer));
+    }
+
+    /// Delivers the summaries of the coalescing windows which have elapsed.
+    pub fn close_expired_windows(&mut self) {
+        let events = match self.coalescer.as_mut() {
+            Some(coalescer) => coalescer.close_expired(),
+            None => return,
+        };
+        for event in events {
+            self.deliver(event);
+        }
+    }
+
     /// Take the first Event, if any is stored.
     pub fn take_first(&mut self) -> Option<Event> {
         self.events.pop_front()
@@ -62,6 +101,18 @@ impl EventBuf {
     pub fn take_all(&mut self) -> VecDeque<Event> {
         mem::replace(&mut self.events, Default::default())
     }
+
+    fn deliver(&mut self, event: Event) {
+        if let Some(sink) = self.sink.take() {
+            if sink.send(event).is_ok() {
+                self.sink = Some(sink);
+            } else {
+                warn!("Event sink closed. Storing further events in the buffer instead.");
+            }
+            return;
+        }
+        self.events.push_back(event)
+    }
 }
 
 impl Drop for EventBuf {
@@ -0,0 +1,98 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use sha3::Digest256;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+use tiny_keccak::sha3_256;
+
+/// Payloads of at least this many bytes are shared via a `PayloadPool`. Smaller ones aren't worth
+/// hashing.
+pub const MIN_POOLED_PAYLOAD_LEN: usize = 4 * 1024;
+
+/// A content-addressed store of message payloads, so that identical payloads held by several
+/// pending messages, e.g. the same chunk sent to several peers, are only kept in memory once.
+///
+/// The pool only holds weak references: an entry is dropped as soon as the last message referring
+/// to it is.
+pub struct PayloadPool {
+    entries: HashMap<Digest256, Weak<Vec<u8>>>,
+}
+
+impl PayloadPool {
+    pub fn new() -> PayloadPool {
+        PayloadPool { entries: HashMap::new() }
+    }
+
+    /// Returns a shared copy of `payload`, reusing the pooled one if an identical payload is
+    /// already held.
+    pub fn share(&mut self, payload: Vec<u8>) -> Rc<Vec<u8>> {
+        self.remove_dropped();
+        let hash = sha3_256(&payload);
+        if let Some(shared) = self.entries.get(&hash).and_then(Weak::upgrade) {
+            return shared;
+        }
+        let shared = Rc::new(payload);
+        let _ = self.entries.insert(hash, Rc::downgrade(&shared));
+        shared
+    }
+
+    /// Returns the number of distinct payloads currently held.
+    pub fn payload_count(&self) -> usize {
+        self.entries
+            .values()
+            .filter(|entry| entry.upgrade().is_some())
+            .count()
+    }
+
+    /// Returns the total size of the distinct payloads currently held, in bytes.
+    pub fn stored_bytes(&self) -> usize {
+        self.entries
+            .values()
+            .filter_map(Weak::upgrade)
+            .map(|payload| payload.len())
+            .sum()
+    }
+
+    fn remove_dropped(&mut self) {
+        self.entries.retain(|_, entry| entry.upgrade().is_some());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identical_payloads_stored_once() {
+        let mut pool = PayloadPool::new();
+        let first = pool.share(vec![1; 100]);
+        let second = pool.share(vec![1; 100]);
+        let other = pool.share(vec![2; 50]);
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!(pool.payload_count(), 2);
+        assert_eq!(pool.stored_bytes(), 150);
+
+        drop(first);
+        assert_eq!(pool.stored_bytes(), 150);
+        drop(second);
+        assert_eq!(pool.payload_count(), 1);
+        assert_eq!(pool.stored_bytes(), 50);
+        drop(other);
+        assert_eq!(pool.payload_count(), 0);
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+#[cfg(feature="use-mock-crust")]
+use fake_clock::FakeClock as Instant;
+use routing_table::Prefix;
+use std::collections::HashMap;
+use std::time::Duration;
+#[cfg(not(feature="use-mock-crust"))]
+use std::time::Instant;
+use xor_name::XorName;
+
+/// Our knowledge of the churn generations of other nodes, used to detect stale messages.
+///
+/// Each node counts the changes to its own section, and stamps churn-sensitive messages with that
+/// count. We expect a node's generation to be at least the one it last stamped a message with,
+/// plus the number of changes we have seen in its section since. A message stamped with an older
+/// generation, by more than the slack, was generated before a churn event and is stale.
+pub struct PeerGenerations {
+    /// The expected generation of each node, and when we last heard from or about it.
+    generations: HashMap<XorName, (u64, Instant)>,
+    expiry: Duration,
+    slack: u64,
+}
+
+impl PeerGenerations {
+    /// Returns a new instance, which forgets about nodes it hasn't heard from or about for
+    /// `expiry`, and tolerates generations up to `slack` older than expected.
+    pub fn new(expiry: Duration, slack: u64) -> PeerGenerations {
+        PeerGenerations {
+            generations: HashMap::new(),
+            expiry: expiry,
+            slack: slack,
+        }
+    }
+
+    /// Returns whether a message from `name` stamped with `generation` is fresh. If so, the
+    /// generation is expected of `name` from now on.
+    pub fn accept(&mut self, name: &XorName, generation: u64) -> bool {
+        self.remove_expired();
+        if let Some(&(expected, _)) = self.generations.get(name) {
+            if generation.saturating_add(self.slack) < expected {
+                return false;
+            }
+        }
+        let _ = self.generations
+            .insert(*name, (generation, Instant::now()));
+        true
+    }
+
+    /// Records a change to the section with the given prefix, which advances the expected
+    /// generation of all its members.
+    pub fn churned(&mut self, prefix: &Prefix<XorName>) {
+        self.remove_expired();
+        let now = Instant::now();
+        for (_, entry) in self.generations
+                .iter_mut()
+                .filter(|&(name, _)| prefix.matches(name)) {
+            *entry = (entry.0 + 1, now);
+        }
+    }
+
+    /// Forgets the generations expected of all peers, e.g. because we lost touch with them and
+    /// missed their churn.
+    pub fn clear(&mut self) {
+        self.generations.clear();
+    }
+
+    fn remove_expired(&mut self) {
+        let expiry = self.expiry;
+        self.generations
+            .retain(|_, &mut (_, last_heard)| last_heard.elapsed() < expiry);
+    }
+}
+
+#[cfg(all(test, feature = "use-mock-crust"))]
+mod tests {
+    use super::*;
+    use fake_clock::FakeClock;
+    use rand;
+
+    #[test]
+    fn messages_from_before_churn_are_stale() {
+        let mut generations = PeerGenerations::new(Duration::from_secs(60), 1);
+        let name: XorName = rand::random();
+        assert!(generations.accept(&name, 5));
+
+        // Within the slack, older messages are still accepted.
+        generations.churned(&Prefix::new(0, name));
+        assert!(generations.accept(&name, 5));
+        generations.churned(&Prefix::new(0, name));
+        generations.churned(&Prefix::new(0, name));
+        assert!(!generations.accept(&name, 5));
+        assert!(generations.accept(&name, 6));
+
+        // Churn in other sections doesn't affect the node.
+        let other_prefix = Prefix::new(1, name).sibling();
+        generations.churned(&other_prefix);
+        generations.churned(&other_prefix);
+        assert!(generations.accept(&name, 5));
+    }
+
+    #[test]
+    fn expected_generations_expire() {
+        let mut generations = PeerGenerations::new(Duration::from_secs(60), 0);
+        let name: XorName = rand::random();
+        assert!(generations.accept(&name, 5));
+        assert!(!generations.accept(&name, 4));
+        FakeClock::advance_time(60 * 1000);
+        assert!(generations.accept(&name, 4));
+    }
+}
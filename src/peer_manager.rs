@@ -17,12 +17,14 @@
 
 use {PrivConnectionInfo, PubConnectionInfo};
 use error::RoutingError;
+use expiring_cache::CacheStats;
 #[cfg(feature="use-mock-crust")]
 use fake_clock::FakeClock as Instant;
 use id::PublicId;
 use itertools::Itertools;
 use log::LogLevel;
 use messages::MessageContent;
+use peer_score_book::{PeerScoreBook, ScoreSnapshot, Violation};
 use rand;
 use resource_proof::ResourceProof;
 use resource_prover::RESOURCE_PROOF_DURATION_SECS;
@@ -35,6 +37,7 @@ use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::time::Duration;
 #[cfg(not(feature="use-mock-crust"))]
 use std::time::Instant;
+use tunables::Tunables;
 use types::MessageId;
 use xor_name::XorName;
 
@@ -46,6 +49,10 @@ const CONNECTING_PEER_TIMEOUT_SECS: u64 = 90;
 const CONNECTED_PEER_TIMEOUT_SECS: u64 = 60;
 /// Time (in seconds) after which a `VotedFor` candidate will be removed.
 const CANDIDATE_ACCEPT_TIMEOUT_SECS: u64 = 60;
+/// Number of times we ask Crust to prepare connection info for a peer before giving up.
+const MAX_CONNECTION_INFO_ATTEMPTS: usize = 3;
+/// Maximum number of peers whose misbehaviour scores are kept, and of banned peers.
+const MAX_SCORED_PEERS: usize = 1000;
 
 #[cfg(feature = "use-mock-crust")]
 #[doc(hidden)]
@@ -56,6 +63,16 @@ pub mod test_consts {
     pub const RESOURCE_PROOF_DURATION_SECS: u64 = super::RESOURCE_PROOF_DURATION_SECS;
     pub const CONNECTING_PEER_TIMEOUT_SECS: u64 = super::CONNECTING_PEER_TIMEOUT_SECS;
     pub const CONNECTED_PEER_TIMEOUT_SECS: u64 = super::CONNECTED_PEER_TIMEOUT_SECS;
+    pub const MAX_CONNECTION_INFO_ATTEMPTS: usize = super::MAX_CONNECTION_INFO_ATTEMPTS;
+    pub const MAX_PROTOCOL_VIOLATIONS: usize = ::tunables::MAX_PROTOCOL_VIOLATIONS;
+    pub const MAX_MALFORMED_MSG_STRIKES: usize = ::tunables::MAX_MALFORMED_MSG_STRIKES;
+    pub const MALFORMED_MSG_STRIKE_DECAY_SECS: u64 = ::tunables::MALFORMED_MSG_STRIKE_DECAY_SECS;
+    pub const MESSAGE_ID_RETRY_WINDOW_SECS: u64 = ::tunables::MESSAGE_ID_RETRY_WINDOW_SECS;
+    pub const MAX_PINGS_PER_WINDOW: usize = ::ping::MAX_PINGS_PER_WINDOW;
+    pub const PING_WINDOW_SECS: u64 = ::ping::PING_WINDOW_SECS;
+    pub const RECOVERY_RETRY_SECS: u64 = ::recovery::RECOVERY_RETRY_SECS;
+    pub const SLOW_MESSAGE_REPORT_INTERVAL_SECS: u64 =
+        ::processing_stats::SLOW_MESSAGE_REPORT_INTERVAL_SECS;
 }
 
 pub type SectionMap = BTreeMap<VersionedPrefix<XorName>, BTreeSet<PublicId>>;
@@ -74,6 +91,10 @@ pub enum Error {
     PeerNotFound,
     /// The peer is in a state that doesn't allow the requested operation.
     UnexpectedState,
+    /// Preparing connection info for the peer failed too many times.
+    ConnectionInfoAttemptsExhausted,
+    /// The peer's ID is our own.
+    OwnId,
 }
 
 impl fmt::Display for Error {
@@ -81,6 +102,10 @@ impl fmt::Display for Error {
         match *self {
             Error::PeerNotFound => write!(formatter, "Peer not found"),
             Error::UnexpectedState => write!(formatter, "Peer state does not allow operation"),
+            Error::ConnectionInfoAttemptsExhausted => {
+                write!(formatter, "Too many failed connection info preparations")
+            }
+            Error::OwnId => write!(formatter, "Peer ID is our own"),
         }
     }
 }
@@ -90,6 +115,10 @@ impl error::Error for Error {
         match *self {
             Error::PeerNotFound => "Peer not found",
             Error::UnexpectedState => "Peer state does not allow operation",
+            Error::ConnectionInfoAttemptsExhausted => {
+                "Too many failed connection info preparations"
+            }
+            Error::OwnId => "Peer ID is our own",
         }
     }
 }
@@ -397,22 +426,35 @@ struct ResourceProofChallenge {
 /// This keeps track of which nodes we know of, which ones we have tried to connect to, which IDs
 /// we have verified, whom we are directly connected to or via a tunnel.
 pub struct PeerManager {
-    connection_token_map: HashMap<u32, PublicId>,
+    /// Maps Crust's connection info tokens to the peer and the number of attempts so far.
+    connection_token_map: HashMap<u32, (PublicId, usize)>,
     peers: HashMap<PublicId, Peer>,
     routing_table: RoutingTable<XorName>,
     our_public_id: PublicId,
     candidate: Candidate,
+    /// The decaying protocol violation and malformed message scores of recently misbehaving
+    /// peers, and the names of peers we refuse to connect to until their ban has expired.
+    scores: PeerScoreBook,
+    /// The number of attempts to treat ourselves as a peer which were skipped.
+    self_skips: usize,
 }
 
 impl PeerManager {
-    /// Returns a new peer manager with no entries.
-    pub fn new(min_section_size: usize, our_public_id: PublicId) -> PeerManager {
+    /// Returns a new peer manager with no entries, applying the given tunables.
+    pub fn new(min_section_size: usize,
+               our_public_id: PublicId,
+               tunables: &Tunables)
+               -> PeerManager {
         PeerManager {
             connection_token_map: HashMap::new(),
             peers: HashMap::new(),
             routing_table: RoutingTable::new(*our_public_id.name(), min_section_size),
             our_public_id: our_public_id,
             candidate: Candidate::None,
+            scores: PeerScoreBook::new(MAX_SCORED_PEERS,
+                                       tunables.protocol_violation_rule,
+                                       tunables.malformed_msg_rule),
+            self_skips: 0,
         }
     }
 
@@ -683,6 +725,9 @@ impl PeerManager {
 
     /// Tries to add the given peer to the routing table.
     pub fn add_to_routing_table(&mut self, pub_id: &PublicId) -> Result<(), RoutingError> {
+        if self.skip_own_id(pub_id) {
+            return Err(RoutingError::InvalidPeer);
+        }
         let self_debug = format!("{:?}", self);
 
         let peer = if let Some(peer) = self.peers.get_mut(pub_id) {
@@ -887,6 +932,77 @@ impl PeerManager {
             .map(Peer::name)
     }
 
+    /// Records a protocol violation by the given peer. Returns `true` if the peer has just reached
+    /// the threshold of the protocol violation rule and should be disconnected.
+    pub fn record_violation(&mut self, pub_id: &PublicId) -> bool {
+        self.scores.record(pub_id, Violation::Protocol)
+    }
+
+    /// Returns whether `pub_id` is our own ID. If it is, the attempt to treat ourselves as a peer
+    /// is counted as skipped.
+    pub fn skip_own_id(&mut self, pub_id: &PublicId) -> bool {
+        if *pub_id != self.our_public_id {
+            return false;
+        }
+        trace!("{:?} Skipping our own ID as a peer.", self);
+        self.self_skips += 1;
+        true
+    }
+
+    /// Returns the number of attempts to treat ourselves as a peer which were skipped so far.
+    pub fn self_skips(&self) -> usize {
+        self.self_skips
+    }
+
+    /// Records a malformed message from the given peer. Strikes start to be forgotten once the
+    /// peer hasn't sent a malformed message for the malformed message rule's window. Returns
+    /// `true` if the peer has just reached the rule's threshold and should be disconnected.
+    pub fn record_malformed_message(&mut self, pub_id: &PublicId) -> bool {
+        self.scores.record(pub_id, Violation::MalformedMessage)
+    }
+
+    /// Returns the totals of the misbehaviour scores.
+    pub fn score_snapshot(&self) -> ScoreSnapshot {
+        self.scores.snapshot()
+    }
+
+    /// Refuses connections to and messages from the peer with the given name until `duration` has
+    /// elapsed. Replaces any previous ban of that peer.
+    pub fn ban_peer(&mut self, name: XorName, duration: Duration) {
+        self.scores.ban(name, duration);
+    }
+
+    /// Returns whether the peer with the given name is currently banned.
+    pub fn is_banned(&self, name: &XorName) -> bool {
+        self.scores.is_banned(name)
+    }
+
+    /// Removes expired bans and returns the number of peers which are still banned.
+    pub fn banned_peer_count(&mut self) -> usize {
+        self.scores.banned_count()
+    }
+
+    /// Removes expired bans.
+    pub fn remove_expired_bans(&mut self) {
+        self.scores.remove_expired_bans();
+    }
+
+    /// Adds the stats of the peer manager's expiring caches to `caches`.
+    pub fn report_caches(&mut self, caches: &mut BTreeMap<String, CacheStats>) {
+        self.scores.report_bans(caches);
+    }
+
+    /// Returns the proxy node's public ID if we have a proxy which is not in our routing table.
+    pub fn get_non_routing_proxy(&self) -> Option<&PublicId> {
+        self.peers
+            .values()
+            .find(|peer| match peer.state {
+                      PeerState::Proxy => true,
+                      _ => false,
+                  })
+            .map(Peer::pub_id)
+    }
+
     pub fn remove_expired_peers(&mut self) -> Vec<PublicId> {
         let remove_candidate = if self.candidate.is_expired() {
             match self.candidate {
@@ -923,8 +1039,39 @@ impl PeerManager {
             .count()
     }
 
+    /// Returns the public IDs of the connected peers which haven't identified themselves yet, the
+    /// oldest connection first.
+    pub fn unidentified_peers(&self) -> Vec<PublicId> {
+        let mut peers = self.peers
+            .values()
+            .filter(|peer| match peer.state {
+                        PeerState::Connected(_) => true,
+                        _ => false,
+                    })
+            .collect_vec();
+        peers.sort_by_key(|peer| peer.timestamp);
+        peers.into_iter().map(|peer| *peer.pub_id()).collect()
+    }
+
+    /// Returns the public IDs of all our peers, in any state.
+    pub fn peer_pub_ids(&self) -> Vec<PublicId> {
+        self.peers.keys().cloned().collect()
+    }
+
+    /// Returns the public IDs of the clients for which we act as a proxy.
+    pub fn client_pub_ids(&self) -> Vec<PublicId> {
+        self.peers
+            .values()
+            .filter(|peer| peer.is_client())
+            .map(|peer| *peer.pub_id())
+            .collect()
+    }
+
     /// Marks the given peer as direct-connected.
     pub fn connected_to(&mut self, pub_id: &PublicId) {
+        if self.skip_own_id(pub_id) {
+            return;
+        }
         if let Some(peer) = self.peers.get_mut(pub_id) {
             match peer.state {
                 // ConnectSuccess may be received after establishing a tunnel
@@ -1172,7 +1319,7 @@ impl PeerManager {
                                     token: u32,
                                     our_info: PrivConnectionInfo)
                                     -> Result<ConnectionInfoPreparedResult, Error> {
-        let pub_id = self.connection_token_map
+        let (pub_id, _) = self.connection_token_map
             .remove(&token)
             .ok_or(Error::PeerNotFound)?;
         let (us_as_src, them_as_dst, opt_their_info, valid, reconnecting) =
@@ -1223,6 +1370,9 @@ impl PeerManager {
                                     msg_id: MessageId)
                                     -> Result<ConnectionInfoReceivedResult, Error> {
         let pub_id = peer_info.id();
+        if self.skip_own_id(&pub_id) {
+            return Err(Error::OwnId);
+        }
 
         match self.peers.remove(&pub_id) {
             Some(Peer {
@@ -1289,7 +1439,7 @@ impl PeerManager {
                 };
                 self.insert_peer(Peer::new(pub_id, state, valid, reconnecting));
                 let token = rand::random();
-                let _ = self.connection_token_map.insert(token, pub_id);
+                let _ = self.connection_token_map.insert(token, (pub_id, 1));
                 Ok(ConnectionInfoReceivedResult::Prepare(token))
             }
         }
@@ -1303,6 +1453,9 @@ impl PeerManager {
                                 pub_id: PublicId,
                                 reconnecting_in: ReconnectingPeer)
                                 -> Option<u32> {
+        if self.skip_own_id(&pub_id) {
+            return None;
+        }
         let reconnecting = match self.get_peer(&pub_id) {
             Some(peer) => {
                 match *peer.state() {
@@ -1313,7 +1466,7 @@ impl PeerManager {
             None => reconnecting_in,
         };
         let token = rand::random();
-        let _ = self.connection_token_map.insert(token, pub_id);
+        let _ = self.connection_token_map.insert(token, (pub_id, 1));
         self.insert_peer(Peer::new(pub_id,
                                    PeerState::ConnectionInfoPreparing {
                                        us_as_src: src,
@@ -1326,12 +1479,23 @@ impl PeerManager {
     }
 
     /// If preparing connection info failed with the given token, prepares and returns a new token.
+    ///
+    /// After `MAX_CONNECTION_INFO_ATTEMPTS` failures the connection attempt is abandoned: the peer
+    /// is removed if it is still waiting for our connection info, and an error is returned.
     pub fn get_new_connection_info_token(&mut self, token: u32) -> Result<u32, Error> {
-        let pub_id = self.connection_token_map
+        let (pub_id, attempts) = self.connection_token_map
             .remove(&token)
             .ok_or(Error::PeerNotFound)?;
+        if attempts >= MAX_CONNECTION_INFO_ATTEMPTS {
+            if let Some(&PeerState::ConnectionInfoPreparing { .. }) =
+                self.get_peer(&pub_id).map(Peer::state) {
+                let _ = self.peers.remove(&pub_id);
+            }
+            return Err(Error::ConnectionInfoAttemptsExhausted);
+        }
         let new_token = rand::random();
-        let _ = self.connection_token_map.insert(new_token, pub_id);
+        let _ = self.connection_token_map
+            .insert(new_token, (pub_id, attempts + 1));
         Ok(new_token)
     }
 
@@ -1376,6 +1540,7 @@ impl PeerManager {
             self.candidate = Candidate::None;
         }
 
+        self.scores.forget(pub_id);
         if let Some(peer) = self.peers.remove(pub_id) {
             let removal_details = self.routing_table.remove(peer.name());
             Some((peer, removal_details))
@@ -1483,10 +1648,13 @@ impl fmt::Debug for PeerManager {
 #[cfg(all(test, feature = "use-mock-crust"))]
 mod tests {
     use super::*;
+    use fake_clock::FakeClock;
     use id::FullId;
     use mock_crust::Endpoint;
     use mock_crust::crust::{PrivConnectionInfo, PubConnectionInfo};
     use routing_table::Authority;
+    use tunables::{MALFORMED_MSG_STRIKE_DECAY_SECS, MAX_MALFORMED_MSG_STRIKES,
+                   MAX_PROTOCOL_VIOLATIONS};
     use types::MessageId;
     use xor_name::{XOR_NAME_LEN, XorName};
 
@@ -1499,7 +1667,7 @@ mod tests {
         let min_section_size = 8;
         let our_pub_id = *FullId::new().public_id();
         let their_pub_id = *FullId::new().public_id();
-        let mut peer_mgr = PeerManager::new(min_section_size, our_pub_id);
+        let mut peer_mgr = PeerManager::new(min_section_size, our_pub_id, &Tunables::default());
 
         let our_connection_info = PrivConnectionInfo {
             id: our_pub_id,
@@ -1546,12 +1714,158 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn protocol_violations() {
+        let min_section_size = 8;
+        let our_pub_id = *FullId::new().public_id();
+        let their_pub_id = *FullId::new().public_id();
+        let other_pub_id = *FullId::new().public_id();
+        let mut peer_mgr = PeerManager::new(min_section_size, our_pub_id, &Tunables::default());
+
+        // Violations are counted per peer, and the peer should be dropped once at the limit.
+        for _ in 1..MAX_PROTOCOL_VIOLATIONS {
+            assert!(!peer_mgr.record_violation(&their_pub_id));
+        }
+        assert!(!peer_mgr.record_violation(&other_pub_id));
+        assert!(peer_mgr.record_violation(&their_pub_id));
+        assert_eq!(MAX_PROTOCOL_VIOLATIONS + 1, peer_mgr.score_snapshot().protocol_violations);
+
+        // Removing the peer resets its count, but not the total.
+        let _ = peer_mgr.remove_peer(&their_pub_id);
+        assert!(!peer_mgr.record_violation(&their_pub_id));
+        assert_eq!(MAX_PROTOCOL_VIOLATIONS + 2, peer_mgr.score_snapshot().protocol_violations);
+    }
+
+    #[test]
+    pub fn malformed_message_strikes() {
+        let min_section_size = 8;
+        let our_pub_id = *FullId::new().public_id();
+        let their_pub_id = *FullId::new().public_id();
+        let mut peer_mgr = PeerManager::new(min_section_size, our_pub_id, &Tunables::default());
+
+        // Strikes decay if the peer behaves for long enough.
+        for _ in 1..MAX_MALFORMED_MSG_STRIKES {
+            assert!(!peer_mgr.record_malformed_message(&their_pub_id));
+        }
+        FakeClock::advance_time(MALFORMED_MSG_STRIKE_DECAY_SECS * 1000 + 1);
+        for _ in 1..MAX_MALFORMED_MSG_STRIKES {
+            assert!(!peer_mgr.record_malformed_message(&their_pub_id));
+        }
+        assert!(peer_mgr.record_malformed_message(&their_pub_id));
+        assert_eq!(2 * MAX_MALFORMED_MSG_STRIKES - 1,
+                   peer_mgr.score_snapshot().malformed_msgs);
+    }
+
+    #[test]
+    pub fn spread_out_malformed_message_strikes() {
+        let min_section_size = 8;
+        let our_pub_id = *FullId::new().public_id();
+        let their_pub_id = *FullId::new().public_id();
+        let mut peer_mgr = PeerManager::new(min_section_size, our_pub_id, &Tunables::default());
+
+        // Strikes still add up if they are spread over a minute, well within the decay time.
+        let interval_secs = 60 / (MAX_MALFORMED_MSG_STRIKES as u64 - 1);
+        for _ in 1..MAX_MALFORMED_MSG_STRIKES {
+            assert!(!peer_mgr.record_malformed_message(&their_pub_id));
+            FakeClock::advance_time(interval_secs * 1000);
+        }
+        assert!(peer_mgr.record_malformed_message(&their_pub_id));
+    }
+
+    #[test]
+    pub fn banned_peers() {
+        let min_section_size = 8;
+        let our_pub_id = *FullId::new().public_id();
+        let short_ban = *FullId::new().public_id().name();
+        let long_ban = *FullId::new().public_id().name();
+        let mut peer_mgr = PeerManager::new(min_section_size, our_pub_id, &Tunables::default());
+
+        peer_mgr.ban_peer(short_ban, Duration::from_secs(10));
+        peer_mgr.ban_peer(long_ban, Duration::from_secs(20));
+        assert!(peer_mgr.is_banned(&short_ban));
+        assert!(peer_mgr.is_banned(&long_ban));
+        assert_eq!(2, peer_mgr.banned_peer_count());
+
+        FakeClock::advance_time(10 * 1000);
+        assert!(!peer_mgr.is_banned(&short_ban));
+        assert!(peer_mgr.is_banned(&long_ban));
+        assert_eq!(1, peer_mgr.banned_peer_count());
+
+        FakeClock::advance_time(10 * 1000);
+        assert!(!peer_mgr.is_banned(&long_ban));
+        assert_eq!(0, peer_mgr.banned_peer_count());
+    }
+
+    #[test]
+    pub fn own_id_is_skipped() {
+        let min_section_size = 8;
+        let our_pub_id = *FullId::new().public_id();
+        let mut peer_mgr = PeerManager::new(min_section_size, our_pub_id, &Tunables::default());
+        let our_connection_info = PubConnectionInfo {
+            id: our_pub_id,
+            endpoint: Endpoint(0),
+        };
+
+        // We never prepare a connection to ourselves, nor accept one in our own name.
+        assert_eq!(None,
+                   peer_mgr.get_connection_token(node_auth(0),
+                                                 node_auth(1),
+                                                 our_pub_id,
+                                                 ReconnectingPeer::False));
+        match peer_mgr.connection_info_received(node_auth(0),
+                                                node_auth(1),
+                                                our_connection_info,
+                                                MessageId::new()) {
+            Err(Error::OwnId) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+
+        // Nor do we become a peer or routing table entry of our own.
+        peer_mgr.connected_to(&our_pub_id);
+        assert!(peer_mgr.get_peer(&our_pub_id).is_none());
+        match peer_mgr.add_to_routing_table(&our_pub_id) {
+            Err(RoutingError::InvalidPeer) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        assert_eq!(0, peer_mgr.routing_table().len());
+        assert_eq!(4, peer_mgr.self_skips());
+    }
+
+    #[test]
+    pub fn connection_info_unexpected_response() {
+        let min_section_size = 8;
+        let our_pub_id = *FullId::new().public_id();
+        let their_pub_id = *FullId::new().public_id();
+        let mut peer_mgr = PeerManager::new(min_section_size, our_pub_id, &Tunables::default());
+        let their_connection_info = PubConnectionInfo {
+            id: their_pub_id,
+            endpoint: Endpoint(1),
+        };
+
+        // The peer is our client, so it should not be sending us connection infos.
+        peer_mgr.insert_peer(Peer::new(their_pub_id,
+                                       PeerState::Client,
+                                       false,
+                                       ReconnectingPeer::False));
+        match peer_mgr.connection_info_received(node_auth(0),
+                                                node_auth(1),
+                                                their_connection_info,
+                                                MessageId::new()) {
+            Ok(ConnectionInfoReceivedResult::IsClient) => (),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+        match peer_mgr.get_peer(&their_pub_id).map(Peer::state) {
+            Some(&PeerState::Client) => (),
+            state => panic!("Unexpected state: {:?}", state),
+        }
+    }
+
     #[test]
     pub fn connection_info_receive_prepare() {
         let min_section_size = 8;
         let our_pub_id = *FullId::new().public_id();
         let their_pub_id = *FullId::new().public_id();
-        let mut peer_mgr = PeerManager::new(min_section_size, our_pub_id);
+        let mut peer_mgr = PeerManager::new(min_section_size, our_pub_id, &Tunables::default());
         let our_connection_info = PrivConnectionInfo {
             id: our_pub_id,
             endpoint: Endpoint(0),
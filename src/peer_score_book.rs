@@ -0,0 +1,318 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use expiring_cache::{CacheStats, ExpiringCache};
+#[cfg(feature="use-mock-crust")]
+use fake_clock::FakeClock as Instant;
+use id::PublicId;
+use lru_time_cache::LruCache;
+use std::collections::BTreeMap;
+use std::time::Duration;
+#[cfg(not(feature="use-mock-crust"))]
+use std::time::Instant;
+use tunables::ScoreRule;
+use xor_name::XorName;
+
+/// A kind of misbehaviour which is scored separately for each peer.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Violation {
+    /// A breach of the routing protocol, e.g. an unrequested connection info.
+    Protocol,
+    /// A message which couldn't be deserialised.
+    MalformedMessage,
+}
+
+const VIOLATION_KINDS: usize = 2;
+
+impl Violation {
+    fn index(&self) -> usize {
+        match *self {
+            Violation::Protocol => 0,
+            Violation::MalformedMessage => 1,
+        }
+    }
+}
+
+/// The totals kept by a `PeerScoreBook`, for the node's diagnostics.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ScoreSnapshot {
+    /// The number of peers with a score.
+    pub scored_peers: usize,
+    /// The number of protocol violations recorded so far.
+    pub protocol_violations: usize,
+    /// The number of malformed messages recorded so far.
+    pub malformed_msgs: usize,
+}
+
+// The decaying score of one kind of violation by one peer.
+#[derive(Clone, Copy, Debug)]
+struct Score {
+    value: f64,
+    last_violation: Instant,
+    // Whether the threshold has been reported as crossed, and the score not dropped below since.
+    crossed: bool,
+}
+
+/// Bounded per-peer scores for misbehaviour, which decay exponentially over time, and the names
+/// of the peers banned for misbehaving.
+///
+/// Once full, the peers which misbehaved least recently are forgotten first, and the earliest
+/// bans are lifted first.
+pub struct PeerScoreBook {
+    scores: LruCache<PublicId, [Option<Score>; VIOLATION_KINDS]>,
+    rules: [ScoreRule; VIOLATION_KINDS],
+    totals: [usize; VIOLATION_KINDS],
+    bans: ExpiringCache<XorName, ()>,
+}
+
+impl PeerScoreBook {
+    /// Returns a score book for at most `capacity` peers and as many bans, applying the given
+    /// rules to protocol violations and malformed messages respectively.
+    pub fn new(capacity: usize, protocol: ScoreRule, malformed_msg: ScoreRule) -> PeerScoreBook {
+        PeerScoreBook {
+            scores: LruCache::with_capacity(capacity),
+            rules: [protocol, malformed_msg],
+            totals: [0; VIOLATION_KINDS],
+            bans: ExpiringCache::with_capacity("banned_peers", Duration::from_secs(0), capacity),
+        }
+    }
+
+    /// Records a violation by the given peer. Returns `true` if this made the peer's decayed
+    /// score for this kind of violation reach the rule's threshold. This is only reported once,
+    /// until the score has decayed below the threshold again. If the rule says so, the peer is
+    /// also banned.
+    pub fn record(&mut self, pub_id: &PublicId, violation: Violation) -> bool {
+        let index = violation.index();
+        self.totals[index] += 1;
+        let rule = self.rules[index];
+        let now = Instant::now();
+        let mut scores = self.scores.remove(pub_id).unwrap_or([None; VIOLATION_KINDS]);
+        let mut score = scores[index].map_or(Score {
+                                                 value: 0.0,
+                                                 last_violation: now,
+                                                 crossed: false,
+                                             },
+                                             |score| decayed(score, rule));
+        score.value += 1.0;
+        score.last_violation = now;
+        let reached = rounded(score.value) >= rule.threshold;
+        let result = reached && !score.crossed;
+        score.crossed = reached;
+        scores[index] = Some(score);
+        let _ = self.scores.insert(*pub_id, scores);
+        if let (true, Some(duration)) = (result, rule.ban) {
+            self.ban(*pub_id.name(), duration);
+        }
+        result
+    }
+
+    /// Bans the peer with the given name until `duration` has elapsed. Replaces any previous ban
+    /// of that peer.
+    pub fn ban(&mut self, name: XorName, duration: Duration) {
+        let _ = self.bans.insert_with_ttl(name, (), duration);
+    }
+
+    /// Returns whether the peer with the given name is currently banned.
+    pub fn is_banned(&self, name: &XorName) -> bool {
+        self.bans.contains_key(name)
+    }
+
+    /// Lifts expired bans and returns the number of peers which are still banned.
+    pub fn banned_count(&mut self) -> usize {
+        self.bans.len()
+    }
+
+    /// Lifts expired bans.
+    pub fn remove_expired_bans(&mut self) {
+        let _ = self.bans.sweep();
+    }
+
+    /// Adds the stats of the bans to `caches`.
+    pub fn report_bans(&mut self, caches: &mut BTreeMap<String, CacheStats>) {
+        self.bans.report(caches);
+    }
+
+    /// Forgets all scores of the given peer.
+    pub fn forget(&mut self, pub_id: &PublicId) {
+        let _ = self.scores.remove(pub_id);
+    }
+
+    /// Returns the number of violations of the given kind recorded so far, including forgotten
+    /// ones.
+    pub fn total(&self, violation: Violation) -> usize {
+        self.totals[violation.index()]
+    }
+
+    /// Returns the current totals.
+    pub fn snapshot(&self) -> ScoreSnapshot {
+        ScoreSnapshot {
+            scored_peers: self.scores.len(),
+            protocol_violations: self.total(Violation::Protocol),
+            malformed_msgs: self.total(Violation::MalformedMessage),
+        }
+    }
+}
+
+// Returns the score decayed until now. It is kept in full while the peer misbehaves again within
+// the rule's window. Once the window has passed, it has decayed since the last violation. The
+// threshold counts as crossed only while the score hasn't dropped below it.
+fn decayed(score: Score, rule: ScoreRule) -> Score {
+    let elapsed = score.last_violation.elapsed();
+    let value = if elapsed <= rule.window {
+        score.value
+    } else {
+        score.value * 0.5f64.powf(secs(elapsed) / secs(rule.half_life))
+    };
+    Score {
+        value: value,
+        last_violation: score.last_violation,
+        crossed: score.crossed && rounded(value) >= rule.threshold,
+    }
+}
+
+fn rounded(value: f64) -> usize {
+    value.round() as usize
+}
+
+fn secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+#[cfg(all(test, feature = "use-mock-crust"))]
+mod tests {
+    use super::*;
+    use fake_clock::FakeClock;
+    use id::FullId;
+
+    fn rule(threshold: usize, half_life_secs: u64) -> ScoreRule {
+        ScoreRule {
+            threshold: threshold,
+            window: Duration::from_secs(0),
+            half_life: Duration::from_secs(half_life_secs),
+            ban: None,
+        }
+    }
+
+    #[test]
+    fn least_recently_scored_peers_evicted() {
+        let mut book = PeerScoreBook::new(2, rule(3, 60), rule(3, 60));
+        let pub_ids = (0..3)
+            .map(|_| *FullId::new().public_id())
+            .collect::<Vec<_>>();
+        for pub_id in &pub_ids {
+            assert!(!book.record(pub_id, Violation::Protocol));
+        }
+        assert_eq!(book.snapshot().scored_peers, 2);
+        assert_eq!(book.snapshot().protocol_violations, 3);
+
+        // The first peer's violation was forgotten, so it takes two more to reach the threshold.
+        assert!(!book.record(&pub_ids[0], Violation::Protocol));
+        assert!(!book.record(&pub_ids[0], Violation::Protocol));
+        assert!(book.record(&pub_ids[0], Violation::Protocol));
+    }
+
+    #[test]
+    fn scores_decay_below_threshold() {
+        let half_life_secs = 60;
+        let mut book = PeerScoreBook::new(10, rule(3, half_life_secs), rule(3, half_life_secs));
+        let pub_id = *FullId::new().public_id();
+        assert!(!book.record(&pub_id, Violation::MalformedMessage));
+        assert!(!book.record(&pub_id, Violation::MalformedMessage));
+        assert!(book.record(&pub_id, Violation::MalformedMessage));
+
+        // After two half-lives, the score is back at 0.75, so it takes two more strikes.
+        FakeClock::advance_time(2 * half_life_secs * 1000);
+        assert!(!book.record(&pub_id, Violation::MalformedMessage));
+        assert!(book.record(&pub_id, Violation::MalformedMessage));
+        assert_eq!(book.snapshot().malformed_msgs, 5);
+    }
+
+    #[test]
+    fn scores_kept_within_window() {
+        let window_secs = 300;
+        let half_life_secs = 30;
+        let windowed_rule = ScoreRule {
+            window: Duration::from_secs(window_secs),
+            ..rule(3, half_life_secs)
+        };
+        let mut book = PeerScoreBook::new(10, windowed_rule, windowed_rule);
+        let pub_id = *FullId::new().public_id();
+
+        // Violations spread over several half-lives, but within the window of each other, add up.
+        assert!(!book.record(&pub_id, Violation::Protocol));
+        FakeClock::advance_time(window_secs * 1000);
+        assert!(!book.record(&pub_id, Violation::Protocol));
+        FakeClock::advance_time(window_secs * 1000);
+        assert!(book.record(&pub_id, Violation::Protocol));
+
+        // Once the window has passed without a violation, the score has decayed since the last
+        // one: after ten half-lives, it is practically forgotten.
+        FakeClock::advance_time(window_secs * 1000 + 1);
+        assert!(!book.record(&pub_id, Violation::Protocol));
+        assert!(!book.record(&pub_id, Violation::Protocol));
+        assert!(book.record(&pub_id, Violation::Protocol));
+    }
+
+    #[test]
+    fn threshold_crossing_bans_if_configured() {
+        let ban_secs = 60;
+        let banning_rule = ScoreRule {
+            ban: Some(Duration::from_secs(ban_secs)),
+            ..rule(2, 60)
+        };
+        let mut book = PeerScoreBook::new(10, banning_rule, rule(2, 60));
+        let pub_id = *FullId::new().public_id();
+        let other_id = *FullId::new().public_id();
+
+        // Only violations whose rule says so lead to a ban.
+        assert!(!book.record(&other_id, Violation::MalformedMessage));
+        assert!(book.record(&other_id, Violation::MalformedMessage));
+        assert!(!book.is_banned(other_id.name()));
+
+        assert!(!book.record(&pub_id, Violation::Protocol));
+        assert!(book.record(&pub_id, Violation::Protocol));
+        assert!(book.is_banned(pub_id.name()));
+        assert_eq!(book.banned_count(), 1);
+
+        // Forgetting the peer's scores doesn't lift the ban, but its expiry does.
+        book.forget(&pub_id);
+        assert!(book.is_banned(pub_id.name()));
+        FakeClock::advance_time(ban_secs * 1000);
+        assert!(!book.is_banned(pub_id.name()));
+        assert_eq!(book.banned_count(), 0);
+    }
+
+    #[test]
+    fn threshold_crossing_reported_once() {
+        let mut book = PeerScoreBook::new(10, rule(2, 60), rule(2, 60));
+        let pub_id = *FullId::new().public_id();
+        let other_id = *FullId::new().public_id();
+        assert!(!book.record(&pub_id, Violation::Protocol));
+        assert!(book.record(&pub_id, Violation::Protocol));
+        assert!(!book.record(&pub_id, Violation::Protocol));
+        assert!(!book.record(&pub_id, Violation::Protocol));
+
+        // Other peers and other kinds of violations are scored separately.
+        assert!(!book.record(&pub_id, Violation::MalformedMessage));
+        assert!(!book.record(&other_id, Violation::Protocol));
+
+        // Forgetting the peer starts it afresh.
+        book.forget(&pub_id);
+        assert!(!book.record(&pub_id, Violation::Protocol));
+        assert!(book.record(&pub_id, Violation::Protocol));
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+#[cfg(feature="use-mock-crust")]
+use fake_clock::FakeClock as Instant;
+use id::PublicId;
+use rand;
+use std::collections::HashMap;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+#[cfg(not(feature="use-mock-crust"))]
+use std::time::Instant;
+
+/// Duration (in seconds) after which a ping without a reply is given up.
+pub const PING_TIMEOUT_SECS: u64 = 30;
+/// The number of pings from a single peer answered within each `PING_WINDOW_SECS`. Any further
+/// ones are dropped.
+pub const MAX_PINGS_PER_WINDOW: usize = 10;
+/// Duration (in seconds) of the window inbound pings are rate-limited over.
+pub const PING_WINDOW_SECS: u64 = 1;
+
+/// Diagnostic pings to directly connected peers which are awaiting a reply, and the rate limits
+/// of pings received from peers.
+pub struct Pings {
+    /// The peer, send time and reply channel of each outstanding ping, by nonce.
+    pending: HashMap<u64, (PublicId, Instant, Sender<Duration>)>,
+    /// The start of the current window, and the number of pings received in it, per peer.
+    inbound: HashMap<PublicId, (Instant, usize)>,
+    answered: usize,
+    throttled: usize,
+}
+
+impl Pings {
+    pub fn new() -> Pings {
+        Pings {
+            pending: HashMap::new(),
+            inbound: HashMap::new(),
+            answered: 0,
+            throttled: 0,
+        }
+    }
+
+    /// Registers a ping to `pub_id`, whose round-trip time will be sent to `reply_tx`. Returns the
+    /// nonce to send with it.
+    pub fn start(&mut self, pub_id: PublicId, reply_tx: Sender<Duration>) -> u64 {
+        self.remove_expired();
+        let mut nonce = rand::random();
+        while self.pending.contains_key(&nonce) {
+            nonce = rand::random();
+        }
+        let _ = self.pending.insert(nonce, (pub_id, Instant::now(), reply_tx));
+        nonce
+    }
+
+    /// Handles the reply to a ping from `pub_id`, sending the round-trip time to the ping's reply
+    /// channel. Returns `false` if there is no such ping outstanding.
+    pub fn handle_pong(&mut self, pub_id: &PublicId, nonce: u64) -> bool {
+        self.remove_expired();
+        match self.pending.get(&nonce) {
+            Some(&(ref ping_dst, _, _)) if ping_dst == pub_id => (),
+            _ => return false,
+        }
+        if let Some((_, sent_at, reply_tx)) = self.pending.remove(&nonce) {
+            let _ = reply_tx.send(sent_at.elapsed());
+        }
+        true
+    }
+
+    /// Returns whether a ping received from `pub_id` should be answered, or dropped because the
+    /// peer exceeded its rate limit.
+    pub fn allow_ping(&mut self, pub_id: PublicId) -> bool {
+        let now = Instant::now();
+        let window = Duration::from_secs(PING_WINDOW_SECS);
+        let entry = self.inbound.entry(pub_id).or_insert((now, 0));
+        if entry.0.elapsed() >= window {
+            *entry = (now, 0);
+        }
+        if entry.1 >= MAX_PINGS_PER_WINDOW {
+            self.throttled += 1;
+            return false;
+        }
+        entry.1 += 1;
+        self.answered += 1;
+        true
+    }
+
+    /// Forgets the rate limit of a peer we are no longer connected to.
+    pub fn forget(&mut self, pub_id: &PublicId) {
+        let _ = self.inbound.remove(pub_id);
+    }
+
+    /// The number of pings we answered.
+    pub fn answered(&self) -> usize {
+        self.answered
+    }
+
+    /// The number of pings we dropped because their sender exceeded the rate limit.
+    pub fn throttled(&self) -> usize {
+        self.throttled
+    }
+
+    /// Drops the outstanding pings which timed out. This disconnects their reply channels.
+    fn remove_expired(&mut self) {
+        let timeout = Duration::from_secs(PING_TIMEOUT_SECS);
+        let expired: Vec<u64> = self.pending
+            .iter()
+            .filter(|&(_, &(_, sent_at, _))| sent_at.elapsed() >= timeout)
+            .map(|(nonce, _)| *nonce)
+            .collect();
+        for nonce in expired {
+            let _ = self.pending.remove(&nonce);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "use-mock-crust"))]
+mod tests {
+    use super::*;
+    use fake_clock::FakeClock;
+    use id::FullId;
+    use std::sync::mpsc;
+
+    #[test]
+    fn pong_reports_round_trip_time() {
+        let mut pings = Pings::new();
+        let pub_id = *FullId::new().public_id();
+        let other_id = *FullId::new().public_id();
+        let (reply_tx, reply_rx) = mpsc::channel();
+        let nonce = pings.start(pub_id, reply_tx);
+
+        // A reply from the wrong peer or with an unknown nonce is ignored.
+        assert!(!pings.handle_pong(&other_id, nonce));
+        assert!(!pings.handle_pong(&pub_id, nonce.wrapping_add(1)));
+        FakeClock::advance_time(7);
+        assert!(pings.handle_pong(&pub_id, nonce));
+        assert_eq!(unwrap!(reply_rx.try_recv()), Duration::from_millis(7));
+        assert!(!pings.handle_pong(&pub_id, nonce));
+
+        // A ping without a reply is given up after the timeout.
+        let (reply_tx, reply_rx) = mpsc::channel();
+        let nonce = pings.start(pub_id, reply_tx);
+        FakeClock::advance_time(PING_TIMEOUT_SECS * 1000);
+        assert!(!pings.handle_pong(&pub_id, nonce));
+        assert_eq!(reply_rx.try_recv(), Err(mpsc::TryRecvError::Disconnected));
+    }
+
+    #[test]
+    fn inbound_pings_rate_limited_per_peer() {
+        let mut pings = Pings::new();
+        let pub_id = *FullId::new().public_id();
+        let other_id = *FullId::new().public_id();
+        for _ in 0..MAX_PINGS_PER_WINDOW {
+            assert!(pings.allow_ping(pub_id));
+        }
+        assert!(!pings.allow_ping(pub_id));
+        assert!(pings.allow_ping(other_id));
+        assert_eq!(pings.answered(), MAX_PINGS_PER_WINDOW + 1);
+        assert_eq!(pings.throttled(), 1);
+
+        FakeClock::advance_time(PING_WINDOW_SECS * 1000);
+        assert!(pings.allow_ping(pub_id));
+    }
+}
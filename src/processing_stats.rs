@@ -0,0 +1,296 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+#[cfg(feature="use-mock-crust")]
+use fake_clock::FakeClock as Instant;
+#[cfg(feature="use-mock-crust")]
+use std::cell::RefCell;
+use std::time::Duration;
+#[cfg(not(feature="use-mock-crust"))]
+use std::time::Instant;
+
+/// The upper bounds (in microseconds, exclusive) of all but the last histogram bucket. The last
+/// bucket holds everything from one second upwards.
+const BUCKET_BOUNDS_MICROS: [u64; HISTOGRAM_BUCKETS - 1] =
+    [100, 1_000, 10_000, 100_000, 1_000_000];
+/// The number of buckets in each processing time histogram.
+pub const HISTOGRAM_BUCKETS: usize = 6;
+/// The number of phases message processing is split into.
+const PHASE_COUNT: usize = 5;
+/// Minimum duration (in seconds) between two reports of slow messages. Slow messages in between
+/// are only counted.
+pub const SLOW_MESSAGE_REPORT_INTERVAL_SECS: u64 = 10;
+
+/// A phase of handling a message received from a peer.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ProcessingPhase {
+    /// Deserialising the received bytes.
+    Decode,
+    /// Checking the signatures of the message.
+    Verify,
+    /// Checking the message against the filter of already handled messages.
+    Filter,
+    /// Handling a message addressed to us.
+    Dispatch,
+    /// Relaying the message to further peers.
+    Send,
+}
+
+impl ProcessingPhase {
+    fn index(&self) -> usize {
+        match *self {
+            ProcessingPhase::Decode => 0,
+            ProcessingPhase::Verify => 1,
+            ProcessingPhase::Filter => 2,
+            ProcessingPhase::Dispatch => 3,
+            ProcessingPhase::Send => 4,
+        }
+    }
+
+    fn from_index(index: usize) -> ProcessingPhase {
+        match index {
+            0 => ProcessingPhase::Decode,
+            1 => ProcessingPhase::Verify,
+            2 => ProcessingPhase::Filter,
+            3 => ProcessingPhase::Dispatch,
+            _ => ProcessingPhase::Send,
+        }
+    }
+}
+
+/// Histograms of the time each phase of message processing took, per message.
+///
+/// The buckets count messages whose phase took less than 100µs, 1ms, 10ms, 100ms and 1s
+/// respectively, and the last one those which took longer.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ProcessingHistograms {
+    buckets: [[u64; HISTOGRAM_BUCKETS]; PHASE_COUNT],
+}
+
+impl ProcessingHistograms {
+    /// Returns the bucket counts for the given phase.
+    pub fn phase(&self, phase: ProcessingPhase) -> &[u64; HISTOGRAM_BUCKETS] {
+        &self.buckets[phase.index()]
+    }
+
+    fn record(&mut self, phase: ProcessingPhase, duration: Duration) {
+        let micros = duration.as_secs() * 1_000_000 + u64::from(duration.subsec_nanos() / 1_000);
+        let bucket = BUCKET_BOUNDS_MICROS
+            .iter()
+            .position(|&bound| micros < bound)
+            .unwrap_or(HISTOGRAM_BUCKETS - 1);
+        self.buckets[phase.index()][bucket] += 1;
+    }
+}
+
+/// A message whose processing took longer than the configured threshold, as reported in
+/// `Event::SlowMessage`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SlowMessageReport {
+    /// The total time spent on the message.
+    pub total: Duration,
+    /// The phase which took longest.
+    pub dominant_phase: ProcessingPhase,
+    /// The time spent in the dominant phase.
+    pub dominant_duration: Duration,
+    /// The number of further slow messages since the previous report, which weren't reported
+    /// individually.
+    pub suppressed: usize,
+}
+
+/// Measures a single phase of message processing. Pass it to `ProcessingStats::record` when the
+/// phase is done.
+pub struct PhaseTimer {
+    phase: ProcessingPhase,
+    started: Instant,
+}
+
+impl PhaseTimer {
+    pub fn start(phase: ProcessingPhase) -> PhaseTimer {
+        PhaseTimer {
+            phase: phase,
+            started: Instant::now(),
+        }
+    }
+
+    #[cfg(not(feature="use-mock-crust"))]
+    fn stop(self) -> Duration {
+        self.started.elapsed()
+    }
+
+    #[cfg(feature="use-mock-crust")]
+    fn stop(self) -> Duration {
+        let cost = INJECTED_COSTS.with(|costs| costs.borrow()[self.phase.index()]);
+        if cost > 0 {
+            Instant::advance_time(cost);
+        }
+        self.started.elapsed()
+    }
+}
+
+/// The processing time histograms of a node, and the detection of individual slow messages.
+pub struct ProcessingStats {
+    histograms: ProcessingHistograms,
+    /// Messages taking at least this long are reported. `None` disables the reports.
+    slow_threshold: Option<Duration>,
+    /// The time spent in each phase by the message currently being processed, if any.
+    current: Option<[Option<Duration>; PHASE_COUNT]>,
+    last_report: Option<Instant>,
+    suppressed: usize,
+}
+
+impl ProcessingStats {
+    pub fn new(slow_threshold: Option<Duration>) -> ProcessingStats {
+        ProcessingStats {
+            histograms: Default::default(),
+            slow_threshold: slow_threshold,
+            current: None,
+            last_report: None,
+            suppressed: 0,
+        }
+    }
+
+    /// Starts timing a newly received message.
+    pub fn start_message(&mut self) {
+        self.current = Some([None; PHASE_COUNT]);
+    }
+
+    /// Adds the time measured by `timer` to the current message. Phases timed outside of a
+    /// message, e.g. while dispatching messages we sent to ourselves, are ignored.
+    pub fn record(&mut self, timer: PhaseTimer) {
+        let phase = timer.phase;
+        let duration = timer.stop();
+        if let Some(ref mut phases) = self.current {
+            let total = phases[phase.index()].unwrap_or_else(|| Duration::from_secs(0));
+            phases[phase.index()] = Some(total + duration);
+        }
+    }
+
+    /// Finishes timing the current message and adds its phases to the histograms. Returns a report
+    /// if the message was slow and no other one was reported within the last
+    /// `SLOW_MESSAGE_REPORT_INTERVAL_SECS`.
+    pub fn finish_message(&mut self) -> Option<SlowMessageReport> {
+        let phases = match self.current.take() {
+            Some(phases) => phases,
+            None => return None,
+        };
+        let mut total = Duration::from_secs(0);
+        let mut dominant = (ProcessingPhase::Decode, Duration::from_secs(0));
+        for (index, duration) in phases.iter().enumerate() {
+            if let Some(duration) = *duration {
+                let phase = ProcessingPhase::from_index(index);
+                self.histograms.record(phase, duration);
+                total += duration;
+                if duration > dominant.1 {
+                    dominant = (phase, duration);
+                }
+            }
+        }
+
+        match self.slow_threshold {
+            Some(threshold) if total >= threshold => (),
+            _ => return None,
+        }
+        let interval = Duration::from_secs(SLOW_MESSAGE_REPORT_INTERVAL_SECS);
+        if self.last_report
+               .map_or(false, |last_report| last_report.elapsed() < interval) {
+            self.suppressed += 1;
+            return None;
+        }
+        self.last_report = Some(Instant::now());
+        let suppressed = self.suppressed;
+        self.suppressed = 0;
+        Some(SlowMessageReport {
+                 total: total,
+                 dominant_phase: dominant.0,
+                 dominant_duration: dominant.1,
+                 suppressed: suppressed,
+             })
+    }
+
+    pub fn histograms(&self) -> ProcessingHistograms {
+        self.histograms
+    }
+}
+
+#[cfg(feature="use-mock-crust")]
+thread_local! {
+    static INJECTED_COSTS: RefCell<[u64; PHASE_COUNT]> = RefCell::new([0; PHASE_COUNT]);
+}
+
+/// Makes every subsequent timing of `phase` on this thread take (at least) `millis` milliseconds
+/// longer, by advancing the mock clock when the phase ends. Pass `0` to remove the cost again.
+#[cfg(feature="use-mock-crust")]
+pub fn inject_phase_cost(phase: ProcessingPhase, millis: u64) {
+    INJECTED_COSTS.with(|costs| costs.borrow_mut()[phase.index()] = millis);
+}
+
+#[cfg(all(test, feature = "use-mock-crust"))]
+mod tests {
+    use super::*;
+
+    fn time_message(stats: &mut ProcessingStats, phases: &[ProcessingPhase]) {
+        stats.start_message();
+        for phase in phases {
+            stats.record(PhaseTimer::start(*phase));
+        }
+    }
+
+    #[test]
+    fn histograms_and_slow_reports() {
+        let mut stats = ProcessingStats::new(Some(Duration::from_millis(50)));
+        let all = [ProcessingPhase::Decode, ProcessingPhase::Verify, ProcessingPhase::Filter];
+
+        time_message(&mut stats, &all);
+        assert_eq!(stats.finish_message(), None);
+        assert_eq!(stats.histograms().phase(ProcessingPhase::Verify),
+                   &[1, 0, 0, 0, 0, 0]);
+        assert_eq!(stats.histograms().phase(ProcessingPhase::Dispatch),
+                   &[0; HISTOGRAM_BUCKETS]);
+
+        inject_phase_cost(ProcessingPhase::Verify, 200);
+        time_message(&mut stats, &all);
+        inject_phase_cost(ProcessingPhase::Verify, 0);
+        let report = unwrap!(stats.finish_message());
+        assert_eq!(report.dominant_phase, ProcessingPhase::Verify);
+        assert_eq!(report.dominant_duration, Duration::from_millis(200));
+        assert_eq!(report.suppressed, 0);
+        assert_eq!(stats.histograms().phase(ProcessingPhase::Verify),
+                   &[1, 0, 0, 0, 1, 0]);
+        assert_eq!(stats.histograms().phase(ProcessingPhase::Decode),
+                   &[2, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn slow_reports_rate_limited() {
+        let mut stats = ProcessingStats::new(Some(Duration::from_millis(50)));
+        inject_phase_cost(ProcessingPhase::Dispatch, 1000);
+        let mut reports = Vec::new();
+        for _ in 0..20 {
+            time_message(&mut stats, &[ProcessingPhase::Dispatch]);
+            reports.extend(stats.finish_message());
+        }
+        inject_phase_cost(ProcessingPhase::Dispatch, 0);
+
+        // 20 messages of one second each span two report intervals.
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].suppressed, 0);
+        assert_eq!(reports[1].suppressed, 9);
+        assert_eq!(stats.histograms().phase(ProcessingPhase::Dispatch),
+                   &[0, 0, 0, 0, 0, 20]);
+    }
+}
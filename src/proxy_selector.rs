@@ -0,0 +1,214 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use id::PublicId;
+use std::time::Duration;
+use tunables::ProxyStrategy;
+use xor_name::XorName;
+
+struct ProxyEntry {
+    pub_id: PublicId,
+    /// The last measured round-trip time, if any.
+    latency: Option<Duration>,
+    /// The number of requests sent via this proxy.
+    sent: u64,
+}
+
+/// The proxies of a client, in the order they identified themselves, and the strategy choosing the
+/// one to send each request via.
+pub struct ProxySelector {
+    strategy: ProxyStrategy,
+    proxies: Vec<ProxyEntry>,
+    /// The index of the proxy chosen next by `ProxyStrategy::RoundRobin`.
+    next: usize,
+}
+
+impl ProxySelector {
+    pub fn new(strategy: ProxyStrategy) -> ProxySelector {
+        ProxySelector {
+            strategy: strategy,
+            proxies: Vec::new(),
+            next: 0,
+        }
+    }
+
+    pub fn set_strategy(&mut self, strategy: ProxyStrategy) {
+        self.strategy = strategy;
+    }
+
+    /// Adds a newly identified proxy. Returns `false` if it was already known.
+    pub fn add(&mut self, pub_id: PublicId) -> bool {
+        if self.contains(&pub_id) {
+            return false;
+        }
+        self.proxies
+            .push(ProxyEntry {
+                      pub_id: pub_id,
+                      latency: None,
+                      sent: 0,
+                  });
+        true
+    }
+
+    /// Takes a proxy which disconnected or stopped responding out of rotation. Returns `false` if it
+    /// wasn't known.
+    pub fn remove(&mut self, pub_id: &PublicId) -> bool {
+        match self.proxies.iter().position(|entry| entry.pub_id == *pub_id) {
+            Some(index) => {
+                let _ = self.proxies.remove(index);
+                if index < self.next {
+                    self.next -= 1;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains(&self, pub_id: &PublicId) -> bool {
+        self.proxies.iter().any(|entry| entry.pub_id == *pub_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.proxies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.proxies.is_empty()
+    }
+
+    /// Returns the proxy with the given name, if it is one of ours.
+    pub fn get(&self, name: &XorName) -> Option<&PublicId> {
+        self.proxies
+            .iter()
+            .find(|entry| entry.pub_id.name() == name)
+            .map(|entry| &entry.pub_id)
+    }
+
+    pub fn pub_ids(&self) -> Vec<PublicId> {
+        self.proxies.iter().map(|entry| entry.pub_id).collect()
+    }
+
+    /// Records a measured round-trip time to the given proxy.
+    pub fn record_latency(&mut self, pub_id: &PublicId, latency: Duration) {
+        if let Some(entry) = self.proxies.iter_mut().find(|entry| entry.pub_id == *pub_id) {
+            entry.latency = Some(latency);
+        }
+    }
+
+    /// Returns the number of requests sent via the given proxy.
+    pub fn sent_count(&self, pub_id: &PublicId) -> u64 {
+        self.proxies
+            .iter()
+            .find(|entry| entry.pub_id == *pub_id)
+            .map_or(0, |entry| entry.sent)
+    }
+
+    /// Chooses the proxy to send the next request via, according to the strategy, and counts the
+    /// request against it.
+    pub fn choose(&mut self) -> Option<PublicId> {
+        if self.proxies.is_empty() {
+            return None;
+        }
+        let index = match self.strategy {
+            ProxyStrategy::FirstIdentified => 0,
+            // Proxies without a measurement yet are only used if none has one.
+            ProxyStrategy::LowestLatency => {
+                self.proxies
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|&(_, entry)| (entry.latency.is_none(), entry.latency))
+                    .map_or(0, |(index, _)| index)
+            }
+            ProxyStrategy::RoundRobin => {
+                let index = self.next % self.proxies.len();
+                self.next = index + 1;
+                index
+            }
+        };
+        let entry = &mut self.proxies[index];
+        entry.sent += 1;
+        Some(entry.pub_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use id::FullId;
+
+    fn selector_with_proxies(strategy: ProxyStrategy,
+                             count: usize)
+                             -> (ProxySelector, Vec<PublicId>) {
+        let mut selector = ProxySelector::new(strategy);
+        let pub_ids: Vec<_> = (0..count).map(|_| *FullId::new().public_id()).collect();
+        for pub_id in &pub_ids {
+            assert!(selector.add(*pub_id));
+        }
+        (selector, pub_ids)
+    }
+
+    #[test]
+    fn first_identified() {
+        let (mut selector, pub_ids) = selector_with_proxies(ProxyStrategy::FirstIdentified, 2);
+        assert!(!selector.add(pub_ids[1]));
+        assert_eq!(selector.choose(), Some(pub_ids[0]));
+        assert_eq!(selector.choose(), Some(pub_ids[0]));
+        assert_eq!(selector.sent_count(&pub_ids[0]), 2);
+        assert_eq!(selector.sent_count(&pub_ids[1]), 0);
+
+        // Losing the first proxy fails over to the next one.
+        assert!(selector.remove(&pub_ids[0]));
+        assert_eq!(selector.choose(), Some(pub_ids[1]));
+        assert!(selector.remove(&pub_ids[1]));
+        assert_eq!(selector.choose(), None);
+    }
+
+    #[test]
+    fn round_robin() {
+        let (mut selector, pub_ids) = selector_with_proxies(ProxyStrategy::RoundRobin, 3);
+        let chosen: Vec<_> = (0..6).filter_map(|_| selector.choose()).collect();
+        assert_eq!(chosen,
+                   vec![pub_ids[0], pub_ids[1], pub_ids[2], pub_ids[0], pub_ids[1], pub_ids[2]]);
+        for pub_id in &pub_ids {
+            assert_eq!(selector.sent_count(pub_id), 2);
+        }
+
+        // A removed proxy is skipped without disturbing the order of the others.
+        assert_eq!(selector.choose(), Some(pub_ids[0]));
+        assert!(selector.remove(&pub_ids[0]));
+        assert_eq!(selector.choose(), Some(pub_ids[1]));
+        assert_eq!(selector.choose(), Some(pub_ids[2]));
+        assert_eq!(selector.choose(), Some(pub_ids[1]));
+    }
+
+    #[test]
+    fn lowest_latency() {
+        let (mut selector, pub_ids) = selector_with_proxies(ProxyStrategy::LowestLatency, 3);
+        assert_eq!(selector.choose(), Some(pub_ids[0]));
+
+        selector.record_latency(&pub_ids[0], Duration::from_millis(300));
+        selector.record_latency(&pub_ids[1], Duration::from_millis(20));
+        for _ in 0..3 {
+            assert_eq!(selector.choose(), Some(pub_ids[1]));
+        }
+
+        // Without the fast proxy, the measured one is preferred over the unmeasured one.
+        assert!(selector.remove(&pub_ids[1]));
+        assert_eq!(selector.choose(), Some(pub_ids[0]));
+    }
+}
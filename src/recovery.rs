@@ -0,0 +1,206 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+#[cfg(feature="use-mock-crust")]
+use fake_clock::FakeClock as Instant;
+use id::PublicId;
+use messages::UserMessage;
+use routing_table::Authority;
+use std::collections::VecDeque;
+use std::time::Duration;
+#[cfg(not(feature="use-mock-crust"))]
+use std::time::Instant;
+use xor_name::XorName;
+
+/// Interval between attempts to rejoin our section after losing all routing connections, in
+/// seconds.
+pub const RECOVERY_RETRY_SECS: u64 = 30;
+
+/// Routing peers whose connection we recently lost, oldest first. They are the peers a disconnected
+/// node tries to reconnect to, and the ones whose reconnection attempts we vote to approve again.
+/// Peers we disconnected from ourselves are not recorded.
+pub struct RecentPeers {
+    peers: VecDeque<(PublicId, Instant)>,
+    capacity: usize,
+    expiry: Duration,
+}
+
+impl RecentPeers {
+    pub fn new(capacity: usize, expiry: Duration) -> RecentPeers {
+        RecentPeers {
+            peers: VecDeque::new(),
+            capacity: capacity,
+            expiry: expiry,
+        }
+    }
+
+    /// Records that we lost the routing connection to `pub_id`.
+    pub fn insert(&mut self, pub_id: PublicId) {
+        self.remove_expired();
+        let _ = self.remove(&pub_id);
+        if self.peers.len() == self.capacity {
+            let _ = self.peers.pop_front();
+        }
+        self.peers.push_back((pub_id, Instant::now()));
+    }
+
+    /// Removes the given peer. Returns whether it was recently lost.
+    pub fn remove(&mut self, pub_id: &PublicId) -> bool {
+        self.remove_expired();
+        match self.peers.iter().position(|&(id, _)| id == *pub_id) {
+            Some(index) => self.peers.remove(index).is_some(),
+            None => false,
+        }
+    }
+
+    /// Returns whether the given peer was recently lost.
+    pub fn contains(&mut self, pub_id: &PublicId) -> bool {
+        self.remove_expired();
+        self.peers.iter().any(|&(id, _)| id == *pub_id)
+    }
+
+    /// Returns the peers lost within the expiry duration, most recent first.
+    pub fn pub_ids(&mut self) -> Vec<PublicId> {
+        self.remove_expired();
+        self.peers.iter().rev().map(|&(pub_id, _)| pub_id).collect()
+    }
+
+    fn remove_expired(&mut self) {
+        let expiry = self.expiry;
+        while self.peers
+                  .front()
+                  .map_or(false, |&(_, lost)| lost.elapsed() >= expiry) {
+            let _ = self.peers.pop_front();
+        }
+    }
+}
+
+/// A user message sent while we were disconnected, to be sent once we have rejoined.
+pub struct QueuedMessage {
+    pub src: Authority<XorName>,
+    pub dst: Authority<XorName>,
+    pub content: UserMessage,
+    pub priority: u8,
+}
+
+/// The state of a node which lost all its routing connections and is trying to rejoin its
+/// section.
+pub struct Recovery {
+    /// The routing peers we knew before we were disconnected.
+    known_peers: Vec<PublicId>,
+    /// The bootstrap contact we are rejoining through, and whether it has accepted us.
+    contact: Option<(PublicId, bool)>,
+    queue: VecDeque<QueuedMessage>,
+    queue_limit: usize,
+    since: Instant,
+}
+
+impl Recovery {
+    pub fn new(known_peers: Vec<PublicId>, queue_limit: usize) -> Recovery {
+        Recovery {
+            known_peers: known_peers,
+            contact: None,
+            queue: VecDeque::new(),
+            queue_limit: queue_limit,
+            since: Instant::now(),
+        }
+    }
+
+    pub fn known_peers(&self) -> &[PublicId] {
+        &self.known_peers
+    }
+
+    /// Returns the bootstrap contact we are rejoining through, if any, and whether it is already
+    /// acting as our proxy.
+    pub fn contact(&self) -> Option<(PublicId, bool)> {
+        self.contact
+    }
+
+    pub fn set_contact(&mut self, contact: Option<(PublicId, bool)>) {
+        self.contact = contact;
+    }
+
+    /// Queues a message to be sent once we have rejoined. Returns `false` if the queue is full.
+    pub fn queue(&mut self, msg: QueuedMessage) -> bool {
+        if self.queue.len() >= self.queue_limit {
+            return false;
+        }
+        self.queue.push_back(msg);
+        true
+    }
+
+    pub fn queued_count(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Returns how long we have been disconnected.
+    pub fn duration(&self) -> Duration {
+        self.since.elapsed()
+    }
+
+    /// Ends the recovery, returning the queued messages in the order they were sent.
+    pub fn into_queued(self) -> Vec<QueuedMessage> {
+        self.queue.into_iter().collect()
+    }
+}
+
+#[cfg(all(test, feature = "use-mock-crust"))]
+mod tests {
+    use super::*;
+    use fake_clock::FakeClock;
+    use id::FullId;
+    use messages::Request;
+    use types::MessageId;
+
+    #[test]
+    fn recent_peers_bounded_and_expire() {
+        let mut recent = RecentPeers::new(2, Duration::from_secs(60));
+        let pub_ids: Vec<_> = (0..3).map(|_| *FullId::new().public_id()).collect();
+        recent.insert(pub_ids[0]);
+        FakeClock::advance_time(30 * 1000);
+        recent.insert(pub_ids[1]);
+        recent.insert(pub_ids[2]);
+        assert_eq!(recent.pub_ids(), vec![pub_ids[2], pub_ids[1]]);
+
+        FakeClock::advance_time(30 * 1000);
+        assert!(!recent.contains(&pub_ids[0]));
+        assert!(recent.contains(&pub_ids[1]));
+        assert!(recent.remove(&pub_ids[1]));
+        assert!(!recent.remove(&pub_ids[1]));
+        FakeClock::advance_time(30 * 1000);
+        assert!(recent.pub_ids().is_empty());
+    }
+
+    #[test]
+    fn queue_is_bounded() {
+        let name = XorName([0; 32]);
+        let mut recovery = Recovery::new(vec![], 2);
+        let msg = || {
+            QueuedMessage {
+                src: Authority::ManagedNode(name),
+                dst: Authority::NaeManager(name),
+                content: UserMessage::Request(Request::Refresh(vec![], MessageId::new())),
+                priority: 0,
+            }
+        };
+        assert!(recovery.queue(msg()));
+        assert!(recovery.queue(msg()));
+        assert!(!recovery.queue(msg()));
+        assert_eq!(recovery.queued_count(), 2);
+        assert_eq!(recovery.into_queued().len(), 2);
+    }
+}
@@ -21,11 +21,14 @@ use maidsafe_utilities::serialisation::serialise;
 use message_filter::MessageFilter;
 use messages::RoutingMessage;
 use sha3;
+use stats::Diagnostics;
 use std::time::Duration;
 use tiny_keccak::sha3_256;
+use tunables::Tunables;
 
-const INCOMING_EXPIRY_DURATION_SECS: u64 = 60 * 20;
 const OUTGOING_EXPIRY_DURATION_SECS: u64 = 60 * 10;
+/// The maximum number of entries held by the outgoing filter.
+const OUTGOING_CAPACITY: usize = 100_000;
 
 /// An enum representing a result of message filtering
 #[derive(Eq, PartialEq)]
@@ -47,13 +50,23 @@ pub struct RoutingMessageFilter {
 
 impl RoutingMessageFilter {
     pub fn new() -> Self {
-        let incoming_duration = Duration::from_secs(INCOMING_EXPIRY_DURATION_SECS);
+        Self::with_tunables(&Tunables::default())
+    }
+
+    // Creates a filter whose incoming filters use the expiry duration and capacity of `tunables`.
+    pub fn with_tunables(tunables: &Tunables) -> Self {
+        let incoming_duration = tunables.filter_expiry;
+        let incoming_capacity = tunables.filter_capacity;
         let outgoing_duration = Duration::from_secs(OUTGOING_EXPIRY_DURATION_SECS);
 
         RoutingMessageFilter {
-            incoming: MessageFilter::with_expiry_duration(incoming_duration),
-            incoming_route: MessageFilter::with_expiry_duration(incoming_duration),
-            outgoing: LruCache::with_expiry_duration(outgoing_duration),
+            incoming: MessageFilter::with_expiry_duration_and_capacity(incoming_duration,
+                                                                       incoming_capacity),
+            incoming_route:
+                MessageFilter::with_expiry_duration_and_capacity(incoming_duration,
+                                                                 incoming_capacity),
+            outgoing: LruCache::with_expiry_duration_and_capacity(outgoing_duration,
+                                                                  OUTGOING_CAPACITY),
         }
     }
 
@@ -85,4 +98,46 @@ impl RoutingMessageFilter {
             false
         }
     }
+
+    // Returns the current sizes and counters of the filters.
+    pub fn diagnostics(&self) -> Diagnostics {
+        Diagnostics {
+            incoming_filter_len: self.incoming.len(),
+            incoming_filter_evictions: self.incoming.evictions(),
+            incoming_filter_hits: self.incoming.hits(),
+            outgoing_filter_len: self.outgoing.len(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(all(test, feature = "use-mock-crust"))]
+mod tests {
+    use super::*;
+    use fake_clock::FakeClock;
+    use messages::MessageContent;
+    use rand;
+    use routing_table::Authority;
+    use types::MessageId;
+
+    #[test]
+    fn incoming_expiry_from_tunables() {
+        let tunables = Tunables {
+            filter_expiry: Duration::from_secs(1),
+            ..Tunables::default()
+        };
+        let mut filter = RoutingMessageFilter::with_tunables(&tunables);
+        let msg = RoutingMessage {
+            src: Authority::ManagedNode(rand::random()),
+            dst: Authority::ManagedNode(rand::random()),
+            content: MessageContent::Relocate { message_id: MessageId::new() },
+        };
+
+        assert!(filter.filter_incoming(&msg, 0) == FilteringResult::NewMessage);
+        assert!(filter.filter_incoming(&msg, 0) == FilteringResult::KnownMessageAndRoute);
+
+        // Once the filter entry expired, the duplicate is handled like a new message again.
+        FakeClock::advance_time(1001);
+        assert!(filter.filter_incoming(&msg, 0) == FilteringResult::NewMessage);
+    }
 }
@@ -511,16 +511,15 @@ impl<T: Binary + Clone + Copy + Debug + Default + Hash + Xorable> RoutingTable<T
         }
     }
 
-    /// Finds the `count` names closest to `name` in the whole routing table.
+    /// Finds the `count` names closest to `name` in the whole routing table, ordered by
+    /// `Xorable::cmp_closeness`. The names of all sections are sorted together, as sections can be
+    /// equally close to `name` by prefix alone, and their order would then depend on which one is
+    /// our own.
     fn closest_known_names(&self, name: &T, count: usize) -> Vec<&T> {
         self.all_sections_iter()
-            .sorted_by(|&(pfx0, _), &(pfx1, _)| pfx0.cmp_distance(&pfx1, name))
+            .flat_map(|(_, (_, section))| section.iter())
+            .sorted_by(|name0, name1| name.cmp_closeness(name0, name1))
             .into_iter()
-            .flat_map(|(_, (_, section))| {
-                          section
-                              .iter()
-                              .sorted_by(|name0, name1| name.cmp_distance(name0, name1))
-                      })
             .take(count)
             .collect_vec()
     }
@@ -1065,7 +1064,7 @@ impl<T: Binary + Clone + Copy + Debug + Default + Hash + Xorable> RoutingTable<T
                                                            -> &'a T {
         let sorted_names = names
             .into_iter()
-            .sorted_by(|&lhs, &rhs| dst_name.cmp_distance(lhs, rhs));
+            .sorted_by(|&lhs, &rhs| dst_name.cmp_closeness(lhs, rhs));
         sorted_names[route % sorted_names.len()]
     }
 
@@ -1468,6 +1467,47 @@ mod tests {
         assert_eq!(*result[2], 0x0040);
     }
 
+    #[test]
+    fn test_closest_names_agree_across_sections() {
+        // Returns a table for `our_name` knowing 00, 10, ..., F0, in sections 00, 01 and 1.
+        let table_for = |our_name: u8| {
+            let mut table = RoutingTable::new(our_name, 1);
+            for name in (0u8..0x10).map(|i| i * 0x10).filter(|name| *name != our_name) {
+                unwrap!(table.add(name));
+            }
+            let _ = table.add_prefix(prefix_str("01").with_version(2));
+            assert_eq!(prefixes_from_strs(vec!["1", "00", "01"]), table.prefixes());
+            table
+        };
+
+        // Sections 00 and 01 are equally close to FF by prefix alone. The nodes in either of them
+        // still agree on the group, as it is determined by the distance of each name.
+        let tables = [table_for(0x00), table_for(0x40)];
+        let expected = vec![0xf0, 0xe0, 0xd0, 0xc0, 0xb0, 0xa0, 0x90, 0x80, 0x70, 0x60];
+        for table in &tables {
+            assert_eq!(expected,
+                       table
+                           .closest_known_names(&0xff, 10)
+                           .into_iter()
+                           .cloned()
+                           .collect_vec());
+        }
+        assert_eq!(unwrap!(tables[0].closest_names(&0xff, 16)),
+                   unwrap!(tables[1].closest_names(&0xff, 16)));
+
+        // With differing knowledge, the groups differ exactly where the knowledge does: a node
+        // which doesn't know 70 picks the next closest name in its place.
+        let mut table = table_for(0x00);
+        let _ = unwrap!(table.remove(&0x70));
+        let expected = vec![0xf0, 0xe0, 0xd0, 0xc0, 0xb0, 0xa0, 0x90, 0x80, 0x60, 0x50];
+        assert_eq!(expected,
+                   table
+                       .closest_known_names(&0xff, 10)
+                       .into_iter()
+                       .cloned()
+                       .collect_vec());
+    }
+
     #[test]
     fn test_add_prefix() {
         let our_name = 0u8;
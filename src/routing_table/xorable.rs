@@ -34,6 +34,17 @@ pub trait Xorable: Ord + Sized {
     /// equal if the arguments are equal.)
     fn cmp_distance(&self, lhs: &Self, rhs: &Self) -> Ordering;
 
+    /// Compares the closeness of the arguments to `self`: by XOR distance first, with the names
+    /// themselves as a tie-break. This is the total order all close groups are computed with, so
+    /// that nodes with the same knowledge of the network agree on the members of every group, no
+    /// matter in which order they come across the names.
+    fn cmp_closeness(&self, lhs: &Self, rhs: &Self) -> Ordering {
+        match self.cmp_distance(lhs, rhs) {
+            Ordering::Equal => lhs.cmp(rhs),
+            ordering => ordering,
+        }
+    }
+
     /// Returns `true` if the `i`-th bit is `1`.
     fn bit(&self, i: usize) -> bool;
 
@@ -348,6 +359,13 @@ mod tests {
                    [1u8, 2, 3, 4].cmp_distance(&[1, 2, 6, 4], &[1, 2, 7, 4]));
     }
 
+    #[test]
+    fn cmp_closeness() {
+        assert_eq!(Ordering::Equal, 42u8.cmp_closeness(&13, &13));
+        assert_eq!(Ordering::Less, 42u8.cmp_closeness(&44, &45));
+        assert_eq!(Ordering::Greater, [1u8, 2].cmp_closeness(&[1, 7], &[1, 6]));
+    }
+
     #[test]
     fn bit() {
         assert_eq!(false, 0b00101000u8.bit(0));
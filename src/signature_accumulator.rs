@@ -94,6 +94,17 @@ impl SignatureAccumulator {
         self.remove_if_complete(min_section_size, &hash)
     }
 
+    /// Returns the number of messages still accumulating, i.e. for which a message or signatures
+    /// have arrived but no quorum has been reached yet. Expired entries are removed first.
+    ///
+    /// Since entries are keyed by the hash of the whole routing message, contributions to messages
+    /// which differ in any way - e.g. refreshes triggered by different churn events, which carry
+    /// distinct message IDs - are accumulated separately.
+    pub fn pending_count(&mut self) -> usize {
+        self.remove_expired();
+        self.sigs.len() + self.msgs.len()
+    }
+
     fn remove_expired(&mut self) {
         let expired_sigs = self.sigs
             .iter()
@@ -137,11 +148,14 @@ mod tests {
     use super::*;
     use id::{FullId, PublicId};
     use itertools::Itertools;
-    use messages::{DirectMessage, MessageContent, RoutingMessage, SectionList, SignedMessage};
+    use messages::{DirectMessage, MessageContent, RELOCATE_PRIORITY, Request, RoutingMessage,
+                   SectionList, SignedMessage, UserMessage};
     use rand;
     use routing_table::Authority;
     use routing_table::Prefix;
     use std::collections::BTreeSet;
+    use types::MessageId;
+    use xor_name::XorName;
 
     struct MessageAndSignatures {
         signed_msg: SignedMessage,
@@ -162,6 +176,16 @@ mod tests {
                                                           .with_version(0),
                                                       rand::random()),
             };
+            MessageAndSignatures::with_routing_msg(routing_msg, msg_sender_id, other_ids, all_ids)
+        }
+
+        fn with_routing_msg<'a, I>(routing_msg: RoutingMessage,
+                                   msg_sender_id: &FullId,
+                                   other_ids: I,
+                                   all_ids: BTreeSet<PublicId>)
+                                   -> MessageAndSignatures
+            where I: Iterator<Item = &'a FullId>
+        {
             let prefix = Prefix::new(0, *unwrap!(all_ids.iter().next()).name());
             let lists = vec![SectionList::new(prefix, all_ids)];
             let signed_msg = unwrap!(SignedMessage::new(routing_msg, msg_sender_id, lists));
@@ -180,7 +204,7 @@ mod tests {
     }
 
     struct Env {
-        _msg_sender_id: FullId,
+        msg_sender_id: FullId,
         other_ids: Vec<FullId>,
         senders: BTreeSet<PublicId>,
         msgs_and_sigs: Vec<MessageAndSignatures>,
@@ -206,7 +230,7 @@ mod tests {
                      })
                 .collect();
             Env {
-                _msg_sender_id: msg_sender_id,
+                msg_sender_id: msg_sender_id,
                 other_ids: other_ids,
                 senders: pub_ids,
                 msgs_and_sigs: msgs_and_sigs,
@@ -334,4 +358,68 @@ mod tests {
                     });
             });
     }
+
+    #[test]
+    fn refresh_per_churn_event() {
+        let mut sig_accumulator = SignatureAccumulator::default();
+        let env = Env::new();
+        let section: XorName = rand::random();
+
+        // Two back-to-back churn events make the section send the same refresh payload twice, each
+        // time with the message ID derived from the node which caused the churn.
+        let refresh_msg = |msg_id| {
+            let user_msg = UserMessage::Request(Request::Refresh(vec![1, 2, 3], msg_id));
+            let mut parts = unwrap!(user_msg.to_parts(RELOCATE_PRIORITY));
+            assert_eq!(parts.len(), 1);
+            RoutingMessage {
+                src: Authority::NaeManager(section),
+                dst: Authority::NaeManager(section),
+                content: unwrap!(parts.pop()),
+            }
+        };
+        let msgs_and_sigs = vec![MessageId::from_added_node(rand::random()),
+                                 MessageId::from_lost_node(rand::random())]
+                .into_iter()
+                .map(|msg_id| {
+                         MessageAndSignatures::with_routing_msg(refresh_msg(msg_id),
+                                                                &env.msg_sender_id,
+                                                                env.other_ids.iter(),
+                                                                env.senders.clone())
+                     })
+                .collect_vec();
+
+        for msg_and_sigs in &msgs_and_sigs {
+            let signed_msg = msg_and_sigs.signed_msg.clone();
+            assert!(sig_accumulator
+                        .add_message(signed_msg, env.num_nodes(), 0)
+                        .is_none());
+        }
+        assert_eq!(sig_accumulator.pending_count(), 2);
+
+        // Interleave the signatures for both churn events: each must accumulate on its own.
+        let mut accumulated = vec![false; msgs_and_sigs.len()];
+        for (signer_index, full_id) in env.other_ids.iter().enumerate() {
+            for (msg_index, msg_and_sigs) in msgs_and_sigs.iter().enumerate() {
+                let result = match msg_and_sigs.signature_msgs[signer_index] {
+                    DirectMessage::MessageSignature(hash, sig) => {
+                        sig_accumulator.add_signature(env.num_nodes(),
+                                                      hash,
+                                                      sig,
+                                                      *full_id.public_id())
+                    }
+                    ref unexpected_msg => panic!("Unexpected message: {:?}", unexpected_msg),
+                };
+
+                if let Some((returned_msg, _)) = result {
+                    assert!(!accumulated[msg_index]);
+                    accumulated[msg_index] = true;
+                    assert_eq!(msg_and_sigs.signed_msg.routing_message(),
+                               returned_msg.routing_message());
+                    assert!(returned_msg.check_fully_signed(env.num_nodes()));
+                }
+            }
+        }
+
+        assert!(accumulated.iter().all(|&accumulated| accumulated));
+    }
 }
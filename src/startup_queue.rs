@@ -0,0 +1,98 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use CrustEvent;
+use id::PublicId;
+use std::collections::VecDeque;
+
+/// Crust events which arrived before the node was ready to handle them, i.e. before it became a
+/// client or a node with its relocated name. They are replayed in arrival order once it is ready.
+pub struct StartupQueue {
+    events: VecDeque<CrustEvent<PublicId>>,
+    capacity: usize,
+    dropped: usize,
+}
+
+impl StartupQueue {
+    /// Returns an empty queue holding at most `capacity` events.
+    pub fn new(capacity: usize) -> StartupQueue {
+        StartupQueue {
+            events: VecDeque::new(),
+            capacity: capacity,
+            dropped: 0,
+        }
+    }
+
+    /// Parks the event until the node is ready. If the queue is full, the oldest event is dropped
+    /// to make room for it.
+    pub fn push(&mut self, event: CrustEvent<PublicId>) {
+        if self.capacity == 0 {
+            self.dropped += 1;
+            return;
+        }
+        if self.events.len() == self.capacity {
+            let _ = self.events.pop_front();
+            self.dropped += 1;
+        }
+        self.events.push_back(event);
+    }
+
+    /// Returns the parked events, oldest first, and the number of events dropped.
+    pub fn into_events(self) -> (Vec<CrustEvent<PublicId>>, usize) {
+        (self.events.into_iter().collect(), self.dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use id::FullId;
+
+    fn payloads(events: Vec<CrustEvent<PublicId>>) -> Vec<u8> {
+        events
+            .into_iter()
+            .map(|event| match event {
+                     CrustEvent::NewMessage(_, bytes) => bytes[0],
+                     event => panic!("Unexpected {:?}", event),
+                 })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_arrival_order() {
+        let pub_id = *FullId::new().public_id();
+        let mut queue = StartupQueue::new(3);
+        for i in 0..3 {
+            queue.push(CrustEvent::NewMessage(pub_id, vec![i]));
+        }
+        let (events, dropped) = queue.into_events();
+        assert_eq!(payloads(events), vec![0, 1, 2]);
+        assert_eq!(dropped, 0);
+    }
+
+    #[test]
+    fn drops_oldest_on_overflow() {
+        let pub_id = *FullId::new().public_id();
+        let mut queue = StartupQueue::new(3);
+        for i in 0..5 {
+            queue.push(CrustEvent::NewMessage(pub_id, vec![i]));
+        }
+        let (events, dropped) = queue.into_events();
+        assert_eq!(payloads(events), vec![2, 3, 4]);
+        assert_eq!(dropped, 2);
+    }
+}
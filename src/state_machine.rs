@@ -17,22 +17,36 @@
 
 use {CrustEvent, CrustEventSender, Service};
 use action::Action;
+use crust::CrustError;
+#[cfg(feature = "use-mock-crust")]
+use error::InterfaceError;
+use error::RoutingError;
+#[cfg(feature = "use-mock-crust")]
+use fake_clock::FakeClock;
 use id::{FullId, PublicId};
+#[cfg(feature = "use-mock-crust")]
+use input_log::{self, InputLog, InputRecorder, RecordedInput, ReplayDivergence};
 use maidsafe_utilities::event_sender::MaidSafeEventCategory;
+use messages::UserMessage;
 #[cfg(feature = "use-mock-crust")]
-use mock_crust::get_current;
+use mock_crust::{Endpoint, get_current};
 use outbox::EventBox;
-use routing_table::{Prefix, RoutingTable};
+use routing_table::{Authority, Prefix, RoutingTable};
 #[cfg(feature = "use-mock-crust")]
 use rust_sodium::crypto::sign;
 use states::{Bootstrapping, Client, JoiningNode, Node};
 use states::common::Base;
 #[cfg(feature = "use-mock-crust")]
+use stats::PendingWork;
+#[cfg(feature = "use-mock-crust")]
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
 use std::fmt::{self, Debug, Formatter};
 use std::mem;
+use std::net::SocketAddr;
 use std::sync::mpsc::{self, Receiver, RecvError, Sender, TryRecvError};
+#[cfg(feature = "use-mock-crust")]
+use std::time::Duration;
 use timer::Timer;
 use types::RoutingActionSender;
 use xor_name::XorName;
@@ -44,9 +58,14 @@ pub struct StateMachine {
     crust_rx: Receiver<CrustEvent<PublicId>>,
     crust_tx: Sender<CrustEvent<PublicId>>,
     action_rx: Receiver<Action>,
+    /// A handle on the timer the states schedule their timeouts on.
+    timer: Timer,
     is_running: bool,
     #[cfg(feature = "use-mock-crust")]
     events: Vec<EventType>,
+    /// The inputs consumed so far, if recording.
+    #[cfg(feature = "use-mock-crust")]
+    input_log: Option<InputRecorder>,
 }
 
 // FIXME - See https://maidsafe.atlassian.net/browse/MAID-2026 for info on removing this exclusion.
@@ -84,8 +103,8 @@ impl EventType {
 impl State {
     pub fn handle_action(&mut self, action: Action, outbox: &mut EventBox) -> Transition {
         match *self {
-            State::Bootstrapping(ref mut state) => state.handle_action(action),
-            State::Client(ref mut state) => state.handle_action(action),
+            State::Bootstrapping(ref mut state) => state.handle_action(action, outbox),
+            State::Client(ref mut state) => state.handle_action(action, outbox),
             State::JoiningNode(ref mut state) => state.handle_action(action, outbox),
             State::Node(ref mut state) => state.handle_action(action, outbox),
             State::Terminated => Transition::Terminate,
@@ -116,6 +135,18 @@ impl State {
         }
     }
 
+    fn estimate_wire_size(&self,
+                          src: Authority<XorName>,
+                          dst: Authority<XorName>,
+                          user_msg: &UserMessage)
+                          -> Result<usize, RoutingError> {
+        match *self {
+            State::Node(ref state) => state.estimate_wire_size(src, dst, user_msg),
+            State::Terminated => Err(RoutingError::Terminated),
+            _ => Err(RoutingError::InvalidStateForOperation),
+        }
+    }
+
     fn close_group(&self, name: XorName, count: usize) -> Option<Vec<XorName>> {
         self.base_state()
             .and_then(|state| state.close_group(name, count))
@@ -180,6 +211,81 @@ impl State {
         }
     }
 
+    pub fn inject_message(&mut self,
+                          endpoint: Endpoint,
+                          bytes: Vec<u8>)
+                          -> Result<(), InterfaceError> {
+        match *self {
+            State::Node(ref mut node) => {
+                if node.inject_message(endpoint, bytes) {
+                    Ok(())
+                } else {
+                    Err(InterfaceError::NotConnected)
+                }
+            }
+            _ => Err(InterfaceError::InvalidState),
+        }
+    }
+
+    pub fn take_message_errors(&mut self) -> Vec<RoutingError> {
+        match *self {
+            State::Node(ref mut node) => node.take_message_errors(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        match *self {
+            State::Node(ref node) => node.is_disconnected(),
+            _ => false,
+        }
+    }
+
+    pub fn in_authority(&self, auth: &Authority<XorName>) -> bool {
+        self.base_state()
+            .map_or(false, |state| state.in_authority(auth))
+    }
+
+    pub fn forget_peer(&mut self, name: &XorName) {
+        if let State::Node(ref mut node) = *self {
+            node.forget_peer(name);
+        }
+    }
+
+    pub fn send_forged_connection_info_request(&mut self, claimed_id: PublicId, dst: XorName) {
+        if let State::Node(ref mut node) = *self {
+            node.send_forged_connection_info_request(claimed_id, dst);
+        }
+    }
+
+    pub fn set_claimed_proxy_name(&mut self, name: Option<XorName>) {
+        if let State::Client(ref mut client) = *self {
+            client.set_claimed_proxy_name(name);
+        }
+    }
+
+    pub fn pending_work(&self, horizon: Duration) -> PendingWork {
+        match *self {
+            State::Node(ref state) => state.pending_work(horizon),
+            State::Bootstrapping(_) |
+            State::JoiningNode(_) => {
+                PendingWork {
+                    joining: true,
+                    ..PendingWork::default()
+                }
+            }
+            State::Client(_) |
+            State::Terminated => PendingWork::default(),
+        }
+    }
+
+    pub fn decision_log_dump(&self) -> Vec<u8> {
+        match *self {
+            State::Node(ref state) => state.dump_decision_log(),
+            _ => Vec::new(),
+        }
+    }
+
     pub fn get_timed_out_tokens(&mut self) -> Vec<u64> {
         match *self {
             State::Node(ref mut state) => state.get_timed_out_tokens(),
@@ -207,8 +313,10 @@ pub enum Transition {
 
 impl StateMachine {
     // Construct a new StateMachine by passing a function returning the initial state.
+    // `bootstrap_contacts` are tried in addition to the ones in Crust's config.
     pub fn new<F>(init_state: F,
                   pub_id: PublicId,
+                  bootstrap_contacts: Vec<SocketAddr>,
                   outbox: &mut EventBox)
                   -> (RoutingActionSender, Self)
         where F: FnOnce(RoutingActionSender, Service, Timer, &mut EventBox) -> State
@@ -225,13 +333,9 @@ impl StateMachine {
                                                  MaidSafeEventCategory::Crust,
                                                  category_tx.clone());
 
-        #[cfg(feature = "use-mock-crust")]
-        let mut crust_service = match Service::new(get_current(), crust_sender, pub_id) {
-            Ok(service) => service,
-            Err(error) => panic!("Unable to start crust::Service {:?}", error),
-        };
-        #[cfg(not(feature = "use-mock-crust"))]
-        let mut crust_service = match Service::new(crust_sender, pub_id) {
+        let mut crust_service = match start_crust_service(crust_sender,
+                                                          pub_id,
+                                                          bootstrap_contacts) {
             Ok(service) => service,
             Err(error) => panic!("Unable to start crust::Service {:?}", error),
         };
@@ -240,7 +344,7 @@ impl StateMachine {
 
         let timer = Timer::new(action_sender.clone());
 
-        let state = init_state(action_sender.clone(), crust_service, timer, outbox);
+        let state = init_state(action_sender.clone(), crust_service, timer.clone(), outbox);
         let is_running = match state {
             State::Terminated => false,
             _ => true,
@@ -252,9 +356,11 @@ impl StateMachine {
             crust_rx: crust_rx,
             crust_tx: crust_tx,
             action_rx: action_rx,
+            timer: timer,
             state: state,
             is_running: is_running,
             events: Vec::new(),
+            input_log: None,
         };
         #[cfg(not(feature = "use-mock-crust"))]
         let machine = StateMachine {
@@ -263,6 +369,7 @@ impl StateMachine {
             crust_rx: crust_rx,
             crust_tx: crust_tx,
             action_rx: action_rx,
+            timer: timer,
             state: state,
             is_running: is_running,
         };
@@ -304,6 +411,16 @@ impl StateMachine {
     fn handle_event_from_list(&mut self, outbox: &mut EventBox) {
         assert!(!self.events.is_empty());
         let event = self.events.remove(0);
+        let input = if self.input_log.is_some() {
+            match event {
+                EventType::Action(ref action) => RecordedInput::from_action(action),
+                EventType::CrustEvent(ref crust_event) => {
+                    Some(RecordedInput::from_crust_event(crust_event))
+                }
+            }
+        } else {
+            None
+        };
         let transition = match event {
             EventType::Action(action) => self.state.handle_action(*action, outbox),
             EventType::CrustEvent(crust_event) => {
@@ -311,21 +428,63 @@ impl StateMachine {
             }
         };
 
+        self.apply_transition(transition, outbox);
+        self.record_input(input);
+    }
+
+    // Appends the input, together with the digest of the resulting decision log, to the input log.
+    #[cfg(feature = "use-mock-crust")]
+    fn record_input(&mut self, input: Option<RecordedInput>) {
+        if let Some(input) = input {
+            let decisions = input_log::decisions_digest(&self.state.decision_log_dump());
+            if let Some(ref mut recorder) = self.input_log {
+                recorder.record(input, decisions);
+            }
+        }
+    }
+
+    /// Handles an action from the user directly, without going through the action channel. Once
+    /// the machine has terminated, the action is refused instead.
+    #[cfg(not(feature = "use-mock-crust"))]
+    pub fn handle_action(&mut self, action: Action, outbox: &mut EventBox) {
+        if !self.is_running {
+            action.refuse();
+            return;
+        }
+        let transition = self.state.handle_action(action, outbox);
         self.apply_transition(transition, outbox)
     }
 
+    /// Handles an action from the user directly, without going through the action channel. Once
+    /// the machine has terminated, the action is refused instead.
+    #[cfg(feature = "use-mock-crust")]
+    pub fn handle_action(&mut self, action: Action, outbox: &mut EventBox) {
+        if !self.is_running {
+            action.refuse();
+            return;
+        }
+        let input = self.input_log
+            .as_ref()
+            .and_then(|_| RecordedInput::from_action(&action));
+        let transition = self.state.handle_action(action, outbox);
+        self.apply_transition(transition, outbox);
+        self.record_input(input);
+    }
+
     pub fn apply_transition(&mut self, transition: Transition, outbox: &mut EventBox) {
         use self::Transition::*;
         match transition {
             Stay => (),
             IntoBootstrapped { proxy_public_id } => {
-                let new_state = match mem::replace(&mut self.state, State::Terminated) {
-                    State::Bootstrapping(bootstrapping) => {
-                        bootstrapping.into_target_state(proxy_public_id, outbox)
-                    }
-                    _ => unreachable!(),
-                };
+                let (new_state, parked_events) =
+                    match mem::replace(&mut self.state, State::Terminated) {
+                        State::Bootstrapping(bootstrapping) => {
+                            bootstrapping.into_target_state(proxy_public_id, outbox)
+                        }
+                        _ => unreachable!(),
+                    };
                 self.state = new_state;
+                self.replay_parked_events(parked_events, outbox);
             }
             IntoBootstrapping {
                 new_id,
@@ -350,6 +509,19 @@ impl StateMachine {
         }
     }
 
+    // Handles the Crust events parked until we were ready, in arrival order.
+    fn replay_parked_events(&mut self,
+                            parked_events: Vec<CrustEvent<PublicId>>,
+                            outbox: &mut EventBox) {
+        for crust_event in parked_events {
+            if !self.is_running {
+                return;
+            }
+            let transition = self.state.handle_crust_event(crust_event, outbox);
+            self.apply_transition(transition, outbox);
+        }
+    }
+
     fn terminate(&mut self) {
         debug!("{:?} Terminating state machine", self);
         self.is_running = false;
@@ -446,6 +618,11 @@ impl StateMachine {
         Err(TryRecvError::Empty)
     }
 
+    /// Returns a handle on the timer shared by the states, whose timeouts wake the machine up.
+    pub fn timer(&self) -> Timer {
+        self.timer.clone()
+    }
+
     pub fn id(&self) -> Option<PublicId> {
         self.state.id()
     }
@@ -458,6 +635,78 @@ impl StateMachine {
         self.state.close_group(name, count)
     }
 
+    pub fn estimate_wire_size(&self,
+                              src: Authority<XorName>,
+                              dst: Authority<XorName>,
+                              user_msg: &UserMessage)
+                              -> Result<usize, RoutingError> {
+        self.state.estimate_wire_size(src, dst, user_msg)
+    }
+
+    /// Returns the work the current state still has to do, including events received but not
+    /// handled yet.
+    #[cfg(feature = "use-mock-crust")]
+    pub fn pending_work(&self, horizon: Duration) -> PendingWork {
+        PendingWork {
+            queued_events: self.events.len(),
+            ..self.state.pending_work(horizon)
+        }
+    }
+
+    /// Returns this machine, recording the inputs its states consume from now on.
+    #[cfg(feature = "use-mock-crust")]
+    pub fn with_input_log(self) -> Self {
+        StateMachine {
+            input_log: Some(InputRecorder::new()),
+            ..self
+        }
+    }
+
+    /// Stops recording and returns the inputs consumed since `with_input_log`.
+    #[cfg(feature = "use-mock-crust")]
+    pub fn take_input_log(&mut self) -> InputLog {
+        self.input_log
+            .take()
+            .map_or_else(InputLog::default, InputRecorder::into_log)
+    }
+
+    /// Feeds the recorded inputs to the current state in order, advancing the mock clock to keep
+    /// their recorded timing, and compares the decision log with the recorded one after each.
+    #[cfg(feature = "use-mock-crust")]
+    pub fn replay_input_log(&mut self,
+                            log: &InputLog,
+                            outbox: &mut EventBox)
+                            -> Result<(), ReplayDivergence> {
+        let start = FakeClock::now();
+        for (step, record) in log.records.iter().enumerate() {
+            let elapsed = start.elapsed();
+            if record.elapsed > elapsed {
+                let ahead = record.elapsed - elapsed;
+                FakeClock::advance_time(ahead.as_secs() * 1000 +
+                                        u64::from(ahead.subsec_nanos() / 1_000_000));
+            }
+            if self.is_running {
+                let transition = if let Some(crust_event) = record.input.to_crust_event() {
+                    self.state.handle_crust_event(crust_event, outbox)
+                } else if let Some(action) = record.input.to_action() {
+                    self.state.handle_action(action, outbox)
+                } else {
+                    Transition::Stay
+                };
+                self.apply_transition(transition, outbox);
+            }
+            let actual = input_log::decisions_digest(&self.state.decision_log_dump());
+            if actual != record.decisions {
+                return Err(ReplayDivergence {
+                               step: step,
+                               expected: record.decisions,
+                               actual: actual,
+                           });
+            }
+        }
+        Ok(())
+    }
+
     #[cfg(feature = "use-mock-crust")]
     /// Get reference to the current state.
     pub fn current(&self) -> &State {
@@ -475,3 +724,28 @@ impl Debug for StateMachine {
         self.state.fmt(formatter)
     }
 }
+
+/// Starts Crust, bootstrapping off `extra_contacts` in addition to the configured contacts.
+#[cfg(not(feature = "use-mock-crust"))]
+fn start_crust_service(crust_sender: CrustEventSender,
+                       pub_id: PublicId,
+                       extra_contacts: Vec<SocketAddr>)
+                       -> Result<Service, CrustError> {
+    if extra_contacts.is_empty() {
+        return Service::new(crust_sender, pub_id);
+    }
+    let mut config = ::crust::read_config_file()?;
+    config.hard_coded_contacts.extend(extra_contacts);
+    Service::with_config(crust_sender, config, pub_id)
+}
+
+/// Starts Crust, bootstrapping off `extra_contacts` in addition to the configured contacts.
+#[cfg(feature = "use-mock-crust")]
+fn start_crust_service(crust_sender: CrustEventSender,
+                       pub_id: PublicId,
+                       extra_contacts: Vec<SocketAddr>)
+                       -> Result<Service, CrustError> {
+    let handle = get_current();
+    handle.0.borrow_mut().add_bootstrap_contacts(&extra_contacts);
+    Service::new(handle, crust_sender, pub_id)
+}
@@ -0,0 +1,383 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Periodically saving a snapshot of the node's state, so that it can be restored after a
+//! restart, including one after a crash.
+
+use event::SnapshotRejection;
+#[cfg(feature="use-mock-crust")]
+use fake_clock::FakeClock as Instant;
+use id::{FullId, PublicId};
+use maidsafe_utilities::serialisation;
+use rust_sodium::crypto::{box_, sign};
+use rust_sodium::crypto::scalarmult::curve25519;
+use sha3::Digest256;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+#[cfg(not(feature="use-mock-crust"))]
+use std::time::Instant;
+use tiny_keccak::sha3_256;
+use tunables::{EffectiveConfig, Tunables};
+
+/// Contacts we haven't been connected to for this long are not saved anymore, in seconds.
+pub const MAX_CONTACT_AGE_SECS: u64 = 60 * 60 * 24;
+
+/// Where the snapshots set up via `NodeBuilder::state_persistence` are kept.
+pub trait StateStore: Send {
+    /// Replaces the stored snapshot with the given one.
+    fn save(&mut self, snapshot: &[u8]) -> io::Result<()>;
+    /// Returns the stored snapshot, or `None` if none has been saved yet.
+    fn load(&mut self) -> io::Result<Option<Vec<u8>>>;
+}
+
+/// A `StateStore` keeping the snapshot in memory. Clones share the same snapshot, so one can be
+/// kept to inspect what the node saved, or to hand it to the next node.
+#[derive(Clone, Default)]
+pub struct MemoryStateStore {
+    snapshot: Arc<Mutex<Option<Vec<u8>>>>,
+}
+
+impl MemoryStateStore {
+    /// Returns a new, empty store.
+    pub fn new() -> MemoryStateStore {
+        Default::default()
+    }
+
+    /// Returns the stored snapshot.
+    pub fn contents(&self) -> Option<Vec<u8>> {
+        unwrap!(self.snapshot.lock()).clone()
+    }
+
+    /// Replaces the stored snapshot, e.g. to simulate a corrupted one.
+    pub fn set_contents(&self, contents: Vec<u8>) {
+        *unwrap!(self.snapshot.lock()) = Some(contents);
+    }
+}
+
+impl StateStore for MemoryStateStore {
+    fn save(&mut self, snapshot: &[u8]) -> io::Result<()> {
+        self.set_contents(snapshot.to_vec());
+        Ok(())
+    }
+
+    fn load(&mut self) -> io::Result<Option<Vec<u8>>> {
+        Ok(self.contents())
+    }
+}
+
+/// A `StateStore` keeping the snapshot in a file. It is written to a temporary file next to it
+/// first, so that a crash while saving leaves the previous snapshot intact.
+///
+/// If the node's keys are included, the file holds its secret keys: on Unix it is created readable
+/// and writable by its owner only, elsewhere it should be kept in a directory only they can access.
+pub struct FileStateStore {
+    path: PathBuf,
+}
+
+impl FileStateStore {
+    /// Returns a store keeping the snapshot at `path`.
+    pub fn new<P: Into<PathBuf>>(path: P) -> FileStateStore {
+        FileStateStore { path: path.into() }
+    }
+}
+
+impl StateStore for FileStateStore {
+    fn save(&mut self, snapshot: &[u8]) -> io::Result<()> {
+        let temp_path = self.path.with_extension("tmp");
+        {
+            let mut options = OpenOptions::new();
+            let _ = options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            let _ = options.mode(0o600);
+            let mut file = options.open(&temp_path)?;
+            file.write_all(snapshot)?;
+            file.sync_all()?;
+        }
+        fs::rename(&temp_path, &self.path)
+    }
+
+    fn load(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let mut snapshot = Vec::new();
+        let _ = file.read_to_end(&mut snapshot)?;
+        Ok(Some(snapshot))
+    }
+}
+
+/// A peer we were connected to, as saved in a snapshot.
+#[derive(Serialize, Deserialize)]
+struct SavedContact {
+    pub_id: PublicId,
+    /// The address we were connected to the peer at.
+    addr: Option<SocketAddr>,
+    /// How long ago we were last connected to the peer when the snapshot was taken, in seconds.
+    age_secs: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    /// The hash of the settings the node was started with.
+    fingerprint: Digest256,
+    /// Our public ID and secret keys, unless excluded.
+    keys: Option<(PublicId, box_::SecretKey, sign::SecretKey)>,
+    contacts: Vec<SavedContact>,
+}
+
+/// The state restored from a snapshot.
+pub struct RestoredState {
+    /// Our keys, if they were saved.
+    pub full_id: Option<FullId>,
+    /// The peers we were connected to.
+    pub contacts: Vec<PublicId>,
+    /// Their addresses, to bootstrap off in addition to the configured contacts.
+    pub bootstrap_addrs: Vec<SocketAddr>,
+}
+
+/// A `StateStore` together with the settings for saving snapshots to it, and the contacts to be
+/// saved. Passed on from state to state until we are a node.
+pub struct StatePersistence {
+    store: Box<StateStore>,
+    interval: Duration,
+    include_keys: bool,
+    fingerprint: Digest256,
+    /// Each contact's address, and how long before the given instant we were last connected to it,
+    /// in seconds.
+    contacts: HashMap<PublicId, (Option<SocketAddr>, u64, Instant)>,
+}
+
+impl StatePersistence {
+    /// Returns an instance saving a snapshot to `store` every `interval`, marked with the hash of
+    /// the settings we were started with.
+    pub fn new(store: Box<StateStore>,
+               interval: Duration,
+               include_keys: bool,
+               min_section_size: usize,
+               tunables: &Tunables)
+               -> StatePersistence {
+        let config = EffectiveConfig::new(min_section_size, tunables);
+        let fingerprint = match serialisation::serialise(&config) {
+            Ok(serialised) => sha3_256(&serialised),
+            Err(error) => {
+                error!("Failed to serialise the config: {:?}", error);
+                [0; 32]
+            }
+        };
+        StatePersistence {
+            store: store,
+            interval: interval,
+            include_keys: include_keys,
+            fingerprint: fingerprint,
+            contacts: HashMap::new(),
+        }
+    }
+
+    /// The time between two snapshots.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Loads the stored snapshot, if any, and remembers its contacts to be saved again. Returns
+    /// why it was discarded if it can't be read, was taken with different settings or its keys
+    /// don't form valid key pairs.
+    pub fn load(&mut self) -> Result<Option<RestoredState>, SnapshotRejection> {
+        let serialised = match self.store.load() {
+            Ok(Some(serialised)) => serialised,
+            Ok(None) => return Ok(None),
+            Err(error) => return Err(SnapshotRejection::Unreadable(error.to_string())),
+        };
+        let snapshot: Snapshot = serialisation::deserialise(&serialised)
+            .map_err(|_| SnapshotRejection::Corrupt)?;
+        if snapshot.fingerprint != self.fingerprint {
+            return Err(SnapshotRejection::ConfigMismatch);
+        }
+        let full_id = match snapshot.keys {
+            Some((pub_id, encrypt_key, sign_key)) => {
+                if !keys_match(&pub_id, &encrypt_key, &sign_key) {
+                    return Err(SnapshotRejection::KeyMismatch);
+                }
+                Some(FullId::with_keys((*pub_id.encrypting_public_key(), encrypt_key),
+                                       (*pub_id.signing_public_key(), sign_key)))
+            }
+            None => None,
+        };
+        let now = Instant::now();
+        let contacts = snapshot
+            .contacts
+            .iter()
+            .map(|contact| contact.pub_id)
+            .collect();
+        let bootstrap_addrs = snapshot
+            .contacts
+            .iter()
+            .filter_map(|contact| contact.addr)
+            .collect();
+        for contact in snapshot.contacts {
+            let _ = self.contacts
+                .insert(contact.pub_id, (contact.addr, contact.age_secs, now));
+        }
+        Ok(Some(RestoredState {
+                    full_id: full_id,
+                    contacts: contacts,
+                    bootstrap_addrs: bootstrap_addrs,
+                }))
+    }
+
+    /// Records that we are connected to the given peers now, and saves a snapshot with them and
+    /// the contacts we were connected to recently.
+    pub fn save(&mut self,
+                full_id: &FullId,
+                connected: Vec<(PublicId, Option<SocketAddr>)>)
+                -> io::Result<()> {
+        let now = Instant::now();
+        for (pub_id, addr) in connected {
+            let _ = self.contacts.insert(pub_id, (addr, 0, now));
+        }
+        self.contacts
+            .retain(|_, &mut (_, age_secs, since)| {
+                        age_secs + since.elapsed().as_secs() < MAX_CONTACT_AGE_SECS
+                    });
+        let contacts = self.contacts
+            .iter()
+            .map(|(pub_id, &(addr, age_secs, since))| {
+                     SavedContact {
+                         pub_id: *pub_id,
+                         addr: addr,
+                         age_secs: age_secs + since.elapsed().as_secs(),
+                     }
+                 })
+            .collect();
+        let keys = if self.include_keys {
+            Some((*full_id.public_id(),
+                  full_id.encrypting_private_key().clone(),
+                  full_id.signing_private_key().clone()))
+        } else {
+            None
+        };
+        let snapshot = Snapshot {
+            fingerprint: self.fingerprint,
+            keys: keys,
+            contacts: contacts,
+        };
+        let serialised = serialisation::serialise(&snapshot)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))?;
+        self.store.save(&serialised)
+    }
+}
+
+/// Returns `true` if the public keys in `pub_id` are the ones belonging to the given secret keys.
+fn keys_match(pub_id: &PublicId,
+              encrypt_key: &box_::SecretKey,
+              sign_key: &sign::SecretKey)
+              -> bool {
+    let encrypt_pub_key = curve25519::scalarmult_base(&curve25519::Scalar(encrypt_key.0));
+    // An Ed25519 secret key is stored as its seed with the public key appended. Both have to be
+    // derived from the seed, so that a key with someone else's public key appended is refused.
+    let mut seed = [0; sign::SEEDBYTES];
+    seed.copy_from_slice(&sign_key.0[..sign::SEEDBYTES]);
+    let (sign_pub_key, derived_sign_key) = sign::keypair_from_seed(&sign::Seed(seed));
+    encrypt_pub_key.0 == pub_id.encrypting_public_key().0 &&
+    sign_pub_key == *pub_id.signing_public_key() && derived_sign_key == *sign_key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand;
+    use std::env;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn file_store_replaces_snapshot() {
+        let file_name = format!("routing_state_{:016x}", rand::random::<u64>());
+        let path = env::temp_dir().join(file_name);
+        let mut store = FileStateStore::new(path.clone());
+        assert_eq!(None, unwrap!(store.load()));
+
+        unwrap!(store.save(b"first"));
+        unwrap!(store.save(b"second"));
+        assert_eq!(Some(b"second".to_vec()), unwrap!(store.load()));
+        #[cfg(unix)]
+        assert_eq!(0o600, unwrap!(fs::metadata(&path)).permissions().mode() & 0o777);
+        unwrap!(fs::remove_file(&path));
+    }
+
+    #[test]
+    fn mismatched_keys_rejected() {
+        let min_section_size = 8;
+        let tunables = Tunables::default();
+        let interval = Duration::from_secs(60);
+        let store = MemoryStateStore::new();
+        let mut persistence = StatePersistence::new(Box::new(store.clone()),
+                                                    interval,
+                                                    true,
+                                                    min_section_size,
+                                                    &tunables);
+        let full_id = FullId::new();
+        unwrap!(persistence.save(&full_id, vec![]));
+        let restored = unwrap!(unwrap!(persistence.load()));
+        assert_eq!(Some(*full_id.public_id()),
+                   restored.full_id.map(|full_id| *full_id.public_id()));
+
+        // A snapshot pairing our public keys with someone else's secret keys is rejected.
+        let other_id = FullId::new();
+        let snapshot = Snapshot {
+            fingerprint: persistence.fingerprint,
+            keys: Some((*full_id.public_id(),
+                        other_id.encrypting_private_key().clone(),
+                        full_id.signing_private_key().clone())),
+            contacts: vec![],
+        };
+        store.set_contents(unwrap!(serialisation::serialise(&snapshot)));
+        assert_eq!(Some(SnapshotRejection::KeyMismatch), persistence.load().err());
+
+        let snapshot = Snapshot {
+            fingerprint: persistence.fingerprint,
+            keys: Some((*full_id.public_id(),
+                        full_id.encrypting_private_key().clone(),
+                        other_id.signing_private_key().clone())),
+            contacts: vec![],
+        };
+        store.set_contents(unwrap!(serialisation::serialise(&snapshot)));
+        assert_eq!(Some(SnapshotRejection::KeyMismatch), persistence.load().err());
+
+        // So is someone else's signing key seed with our public key appended.
+        let mut forged_sign_key = other_id.signing_private_key().clone();
+        forged_sign_key.0[sign::SEEDBYTES..]
+            .copy_from_slice(&full_id.public_id().signing_public_key().0);
+        let snapshot = Snapshot {
+            fingerprint: persistence.fingerprint,
+            keys: Some((*full_id.public_id(),
+                        full_id.encrypting_private_key().clone(),
+                        forged_sign_key)),
+            contacts: vec![],
+        };
+        store.set_contents(unwrap!(serialisation::serialise(&snapshot)));
+        assert_eq!(Some(SnapshotRejection::KeyMismatch), persistence.load().err());
+    }
+}
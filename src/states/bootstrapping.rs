@@ -20,22 +20,29 @@ use super::common::Base;
 use {CrustEvent, Service};
 use action::Action;
 use cache::Cache;
+#[cfg(feature = "use-mock-crust")]
+use crust::BootstrapFailureReason;
 use crust::CrustUser;
-use error::RoutingError;
-use event::Event;
+use crypto::Signer;
+use error::{InterfaceError, RoutingError};
+use event::{BootstrapFailure, Event, JoinProgress, JoinProgressMask};
+use forwarding::ForwardingSink;
 use id::{FullId, PublicId};
 use maidsafe_utilities::serialisation;
-use messages::{DirectMessage, Message};
+use messages::{DirectMessage, Message, identify_signed_bytes};
 use outbox::EventBox;
 use routing_table::{Authority, Prefix};
-use rust_sodium::crypto::sign;
+use startup_queue::StartupQueue;
 use state_machine::{State, Transition};
+use state_store::StatePersistence;
 use stats::Stats;
 use std::collections::{BTreeSet, HashSet};
 use std::fmt::{self, Debug, Formatter};
+use std::mem;
 use std::net::SocketAddr;
 use std::time::Duration;
 use timer::Timer;
+use tunables::{EffectiveConfig, Tunables};
 use types::RoutingActionSender;
 use xor_name::XorName;
 
@@ -57,25 +64,37 @@ pub enum TargetState {
 // State of Client, JoiningNode or Node while bootstrapping.
 pub struct Bootstrapping {
     action_sender: RoutingActionSender,
+    bootstrap_attempts: u32,
     bootstrap_blacklist: HashSet<SocketAddr>,
-    bootstrap_connection: Option<(PublicId, u64)>,
+    bootstrap_connection: Option<(PublicId, SocketAddr, u64)>,
+    /// The contacts we failed to bootstrap off so far, and why.
+    bootstrap_failures: Vec<(SocketAddr, BootstrapFailure)>,
     cache: Box<Cache>,
+    forwarding_sink: Option<Box<ForwardingSink>>,
+    state_persistence: Option<StatePersistence>,
     target_state: TargetState,
     crust_service: Service,
     full_id: FullId,
     min_section_size: usize,
+    /// Crust events which are not needed to bootstrap, to be handled once we are ready.
+    startup_queue: StartupQueue,
     stats: Stats,
     timer: Timer,
+    tunables: Tunables,
 }
 
 impl Bootstrapping {
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     pub fn new(action_sender: RoutingActionSender,
                cache: Box<Cache>,
+               forwarding_sink: Option<Box<ForwardingSink>>,
+               state_persistence: Option<StatePersistence>,
                target_state: TargetState,
                mut crust_service: Service,
                full_id: FullId,
                min_section_size: usize,
-               timer: Timer)
+               timer: Timer,
+               tunables: Tunables)
                -> Option<Self> {
         match target_state {
             TargetState::Client => {
@@ -93,22 +112,34 @@ impl Bootstrapping {
         }
         Some(Bootstrapping {
                  action_sender: action_sender,
+                 bootstrap_attempts: 1,
                  bootstrap_blacklist: HashSet::new(),
                  bootstrap_connection: None,
+                 bootstrap_failures: Vec::new(),
                  cache: cache,
+                 forwarding_sink: forwarding_sink,
+                 state_persistence: state_persistence,
                  target_state: target_state,
                  crust_service: crust_service,
                  full_id: full_id,
                  min_section_size: min_section_size,
+                 startup_queue: StartupQueue::new(tunables.startup_queue_capacity),
                  stats: Stats::new(),
                  timer: timer,
+                 tunables: tunables,
              })
     }
 
-    pub fn handle_action(&mut self, action: Action) -> Transition {
+    /// Takes over the events parked before a relocation, so they are handled once we are ready.
+    pub fn resume_startup_queue(&mut self, startup_queue: StartupQueue) {
+        self.startup_queue = startup_queue;
+    }
+
+    pub fn handle_action(&mut self, action: Action, outbox: &mut EventBox) -> Transition {
         match action {
             Action::ClientSendRequest { ref result_tx, .. } |
-            Action::NodeSendMessage { ref result_tx, .. } => {
+            Action::NodeSendMessage { ref result_tx, .. } |
+            Action::NodeSendBatch { ref result_tx, .. } => {
                 warn!("{:?} Cannot handle {:?} - not bootstrapped.", self, action);
                 // TODO: return Err here eventually. Returning Ok for now to
                 // preserve the pre-refactor behaviour.
@@ -117,11 +148,46 @@ impl Bootstrapping {
             Action::Id { result_tx } => {
                 let _ = result_tx.send(*self.id());
             }
+            Action::GetStats { result_tx } => {
+                let _ = result_tx.send(Default::default());
+            }
+            Action::CancelRequest { ref result_tx, .. } |
+            Action::DisconnectPeer { ref result_tx, .. } |
+            Action::BanPeer { ref result_tx, .. } |
+            Action::SetConnectionQuotas { ref result_tx, .. } |
+            Action::UpdateConfig { ref result_tx, .. } |
+            Action::PingPeer { ref result_tx, .. } => {
+                warn!("{:?} Cannot handle {:?} - not bootstrapped.", self, action);
+                let _ = result_tx.send(Err(InterfaceError::InvalidState));
+            }
+            // Only a client has several proxies to choose from. It applies the strategy once
+            // bootstrapped.
+            Action::SetProxyStrategy { strategy, result_tx } => {
+                let result = if self.client_restriction() {
+                    self.tunables.proxy_strategy = strategy;
+                    Ok(())
+                } else {
+                    Err(InterfaceError::InvalidState)
+                };
+                let _ = result_tx.send(result);
+            }
+            Action::DumpDecisionLog { ref result_tx } => {
+                warn!("{:?} Cannot handle {:?} - not bootstrapped.", self, action);
+                let _ = result_tx.send(Err(InterfaceError::InvalidState));
+            }
+            Action::GetEffectiveConfig { result_tx } => {
+                let config = EffectiveConfig::new(self.min_section_size, &self.tunables);
+                let _ = result_tx.send(Ok(config));
+            }
             Action::Timeout(token) => self.handle_timeout(token),
             Action::ResourceProofResult(..) => {
                 warn!("{:?} Cannot handle {:?} - not bootstrapped.", self, action);
             }
             Action::Terminate => {
+                if let Some((bootstrap_id, _, _)) = self.bootstrap_connection.take() {
+                    self.disconnect_peer(&bootstrap_id);
+                }
+                outbox.send_event(Event::Terminate);
                 return Transition::Terminate;
             }
         }
@@ -132,11 +198,38 @@ impl Bootstrapping {
                               crust_event: CrustEvent<PublicId>,
                               outbox: &mut EventBox)
                               -> Transition {
+        if !self.is_essential(&crust_event) {
+            trace!("{:?} Parking {:?} until we are ready.", self, crust_event);
+            self.startup_queue.push(crust_event);
+            return Transition::Stay;
+        }
         match crust_event {
             CrustEvent::BootstrapConnect(pub_id, socket_addr) => {
-                self.handle_bootstrap_connect(pub_id, socket_addr)
+                self.handle_bootstrap_connect(pub_id, socket_addr, outbox)
             }
             CrustEvent::BootstrapFailed => self.handle_bootstrap_failed(outbox),
+            #[cfg(feature = "use-mock-crust")]
+            CrustEvent::BootstrapAttemptFailed(socket_addr, reason) => {
+                let failure = match reason {
+                    BootstrapFailureReason::NotListening => BootstrapFailure::NotListening,
+                    BootstrapFailureReason::Refused(_) => BootstrapFailure::Refused,
+                    BootstrapFailureReason::Blocked |
+                    BootstrapFailureReason::NotWhitelisted |
+                    BootstrapFailureReason::ConnectionLimit => BootstrapFailure::Unreachable,
+                };
+                self.record_failure(socket_addr, failure);
+                Transition::Stay
+            }
+            CrustEvent::LostPeer(pub_id) => {
+                if self.bootstrap_connection
+                       .map_or(false, |(bootstrap_id, _, _)| bootstrap_id == pub_id) {
+                    debug!("{:?} Lost bootstrap node {:?} before it identified itself.",
+                           self,
+                           pub_id);
+                    self.rebootstrap(BootstrapFailure::Lost);
+                }
+                Transition::Stay
+            }
             CrustEvent::NewMessage(pub_id, bytes) => {
                 match self.handle_new_message(pub_id, bytes) {
                     Ok(transition) => transition,
@@ -174,31 +267,52 @@ impl Bootstrapping {
         }
     }
 
-    pub fn into_target_state(self, proxy_public_id: PublicId, outbox: &mut EventBox) -> State {
+    /// Returns the state we bootstrapped into, and the parked Crust events it needs to handle, in
+    /// arrival order. A joining node takes them over instead, as it isn't ready yet either.
+    pub fn into_target_state(self,
+                             proxy_public_id: PublicId,
+                             outbox: &mut EventBox)
+                             -> (State, Vec<CrustEvent<PublicId>>) {
+        let join_progress = self.join_progress_mask();
+        let step = JoinProgress::BootstrapConnected(*proxy_public_id.name());
+        if join_progress.matches(&step) {
+            outbox.send_event(Event::JoinProgress(step));
+        }
         match self.target_state {
             TargetState::Client { .. } => {
-                State::Client(Client::from_bootstrapping(self.crust_service,
-                                                         self.full_id,
-                                                         self.min_section_size,
-                                                         proxy_public_id,
-                                                         self.stats,
-                                                         self.timer,
-                                                         outbox))
+                let mut stats = self.stats;
+                let parked_events = Self::unpark(self.startup_queue, &mut stats);
+                let client = Client::from_bootstrapping(self.crust_service,
+                                                        self.full_id,
+                                                        self.min_section_size,
+                                                        proxy_public_id,
+                                                        self.tunables.proxy_strategy,
+                                                        stats,
+                                                        self.timer,
+                                                        outbox);
+                (State::Client(client), parked_events)
             }
             TargetState::JoiningNode => {
                 if let Some(joining_node) =
                     JoiningNode::from_bootstrapping(self.action_sender,
                                                     self.cache,
+                                                    self.forwarding_sink,
+                                                    self.state_persistence,
                                                     self.crust_service,
                                                     self.full_id,
                                                     self.min_section_size,
                                                     proxy_public_id,
+                                                    self.startup_queue,
                                                     self.stats,
-                                                    self.timer) {
-                    State::JoiningNode(joining_node)
+                                                    self.timer,
+                                                    self.tunables) {
+                    if join_progress.matches(&JoinProgress::RelocationRequested) {
+                        outbox.send_event(Event::JoinProgress(JoinProgress::RelocationRequested));
+                    }
+                    (State::JoiningNode(joining_node), Vec::new())
                 } else {
                     outbox.send_event(Event::RestartRequired);
-                    State::Terminated
+                    (State::Terminated, Vec::new())
                 }
             }
             TargetState::Node {
@@ -206,17 +320,58 @@ impl Bootstrapping {
                 our_section,
                 ..
             } => {
-                State::Node(Node::from_bootstrapping(our_section,
-                                                     self.action_sender,
-                                                     self.cache,
-                                                     self.crust_service,
-                                                     old_full_id,
-                                                     self.full_id,
-                                                     self.min_section_size,
-                                                     proxy_public_id,
-                                                     self.stats,
-                                                     self.timer))
+                let mut stats = self.stats;
+                let parked_events = Self::unpark(self.startup_queue, &mut stats);
+                let node = Node::from_bootstrapping(our_section,
+                                                    self.action_sender,
+                                                    self.cache,
+                                                    self.forwarding_sink,
+                                                    self.state_persistence,
+                                                    self.crust_service,
+                                                    old_full_id,
+                                                    self.full_id,
+                                                    self.min_section_size,
+                                                    proxy_public_id,
+                                                    stats,
+                                                    self.timer,
+                                                    self.tunables);
+                (State::Node(node), parked_events)
+            }
+        }
+    }
+
+    // Returns the parked events, and counts them and the dropped ones in `stats`.
+    fn unpark(startup_queue: StartupQueue, stats: &mut Stats) -> Vec<CrustEvent<PublicId>> {
+        let (events, dropped) = startup_queue.into_events();
+        stats.count_startup_queue(events.len(), dropped);
+        events
+    }
+
+    // Returns whether the event is needed to bootstrap, and so must be handled right away: events
+    // of the bootstrap process itself, of our listener, and from our bootstrap connection.
+    fn is_essential(&self, crust_event: &CrustEvent<PublicId>) -> bool {
+        match *crust_event {
+            CrustEvent::BootstrapConnect(..) |
+            CrustEvent::BootstrapFailed |
+            CrustEvent::ListenerStarted(_) |
+            CrustEvent::ListenerFailed => true,
+            #[cfg(feature = "use-mock-crust")]
+            CrustEvent::BootstrapAttemptFailed(..) => true,
+            CrustEvent::LostPeer(pub_id) |
+            CrustEvent::NewMessage(pub_id, _) => {
+                self.bootstrap_connection
+                    .map_or(false, |(bootstrap_id, _, _)| bootstrap_id == pub_id)
             }
+            _ => false,
+        }
+    }
+
+    // Returns the join progress steps to raise. A client doesn't join, so it raises none.
+    fn join_progress_mask(&self) -> JoinProgressMask {
+        if self.client_restriction() {
+            JoinProgressMask::default()
+        } else {
+            self.tunables.join_progress_events
         }
     }
 
@@ -229,29 +384,41 @@ impl Bootstrapping {
     }
 
     fn handle_timeout(&mut self, token: u64) {
-        if let Some((bootstrap_id, bootstrap_token)) = self.bootstrap_connection {
+        if let Some((bootstrap_id, _, bootstrap_token)) = self.bootstrap_connection {
             if bootstrap_token == token {
                 debug!("{:?} Timeout when trying to bootstrap against {:?}.",
                        self,
                        bootstrap_id);
 
-                self.rebootstrap();
+                self.rebootstrap(BootstrapFailure::Timeout);
             }
         }
     }
 
     fn handle_bootstrap_connect(&mut self,
                                 pub_id: PublicId,
-                                socket_addr: SocketAddr)
+                                socket_addr: SocketAddr,
+                                outbox: &mut EventBox)
                                 -> Transition {
         match self.bootstrap_connection {
             None => {
-                debug!("{:?} Received BootstrapConnect from {}.", self, pub_id);
+                debug!("{:?} Received BootstrapConnect from {}. Awaiting IdentifyChallenge.",
+                       self,
+                       pub_id);
                 // Established connection. Pending Validity checks
-                self.send_client_identify(pub_id);
+                let token = self.timer
+                    .schedule(Duration::from_secs(BOOTSTRAP_TIMEOUT_SECS));
+                self.bootstrap_connection = Some((pub_id, socket_addr, token));
                 let _ = self.bootstrap_blacklist.insert(socket_addr);
+                let step = JoinProgress::BootstrapContactAttempt {
+                    contact: socket_addr,
+                    attempt: self.bootstrap_attempts,
+                };
+                if self.join_progress_mask().matches(&step) {
+                    outbox.send_event(Event::JoinProgress(step));
+                }
             }
-            Some((bootstrap_id, _)) if bootstrap_id == pub_id => {
+            Some((bootstrap_id, _, _)) if bootstrap_id == pub_id => {
                 warn!("{:?} Got more than one BootstrapConnect for peer {}.",
                       self,
                       pub_id);
@@ -265,7 +432,19 @@ impl Bootstrapping {
     }
 
     fn handle_bootstrap_failed(&mut self, outbox: &mut EventBox) -> Transition {
-        info!("{:?} Failed to bootstrap. Terminating.", self);
+        if let Some((bootstrap_id, _, _)) = self.bootstrap_connection {
+            // Crust has no more contacts to try, but the connection to this one is still pending
+            // identification. If that fails too, rebootstrapping will report the failure again.
+            debug!("{:?} Bootstrap failed, but still awaiting identification from {:?}.",
+                   self,
+                   bootstrap_id);
+            return Transition::Stay;
+        }
+        info!("{:?} Failed to bootstrap: {:?}. Terminating.",
+              self,
+              self.bootstrap_failures);
+        let failures = mem::replace(&mut self.bootstrap_failures, Vec::new());
+        outbox.send_event(Event::BootstrapFailed(failures));
         outbox.send_event(Event::Terminate);
         Transition::Terminate
     }
@@ -291,6 +470,9 @@ impl Bootstrapping {
         match direct_message {
             DirectMessage::BootstrapIdentify => self.handle_bootstrap_identify(pub_id),
             DirectMessage::BootstrapDeny => self.handle_bootstrap_deny(),
+            DirectMessage::IdentifyChallenge(nonce) => {
+                self.handle_identify_challenge(pub_id, nonce)
+            }
             _ => {
                 debug!("{:?} - Unhandled direct message: {:?}",
                        self,
@@ -307,16 +489,27 @@ impl Bootstrapping {
     fn handle_bootstrap_deny(&mut self) -> Transition {
         info!("{:?} Connection failed: Proxy node needs a larger routing table to accept clients.",
               self);
-        self.rebootstrap();
+        self.rebootstrap(BootstrapFailure::Denied);
         Transition::Stay
     }
 
-    fn send_client_identify(&mut self, pub_id: PublicId) {
-        debug!("{:?} - Sending ClientIdentify to {}.", self, pub_id);
+    fn handle_identify_challenge(&mut self, pub_id: PublicId, nonce: u64) -> Transition {
+        match self.bootstrap_connection {
+            Some((bootstrap_id, _, _)) if bootstrap_id == pub_id => {
+                self.send_client_identify(pub_id, nonce)
+            }
+            _ => {
+                debug!("{:?} - Ignoring IdentifyChallenge from {}, which we are not \
+                        bootstrapping off.",
+                       self,
+                       pub_id);
+            }
+        }
+        Transition::Stay
+    }
 
-        let token = self.timer
-            .schedule(Duration::from_secs(BOOTSTRAP_TIMEOUT_SECS));
-        self.bootstrap_connection = Some((pub_id, token));
+    fn send_client_identify(&mut self, pub_id: PublicId, nonce: u64) {
+        debug!("{:?} - Sending ClientIdentify to {}.", self, pub_id);
 
         let serialised_public_id = match serialisation::serialise(self.full_id.public_id()) {
             Ok(rslt) => rslt,
@@ -325,8 +518,20 @@ impl Bootstrapping {
                 return;
             }
         };
-        let signature = sign::sign_detached(&serialised_public_id,
-                                            self.full_id.signing_private_key());
+        let signed_bytes = match identify_signed_bytes(serialised_public_id.clone(), nonce) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                error!("Failed to serialise nonce: {:?}", error);
+                return;
+            }
+        };
+        let signature = match self.full_id.signing_private_key().sign(&signed_bytes) {
+            Ok(signature) => signature,
+            Err(error) => {
+                error!("{:?} Failed to sign nonce: {:?}", self, error);
+                return;
+            }
+        };
 
         let direct_message = DirectMessage::ClientIdentify {
             serialised_public_id: serialised_public_id,
@@ -345,12 +550,16 @@ impl Bootstrapping {
         let _ = self.crust_service.disconnect(*pub_id);
     }
 
-    fn rebootstrap(&mut self) {
-        if let Some((bootstrap_id, _)) = self.bootstrap_connection.take() {
+    // Records the failure of the pending bootstrap connection, if any, and retries with the
+    // remaining contacts.
+    fn rebootstrap(&mut self, failure: BootstrapFailure) {
+        if let Some((bootstrap_id, socket_addr, _)) = self.bootstrap_connection.take() {
             debug!("{:?} Dropping bootstrap node {:?} and retrying.",
                    self,
                    bootstrap_id);
+            self.record_failure(socket_addr, failure);
             self.crust_service.disconnect(bootstrap_id);
+            self.bootstrap_attempts += 1;
             let crust_user = if self.client_restriction() {
                 CrustUser::Client
             } else {
@@ -360,6 +569,13 @@ impl Bootstrapping {
                 .start_bootstrap(self.bootstrap_blacklist.clone(), crust_user);
         }
     }
+
+    // Remembers why bootstrapping off the contact failed, replacing any earlier reason for it.
+    fn record_failure(&mut self, socket_addr: SocketAddr, failure: BootstrapFailure) {
+        self.bootstrap_failures
+            .retain(|&(addr, _)| addr != socket_addr);
+        self.bootstrap_failures.push((socket_addr, failure));
+    }
 }
 
 impl Base for Bootstrapping {
@@ -15,37 +15,56 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
-use super::common::{Base, Bootstrapped, USER_MSG_CACHE_EXPIRY_DURATION_SECS};
+use super::common::{Base, Bootstrapped, MAX_CANCELLABLE_REQUESTS,
+                    REQUEST_CANCELLATION_WINDOW_SECS, USER_MSG_CACHE_EXPIRY_DURATION_SECS};
 use {CrustEvent, Service};
 use ack_manager::{Ack, AckManager};
 use action::Action;
+use crypto::Signer;
 use error::{InterfaceError, RoutingError};
 use event::Event;
+use expiring_cache::ExpiringCache;
 use id::{FullId, PublicId};
 use maidsafe_utilities::serialisation;
 use messages::{HopMessage, Message, MessageContent, RoutingMessage, SignedMessage, UserMessage,
                UserMessageCache};
 use outbox::EventBox;
+use ping::Pings;
+use proxy_selector::ProxySelector;
 use routing_message_filter::{FilteringResult, RoutingMessageFilter};
 use routing_table::Authority;
 use state_machine::Transition;
 use stats::Stats;
 use std::collections::BTreeSet;
 use std::fmt::{self, Debug, Formatter};
+use std::mem;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
 use std::time::Duration;
 use timer::Timer;
+use tunables::ProxyStrategy;
+use types::MessageId;
 use xor_name::XorName;
 
 /// A node connecting a user to the network, as opposed to a routing / data storage node.
 ///
-/// Each client has a _proxy_: a node through which all requests are routed.
+/// Each client has one or more _proxies_: nodes through which all requests are routed. Which one
+/// carries each request is decided by the `ProxyStrategy`.
 pub struct Client {
     ack_mgr: AckManager,
+    /// The requests we cancelled, whose late responses are dropped.
+    cancelled_requests: ExpiringCache<MessageId, ()>,
+    #[cfg(feature = "use-mock-crust")]
+    claimed_proxy_name: Option<XorName>,
     crust_service: Service,
     full_id: FullId,
+    /// Round-trip time measurements to our proxies which are awaiting a pong.
+    latency_probes: Vec<(PublicId, Receiver<Duration>)>,
     min_section_size: usize,
-    proxy_pub_id: PublicId,
+    pings: Pings,
+    proxies: ProxySelector,
     routing_msg_filter: RoutingMessageFilter,
+    /// The destinations of the requests we sent recently, by message ID, so they can be cancelled.
+    sent_requests: ExpiringCache<MessageId, Authority<XorName>>,
     stats: Stats,
     timer: Timer,
     user_msg_cache: UserMessageCache,
@@ -57,17 +76,31 @@ impl Client {
                               full_id: FullId,
                               min_section_size: usize,
                               proxy_pub_id: PublicId,
+                              proxy_strategy: ProxyStrategy,
                               stats: Stats,
                               timer: Timer,
                               outbox: &mut EventBox)
                               -> Self {
-        let client = Client {
+        let mut proxies = ProxySelector::new(proxy_strategy);
+        let _ = proxies.add(proxy_pub_id);
+        let cancellation_window = Duration::from_secs(REQUEST_CANCELLATION_WINDOW_SECS);
+        let mut client = Client {
             ack_mgr: AckManager::new(),
+            cancelled_requests: ExpiringCache::with_capacity("cancelled_requests",
+                                                             cancellation_window,
+                                                             MAX_CANCELLABLE_REQUESTS),
+            #[cfg(feature = "use-mock-crust")]
+            claimed_proxy_name: None,
             crust_service: crust_service,
             full_id: full_id,
+            latency_probes: Vec::new(),
             min_section_size: min_section_size,
-            proxy_pub_id: proxy_pub_id,
+            pings: Pings::new(),
+            proxies: proxies,
             routing_msg_filter: RoutingMessageFilter::new(),
+            sent_requests: ExpiringCache::with_capacity("sent_requests",
+                                                        cancellation_window,
+                                                        MAX_CANCELLABLE_REQUESTS),
             stats: stats,
             timer: timer,
             user_msg_cache: UserMessageCache::with_expiry_duration(
@@ -75,12 +108,15 @@ impl Client {
         };
 
         debug!("{:?} - State changed to client.", client);
+        if proxy_strategy == ProxyStrategy::LowestLatency {
+            client.probe_proxy_latencies();
+        }
 
         outbox.send_event(Event::Connected);
         client
     }
 
-    pub fn handle_action(&mut self, action: Action) -> Transition {
+    pub fn handle_action(&mut self, action: Action, outbox: &mut EventBox) -> Transition {
         match action {
             Action::ClientSendRequest {
                 content,
@@ -88,11 +124,23 @@ impl Client {
                 priority,
                 result_tx,
             } => {
+                let proxy_pub_id = match self.proxies.choose() {
+                    Some(pub_id) => pub_id,
+                    None => {
+                        let _ = result_tx.send(Err(InterfaceError::NotConnected));
+                        return Transition::Stay;
+                    }
+                };
+                trace!("{:?} Sending request #{} via proxy {}.",
+                       self,
+                       self.proxies.sent_count(&proxy_pub_id),
+                       proxy_pub_id);
                 let src = Authority::Client {
                     client_id: *self.full_id.public_id(),
-                    proxy_node_name: *self.proxy_pub_id.name(),
+                    proxy_node_name: *proxy_pub_id.name(),
                 };
 
+                let _ = self.sent_requests.insert(content.message_id(), dst);
                 let user_msg = UserMessage::Request(content);
                 let result = match self.send_user_message(src, dst, user_msg, priority) {
                     Err(RoutingError::Interface(err)) => Err(err),
@@ -101,17 +149,49 @@ impl Client {
 
                 let _ = result_tx.send(result);
             }
-            Action::NodeSendMessage { result_tx, .. } => {
+            Action::CancelRequest { msg_id, result_tx } => {
+                let _ = result_tx.send(self.cancel_request(msg_id));
+            }
+            Action::NodeSendMessage { result_tx, .. } |
+            Action::NodeSendBatch { result_tx, .. } |
+            Action::DisconnectPeer { result_tx, .. } |
+            Action::BanPeer { result_tx, .. } |
+            Action::SetConnectionQuotas { result_tx, .. } |
+            Action::PingPeer { result_tx, .. } => {
+                let _ = result_tx.send(Err(InterfaceError::InvalidState));
+            }
+            Action::SetProxyStrategy { strategy, result_tx } => {
+                debug!("{:?} Setting proxy strategy to {:?}.", self, strategy);
+                self.proxies.set_strategy(strategy);
+                if strategy == ProxyStrategy::LowestLatency {
+                    self.probe_proxy_latencies();
+                }
+                let _ = result_tx.send(Ok(()));
+            }
+            Action::DumpDecisionLog { result_tx } => {
+                let _ = result_tx.send(Err(InterfaceError::InvalidState));
+            }
+            Action::GetEffectiveConfig { result_tx } => {
+                let _ = result_tx.send(Err(InterfaceError::InvalidState));
+            }
+            Action::UpdateConfig { result_tx, .. } => {
                 let _ = result_tx.send(Err(InterfaceError::InvalidState));
             }
             Action::Id { result_tx } => {
                 let _ = result_tx.send(*self.id());
             }
+            Action::GetStats { result_tx } => {
+                let _ = result_tx.send(self.routing_msg_filter.diagnostics());
+            }
             Action::Timeout(token) => self.handle_timeout(token),
             Action::ResourceProofResult(..) => {
                 error!("Action::ResourceProofResult received by Client state");
             }
             Action::Terminate => {
+                for pub_id in self.proxies.pub_ids() {
+                    self.disconnect_peer(&pub_id);
+                }
+                outbox.send_event(Event::Terminate);
                 return Transition::Terminate;
             }
         }
@@ -126,6 +206,8 @@ impl Client {
         match crust_event {
             CrustEvent::LostPeer(pub_id) => self.handle_lost_peer(pub_id, outbox),
             CrustEvent::NewMessage(pub_id, bytes) => self.handle_new_message(pub_id, bytes, outbox),
+            CrustEvent::BootstrapAccept(pub_id, _) |
+            CrustEvent::ConnectSuccess(pub_id) => self.handle_connect(pub_id),
             _ => {
                 debug!("{:?} Unhandled crust event {:?}", self, crust_event);
                 Transition::Stay
@@ -133,15 +215,109 @@ impl Client {
         }
     }
 
+    /// Clients don't perform any node duties, so only the connections to our proxies are kept.
+    fn handle_connect(&mut self, pub_id: PublicId) -> Transition {
+        if !self.proxies.contains(&pub_id) {
+            debug!("{:?} Refusing connection from {:?} - clients don't accept peers.",
+                   self,
+                   pub_id);
+            self.disconnect_peer(&pub_id);
+        }
+        Transition::Stay
+    }
+
+    fn disconnect_peer(&mut self, pub_id: &PublicId) {
+        debug!("{:?} Disconnecting {}. Calling crust::Service::disconnect.",
+               self,
+               pub_id);
+        let _ = self.crust_service.disconnect(*pub_id);
+    }
+
     fn handle_ack_response(&mut self, ack: Ack) -> Transition {
         self.ack_mgr.receive(ack);
         Transition::Stay
     }
 
+    /// Forgets the request, so that late responses to it are dropped, and asks its destination to
+    /// stop handling it. The notice is best-effort: it isn't retried if it gets lost.
+    fn cancel_request(&mut self, msg_id: MessageId) -> Result<(), InterfaceError> {
+        let dst = match self.sent_requests.remove(&msg_id) {
+            Some(dst) => dst,
+            None => return Err(InterfaceError::UnknownRequest),
+        };
+        let _ = self.cancelled_requests.insert(msg_id, ());
+        let proxy_pub_id = match self.proxies.choose() {
+            Some(pub_id) => pub_id,
+            None => return Err(InterfaceError::NotConnected),
+        };
+        let src = Authority::Client {
+            client_id: *self.full_id.public_id(),
+            proxy_node_name: *proxy_pub_id.name(),
+        };
+        let signature = match serialisation::serialise(&msg_id)
+                  .ok()
+                  .and_then(|bytes| self.full_id.signing_private_key().sign(&bytes).ok()) {
+            Some(signature) => signature,
+            None => {
+                debug!("{:?} Failed to sign the cancellation of {:?}.", self, msg_id);
+                return Ok(());
+            }
+        };
+        let content = MessageContent::CancelRequest {
+            msg_id: msg_id,
+            signature: signature,
+        };
+        match self.send_routing_message(src, dst, content) {
+            Err(RoutingError::Interface(err)) => Err(err),
+            Err(_) | Ok(_) => Ok(()),
+        }
+    }
+
     fn handle_timeout(&mut self, token: u64) {
+        self.collect_latency_probes();
         self.resend_unacknowledged_timed_out_msgs(token)
     }
 
+    /// Pings each of our proxies to measure its round-trip time, for
+    /// `ProxyStrategy::LowestLatency`.
+    fn probe_proxy_latencies(&mut self) {
+        for pub_id in self.proxies.pub_ids() {
+            let (reply_tx, reply_rx) = mpsc::channel();
+            let ping = Message::Ping(self.pings.start(pub_id, reply_tx));
+            self.send_message(&pub_id, ping);
+            self.latency_probes.push((pub_id, reply_rx));
+        }
+    }
+
+    /// Records the round-trip times of the answered probes. A proxy which didn't answer before the
+    /// ping timed out is taken out of rotation, unless it is our last one.
+    fn collect_latency_probes(&mut self) {
+        let probes = mem::replace(&mut self.latency_probes, Vec::new());
+        for (pub_id, reply_rx) in probes {
+            match reply_rx.try_recv() {
+                Ok(latency) => self.proxies.record_latency(&pub_id, latency),
+                Err(TryRecvError::Empty) => self.latency_probes.push((pub_id, reply_rx)),
+                Err(TryRecvError::Disconnected) => {
+                    if self.proxies.len() > 1 && self.proxies.remove(&pub_id) {
+                        debug!("{:?} Proxy {} is unresponsive - removed from rotation.",
+                               self,
+                               pub_id);
+                        self.disconnect_peer(&pub_id);
+                    }
+                }
+            }
+        }
+    }
+
+    fn handle_pong(&mut self, pub_id: PublicId, nonce: u64) -> Transition {
+        if self.pings.handle_pong(&pub_id, nonce) {
+            self.collect_latency_probes();
+        } else {
+            debug!("{:?} Unexpected pong from {}.", self, pub_id);
+        }
+        Transition::Stay
+    }
+
     fn handle_new_message(&mut self,
                           pub_id: PublicId,
                           bytes: Vec<u8>,
@@ -149,6 +325,7 @@ impl Client {
                           -> Transition {
         let transition = match serialisation::deserialise(&bytes) {
             Ok(Message::Hop(hop_msg)) => self.handle_hop_message(hop_msg, pub_id, outbox),
+            Ok(Message::Pong(nonce)) => Ok(self.handle_pong(pub_id, nonce)),
             Ok(message) => {
                 debug!("{:?} - Unhandled new message: {:?}", self, message);
                 Ok(Transition::Stay)
@@ -171,8 +348,8 @@ impl Client {
                           pub_id: PublicId,
                           outbox: &mut EventBox)
                           -> Result<Transition, RoutingError> {
-        if self.proxy_pub_id == pub_id {
-            hop_msg.verify(self.proxy_pub_id.signing_public_key())?;
+        if self.proxies.contains(&pub_id) {
+            hop_msg.verify(pub_id.signing_public_key())?;
         } else {
             return Err(RoutingError::UnknownConnection(pub_id));
         }
@@ -225,6 +402,14 @@ impl Client {
                        routing_msg.dst);
                 if let Some(msg) = self.user_msg_cache
                        .add(hash, part_count, part_index, payload) {
+                    if let UserMessage::Response(ref response) = msg {
+                        if self.cancelled_requests.contains_key(&response.message_id()) {
+                            trace!("{:?} Dropping response to cancelled request {:?}.",
+                                   self,
+                                   response.message_id());
+                            return Transition::Stay;
+                        }
+                    }
                     self.stats().count_user_message(&msg);
                     outbox.send_event(msg.into_event(routing_msg.src, routing_msg.dst));
                 }
@@ -277,14 +462,20 @@ impl Base for Client {
     fn handle_lost_peer(&mut self, pub_id: PublicId, outbox: &mut EventBox) -> Transition {
         debug!("{:?} Received LostPeer - {:?}", self, pub_id);
 
-        if self.proxy_pub_id == pub_id {
+        if !self.proxies.remove(&pub_id) {
+            return Transition::Stay;
+        }
+        if self.proxies.is_empty() {
             debug!("{:?} Lost bootstrap connection to {:?} ({:?}).",
                    self,
-                   self.proxy_pub_id.name(),
+                   pub_id.name(),
                    pub_id);
             outbox.send_event(Event::Terminate);
             Transition::Terminate
         } else {
+            debug!("{:?} Lost proxy {:?} - failing over to the remaining ones.",
+                   self,
+                   pub_id);
             Transition::Stay
         }
     }
@@ -319,9 +510,12 @@ impl Bootstrapped for Client {
                        self,
                        unacked_msg);
                 self.stats.count_unacked();
-            } else if let Err(error) =
-                self.send_routing_message_via_route(unacked_msg.routing_msg, unacked_msg.route) {
-                debug!("{:?} Failed to send message: {:?}", self, error);
+            } else {
+                let route = unacked_msg.route;
+                if let Err(error) =
+                    self.send_routing_message_via_route(unacked_msg.into_routing_msg(), route) {
+                    debug!("{:?} Failed to send message: {:?}", self, error);
+                }
             }
         }
     }
@@ -337,12 +531,15 @@ impl Bootstrapped for Client {
         }
 
         // Get PublicId of the proxy node
-        match routing_msg.src {
+        let proxy_pub_id = match routing_msg.src {
             Authority::Client { ref proxy_node_name, .. } => {
-                if *self.proxy_pub_id.name() != *proxy_node_name {
-                    error!("{:?} Unable to find connection to proxy node in proxy map",
-                           self);
-                    return Err(RoutingError::ProxyConnectionNotFound);
+                match self.proxies.get(proxy_node_name) {
+                    Some(&pub_id) => pub_id,
+                    None => {
+                        error!("{:?} Unable to find connection to proxy node in proxy map",
+                               self);
+                        return Err(RoutingError::ProxyConnectionNotFound);
+                    }
                 }
             }
             _ => {
@@ -352,12 +549,13 @@ impl Bootstrapped for Client {
             }
         };
 
+        #[cfg(feature = "use-mock-crust")]
+        let routing_msg = self.with_claimed_proxy_name(routing_msg);
         let signed_msg = SignedMessage::new(routing_msg, self.full_id(), vec![])?;
 
-        let proxy_pub_id = self.proxy_pub_id;
         if self.add_to_pending_acks(signed_msg.routing_message(), route) &&
            !self.filter_outgoing_routing_msg(signed_msg.routing_message(), &proxy_pub_id, route) {
-            let bytes = self.to_hop_bytes(signed_msg.clone(), route, BTreeSet::new())?;
+            let bytes = self.to_hop_bytes(signed_msg.clone(), route, BTreeSet::new(), 0)?;
             self.send_or_drop(&proxy_pub_id, bytes, signed_msg.priority());
         }
 
@@ -378,6 +576,21 @@ impl Client {
     pub fn get_timed_out_tokens(&mut self) -> Vec<u64> {
         self.timer.get_timed_out_tokens()
     }
+
+    pub fn set_claimed_proxy_name(&mut self, name: Option<XorName>) {
+        self.claimed_proxy_name = name;
+    }
+
+    // Replaces the proxy name in the source of the message with the one set via
+    // `set_claimed_proxy_name`, if any, to impersonate a misbehaving client.
+    fn with_claimed_proxy_name(&self, mut routing_msg: RoutingMessage) -> RoutingMessage {
+        if let Some(name) = self.claimed_proxy_name {
+            if let Authority::Client { ref mut proxy_node_name, .. } = routing_msg.src {
+                *proxy_node_name = name;
+            }
+        }
+        routing_msg
+    }
 }
 
 impl Debug for Client {
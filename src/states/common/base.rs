@@ -49,6 +49,12 @@ pub trait Base: Debug {
         None
     }
 
+    // Returns the serialised message `bytes` in the wire format version to send to the given
+    // peer.
+    fn encode_for(&self, _pub_id: &PublicId, bytes: Vec<u8>) -> Vec<u8> {
+        bytes
+    }
+
     fn send_message(&mut self, pub_id: &PublicId, message: Message) {
         let priority = message.priority();
 
@@ -71,6 +77,7 @@ pub trait Base: Debug {
     // Sends the given `bytes` to the peer with the given Crust `PublicId`. If that results in an
     // error, it disconnects from the peer.
     fn send_or_drop(&mut self, pub_id: &PublicId, bytes: Vec<u8>, priority: u8) {
+        let bytes = self.encode_for(pub_id, bytes);
         self.stats().count_bytes(bytes.len());
 
         if let Err(err) = self.crust_service().send(*pub_id, bytes, priority) {
@@ -16,11 +16,10 @@
 // relating to use of the SAFE Network Software.
 
 use super::Base;
-use ack_manager::{ACK_TIMEOUT_SECS, Ack, AckManager, UnacknowledgedMessage};
+use ack_manager::{ACK_TIMEOUT_SECS, Ack, AckManager};
 use error::RoutingError;
 use id::PublicId;
-use maidsafe_utilities::serialisation;
-use messages::{HopMessage, Message, MessageContent, RoutingMessage, SignedMessage};
+use messages::{HopMessage, MessageContent, RoutingMessage, SignedMessage};
 use routing_message_filter::RoutingMessageFilter;
 use routing_table::Authority;
 use std::collections::BTreeSet;
@@ -43,6 +42,18 @@ pub trait Bootstrapped: Base {
     fn routing_msg_filter(&mut self) -> &mut RoutingMessageFilter;
     fn timer(&mut self) -> &mut Timer;
 
+    /// Returns true if this is a message we already received an ack for, so it needn't be sent
+    /// again.
+    fn did_receive_ack(&mut self, routing_msg: &RoutingMessage) -> bool {
+        if let MessageContent::Ack(..) = routing_msg.content {
+            return false;
+        }
+        match Ack::compute(routing_msg) {
+            Ok(ack) => self.ack_mgr_mut().did_receive(ack),
+            Err(_) => false,
+        }
+    }
+
     /// Examines a message, and possibly adds a pending ack. Returns true unless
     /// this is a message we already received an ack for.
     ///
@@ -68,13 +79,8 @@ pub trait Bootstrapped: Base {
 
         let token = self.timer()
             .schedule(Duration::from_secs(ACK_TIMEOUT_SECS));
-        let unacked_msg = UnacknowledgedMessage {
-            routing_msg: routing_msg.clone(),
-            route: route,
-            timer_token: token,
-        };
-
-        if let Some(ejected) = self.ack_mgr_mut().add_to_pending(ack, unacked_msg) {
+        let unacked_msg = routing_msg.clone();
+        if let Some(ejected) = self.ack_mgr_mut().add_to_pending(ack, unacked_msg, route, token) {
             debug!("{:?} - Ejected pending ack: {:?} - {:?}",
                    self,
                    ack,
@@ -107,9 +113,12 @@ pub trait Bootstrapped: Base {
                        self,
                        unacked_msg);
                 self.stats().count_unacked();
-            } else if let Err(error) =
-                self.send_routing_message_via_route(unacked_msg.routing_msg, unacked_msg.route) {
-                debug!("{:?} Failed to send message: {:?}", self, error);
+            } else {
+                let route = unacked_msg.route;
+                if let Err(error) =
+                    self.send_routing_message_via_route(unacked_msg.into_routing_msg(), route) {
+                    debug!("{:?} Failed to send message: {:?}", self, error);
+                }
             }
         }
     }
@@ -153,13 +162,14 @@ pub trait Bootstrapped: Base {
     fn to_hop_bytes(&self,
                     signed_msg: SignedMessage,
                     route: u8,
-                    sent_to: BTreeSet<XorName>)
+                    sent_to: BTreeSet<XorName>,
+                    hop_count: u8)
                     -> Result<Vec<u8>, RoutingError> {
         let hop_msg = HopMessage::new(signed_msg,
                                       route,
                                       sent_to,
+                                      hop_count,
                                       self.full_id().signing_private_key())?;
-        let message = Message::Hop(hop_msg);
-        Ok(serialisation::serialise(&message)?)
+        hop_msg.into_bytes()
     }
 }
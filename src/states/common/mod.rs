@@ -22,3 +22,8 @@ pub use self::base::Base;
 pub use self::bootstrapped::Bootstrapped;
 
 pub const USER_MSG_CACHE_EXPIRY_DURATION_SECS: u64 = 60 * 20;
+/// Time (in seconds) for which a client can cancel a request it sent, and for which nodes remember
+/// the client requests they delivered and the cancellations they received.
+pub const REQUEST_CANCELLATION_WINDOW_SECS: u64 = 60 * 5;
+/// The maximum number of requests a client or node remembers for cancellation.
+pub const MAX_CANCELLABLE_REQUESTS: usize = 1000;
@@ -22,7 +22,8 @@ use ack_manager::{Ack, AckManager};
 use action::Action;
 use cache::Cache;
 use error::{InterfaceError, RoutingError};
-use event::Event;
+use event::{Event, JoinProgress};
+use forwarding::ForwardingSink;
 use id::{FullId, PublicId};
 use maidsafe_utilities::serialisation;
 use messages::{HopMessage, Message, MessageContent, RoutingMessage, SignedMessage};
@@ -30,7 +31,9 @@ use outbox::EventBox;
 use resource_prover::RESOURCE_PROOF_DURATION_SECS;
 use routing_message_filter::{FilteringResult, RoutingMessageFilter};
 use routing_table::{Authority, Prefix};
+use startup_queue::StartupQueue;
 use state_machine::{State, Transition};
+use state_store::StatePersistence;
 use stats::Stats;
 use std::collections::BTreeSet;
 use std::fmt;
@@ -38,6 +41,7 @@ use std::fmt::{Debug, Formatter};
 use std::sync::mpsc::Receiver;
 use std::time::Duration;
 use timer::Timer;
+use tunables::{EffectiveConfig, Tunables};
 use types::{MessageId, RoutingActionSender};
 use xor_name::XorName;
 
@@ -51,26 +55,38 @@ pub struct JoiningNode {
     full_id: FullId,
     /// Only held here to be passed eventually to the `Node` state.
     cache: Box<Cache>,
+    /// Only held here to be passed eventually to the `Node` state.
+    forwarding_sink: Option<Box<ForwardingSink>>,
+    /// Only held here to be passed eventually to the `Node` state.
+    state_persistence: Option<StatePersistence>,
     min_section_size: usize,
     proxy_pub_id: PublicId,
     /// The queue of routing messages addressed to us. These do not themselves need forwarding,
     /// although they may wrap a message which needs forwarding.
     routing_msg_filter: RoutingMessageFilter,
+    /// Crust events which are not needed to relocate, to be handled once we joined as a node.
+    startup_queue: StartupQueue,
     stats: Stats,
     relocation_timer_token: u64,
     timer: Timer,
+    /// Only held here to be passed eventually to the `Node` state.
+    tunables: Tunables,
 }
 
 impl JoiningNode {
     #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     pub fn from_bootstrapping(action_sender: RoutingActionSender,
                               cache: Box<Cache>,
+                              forwarding_sink: Option<Box<ForwardingSink>>,
+                              state_persistence: Option<StatePersistence>,
                               crust_service: Service,
                               full_id: FullId,
                               min_section_size: usize,
                               proxy_pub_id: PublicId,
+                              startup_queue: StartupQueue,
                               stats: Stats,
-                              timer: Timer)
+                              timer: Timer,
+                              tunables: Tunables)
                               -> Option<Self> {
         let duration = Duration::from_secs(RELOCATE_TIMEOUT_SECS);
         let relocation_timer_token = timer.schedule(duration);
@@ -80,12 +96,16 @@ impl JoiningNode {
             crust_service: crust_service,
             full_id: full_id,
             cache: cache,
+            forwarding_sink: forwarding_sink,
+            state_persistence: state_persistence,
             min_section_size: min_section_size,
             proxy_pub_id: proxy_pub_id,
-            routing_msg_filter: RoutingMessageFilter::new(),
+            routing_msg_filter: RoutingMessageFilter::with_tunables(&tunables),
+            startup_queue: startup_queue,
             stats: stats,
             relocation_timer_token: relocation_timer_token,
             timer: timer,
+            tunables: tunables,
         };
         if let Err(error) = joining_node.relocate() {
             error!("{:?} Failed to start relocation: {:?}", joining_node, error);
@@ -99,13 +119,32 @@ impl JoiningNode {
     pub fn handle_action(&mut self, action: Action, outbox: &mut EventBox) -> Transition {
         match action {
             Action::ClientSendRequest { ref result_tx, .. } |
-            Action::NodeSendMessage { ref result_tx, .. } => {
+            Action::CancelRequest { ref result_tx, .. } |
+            Action::NodeSendMessage { ref result_tx, .. } |
+            Action::NodeSendBatch { ref result_tx, .. } |
+            Action::DisconnectPeer { ref result_tx, .. } |
+            Action::BanPeer { ref result_tx, .. } |
+            Action::SetConnectionQuotas { ref result_tx, .. } |
+            Action::SetProxyStrategy { ref result_tx, .. } |
+            Action::UpdateConfig { ref result_tx, .. } |
+            Action::PingPeer { ref result_tx, .. } => {
+                warn!("{:?} Cannot handle {:?} - not joined.", self, action);
+                let _ = result_tx.send(Err(InterfaceError::InvalidState));
+            }
+            Action::DumpDecisionLog { ref result_tx } => {
                 warn!("{:?} Cannot handle {:?} - not joined.", self, action);
                 let _ = result_tx.send(Err(InterfaceError::InvalidState));
             }
+            Action::GetEffectiveConfig { result_tx } => {
+                let config = EffectiveConfig::new(self.min_section_size, &self.tunables);
+                let _ = result_tx.send(Ok(config));
+            }
             Action::Id { result_tx } => {
                 let _ = result_tx.send(*self.id());
             }
+            Action::GetStats { result_tx } => {
+                let _ = result_tx.send(self.routing_msg_filter.diagnostics());
+            }
             Action::Timeout(token) => {
                 if let Transition::Terminate = self.handle_timeout(token, outbox) {
                     return Transition::Terminate;
@@ -115,6 +154,8 @@ impl JoiningNode {
                 warn!("{:?} Cannot handle {:?} - not joined.", self, action);
             }
             Action::Terminate => {
+                let _ = self.crust_service.disconnect(self.proxy_pub_id);
+                outbox.send_event(Event::Terminate);
                 return Transition::Terminate;
             }
         }
@@ -125,6 +166,17 @@ impl JoiningNode {
                               crust_event: CrustEvent<PublicId>,
                               outbox: &mut EventBox)
                               -> Transition {
+        // Only our proxy is needed to relocate. Anything else is handled once we joined.
+        let from_proxy = match crust_event {
+            CrustEvent::LostPeer(pub_id) |
+            CrustEvent::NewMessage(pub_id, _) => pub_id == self.proxy_pub_id,
+            _ => false,
+        };
+        if !from_proxy {
+            trace!("{:?} Parking {:?} until we are ready.", self, crust_event);
+            self.startup_queue.push(crust_event);
+            return Transition::Stay;
+        }
         match crust_event {
             CrustEvent::LostPeer(pub_id) => self.handle_lost_peer(pub_id, outbox),
             CrustEvent::NewMessage(pub_id, bytes) => self.handle_new_message(pub_id, bytes),
@@ -142,6 +194,10 @@ impl JoiningNode {
                               our_section: (Prefix<XorName>, BTreeSet<PublicId>),
                               outbox: &mut EventBox)
                               -> State {
+        let step = JoinProgress::RelocationReceived(*new_full_id.public_id().name());
+        if self.tunables.join_progress_events.matches(&step) {
+            outbox.send_event(Event::JoinProgress(step));
+        }
         let service = Self::start_new_crust_service(self.crust_service,
                                                     *new_full_id.public_id(),
                                                     crust_rx,
@@ -150,14 +206,18 @@ impl JoiningNode {
             old_full_id: self.full_id,
             our_section: our_section,
         };
-        if let Some(bootstrapping) =
+        if let Some(mut bootstrapping) =
             Bootstrapping::new(self.action_sender,
                                self.cache,
+                               self.forwarding_sink,
+                               self.state_persistence,
                                target_state,
                                service,
                                new_full_id,
                                self.min_section_size,
-                               self.timer) {
+                               self.timer,
+                               self.tunables) {
+            bootstrapping.resume_startup_queue(self.startup_queue);
             State::Bootstrapping(bootstrapping)
         } else {
             outbox.send_event(Event::RestartRequired);
@@ -261,7 +321,11 @@ impl JoiningNode {
             UserMessagePart { .. } |
             AcceptAsCandidate { .. } |
             CandidateApproval { .. } |
-            NodeApproval { .. } => {
+            NodeApproval { .. } |
+            ClientRelay(..) |
+            RedirectToClient(..) |
+            ClientUnreachable(..) |
+            CancelRequest { .. } => {
                 warn!("{:?} Not joined yet. Not handling {:?} from {:?} to {:?}",
                       self,
                       routing_msg.content,
@@ -407,7 +471,7 @@ impl Bootstrapped for JoiningNode {
         let proxy_pub_id = self.proxy_pub_id;
         if self.add_to_pending_acks(signed_msg.routing_message(), route) &&
            !self.filter_outgoing_routing_msg(signed_msg.routing_message(), &proxy_pub_id, route) {
-            let bytes = self.to_hop_bytes(signed_msg.clone(), route, BTreeSet::new())?;
+            let bytes = self.to_hop_bytes(signed_msg.clone(), route, BTreeSet::new(), 0)?;
             self.send_or_drop(&proxy_pub_id, bytes, signed_msg.priority());
         }
 
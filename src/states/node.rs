@@ -15,26 +15,45 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
-use super::common::{Base, Bootstrapped, USER_MSG_CACHE_EXPIRY_DURATION_SECS};
+use super::common::{Base, Bootstrapped, MAX_CANCELLABLE_REQUESTS,
+                    REQUEST_CANCELLATION_WINDOW_SECS, USER_MSG_CACHE_EXPIRY_DURATION_SECS};
 use {CrustEvent, PrivConnectionInfo, PubConnectionInfo, QUORUM_DENOMINATOR, QUORUM_NUMERATOR,
      Service};
 use ack_manager::{Ack, AckManager};
 use action::Action;
 use cache::Cache;
+use connection_audit::ConnectionAudit;
+use convergence::ConvergenceTracker;
 use crust::{ConnectionInfoResult, CrustError, CrustUser};
+use crypto::{Signer, Verifier};
+use decision_log::{self, Decision, DecisionLog, FilterOutcome};
+use departure::DepartureConsensus;
 use error::{InterfaceError, RoutingError};
-use event::Event;
+use event::{AuditReport, ConfigRefusal, Event, Health, JoinProgress, RefusalReason,
+            RefusedSetting};
+use expiring_cache::ExpiringCache;
+use forwarding::{ForwardingDecision, ForwardingReason, ForwardingSink};
 use id::{FullId, PublicId};
 use itertools::Itertools;
 use log::LogLevel;
 use lru_time_cache::LruCache;
+#[cfg(feature = "use-mock-crust")]
+use maidsafe_utilities::SeededRng;
 use maidsafe_utilities::serialisation;
 use messages::{DEFAULT_PRIORITY, DirectMessage, HopMessage, Message, MessageContent,
-               RoutingMessage, SectionList, SignedMessage, UserMessage, UserMessageCache};
+               RoutingMessage, SectionList, SignedMessage, UserMessage, UserMessageCache,
+               identify_signed_bytes};
+#[cfg(feature = "use-mock-crust")]
+use mock_crust::Endpoint;
 use outbox::{EventBox, EventBuf};
+use peer_generations::PeerGenerations;
 use peer_manager::{ConnectionInfoPreparedResult, Peer, PeerManager, PeerState, ReconnectingPeer,
                    RoutingConnection, SectionMap};
-use rand::{self, Rng};
+use peer_manager::Error as PeerManagerError;
+use ping::Pings;
+use processing_stats::{PhaseTimer, ProcessingPhase, ProcessingStats};
+use rand::{self, Rand, Rng};
+use recovery::{QueuedMessage, RECOVERY_RETRY_SECS, RecentPeers, Recovery};
 use resource_prover::{RESOURCE_PROOF_DURATION_SECS, ResourceProver};
 use routing_message_filter::{FilteringResult, RoutingMessageFilter};
 use routing_table::{Authority, OwnMergeState, Prefix, RemovalDetails, RoutingTable,
@@ -43,23 +62,35 @@ use routing_table::Error as RoutingTableError;
 use rust_sodium::crypto::{box_, sign};
 use rust_sodium::crypto::hash::sha256;
 use section_list_cache::SectionListCache;
+use sha3;
 use signature_accumulator::SignatureAccumulator;
 use state_machine::Transition;
-use stats::Stats;
-use std::{cmp, fmt, iter, mem};
-use std::collections::{BTreeSet, VecDeque};
+use state_store::StatePersistence;
+use stats::{Diagnostics, Stats};
 #[cfg(feature = "use-mock-crust")]
-use std::collections::BTreeMap;
+use stats::PendingWork;
+use std::{cmp, fmt, iter, mem};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::collections::hash_map::Entry;
 use std::fmt::{Debug, Formatter};
+use std::net::IpAddr;
+use std::sync::mpsc::Sender;
 use std::time::Duration;
+use table_gossip::{MAX_GOSSIP_NAMES, TableGossip};
 use timer::Timer;
+use tiny_keccak::sha3_256;
+use tunables::{EffectiveConfig, LiveConfig, PartialConfig, Tunables, group_quorum};
 use tunnels::Tunnels;
 use types::{MessageId, RoutingActionSender};
 use utils::{self, DisplayDuration};
+use wire_version::WireVersions;
 use xor_name::XorName;
 
 /// Time (in seconds) after which a `Tick` event is sent.
 const TICK_TIMEOUT_SECS: u64 = 60;
+/// Time (in seconds) after which a client's proxy node is forgotten by its `ClientManager`s unless
+/// the proxy repeats its `ClientRelay` announcement, which it does on every `Tick`.
+const CLIENT_RELAY_EXPIRY_SECS: u64 = 3 * TICK_TIMEOUT_SECS;
 /// The number of required leading zero bits for the resource proof
 const RESOURCE_PROOF_DIFFICULTY: u8 = 0;
 /// The total size of the resource proof data.
@@ -72,8 +103,18 @@ const SU_MAX_TIMEOUT_SECS: u64 = 300;
 const CANDIDATE_STATUS_INTERVAL_SECS: u64 = 60;
 /// Duration for which `OwnSectionMerge` messages are kept in the cache, in seconds.
 const MERGE_TIMEOUT_SECS: u64 = 300;
-/// Duration for which to hold the bootstrappers, in seconds.
-const BOOTSTRAPPER_HOLD_DUR_SECS: u64 = 300;
+/// Duration for which the expected churn generation of a peer is remembered, in seconds.
+const PEER_GENERATION_EXPIRY_SECS: u64 = 300;
+/// The maximum number of recently lost routing peers remembered, to reconnect to them.
+const RECENT_PEERS_CAPACITY: usize = 64;
+/// Duration for which a lost routing peer is remembered, in seconds.
+const RECENT_PEER_EXPIRY_SECS: u64 = 600;
+/// The number of errors from handling received messages kept for tests to inspect.
+#[cfg(feature = "use-mock-crust")]
+const MAX_RECORDED_MESSAGE_ERRORS: usize = 64;
+
+/// The target interval and section sent to a candidate in a `RelocateResponse`.
+type RelocateResponseContent = ((XorName, XorName), (Prefix<XorName>, BTreeSet<PublicId>));
 
 pub struct Node {
     ack_mgr: AckManager,
@@ -114,24 +155,115 @@ pub struct Node {
     candidate_timer_token: Option<u64>,
     /// The timer token for displaying the current candidate status.
     candidate_status_token: Option<u64>,
-    /// Hold the kind of bootstrappers.
-    bootstrappers: LruCache<PublicId, CrustUser>,
+    /// Hold the kind of bootstrappers, until they identify themselves. Entries are only ever
+    /// inserted and removed, so they expire a fixed time after the peer bootstrapped.
+    bootstrappers: ExpiringCache<PublicId, CrustUser>,
+    /// The nonces we challenged newly connected peers with, which they need to sign to identify
+    /// themselves.
+    identify_nonces: ExpiringCache<PublicId, u64>,
+    /// The client requests we delivered recently, by client and message ID, so that we can
+    /// report their cancellation.
+    delivered_requests: ExpiringCache<(PublicId, MessageId), ()>,
+    /// The client requests cancelled recently. They are dropped if they still arrive afterwards.
+    cancelled_requests: ExpiringCache<(PublicId, MessageId), ()>,
+    /// The IDs of the requests we sent recently as a managed node. Responses to any other IDs
+    /// addressed to us are dropped.
+    sent_requests: ExpiringCache<MessageId, ()>,
+    /// The wire messages, with their recipients and priorities, of the retry-safe user messages
+    /// we sent within the retry window, by hash of their source, destination and content.
+    recent_sends: ExpiringCache<sha3::Digest256, Vec<(PublicId, Vec<u8>, u8)>>,
+    /// Collects the wire messages sent while a retry-safe user message is being sent.
+    captured_sends: Option<Vec<(PublicId, Vec<u8>, u8)>>,
+    /// Relocated names recently assigned to joining nodes, by their original public ID.
+    relocation_cache: LruCache<PublicId, XorName>,
+    /// The target intervals and section our section recently sent to candidates in their
+    /// `RelocateResponse`, by their original public ID.
+    relocate_responses: LruCache<PublicId, RelocateResponseContent>,
+    /// Proxy node names announced for clients we are a `ClientManager` of, by client public ID.
+    client_relays: LruCache<PublicId, XorName>,
     resource_prover: ResourceProver,
     joining_prefix: Prefix<XorName>,
+    /// The number of members of the section we are joining, as reported in our relocation.
+    joining_section_size: usize,
+    /// Where to report the peers chosen for each routing message, if anywhere.
+    forwarding_sink: Option<Box<ForwardingSink>>,
+    /// Our health as last computed by `update_health`.
+    health: Health,
+    /// Peers we initiated connection attempts to, some of which may have completed since.
+    connects_in_flight: BTreeSet<PublicId>,
+    /// Connection attempts waiting for `connect_slot_free`, in the order they were requested.
+    queued_connects: VecDeque<(PublicId, Authority<XorName>, Authority<XorName>, ReconnectingPeer)>,
+    /// Token of the timer which runs for `connect_spacing` after initiating a connection attempt.
+    connect_spacing_token: Option<u64>,
+    /// The number of changes to our section we have seen, stamped on connection info messages.
+    churn_generation: u64,
+    /// The churn generations we expect of other nodes, to drop stale connection info messages.
+    peer_generations: PeerGenerations,
+    /// The state of periodic audits of our routing table against Crust's live connections, if
+    /// enabled.
+    audit: Option<ConnectionAudit>,
+    /// The timer token for the next connection audit.
+    audit_timer_token: Option<u64>,
+    /// The routing peers we banned, by the timer token for the end of their ban.
+    ban_timer_tokens: BTreeMap<u64, PublicId>,
+    /// The state of periodic routing table gossip with our peers, if enabled.
+    gossip: Option<TableGossip>,
+    /// The timer token for the next round of routing table gossip.
+    gossip_timer_token: Option<u64>,
+    /// The metrics of our routing table's convergence, if tracked.
+    convergence: Option<ConvergenceTracker>,
+    /// The timer token for the next snapshot of the convergence metrics.
+    convergence_timer_token: Option<u64>,
+    /// The store we periodically save a snapshot of our state to, if set.
+    state_persistence: Option<StatePersistence>,
+    /// The timer token for the next snapshot of our state.
+    state_save_timer_token: Option<u64>,
+    /// Our section's reports of losing its members, if departure consensus is enabled.
+    departures: Option<DepartureConsensus>,
+    /// The log of routing decisions taken for each message, if enabled.
+    decision_log: Option<DecisionLog>,
+    /// Our outstanding diagnostic pings, and the rate limits of the ones we receive.
+    pings: Pings,
+    /// Timing of the phases of handling received messages.
+    processing_stats: ProcessingStats,
+    /// The routing peers we lost recently, which may reconnect to us after losing all their
+    /// routing connections.
+    recent_peers: RecentPeers,
+    /// The state of rejoining our section after losing all routing connections, if we are.
+    recovery: Option<Recovery>,
+    /// The timer token for the next attempt to rejoin our section.
+    recovery_timer_token: Option<u64>,
+    /// Our settings, including the live ones changed since we started.
+    config: EffectiveConfig,
+    /// The wire format versions we accept, and the ones our peers use.
+    wire_versions: WireVersions,
+    /// The most recent errors from handling received messages, for tests to inspect.
+    #[cfg(feature = "use-mock-crust")]
+    message_errors: VecDeque<RoutingError>,
+    /// The source of the nonces and message IDs we generate, seeded via `NodeBuilder::rng_seed`
+    /// so that replaying our inputs reproduces them.
+    #[cfg(feature = "use-mock-crust")]
+    rng: SeededRng,
 }
 
 impl Node {
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     pub fn first(action_sender: RoutingActionSender,
                  cache: Box<Cache>,
+                 forwarding_sink: Option<Box<ForwardingSink>>,
+                 state_persistence: Option<StatePersistence>,
                  crust_service: Service,
                  full_id: FullId,
                  min_section_size: usize,
-                 timer: Timer)
+                 timer: Timer,
+                 tunables: Tunables)
                  -> Option<Self> {
         // old_id is useless for first node
         let old_id = FullId::new();
         let mut node = Self::new(action_sender,
                                  cache,
+                                 forwarding_sink,
+                                 state_persistence,
                                  crust_service,
                                  true,
                                  old_id,
@@ -139,6 +271,7 @@ impl Node {
                                  min_section_size,
                                  Stats::new(),
                                  timer,
+                                 tunables,
                                  0);
         if let Err(error) = node.crust_service.start_listening_tcp() {
             error!("{:?} Failed to start listening: {:?}", node, error);
@@ -154,16 +287,21 @@ impl Node {
     pub fn from_bootstrapping(our_section: (Prefix<XorName>, BTreeSet<PublicId>),
                               action_sender: RoutingActionSender,
                               cache: Box<Cache>,
+                              forwarding_sink: Option<Box<ForwardingSink>>,
+                              state_persistence: Option<StatePersistence>,
                               crust_service: Service,
                               old_full_id: FullId,
                               new_full_id: FullId,
                               min_section_size: usize,
                               proxy_pub_id: PublicId,
                               stats: Stats,
-                              timer: Timer)
+                              timer: Timer,
+                              tunables: Tunables)
                               -> Self {
         let mut node = Self::new(action_sender,
                                  cache,
+                                 forwarding_sink,
+                                 state_persistence,
                                  crust_service,
                                  false,
                                  old_full_id,
@@ -171,8 +309,10 @@ impl Node {
                                  min_section_size,
                                  stats,
                                  timer,
+                                 tunables,
                                  our_section.1.len());
         node.joining_prefix = our_section.0;
+        node.joining_section_size = our_section.1.len();
         node.peer_mgr
             .insert_peer(Peer::new(proxy_pub_id,
                                    PeerState::Proxy,
@@ -185,6 +325,8 @@ impl Node {
     #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     fn new(action_sender: RoutingActionSender,
            cache: Box<Cache>,
+           forwarding_sink: Option<Box<ForwardingSink>>,
+           state_persistence: Option<StatePersistence>,
            crust_service: Service,
            first_node: bool,
            old_full_id: FullId,
@@ -192,12 +334,41 @@ impl Node {
            min_section_size: usize,
            stats: Stats,
            timer: Timer,
+           tunables: Tunables,
            challenger_count: usize)
            -> Self {
         let public_id = *new_full_id.public_id();
         let tick_period = Duration::from_secs(TICK_TIMEOUT_SECS);
         let tick_timer_token = timer.schedule(tick_period);
         let user_msg_cache_duration = Duration::from_secs(USER_MSG_CACHE_EXPIRY_DURATION_SECS);
+        let cancellation_window = Duration::from_secs(REQUEST_CANCELLATION_WINDOW_SECS);
+        let audit = tunables
+            .audit_interval
+            .map(|interval| ConnectionAudit::new(interval, tunables.audit_grace));
+        let audit_timer_token = audit.as_ref().map(|audit| timer.schedule(audit.interval()));
+        let gossip = tunables
+            .gossip_interval
+            .map(|interval| {
+                     TableGossip::new(interval,
+                                      tunables.gossip_fanout,
+                                      tunables.gossip_sample_size)
+                 });
+        let gossip_timer_token = gossip
+            .as_ref()
+            .map(|gossip| timer.schedule(gossip.interval()));
+        let convergence = tunables
+            .convergence_interval
+            .map(|interval| {
+                     ConvergenceTracker::new(interval,
+                                             tunables.convergence_stable_snapshots,
+                                             tunables.convergence_min_table_size)
+                 });
+        let convergence_timer_token = convergence
+            .as_ref()
+            .map(|convergence| timer.schedule(convergence.interval()));
+        let state_save_timer_token = state_persistence
+            .as_ref()
+            .map(|state_persistence| timer.schedule(state_persistence.interval()));
         Node {
             ack_mgr: AckManager::new(),
             cacheable_user_msg_cache:
@@ -208,9 +379,9 @@ impl Node {
             is_first_node: first_node,
             is_approved: first_node,
             msg_queue: VecDeque::new(),
-            peer_mgr: PeerManager::new(min_section_size, public_id),
+            peer_mgr: PeerManager::new(min_section_size, public_id, &tunables),
             response_cache: cache,
-            routing_msg_filter: RoutingMessageFilter::new(),
+            routing_msg_filter: RoutingMessageFilter::with_tunables(&tunables),
             sig_accumulator: Default::default(),
             section_list_sigs: SectionListCache::new(),
             stats: stats,
@@ -226,10 +397,75 @@ impl Node {
             merge_cache: LruCache::with_expiry_duration(Duration::from_secs(MERGE_TIMEOUT_SECS)),
             candidate_timer_token: None,
             candidate_status_token: None,
-            bootstrappers:
-                LruCache::with_expiry_duration(Duration::from_secs(BOOTSTRAPPER_HOLD_DUR_SECS)),
+            bootstrappers: ExpiringCache::with_capacity("connection_cache",
+                                                        tunables.bootstrapper_cache_duration,
+                                                        tunables.bootstrapper_cache_capacity),
+            identify_nonces: ExpiringCache::with_capacity("identify_nonces",
+                                                          tunables.bootstrapper_cache_duration,
+                                                          tunables.bootstrapper_cache_capacity),
+            delivered_requests: ExpiringCache::with_capacity("delivered_requests",
+                                                             cancellation_window,
+                                                             MAX_CANCELLABLE_REQUESTS),
+            cancelled_requests: ExpiringCache::with_capacity("cancelled_requests",
+                                                             cancellation_window,
+                                                             MAX_CANCELLABLE_REQUESTS),
+            sent_requests: ExpiringCache::with_capacity("sent_requests",
+                                                        cancellation_window,
+                                                        MAX_CANCELLABLE_REQUESTS),
+            recent_sends: ExpiringCache::with_capacity("recent_sends",
+                                                       tunables.message_id_retry_window,
+                                                       tunables.message_id_retry_capacity),
+            captured_sends: None,
+            relocation_cache:
+                LruCache::with_expiry_duration_and_capacity(tunables.relocation_cache_duration,
+                                                            tunables.relocation_cache_capacity),
+            relocate_responses:
+                LruCache::with_expiry_duration_and_capacity(tunables.relocation_cache_duration,
+                                                            tunables.relocation_cache_capacity),
+            client_relays:
+                LruCache::with_expiry_duration(Duration::from_secs(CLIENT_RELAY_EXPIRY_SECS)),
             resource_prover: ResourceProver::new(action_sender, timer, challenger_count),
             joining_prefix: Default::default(),
+            joining_section_size: 0,
+            forwarding_sink: forwarding_sink,
+            health: Health::Critical,
+            connects_in_flight: BTreeSet::new(),
+            queued_connects: VecDeque::new(),
+            connect_spacing_token: None,
+            churn_generation: 0,
+            peer_generations:
+                PeerGenerations::new(Duration::from_secs(PEER_GENERATION_EXPIRY_SECS),
+                                     tunables.churn_generation_slack),
+            audit: audit,
+            audit_timer_token: audit_timer_token,
+            ban_timer_tokens: BTreeMap::new(),
+            gossip: gossip,
+            gossip_timer_token: gossip_timer_token,
+            convergence: convergence,
+            convergence_timer_token: convergence_timer_token,
+            state_persistence: state_persistence,
+            state_save_timer_token: state_save_timer_token,
+            departures: if tunables.departure_consensus {
+                Some(DepartureConsensus::new())
+            } else {
+                None
+            },
+            decision_log: tunables.decision_log_capacity.map(DecisionLog::new),
+            pings: Pings::new(),
+            wire_versions: WireVersions::new(tunables.oldest_wire_version,
+                                             tunables.newest_wire_version),
+            processing_stats: ProcessingStats::new(tunables.slow_message_threshold),
+            recent_peers: RecentPeers::new(RECENT_PEERS_CAPACITY,
+                                           Duration::from_secs(RECENT_PEER_EXPIRY_SECS)),
+            recovery: None,
+            recovery_timer_token: None,
+            config: EffectiveConfig::new(min_section_size, &tunables),
+            #[cfg(feature = "use-mock-crust")]
+            message_errors: VecDeque::new(),
+            #[cfg(feature = "use-mock-crust")]
+            rng: tunables
+                .rng_seed
+                .map_or_else(SeededRng::thread_rng, SeededRng::from_seed),
         }
     }
 
@@ -326,7 +562,9 @@ impl Node {
 
     pub fn handle_action(&mut self, action: Action, outbox: &mut EventBox) -> Transition {
         match action {
-            Action::ClientSendRequest { result_tx, .. } => {
+            Action::ClientSendRequest { result_tx, .. } |
+            Action::CancelRequest { result_tx, .. } |
+            Action::SetProxyStrategy { result_tx, .. } => {
                 let _ = result_tx.send(Err(InterfaceError::InvalidState));
             }
             Action::NodeSendMessage {
@@ -334,18 +572,176 @@ impl Node {
                 dst,
                 content,
                 priority,
+                retry_safe,
                 result_tx,
             } => {
-                let result = match self.send_user_message(src, dst, content, priority) {
+                let msg_id = content.message_id();
+                let sent = if retry_safe {
+                    self.send_retry_safe_message(src, dst, content, priority)
+                        .map(Some)
+                } else {
+                    self.send_user_message(src, dst, content, priority)
+                        .map(|()| None)
+                };
+                let result = match sent {
                     Err(RoutingError::Interface(err)) => Err(err),
-                    Err(_) | Ok(()) => Ok(()),
+                    Err(RoutingError::Crypto(err)) => {
+                        warn!("{:?} Dropping message to {:?}: {:?}", self, dst, err);
+                        outbox.send_event(Event::SigningFailed { src: src, dst: dst });
+                        Ok(())
+                    }
+                    Ok(Some(resent)) => {
+                        outbox.send_event(Event::MessageSent {
+                                              msg_id: msg_id,
+                                              dst: dst,
+                                              resent: resent,
+                                          });
+                        Ok(())
+                    }
+                    Err(_) | Ok(None) => Ok(()),
                 };
 
                 let _ = result_tx.send(result);
             }
+            Action::NodeSendBatch {
+                src,
+                messages,
+                batch_id,
+                result_tx,
+            } => {
+                let (succeeded, failed) = self.send_user_message_batch(src, messages);
+                outbox.send_event(Event::BatchSent {
+                                      batch_id: batch_id,
+                                      succeeded: succeeded,
+                                      failed: failed,
+                                  });
+                let _ = result_tx.send(Ok(()));
+            }
             Action::Id { result_tx } => {
                 let _ = result_tx.send(*self.id());
             }
+            Action::GetStats { result_tx } => {
+                let scores = self.peer_mgr.score_snapshot();
+                let (pooled_payloads, pooled_payload_bytes) = self.ack_mgr.pooled_payloads();
+                let mut caches = BTreeMap::new();
+                self.bootstrappers.report(&mut caches);
+                self.identify_nonces.report(&mut caches);
+                self.delivered_requests.report(&mut caches);
+                self.cancelled_requests.report(&mut caches);
+                self.sent_requests.report(&mut caches);
+                self.recent_sends.report(&mut caches);
+                self.peer_mgr.report_caches(&mut caches);
+                if let Some(ref mut departures) = self.departures {
+                    departures.report(&mut caches);
+                }
+                let _ = result_tx.send(Diagnostics {
+                                           connection_cache_len: self.bootstrappers.len(),
+                                           protocol_violations: scores.protocol_violations,
+                                           malformed_msgs: scores.malformed_msgs,
+                                           scored_peers: scores.scored_peers,
+                                           banned_peers: self.peer_mgr.banned_peer_count(),
+                                           connects_in_flight: self.connect_count(),
+                                           queued_connects: self.queued_connects.len(),
+                                           self_skips: self.peer_mgr.self_skips(),
+                                           unknown_sender_msgs: self.stats.unknown_sender_msgs(),
+                                           hop_limit_drops: self.stats.hop_limit_drops(),
+                                           relocation_cache_hits: self.stats
+                                               .relocation_cache_hits(),
+                                           relocation_cache_misses: self.stats
+                                               .relocation_cache_misses(),
+                                           relocate_responses: self.stats.relocate_responses(),
+                                           pending_accumulations: self.sig_accumulator
+                                               .pending_count(),
+                                           stale_msgs: self.stats.stale_msgs(),
+                                           audit_repairs: self.stats.audit_repairs(),
+                                           startup_events_replayed: self.stats
+                                               .startup_events_replayed(),
+                                           startup_queue_drops: self.stats.startup_queue_drops(),
+                                           peer_wire_versions: self.wire_versions.peer_versions(),
+                                           relayed_clients: self.peer_mgr.client_num(),
+                                           unidentified_connections: self.peer_mgr
+                                               .unidentified_peers()
+                                               .len(),
+                                           far_contacts: self.far_contact_count(),
+                                           pings_answered: self.pings.answered(),
+                                           pings_throttled: self.pings.throttled(),
+                                           pooled_payloads: pooled_payloads,
+                                           pooled_payload_bytes: pooled_payload_bytes,
+                                           gossip_samples_sent: self.gossip
+                                               .as_ref()
+                                               .map_or(0, TableGossip::sent),
+                                           gossip_samples_received: self.gossip
+                                               .as_ref()
+                                               .map_or(0, TableGossip::received),
+                                           gossip_samples_throttled: self.gossip
+                                               .as_ref()
+                                               .map_or(0, TableGossip::throttled),
+                                           processing_histograms: self.processing_stats
+                                               .histograms(),
+                                           convergence: self.convergence
+                                               .as_ref()
+                                               .map_or_else(Default::default,
+                                                            ConvergenceTracker::metrics),
+                                           caches: caches,
+                                           ..self.routing_msg_filter.diagnostics()
+                                       });
+            }
+            Action::DisconnectPeer { name, result_tx } => {
+                let (result, keep_going) = match self.disconnect_peer_by_name(&name, outbox) {
+                    Some(keep_going) => (Ok(()), keep_going),
+                    None => (Err(InterfaceError::NotConnected), true),
+                };
+                let _ = result_tx.send(result);
+                if !keep_going {
+                    return Transition::Terminate;
+                }
+            }
+            Action::BanPeer {
+                name,
+                duration,
+                result_tx,
+            } => {
+                debug!("{:?} Banning {} for {:?}.", self, name, duration);
+                self.peer_mgr.ban_peer(name, duration);
+                let banned_routing_peer = if self.routing_table().has(&name) {
+                    self.peer_mgr.get_pub_id(&name).cloned()
+                } else {
+                    None
+                };
+                if let Some(pub_id) = banned_routing_peer {
+                    let token = self.timer.schedule(duration);
+                    let _ = self.ban_timer_tokens.insert(token, pub_id);
+                }
+                let keep_going = self.disconnect_peer_by_name(&name, outbox)
+                    .unwrap_or(true);
+                let _ = result_tx.send(Ok(()));
+                if !keep_going {
+                    return Transition::Terminate;
+                }
+            }
+            Action::SetConnectionQuotas { quotas, result_tx } => {
+                debug!("{:?} Setting connection quotas to {:?}.", self, quotas);
+                self.config.connection_quotas = quotas;
+                self.cull_unidentified_peers(outbox);
+                let _ = result_tx.send(Ok(()));
+            }
+            Action::GetEffectiveConfig { result_tx } => {
+                let _ = result_tx.send(Ok(self.config));
+            }
+            Action::UpdateConfig { update, result_tx } => {
+                self.update_config(update, outbox);
+                let _ = result_tx.send(Ok(()));
+            }
+            Action::DumpDecisionLog { result_tx } => {
+                let _ = result_tx.send(Ok(self.dump_decision_log()));
+            }
+            Action::PingPeer {
+                name,
+                reply_tx,
+                result_tx,
+            } => {
+                let _ = result_tx.send(self.ping_peer(&name, reply_tx));
+            }
             Action::Timeout(token) => {
                 if let Transition::Terminate = self.handle_timeout(token, outbox) {
                     return Transition::Terminate;
@@ -357,6 +753,12 @@ impl Node {
                 self.send_direct_message(pub_id, msg);
             }
             Action::Terminate => {
+                self.save_state();
+                debug!("{:?} Terminating; disconnecting from all peers.", self);
+                for pub_id in self.peer_mgr.peer_pub_ids() {
+                    let _ = self.crust_service.disconnect(pub_id);
+                }
+                outbox.send_event(Event::Terminate);
                 return Transition::Terminate;
             }
         }
@@ -372,23 +774,39 @@ impl Node {
                               -> Transition {
         match crust_event {
             CrustEvent::BootstrapAccept(pub_id, peer_kind) => {
-                self.handle_bootstrap_accept(pub_id, peer_kind)
+                self.audit_live_connection(pub_id);
+                self.handle_bootstrap_accept(pub_id, peer_kind, outbox)
             }
             CrustEvent::BootstrapConnect(pub_id, _) => {
                 self.handle_bootstrap_connect(pub_id, outbox)
             }
-            CrustEvent::ConnectSuccess(pub_id) => self.handle_connect_success(pub_id, outbox),
-            CrustEvent::ConnectFailure(pub_id) => self.handle_connect_failure(pub_id),
+            CrustEvent::ConnectSuccess(pub_id) => {
+                self.audit_live_connection(pub_id);
+                self.handle_connect_success(pub_id, outbox);
+                self.send_queued_connects(outbox);
+            }
+            CrustEvent::ConnectFailure(pub_id) => {
+                self.handle_connect_failure(pub_id);
+                self.send_queued_connects(outbox);
+            }
             CrustEvent::LostPeer(pub_id) => {
+                if let Some(ref mut audit) = self.audit {
+                    audit.disconnected(&pub_id);
+                }
                 if let Transition::Terminate = self.handle_lost_peer(pub_id, outbox) {
                     return Transition::Terminate;
                 }
             }
             CrustEvent::NewMessage(pub_id, bytes) => {
+                self.audit_live_connection(pub_id);
+                self.processing_stats.start_message();
                 match self.handle_new_message(pub_id, bytes, outbox) {
                     Err(RoutingError::FilterCheckFailed) |
                     Ok(_) => (),
-                    Err(err) => debug!("{:?} - {:?}", self, err),
+                    Err(err) => {
+                        debug!("{:?} - {:?}", self, err);
+                        self.record_message_error(err);
+                    }
                 }
             }
             CrustEvent::ConnectionInfoPrepared(ConnectionInfoResult {
@@ -419,6 +837,7 @@ impl Node {
         }
 
         self.handle_routing_messages(outbox);
+        self.finish_message_timing(outbox);
         self.update_stats();
         Transition::Stay
     }
@@ -431,37 +850,105 @@ impl Node {
     fn handle_routing_messages(&mut self, outbox: &mut EventBox) {
         while let Some(routing_msg) = self.msg_queue.pop_front() {
             if self.in_authority(&routing_msg.dst) {
-                if let Err(err) = self.dispatch_routing_message(routing_msg, outbox) {
+                let timer = PhaseTimer::start(ProcessingPhase::Dispatch);
+                let result = self.dispatch_routing_message(routing_msg, outbox);
+                self.processing_stats.record(timer);
+                if let Err(err) = result {
                     debug!("{:?} Routing message dispatch failed: {:?}", self, err);
+                    self.record_message_error(err);
                 }
             }
         }
     }
 
-    fn handle_bootstrap_accept(&mut self, pub_id: PublicId, peer_kind: CrustUser) {
+    #[cfg(feature = "use-mock-crust")]
+    fn record_message_error(&mut self, err: RoutingError) {
+        if self.message_errors.len() == MAX_RECORDED_MESSAGE_ERRORS {
+            let _ = self.message_errors.pop_front();
+        }
+        self.message_errors.push_back(err);
+    }
+
+    #[cfg(not(feature = "use-mock-crust"))]
+    fn record_message_error(&mut self, _err: RoutingError) {}
+
+    // Adds the timing of the message received last to the processing histograms, and reports it
+    // if it was slow.
+    fn finish_message_timing(&mut self, outbox: &mut EventBox) {
+        if let Some(report) = self.processing_stats.finish_message() {
+            warn!("{:?} Slow message handling: {:?}", self, report);
+            outbox.send_event(Event::SlowMessage(report));
+        }
+    }
+
+    fn handle_bootstrap_accept(&mut self,
+                               pub_id: PublicId,
+                               peer_kind: CrustUser,
+                               outbox: &mut EventBox) {
         trace!("{:?} Received BootstrapAccept from {:?} as {:?}.",
                self,
                pub_id,
                peer_kind);
+        if self.peer_mgr.is_banned(pub_id.name()) {
+            debug!("{:?} Refusing bootstrap connection from banned peer {}.",
+                   self,
+                   pub_id);
+            let _ = self.crust_service.disconnect(pub_id);
+            return;
+        }
+        // A peer we still hold an entry for replaces it, so doesn't count against the quota.
+        if self.peer_mgr.get_peer(&pub_id).is_none() &&
+           self.config.connection_quotas
+               .unidentified
+               .map_or(false, |max| self.peer_mgr.unidentified_peers().len() >= max) {
+            debug!("{:?} Refusing bootstrap connection from {}: Unidentified quota reached.",
+                   self,
+                   pub_id);
+            outbox.send_event(Event::PeerRefused(*pub_id.name(), RefusalReason::UnidentifiedQuota));
+            let _ = self.crust_service.disconnect(pub_id);
+            return;
+        }
         if let Some(peer) = self.bootstrappers.insert(pub_id, peer_kind) {
             trace!("{:?} Replacing Bootstrapper {:?} who was previously registered as {:?}",
                    self,
                    pub_id,
                    peer);
         }
-        self.peer_mgr
-            .insert_peer(Peer::new(pub_id,
-                                   PeerState::Connected(false),
-                                   false,
-                                   ReconnectingPeer::False));
+        if self.peer_mgr.is_routing_peer(&pub_id) {
+            // The new connection only replaces the routing table entry once it has proven to
+            // belong to the same peer by answering the challenge.
+            debug!("{:?} {} reconnected while in our routing table. Challenging it to identify.",
+                   self,
+                   pub_id);
+        } else {
+            self.peer_mgr
+                .insert_peer(Peer::new(pub_id,
+                                       PeerState::Connected(false),
+                                       false,
+                                       ReconnectingPeer::False));
+        }
+        // This is a new connection, so any earlier challenge is void.
+        let _ = self.identify_nonces.remove(&pub_id);
+        self.send_identify_challenge(pub_id);
     }
 
     fn handle_bootstrap_connect(&mut self, pub_id: PublicId, outbox: &mut EventBox) {
+        if self.recovery.is_some() && self.recovery_contact().is_none() {
+            debug!("{:?} Rejoining our section via bootstrap contact {}.",
+                   self,
+                   pub_id);
+            self.set_recovery_contact(Some((pub_id, false)));
+            return;
+        }
         // A mature node doesn't need a bootstrap connection
         self.disconnect_peer(&pub_id, Some(outbox))
     }
 
     fn handle_connect_success(&mut self, pub_id: PublicId, outbox: &mut EventBox) {
+        if self.peer_mgr.skip_own_id(&pub_id) {
+            return;
+        }
+
         if !self.crust_service.is_peer_whitelisted(&pub_id) {
             debug!("{:?} Received ConnectSuccess, but {:?} is not whitelisted.",
                    self,
@@ -470,6 +957,14 @@ impl Node {
             return;
         }
 
+        if self.peer_mgr.is_banned(pub_id.name()) {
+            debug!("{:?} Received ConnectSuccess, but {:?} is banned.",
+                   self,
+                   pub_id);
+            self.disconnect_peer(&pub_id, Some(outbox));
+            return;
+        }
+
         // Remove tunnel connection if we have one for this peer already
         if let Some(tunnel_id) = self.tunnels.remove_tunnel_for(&pub_id) {
             debug!("{:?} Removing unwanted tunnel for {:?}", self, pub_id);
@@ -483,6 +978,17 @@ impl Node {
             return;
         }
 
+        if let Some(reason) = self.ip_limit_refusal(&pub_id)
+               .or_else(|| self.far_contact_refusal(&pub_id)) {
+            debug!("{:?} Received ConnectSuccess, but refusing {:?}: {:?}.",
+                   self,
+                   pub_id,
+                   reason);
+            outbox.send_event(Event::PeerRefused(*pub_id.name(), reason));
+            self.disconnect_peer(&pub_id, Some(outbox));
+            return;
+        }
+
         self.peer_mgr.connected_to(&pub_id);
 
         let id_type = if self.is_approved {
@@ -497,6 +1003,290 @@ impl Node {
         self.process_connection(pub_id, outbox);
     }
 
+    /// Recomputes our health from the number of members of our section we are connected to, and
+    /// raises `Event::HealthChanged` if it changed.
+    fn update_health(&mut self, outbox: &mut EventBox) {
+        if !self.config.health_events || !self.is_approved {
+            return;
+        }
+        let connected = self.routing_table().our_section().len();
+        let expected = self.min_section_size();
+        let health = if connected >= expected {
+            Health::Healthy
+        } else if connected * QUORUM_DENOMINATOR > expected * QUORUM_NUMERATOR {
+            Health::Degraded
+        } else {
+            Health::Critical
+        };
+        if health != self.health {
+            debug!("{:?} Health changed from {:?} to {:?}.", self, self.health, health);
+            self.health = health;
+            outbox.send_event(Event::HealthChanged {
+                                  health: health,
+                                  connected: connected,
+                                  expected: expected,
+                              });
+        }
+    }
+
+    /// Updates the metrics of our routing table's convergence after a change to it, if tracked.
+    fn update_convergence(&mut self) {
+        if let Some(ref mut convergence) = self.convergence {
+            convergence.update(self.peer_mgr.routing_table());
+        }
+    }
+
+    /// Takes a snapshot of the convergence metrics, raises `Event::TableConverged` if the table
+    /// has now converged, and schedules the next snapshot.
+    fn take_convergence_snapshot(&mut self, outbox: &mut EventBox) {
+        // Catch any changes to the table which weren't tracked as they happened.
+        self.update_convergence();
+        let converged = match self.convergence {
+            Some(ref mut convergence) => {
+                self.convergence_timer_token = Some(self.timer.schedule(convergence.interval()));
+                if convergence.take_snapshot() {
+                    Some(convergence.metrics().current)
+                } else {
+                    None
+                }
+            }
+            None => None,
+        };
+        if let Some(snapshot) = converged {
+            debug!("{:?} Routing table converged: {:?}", self, snapshot);
+            outbox.send_event(Event::TableConverged(snapshot));
+        }
+    }
+
+    /// Saves a snapshot of our state to the store set via `NodeBuilder::state_persistence`, once
+    /// we have joined.
+    fn save_state(&mut self) {
+        if self.state_persistence.is_none() || !self.is_approved {
+            return;
+        }
+        let names = self.routing_table().iter().cloned().collect();
+        let connected = self.peer_mgr
+            .get_pub_ids(&names)
+            .into_iter()
+            .map(|pub_id| (pub_id, self.crust_service.get_peer_socket_addr(&pub_id).ok()))
+            .collect();
+        let result = match self.state_persistence {
+            Some(ref mut state_persistence) => state_persistence.save(&self.full_id, connected),
+            None => Ok(()),
+        };
+        if let Err(error) = result {
+            warn!("{:?} Failed to save our state: {:?}", self, error);
+        }
+    }
+
+    /// Raises `Event::WireUpgraded` if all the other members of our section have just been seen
+    /// using the newest wire format version we accept.
+    fn check_wire_upgrade(&mut self, outbox: &mut EventBox) {
+        let upgraded = {
+            let our_name = *self.name();
+            let others = self.peer_mgr
+                .routing_table()
+                .our_section()
+                .iter()
+                .filter(|name| **name != our_name);
+            self.wire_versions.check_upgraded(others)
+        };
+        if let Some(version) = upgraded {
+            info!("{:?} Our section has upgraded to wire version {}.", self, version);
+            outbox.send_event(Event::WireUpgraded(version));
+        }
+    }
+
+    /// Returns the reason to refuse a direct connection to `pub_id` if that would exceed the limits
+    /// on routing table entries sharing an IP address or subnet.
+    fn ip_limit_refusal(&self, pub_id: &PublicId) -> Option<RefusalReason> {
+        if self.config.max_peers_per_ip.is_none() && self.config.max_peers_per_subnet.is_none() {
+            return None;
+        }
+        let ip = match self.crust_service.get_peer_ip_addr(pub_id) {
+            Ok(ip) => ip,
+            Err(_) => return None,
+        };
+        let names = self.routing_table()
+            .iter()
+            .filter(|name| *name != pub_id.name())
+            .cloned()
+            .collect();
+        let peer_ips: Vec<IpAddr> = self.peer_mgr
+            .get_pub_ids(&names)
+            .iter()
+            .filter_map(|peer_id| self.crust_service.get_peer_ip_addr(peer_id).ok())
+            .collect();
+        if let Some(max) = self.config.max_peers_per_ip {
+            if peer_ips.iter().filter(|peer_ip| **peer_ip == ip).count() >= max {
+                return Some(RefusalReason::IpLimit(ip));
+            }
+        }
+        if let Some(max) = self.config.max_peers_per_subnet {
+            if peer_ips
+                   .iter()
+                   .filter(|peer_ip| utils::same_subnet(peer_ip, &ip))
+                   .count() >= max {
+                return Some(RefusalReason::SubnetLimit(ip));
+            }
+        }
+        None
+    }
+
+    /// Returns the reason to refuse a direct connection to `pub_id` if it would add a routing table
+    /// entry outside our section beyond the far contacts quota.
+    fn far_contact_refusal(&self, pub_id: &PublicId) -> Option<RefusalReason> {
+        let max = match self.config.connection_quotas.far_contacts {
+            Some(max) => max,
+            None => return None,
+        };
+        if !self.is_approved || self.our_prefix().matches(pub_id.name()) ||
+           self.far_contact_count() < max {
+            return None;
+        }
+        Some(RefusalReason::FarContactQuota)
+    }
+
+    /// Returns the number of routing table entries outside our section.
+    fn far_contact_count(&self) -> usize {
+        let our_prefix = self.our_prefix();
+        self.routing_table()
+            .iter()
+            .filter(|name| !our_prefix.matches(name))
+            .count()
+    }
+
+    /// Disconnects from the oldest unidentified peers until their number is within the quota.
+    fn cull_unidentified_peers(&mut self, outbox: &mut EventBox) {
+        let max = match self.config.connection_quotas.unidentified {
+            Some(max) => max,
+            None => return,
+        };
+        let unidentified = self.peer_mgr.unidentified_peers();
+        let excess = unidentified.len().saturating_sub(max);
+        for pub_id in unidentified.into_iter().take(excess) {
+            debug!("{:?} Dropping unidentified peer {}: Unidentified quota reached.",
+                   self,
+                   pub_id);
+            self.disconnect_peer(&pub_id, Some(outbox));
+        }
+    }
+
+    /// Applies the valid live settings of `update` at once, and raises `Event::ConfigRefused` for
+    /// the invalid ones and all startup settings, followed by `Event::ConfigUpdated`.
+    fn update_config(&mut self, update: PartialConfig, outbox: &mut EventBox) {
+        debug!("{:?} Updating config: {:?}.", self, update);
+        let mut refused: Vec<RefusedSetting> = update
+            .startup
+            .given_fields()
+            .into_iter()
+            .map(|field| {
+                     RefusedSetting {
+                         field: field,
+                         reason: ConfigRefusal::NotLive,
+                     }
+                 })
+            .collect();
+        let live = self.validate_live_config(update.live, &mut refused);
+        if !refused.is_empty() {
+            debug!("{:?} Refused settings: {:?}.", self, refused);
+            outbox.send_event(Event::ConfigRefused(refused));
+        }
+
+        if let Some(quotas) = live.connection_quotas {
+            self.config.connection_quotas = quotas;
+        }
+        if let Some(group_fanout) = live.group_fanout {
+            self.config.group_fanout = group_fanout;
+        }
+        if let Some(max_connects_in_flight) = live.max_connects_in_flight {
+            self.config.max_connects_in_flight = max_connects_in_flight;
+        }
+        if let Some(connect_spacing) = live.connect_spacing {
+            self.config.connect_spacing = connect_spacing;
+        }
+        if let Some(max_peers_per_ip) = live.max_peers_per_ip {
+            self.config.max_peers_per_ip = max_peers_per_ip;
+        }
+        if let Some(max_peers_per_subnet) = live.max_peers_per_subnet {
+            self.config.max_peers_per_subnet = max_peers_per_subnet;
+        }
+        if let Some(join_progress_events) = live.join_progress_events {
+            self.config.join_progress_events = join_progress_events;
+        }
+        if let Some(health_events) = live.health_events {
+            self.config.health_events = health_events;
+        }
+        if let Some(decisions_only) = live.decisions_only {
+            self.config.decisions_only = decisions_only;
+        }
+
+        if live.connection_quotas.is_some() {
+            self.cull_unidentified_peers(outbox);
+        }
+        if live.max_connects_in_flight.is_some() {
+            self.send_queued_connects(outbox);
+        }
+        outbox.send_event(Event::ConfigUpdated(self.config));
+    }
+
+    /// Returns `live` without the settings which are out of range or conflict with the others or
+    /// with our current settings, and adds those to `refused`.
+    fn validate_live_config(&self,
+                            mut live: LiveConfig,
+                            refused: &mut Vec<RefusedSetting>)
+                            -> LiveConfig {
+        let mut refuse = |field, reason: String| {
+            refused.push(RefusedSetting {
+                             field: field,
+                             reason: ConfigRefusal::Invalid(reason),
+                         });
+        };
+
+        let quorum = group_quorum(self.min_section_size());
+        if let Some(group_fanout) = live.group_fanout {
+            if group_fanout < quorum {
+                refuse("group_fanout",
+                       format!("{} is less than a quorum of {}.", group_fanout, quorum));
+                live.group_fanout = None;
+            }
+        }
+        if let Some(Some(0)) = live.max_connects_in_flight {
+            refuse("max_connects_in_flight", "must be at least 1.".to_owned());
+            live.max_connects_in_flight = None;
+        }
+        if let Some(Some(0)) = live.max_peers_per_ip {
+            refuse("max_peers_per_ip", "must be at least 1.".to_owned());
+            live.max_peers_per_ip = None;
+        }
+        if let Some(Some(0)) = live.max_peers_per_subnet {
+            refuse("max_peers_per_subnet", "must be at least 1.".to_owned());
+            live.max_peers_per_subnet = None;
+        }
+
+        // Each IP address is in one subnet, so the limit per IP address can't exceed the one per
+        // subnet. If they conflict, whichever of them is being changed is refused.
+        let per_ip = live.max_peers_per_ip.unwrap_or(self.config.max_peers_per_ip);
+        let per_subnet = live.max_peers_per_subnet.unwrap_or(self.config.max_peers_per_subnet);
+        if let (Some(per_ip), Some(per_subnet)) = (per_ip, per_subnet) {
+            if per_ip > per_subnet {
+                let reason = format!("the limit of {} per IP address exceeds the limit of {} per \
+                                      subnet.",
+                                     per_ip,
+                                     per_subnet);
+                if live.max_peers_per_ip.is_some() {
+                    refuse("max_peers_per_ip", reason.clone());
+                    live.max_peers_per_ip = None;
+                }
+                if live.max_peers_per_subnet.is_some() {
+                    refuse("max_peers_per_subnet", reason);
+                    live.max_peers_per_subnet = None;
+                }
+            }
+        }
+        live
+    }
+
     fn handle_connect_failure(&mut self, pub_id: PublicId) {
         if let Some(&PeerState::CrustConnecting) =
             self.peer_mgr.get_peer(&pub_id).map(Peer::state) {
@@ -528,8 +1318,39 @@ impl Node {
                           bytes: Vec<u8>,
                           outbox: &mut EventBox)
                           -> Result<(), RoutingError> {
-        match serialisation::deserialise(&bytes) {
-            Ok(Message::Hop(hop_msg)) => self.handle_hop_message(hop_msg, pub_id),
+        if self.peer_mgr.is_banned(pub_id.name()) {
+            debug!("{:?} Dropping message from banned peer {}.", self, pub_id);
+            let _ = self.crust_service.disconnect(pub_id);
+            return Ok(());
+        }
+
+        let bytes = match self.wire_versions.decode(pub_id.name(), bytes) {
+            Ok(bytes) => bytes,
+            Err(version) => {
+                debug!("{:?} Dropping message from {} in unaccepted wire version {}.",
+                       self,
+                       pub_id,
+                       version);
+                return Ok(());
+            }
+        };
+        self.check_wire_upgrade(outbox);
+
+        let timer = PhaseTimer::start(ProcessingPhase::Decode);
+        let message = serialisation::deserialise(&bytes);
+        self.processing_stats.record(timer);
+        match message {
+            Ok(Message::Ping(nonce)) => {
+                self.handle_ping(pub_id, nonce);
+                Ok(())
+            }
+            Ok(Message::Pong(nonce)) => {
+                if !self.pings.handle_pong(&pub_id, nonce) {
+                    debug!("{:?} Unexpected pong from {}.", self, pub_id);
+                }
+                Ok(())
+            }
+            Ok(Message::Hop(hop_msg)) => self.handle_hop_message(hop_msg, pub_id, outbox),
             Ok(Message::Direct(direct_msg)) => {
                 self.handle_direct_message(direct_msg, pub_id, outbox)
             }
@@ -567,7 +1388,7 @@ impl Node {
             }
             Ok(Message::TunnelHop { content, src, dst }) => {
                 if dst == *self.full_id.public_id() {
-                    self.handle_hop_message(content, src)
+                    self.handle_hop_message(content, src, outbox)
                 } else if self.tunnels.has_clients(src, dst) {
                     self.send_or_drop(&dst, bytes, content.content.priority());
                     Ok(())
@@ -581,8 +1402,55 @@ impl Node {
                     Err(RoutingError::InvalidDestination)
                 }
             }
-            Err(error) => Err(RoutingError::SerialisationError(error)),
+            Err(error) => {
+                self.handle_malformed_message(pub_id, bytes.len(), outbox);
+                Err(RoutingError::SerialisationError(error))
+            }
+        }
+    }
+
+    // Answers a diagnostic ping straight away, unless its sender exceeded the rate limit.
+    fn handle_ping(&mut self, pub_id: PublicId, nonce: u64) {
+        if !self.pings.allow_ping(pub_id) {
+            trace!("{:?} Dropping ping from {}: rate limit exceeded.", self, pub_id);
+            return;
+        }
+        let pong = Message::Pong(nonce);
+        match serialisation::serialise(&pong) {
+            Ok(bytes) => self.send_or_drop(&pub_id, bytes, pong.priority()),
+            Err(error) => debug!("{:?} Failed to serialise {:?}: {:?}", self, pong, error),
+        }
+    }
+
+    // Sends a diagnostic ping to the peer with the given name, if we are directly connected to it.
+    fn ping_peer(&mut self,
+                 name: &XorName,
+                 reply_tx: Sender<Duration>)
+                 -> Result<(), InterfaceError> {
+        let pub_id = match self.peer_mgr.get_pub_id(name) {
+            Some(&pub_id) if self.crust_service.is_connected(&pub_id) => pub_id,
+            _ => return Err(InterfaceError::NotConnected),
+        };
+        let ping = Message::Ping(self.pings.start(pub_id, reply_tx));
+        match serialisation::serialise(&ping) {
+            Ok(bytes) => self.send_or_drop(&pub_id, bytes, ping.priority()),
+            Err(error) => debug!("{:?} Failed to serialise {:?}: {:?}", self, ping, error),
+        }
+        Ok(())
+    }
+
+    /// Reports a message from `pub_id` that couldn't be deserialised, and drops the connection to
+    /// them once they have sent too many.
+    fn handle_malformed_message(&mut self, pub_id: PublicId, len: usize, outbox: &mut EventBox) {
+        outbox.send_event(Event::MalformedMessage(pub_id, len));
+        if !self.peer_mgr.record_malformed_message(&pub_id) {
+            return;
         }
+        debug!("{:?} Disconnecting {} due to repeated malformed messages.",
+               self,
+               pub_id);
+        let _ = self.crust_service.disconnect(pub_id);
+        let _ = self.dropped_peer(&pub_id, outbox, false);
     }
 
     // Deconstruct a `DirectMessage` and handle or forward as appropriate.
@@ -592,6 +1460,15 @@ impl Node {
                              outbox: &mut EventBox)
                              -> Result<(), RoutingError> {
         use messages::DirectMessage::*;
+        let is_identify = match direct_message {
+            ClientIdentify { .. } |
+            CandidateIdentify { .. } => true,
+            _ => false,
+        };
+        if is_identify && self.peer_mgr.skip_own_id(&pub_id) {
+            return Ok(());
+        }
+
         match direct_message {
             MessageSignature(digest, sig) => self.handle_message_signature(digest, sig, pub_id)?,
             SectionListSignature(section_list, sig) => {
@@ -604,8 +1481,7 @@ impl Node {
             } => {
                 let drop = match self.bootstrappers.remove(&pub_id) {
                     Some(kind) => {
-                        if kind == CrustUser::Client && client_restriction ||
-                           kind == CrustUser::Node && !client_restriction {
+                        if identify_matches_bootstrap(kind, client_restriction) {
                             false
                         } else {
                             debug!("{:?} Peer bootstrapped to us as {:?} but is sending messages \
@@ -625,9 +1501,28 @@ impl Node {
                     return Ok(self.disconnect_peer(&pub_id, Some(outbox)));
                 }
 
-                match verify_signed_public_id(serialised_public_id, signature) {
+                let result = self.identify_nonces
+                    .remove(&pub_id)
+                    .ok_or(RoutingError::FailedSignature)
+                    .and_then(|nonce| {
+                                  verify_signed_public_id(serialised_public_id, nonce, signature)
+                              });
+                let is_routing_peer = self.peer_mgr.is_routing_peer(&pub_id);
+                match result {
                     Ok(signed_pub_id) if signed_pub_id == pub_id => {
-                        self.handle_client_identify(pub_id, client_restriction, outbox)
+                        if is_routing_peer {
+                            self.replace_routing_peer(pub_id);
+                        } else {
+                            self.handle_client_identify(pub_id, client_restriction, outbox)
+                        }
+                    }
+                    _ if is_routing_peer => {
+                        // Someone else claims to be our routing peer: only drop their connection.
+                        warn!("{:?} Invalid ClientIdentify received from a reconnection of \
+                               routing peer {}, dropping the connection.",
+                              self,
+                              pub_id);
+                        let _ = self.crust_service.disconnect(pub_id);
                     }
                     _ => {
                         warn!("{:?} Invalid ClientIdentify received, dropping {}.",
@@ -652,10 +1547,21 @@ impl Node {
                     self.disconnect_peer(&pub_id, Some(outbox));
                     return Err(RoutingError::InvalidSource);
                 }
+                let nonce = match self.identify_nonces.remove(&pub_id) {
+                    Some(nonce) => nonce,
+                    None => {
+                        warn!("{:?} Unsolicited CandidateIdentify received, dropping {}.",
+                              self,
+                              pub_id);
+                        self.disconnect_peer(&pub_id, Some(outbox));
+                        return Err(RoutingError::FailedSignature);
+                    }
+                };
                 self.handle_candidate_identify(old_public_id,
                                                &pub_id,
                                                signature_using_old,
                                                signature_using_new,
+                                               nonce,
                                                new_client_auth,
                                                outbox);
             }
@@ -690,10 +1596,13 @@ impl Node {
                                                     proof,
                                                     leading_zero_bytes);
             }
-            msg @ BootstrapIdentify { .. } |
-            msg @ BootstrapDeny => {
-                debug!("{:?} Unhandled direct message: {:?}", self, msg);
-            }
+            IdentifyChallenge(nonce) => self.handle_identify_challenge(pub_id, nonce),
+            TableSample(names) => self.handle_table_sample(pub_id, names),
+            PeerIdsRequest(names) => self.handle_peer_ids_request(pub_id, &names),
+            PeerIds(pub_ids) => self.handle_peer_ids(pub_id, pub_ids, outbox),
+            PeerLost(name) => self.handle_peer_lost(pub_id, name, outbox),
+            BootstrapIdentify => self.handle_rejoin_accepted(pub_id, outbox),
+            BootstrapDeny => self.handle_rejoin_denied(pub_id),
         }
         Ok(())
     }
@@ -717,7 +1626,7 @@ impl Node {
             self.sig_accumulator
                 .add_signature(min_section_size, digest, sig, pub_id) {
             let hop = *self.name(); // we accumulated the message, so now we act as the last hop
-            self.handle_signed_message(signed_msg, route, hop, &BTreeSet::new())?;
+            self.handle_signed_message(signed_msg, route, hop, &BTreeSet::new(), 0)?;
         }
         Ok(())
     }
@@ -772,7 +1681,16 @@ impl Node {
                     return;
                 }
             };
-            let sig = sign::sign_detached(&serialised, self.full_id.signing_private_key());
+            let sig = match self.full_id.signing_private_key().sign(&serialised) {
+                Ok(sig) => sig,
+                Err(err) => {
+                    warn!("{:?} Error signing section list for {:?}: {:?}",
+                          self,
+                          prefix,
+                          err);
+                    return;
+                }
+            };
 
             let section_len = self.routing_table().our_section().len();
             let our_id = *self.full_id.public_id();
@@ -816,7 +1734,7 @@ impl Node {
         }
 
         let serialised = serialisation::serialise(&section_list)?;
-        if sign::verify_detached(&sig, &serialised, pub_id.signing_public_key()) {
+        if pub_id.signing_public_key().verify(&sig, &serialised) {
             let section_len = self.routing_table().our_section().len();
             self.section_list_sigs
                 .add_signature(section_list.prefix, pub_id, section_list, sig, section_len);
@@ -828,10 +1746,14 @@ impl Node {
 
     fn handle_hop_message(&mut self,
                           hop_msg: HopMessage,
-                          pub_id: PublicId)
+                          pub_id: PublicId,
+                          outbox: &mut EventBox)
                           -> Result<(), RoutingError> {
         let hop_name = if let Some(peer) = self.peer_mgr.get_peer(&pub_id) {
-            hop_msg.verify(peer.pub_id().signing_public_key())?;
+            let timer = PhaseTimer::start(ProcessingPhase::Verify);
+            let verified = hop_msg.verify(peer.pub_id().signing_public_key());
+            self.processing_stats.record(timer);
+            verified?;
 
             match *peer.state() {
                 PeerState::Client => {
@@ -855,6 +1777,7 @@ impl Node {
             }
         } else {
             debug!("{:?} Can't find sender {} of {:?}", self, pub_id, hop_msg);
+            self.stats.count_unknown_sender();
             // FIXME - confirm we can return with an error here by running soak tests
             // // TODO - We could return `UnknownConnection` here and not handle the message, but we
             // //        could be handling a tunnelled message here immediately after the tunnel
@@ -864,13 +1787,33 @@ impl Node {
             // return Err(RoutingError::UnknownConnection);
         };
 
+        if self.peer_mgr.is_client(&pub_id) {
+            if let Err(error) = self.check_client_src(&pub_id, hop_msg.content.routing_message()) {
+                self.handle_protocol_violation(&pub_id, outbox);
+                return Err(error);
+            }
+        }
+
+        // Nobody else may ask to connect in our name.
+        if let MessageContent::ConnectionInfoRequest { pub_id: ref claimed_id, .. } =
+            hop_msg.content.routing_message().content {
+            if claimed_id.name() == self.name() {
+                debug!("{:?} {} sent a connection info request claiming our name.",
+                       self,
+                       pub_id);
+                self.handle_protocol_violation(&pub_id, outbox);
+                return Err(RoutingError::InvalidPeer);
+            }
+        }
+
         let HopMessage {
             content,
             route,
             sent_to,
+            hop_count,
             ..
         } = hop_msg;
-        self.handle_signed_message(content, route, hop_name, &sent_to)
+        self.handle_signed_message(content, route, hop_name, &sent_to, hop_count)
     }
 
     // Acknowledge reception of the message and broadcast to our section if necessary
@@ -879,25 +1822,48 @@ impl Node {
                          signed_msg: &SignedMessage,
                          route: u8,
                          hop_name: XorName,
-                         sent_to: &BTreeSet<XorName>) {
+                         sent_to: &BTreeSet<XorName>,
+                         hop_count: u8) {
+        let timer = PhaseTimer::start(ProcessingPhase::Send);
         self.send_ack(signed_msg.routing_message(), route);
         // If the destination is our section we need to forward it to the rest of the section
         if signed_msg.routing_message().dst.is_multiple() {
-            if let Err(error) = self.send_signed_message(signed_msg, route, &hop_name, sent_to) {
+            if let Err(error) =
+                self.send_signed_message(signed_msg, route, &hop_name, sent_to, hop_count) {
                 debug!("{:?} Failed to send {:?}: {:?}", self, signed_msg, error);
             }
         }
+        self.processing_stats.record(timer);
     }
 
     // Verify the message, then, if it is for us, handle the enclosed routing message; if not,
-    // forward it.
+    // forward it. `hop_count` is the number of times the message has been relayed to us.
     fn handle_signed_message(&mut self,
                              signed_msg: SignedMessage,
                              route: u8,
                              hop_name: XorName,
-                             sent_to: &BTreeSet<XorName>)
+                             sent_to: &BTreeSet<XorName>,
+                             hop_count: u8)
                              -> Result<(), RoutingError> {
-        signed_msg.check_integrity(self.min_section_size())?;
+        let next_hop_count = hop_count.saturating_add(1);
+        let msg_hash = self.decision_log
+            .as_ref()
+            .map(|_| decision_log::message_hash(signed_msg.routing_message()));
+        let dst = signed_msg.routing_message().dst;
+        self.log_decision(msg_hash, &hop_name, route, &dst, Decision::Received);
+
+        let timer = PhaseTimer::start(ProcessingPhase::Verify);
+        let integrity = signed_msg.check_integrity(self.min_section_size());
+        self.processing_stats.record(timer);
+        integrity?;
+
+        // A client message must be signed by the client it claims to come from.
+        if let Authority::Client { ref client_id, .. } = signed_msg.routing_message().src {
+            if !signed_msg.signed_by(client_id) {
+                debug!("{:?} Message not signed by its source client: {:?}", self, signed_msg);
+                return Err(RoutingError::FailedSignature);
+            }
+        }
 
         // TODO(MAID-1677): Remove this once messages are fully validated.
         // Expect group/section messages to be sent by at least a quorum of `min_section_size`.
@@ -908,16 +1874,28 @@ impl Node {
             return Err(RoutingError::NotEnoughSignatures);
         }
 
-        match self.routing_msg_filter
-                  .filter_incoming(signed_msg.routing_message(), route) {
+        let timer = PhaseTimer::start(ProcessingPhase::Filter);
+        let filtering_result = self.routing_msg_filter
+            .filter_incoming(signed_msg.routing_message(), route);
+        self.processing_stats.record(timer);
+        match filtering_result {
             FilteringResult::KnownMessageAndRoute => {
+                let outcome = FilterOutcome::KnownMessageAndRoute;
+                self.log_decision(msg_hash, &hop_name, route, &dst, Decision::Filtered(outcome));
                 return Ok(());
             }
             frslt @ FilteringResult::KnownMessage |
             frslt @ FilteringResult::NewMessage => {
-                if self.in_authority(&signed_msg.routing_message().dst) {
-                    self.ack_and_broadcast(&signed_msg, route, hop_name, sent_to);
+                let outcome = if frslt == FilteringResult::NewMessage {
+                    FilterOutcome::New
+                } else {
+                    FilterOutcome::KnownMessage
+                };
+                self.log_decision(msg_hash, &hop_name, route, &dst, Decision::Filtered(outcome));
+                if self.in_authority(&dst) {
+                    self.ack_and_broadcast(&signed_msg, route, hop_name, sent_to, next_hop_count);
                     if frslt == FilteringResult::NewMessage {
+                        self.log_decision(msg_hash, &hop_name, route, &dst, Decision::Delivered);
                         // if addressed to us, then we just queue it and return
                         self.msg_queue
                             .push_back(signed_msg.into_routing_message());
@@ -931,9 +1909,12 @@ impl Node {
             return Ok(());
         }
 
-        if let Err(error) = self.send_signed_message(&signed_msg, route, &hop_name, sent_to) {
+        let timer = PhaseTimer::start(ProcessingPhase::Send);
+        if let Err(error) =
+            self.send_signed_message(&signed_msg, route, &hop_name, sent_to, next_hop_count) {
             debug!("{:?} Failed to send {:?}: {:?}", self, signed_msg, error);
         }
+        self.processing_stats.record(timer);
 
         Ok(())
     }
@@ -943,7 +1924,7 @@ impl Node {
                                 outbox: &mut EventBox)
                                 -> Result<(), RoutingError> {
         use messages::MessageContent::*;
-        use Authority::{Client, ManagedNode, PrefixSection, Section};
+        use Authority::{Client, ClientManager, ManagedNode, PrefixSection, Section};
 
         if !self.is_approved {
             match routing_msg.content {
@@ -954,7 +1935,11 @@ impl Node {
                 AcceptAsCandidate { .. } |
                 CandidateApproval { .. } |
                 SectionUpdate { .. } |
-                UserMessagePart { .. } => {
+                UserMessagePart { .. } |
+                ClientRelay(..) |
+                RedirectToClient(..) |
+                ClientUnreachable(..) |
+                CancelRequest { .. } => {
                     // These messages should not be handled before node approval
                     trace!("{:?} Not approved yet. Delaying message handling: {:?}",
                            self,
@@ -988,10 +1973,28 @@ impl Node {
             _ => trace!("{:?} Got routing message {:?}.", self, routing_msg),
         }
 
-        match (routing_msg.content, routing_msg.src, routing_msg.dst) {
-            (Relocate { message_id },
-             Client {
-                 client_id,
+        if let ManagedNode(src_name) = routing_msg.src {
+            match routing_msg.content {
+                ConnectionInfoRequest { generation, .. } |
+                ConnectionInfoResponse { generation, .. } => {
+                    if !self.peer_generations.accept(&src_name, generation) {
+                        debug!("{:?} Dropping stale message from generation {} of {}: {:?}",
+                               self,
+                               generation,
+                               src_name,
+                               routing_msg);
+                        self.stats.count_stale_drop();
+                        return Ok(());
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        match (routing_msg.content, routing_msg.src, routing_msg.dst) {
+            (Relocate { message_id },
+             Client {
+                 client_id,
                  proxy_node_name,
              },
              Section(dst_name)) => {
@@ -1030,6 +2033,7 @@ impl Node {
                  nonce,
                  pub_id,
                  msg_id,
+                 ..
              },
              src @ Client { .. },
              dst @ ManagedNode(_)) |
@@ -1038,6 +2042,7 @@ impl Node {
                  nonce,
                  pub_id,
                  msg_id,
+                 ..
              },
              src @ ManagedNode(_),
              dst @ ManagedNode(_)) => {
@@ -1054,6 +2059,7 @@ impl Node {
                  nonce,
                  pub_id,
                  msg_id,
+                 ..
              },
              ManagedNode(src_name),
              dst @ Client { .. }) |
@@ -1062,6 +2068,7 @@ impl Node {
                  nonce,
                  pub_id,
                  msg_id,
+                 ..
              },
              ManagedNode(src_name),
              dst @ ManagedNode(_)) => {
@@ -1070,7 +2077,8 @@ impl Node {
                                                      pub_id,
                                                      msg_id,
                                                      src_name,
-                                                     dst)
+                                                     dst,
+                                                     outbox)
             }
             (CandidateApproval {
                  new_public_id,
@@ -1101,6 +2109,24 @@ impl Node {
              PrefixSection(_)) => {
                 self.handle_other_section_merge(merge_prefix.with_version(version), section, outbox)
             }
+            (ClientRelay(client_id), ManagedNode(relay_name), ClientManager(dst_name)) => {
+                self.handle_client_relay(client_id, relay_name, dst_name)
+            }
+            (RedirectToClient(signed_msg), ManagedNode(_), dst @ ClientManager(_)) |
+            (RedirectToClient(signed_msg), ManagedNode(_), dst @ ManagedNode(_)) => {
+                self.handle_redirect_to_client(*signed_msg, dst)
+            }
+            (CancelRequest { msg_id, signature }, Client { client_id, .. }, _) => {
+                self.handle_cancel_request(client_id, msg_id, signature, outbox)
+            }
+            (ClientUnreachable(client_id), ManagedNode(proxy_node_name), _) => {
+                let client = Client {
+                    client_id: client_id,
+                    proxy_node_name: proxy_node_name,
+                };
+                outbox.send_event(Event::ClientUnreachable(client));
+                Ok(())
+            }
             (Ack(ack, _), _, _) => self.handle_ack_response(ack),
             (UserMessagePart {
                  hash,
@@ -1113,8 +2139,17 @@ impl Node {
              dst) => {
                 if let Some(msg) = self.user_msg_cache
                        .add(hash, part_count, part_index, payload) {
-                    self.stats().count_user_message(&msg);
-                    outbox.send_event(msg.into_event(src, dst));
+                    if !self.expects_response(&msg, &dst) {
+                        debug!("{:?} Dropping response {:?} from {:?} to a request we never sent.",
+                               self,
+                               msg.message_id(),
+                               src);
+                        return Err(RoutingError::UnknownMessageType);
+                    }
+                    if self.admit_request(&msg, &src) {
+                        self.stats().count_user_message(&msg);
+                        outbox.send_event(msg.into_event(src, dst));
+                    }
                 }
                 Ok(())
             }
@@ -1142,6 +2177,8 @@ impl Node {
         // to our RT.
         // This will flag peer as valid if its found in peer_mgr regardless of their
         // connection status to us.
+        // A peer rejoining our section after losing all its routing connections is still approved.
+        let rejoining = self.recent_peers.remove(&new_pub_id);
         let is_connected = match self.peer_mgr.handle_candidate_approval(&new_pub_id) {
             Ok(is_connected) => is_connected.is_some(),
             Err(_) => {
@@ -1165,7 +2202,11 @@ impl Node {
               self,
               self.our_prefix(),
               new_pub_id);
-        if self.we_want_to_merge() || self.they_want_to_merge() {
+        if rejoining {
+            debug!("{:?} Not sending NodeApproval since {} is rejoining our section.",
+                   self,
+                   new_pub_id);
+        } else if self.we_want_to_merge() || self.they_want_to_merge() {
             debug!("{:?} Not sending NodeApproval since our section is currently merging.",
                    self);
         } else if !self.routing_table().is_valid() {
@@ -1228,11 +2269,16 @@ impl Node {
         }
 
         self.is_approved = true;
+        if self.config.join_progress_events.matches(&JoinProgress::JoinComplete) {
+            outbox.send_event(Event::JoinProgress(JoinProgress::JoinComplete));
+        }
         outbox.send_event(Event::Connected);
         for name in self.routing_table().iter() {
             // TODO: try to remove this as safe_core/safe_vault may not require this notification
             outbox.send_event(Event::NodeAdded(*name, self.routing_table().clone()));
         }
+        self.update_health(outbox);
+        self.update_convergence();
 
         let our_prefix = *self.our_prefix();
         self.send_section_list_signature(our_prefix, None);
@@ -1263,6 +2309,7 @@ impl Node {
         info!("{:?} Resource proof challenges completed. This node has been approved to join the \
                network!",
               self);
+        self.drop_proxy_if_established(outbox);
         trace!("{:?} Node approval completed. Prefixes: {:?}",
                self,
                self.routing_table().prefixes());
@@ -1337,7 +2384,8 @@ impl Node {
     /// Returns `Ok` if a client is allowed to send the given message.
     fn check_valid_client_message(&self, msg: &RoutingMessage) -> Result<(), RoutingError> {
         match msg.content {
-            MessageContent::Ack(..) => Ok(()),
+            MessageContent::Ack(..) |
+            MessageContent::CancelRequest { .. } => Ok(()),
             MessageContent::UserMessagePart { priority, .. } if priority >= DEFAULT_PRIORITY => {
                 Ok(())
             }
@@ -1350,6 +2398,27 @@ impl Node {
         }
     }
 
+    // Checks that a message relayed for one of our clients claims us as the proxy node and the
+    // client's connection key as its ID, so it can't impersonate other clients or proxies.
+    fn check_client_src(&self,
+                        pub_id: &PublicId,
+                        msg: &RoutingMessage)
+                        -> Result<(), RoutingError> {
+        match msg.src {
+            Authority::Client {
+                ref client_id,
+                ref proxy_node_name,
+            } if client_id == pub_id && proxy_node_name == self.name() => Ok(()),
+            _ => {
+                debug!("{:?} Client {} sent a message with invalid source {:?}. Dropping it.",
+                       self,
+                       pub_id,
+                       msg.src);
+                Err(RoutingError::InvalidSource)
+            }
+        }
+    }
+
     fn respond_from_cache(&mut self,
                           routing_msg: &RoutingMessage,
                           route: u8)
@@ -1417,6 +2486,16 @@ impl Node {
             return;
         }
 
+        if client_restriction &&
+           self.config.connection_quotas
+               .clients
+               .map_or(false, |max| self.peer_mgr.client_num() >= max) {
+            debug!("{:?} Client {:?} rejected: Client quota reached.", self, pub_id);
+            outbox.send_event(Event::PeerRefused(*pub_id.name(), RefusalReason::ClientQuota));
+            self.send_direct_message(pub_id, DirectMessage::BootstrapDeny);
+            return;
+        }
+
         if (client_restriction || !self.is_first_node) &&
            self.routing_table().len() < self.min_section_size() - 1 {
             debug!("{:?} Client {:?} rejected: Routing table has {} entries. {} required.",
@@ -1440,6 +2519,38 @@ impl Node {
             .insert_peer(Peer::new(pub_id, peer_state, false, ReconnectingPeer::False));
 
         self.send_direct_message(pub_id, DirectMessage::BootstrapIdentify);
+        if client_restriction {
+            self.send_client_relay(pub_id);
+        }
+    }
+
+    // Replaces the entry of a routing peer which reconnected to us and identified itself, so that
+    // it refers to the new direct connection.
+    fn replace_routing_peer(&mut self, pub_id: PublicId) {
+        debug!("{:?} Routing peer {} identified on its new connection. Replacing its entry.",
+               self,
+               pub_id);
+        self.peer_mgr
+            .insert_peer(Peer::new(pub_id,
+                                   PeerState::Routing(RoutingConnection::Direct),
+                                   true,
+                                   ReconnectingPeer::False));
+        // In case it is rejoining its section through us.
+        self.send_direct_message(pub_id, DirectMessage::BootstrapIdentify);
+    }
+
+    // Announces to the client's `ClientManager`s that we are its proxy node, so that they can
+    // redirect messages addressed to the client via a previous proxy node to us.
+    fn send_client_relay(&mut self, client_id: PublicId) {
+        let src = Authority::ManagedNode(*self.name());
+        let dst = Authority::ClientManager(*client_id.name());
+        let content = MessageContent::ClientRelay(client_id);
+        if let Err(error) = self.send_routing_message(src, dst, content) {
+            debug!("{:?} Failed to send ClientRelay for {:?}: {:?}",
+                   self,
+                   client_id,
+                   error);
+        }
     }
 
     #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
@@ -1448,6 +2559,7 @@ impl Node {
                                  new_pub_id: &PublicId,
                                  signature_using_old: &sign::Signature,
                                  signature_using_new: &sign::Signature,
+                                 nonce: u64,
                                  new_client_auth: &Authority<XorName>,
                                  outbox: &mut EventBox) {
         debug!("{:?} Handling CandidateIdentify from {}->{}.",
@@ -1457,11 +2569,29 @@ impl Node {
         if !self.is_candidate_identify_valid(old_pub_id,
                                              new_pub_id,
                                              signature_using_old,
-                                             signature_using_new) {
+                                             signature_using_new,
+                                             nonce) {
             warn!("{:?} Signature check failed in CandidateIdentify, so dropping peer {:?}.",
                   self,
                   new_pub_id);
             self.disconnect_peer(new_pub_id, Some(outbox));
+            return;
+        }
+
+        // The candidate must use a name within the interval our section assigned to it.
+        let assigned_interval = self.relocate_responses
+            .get(old_pub_id)
+            .map(|&(target_interval, _)| target_interval);
+        if let Some((lower, upper)) = assigned_interval {
+            if *new_pub_id.name() < lower || *new_pub_id.name() > upper {
+                warn!("{:?} Candidate {} identified as {}, which is outside its assigned \
+                       interval, so dropping it.",
+                      self,
+                      old_pub_id,
+                      new_pub_id);
+                self.disconnect_peer(new_pub_id, Some(outbox));
+                return;
+            }
         }
 
         // If this is a valid node in peer_mgr but the Candidate has sent us a CandidateIdentify,
@@ -1535,19 +2665,22 @@ impl Node {
                                    old_pub_id: &PublicId,
                                    new_pub_id: &PublicId,
                                    signature_using_old: &sign::Signature,
-                                   signature_using_new: &sign::Signature)
+                                   signature_using_new: &sign::Signature,
+                                   nonce: u64)
                                    -> bool {
         let old_and_new_pub_ids = (old_pub_id, new_pub_id);
-        let mut signed_data = match serialisation::serialise(&old_and_new_pub_ids) {
+        let mut signed_data = match serialisation::serialise(&old_and_new_pub_ids)
+                  .map_err(RoutingError::from)
+                  .and_then(|serialised| identify_signed_bytes(serialised, nonce)) {
             Ok(result) => result,
             Err(error) => {
                 error!("Failed to serialise public IDs: {:?}", error);
                 return false;
             }
         };
-        if !sign::verify_detached(signature_using_old,
-                                  &signed_data,
-                                  old_pub_id.signing_public_key()) {
+        if !old_pub_id
+                .signing_public_key()
+                .verify(signature_using_old, &signed_data) {
             debug!("{:?} CandidateIdentify from {}->{} has invalid old signature.",
                    self,
                    old_pub_id,
@@ -1555,9 +2688,9 @@ impl Node {
             return false;
         }
         signed_data.extend_from_slice(&signature_using_old.0);
-        if !sign::verify_detached(signature_using_new,
-                                  &signed_data,
-                                  new_pub_id.signing_public_key()) {
+        if !new_pub_id
+                .signing_public_key()
+                .verify(signature_using_new, &signed_data) {
             debug!("{:?} CandidateIdentify from {}->{} has invalid new signature.",
                    self,
                    old_pub_id,
@@ -1602,8 +2735,25 @@ impl Node {
             outbox.send_event(Event::Connected);
         }
 
+        if !self.is_approved {
+            let step = JoinProgress::CloseGroupConnecting {
+                connected: self.routing_table().len(),
+                required: self.joining_section_size,
+            };
+            if self.config.join_progress_events.matches(&step) {
+                outbox.send_event(Event::JoinProgress(step));
+            }
+        }
+
+        if let Some(ref mut departures) = self.departures {
+            departures.rejoined(pub_id.name());
+        }
+
         if self.is_approved {
             outbox.send_event(Event::NodeAdded(*pub_id.name(), self.routing_table().clone()));
+            if self.our_prefix().matches(pub_id.name()) {
+                self.note_churn();
+            }
 
             if let Some(prefix) = self.routing_table().find_section_prefix(pub_id.name()) {
                 self.send_section_list_signature(prefix, None);
@@ -1618,6 +2768,11 @@ impl Node {
                     self.send_section_update(Some(prefix));
                 }
             }
+
+            self.drop_proxy_if_established(outbox);
+            self.update_health(outbox);
+            self.update_convergence();
+            self.finish_recovery_if_rejoined(outbox);
         }
 
         for dst_id in self.peer_mgr.peers_needing_tunnel() {
@@ -1691,7 +2846,7 @@ impl Node {
                 return;
             }
         };
-        let nonce = box_::gen_nonce();
+        let nonce = box_::Nonce(self.random());
         let encrypted_conn_info = box_::seal(&encoded_connection_info,
                                              &nonce,
                                              their_pub_id.encrypting_public_key(),
@@ -1702,13 +2857,15 @@ impl Node {
                 nonce: nonce.0,
                 pub_id: *self.full_id.public_id(),
                 msg_id: msg_id,
+                generation: self.churn_generation,
             }
         } else {
             MessageContent::ConnectionInfoRequest {
                 encrypted_conn_info: encrypted_conn_info,
                 nonce: nonce.0,
                 pub_id: *self.full_id.public_id(),
-                msg_id: MessageId::new(),
+                msg_id: MessageId::from_name(self.random()),
+                generation: self.churn_generation,
             }
         };
 
@@ -1729,6 +2886,12 @@ impl Node {
                        self,
                        err);
                 let new_token = match self.peer_mgr.get_new_connection_info_token(result_token) {
+                    Err(PeerManagerError::ConnectionInfoAttemptsExhausted) => {
+                        debug!("{:?} Failed to prepare connection info too many times. \
+                               Abandoning connection attempt.",
+                               self);
+                        return;
+                    }
                     Err(error) => {
                         debug!("{:?} Failed to prepare connection info, but no entry found in \
                                token map: {:?}",
@@ -1789,6 +2952,12 @@ impl Node {
                                       outbox: &mut EventBox)
                                       -> Result<(), RoutingError> {
         self.peer_mgr.allow_connect(pub_id.name())?;
+        if self.peer_mgr.is_banned(pub_id.name()) {
+            debug!("{:?} Ignoring connection info request from banned peer {}.",
+                   self,
+                   pub_id);
+            return Err(RoutingError::InvalidPeer);
+        }
         let their_connection_info =
             self.decrypt_connection_info(&encrypted_connection_info,
                                          &box_::Nonce(nonce_bytes),
@@ -1803,8 +2972,15 @@ impl Node {
         }
 
         use peer_manager::ConnectionInfoReceivedResult::*;
-        match self.peer_mgr
-                  .connection_info_received(src, dst, their_connection_info, message_id) {
+        let result = self.peer_mgr
+            .connection_info_received(src, dst, their_connection_info, message_id);
+        if result.is_ok() && self.recent_peers.contains(&pub_id) {
+            // A peer we lost recently is rejoining our section. Like a candidate, it only becomes
+            // valid once our section approved it.
+            debug!("{:?} Recently lost peer {} is reconnecting.", self, pub_id);
+            self.send_rejoin_approval(pub_id, src);
+        }
+        match result {
             Ok(Ready(our_info, their_info)) => {
                 debug!("{:?} Already sent a connection info request to {}; resending \
                         our same details as a response.",
@@ -1825,18 +3001,12 @@ impl Node {
             Ok(IsProxy) |
             Ok(IsClient) |
             Ok(IsJoiningNode) => {
-                // TODO: we should not be getting conn info req from Proxy/JoiningNode
-
-                log_or_panic!(LogLevel::Error,
-                              "{:?} Received ConnectionInfoRequest from peer {} \
-                              with invalid state.",
-                              self,
-                              pub_id);
-                if self.peer_mgr
-                       .get_peer(&pub_id)
-                       .map_or(false, Peer::valid) {
-                    self.process_connection(pub_id, outbox);
-                }
+                debug!("{:?} Received connection info request from {}, which is our proxy, \
+                        client or joining node and shouldn't be connecting as a node.",
+                       self,
+                       pub_id);
+                self.handle_protocol_violation(&pub_id, outbox);
+                return Err(RoutingError::UnexpectedConnectionInfo);
             }
             Ok(Waiting) | Ok(IsConnected) | Err(_) => (),
         }
@@ -1849,10 +3019,15 @@ impl Node {
                                        public_id: PublicId,
                                        message_id: MessageId,
                                        src: XorName,
-                                       dst: Authority<XorName>)
+                                       dst: Authority<XorName>,
+                                       outbox: &mut EventBox)
                                        -> Result<(), RoutingError> {
         self.peer_mgr.allow_connect(&src)?;
         if self.peer_mgr.get_peer(&public_id).is_none() {
+            debug!("{:?} Dropping connection info response from {}: we didn't request it, or the \
+                    request has expired.",
+                   self,
+                   public_id);
             return Err(RoutingError::InvalidDestination);
         }
 
@@ -1894,12 +3069,63 @@ impl Node {
                       sent a corresponding request",
                        self,
                        public_id);
+                self.handle_protocol_violation(&public_id, outbox);
+                return Err(RoutingError::UnexpectedConnectionInfo);
             }
             Ok(Waiting) | Ok(IsConnected) | Err(_) => (),
         }
         Ok(())
     }
 
+    /// Records a protocol violation by `pub_id`, and drops the connection to them once they have
+    /// committed too many.
+    fn handle_protocol_violation(&mut self, pub_id: &PublicId, outbox: &mut EventBox) {
+        if !self.peer_mgr.record_violation(pub_id) {
+            return;
+        }
+        debug!("{:?} Disconnecting {} due to repeated protocol violations.",
+               self,
+               pub_id);
+        let _ = self.crust_service.disconnect(*pub_id);
+        let _ = self.dropped_peer(pub_id, outbox, false);
+    }
+
+    /// Disconnects from the peer with the given name on behalf of the user and removes it from the
+    /// routing table. Returns `None` if we don't know the peer, otherwise whether we should keep
+    /// running.
+    fn disconnect_peer_by_name(&mut self, name: &XorName, outbox: &mut EventBox) -> Option<bool> {
+        let pub_id = match self.peer_mgr.get_pub_id(name) {
+            Some(&pub_id) => pub_id,
+            None => return None,
+        };
+        debug!("{:?} Disconnecting {} as requested by the user.", self, pub_id);
+        let _ = self.crust_service.disconnect(pub_id);
+        Some(self.dropped_peer(&pub_id, outbox, false))
+    }
+
+    /// Reconnects to a routing peer whose ban has lapsed, if our routing table still needs it.
+    fn reconnect_after_ban(&mut self, pub_id: PublicId, outbox: &mut EventBox) {
+        if self.peer_mgr.is_banned(pub_id.name()) ||
+           self.routing_table().need_to_add(pub_id.name()).is_err() {
+            return;
+        }
+        debug!("{:?} Sending connection info to {} as its ban has lapsed.",
+               self,
+               pub_id);
+        let src = Authority::ManagedNode(*self.name());
+        let dst = Authority::ManagedNode(*pub_id.name());
+        if let Err(error) = self.send_connection_info_request(pub_id,
+                                                              src,
+                                                              dst,
+                                                              outbox,
+                                                              ReconnectingPeer::False) {
+            debug!("{:?} - Failed to send connection info to {}: {:?}",
+                   self,
+                   pub_id,
+                   error);
+        }
+    }
+
     /// Handles a request by `src_id` to act as a tunnel connecting it with `dst_id`.
     fn handle_tunnel_request(&mut self, srd_id: PublicId, dst_id: PublicId) {
         if self.peer_mgr.can_tunnel_for(&srd_id, &dst_id) {
@@ -1975,6 +3201,7 @@ impl Node {
         if self.tunnels.remove(dst_id, src_id) {
             debug!("{:?} Tunnel to {} via {} closed.", self, dst_id, src_id);
             if !self.crust_service.is_connected(&dst_id) {
+                self.remember_lost_peer(&dst_id);
                 self.dropped_peer(&dst_id, outbox, true);
             }
         }
@@ -1991,6 +3218,38 @@ impl Node {
         }
     }
 
+    /// Disconnects from our proxy node once we are approved and have `proxy_drop_threshold`
+    /// routing table entries, so we stop using its resources. The proxy is kept if it is also a
+    /// routing table entry, or while messages which can only be resent via the proxy are still
+    /// awaiting acknowledgement.
+    fn drop_proxy_if_established(&mut self, outbox: &mut EventBox) {
+        if !self.is_approved || self.routing_table().len() < self.config.proxy_drop_threshold {
+            return;
+        }
+
+        let proxy_pub_id = match self.peer_mgr.get_non_routing_proxy() {
+            Some(pub_id) => *pub_id,
+            None => return,
+        };
+
+        if self.ack_mgr
+               .has_pending(|unacked_msg| unacked_msg.src().is_client()) {
+            trace!("{:?} Not disconnecting proxy node {:?} while messages via it are \
+                    unacknowledged.",
+                   self,
+                   proxy_pub_id);
+            return;
+        }
+
+        debug!("{:?} Routing table has {} entries. Disconnecting proxy node {:?}.",
+               self,
+               self.routing_table().len(),
+               proxy_pub_id);
+        let _ = self.crust_service.disconnect(proxy_pub_id);
+        let _ = self.peer_mgr.remove_peer(&proxy_pub_id);
+        outbox.send_event(Event::ProxyDropped);
+    }
+
     /// Disconnects from the given peer, via Crust or by dropping the tunnel node, if the peer is
     /// not a proxy, client or routing table entry.
     fn disconnect_peer(&mut self, pub_id: &PublicId, outbox: Option<&mut EventBox>) {
@@ -2047,13 +3306,51 @@ impl Node {
             return Err(RoutingError::InvalidDestination);
         }
 
-        let close_section = match self.routing_table().close_names(&dst_name) {
-            Some(close_section) => close_section.into_iter().collect(),
-            None => return Err(RoutingError::InvalidDestination),
+        // A node which already holds its name in the network can't be relocated again.
+        if self.routing_table().has(&dst_name) {
+            debug!("{:?} Rejecting relocate request from {}, which is already in our routing \
+                    table.",
+                   self,
+                   relocating_node_id);
+            return Err(RoutingError::InvalidSource);
+        }
+
+        // Nor can a name another node is already being relocated to, even if it hasn't joined yet.
+        if self.relocation_cache
+               .iter()
+               .any(|(pub_id, relocated_name)| {
+                        *relocated_name == dst_name && *pub_id != relocating_node_id
+                    }) {
+            debug!("{:?} Rejecting relocate request from {}, whose name we assigned to another \
+                    node.",
+                   self,
+                   relocating_node_id);
+            return Err(RoutingError::InvalidSource);
+        }
+
+        // A repeated join request from the same node is passed on to the section it is already
+        // being relocated to, which answers it with the response it sent before.
+        let cached_dst = self.relocation_cache.get(&relocating_node_id).cloned();
+        let relocation_dst = if let Some(relocation_dst) = cached_dst {
+            self.stats.count_relocation_cache_hit();
+            debug!("{:?} Already relocating {} to {:?}. Requesting the earlier response again.",
+                   self,
+                   relocating_node_id,
+                   relocation_dst);
+            relocation_dst
+        } else {
+            self.stats.count_relocation_cache_miss();
+            let close_section = match self.routing_table().close_names(&dst_name) {
+                Some(close_section) => close_section.into_iter().collect(),
+                None => return Err(RoutingError::InvalidDestination),
+            };
+            let relocation_dst =
+                self.next_relocation_dst
+                    .unwrap_or_else(|| utils::calculate_relocation_dst(close_section, &dst_name));
+            let _ = self.relocation_cache
+                .insert(relocating_node_id, relocation_dst);
+            relocation_dst
         };
-        let relocation_dst =
-            self.next_relocation_dst
-                .unwrap_or_else(|| utils::calculate_relocation_dst(close_section, &dst_name));
 
         // From X -> Y; Send to close section of the relocated name
         let request_content = MessageContent::ExpectCandidate {
@@ -2106,6 +3403,19 @@ impl Node {
             return self.send_routing_message(src, dst, request_content);
         }
 
+        // We already accepted this candidate: answer its repeated request the same way.
+        let cached_response = self.relocate_responses.get(&old_pub_id).cloned();
+        if let Some((target_interval, section)) = cached_response {
+            debug!("{:?} Repeating relocate response to candidate with old name {}.",
+                   self,
+                   old_pub_id);
+            return self.send_relocate_response(relocation_dst,
+                                               old_client_auth,
+                                               target_interval,
+                                               section,
+                                               message_id);
+        }
+
         let target_interval = self.next_relocation_interval
             .take()
             .unwrap_or_else(|| {
@@ -2152,20 +3462,36 @@ impl Node {
 
         let own_section = self.peer_mgr
             .accept_as_candidate(old_pub_id, target_interval);
-        let response_content = MessageContent::RelocateResponse {
-            target_interval: target_interval,
-            section: own_section,
-            message_id: message_id,
-        };
+        let _ = self.relocate_responses
+            .insert(old_pub_id, (target_interval, own_section.clone()));
         info!("{:?} Our section with {:?} accepted candidate with old name {}.",
               self,
               self.our_prefix(),
               old_pub_id);
+        self.send_relocate_response(relocation_dst,
+                                    old_client_auth,
+                                    target_interval,
+                                    own_section,
+                                    message_id)
+    }
+
+    fn send_relocate_response(&mut self,
+                              relocation_dst: Authority<XorName>,
+                              old_client_auth: Authority<XorName>,
+                              target_interval: (XorName, XorName),
+                              section: (Prefix<XorName>, BTreeSet<PublicId>),
+                              message_id: MessageId)
+                              -> Result<(), RoutingError> {
+        let response_content = MessageContent::RelocateResponse {
+            target_interval: target_interval,
+            section: section,
+            message_id: message_id,
+        };
         trace!("{:?} Sending {:?} to {:?}",
                self,
                response_content,
                old_client_auth);
-
+        self.stats.count_relocate_response();
         self.send_routing_message(relocation_dst, old_client_auth, response_content)
     }
 
@@ -2238,6 +3564,9 @@ impl Node {
         let (peers_to_drop, our_new_prefix) = self.peer_mgr.split_section(ver_pfx);
         if let Some(new_prefix) = our_new_prefix {
             outbox.send_event(Event::SectionSplit(new_prefix));
+            self.note_churn();
+            self.update_health(outbox);
+            self.update_convergence();
         }
 
         for pub_id in peers_to_drop {
@@ -2343,6 +3672,9 @@ impl Node {
              needed_peers) => {
                 // TODO - the event should maybe only fire once all new connections have been made?
                 outbox.send_event(Event::SectionMerge(*versioned_prefix.prefix()));
+                self.note_churn();
+                self.update_health(outbox);
+                self.update_convergence();
                 info!("{:?} Own section merge completed. Prefixes: {:?}",
                       self,
                       self.routing_table().prefixes());
@@ -2425,6 +3757,128 @@ impl Node {
         Ok(())
     }
 
+    // Returns whether the user message should be delivered, i.e. it isn't a client request which
+    // was cancelled already. Client requests we deliver are remembered, in case they are
+    // cancelled later.
+    fn admit_request(&mut self, msg: &UserMessage, src: &Authority<XorName>) -> bool {
+        let key = match (msg, src) {
+            (&UserMessage::Request(ref request), &Authority::Client { client_id, .. }) => {
+                (client_id, request.message_id())
+            }
+            _ => return true,
+        };
+        if self.cancelled_requests.contains_key(&key) {
+            debug!("{:?} Dropping cancelled request {:?} from {}.",
+                   self,
+                   key.1,
+                   key.0);
+            return false;
+        }
+        let _ = self.delivered_requests.insert(key, ());
+        true
+    }
+
+    // Remembers the requests we send as a managed node, so that we can tell whether a response
+    // addressed to us answers one of them.
+    fn note_sent_request(&mut self, src: &Authority<XorName>, msg: &UserMessage) {
+        if let (&Authority::ManagedNode(_), &UserMessage::Request(ref request)) = (src, msg) {
+            let _ = self.sent_requests.insert(request.message_id(), ());
+        }
+    }
+
+    // Returns whether the user message isn't a response to us as a managed node for a request we
+    // never sent. Responses to clients aren't checked anywhere: a client which reconnects after
+    // losing its proxy still receives the responses to the requests it sent before.
+    fn expects_response(&self, msg: &UserMessage, dst: &Authority<XorName>) -> bool {
+        match (msg, dst) {
+            (&UserMessage::Response(ref response), &Authority::ManagedNode(_)) => {
+                self.sent_requests.contains_key(&response.message_id())
+            }
+            _ => true,
+        }
+    }
+
+    // Records the cancellation of a client's request, if the client signed it. If we delivered
+    // the request already, raises `Event::RequestCancelled`, so that the user can stop handling it.
+    fn handle_cancel_request(&mut self,
+                             client_id: PublicId,
+                             msg_id: MessageId,
+                             signature: sign::Signature,
+                             outbox: &mut EventBox)
+                             -> Result<(), RoutingError> {
+        let serialised = serialisation::serialise(&msg_id)?;
+        if !client_id
+                .signing_public_key()
+                .verify(&signature, &serialised) {
+            debug!("{:?} Invalid signature on the cancellation of {:?} from {}.",
+                   self,
+                   msg_id,
+                   client_id);
+            return Err(RoutingError::FailedSignature);
+        }
+        let key = (client_id, msg_id);
+        if self.cancelled_requests.insert(key, ()).is_some() {
+            return Ok(()); // Duplicate cancellation.
+        }
+        if self.delivered_requests.remove(&key).is_some() {
+            outbox.send_event(Event::RequestCancelled { message_id: msg_id });
+        }
+        Ok(())
+    }
+
+    fn handle_client_relay(&mut self,
+                           client_id: PublicId,
+                           relay_name: XorName,
+                           dst_name: XorName)
+                           -> Result<(), RoutingError> {
+        if *client_id.name() != dst_name {
+            debug!("{:?} Received ClientRelay for {:?} addressed to {:?}.",
+                   self,
+                   client_id,
+                   dst_name);
+            return Err(RoutingError::BadAuthority);
+        }
+        let _ = self.client_relays.insert(client_id, relay_name);
+        Ok(())
+    }
+
+    // Passes on a message for a client whose proxy node was not found: as one of the client's
+    // `ClientManager`s to the client's current proxy node, and as that proxy node to the client.
+    fn handle_redirect_to_client(&mut self,
+                                 signed_msg: SignedMessage,
+                                 dst: Authority<XorName>)
+                                 -> Result<(), RoutingError> {
+        let client_id = match signed_msg.routing_message().dst {
+            Authority::Client { client_id, .. } => client_id,
+            _ => {
+                debug!("{:?} Received RedirectToClient with invalid content {:?}.",
+                       self,
+                       signed_msg);
+                return Err(RoutingError::BadAuthority);
+            }
+        };
+        signed_msg.check_integrity(self.min_section_size())?;
+
+        if self.peer_mgr.is_client(&client_id) {
+            return self.relay_to_client(&signed_msg, &client_id, 0);
+        }
+        let relay_name = match (dst, self.client_relays.get(&client_id).cloned()) {
+            (Authority::ClientManager(name), Some(relay_name)) if name == *client_id.name() => {
+                relay_name
+            }
+            _ => {
+                debug!("{:?} Proxy node of client {:?} not known. Dropping {:?}.",
+                       self,
+                       client_id,
+                       signed_msg);
+                return Ok(());
+            }
+        };
+        let src = Authority::ManagedNode(*self.name());
+        let content = MessageContent::RedirectToClient(Box::new(signed_msg));
+        self.send_routing_message(src, Authority::ManagedNode(relay_name), content)
+    }
+
     fn handle_ack_response(&mut self, ack: Ack) -> Result<(), RoutingError> {
         self.ack_mgr.receive(ack);
         Ok(())
@@ -2441,6 +3895,14 @@ impl Node {
             let tick_period = Duration::from_secs(TICK_TIMEOUT_SECS);
             self.tick_timer_token = self.timer.schedule(tick_period);
             self.remove_expired_peers(outbox);
+            let _ = self.bootstrappers.sweep();
+            let _ = self.identify_nonces.sweep();
+            self.peer_mgr.remove_expired_bans();
+            self.send_queued_connects(outbox);
+            self.drop_proxy_if_established(outbox);
+            for client_id in self.peer_mgr.client_pub_ids() {
+                self.send_client_relay(client_id);
+            }
 
             let transition = if cfg!(feature = "use-mock-crust") {
                 Transition::Stay
@@ -2454,6 +3916,40 @@ impl Node {
             return transition;
         }
 
+        if self.audit_timer_token == Some(token) {
+            return self.audit_connections(outbox);
+        }
+
+        if let Some(pub_id) = self.ban_timer_tokens.remove(&token) {
+            self.reconnect_after_ban(pub_id, outbox);
+            return Transition::Stay;
+        }
+
+        if self.gossip_timer_token == Some(token) {
+            self.send_table_samples();
+            return Transition::Stay;
+        }
+
+        if self.convergence_timer_token == Some(token) {
+            self.take_convergence_snapshot(outbox);
+            return Transition::Stay;
+        }
+
+        if self.state_save_timer_token == Some(token) {
+            if let Some(interval) = self.state_persistence
+                   .as_ref()
+                   .map(StatePersistence::interval) {
+                self.state_save_timer_token = Some(self.timer.schedule(interval));
+            }
+            self.save_state();
+            return Transition::Stay;
+        }
+
+        if self.recovery_timer_token == Some(token) {
+            self.retry_recovery(outbox);
+            return Transition::Stay;
+        }
+
         if self.su_timer_token == Some(token) {
             if cfg!(feature = "use-mock-crust") {
                 trace!("{:?} not to schedule next section update during mock_crust test.",
@@ -2470,6 +3966,9 @@ impl Node {
         } else if self.candidate_timer_token == Some(token) {
             self.candidate_timer_token = None;
             self.send_candidate_approval();
+        } else if self.connect_spacing_token == Some(token) {
+            self.connect_spacing_token = None;
+            self.send_queued_connects(outbox);
         } else if self.candidate_status_token == Some(token) {
             self.candidate_status_token =
                 Some(self.timer
@@ -2549,48 +4048,305 @@ impl Node {
         transition
     }
 
-    fn send_candidate_approval(&mut self) {
-        let (response_content, new_name) = match self.peer_mgr.verified_candidate_info() {
-            Err(_) => {
-                trace!("{:?} No candidate for which to send CandidateApproval.",
-                       self);
-                return;
-            }
-            Ok(result) => result,
+    fn audit_live_connection(&mut self, pub_id: PublicId) {
+        if let Some(ref mut audit) = self.audit {
+            audit.connected(pub_id);
+        }
+    }
+
+    // Cross-checks our routing table against Crust's live connections. Routing table entries
+    // without a connection, and connections to peers we don't know, are dropped once they have
+    // persisted for the grace period.
+    fn audit_connections(&mut self, outbox: &mut EventBox) -> Transition {
+        let mut audit = match self.audit.take() {
+            Some(audit) => audit,
+            None => return Transition::Stay,
         };
+        self.audit_timer_token = Some(self.timer.schedule(audit.interval()));
 
-        if self.we_want_to_merge() || self.they_want_to_merge() {
-            debug!("{:?} Resource proof duration has finished, but not voting to approve \
-                   candidate {} since our section is currently merging.",
-                   self,
-                   new_name);
-            return;
+        let unconnected_entries = self.routing_table()
+            .iter()
+            .filter_map(|name| self.peer_mgr.get_peer_by_name(name))
+            .filter(|peer| match *peer.state() {
+                        PeerState::Routing(RoutingConnection::Tunnel) => {
+                            self.tunnels
+                                .tunnel_for(peer.pub_id())
+                                .map_or(true, |node_id| !self.crust_service.is_connected(node_id))
+                        }
+                        PeerState::Routing(_) => !self.crust_service.is_connected(peer.pub_id()),
+                        _ => false,
+                    })
+            .map(|peer| *peer.pub_id())
+            .collect_vec();
+        let mut unknown_connections = vec![];
+        for pub_id in audit.live_peers() {
+            if !self.crust_service.is_connected(&pub_id) {
+                audit.disconnected(&pub_id);
+            } else if self.peer_mgr.get_peer(&pub_id).is_none() {
+                unknown_connections.push(pub_id);
+            }
         }
+        let due = audit.due(unconnected_entries
+                                .iter()
+                                .chain(&unknown_connections)
+                                .cloned());
 
-        let src = Authority::Section(new_name);
-        info!("{:?} Resource proof duration has finished. Voting to approve candidate {}.",
-              self,
-              new_name);
-        trace!("{:?} Sending {:?} to {:?}.", self, response_content, src);
+        let mut report = AuditReport::default();
+        let mut transition = Transition::Stay;
+        for pub_id in unconnected_entries {
+            if !due.contains(&pub_id) {
+                report.pending.push(*pub_id.name());
+                continue;
+            }
+            debug!("{:?} Audit: dropping {} from the routing table; no live connection.",
+                   self,
+                   pub_id);
+            report.dropped_entries.push(*pub_id.name());
+            if let Transition::Terminate = self.handle_lost_peer(pub_id, outbox) {
+                transition = Transition::Terminate;
+            }
+        }
+        for pub_id in unknown_connections {
+            if !due.contains(&pub_id) {
+                report.pending.push(*pub_id.name());
+                continue;
+            }
+            debug!("{:?} Audit: closing the connection to unknown peer {}.",
+                   self,
+                   pub_id);
+            report.closed_connections.push(*pub_id.name());
+            let _ = self.crust_service.disconnect(pub_id);
+            audit.disconnected(&pub_id);
+        }
+        self.audit = Some(audit);
 
-        if let Err(error) = self.send_routing_message(src, src, response_content) {
-            debug!("{:?} Failed sending CandidateApproval: {:?}", self, error);
+        self.stats
+            .count_audit_repairs(report.dropped_entries.len() + report.closed_connections.len());
+        if !report.is_empty() {
+            info!("{:?} Connection audit found discrepancies: {:?}", self, report);
+            outbox.send_event(Event::ConnectionAudit(report));
         }
+        transition
     }
 
-    fn decrypt_connection_info(&self,
-                               encrypted_connection_info: &[u8],
-                               nonce: &box_::Nonce,
-                               public_id: &PublicId)
-                               -> Result<PubConnectionInfo, RoutingError> {
-        let decipher_result = box_::open(encrypted_connection_info,
-                                         nonce,
-                                         public_id.encrypting_public_key(),
-                                         self.full_id.encrypting_private_key());
-
-        let serialised_connection_info =
-            decipher_result.map_err(|()| RoutingError::AsymmetricDecryptionFailure)?;
-        Ok(serialisation::deserialise(&serialised_connection_info)?)
+    // Whether our routing table is large enough to take part in gossip. Smaller ones are still
+    // being filled by the join process.
+    fn is_gossiping(&self) -> bool {
+        self.gossip.is_some() && self.is_approved &&
+        self.routing_table().len() >= self.min_section_size()
+    }
+
+    // Sends samples of our routing table's names to a few random peers, and schedules the next
+    // round.
+    fn send_table_samples(&mut self) {
+        if let Some(ref mut gossip) = self.gossip {
+            self.gossip_timer_token = Some(self.timer.schedule(gossip.interval()));
+            gossip.remove_expired();
+        }
+        if !self.is_gossiping() {
+            return;
+        }
+        let names = self.routing_table().iter().cloned().collect_vec();
+        let samples = match self.gossip {
+            Some(ref mut gossip) => gossip.choose_samples(&names),
+            None => return,
+        };
+        for (recipient, sample) in samples {
+            if let Some(&pub_id) = self.peer_mgr.get_pub_id(&recipient) {
+                self.send_direct_message(pub_id, DirectMessage::TableSample(sample));
+            }
+        }
+    }
+
+    // Tells the other members of our section that we lost our connection to the member `name`,
+    // and counts that as our own report of its departure.
+    fn report_lost_member(&mut self, name: XorName, outbox: &mut EventBox) {
+        match self.departures {
+            Some(ref departures) if !departures.has_departed(&name) => (),
+            _ => return,
+        }
+        let members = self.routing_table()
+            .our_section()
+            .iter()
+            .filter_map(|member| self.peer_mgr.get_pub_id(member))
+            .cloned()
+            .collect_vec();
+        for pub_id in members {
+            self.send_direct_message(pub_id, DirectMessage::PeerLost(name));
+        }
+        let our_name = *self.name();
+        self.observe_departure(name, our_name, outbox);
+    }
+
+    fn handle_peer_lost(&mut self, pub_id: PublicId, name: XorName, outbox: &mut EventBox) {
+        if !self.is_approved || !self.peer_mgr.is_routing_peer(&pub_id) ||
+           !self.our_prefix().matches(&name) {
+            return;
+        }
+        self.observe_departure(name, *pub_id.name(), outbox);
+    }
+
+    // Counts `observer`'s report of losing the member `name`, and raises `Event::NodeDeparted` once
+    // a quorum of the rest of our section reported it.
+    fn observe_departure(&mut self, name: XorName, observer: XorName, outbox: &mut EventBox) {
+        let mut group = self.routing_table().our_section().clone();
+        let _ = group.remove(&name);
+        let departed = match self.departures {
+            Some(ref mut departures) => departures.observe(name, observer, &group),
+            None => false,
+        };
+        if departed {
+            info!("{:?} A quorum of our section lost {:?}; it has departed.",
+                  self,
+                  name);
+            outbox.send_event(Event::NodeDeparted(name));
+        }
+    }
+
+    // Buffers the names from a peer's table sample which we should add to our routing table, and
+    // requests their IDs from the peer.
+    fn handle_table_sample(&mut self, pub_id: PublicId, names: Vec<XorName>) {
+        if !self.is_gossiping() || !self.peer_mgr.is_routing_peer(&pub_id) {
+            return;
+        }
+        let allowed = match self.gossip {
+            Some(ref mut gossip) => gossip.allow_sample(pub_id),
+            None => false,
+        };
+        if !allowed {
+            trace!("{:?} Dropping table sample from {}; rate limit exceeded.",
+                   self,
+                   pub_id);
+            return;
+        }
+        let wanted = names
+            .into_iter()
+            .take(MAX_GOSSIP_NAMES)
+            .filter(|name| self.routing_table().need_to_add(name).is_ok())
+            .collect_vec();
+        if wanted.is_empty() {
+            return;
+        }
+        let our_name = *self.name();
+        let requested = match self.gossip {
+            Some(ref mut gossip) => gossip.add_candidates(&our_name, pub_id, wanted),
+            None => return,
+        };
+        if !requested.is_empty() {
+            debug!("{:?} Requesting the IDs of {:?} learned from {}'s table sample.",
+                   self,
+                   requested,
+                   pub_id);
+            self.send_direct_message(pub_id, DirectMessage::PeerIdsRequest(requested));
+        }
+    }
+
+    fn handle_peer_ids_request(&mut self, pub_id: PublicId, names: &[XorName]) {
+        if self.gossip.is_none() || !self.peer_mgr.is_routing_peer(&pub_id) {
+            return;
+        }
+        let pub_ids = names
+            .iter()
+            .take(MAX_GOSSIP_NAMES)
+            .filter(|name| self.routing_table().has(name))
+            .filter_map(|name| self.peer_mgr.get_pub_id(name).cloned())
+            .collect_vec();
+        self.send_direct_message(pub_id, DirectMessage::PeerIds(pub_ids));
+    }
+
+    // Connects to the peers whose IDs we requested, via the usual connection info exchange.
+    fn handle_peer_ids(&mut self, pub_id: PublicId, pub_ids: Vec<PublicId>, outbox: &mut EventBox) {
+        let our_name = *self.name();
+        for their_id in pub_ids.into_iter().take(MAX_GOSSIP_NAMES) {
+            let requested = match self.gossip {
+                Some(ref mut gossip) => gossip.take_candidate(their_id.name(), &pub_id),
+                None => false,
+            };
+            if !requested || self.routing_table().need_to_add(their_id.name()).is_err() {
+                continue;
+            }
+            debug!("{:?} Connecting to {}, learned of via table gossip from {}.",
+                   self,
+                   their_id,
+                   pub_id);
+            let src = Authority::ManagedNode(our_name);
+            let dst = Authority::ManagedNode(*their_id.name());
+            if let Err(error) = self.send_connection_info_request(their_id,
+                                                                  src,
+                                                                  dst,
+                                                                  outbox,
+                                                                  ReconnectingPeer::False) {
+                debug!("{:?} - Failed to send connection info to {}: {:?}",
+                       self,
+                       their_id,
+                       error);
+            }
+        }
+    }
+
+    fn send_candidate_approval(&mut self) {
+        let (response_content, new_name) = match self.peer_mgr.verified_candidate_info() {
+            Err(_) => {
+                trace!("{:?} No candidate for which to send CandidateApproval.",
+                       self);
+                return;
+            }
+            Ok(result) => result,
+        };
+
+        if self.we_want_to_merge() || self.they_want_to_merge() {
+            debug!("{:?} Resource proof duration has finished, but not voting to approve \
+                   candidate {} since our section is currently merging.",
+                   self,
+                   new_name);
+            return;
+        }
+
+        let src = Authority::Section(new_name);
+        info!("{:?} Resource proof duration has finished. Voting to approve candidate {}.",
+              self,
+              new_name);
+        trace!("{:?} Sending {:?} to {:?}.", self, response_content, src);
+
+        if let Err(error) = self.send_routing_message(src, src, response_content) {
+            debug!("{:?} Failed sending CandidateApproval: {:?}", self, error);
+        }
+    }
+
+    // Votes for our section to approve a peer we lost recently, which is reconnecting to us after
+    // losing all its routing connections.
+    fn send_rejoin_approval(&mut self, pub_id: PublicId, client_auth: Authority<XorName>) {
+        if !self.our_prefix().matches(pub_id.name()) {
+            debug!("{:?} Not voting to approve {}: it is not in our section.",
+                   self,
+                   pub_id);
+            return;
+        }
+        let content = MessageContent::CandidateApproval {
+            new_public_id: pub_id,
+            new_client_auth: client_auth,
+            sections: self.peer_mgr.ideal_rt(),
+        };
+        let src = Authority::Section(*pub_id.name());
+        info!("{:?} Voting to approve rejoining peer {}.", self, pub_id);
+        if let Err(error) = self.send_routing_message(src, src, content) {
+            debug!("{:?} Failed sending CandidateApproval: {:?}", self, error);
+        }
+    }
+
+    fn decrypt_connection_info(&self,
+                               encrypted_connection_info: &[u8],
+                               nonce: &box_::Nonce,
+                               public_id: &PublicId)
+                               -> Result<PubConnectionInfo, RoutingError> {
+        let decipher_result = box_::open(encrypted_connection_info,
+                                         nonce,
+                                         public_id.encrypting_public_key(),
+                                         self.full_id.encrypting_private_key());
+
+        let serialised_connection_info =
+            decipher_result.map_err(|()| RoutingError::AsymmetricDecryptionFailure)?;
+        Ok(serialisation::deserialise(&serialised_connection_info)?)
     }
 
     fn reset_su_timer(&mut self) {
@@ -2608,6 +4364,11 @@ impl Node {
                          user_msg: UserMessage,
                          priority: u8)
                          -> Result<(), RoutingError> {
+        self.note_sent_request(&src, &user_msg);
+        let user_msg = match self.queue_if_disconnected(src, dst, user_msg, priority)? {
+            Some(user_msg) => user_msg,
+            None => return Ok(()),
+        };
         self.stats.count_user_message(&user_msg);
         for part in user_msg.to_parts(priority)? {
             self.send_routing_message(src, dst, part)?;
@@ -2615,80 +4376,340 @@ impl Node {
         Ok(())
     }
 
+    // Sends a user message whose ID was assigned by `Node::message_id_for`. If the same message
+    // was sent within the retry window, the wire messages sent then are resent unchanged, instead
+    // of signing it anew, so that the recipients' filters recognise it. Returns whether it was
+    // resent.
+    fn send_retry_safe_message(&mut self,
+                               src: Authority<XorName>,
+                               dst: Authority<XorName>,
+                               user_msg: UserMessage,
+                               priority: u8)
+                               -> Result<bool, RoutingError> {
+        let key = sha3_256(&serialisation::serialise(&(src, dst, &user_msg))?);
+        let recent = self.recent_sends.get(&key).cloned();
+        if let Some(sends) = recent {
+            debug!("{:?} Resending {:?} to {:?} as first sent.",
+                   self,
+                   user_msg.message_id(),
+                   dst);
+            for (pub_id, bytes, priority) in sends {
+                self.send_or_drop(&pub_id, bytes, priority);
+            }
+            return Ok(true);
+        }
+
+        self.captured_sends = Some(Vec::new());
+        let result = self.send_user_message(src, dst, user_msg, priority);
+        let sends = self.captured_sends.take().unwrap_or_else(Vec::new);
+        result?;
+        // Messages which are only queued, or signed on behalf of our section, aren't on the wire
+        // yet, so their retries are sent normally.
+        if !sends.is_empty() {
+            let _ = self.recent_sends.insert(key, sends);
+        }
+        Ok(false)
+    }
+
+    /// Sends all messages of a batch, continuing past failures, and returns the destinations the
+    /// messages were and weren't successfully sent to. Each distinct message is only split into
+    /// parts once per priority, however many destinations it is sent to.
+    fn send_user_message_batch(&mut self,
+                               src: Authority<XorName>,
+                               messages: Vec<(Authority<XorName>, UserMessage, u8)>)
+                               -> (Vec<Authority<XorName>>, Vec<Authority<XorName>>) {
+        let mut parts_cache: HashMap<(UserMessage, u8), Vec<MessageContent>> = HashMap::new();
+        let mut succeeded = vec![];
+        let mut failed = vec![];
+        for (dst, user_msg, priority) in messages {
+            self.note_sent_request(&src, &user_msg);
+            let user_msg = match self.queue_if_disconnected(src, dst, user_msg, priority) {
+                Ok(Some(user_msg)) => user_msg,
+                Ok(None) => {
+                    succeeded.push(dst);
+                    continue;
+                }
+                Err(error) => {
+                    debug!("{:?} Failed to queue batched message to {:?}: {:?}",
+                           self,
+                           dst,
+                           error);
+                    failed.push(dst);
+                    continue;
+                }
+            };
+            self.stats.count_user_message(&user_msg);
+            let parts = match parts_cache.entry((user_msg, priority)) {
+                Entry::Occupied(entry) => Ok(entry.get().clone()),
+                Entry::Vacant(entry) => {
+                    match entry.key().0.to_parts(priority) {
+                        Ok(parts) => Ok(entry.insert(parts).clone()),
+                        Err(error) => Err(error),
+                    }
+                }
+            };
+            let result = parts.and_then(|parts| {
+                for part in parts {
+                    self.send_routing_message(src, dst, part)?;
+                }
+                Ok(())
+            });
+            match result {
+                Ok(()) => succeeded.push(dst),
+                Err(error) => {
+                    debug!("{:?} Failed to send batched message to {:?}: {:?}",
+                           self,
+                           dst,
+                           error);
+                    failed.push(dst);
+                }
+            }
+        }
+        (succeeded, failed)
+    }
+
+    /// Returns the total number of bytes that sending `user_msg` from `src` to `dst` would put on
+    /// the wire for the first hop, without sending or signing anything.
+    pub fn estimate_wire_size(&self,
+                              src: Authority<XorName>,
+                              dst: Authority<XorName>,
+                              user_msg: &UserMessage)
+                              -> Result<usize, RoutingError> {
+        let src_sections = self.src_section_lists(&src)?;
+        user_msg.wire_size(src, dst, self.full_id.public_id(), &src_sections)
+    }
+
+    // Returns the lists of the source sections' members, which sign a message from `src`.
+    fn src_section_lists(&self,
+                         src: &Authority<XorName>)
+                         -> Result<Vec<SectionList>, RoutingError> {
+        use routing_table::Authority::*;
+        Ok(match *src {
+            ClientManager(_) | NaeManager(_) | NodeManager(_) | ManagedNode(_) => {
+                let section =
+                    self.routing_table()
+                        .get_section(self.name())
+                        .ok_or(RoutingError::RoutingTable(RoutingTableError::NoSuchPeer))?;
+                let pub_ids = self.peer_mgr.get_pub_ids(section);
+                vec![SectionList::new(*self.our_prefix(), pub_ids)]
+            }
+            Section(_) => {
+                vec![SectionList::new(*self.our_prefix(),
+                                      self.peer_mgr
+                                          .get_pub_ids(self.routing_table().our_section()))]
+            }
+            PrefixSection(ref prefix) => {
+                self.routing_table()
+                    .all_sections()
+                    .into_iter()
+                    .filter_map(|(p, (_, members))| if prefix.is_compatible(&p) {
+                                    Some(SectionList::new(p, self.peer_mgr.get_pub_ids(&members)))
+                                } else {
+                                    None
+                                })
+                    .collect()
+            }
+            Client { .. } => vec![],
+        })
+    }
+
     // Send signed_msg on route. Hop is the name of the peer we received this from, or our name if
     // we are the first sender or the proxy for a client or joining node.
     //
-    // Don't send to any nodes already sent_to.
+    // Don't send to any nodes already sent_to. The `hop_count` is included in the outgoing `Hop`
+    // messages; if it exceeds `max_hop_count`, the message is dropped instead.
     fn send_signed_message(&mut self,
                            signed_msg: &SignedMessage,
                            route: u8,
                            hop: &XorName,
-                           sent_to: &BTreeSet<XorName>)
+                           sent_to: &BTreeSet<XorName>,
+                           hop_count: u8)
                            -> Result<(), RoutingError> {
         let sent_by_us = hop == self.name() && signed_msg.signed_by(self.full_id.public_id());
         if sent_by_us {
             self.stats.count_route(route);
         }
 
+        let msg_hash = self.decision_log
+            .as_ref()
+            .map(|_| decision_log::message_hash(signed_msg.routing_message()));
         let dst = signed_msg.routing_message().dst;
 
-        if let Authority::Client { ref client_id, .. } = dst {
+        if hop_count > self.config.max_hop_count {
+            debug!("{:?} Hop limit exceeded. Dropping {:?}.", self, signed_msg);
+            self.stats.count_hop_limit_drop();
+            self.log_decision(msg_hash, hop, route, &dst, Decision::HopLimitExceeded);
+            return Ok(());
+        }
+
+        if let Authority::Client {
+                   ref client_id,
+                   ref proxy_node_name,
+               } = dst {
             if *self.name() == dst.name() {
                 // This is a message for a client we are the proxy of. Relay it.
-                return self.relay_to_client(signed_msg, client_id);
+                return self.relay_to_client(signed_msg, client_id, hop_count);
             } else if self.in_authority(&dst) {
                 return Ok(()); // Message is for us as a client.
+            } else if self.is_proxy_gone(proxy_node_name) {
+                return self.redirect_to_client(signed_msg, client_id);
             }
         }
 
         let (new_sent_to, target_pub_ids) =
             self.get_targets(signed_msg.routing_message(), route, hop, sent_to)?;
 
+        // The `Hop` message is the same for all directly connected targets, so it is only signed
+        // and serialised once. A received `signed_msg` is embedded as it was received.
+        let mut hop_bytes = None;
+        let mut hops = Vec::new();
         for target_pub_id in target_pub_ids {
-            self.send_signed_msg_to_peer(signed_msg.clone(),
-                                         target_pub_id,
-                                         route,
-                                         new_sent_to.clone())?;
+            if let Some(peer_id) = self.send_signed_msg_to_peer(signed_msg,
+                                                                target_pub_id,
+                                                                route,
+                                                                &new_sent_to,
+                                                                hop_count,
+                                                                &mut hop_bytes)? {
+                hops.push((*target_pub_id.name(), peer_id));
+            }
         }
+        let forwarded = Decision::Forwarded(cmp::min(hops.len(), u8::max_value() as usize) as u8);
+        self.log_decision(msg_hash, hop, route, &dst, forwarded);
+        let reason = if sent_by_us {
+            ForwardingReason::Originated
+        } else {
+            ForwardingReason::Forwarded
+        };
+        self.report_forwarding(signed_msg, hops, reason);
         Ok(())
     }
 
     // Filter, then convert the message to a `Hop` or `TunnelHop` `Message` and serialise.
-    // Send this byte string.
+    // Send this byte string. The serialised `Hop` message is cached in `hop_bytes`, to be reused
+    // for further targets. Returns the peer the message was sent to: the target or the tunnel
+    // node, or `None` if it wasn't sent.
     fn send_signed_msg_to_peer(&mut self,
-                               signed_msg: SignedMessage,
+                               signed_msg: &SignedMessage,
                                target: PublicId,
                                route: u8,
-                               sent_to: BTreeSet<XorName>)
-                               -> Result<(), RoutingError> {
+                               sent_to: &BTreeSet<XorName>,
+                               hop_count: u8,
+                               hop_bytes: &mut Option<Vec<u8>>)
+                               -> Result<Option<PublicId>, RoutingError> {
         let priority = signed_msg.priority();
-        let routing_msg = signed_msg.routing_message().clone();
+        let routing_msg = signed_msg.routing_message();
 
         let (pub_id, bytes) = if self.crust_service.is_connected(&target) {
-            let serialised = self.to_hop_bytes(signed_msg, route, sent_to)?;
-            (target, serialised)
+            if hop_bytes.is_none() {
+                *hop_bytes =
+                    Some(self.to_hop_bytes(signed_msg.clone(), route, sent_to.clone(), hop_count)?);
+            }
+            (target, hop_bytes.clone().unwrap_or_default())
         } else if let Some(&tunnel_id) = self.tunnels.tunnel_for(&target) {
-            let serialised = self.to_tunnel_hop_bytes(signed_msg, route, sent_to, target)?;
+            let serialised = self.to_tunnel_hop_bytes(signed_msg.clone(),
+                                                      route,
+                                                      sent_to.clone(),
+                                                      hop_count,
+                                                      target)?;
             (tunnel_id, serialised)
         } else {
             trace!("{:?} Not connected or tunnelling to {:?}. Dropping peer.",
                    self,
                    target);
             self.disconnect_peer(&target, None);
-            return Ok(());
+            return Ok(None);
         };
-        if !self.filter_outgoing_routing_msg(&routing_msg, &target, route) {
+        if self.filter_outgoing_routing_msg(routing_msg, &target, route) {
+            return Ok(None);
+        }
+        if !self.config.decisions_only {
+            if let Some(ref mut captured) = self.captured_sends {
+                captured.push((pub_id, bytes.clone(), priority));
+            }
             self.send_or_drop(&pub_id, bytes, priority);
         }
-        Ok(())
+        Ok(Some(pub_id))
+    }
+
+    // Hands the peers chosen for `signed_msg` to the forwarding sink, if any.
+    fn report_forwarding(&self,
+                         signed_msg: &SignedMessage,
+                         chosen_hops: Vec<(XorName, PublicId)>,
+                         reason: ForwardingReason) {
+        if let Some(ref sink) = self.forwarding_sink {
+            let routing_msg = signed_msg.routing_message();
+            sink.record(ForwardingDecision {
+                            message_hash: decision_log::message_hash(routing_msg),
+                            destination: routing_msg.dst,
+                            chosen_hops: chosen_hops,
+                            reason: reason,
+                        });
+        }
+    }
+
+    // Records a decision taken for the message with the given hash in the decision log. The hash
+    // is only computed if the log is enabled, and is `None` otherwise.
+    fn log_decision(&mut self,
+                    msg_hash: Option<u64>,
+                    peer: &XorName,
+                    route: u8,
+                    dst: &Authority<XorName>,
+                    decision: Decision) {
+        if let (Some(log), Some(msg_hash)) = (self.decision_log.as_mut(), msg_hash) {
+            log.record(msg_hash, peer, route, dst, decision);
+        }
+    }
+
+    /// Returns the serialised decision log, which is empty if the log isn't enabled.
+    pub fn dump_decision_log(&self) -> Vec<u8> {
+        self.decision_log
+            .as_ref()
+            .map_or_else(|| DecisionLog::new(0).dump(), DecisionLog::dump)
+    }
+
+    // Returns a random value. With mock Crust it is drawn from our own seeded RNG, so that
+    // replaying our inputs reproduces it.
+    #[cfg(feature = "use-mock-crust")]
+    fn random<T: Rand>(&mut self) -> T {
+        self.rng.gen()
+    }
+
+    #[cfg(not(feature = "use-mock-crust"))]
+    fn random<T: Rand>(&mut self) -> T {
+        rand::random()
+    }
+
+    // Returns whether the given proxy node is no longer in the network: it would belong to our
+    // section, but isn't in our routing table.
+    fn is_proxy_gone(&self, proxy_name: &XorName) -> bool {
+        self.is_approved && self.our_prefix().matches(proxy_name) &&
+        !self.routing_table().has(proxy_name)
+    }
+
+    // Sends a message for a client whose proxy node is gone to the client's `ClientManager`s,
+    // which pass it on to the client's current proxy node, if any.
+    fn redirect_to_client(&mut self,
+                          signed_msg: &SignedMessage,
+                          client_id: &PublicId)
+                          -> Result<(), RoutingError> {
+        debug!("{:?} Proxy node of client {:?} not found. Redirecting {:?}.",
+               self,
+               client_id,
+               signed_msg);
+        let src = Authority::ManagedNode(*self.name());
+        let dst = Authority::ClientManager(*client_id.name());
+        let content = MessageContent::RedirectToClient(Box::new(signed_msg.clone()));
+        self.send_routing_message(src, dst, content)
     }
 
     // Wraps the signed message in a `HopMessage` and sends it on.
     //
-    // In the case that the `pub_id` is unknown, an ack is sent and the message dropped.
+    // In the case that the `pub_id` is unknown, an ack is sent, the message dropped and its source
+    // told that the client is unreachable.
     fn relay_to_client(&mut self,
                        signed_msg: &SignedMessage,
-                       pub_id: &PublicId)
+                       pub_id: &PublicId,
+                       hop_count: u8)
                        -> Result<(), RoutingError> {
         let priority = signed_msg.priority();
 
@@ -2699,10 +4720,14 @@ impl Node {
             let hop_msg = HopMessage::new(signed_msg.clone(),
                                           0,
                                           BTreeSet::new(),
+                                          hop_count,
                                           self.full_id.signing_private_key())?;
-            let message = Message::Hop(hop_msg);
-            let raw_bytes = serialisation::serialise(&message)?;
-            self.send_or_drop(pub_id, raw_bytes, priority);
+            if !self.config.decisions_only {
+                self.send_or_drop(pub_id, hop_msg.into_bytes()?, priority);
+            }
+            self.report_forwarding(signed_msg,
+                                   vec![(*pub_id.name(), *pub_id)],
+                                   ForwardingReason::RelayedToClient);
             Ok(())
         } else {
             // Acknowledge the message so that the sender doesn't retry.
@@ -2711,10 +4736,28 @@ impl Node {
             debug!("{:?} Client connection not found for message {:?}.",
                    self,
                    signed_msg);
+            self.report_unreachable_client(signed_msg.routing_message(), pub_id);
             Err(RoutingError::ClientConnectionNotFound)
         }
     }
 
+    // Tells the source of a user message which we couldn't relay that the client is gone. Only
+    // the first part of each message is reported, so that the source hears about it once.
+    fn report_unreachable_client(&mut self, routing_msg: &RoutingMessage, client_id: &PublicId) {
+        match routing_msg.content {
+            MessageContent::UserMessagePart { part_index: 0, .. } => (),
+            _ => return,
+        }
+        let src = Authority::ManagedNode(*self.name());
+        let content = MessageContent::ClientUnreachable(*client_id);
+        if let Err(error) = self.send_routing_message(src, routing_msg.src, content) {
+            debug!("{:?} Failed to report unreachable client {:?}: {:?}",
+                   self,
+                   client_id,
+                   error);
+        }
+    }
+
     /// Returns the peer that is responsible for collecting signatures to verify a message; this
     /// may be us or another node. If our signature is not required, this returns `None`.
     fn get_signature_target(&self, src: &Authority<XorName>, route: u8) -> Option<XorName> {
@@ -2724,7 +4767,7 @@ impl Node {
                 let mut v = self.routing_table()
                     .our_section()
                     .iter()
-                    .sorted_by(|&lhs, &rhs| src.name().cmp_distance(lhs, rhs));
+                    .sorted_by(|&lhs, &rhs| src.name().cmp_closeness(lhs, rhs));
                 v.truncate(self.min_section_size());
                 v
             }
@@ -2732,14 +4775,14 @@ impl Node {
                 self.routing_table()
                     .our_section()
                     .iter()
-                    .sorted_by(|&lhs, &rhs| src.name().cmp_distance(lhs, rhs))
+                    .sorted_by(|&lhs, &rhs| src.name().cmp_closeness(lhs, rhs))
             }
             PrefixSection(ref pfx) => {
                 self.routing_table()
                     .iter()
                     .filter(|name| pfx.matches(name))
                     .chain(iter::once(self.name()))
-                    .sorted_by(|&lhs, &rhs| src.name().cmp_distance(lhs, rhs))
+                    .sorted_by(|&lhs, &rhs| src.name().cmp_closeness(lhs, rhs))
             }
             ManagedNode(_) | Client { .. } => return Some(*self.name()),
         };
@@ -2773,6 +4816,7 @@ impl Node {
                 .into_iter()
                 .filter(|target| !sent_to.contains(target))
                 .collect();
+            let targets = self.cap_group_fanout(&routing_msg.dst, targets);
             let new_sent_to =
                 if self.in_authority(&routing_msg.dst) {
                     sent_to.iter()
@@ -2818,17 +4862,45 @@ impl Node {
         }
     }
 
+    /// If `dst` is a close group we are not a member of, returns only the `group_fanout` targets
+    /// closest to it, so that retries take the same path as long as our routing table doesn't
+    /// change. The members we skip are reached by the ones we send to, as they pass the message on
+    /// within the group. Within the group, the message is sent to all the targets.
+    fn cap_group_fanout(&self,
+                        dst: &Authority<XorName>,
+                        targets: BTreeSet<XorName>)
+                        -> BTreeSet<XorName> {
+        if self.in_authority(dst) {
+            return targets;
+        }
+        match *dst {
+            Authority::ClientManager(ref name) |
+            Authority::NaeManager(ref name) |
+            Authority::NodeManager(ref name) if targets.len() > self.config.group_fanout => {
+                targets
+                    .into_iter()
+                    .sorted_by(|lhs, rhs| name.cmp_closeness(lhs, rhs))
+                    .into_iter()
+                    .take(self.config.group_fanout)
+                    .collect()
+            }
+            _ => targets,
+        }
+    }
+
     // Wrap the `signed_msg` with a `HopMessage`, then wrap that with `Message::TunnelHop`.
     // Serialise the result to a byte string.
     fn to_tunnel_hop_bytes(&self,
                            signed_msg: SignedMessage,
                            route: u8,
                            sent_to: BTreeSet<XorName>,
+                           hop_count: u8,
                            dst: PublicId)
                            -> Result<Vec<u8>, RoutingError> {
         let hop_msg = HopMessage::new(signed_msg,
                                       route,
                                       sent_to,
+                                      hop_count,
                                       self.full_id.signing_private_key())?;
         let message = Message::TunnelHop {
             content: hop_msg,
@@ -2840,34 +4912,76 @@ impl Node {
     }
 
     fn process_connection(&mut self, pub_id: PublicId, outbox: &mut EventBox) {
-        if self.peer_mgr
-               .get_peer(&pub_id)
-               .map_or(false, |peer| peer.valid()) {
+        let valid = self.peer_mgr
+            .get_peer(&pub_id)
+            .map_or(false, |peer| peer.valid());
+        if valid {
             self.add_to_routing_table(&pub_id, outbox);
         }
 
-        if self.is_approved {
+        // A peer we don't know as valid needs to identify itself. If we're not approved yet, we
+        // need to identify ourselves, and the challenge prompts the peer to send us one, in case
+        // it doesn't process this connection itself, e.g. because we requested the tunnel.
+        if !valid || !self.is_approved {
+            self.send_identify_challenge(pub_id);
+        }
+    }
+
+    // Sends the peer a fresh nonce to sign in its `ClientIdentify` or `CandidateIdentify`, unless
+    // it still has to answer an earlier one.
+    fn send_identify_challenge(&mut self, pub_id: PublicId) {
+        if self.identify_nonces.contains_key(&pub_id) {
             return;
         }
+        let nonce = self.random();
+        let _ = self.identify_nonces.insert(pub_id, nonce);
+        self.send_direct_message(pub_id, DirectMessage::IdentifyChallenge(nonce));
+    }
+
+    fn handle_identify_challenge(&mut self, pub_id: PublicId, nonce: u64) {
+        if self.recovery_contact() == Some((pub_id, false)) {
+            self.send_rejoin_identify(pub_id, nonce);
+        } else if !self.is_approved {
+            self.send_candidate_identify(pub_id, nonce);
+        } else if self.peer_mgr
+                      .get_peer(&pub_id)
+                      .map_or(false, |peer| !peer.valid()) {
+            // A candidate wants to identify itself to us.
+            self.send_identify_challenge(pub_id);
+        }
+    }
 
-        // If we're not approved yet, we need to identify ourselves
-        // with our old and new ids via CandidateIdentify
-        // Serialise the old and new `PublicId`s and sign this using the old key.
+    fn send_candidate_identify(&mut self, pub_id: PublicId, nonce: u64) {
+        // We're not approved yet, so we need to identify ourselves with our old and new IDs via
+        // `CandidateIdentify`. Serialise the old and new `PublicId`s together with the nonce and
+        // sign this using the old key.
         let msg = {
             let old_and_new_pub_ids = (self.old_full_id.public_id(), self.full_id.public_id());
-            let mut to_sign = match serialisation::serialise(&old_and_new_pub_ids) {
+            let mut to_sign = match serialisation::serialise(&old_and_new_pub_ids)
+                      .map_err(RoutingError::from)
+                      .and_then(|serialised| identify_signed_bytes(serialised, nonce)) {
                 Ok(result) => result,
                 Err(error) => {
                     error!("Failed to serialise public IDs: {:?}", error);
                     return;
                 }
             };
-            let signature_using_old = sign::sign_detached(&to_sign,
-                                                          self.old_full_id.signing_private_key());
+            let signature_using_old = match self.old_full_id.signing_private_key().sign(&to_sign) {
+                Ok(signature) => signature,
+                Err(error) => {
+                    error!("{:?} Failed to sign public IDs: {:?}", self, error);
+                    return;
+                }
+            };
             // Append this signature onto the serialised IDs and sign that using the new key.
             to_sign.extend_from_slice(&signature_using_old.0);
-            let signature_using_new = sign::sign_detached(&to_sign,
-                                                          self.full_id.signing_private_key());
+            let signature_using_new = match self.full_id.signing_private_key().sign(&to_sign) {
+                Ok(signature) => signature,
+                Err(error) => {
+                    error!("{:?} Failed to sign public IDs: {:?}", self, error);
+                    return;
+                }
+            };
             let proxy_node_name = if let Some(proxy_node_name) = self.peer_mgr.get_proxy_name() {
                 *proxy_node_name
             } else {
@@ -2901,9 +5015,15 @@ impl Node {
                                     outbox: &mut EventBox,
                                     reconnecting: ReconnectingPeer)
                                     -> Result<(), RoutingError> {
+        if self.peer_mgr.skip_own_id(&their_public_id) {
+            return Ok(());
+        }
         let their_name = *their_public_id.name();
         self.peer_mgr.allow_connect(&their_name)?;
-
+        if self.peer_mgr.is_banned(&their_name) {
+            debug!("{:?} Not connecting to banned peer {}.", self, their_public_id);
+            return Err(RoutingError::InvalidPeer);
+        }
 
         if self.peer_mgr.is_client(&their_public_id) ||
            self.peer_mgr.is_joining_node(&their_public_id) ||
@@ -2928,10 +5048,30 @@ impl Node {
             return Ok(());
         }
 
+        let is_new_attempt = match self.peer_mgr.get_peer(&their_public_id).map(Peer::state) {
+            None |
+            Some(&PeerState::SearchingForTunnel) => true,
+            Some(_) => false,
+        };
+        if is_new_attempt && !self.connect_slot_free() {
+            if self.queued_connects
+                   .iter()
+                   .all(|&(pub_id, ..)| pub_id != their_public_id) {
+                trace!("{:?} Queueing connection attempt to {:?}.", self, their_name);
+                self.queued_connects
+                    .push_back((their_public_id, src, dst, reconnecting));
+            }
+            return Ok(());
+        }
+
         // This will insert the peer if peer is not in peer_mgr and flag them to `valid`
         if let Some(token) = self.peer_mgr
                .get_connection_token(src, dst, their_public_id, reconnecting) {
             self.crust_service.prepare_connection_info(token);
+            let _ = self.connects_in_flight.insert(their_public_id);
+            if self.config.connect_spacing > Duration::from_secs(0) {
+                self.connect_spacing_token = Some(self.timer.schedule(self.config.connect_spacing));
+            }
             return Ok(());
         }
 
@@ -2956,6 +5096,65 @@ impl Node {
         Ok(())
     }
 
+    /// Returns whether connection pacing allows initiating another outgoing connection attempt.
+    fn connect_slot_free(&mut self) -> bool {
+        if self.connect_spacing_token.is_some() {
+            return false;
+        }
+        let max = match self.config.max_connects_in_flight {
+            Some(max) => max,
+            None => return true,
+        };
+        self.connects_in_flight = self.connects_in_flight
+            .iter()
+            .filter(|pub_id| self.is_connecting(pub_id))
+            .cloned()
+            .collect();
+        self.connects_in_flight.len() < max
+    }
+
+    /// Returns the number of outgoing connection attempts still in flight.
+    fn connect_count(&self) -> usize {
+        self.connects_in_flight
+            .iter()
+            .filter(|pub_id| self.is_connecting(pub_id))
+            .count()
+    }
+
+    fn is_connecting(&self, pub_id: &PublicId) -> bool {
+        match self.peer_mgr.get_peer(pub_id).map(Peer::state) {
+            Some(&PeerState::ConnectionInfoPreparing { .. }) |
+            Some(&PeerState::ConnectionInfoReady(_)) |
+            Some(&PeerState::CrustConnecting) => true,
+            _ => false,
+        }
+    }
+
+    /// Initiates queued connection attempts for as long as connection pacing allows.
+    fn send_queued_connects(&mut self, outbox: &mut EventBox) {
+        while !self.queued_connects.is_empty() && self.connect_slot_free() {
+            let (pub_id, src, dst, reconnecting) = match self.queued_connects.pop_front() {
+                Some(queued) => queued,
+                None => break,
+            };
+            if let Err(error) =
+                self.send_connection_info_request(pub_id, src, dst, outbox, reconnecting) {
+                debug!("{:?} - Failed to send queued connection info to {:?}: {:?}",
+                       self,
+                       pub_id,
+                       error);
+            }
+        }
+    }
+
+    /// Records a routing peer whose connection we lost without disconnecting it ourselves, so that
+    /// it may rejoin our section if it lost all its routing connections.
+    fn remember_lost_peer(&mut self, pub_id: &PublicId) {
+        if self.peer_mgr.is_routing_peer(pub_id) && !self.peer_mgr.is_banned(pub_id.name()) {
+            self.recent_peers.insert(*pub_id);
+        }
+    }
+
     /// Handles dropped peer with the given ID. Returns true if we should keep running, false if
     /// we should terminate.
     fn dropped_peer(&mut self,
@@ -2963,6 +5162,12 @@ impl Node {
                     outbox: &mut EventBox,
                     mut try_reconnect: bool)
                     -> bool {
+        let _ = self.identify_nonces.remove(pub_id);
+        self.pings.forget(pub_id);
+        self.wire_versions.forget(pub_id.name());
+        if let Some(ref mut gossip) = self.gossip {
+            gossip.forget(pub_id);
+        }
         let (peer, removal_result) = match self.peer_mgr.remove_peer(pub_id) {
             Some(result) => result,
             None => return true,
@@ -2972,6 +5177,7 @@ impl Node {
             if !self.dropped_routing_node(peer.name(), removal_details, outbox) {
                 return false;
             }
+            self.check_wire_upgrade(outbox);
         }
 
         match *peer.state() {
@@ -2986,7 +5192,10 @@ impl Node {
             PeerState::Proxy => {
                 debug!("{:?} Lost bootstrap connection to {:?}.", self, peer);
 
-                if self.routing_table().len() < self.min_section_size() - 1 {
+                if self.recovery_contact().map_or(false, |(contact, _)| contact == *pub_id) {
+                    self.set_recovery_contact(None);
+                } else if self.recovery.is_none() &&
+                          self.routing_table().len() < self.min_section_size() - 1 {
                     outbox.send_event(Event::Terminate);
                     return false;
                 }
@@ -3029,6 +5238,12 @@ impl Node {
 
         if self.is_approved {
             outbox.send_event(Event::NodeLost(details.name, self.routing_table().clone()));
+            if self.our_prefix().matches(&details.name) {
+                self.note_churn();
+                self.report_lost_member(details.name, outbox);
+            }
+            self.update_health(outbox);
+            self.update_convergence();
         }
 
         self.merge_if_necessary(outbox);
@@ -3046,14 +5261,254 @@ impl Node {
         if self.routing_table().is_empty() {
             debug!("{:?} Lost all routing connections.", self);
             if !self.is_first_node {
-                outbox.send_event(Event::RestartRequired);
-                return false;
+                match self.config.disconnected_queue_limit {
+                    Some(queue_limit) if self.is_approved => {
+                        self.start_recovery(queue_limit, outbox)
+                    }
+                    _ => {
+                        outbox.send_event(Event::RestartRequired);
+                        return false;
+                    }
+                }
             }
         }
 
         true
     }
 
+    /// Enters recovery after losing all routing connections: we stop acting for any section, queue
+    /// user messages, and bootstrap off our configured contacts to reconnect to the peers we lost.
+    fn start_recovery(&mut self, queue_limit: usize, outbox: &mut EventBox) {
+        if self.recovery.is_some() {
+            return;
+        }
+        let known_peers = self.recent_peers.pub_ids();
+        info!("{:?} Disconnected. Trying to rejoin our section via {} recently known peers.",
+              self,
+              known_peers.len());
+        self.recovery = Some(Recovery::new(known_peers, queue_limit));
+        // We will miss the churn in our section while disconnected.
+        self.peer_generations.clear();
+        outbox.send_event(Event::Disconnected);
+        self.rebootstrap();
+    }
+
+    fn rebootstrap(&mut self) {
+        if let Err(error) = self.crust_service
+               .start_bootstrap(HashSet::new(), CrustUser::Node) {
+            debug!("{:?} Failed to start bootstrapping: {:?}", self, error);
+        }
+        let retry = Duration::from_secs(RECOVERY_RETRY_SECS);
+        self.recovery_timer_token = Some(self.timer.schedule(retry));
+    }
+
+    fn retry_recovery(&mut self, outbox: &mut EventBox) {
+        if self.recovery.is_none() {
+            return;
+        }
+        match self.recovery_contact() {
+            Some((contact_id, true)) => {
+                let retry = Duration::from_secs(RECOVERY_RETRY_SECS);
+                self.recovery_timer_token = Some(self.timer.schedule(retry));
+                self.send_rejoin_requests(contact_id, outbox);
+            }
+            Some((contact_id, false)) => {
+                debug!("{:?} Bootstrap contact {} didn't accept us. Bootstrapping again.",
+                       self,
+                       contact_id);
+                self.handle_rejoin_denied(contact_id);
+                self.rebootstrap();
+            }
+            None => self.rebootstrap(),
+        }
+    }
+
+    // Returns the bootstrap contact we are rejoining through, and whether it accepted us.
+    fn recovery_contact(&self) -> Option<(PublicId, bool)> {
+        self.recovery.as_ref().and_then(Recovery::contact)
+    }
+
+    fn set_recovery_contact(&mut self, contact: Option<(PublicId, bool)>) {
+        if let Some(ref mut recovery) = self.recovery {
+            recovery.set_contact(contact);
+        }
+    }
+
+    // Identifies ourselves to our bootstrap contact as a node, so that it relays our connection
+    // info requests to the peers we lost.
+    fn send_rejoin_identify(&mut self, pub_id: PublicId, nonce: u64) {
+        let serialised_public_id = match serialisation::serialise(self.full_id.public_id()) {
+            Ok(rslt) => rslt,
+            Err(error) => {
+                error!("{:?} Failed to serialise public ID: {:?}", self, error);
+                return;
+            }
+        };
+        let signed_bytes = match identify_signed_bytes(serialised_public_id.clone(), nonce) {
+            Ok(bytes) => bytes,
+            Err(error) => {
+                error!("{:?} Failed to serialise nonce: {:?}", self, error);
+                return;
+            }
+        };
+        let signature = match self.full_id.signing_private_key().sign(&signed_bytes) {
+            Ok(signature) => signature,
+            Err(error) => {
+                error!("{:?} Failed to sign nonce: {:?}", self, error);
+                return;
+            }
+        };
+        self.send_direct_message(pub_id,
+                                 DirectMessage::ClientIdentify {
+                                     serialised_public_id: serialised_public_id,
+                                     signature: signature,
+                                     client_restriction: false,
+                                 });
+    }
+
+    fn handle_rejoin_accepted(&mut self, pub_id: PublicId, outbox: &mut EventBox) {
+        if self.recovery_contact() != Some((pub_id, false)) {
+            debug!("{:?} Unexpected BootstrapIdentify from {}.", self, pub_id);
+            return;
+        }
+        self.set_recovery_contact(Some((pub_id, true)));
+        self.peer_mgr
+            .insert_peer(Peer::new(pub_id, PeerState::Proxy, false, ReconnectingPeer::False));
+        self.send_rejoin_requests(pub_id, outbox);
+    }
+
+    fn handle_rejoin_denied(&mut self, pub_id: PublicId) {
+        if !self.recovery_contact().map_or(false, |(contact, _)| contact == pub_id) {
+            debug!("{:?} Unexpected BootstrapDeny from {}.", self, pub_id);
+            return;
+        }
+        self.set_recovery_contact(None);
+        debug!("{:?} Bootstrap contact {} denied us. Retrying later.", self, pub_id);
+        let _ = self.peer_mgr.remove_peer(&pub_id);
+        let _ = self.crust_service.disconnect(pub_id);
+    }
+
+    // Sends connection info requests via our bootstrap contact to the peers we lost. As they still
+    // remember us, they vote for our section to approve us again.
+    fn send_rejoin_requests(&mut self, contact_id: PublicId, outbox: &mut EventBox) {
+        let known_peers = match self.recovery {
+            Some(ref recovery) => recovery.known_peers().to_vec(),
+            None => return,
+        };
+        let src = Authority::Client {
+            client_id: *self.full_id.public_id(),
+            proxy_node_name: *contact_id.name(),
+        };
+        for pub_id in known_peers {
+            // The contact itself only knows us as a joining node while it acts as our proxy.
+            if pub_id == contact_id || self.peer_mgr.is_routing_peer(&pub_id) {
+                continue;
+            }
+            let dst = Authority::ManagedNode(*pub_id.name());
+            if let Err(error) = self.send_connection_info_request(pub_id,
+                                                                  src,
+                                                                  dst,
+                                                                  outbox,
+                                                                  ReconnectingPeer::False) {
+                debug!("{:?} - Failed to send connection info to {}: {:?}",
+                       self,
+                       pub_id,
+                       error);
+            }
+        }
+    }
+
+    /// Leaves recovery once we are connected to a quorum of our section again, and sends the user
+    /// messages queued in the meantime.
+    fn finish_recovery_if_rejoined(&mut self, outbox: &mut EventBox) {
+        let rejoined = self.recovery.is_some() &&
+                       self.routing_table().our_section().len() * QUORUM_DENOMINATOR >
+                       self.min_section_size() * QUORUM_NUMERATOR;
+        if !rejoined {
+            return;
+        }
+        let recovery = unwrap!(self.recovery.take());
+        self.recovery_timer_token = None;
+        info!("{:?} Rejoined our section after {}. Sending {} queued messages.",
+              self,
+              recovery.duration().display_secs(),
+              recovery.queued_count());
+        outbox.send_event(Event::Reconnected);
+        let contact = recovery
+            .contact()
+            .map(|(contact_id, _)| (contact_id, recovery.known_peers().contains(&contact_id)));
+        for QueuedMessage {
+                src,
+                dst,
+                content,
+                priority,
+            } in recovery.into_queued() {
+            if let Err(error) = self.send_user_message(src, dst, content, priority) {
+                debug!("{:?} Failed to send queued message to {:?}: {:?}",
+                       self,
+                       dst,
+                       error);
+            }
+        }
+        if let Some((contact_id, was_routing_peer)) = contact {
+            self.replace_recovery_contact(contact_id, was_routing_peer, outbox);
+        }
+    }
+
+    // Closes the bootstrap connection to our contact, and reconnects to it as a routing peer if it
+    // is one of the peers we lost.
+    fn replace_recovery_contact(&mut self,
+                                contact_id: PublicId,
+                                was_routing_peer: bool,
+                                outbox: &mut EventBox) {
+        if !self.peer_mgr.is_proxy(&contact_id) {
+            return;
+        }
+        debug!("{:?} Disconnecting bootstrap contact {}.", self, contact_id);
+        let _ = self.peer_mgr.remove_peer(&contact_id);
+        let _ = self.crust_service.disconnect(contact_id);
+        if !was_routing_peer {
+            return;
+        }
+        let src = Authority::ManagedNode(*self.name());
+        let dst = Authority::ManagedNode(*contact_id.name());
+        if let Err(error) = self.send_connection_info_request(contact_id,
+                                                              src,
+                                                              dst,
+                                                              outbox,
+                                                              ReconnectingPeer::False) {
+            debug!("{:?} - Failed to send connection info to {}: {:?}",
+                   self,
+                   contact_id,
+                   error);
+        }
+    }
+
+    // Queues the user message if we are disconnected and trying to rejoin our section, failing
+    // if the queue is full. Returns the message back if we are connected.
+    fn queue_if_disconnected(&mut self,
+                             src: Authority<XorName>,
+                             dst: Authority<XorName>,
+                             user_msg: UserMessage,
+                             priority: u8)
+                             -> Result<Option<UserMessage>, RoutingError> {
+        let recovery = match self.recovery {
+            Some(ref mut recovery) => recovery,
+            None => return Ok(Some(user_msg)),
+        };
+        let queued = recovery.queue(QueuedMessage {
+                                        src: src,
+                                        dst: dst,
+                                        content: user_msg,
+                                        priority: priority,
+                                    });
+        if queued {
+            Ok(None)
+        } else {
+            Err(RoutingError::Interface(InterfaceError::NotConnected))
+        }
+    }
+
     fn send_section_split(&mut self,
                           our_ver_pfx: VersionedPrefix<XorName>,
                           joining_node: XorName) {
@@ -3169,6 +5624,13 @@ impl Node {
         }
     }
 
+    /// Records a change to our section: connection info messages created before it are stale.
+    fn note_churn(&mut self) {
+        self.churn_generation += 1;
+        let our_prefix = *self.our_prefix();
+        self.peer_generations.churned(&our_prefix);
+    }
+
     fn our_prefix(&self) -> &Prefix<XorName> {
         self.routing_table().our_prefix()
     }
@@ -3183,9 +5645,16 @@ impl Base for Node {
         &self.full_id
     }
 
+    fn encode_for(&self, pub_id: &PublicId, bytes: Vec<u8>) -> Vec<u8> {
+        self.wire_versions.encode(pub_id.name(), bytes)
+    }
+
     fn in_authority(&self, auth: &Authority<XorName>) -> bool {
         if let Authority::Client { ref client_id, .. } = *auth {
             client_id == self.full_id.public_id()
+        } else if self.recovery.is_some() {
+            // Until we rejoined our section, our routing table is incomplete.
+            false
         } else {
             self.is_proper() && self.routing_table().in_authority(auth)
         }
@@ -3203,6 +5672,7 @@ impl Base for Node {
         self.dropped_tunnel_client(&pub_id);
         self.dropped_tunnel_node(&pub_id, outbox);
 
+        self.remember_lost_peer(&pub_id);
         if self.dropped_peer(&pub_id, outbox, true) {
             Transition::Stay
         } else {
@@ -3231,6 +5701,17 @@ impl Node {
         self.timer.get_timed_out_tokens()
     }
 
+    pub fn pending_work(&self, horizon: Duration) -> PendingWork {
+        PendingWork {
+            queued_events: 0,
+            unacked_msgs: self.ack_mgr.pending_count(),
+            connects_in_flight: self.connect_count(),
+            queued_connects: self.queued_connects.len(),
+            timer_due: self.timer.has_deadline_within(horizon),
+            joining: false,
+        }
+    }
+
     pub fn section_list_signatures(&self,
                                    prefix: Prefix<XorName>)
                                    -> Result<BTreeMap<PublicId, sign::Signature>, RoutingError> {
@@ -3251,6 +5732,43 @@ impl Node {
     pub fn set_next_relocation_interval(&mut self, interval: (XorName, XorName)) {
         self.next_relocation_interval = Some(interval);
     }
+
+    /// Feeds `bytes` to this node as a message from the peer at `endpoint`, as if it had been
+    /// received via Crust. Returns `false` if we aren't connected to that endpoint.
+    pub fn inject_message(&mut self, endpoint: Endpoint, bytes: Vec<u8>) -> bool {
+        self.crust_service.inject_message(endpoint, bytes)
+    }
+
+    /// Returns the errors from handling received messages since the last call, oldest first.
+    pub fn take_message_errors(&mut self) -> Vec<RoutingError> {
+        self.message_errors.drain(..).collect()
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.recovery.is_some()
+    }
+
+    pub fn forget_peer(&mut self, name: &XorName) {
+        if let Some(pub_id) = self.peer_mgr.get_pub_id(name).cloned() {
+            let _ = self.peer_mgr.remove_peer(&pub_id);
+        }
+    }
+
+    pub fn send_forged_connection_info_request(&mut self, claimed_id: PublicId, dst: XorName) {
+        let content = MessageContent::ConnectionInfoRequest {
+            encrypted_conn_info: vec![],
+            nonce: [0; box_::NONCEBYTES],
+            pub_id: claimed_id,
+            msg_id: MessageId::new(),
+            generation: self.churn_generation,
+        };
+        let src = Authority::ManagedNode(*self.name());
+        if let Err(error) = self.send_routing_message(src, Authority::ManagedNode(dst), content) {
+            debug!("{:?} Failed to send forged connection info request: {:?}",
+                   self,
+                   error);
+        }
+    }
 }
 
 impl Bootstrapped for Node {
@@ -3280,42 +5798,16 @@ impl Bootstrapped for Node {
                    routing_msg);
             return Ok(());
         }
-        if !self.add_to_pending_acks(&routing_msg, route) {
+        if self.did_receive_ack(&routing_msg) {
             debug!("{:?} already received an ack for {:?} - so not resending it.",
                    self,
                    routing_msg);
             return Ok(());
         }
-        use routing_table::Authority::*;
-        let sending_names = match routing_msg.src {
-            ClientManager(_) | NaeManager(_) | NodeManager(_) | ManagedNode(_) => {
-                let section =
-                    self.routing_table()
-                        .get_section(self.name())
-                        .ok_or(RoutingError::RoutingTable(RoutingTableError::NoSuchPeer))?;
-                let pub_ids = self.peer_mgr.get_pub_ids(section);
-                vec![SectionList::new(*self.our_prefix(), pub_ids)]
-            }
-            Section(_) => {
-                vec![SectionList::new(*self.our_prefix(),
-                                      self.peer_mgr
-                                          .get_pub_ids(self.routing_table().our_section()))]
-            }
-            PrefixSection(ref prefix) => {
-                self.routing_table()
-                    .all_sections()
-                    .into_iter()
-                    .filter_map(|(p, (_, members))| if prefix.is_compatible(&p) {
-                                    Some(SectionList::new(p, self.peer_mgr.get_pub_ids(&members)))
-                                } else {
-                                    None
-                                })
-                    .collect()
-            }
-            Client { .. } => vec![],
-        };
-
+        let sending_names = self.src_section_lists(&routing_msg.src)?;
+        // Only expect an ack once signed, so that a message we fail to sign isn't resent.
         let signed_msg = SignedMessage::new(routing_msg, &self.full_id, sending_names)?;
+        let _ = self.add_to_pending_acks(signed_msg.routing_message(), route);
 
         match self.get_signature_target(&signed_msg.routing_message().src, route) {
             None => Ok(()),
@@ -3325,9 +5817,9 @@ impl Bootstrapped for Node {
                     self.sig_accumulator
                         .add_message(signed_msg, min_section_size, route) {
                     if self.in_authority(&msg.routing_message().dst) {
-                        self.handle_signed_message(msg, route, our_name, &BTreeSet::new())?;
+                        self.handle_signed_message(msg, route, our_name, &BTreeSet::new(), 0)?;
                     } else {
-                        self.send_signed_message(&msg, route, &our_name, &BTreeSet::new())?;
+                        self.send_signed_message(&msg, route, &our_name, &BTreeSet::new(), 0)?;
                     }
                 }
                 Ok(())
@@ -3361,15 +5853,72 @@ impl Debug for Node {
     }
 }
 
+// Returns whether a peer which bootstrapped off us as `kind` may identify itself with the given
+// client restriction: a client must be restricted, a node must not.
+fn identify_matches_bootstrap(kind: CrustUser, client_restriction: bool) -> bool {
+    match kind {
+        CrustUser::Client => client_restriction,
+        CrustUser::Node => !client_restriction,
+    }
+}
+
 // Verify the serialised public id against the signature.
 fn verify_signed_public_id(serialised_public_id: &[u8],
+                           nonce: u64,
                            signature: &sign::Signature)
                            -> Result<PublicId, RoutingError> {
     let public_id: PublicId = serialisation::deserialise(serialised_public_id)?;
     let public_key = public_id.signing_public_key();
-    if sign::verify_detached(signature, serialised_public_id, public_key) {
+    let signed_bytes = identify_signed_bytes(serialised_public_id.to_vec(), nonce)?;
+    if public_key.verify(signature, &signed_bytes) {
         Ok(public_id)
     } else {
         Err(RoutingError::FailedSignature)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{identify_matches_bootstrap, verify_signed_public_id};
+    use crust::CrustUser;
+    use id::FullId;
+    use maidsafe_utilities::serialisation::serialise;
+    use messages::identify_signed_bytes;
+    use rust_sodium::crypto::sign;
+
+    #[test]
+    fn client_identify_bound_to_nonce() {
+        let full_id = FullId::new();
+        let serialised_public_id = unwrap!(serialise(full_id.public_id()));
+        let nonce = 42;
+        let signed_bytes = unwrap!(identify_signed_bytes(serialised_public_id.clone(), nonce));
+        let signature = sign::sign_detached(&signed_bytes, full_id.signing_private_key());
+
+        let public_id = unwrap!(verify_signed_public_id(&serialised_public_id, nonce, &signature));
+        assert_eq!(public_id, *full_id.public_id());
+
+        // Replayed on a connection with a different challenge, the signature doesn't verify.
+        assert!(verify_signed_public_id(&serialised_public_id, nonce + 1, &signature).is_err());
+    }
+
+    #[test]
+    fn client_identify_claiming_other_id_rejected() {
+        let full_id = FullId::new();
+        let claimed_id = FullId::new();
+        let serialised_public_id = unwrap!(serialise(claimed_id.public_id()));
+        let nonce = 42;
+        let signed_bytes = unwrap!(identify_signed_bytes(serialised_public_id.clone(), nonce));
+        let signature = sign::sign_detached(&signed_bytes, full_id.signing_private_key());
+
+        // The claimed ID's key doesn't verify a signature made with another one.
+        assert!(verify_signed_public_id(&serialised_public_id, nonce, &signature).is_err());
+    }
+
+    #[test]
+    fn identify_must_match_bootstrap_kind() {
+        assert!(identify_matches_bootstrap(CrustUser::Client, true));
+        assert!(identify_matches_bootstrap(CrustUser::Node, false));
+        assert!(!identify_matches_bootstrap(CrustUser::Client, false));
+        assert!(!identify_matches_bootstrap(CrustUser::Node, true));
+    }
+}
@@ -15,11 +15,122 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
+use convergence::ConvergenceMetrics;
+use expiring_cache::CacheStats;
 use messages::{DirectMessage, MessageContent, Request, Response, RoutingMessage, UserMessage};
+use processing_stats::ProcessingHistograms;
+use std::collections::BTreeMap;
+use xor_name::XorName;
 
 /// The number of messages after which the message statistics should be printed.
 const MSG_LOG_COUNT: usize = 5000;
 
+/// A snapshot of the sizes of Routing's bounded message filters and caches.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Diagnostics {
+    /// The number of distinct messages held in the incoming message filter.
+    pub incoming_filter_len: usize,
+    /// The number of messages evicted from the incoming message filter to stay within capacity.
+    pub incoming_filter_evictions: usize,
+    /// The number of already known messages caught by the incoming message filter.
+    pub incoming_filter_hits: usize,
+    /// The number of entries held in the outgoing message filter.
+    pub outgoing_filter_len: usize,
+    /// The number of entries held in the cache of recently connected bootstrappers.
+    pub connection_cache_len: usize,
+    /// The number of protocol violations committed by peers, e.g. unrequested connection infos.
+    pub protocol_violations: usize,
+    /// The number of messages received from peers which couldn't be deserialised.
+    pub malformed_msgs: usize,
+    /// The number of peers whose misbehaviour is currently scored.
+    pub scored_peers: usize,
+    /// The number of peers which are currently banned.
+    pub banned_peers: usize,
+    /// The number of outgoing connection attempts currently in flight.
+    pub connects_in_flight: usize,
+    /// The number of outgoing connection attempts queued by connection pacing.
+    pub queued_connects: usize,
+    /// The number of attempts to connect to or identify ourselves as a peer which were skipped.
+    pub self_skips: usize,
+    /// The number of messages received from peers we don't know.
+    pub unknown_sender_msgs: usize,
+    /// The number of section messages which are still waiting for a quorum of signatures.
+    pub pending_accumulations: usize,
+    /// The number of messages dropped because they were relayed too many times.
+    pub hop_limit_drops: usize,
+    /// The number of join requests from nodes which are already being relocated. They are passed
+    /// on to the same section as before, which repeats its response.
+    pub relocation_cache_hits: usize,
+    /// The number of join requests for which a new relocated name was assigned.
+    pub relocation_cache_misses: usize,
+    /// The number of relocate responses we sent to candidates, including repeated ones.
+    pub relocate_responses: usize,
+    /// The number of connection info messages dropped because they predate a churn event.
+    pub stale_msgs: usize,
+    /// The number of routing table entries and connections dropped by connection audits.
+    pub audit_repairs: usize,
+    /// The number of Crust events parked while joining and handled once we joined.
+    pub startup_events_replayed: usize,
+    /// The number of Crust events parked while joining which were dropped because too many arrived.
+    pub startup_queue_drops: usize,
+    /// The wire format version each peer we heard from uses, by name.
+    pub peer_wire_versions: BTreeMap<XorName, u8>,
+    /// The number of clients we currently act as a proxy for.
+    pub relayed_clients: usize,
+    /// The number of connected peers which haven't identified themselves yet.
+    pub unidentified_connections: usize,
+    /// The number of routing table entries outside our own section.
+    pub far_contacts: usize,
+    /// The number of diagnostic pings we answered.
+    pub pings_answered: usize,
+    /// The number of diagnostic pings we dropped because their sender sent too many.
+    pub pings_throttled: usize,
+    /// The number of distinct large payloads held by messages awaiting an ack. Identical payloads
+    /// sent to several recipients are only held once.
+    pub pooled_payloads: usize,
+    /// The total size of the distinct payloads held by messages awaiting an ack, in bytes.
+    pub pooled_payload_bytes: usize,
+    /// The number of routing table samples we sent to peers.
+    pub gossip_samples_sent: usize,
+    /// The number of routing table samples we accepted from peers.
+    pub gossip_samples_received: usize,
+    /// The number of routing table samples we dropped because their sender sent them too often.
+    pub gossip_samples_throttled: usize,
+    /// Histograms of the time spent in each phase of handling received messages.
+    pub processing_histograms: ProcessingHistograms,
+    /// How far the routing table has converged. Only tracked if enabled via
+    /// `NodeBuilder::convergence_tracking`.
+    pub convergence: ConvergenceMetrics,
+    /// The size and activity of the node's expiring caches, by name.
+    pub caches: BTreeMap<String, CacheStats>,
+}
+
+/// The work a node still has to do, as reported by `Node::pending_work`. Only available in tests.
+#[cfg(feature = "use-mock-crust")]
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PendingWork {
+    /// The number of Crust events, actions and timeouts received but not handled yet.
+    pub queued_events: usize,
+    /// The number of sent messages which haven't been acknowledged yet.
+    pub unacked_msgs: usize,
+    /// The number of outgoing connection attempts in flight.
+    pub connects_in_flight: usize,
+    /// The number of outgoing connection attempts queued by connection pacing.
+    pub queued_connects: usize,
+    /// Whether a timer is due within the horizon passed to `Node::pending_work`.
+    pub timer_due: bool,
+    /// Whether the node is still bootstrapping or joining the network.
+    pub joining: bool,
+}
+
+#[cfg(feature = "use-mock-crust")]
+impl PendingWork {
+    /// Returns whether there is no pending work at all.
+    pub fn is_idle(&self) -> bool {
+        *self == PendingWork::default()
+    }
+}
+
 /// A collection of counters to gather Routing statistics.
 #[derive(Default, Clone)]
 pub struct Stats {
@@ -34,6 +145,24 @@ pub struct Stats {
     routes: Vec<usize>,
     /// Messages we sent unsuccessfully: unacknowledged on all routes.
     unacked_msgs: usize,
+    /// Messages received from peers we don't know.
+    unknown_sender_msgs: usize,
+    /// Messages dropped because they exceeded the hop limit.
+    hop_limit_drops: usize,
+    /// Join requests from nodes we are already relocating.
+    relocation_cache_hits: usize,
+    /// Join requests from nodes we are not yet relocating.
+    relocation_cache_misses: usize,
+    /// Relocate responses sent to candidates, including repeated ones.
+    relocate_responses: usize,
+    /// Messages dropped because they were created before a churn event.
+    stale_msgs: usize,
+    /// Routing table entries and connections dropped by connection audits.
+    audit_repairs: usize,
+    /// Crust events parked while joining, and handled once we joined.
+    startup_events_replayed: usize,
+    /// Crust events parked while joining, but dropped because too many arrived.
+    startup_queue_drops: usize,
 
     msg_direct_candidate_identify: usize,
     msg_direct_sig: usize,
@@ -89,6 +218,75 @@ impl Stats {
         Default::default()
     }
 
+    pub fn count_unknown_sender(&mut self) {
+        self.unknown_sender_msgs += 1;
+    }
+
+    pub fn unknown_sender_msgs(&self) -> usize {
+        self.unknown_sender_msgs
+    }
+
+    pub fn count_hop_limit_drop(&mut self) {
+        self.hop_limit_drops += 1;
+    }
+
+    pub fn hop_limit_drops(&self) -> usize {
+        self.hop_limit_drops
+    }
+
+    pub fn count_relocation_cache_hit(&mut self) {
+        self.relocation_cache_hits += 1;
+    }
+
+    pub fn relocation_cache_hits(&self) -> usize {
+        self.relocation_cache_hits
+    }
+
+    pub fn count_relocation_cache_miss(&mut self) {
+        self.relocation_cache_misses += 1;
+    }
+
+    pub fn relocation_cache_misses(&self) -> usize {
+        self.relocation_cache_misses
+    }
+
+    pub fn count_relocate_response(&mut self) {
+        self.relocate_responses += 1;
+    }
+
+    pub fn relocate_responses(&self) -> usize {
+        self.relocate_responses
+    }
+
+    pub fn count_stale_drop(&mut self) {
+        self.stale_msgs += 1;
+    }
+
+    pub fn stale_msgs(&self) -> usize {
+        self.stale_msgs
+    }
+
+    pub fn count_audit_repairs(&mut self, count: usize) {
+        self.audit_repairs += count;
+    }
+
+    pub fn audit_repairs(&self) -> usize {
+        self.audit_repairs
+    }
+
+    pub fn count_startup_queue(&mut self, replayed: usize, dropped: usize) {
+        self.startup_events_replayed += replayed;
+        self.startup_queue_drops += dropped;
+    }
+
+    pub fn startup_events_replayed(&self) -> usize {
+        self.startup_events_replayed
+    }
+
+    pub fn startup_queue_drops(&self) -> usize {
+        self.startup_queue_drops
+    }
+
     pub fn count_unacked(&mut self) {
         self.unacked_msgs += 1;
     }
@@ -155,6 +353,10 @@ impl Stats {
             MessageContent::Ack(..) => self.msg_ack += 1,
             MessageContent::CandidateApproval { .. } => self.msg_candidate_approval += 1,
             MessageContent::NodeApproval { .. } => self.msg_node_approval += 1,
+            MessageContent::ClientRelay(..) |
+            MessageContent::RedirectToClient(..) |
+            MessageContent::ClientUnreachable(..) |
+            MessageContent::CancelRequest { .. } => self.msg_other += 1,
             MessageContent::UserMessagePart { .. } => return, // Counted as request/response.
         }
         self.increment_msg_total();
@@ -173,6 +375,11 @@ impl Stats {
             BootstrapIdentify { .. } |
             BootstrapDeny |
             ClientIdentify { .. } |
+            IdentifyChallenge(_) |
+            TableSample(_) |
+            PeerIdsRequest(_) |
+            PeerIds(_) |
+            PeerLost(_) |
             TunnelRequest(_) |
             TunnelSuccess(_) |
             TunnelSelect(_) |
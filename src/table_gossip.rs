@@ -0,0 +1,235 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+#[cfg(feature="use-mock-crust")]
+use fake_clock::FakeClock as Instant;
+use id::PublicId;
+use rand;
+use routing_table::Xorable;
+use std::collections::HashMap;
+use std::time::Duration;
+#[cfg(not(feature="use-mock-crust"))]
+use std::time::Instant;
+use xor_name::XorName;
+
+/// The maximum number of names or IDs handled from a single gossip message. Any further ones are
+/// ignored.
+pub const MAX_GOSSIP_NAMES: usize = 64;
+/// The maximum number of names learned from gossip which are held while their IDs are requested.
+/// The ones furthest from our own name are dropped first.
+const MAX_CANDIDATES: usize = 32;
+
+/// A name learned from a table sample, whose `PublicId` we requested from the sample's sender.
+struct Candidate {
+    name: XorName,
+    source: PublicId,
+    added: Instant,
+}
+
+/// The state of periodic routing table gossip: the peers' samples we accepted recently, and the
+/// names from them we want to connect to.
+pub struct TableGossip {
+    interval: Duration,
+    fanout: usize,
+    sample_size: usize,
+    /// The time we last accepted a sample from each peer.
+    last_sample_from: HashMap<PublicId, Instant>,
+    /// The names we are missing, sorted by closeness to our own name.
+    candidates: Vec<Candidate>,
+    sent: usize,
+    received: usize,
+    throttled: usize,
+}
+
+impl TableGossip {
+    /// Returns a new instance sending samples of `sample_size` names to `fanout` peers every
+    /// `interval`.
+    pub fn new(interval: Duration, fanout: usize, sample_size: usize) -> TableGossip {
+        TableGossip {
+            interval: interval,
+            fanout: fanout,
+            sample_size: sample_size,
+            last_sample_from: HashMap::new(),
+            candidates: Vec::new(),
+            sent: 0,
+            received: 0,
+            throttled: 0,
+        }
+    }
+
+    /// The time between two gossip rounds.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Chooses the names to send to each of the peers a sample goes to this round: up to `fanout`
+    /// recipients, each with up to `sample_size` of the other names.
+    pub fn choose_samples(&mut self, names: &[XorName]) -> Vec<(XorName, Vec<XorName>)> {
+        let mut rng = rand::thread_rng();
+        let recipients = rand::sample(&mut rng, names.iter().cloned(), self.fanout);
+        self.sent += recipients.len();
+        recipients
+            .into_iter()
+            .map(|recipient| {
+                     let others = names.iter().filter(|name| **name != recipient).cloned();
+                     (recipient, rand::sample(&mut rng, others, self.sample_size))
+                 })
+            .collect()
+    }
+
+    /// Returns whether a sample received from `pub_id` should be handled, or dropped because the
+    /// peer already sent one within the last half interval.
+    pub fn allow_sample(&mut self, pub_id: PublicId) -> bool {
+        let now = Instant::now();
+        let min_spacing = self.interval / 2;
+        if let Some(last) = self.last_sample_from.get(&pub_id) {
+            if last.elapsed() < min_spacing {
+                self.throttled += 1;
+                return false;
+            }
+        }
+        let _ = self.last_sample_from.insert(pub_id, now);
+        self.received += 1;
+        true
+    }
+
+    /// Buffers the names from a sample sent by `source` which we are missing. Returns those which
+    /// were kept, and whose IDs should now be requested from `source`.
+    pub fn add_candidates(&mut self,
+                          our_name: &XorName,
+                          source: PublicId,
+                          names: Vec<XorName>)
+                          -> Vec<XorName> {
+        let now = Instant::now();
+        let mut added = Vec::new();
+        for name in names {
+            if self.candidates.iter().all(|candidate| candidate.name != name) {
+                self.candidates
+                    .push(Candidate {
+                              name: name,
+                              source: source,
+                              added: now,
+                          });
+                added.push(name);
+            }
+        }
+        self.candidates
+            .sort_by(|lhs, rhs| our_name.cmp_closeness(&lhs.name, &rhs.name));
+        self.candidates.truncate(MAX_CANDIDATES);
+        self.candidates
+            .iter()
+            .filter(|candidate| added.contains(&candidate.name))
+            .map(|candidate| candidate.name)
+            .collect()
+    }
+
+    /// Removes the candidate with the given name if its ID was requested from `source`. Returns
+    /// whether it was, i.e. whether an ID for the name from `source` should be accepted.
+    pub fn take_candidate(&mut self, name: &XorName, source: &PublicId) -> bool {
+        match self.candidates
+                  .iter()
+                  .position(|candidate| candidate.name == *name && candidate.source == *source) {
+            Some(index) => {
+                let _ = self.candidates.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Drops the candidates whose IDs weren't received within an interval.
+    pub fn remove_expired(&mut self) {
+        let interval = self.interval;
+        self.candidates
+            .retain(|candidate| candidate.added.elapsed() < interval);
+    }
+
+    /// Forgets the rate limit of, and the candidates learned from, a peer we are no longer
+    /// connected to.
+    pub fn forget(&mut self, pub_id: &PublicId) {
+        let _ = self.last_sample_from.remove(pub_id);
+        self.candidates
+            .retain(|candidate| candidate.source != *pub_id);
+    }
+
+    /// The number of samples we sent.
+    pub fn sent(&self) -> usize {
+        self.sent
+    }
+
+    /// The number of samples we accepted from peers.
+    pub fn received(&self) -> usize {
+        self.received
+    }
+
+    /// The number of samples we dropped because their sender exceeded the rate limit.
+    pub fn throttled(&self) -> usize {
+        self.throttled
+    }
+}
+
+#[cfg(all(test, feature = "use-mock-crust"))]
+mod tests {
+    use super::*;
+    use fake_clock::FakeClock;
+    use id::FullId;
+    use rand;
+
+    #[test]
+    fn samples_bounded_and_rate_limited() {
+        let mut gossip = TableGossip::new(Duration::from_secs(10), 3, 4);
+        let names: Vec<XorName> = (0..10).map(|_| rand::random()).collect();
+        let samples = gossip.choose_samples(&names);
+        assert_eq!(samples.len(), 3);
+        for &(ref recipient, ref sample) in &samples {
+            assert_eq!(sample.len(), 4);
+            assert!(!sample.contains(recipient));
+        }
+        assert_eq!(gossip.sent(), 3);
+
+        let pub_id = *FullId::new().public_id();
+        assert!(gossip.allow_sample(pub_id));
+        FakeClock::advance_time(4 * 1000);
+        assert!(!gossip.allow_sample(pub_id));
+        FakeClock::advance_time(1000);
+        assert!(gossip.allow_sample(pub_id));
+        assert_eq!((gossip.received(), gossip.throttled()), (2, 1));
+    }
+
+    #[test]
+    fn candidates_closest_kept_and_only_requested_accepted() {
+        let mut gossip = TableGossip::new(Duration::from_secs(10), 3, 4);
+        let our_name: XorName = rand::random();
+        let source = *FullId::new().public_id();
+        let other_source = *FullId::new().public_id();
+        let mut names: Vec<XorName> = (0..(MAX_CANDIDATES + 8)).map(|_| rand::random()).collect();
+
+        let kept = gossip.add_candidates(&our_name, source, names.clone());
+        names.sort_by(|lhs, rhs| our_name.cmp_closeness(lhs, rhs));
+        assert_eq!(kept, &names[..MAX_CANDIDATES]);
+
+        // Only an ID requested from the peer the name was learned from is accepted, and only once.
+        assert!(!gossip.take_candidate(&names[0], &other_source));
+        assert!(gossip.take_candidate(&names[0], &source));
+        assert!(!gossip.take_candidate(&names[0], &source));
+        assert!(!gossip.take_candidate(&names[MAX_CANDIDATES], &source));
+
+        FakeClock::advance_time(10 * 1000);
+        gossip.remove_expired();
+        assert!(!gossip.take_candidate(&names[1], &source));
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! Builders for wire messages with arbitrary authorities, contents and signing keys, including
+//! combinations a correct node never sends. Pass the bytes to `Node::inject_message_for_test` to
+//! present them to a node as if they had been received from one of its peers.
+
+use PubConnectionInfo;
+use id::{FullId, PublicId};
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use messages::{DEFAULT_PRIORITY, DirectMessage, HopMessage, Message, MessageContent, Request,
+               RoutingMessage, SectionList, SignedMessage, UserMessage,
+               identify_signed_bytes};
+use mock_crust::Endpoint;
+use routing_table::{Authority, Prefix};
+use rust_sodium::crypto::{box_, sign};
+use std::collections::BTreeSet;
+use types::MessageId;
+use xor_name::XorName;
+
+/// A routing message to be signed and serialised for injection.
+pub struct TestMessage {
+    content: RoutingMessage,
+    route: u8,
+    hop_count: u8,
+}
+
+impl TestMessage {
+    /// A user request from `src` to `dst`. The request must fit into a single message part.
+    pub fn request(src: Authority<XorName>,
+                   dst: Authority<XorName>,
+                   request: Request)
+                   -> TestMessage {
+        let mut parts = unwrap!(UserMessage::Request(request).to_parts(DEFAULT_PRIORITY));
+        assert_eq!(parts.len(), 1, "The request doesn't fit into a single part.");
+        Self::with_content(src, dst, parts.remove(0))
+    }
+
+    /// A connection info request claiming to be from `claimed_id`, with empty connection info.
+    pub fn connection_info_request(src: Authority<XorName>,
+                                   dst: Authority<XorName>,
+                                   claimed_id: PublicId)
+                                   -> TestMessage {
+        let content = MessageContent::ConnectionInfoRequest {
+            encrypted_conn_info: vec![],
+            nonce: [0; box_::NONCEBYTES],
+            pub_id: claimed_id,
+            msg_id: MessageId::new(),
+            generation: 0,
+        };
+        Self::with_content(src, dst, content)
+    }
+
+    /// A connection info request from `sender` to `recipient`, carrying `sender`'s connection info
+    /// for `endpoint`, encrypted as a correct node would.
+    pub fn sealed_connection_info_request(src: Authority<XorName>,
+                                          dst: Authority<XorName>,
+                                          sender: &FullId,
+                                          recipient: &PublicId,
+                                          endpoint: Endpoint)
+                                          -> TestMessage {
+        let conn_info = PubConnectionInfo {
+            id: *sender.public_id(),
+            endpoint: endpoint,
+        };
+        let nonce = box_::gen_nonce();
+        let encrypted_conn_info = box_::seal(&unwrap!(serialise(&conn_info)),
+                                             &nonce,
+                                             recipient.encrypting_public_key(),
+                                             sender.encrypting_private_key());
+        let content = MessageContent::ConnectionInfoRequest {
+            encrypted_conn_info: encrypted_conn_info,
+            nonce: nonce.0,
+            pub_id: *sender.public_id(),
+            msg_id: MessageId::new(),
+            generation: 0,
+        };
+        Self::with_content(src, dst, content)
+    }
+
+    /// Returns a connection info response from `src` to `dst`, claiming to be sent by `claimed_id`.
+    /// The connection info itself is empty.
+    pub fn connection_info_response(src: Authority<XorName>,
+                                    dst: Authority<XorName>,
+                                    claimed_id: PublicId)
+                                    -> TestMessage {
+        let content = MessageContent::ConnectionInfoResponse {
+            encrypted_conn_info: vec![],
+            nonce: [0; box_::NONCEBYTES],
+            pub_id: claimed_id,
+            msg_id: MessageId::new(),
+            generation: 0,
+        };
+        Self::with_content(src, dst, content)
+    }
+
+    /// A request to relocate the client `src` into the network, sent to the section `dst`.
+    pub fn relocate_request(src: Authority<XorName>, dst: Authority<XorName>) -> TestMessage {
+        Self::with_content(src, dst, MessageContent::Relocate { message_id: MessageId::new() })
+    }
+
+    /// A cancellation of the request `msg_id` from `src` to `dst`, with `msg_id` signed by
+    /// `signer`, who should be the client that sent the request.
+    pub fn cancel_request(src: Authority<XorName>,
+                          dst: Authority<XorName>,
+                          msg_id: MessageId,
+                          signer: &FullId)
+                          -> TestMessage {
+        let signature = sign::sign_detached(&unwrap!(serialise(&msg_id)),
+                                            signer.signing_private_key());
+        let content = MessageContent::CancelRequest {
+            msg_id: msg_id,
+            signature: signature,
+        };
+        Self::with_content(src, dst, content)
+    }
+
+    /// Sets the route and the number of hops the message claims to have been relayed already.
+    pub fn with_route(mut self, route: u8, hop_count: u8) -> TestMessage {
+        self.route = route;
+        self.hop_count = hop_count;
+        self
+    }
+
+    /// Signs the message as `claimant`, wraps it into a hop signed by `hop_signer` and serialises
+    /// it. Neither key is checked against the authorities; the hop is only accepted by the
+    /// recipient if `hop_signer` is the peer it is injected from.
+    pub fn to_bytes(&self, claimant: &FullId, hop_signer: &FullId) -> Vec<u8> {
+        let signed_msg = unwrap!(SignedMessage::new(self.content.clone(), claimant, vec![]));
+        self.wrap(signed_msg, hop_signer)
+    }
+
+    /// Returns the encoding of the signed message which `to_bytes` wraps into a hop, when signed
+    /// by `claimant`.
+    pub fn signed_bytes(&self, claimant: &FullId) -> Vec<u8> {
+        let signed_msg = unwrap!(SignedMessage::new(self.content.clone(), claimant, vec![]));
+        unwrap!(serialise(&signed_msg))
+    }
+
+    /// Like `to_bytes`, but for a message from a section or group: it is signed by every one of
+    /// `claimants`, and claims `members` of the section `prefix` as its senders.
+    pub fn to_section_bytes(&self,
+                            prefix: Prefix<XorName>,
+                            members: BTreeSet<PublicId>,
+                            claimants: &[FullId],
+                            hop_signer: &FullId)
+                            -> Vec<u8> {
+        let src_sections = vec![SectionList::new(prefix, members)];
+        let mut signed_msg =
+            unwrap!(SignedMessage::new(self.content.clone(), &claimants[0], src_sections));
+        let signed_bytes = unwrap!(serialise(&self.content));
+        for claimant in &claimants[1..] {
+            let sig = sign::sign_detached(&signed_bytes, claimant.signing_private_key());
+            signed_msg.add_signature(*claimant.public_id(), sig);
+        }
+        self.wrap(signed_msg, hop_signer)
+    }
+
+    fn with_content(src: Authority<XorName>,
+                    dst: Authority<XorName>,
+                    content: MessageContent)
+                    -> TestMessage {
+        TestMessage {
+            content: RoutingMessage {
+                src: src,
+                dst: dst,
+                content: content,
+            },
+            route: 0,
+            hop_count: 0,
+        }
+    }
+
+    fn wrap(&self, signed_msg: SignedMessage, hop_signer: &FullId) -> Vec<u8> {
+        let hop_msg = unwrap!(HopMessage::new(signed_msg,
+                                              self.route,
+                                              BTreeSet::new(),
+                                              self.hop_count,
+                                              hop_signer.signing_private_key()));
+        unwrap!(hop_msg.into_bytes())
+    }
+}
+
+/// A `ClientIdentify` direct message by which a node claiming `pub_id` answers the challenge
+/// `nonce`, signed by `signer`.
+pub fn node_identify_bytes(pub_id: &PublicId, signer: &FullId, nonce: u64) -> Vec<u8> {
+    let serialised_public_id = unwrap!(serialise(pub_id));
+    let signed_bytes = unwrap!(identify_signed_bytes(serialised_public_id.clone(), nonce));
+    let signature = sign::sign_detached(&signed_bytes, signer.signing_private_key());
+    let direct_msg = DirectMessage::ClientIdentify {
+        serialised_public_id: serialised_public_id,
+        signature: signature,
+        client_restriction: false,
+    };
+    unwrap!(serialise(&Message::Direct(direct_msg)))
+}
+
+/// Returns the nonce if `bytes` is an `IdentifyChallenge` direct message.
+pub fn identify_challenge_nonce(bytes: &[u8]) -> Option<u64> {
+    match deserialise(bytes) {
+        Ok(Message::Direct(DirectMessage::IdentifyChallenge(nonce))) => Some(nonce),
+        _ => None,
+    }
+}
@@ -270,6 +270,17 @@ mod implementation {
             token
         }
 
+        /// Returns whether any timeout is due within `horizon` from now.
+        pub fn has_deadline_within(&self, horizon: Duration) -> bool {
+            let limit = Instant::now() + horizon;
+            self.inner
+                .borrow()
+                .deadlines
+                .keys()
+                .next()
+                .map_or(false, |&deadline| deadline <= limit)
+        }
+
         pub fn get_timed_out_tokens(&mut self) -> Vec<u64> {
             let mut inner = self.inner.borrow_mut();
             let now = Instant::now();
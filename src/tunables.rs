@@ -0,0 +1,428 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use {QUORUM_DENOMINATOR, QUORUM_NUMERATOR};
+use event::JoinProgressMask;
+use std::time::Duration;
+use wire_version::BASE_WIRE_VERSION;
+
+/// Duration (in seconds) for which received routing messages are remembered to filter duplicates.
+const FILTER_EXPIRY_DURATION_SECS: u64 = 60 * 20;
+/// The maximum number of received routing messages remembered to filter duplicates.
+const FILTER_CAPACITY: usize = 100_000;
+/// Duration (in seconds) for which the kind of a bootstrapping peer is remembered.
+const BOOTSTRAPPER_HOLD_DUR_SECS: u64 = 300;
+/// The maximum number of bootstrapping peers whose kind is remembered.
+const BOOTSTRAPPER_CAPACITY: usize = 1000;
+/// Duration (in seconds) for which relocated names assigned to joining nodes are remembered.
+const RELOCATION_CACHE_DUR_SECS: u64 = 300;
+/// The maximum number of relocated names assigned to joining nodes which are remembered.
+const RELOCATION_CACHE_CAPACITY: usize = 1000;
+/// Duration (in seconds) for which retries of a request are assigned the original `MessageId`.
+pub const MESSAGE_ID_RETRY_WINDOW_SECS: u64 = 120;
+/// The maximum number of retry-safe messages whose `MessageId` and wire messages are remembered.
+const MESSAGE_ID_RETRY_CAPACITY: usize = 100;
+/// The number of churn events by which a connection info message may lag behind our knowledge of
+/// its sender's section before it is dropped as stale.
+const CHURN_GENERATION_SLACK: u64 = 1;
+/// The number of close group members beyond a quorum to which a message bound for the group is
+/// sent by default.
+pub const GROUP_FANOUT_MARGIN: usize = 2;
+/// The maximal number of times a message is relayed between nodes. Each hop takes a message into a
+/// section whose prefix shares at least one more bit with the destination, so this comfortably
+/// exceeds the network's diameter plus the final broadcast within the destination section. A
+/// message which exceeds it is caught in a routing loop and is dropped.
+const MAX_HOP_COUNT: u8 = 40;
+/// The maximum number of Crust events parked until the node is ready to handle them.
+const STARTUP_QUEUE_CAPACITY: usize = 1000;
+/// Number of protocol violations after which we drop the connection to a peer.
+pub const MAX_PROTOCOL_VIOLATIONS: usize = 3;
+/// Time (in seconds) without protocol violations after which a peer's count starts to decay.
+pub const PROTOCOL_VIOLATION_WINDOW_SECS: u64 = 600;
+/// Time (in seconds) after which a peer's protocol violations have decayed to half their count.
+pub const PROTOCOL_VIOLATION_HALF_LIFE_SECS: u64 = 600;
+/// Number of malformed messages after which we drop the connection to a peer.
+pub const MAX_MALFORMED_MSG_STRIKES: usize = 3;
+/// Time (in seconds) without malformed messages after which a peer's strikes are forgotten.
+pub const MALFORMED_MSG_STRIKE_DECAY_SECS: u64 = 300;
+/// Time (in seconds) after which a peer's malformed message strikes have decayed to half their
+/// count, once they are being forgotten.
+const MALFORMED_MSG_STRIKE_HALF_LIFE_SECS: u64 = 30;
+
+/// Returns the number of members of a close group of `min_section_size` which form a quorum.
+pub fn group_quorum(min_section_size: usize) -> usize {
+    min_section_size * QUORUM_NUMERATOR / QUORUM_DENOMINATOR + 1
+}
+
+/// Limits on the number of connections of each kind a node holds. `None` means unlimited.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionQuotas {
+    /// The maximum number of clients we act as a proxy for.
+    pub clients: Option<usize>,
+    /// The maximum number of connected peers which haven't identified themselves yet.
+    pub unidentified: Option<usize>,
+    /// The maximum number of routing table entries outside our own section.
+    pub far_contacts: Option<usize>,
+}
+
+/// When a kind of misbehaviour gets a peer disconnected, and how quickly it is forgiven.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ScoreRule {
+    /// The score, rounded to the nearest integer, at which the peer is disconnected.
+    pub threshold: usize,
+    /// For how long after its latest violation the peer's score is kept in full.
+    pub window: Duration,
+    /// The time after which a score has decayed to half its value, once the window has passed.
+    pub half_life: Duration,
+    /// For how long the peer is banned once it reaches the threshold, if at all.
+    pub ban: Option<Duration>,
+}
+
+/// How a client with several bootstrap connections chooses the proxy carrying each request.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ProxyStrategy {
+    /// Use the proxy which identified itself first, as long as it stays connected.
+    FirstIdentified,
+    /// Use the proxy with the lowest measured round-trip time.
+    LowestLatency,
+    /// Take turns between all connected proxies.
+    RoundRobin,
+}
+
+impl Default for ProxyStrategy {
+    fn default() -> ProxyStrategy {
+        ProxyStrategy::FirstIdentified
+    }
+}
+
+/// Configurable parameters of a node, such as the sizes and expiry durations of its filters and
+/// caches, as set via `NodeBuilder`.
+#[derive(Clone, Copy, Debug)]
+pub struct Tunables {
+    pub filter_expiry: Duration,
+    pub filter_capacity: usize,
+    pub bootstrapper_cache_duration: Duration,
+    pub bootstrapper_cache_capacity: usize,
+    pub relocation_cache_duration: Duration,
+    pub relocation_cache_capacity: usize,
+    pub join_progress_events: JoinProgressMask,
+    pub health_events: bool,
+    pub departure_consensus: bool,
+    pub decisions_only: bool,
+    pub max_peers_per_ip: Option<usize>,
+    pub max_peers_per_subnet: Option<usize>,
+    pub message_id_retry_window: Duration,
+    pub message_id_retry_capacity: usize,
+    pub max_connects_in_flight: Option<usize>,
+    pub connect_spacing: Duration,
+    pub churn_generation_slack: u64,
+    pub audit_interval: Option<Duration>,
+    pub audit_grace: Duration,
+    pub connection_quotas: ConnectionQuotas,
+    pub decision_log_capacity: Option<usize>,
+    pub proxy_strategy: ProxyStrategy,
+    pub slow_message_threshold: Option<Duration>,
+    pub gossip_interval: Option<Duration>,
+    pub gossip_fanout: usize,
+    pub gossip_sample_size: usize,
+    pub disconnected_queue_limit: Option<usize>,
+    pub group_fanout: Option<usize>,
+    pub proxy_drop_threshold: Option<usize>,
+    pub max_hop_count: u8,
+    pub convergence_interval: Option<Duration>,
+    pub convergence_stable_snapshots: usize,
+    pub convergence_min_table_size: usize,
+    pub startup_queue_capacity: usize,
+    pub oldest_wire_version: u8,
+    pub newest_wire_version: u8,
+    pub protocol_violation_rule: ScoreRule,
+    pub malformed_msg_rule: ScoreRule,
+    pub event_coalescing_window: Option<Duration>,
+    #[cfg(feature = "use-mock-crust")]
+    pub rng_seed: Option<[u32; 4]>,
+}
+
+impl Default for Tunables {
+    fn default() -> Tunables {
+        Tunables {
+            filter_expiry: Duration::from_secs(FILTER_EXPIRY_DURATION_SECS),
+            filter_capacity: FILTER_CAPACITY,
+            bootstrapper_cache_duration: Duration::from_secs(BOOTSTRAPPER_HOLD_DUR_SECS),
+            bootstrapper_cache_capacity: BOOTSTRAPPER_CAPACITY,
+            relocation_cache_duration: Duration::from_secs(RELOCATION_CACHE_DUR_SECS),
+            relocation_cache_capacity: RELOCATION_CACHE_CAPACITY,
+            join_progress_events: JoinProgressMask::default(),
+            health_events: false,
+            departure_consensus: false,
+            decisions_only: false,
+            max_peers_per_ip: None,
+            max_peers_per_subnet: None,
+            message_id_retry_window: Duration::from_secs(MESSAGE_ID_RETRY_WINDOW_SECS),
+            message_id_retry_capacity: MESSAGE_ID_RETRY_CAPACITY,
+            max_connects_in_flight: None,
+            connect_spacing: Duration::from_secs(0),
+            churn_generation_slack: CHURN_GENERATION_SLACK,
+            audit_interval: None,
+            audit_grace: Duration::from_secs(0),
+            connection_quotas: ConnectionQuotas::default(),
+            decision_log_capacity: None,
+            proxy_strategy: ProxyStrategy::default(),
+            slow_message_threshold: None,
+            gossip_interval: None,
+            gossip_fanout: 0,
+            gossip_sample_size: 0,
+            disconnected_queue_limit: None,
+            group_fanout: None,
+            proxy_drop_threshold: None,
+            max_hop_count: MAX_HOP_COUNT,
+            convergence_interval: None,
+            convergence_stable_snapshots: 0,
+            convergence_min_table_size: 0,
+            startup_queue_capacity: STARTUP_QUEUE_CAPACITY,
+            oldest_wire_version: BASE_WIRE_VERSION,
+            newest_wire_version: BASE_WIRE_VERSION,
+            protocol_violation_rule: ScoreRule {
+                threshold: MAX_PROTOCOL_VIOLATIONS,
+                window: Duration::from_secs(PROTOCOL_VIOLATION_WINDOW_SECS),
+                half_life: Duration::from_secs(PROTOCOL_VIOLATION_HALF_LIFE_SECS),
+                ban: None,
+            },
+            malformed_msg_rule: ScoreRule {
+                threshold: MAX_MALFORMED_MSG_STRIKES,
+                window: Duration::from_secs(MALFORMED_MSG_STRIKE_DECAY_SECS),
+                half_life: Duration::from_secs(MALFORMED_MSG_STRIKE_HALF_LIFE_SECS),
+                ban: None,
+            },
+            event_coalescing_window: None,
+            #[cfg(feature = "use-mock-crust")]
+            rng_seed: None,
+        }
+    }
+}
+
+/// Every setting of a running node, including changes made since it started, as returned by
+/// `Node::effective_config` and reported in `Event::ConfigUpdated`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EffectiveConfig {
+    /// The minimum number of nodes in a section.
+    pub min_section_size: usize,
+    /// For how long received routing messages are remembered to filter duplicates.
+    pub filter_expiry: Duration,
+    /// How many received routing messages are remembered to filter duplicates.
+    pub filter_capacity: usize,
+    /// For how long the kind of a bootstrapping peer is remembered.
+    pub bootstrapper_cache_duration: Duration,
+    /// How many bootstrapping peers' kinds are remembered.
+    pub bootstrapper_cache_capacity: usize,
+    /// For how long relocated names assigned to joining nodes are remembered.
+    pub relocation_cache_duration: Duration,
+    /// How many relocated names assigned to joining nodes are remembered.
+    pub relocation_cache_capacity: usize,
+    /// The kinds of `Event::JoinProgress` steps raised.
+    pub join_progress_events: JoinProgressMask,
+    /// Whether `Event::HealthChanged` is raised.
+    pub health_events: bool,
+    /// Whether `Event::NodeDeparted` is raised once a quorum of our section lost a member.
+    pub departure_consensus: bool,
+    /// Whether routing messages are only reported to the forwarding sink instead of being sent.
+    pub decisions_only: bool,
+    /// The maximum number of routing table entries connected from the same IP address.
+    pub max_peers_per_ip: Option<usize>,
+    /// The maximum number of routing table entries connected from the same subnet.
+    pub max_peers_per_subnet: Option<usize>,
+    /// For how long retries of a request are assigned the original `MessageId`.
+    pub message_id_retry_window: Duration,
+    /// The maximum number of retry-safe messages remembered within the retry window.
+    pub message_id_retry_capacity: usize,
+    /// The maximum number of outgoing connection attempts in flight at a time.
+    pub max_connects_in_flight: Option<usize>,
+    /// The minimum time between initiating two outgoing connection attempts.
+    pub connect_spacing: Duration,
+    /// By how many churn events a connection info message may lag behind before it is dropped.
+    pub churn_generation_slack: u64,
+    /// The interval between connection audits, if enabled.
+    pub audit_interval: Option<Duration>,
+    /// For how long a discrepancy found by an audit is tolerated.
+    pub audit_grace: Duration,
+    /// The limits on the number of connections of each kind.
+    pub connection_quotas: ConnectionQuotas,
+    /// The number of entries kept in the decision log, if enabled.
+    pub decision_log_capacity: Option<usize>,
+    /// How a client chooses the proxy carrying each request.
+    pub proxy_strategy: ProxyStrategy,
+    /// The processing time beyond which `Event::SlowMessage` is raised, if enabled.
+    pub slow_message_threshold: Option<Duration>,
+    /// The interval between rounds of routing table gossip, if enabled.
+    pub gossip_interval: Option<Duration>,
+    /// The number of peers sent a routing table sample in each round of gossip.
+    pub gossip_fanout: usize,
+    /// The number of routing table entries in each gossiped sample.
+    pub gossip_sample_size: usize,
+    /// The maximum number of user messages queued while disconnected, if recovery is enabled.
+    pub disconnected_queue_limit: Option<usize>,
+    /// The maximum number of peers a message bound for a close group is sent to.
+    pub group_fanout: usize,
+    /// The number of routing table entries at which a joined node disconnects from its proxy.
+    pub proxy_drop_threshold: usize,
+    /// The number of times a message can be relayed before it is dropped as caught in a loop.
+    pub max_hop_count: u8,
+    /// The interval between snapshots of the routing table's convergence, if tracked.
+    pub convergence_interval: Option<Duration>,
+    /// The number of snapshots over which the routing table must be unchanged to be converged.
+    pub convergence_stable_snapshots: usize,
+    /// The minimum number of routing table entries for the table to be converged.
+    pub convergence_min_table_size: usize,
+    /// The maximum number of Crust events parked until the node is ready to handle them.
+    pub startup_queue_capacity: usize,
+    /// The oldest wire format version we accept, and send to peers we haven't heard from yet.
+    pub oldest_wire_version: u8,
+    /// The newest wire format version we accept.
+    pub newest_wire_version: u8,
+    /// When protocol violations get a peer disconnected.
+    pub protocol_violation_rule: ScoreRule,
+    /// When malformed messages get a peer disconnected.
+    pub malformed_msg_rule: ScoreRule,
+    /// The window over which repetitive events are coalesced, if enabled.
+    pub event_coalescing_window: Option<Duration>,
+}
+
+impl EffectiveConfig {
+    /// Returns the settings given by `tunables`, resolving the defaults which depend on
+    /// `min_section_size`.
+    pub fn new(min_section_size: usize, tunables: &Tunables) -> EffectiveConfig {
+        EffectiveConfig {
+            min_section_size: min_section_size,
+            filter_expiry: tunables.filter_expiry,
+            filter_capacity: tunables.filter_capacity,
+            bootstrapper_cache_duration: tunables.bootstrapper_cache_duration,
+            bootstrapper_cache_capacity: tunables.bootstrapper_cache_capacity,
+            relocation_cache_duration: tunables.relocation_cache_duration,
+            relocation_cache_capacity: tunables.relocation_cache_capacity,
+            join_progress_events: tunables.join_progress_events,
+            health_events: tunables.health_events,
+            departure_consensus: tunables.departure_consensus,
+            decisions_only: tunables.decisions_only,
+            max_peers_per_ip: tunables.max_peers_per_ip,
+            max_peers_per_subnet: tunables.max_peers_per_subnet,
+            message_id_retry_window: tunables.message_id_retry_window,
+            message_id_retry_capacity: tunables.message_id_retry_capacity,
+            max_connects_in_flight: tunables.max_connects_in_flight,
+            connect_spacing: tunables.connect_spacing,
+            churn_generation_slack: tunables.churn_generation_slack,
+            audit_interval: tunables.audit_interval,
+            audit_grace: tunables.audit_grace,
+            connection_quotas: tunables.connection_quotas,
+            decision_log_capacity: tunables.decision_log_capacity,
+            proxy_strategy: tunables.proxy_strategy,
+            slow_message_threshold: tunables.slow_message_threshold,
+            gossip_interval: tunables.gossip_interval,
+            gossip_fanout: tunables.gossip_fanout,
+            gossip_sample_size: tunables.gossip_sample_size,
+            disconnected_queue_limit: tunables.disconnected_queue_limit,
+            group_fanout: tunables
+                .group_fanout
+                .unwrap_or_else(|| group_quorum(min_section_size) + GROUP_FANOUT_MARGIN),
+            proxy_drop_threshold: tunables
+                .proxy_drop_threshold
+                .unwrap_or(min_section_size - 1),
+            max_hop_count: tunables.max_hop_count,
+            convergence_interval: tunables.convergence_interval,
+            convergence_stable_snapshots: tunables.convergence_stable_snapshots,
+            convergence_min_table_size: tunables.convergence_min_table_size,
+            startup_queue_capacity: tunables.startup_queue_capacity,
+            oldest_wire_version: tunables.oldest_wire_version,
+            newest_wire_version: tunables.newest_wire_version,
+            protocol_violation_rule: tunables.protocol_violation_rule,
+            malformed_msg_rule: tunables.malformed_msg_rule,
+            event_coalescing_window: tunables.event_coalescing_window,
+        }
+    }
+}
+
+/// A change to the settings of a running node, passed to `Node::update_config`. Fields left as
+/// `None` are kept as they are.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct PartialConfig {
+    /// The settings which take effect immediately.
+    pub live: LiveConfig,
+    /// The settings which are only read when the node starts. Any given here are refused.
+    pub startup: StartupConfig,
+}
+
+/// The settings of a running node which can be changed without restarting it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct LiveConfig {
+    /// See `NodeBuilder::connection_quotas`.
+    pub connection_quotas: Option<ConnectionQuotas>,
+    /// See `NodeBuilder::group_fanout`. Must be at least a quorum.
+    pub group_fanout: Option<usize>,
+    /// See `NodeBuilder::connect_pacing`. A limit must be at least 1.
+    pub max_connects_in_flight: Option<Option<usize>>,
+    /// See `NodeBuilder::connect_pacing`.
+    pub connect_spacing: Option<Duration>,
+    /// See `NodeBuilder::max_peers_per_ip`. A limit must be at least 1, and no more than the
+    /// limit per subnet.
+    pub max_peers_per_ip: Option<Option<usize>>,
+    /// See `NodeBuilder::max_peers_per_subnet`. A limit must be at least 1.
+    pub max_peers_per_subnet: Option<Option<usize>>,
+    /// See `NodeBuilder::join_progress_events`.
+    pub join_progress_events: Option<JoinProgressMask>,
+    /// See `NodeBuilder::health_events`.
+    pub health_events: Option<bool>,
+    /// See `NodeBuilder::decisions_only`.
+    pub decisions_only: Option<bool>,
+}
+
+/// The settings of a node which are only read when it starts.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct StartupConfig {
+    /// See `NodeBuilder::message_filter`.
+    pub filter_expiry: Option<Duration>,
+    /// See `NodeBuilder::message_filter`.
+    pub filter_capacity: Option<usize>,
+    /// See `NodeBuilder::message_id_retry_window`.
+    pub message_id_retry_window: Option<Duration>,
+    /// See `NodeBuilder::message_id_retry_capacity`.
+    pub message_id_retry_capacity: Option<usize>,
+    /// See `NodeBuilder::churn_generation_slack`.
+    pub churn_generation_slack: Option<u64>,
+    /// See `NodeBuilder::slow_message_reports`.
+    pub slow_message_threshold: Option<Duration>,
+    /// See `NodeBuilder::table_gossip`.
+    pub gossip_interval: Option<Duration>,
+    /// See `NodeBuilder::disconnected_recovery`.
+    pub disconnected_queue_limit: Option<usize>,
+}
+
+impl StartupConfig {
+    /// Returns the names of the settings which are given.
+    pub fn given_fields(&self) -> Vec<&'static str> {
+        let fields = [("filter_expiry", self.filter_expiry.is_some()),
+                      ("filter_capacity", self.filter_capacity.is_some()),
+                      ("message_id_retry_window", self.message_id_retry_window.is_some()),
+                      ("message_id_retry_capacity", self.message_id_retry_capacity.is_some()),
+                      ("churn_generation_slack", self.churn_generation_slack.is_some()),
+                      ("slow_message_threshold", self.slow_message_threshold.is_some()),
+                      ("gossip_interval", self.gossip_interval.is_some()),
+                      ("disconnected_queue_limit", self.disconnected_queue_limit.is_some())];
+        fields
+            .iter()
+            .filter(|&&(_, given)| given)
+            .map(|&(name, _)| name)
+            .collect()
+    }
+}
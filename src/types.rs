@@ -54,6 +54,11 @@ impl MessageId {
         MessageId(XorName([0; 32]))
     }
 
+    /// Generate a `MessageId` with the given value, e.g. to construct reproducible messages.
+    pub fn from_name(name: XorName) -> MessageId {
+        MessageId(name)
+    }
+
     /// Generate a new `MessageId` with contents extracted from lost node.
     pub fn from_lost_node(mut name: XorName) -> MessageId {
         name.0[0] = b'L';
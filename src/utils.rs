@@ -21,6 +21,7 @@ use routing_table::Xorable;
 use std::collections::BTreeSet;
 use std::fmt::{self, Display, Write};
 use std::iter;
+use std::net::IpAddr;
 use std::time::Duration;
 use tiny_keccak::sha3_256;
 use xor_name::XorName;
@@ -92,7 +93,7 @@ pub fn format_binary_array<V: AsRef<[u8]>>(input: V) -> String {
 ///
 /// [`current_name`, 1st closest node id]
 pub fn calculate_relocation_dst(mut close_nodes: Vec<XorName>, current_name: &XorName) -> XorName {
-    close_nodes.sort_by(|a, b| current_name.cmp_distance(a, b));
+    close_nodes.sort_by(|a, b| current_name.cmp_closeness(a, b));
     let combined: Vec<u8> = iter::once(current_name)
         .chain(close_nodes.iter().take(2))
         .flat_map(|close_node| close_node.0.into_iter())
@@ -123,11 +124,22 @@ pub fn calculate_relocation_interval(prefix: &Prefix<XorName>,
     (new_end - third_of_distance, new_end)
 }
 
+/// Returns whether the two addresses are in the same subnet: the same /24 for IPv4 or the same /64
+/// for IPv6 addresses.
+pub fn same_subnet(lhs: &IpAddr, rhs: &IpAddr) -> bool {
+    match (*lhs, *rhs) {
+        (IpAddr::V4(lhs), IpAddr::V4(rhs)) => lhs.octets()[..3] == rhs.octets()[..3],
+        (IpAddr::V6(lhs), IpAddr::V6(rhs)) => lhs.segments()[..4] == rhs.segments()[..4],
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::DisplayDuration;
     use rand;
     use routing_table::Xorable;
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
     use std::time::Duration;
     use tiny_keccak::sha3_256;
     use xor_name::XorName;
@@ -210,4 +222,36 @@ mod tests {
         let invalid_relocated_name = XorName(sha3_256(&invalid_combined));
         assert_ne!(invalid_relocated_name, actual_relocated_name);
     }
+
+    #[test]
+    fn relocation_dst_agrees_across_members() {
+        let original_name: XorName = rand::random();
+        let section: Vec<XorName> = (0..8).map(|_| rand::random()).collect();
+
+        // Two members may list the section in a different order, but compute the same name.
+        let mut reversed = section.clone();
+        reversed.reverse();
+        let name_1 = super::calculate_relocation_dst(section.clone(), &original_name);
+        let name_2 = super::calculate_relocation_dst(reversed, &original_name);
+        assert_eq!(name_1, name_2);
+
+        // It only depends on the two closest nodes.
+        let mut closest_two = section;
+        closest_two.sort_by(|a, b| original_name.cmp_closeness(a, b));
+        closest_two.truncate(2);
+        assert_eq!(super::calculate_relocation_dst(closest_two, &original_name),
+                   name_1);
+    }
+
+    #[test]
+    fn same_subnet() {
+        let ip = IpAddr::V4(Ipv4Addr::new(10, 0, 1, 1));
+        assert!(super::same_subnet(&ip, &IpAddr::V4(Ipv4Addr::new(10, 0, 1, 254))));
+        assert!(!super::same_subnet(&ip, &IpAddr::V4(Ipv4Addr::new(10, 0, 2, 1))));
+        assert!(!super::same_subnet(&ip, &IpAddr::V6(Ipv6Addr::new(10, 0, 1, 1, 0, 0, 0, 0))));
+
+        let ip = IpAddr::V6(Ipv6Addr::new(1, 2, 3, 4, 5, 6, 7, 8));
+        assert!(super::same_subnet(&ip, &IpAddr::V6(Ipv6Addr::new(1, 2, 3, 4, 0, 0, 0, 0))));
+        assert!(!super::same_subnet(&ip, &IpAddr::V6(Ipv6Addr::new(1, 2, 3, 5, 5, 6, 7, 8))));
+    }
 }
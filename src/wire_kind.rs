@@ -0,0 +1,129 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! The kind of a serialised `Message`, as told by the first byte of its payload alone.
+//!
+//! A `Message` is serialised with its variant index as a little-endian `u32` tag in front, so the
+//! first byte identifies the variant. This is part of the wire format: changing the order of the
+//! variants breaks networks of mixed versions. It lets the mock network tell messages apart
+//! without deserialising them.
+
+use wire_version;
+
+/// The first payload byte of a `Message::Direct`.
+const DIRECT_TAG: u8 = 0;
+/// The first payload byte of a `Message::Hop`.
+const HOP_TAG: u8 = 1;
+/// The first payload byte of a `Message::TunnelDirect`.
+const TUNNEL_DIRECT_TAG: u8 = 2;
+/// The first payload byte of a `Message::TunnelHop`.
+const TUNNEL_HOP_TAG: u8 = 3;
+/// The first payload byte of a `Message::Ping`.
+const PING_TAG: u8 = 4;
+/// The first payload byte of a `Message::Pong`.
+const PONG_TAG: u8 = 5;
+
+/// The kind of a message payload on the wire.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub enum WireKind {
+    /// A message between directly connected peers, e.g. during the identification handshake.
+    Direct,
+    /// A signed routing message, possibly relayed across the network.
+    Hop,
+    /// A direct message sent via a tunnel node.
+    TunnelDirect,
+    /// A signed routing message sent via a tunnel node.
+    TunnelHop,
+    /// A diagnostic ping.
+    Ping,
+    /// The reply to a diagnostic ping.
+    Pong,
+    /// A payload starting with the given unknown byte. An empty payload is `Unknown(0)`.
+    Unknown(u8),
+}
+
+impl WireKind {
+    /// Returns the kind of the serialised message `payload`, in any wire format version.
+    pub fn of(payload: &[u8]) -> WireKind {
+        match wire_version::split(payload).1.first() {
+            Some(&DIRECT_TAG) => WireKind::Direct,
+            Some(&HOP_TAG) => WireKind::Hop,
+            Some(&TUNNEL_DIRECT_TAG) => WireKind::TunnelDirect,
+            Some(&TUNNEL_HOP_TAG) => WireKind::TunnelHop,
+            Some(&PING_TAG) => WireKind::Ping,
+            Some(&PONG_TAG) => WireKind::Pong,
+            Some(&tag) => WireKind::Unknown(tag),
+            None => WireKind::Unknown(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use id::FullId;
+    use maidsafe_utilities::serialisation::serialise;
+    use messages::{DirectMessage, HopMessage, Message, MessageContent, RoutingMessage,
+                   SignedMessage};
+    use routing_table::Authority;
+    use std::collections::BTreeSet;
+    use types::MessageId;
+    use wire_version::with_version;
+
+    fn hop_message(full_id: &FullId) -> HopMessage {
+        let name = *full_id.public_id().name();
+        let routing_msg = RoutingMessage {
+            src: Authority::ManagedNode(name),
+            dst: Authority::ManagedNode(name),
+            content: MessageContent::Relocate { message_id: MessageId::new() },
+        };
+        let signed_msg = unwrap!(SignedMessage::new(routing_msg, full_id, vec![]));
+        unwrap!(HopMessage::new(signed_msg,
+                                0,
+                                BTreeSet::new(),
+                                0,
+                                full_id.signing_private_key()))
+    }
+
+    #[test]
+    fn kinds_of_serialised_messages() {
+        let full_id = FullId::new();
+        let pub_id = *full_id.public_id();
+        let messages = vec![(Message::Direct(DirectMessage::BootstrapIdentify), WireKind::Direct),
+                            (Message::Hop(hop_message(&full_id)), WireKind::Hop),
+                            (Message::TunnelDirect {
+                                 content: DirectMessage::BootstrapIdentify,
+                                 src: pub_id,
+                                 dst: pub_id,
+                             },
+                             WireKind::TunnelDirect),
+                            (Message::TunnelHop {
+                                 content: hop_message(&full_id),
+                                 src: pub_id,
+                                 dst: pub_id,
+                             },
+                             WireKind::TunnelHop),
+                            (Message::Ping(1), WireKind::Ping),
+                            (Message::Pong(1), WireKind::Pong)];
+        for (message, kind) in messages {
+            assert_eq!(WireKind::of(&unwrap!(serialise(&message))), kind);
+        }
+        assert_eq!(WireKind::of(&[6, 0, 0, 0]), WireKind::Unknown(6));
+        assert_eq!(WireKind::of(&[]), WireKind::Unknown(0));
+        assert_eq!(WireKind::of(&with_version(1, vec![PING_TAG, 0, 0, 0])), WireKind::Ping);
+    }
+}
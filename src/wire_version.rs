@@ -0,0 +1,197 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+
+//! Wire format versions, and the versions our peers use while a network is being upgraded from
+//! one version to the next.
+//!
+//! A message in the base version is sent as the serialised `Message` alone, as it was before
+//! versions existed. A message in any later version is preceded by `VERSION_MARKER` and the
+//! version number. No serialised `Message` starts with `VERSION_MARKER`, so both can be told
+//! apart, and nodes can accept several versions during an upgrade.
+
+use std::cmp;
+use std::collections::BTreeMap;
+use xor_name::XorName;
+
+/// The version of messages sent without a version prefix.
+pub const BASE_WIRE_VERSION: u8 = 0;
+/// The first byte of a message in a version other than `BASE_WIRE_VERSION`.
+const VERSION_MARKER: u8 = 0xf0;
+
+/// Returns the wire format version of `payload`, and the serialised message it holds.
+pub fn split(payload: &[u8]) -> (u8, &[u8]) {
+    if payload.len() >= 2 && payload[0] == VERSION_MARKER {
+        (payload[1], &payload[2..])
+    } else {
+        (BASE_WIRE_VERSION, payload)
+    }
+}
+
+/// Returns the serialised message `bytes` in the wire format `version`.
+pub fn with_version(version: u8, bytes: Vec<u8>) -> Vec<u8> {
+    if version == BASE_WIRE_VERSION {
+        return bytes;
+    }
+    let mut payload = Vec::with_capacity(bytes.len() + 2);
+    payload.push(VERSION_MARKER);
+    payload.push(version);
+    payload.extend(bytes);
+    payload
+}
+
+/// The range of wire format versions we accept, and the version each peer's traffic uses.
+///
+/// We send to each peer in the version it was last seen using, or in the oldest version we accept
+/// if we haven't heard from it yet. That way peers which only accept older versions can still
+/// talk to us while the network is being upgraded.
+pub struct WireVersions {
+    oldest: u8,
+    newest: u8,
+    peers: BTreeMap<XorName, u8>,
+    upgraded: bool,
+}
+
+impl WireVersions {
+    /// Returns a tracker accepting the versions from `oldest` to `newest`, inclusive.
+    pub fn new(oldest: u8, newest: u8) -> WireVersions {
+        WireVersions {
+            oldest: cmp::min(oldest, newest),
+            newest: cmp::max(oldest, newest),
+            peers: BTreeMap::new(),
+            upgraded: false,
+        }
+    }
+
+    /// Returns the serialised message in `payload` received from `name`, and records its version
+    /// as the one the peer uses. Returns the version instead if we don't accept it.
+    pub fn decode(&mut self, name: &XorName, mut payload: Vec<u8>) -> Result<Vec<u8>, u8> {
+        let (version, prefix_len) = {
+            let (version, bytes) = split(&payload);
+            (version, payload.len() - bytes.len())
+        };
+        if version < self.oldest || version > self.newest {
+            return Err(version);
+        }
+        let _ = self.peers.insert(*name, version);
+        let _ = payload.drain(..prefix_len);
+        Ok(payload)
+    }
+
+    /// Returns the serialised message `bytes` in the version to send to `name`.
+    pub fn encode(&self, name: &XorName, bytes: Vec<u8>) -> Vec<u8> {
+        let version = self.peers.get(name).cloned().unwrap_or(self.oldest);
+        with_version(version, bytes)
+    }
+
+    /// Forgets the version used by the given peer.
+    pub fn forget(&mut self, name: &XorName) {
+        let _ = self.peers.remove(name);
+    }
+
+    /// Returns the version each peer we heard from uses.
+    pub fn peer_versions(&self) -> BTreeMap<XorName, u8> {
+        self.peers.clone()
+    }
+
+    /// Returns `Some(version)` if all the given members of our close group have just been seen
+    /// using the newest version we accept, and we accept older ones too. After that, returns
+    /// `None` until some member uses an older version again.
+    pub fn check_upgraded<'a, I>(&mut self, close_group: I) -> Option<u8>
+        where I: IntoIterator<Item = &'a XorName>
+    {
+        if self.oldest == self.newest {
+            return None;
+        }
+        let mut close_group = close_group.into_iter().peekable();
+        let upgraded = close_group.peek().is_some() &&
+                       close_group.all(|name| self.peers.get(name) == Some(&self.newest));
+        if upgraded == self.upgraded {
+            return None;
+        }
+        self.upgraded = upgraded;
+        if upgraded { Some(self.newest) } else { None }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand;
+
+    #[test]
+    fn versions_round_trip() {
+        let bytes = vec![1, 2, 3];
+        assert_eq!(with_version(BASE_WIRE_VERSION, bytes.clone()), bytes);
+        let payload = with_version(1, bytes.clone());
+        assert_eq!(payload, vec![VERSION_MARKER, 1, 1, 2, 3]);
+        assert_eq!(split(&payload), (1, &bytes[..]));
+        assert_eq!(split(&bytes), (BASE_WIRE_VERSION, &bytes[..]));
+    }
+
+    #[test]
+    fn peers_sent_their_own_version() {
+        let old_peer: XorName = rand::random();
+        let new_peer: XorName = rand::random();
+        let unknown_peer: XorName = rand::random();
+        let mut versions = WireVersions::new(0, 1);
+        let bytes = vec![1, 2, 3];
+
+        assert_eq!(versions.decode(&old_peer, bytes.clone()), Ok(bytes.clone()));
+        assert_eq!(versions.decode(&new_peer, with_version(1, bytes.clone())),
+                   Ok(bytes.clone()));
+        assert_eq!(versions.decode(&new_peer, with_version(2, bytes.clone())), Err(2));
+
+        assert_eq!(versions.encode(&old_peer, bytes.clone()), bytes);
+        assert_eq!(versions.encode(&new_peer, bytes.clone()),
+                   with_version(1, bytes.clone()));
+        assert_eq!(versions.encode(&unknown_peer, bytes.clone()), bytes);
+        assert_eq!(versions.peer_versions().len(), 2);
+
+        let mut new_only = WireVersions::new(1, 1);
+        assert_eq!(new_only.decode(&old_peer, bytes.clone()), Err(0));
+        assert_eq!(new_only.encode(&unknown_peer, bytes.clone()),
+                   with_version(1, bytes.clone()));
+    }
+
+    #[test]
+    fn upgraded_once_last_old_peer_gone() {
+        let old_peer: XorName = rand::random();
+        let new_peers: Vec<XorName> = (0..3).map(|_| rand::random()).collect();
+        let mut versions = WireVersions::new(0, 1);
+        let _ = versions.decode(&old_peer, vec![0]);
+        for name in &new_peers {
+            let _ = versions.decode(name, with_version(1, vec![0]));
+        }
+
+        let mut close_group = new_peers.clone();
+        close_group.push(old_peer);
+        assert_eq!(versions.check_upgraded(&close_group), None);
+
+        versions.forget(&old_peer);
+        let _ = close_group.pop();
+        assert_eq!(versions.check_upgraded(&close_group), Some(1));
+        assert_eq!(versions.check_upgraded(&close_group), None);
+
+        // An old peer joining resets it, so it fires again once that one is gone too.
+        let _ = versions.decode(&old_peer, vec![0]);
+        close_group.push(old_peer);
+        assert_eq!(versions.check_upgraded(&close_group), None);
+        let _ = close_group.pop();
+        assert_eq!(versions.check_upgraded(&close_group), Some(1));
+    }
+}
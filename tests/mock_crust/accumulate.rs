@@ -40,6 +40,17 @@ fn messages_accumulate_with_quorum() {
     };
 
     let dst = Authority::ManagedNode(nodes[0].name()); // The closest node.
+
+    // Sends the request `dst` expects the responses to, and discards the resulting events.
+    let request = |nodes: &mut [TestNode], message_id: MessageId| {
+        unwrap!(nodes[0]
+                    .inner
+                    .send_get_request(dst, src, data.identifier(), message_id));
+        let _ = poll_all(nodes, &mut []);
+        for node in &mut *nodes {
+            while let Ok(_) = node.try_next_ev() {}
+        }
+    };
     // The smallest number such that
     // `quorum * QUORUM_DENOMINATOR > min_section_size * QUORUM_NUMERATOR`:
     let quorum = 1 + (min_section_size * QUORUM_NUMERATOR) / QUORUM_DENOMINATOR;
@@ -48,6 +59,7 @@ fn messages_accumulate_with_quorum() {
     // Only the `quorum`-th sender should cause accumulation and a
     // `Response` event. The event should only occur once.
     let message_id = MessageId::new();
+    request(&mut nodes, message_id);
     for node in nodes.iter_mut().take(quorum - 1) {
         send(node, &dst, message_id);
     }
@@ -64,6 +76,7 @@ fn messages_accumulate_with_quorum() {
     // Only after `nodes[0]`, which is closest to `src.name()`, has sent the full message, it
     // accumulates.
     let message_id = MessageId::new();
+    request(&mut nodes, message_id);
     for node in nodes.iter_mut().skip(1).take(quorum) {
         send(node, &dst, message_id);
     }
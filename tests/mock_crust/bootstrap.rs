@@ -0,0 +1,291 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use super::{TestClient, TestNode, create_connected_clients, create_connected_nodes, poll_all,
+            poll_and_resend, wait_for, wait_until_joined};
+use routing::{BootstrapFailure, Event, EventStream, FullId, JoinProgress, JoinProgressMask,
+              PublicId};
+use routing::mock_crust::{BootstrapPolicy, Config, Endpoint, Network, PacketKind, PacketKindMask,
+                          crust};
+
+#[test]
+fn client_bootstraps_off_contact_accepting_clients() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    // Only the last of the three contacts accepts clients.
+    nodes[0].handle.set_accept_bootstrap(BootstrapPolicy::NodesOnly);
+    nodes[1].handle.set_accept_bootstrap(BootstrapPolicy::NodesOnly);
+    let contacts = [nodes[0].handle.endpoint(),
+                    nodes[1].handle.endpoint(),
+                    nodes[2].handle.endpoint()];
+
+    let config = Config::with_contacts(&contacts);
+    let mut clients = vec![TestClient::new(&network, Some(config), None)];
+    let _ = poll_all(&mut nodes, &mut clients);
+
+    expect_next_event!(clients[0], Event::Connected);
+    assert!(clients[0].handle.is_connected(&nodes[2].handle));
+    assert!(!clients[0].handle.is_connected(&nodes[0].handle));
+    assert!(!clients[0].handle.is_connected(&nodes[1].handle));
+}
+
+#[test]
+fn excess_bootstrap_connections_dropped() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    // Both contacts accept the client, but it only keeps the first connection.
+    let contacts = [nodes[0].handle.endpoint(), nodes[1].handle.endpoint()];
+    let config = Config::with_contacts(&contacts);
+    let mut clients = vec![TestClient::new(&network, Some(config), None)];
+    let _ = poll_all(&mut nodes, &mut clients);
+
+    expect_next_event!(clients[0], Event::Connected);
+    let connected = nodes[..2]
+        .iter()
+        .filter(|node| clients[0].handle.is_connected(&node.handle))
+        .count();
+    assert_eq!(connected, 1);
+}
+
+#[test]
+fn bootstrap_failure_reports_each_contact() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let mut clients = create_connected_clients(&network, &mut nodes, 1);
+
+    // The client isn't listening, the first node refuses clients, and the route to the second one
+    // is blocked.
+    let endpoint = network.gen_endpoint(None);
+    nodes[1].handle.set_accept_bootstrap(BootstrapPolicy::NodesOnly);
+    network.block_connection(endpoint, nodes[2].handle.endpoint());
+    let contacts = [clients[0].handle.endpoint(),
+                    nodes[1].handle.endpoint(),
+                    nodes[2].handle.endpoint()];
+    let config = Config::with_contacts(&contacts);
+    clients.push(TestClient::new(&network, Some(config), Some(endpoint)));
+    let _ = poll_all(&mut nodes, &mut clients);
+
+    let failures = match clients[1].inner.try_next_ev() {
+        Ok(Event::BootstrapFailed(failures)) => failures,
+        other => panic!("Expected Ok(Event::BootstrapFailed(..)), got {:?}", other),
+    };
+    // The mock network uses the endpoint as the port.
+    let mut failures = failures
+        .into_iter()
+        .map(|(addr, failure)| (Endpoint(addr.port() as usize), failure))
+        .collect::<Vec<_>>();
+    failures.sort_by_key(|&(endpoint, _)| endpoint);
+    let mut expected = vec![(contacts[0], BootstrapFailure::NotListening),
+                            (contacts[1], BootstrapFailure::Refused),
+                            (contacts[2], BootstrapFailure::Unreachable)];
+    expected.sort_by_key(|&(endpoint, _)| endpoint);
+    assert_eq!(failures, expected);
+    expect_next_event!(clients[1], Event::Terminate);
+}
+
+#[test]
+fn bootstrap_success_among_failures() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let mut clients = create_connected_clients(&network, &mut nodes, 1);
+
+    let contacts = [clients[0].handle.endpoint(), nodes[0].handle.endpoint()];
+    let config = Config::with_contacts(&contacts);
+    clients.push(TestClient::new(&network, Some(config), None));
+    let _ = poll_all(&mut nodes, &mut clients);
+
+    expect_next_event!(clients[1], Event::Connected);
+    while let Ok(event) = clients[1].inner.try_next_ev() {
+        if let Event::BootstrapFailed(..) = event {
+            panic!("Unexpected {:?}", event);
+        }
+    }
+}
+
+// Asserts that `step` is an attempt to bootstrap off `contact`, with the given number.
+fn assert_contact_attempt(step: &JoinProgress, contact: Endpoint, expected_attempt: u32) {
+    match *step {
+        JoinProgress::BootstrapContactAttempt { contact: addr, attempt }
+            if addr.port() as usize == contact.0 && attempt == expected_attempt => (),
+        ref step => {
+            panic!("Expected attempt {} with {:?}, got {:?}",
+                   expected_attempt,
+                   contact,
+                   step)
+        }
+    }
+}
+
+#[test]
+fn join_progress_events() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    let contact = nodes[0].handle.endpoint();
+    let config = Config::with_contacts(&[contact]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(Endpoint(min_section_size))
+                   .join_progress_events(JoinProgressMask::all())
+                   .create());
+    let progress = wait_until_joined(&mut nodes, &mut [], min_section_size);
+
+    assert_contact_attempt(&progress[0], contact, 1);
+    assert_eq!(progress[1], JoinProgress::BootstrapConnected(nodes[0].name()));
+    assert_eq!(progress[2], JoinProgress::RelocationRequested);
+    assert_eq!(progress[3],
+               JoinProgress::RelocationReceived(nodes[min_section_size].name()));
+    assert_contact_attempt(&progress[4], contact, 1);
+    match progress[5] {
+        JoinProgress::BootstrapConnected(_) => (),
+        step => panic!("Expected a bootstrap connection after relocation, got {:?}", step),
+    }
+    assert_eq!(unwrap!(progress.last()), &JoinProgress::JoinComplete);
+    let connecting = &progress[6..progress.len() - 1];
+    assert!(!connecting.is_empty());
+    for (index, step) in connecting.iter().enumerate() {
+        let expected = JoinProgress::CloseGroupConnecting {
+            connected: index + 1,
+            required: min_section_size,
+        };
+        assert_eq!(*step, expected);
+    }
+}
+
+#[test]
+fn join_progress_events_filtered_by_mask() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(Endpoint(min_section_size))
+                   .join_progress_events(JoinProgressMask::completion())
+                   .create());
+    let progress = wait_until_joined(&mut nodes, &mut [], min_section_size);
+    assert_eq!(progress, vec![JoinProgress::JoinComplete]);
+}
+
+#[test]
+fn join_progress_retries_next_contact() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    // The second contact refuses to bootstrap us at first, and the first one accepts us but its
+    // identify challenge never arrives. Once the first attempt times out, the second contact is
+    // reachable.
+    let first = nodes[0].handle.endpoint();
+    let second = nodes[1].handle.endpoint();
+    let endpoint = Endpoint(min_section_size);
+    network.block_packet_kind(endpoint, second, PacketKindMask::bootstrap());
+    let observer_network = network.clone();
+    network.set_packet_observer(move |packet| {
+        if packet.sender == first && packet.receiver == endpoint &&
+           packet.kind == PacketKind::BootstrapSuccess {
+            observer_network.blackhole_connection(first, endpoint);
+            observer_network.unblock_packet_kind(endpoint, second, PacketKindMask::bootstrap());
+        }
+    });
+
+    let config = Config::with_contacts(&[first, second]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(endpoint)
+                   .join_progress_events(JoinProgressMask::all())
+                   .create());
+
+    let mut progress = Vec::new();
+    let _ = wait_for(&mut nodes, &mut [], |index, event| match *event {
+        Event::JoinProgress(step) if index == min_section_size => {
+            progress.push(step);
+            if let JoinProgress::BootstrapConnected(_) = step {
+                true
+            } else {
+                false
+            }
+        }
+        _ => false,
+    });
+    network.clear_packet_observer();
+    network.unblackhole_connection(first, endpoint);
+
+    assert_eq!(progress.len(), 3, "Unexpected steps: {:?}", progress);
+    assert_contact_attempt(&progress[0], first, 1);
+    assert_contact_attempt(&progress[1], second, 2);
+    assert_eq!(progress[2], JoinProgress::BootstrapConnected(nodes[1].name()));
+
+    // The rest of the join is unaffected.
+    let progress = wait_until_joined(&mut nodes, &mut [], min_section_size);
+    assert_eq!(progress[0], JoinProgress::RelocationRequested);
+    assert_eq!(unwrap!(progress.last()), &JoinProgress::JoinComplete);
+}
+
+#[test]
+fn events_parked_until_joined() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    let endpoint = Endpoint(min_section_size);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(endpoint)
+                   .join_progress_events(JoinProgressMask::bootstrap())
+                   .startup_queue_capacity(5)
+                   .create());
+
+    // Malformed messages of distinct lengths from peers other than our proxy, each from a new one
+    // so none of them is disconnected for misbehaving. Only the last five fit into the queue.
+    let senders: Vec<PublicId> = (0..7).map(|_| *FullId::new().public_id()).collect();
+    for (len, sender_id) in (1..8).zip(&senders) {
+        let event = crust::Event::NewMessage(*sender_id, vec![0xff; len]);
+        network.send_crust_event_or_panic(endpoint, event);
+    }
+    poll_and_resend(&mut nodes, &mut []);
+
+    // They are handled once, in order, only after bootstrapping under the relocated name.
+    let joined_node = unwrap!(nodes.last_mut());
+    let mut bootstrap_connections = 0;
+    let mut lengths = Vec::new();
+    while let Ok(event) = joined_node.inner.try_next_ev() {
+        match event {
+            Event::JoinProgress(JoinProgress::BootstrapConnected(_)) => {
+                bootstrap_connections += 1;
+            }
+            Event::MalformedMessage(pub_id, len) if senders.contains(&pub_id) => {
+                assert_eq!(bootstrap_connections, 2, "Message handled before joining.");
+                lengths.push(len);
+            }
+            _ => (),
+        }
+    }
+    assert_eq!(lengths, vec![3, 4, 5, 6, 7]);
+    let diagnostics = unwrap!(joined_node.inner.diagnostics());
+    assert_eq!(diagnostics.startup_events_replayed, 5);
+    assert_eq!(diagnostics.startup_queue_drops, 2);
+}
@@ -156,8 +156,8 @@ fn random_churn<R: Rng>(rng: &mut R,
             debug!("Lost connection between {} and {}",
                    nodes[peer_1].name(),
                    nodes[peer_2].name());
-            network.lost_connection(nodes[peer_1].handle.endpoint(),
-                                    nodes[peer_2].handle.endpoint());
+            let _ = network.lost_connection(nodes[peer_1].handle.endpoint(),
+                                            nodes[peer_2].handle.endpoint());
         }
 
         let config = Config::with_contacts(&[nodes[proxy].handle.endpoint()]);
@@ -449,8 +449,8 @@ fn aggressive_churn() {
             debug!("Lost connection between {} and {}",
                    nodes[peer_1].name(),
                    nodes[peer_2].name());
-            network.lost_connection(nodes[peer_1].handle.endpoint(),
-                                    nodes[peer_2].handle.endpoint());
+            let _ = network.lost_connection(nodes[peer_1].handle.endpoint(),
+                                            nodes[peer_2].handle.endpoint());
         }
 
         // A candidate could be blocked if some nodes of the section it connected to has lost node
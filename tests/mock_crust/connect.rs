@@ -0,0 +1,397 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use super::{TestClient, TestNode, create_connected_nodes, gen_immutable_data, poll_all,
+            poll_and_resend, settle, verify_invariant_for_all_nodes};
+use fake_clock::FakeClock;
+use rand::Rng;
+use routing::{Authority, CoalescingKey, DataIdentifier, Event, EventStream, InterfaceError,
+              MessageId, Request, XorName};
+use routing::mock_crust::{Config, ConnectionInfoBehaviour, Endpoint, Network};
+use routing::test_consts::{ACK_TIMEOUT_SECS, MAX_CONNECTION_INFO_ATTEMPTS, MAX_PINGS_PER_WINDOW,
+                           PING_WINDOW_SECS};
+use std::io;
+use std::time::Duration;
+
+#[test]
+fn delayed_connection_info_preparation() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+
+    for node in &*nodes {
+        node.handle
+            .set_connection_info_behaviour(ConnectionInfoBehaviour::Delayed(5));
+    }
+    let node = TestNode::builder(&network).config(config).create();
+    node.handle
+        .set_connection_info_behaviour(ConnectionInfoBehaviour::Delayed(5));
+    nodes.push(node);
+
+    poll_and_resend(&mut nodes, &mut []);
+    let index = nodes.len() - 1;
+    expect_any_event!(nodes[index], Event::Connected);
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn retried_connection_info_preparation() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+
+    // Every connection attempt survives this many failures, so the node still joins.
+    let node = TestNode::builder(&network).config(config).create();
+    node.handle
+        .set_connection_info_failures(MAX_CONNECTION_INFO_ATTEMPTS - 1);
+    node.handle.set_connection_info_async(true);
+    nodes.push(node);
+
+    poll_and_resend(&mut nodes, &mut []);
+    let index = nodes.len() - 1;
+    expect_any_event!(nodes[index], Event::Connected);
+    assert_eq!(nodes[index].routing_table().len(), min_section_size);
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn failed_connection_info_preparation() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+
+    let node = TestNode::builder(&network).config(config).create();
+    node.handle
+        .set_connection_info_behaviour(ConnectionInfoBehaviour::Fail(io::ErrorKind::Other));
+    nodes.push(node);
+
+    // The joining node gives up after a bounded number of retries, so polling terminates.
+    poll_and_resend(&mut nodes, &mut []);
+    let _ = nodes.pop();
+
+    // None of the existing nodes added the joining node to their routing tables.
+    assert!(nodes
+                .iter()
+                .all(|node| node.routing_table().len() == min_section_size - 1));
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn node_reconnects_after_idle_connection_drop() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    // All connections except the one between the first two nodes carry keep-alives.
+    let idle_timeout = 5;
+    for node in nodes.iter().skip(2) {
+        node.handle.enable_keepalive(1);
+    }
+    network.set_idle_timeout(Some(idle_timeout));
+    for _ in 0..idle_timeout {
+        network.poll();
+    }
+    assert!(!nodes[0].handle.is_connected(&nodes[1].handle));
+    assert!(nodes[0].handle.is_connected(&nodes[2].handle));
+
+    network.set_idle_timeout(None);
+    poll_and_resend(&mut nodes, &mut []);
+    assert!(nodes[0].handle.is_connected(&nodes[1].handle));
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn flapping_connection_events_coalesced() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let window = Duration::from_secs(60 * 60);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(Endpoint(min_section_size))
+                   .event_coalescing(window)
+                   .create());
+    poll_and_resend(&mut nodes, &mut []);
+    verify_invariant_for_all_nodes(&mut nodes);
+    let observer = min_section_size;
+    let name = nodes[0].name();
+    while let Ok(_) = nodes[observer].inner.try_next_ev() {}
+
+    // Only the connection between the first and the new node goes idle, and is dropped and
+    // re-established repeatedly.
+    let idle_timeout = 5;
+    for node in &nodes[1..observer] {
+        node.handle.enable_keepalive(1);
+    }
+    let flaps = 3;
+    for _ in 0..flaps {
+        network.set_idle_timeout(Some(idle_timeout));
+        for _ in 0..idle_timeout {
+            network.poll();
+        }
+        assert!(!nodes[observer].handle.is_connected(&nodes[0].handle));
+        network.set_idle_timeout(None);
+        poll_and_resend(&mut nodes, &mut []);
+        assert!(nodes[observer].handle.is_connected(&nodes[0].handle));
+    }
+
+    // A request within the same window is delivered straight away, while the churn is withheld.
+    let requester = Authority::ManagedNode(nodes[1].name());
+    let holder = Authority::ManagedNode(nodes[observer].name());
+    let data_id = gen_immutable_data(&mut rng, 1024).identifier();
+    let message_id = MessageId::new();
+    unwrap!(nodes[1]
+                .inner
+                .send_get_request(requester, holder, data_id, message_id));
+    let _ = poll_all(&mut nodes, &mut []);
+    let mut requests = 0;
+    while let Ok(event) = nodes[observer].inner.try_next_ev() {
+        match event {
+            Event::Request { request: Request::Get(_, msg_id), .. } if msg_id == message_id => {
+                requests += 1
+            }
+            Event::NodeAdded(peer, _) |
+            Event::NodeLost(peer, _) if peer == name => panic!("Unexpected {:?}", event),
+            Event::Coalesced { .. } => panic!("Unexpected {:?}", event),
+            _ => (),
+        }
+    }
+    assert_eq!(requests, 1);
+
+    // Once the window has closed, the number of withheld events is reported. The peer is
+    // connected again, as reported when the window opened, so no other event is raised for it.
+    FakeClock::advance_time(window.as_secs() * 1000 + 1);
+    let _ = nodes[observer].poll();
+    let mut counts = Vec::new();
+    while let Ok(event) = nodes[observer].inner.try_next_ev() {
+        match event {
+            Event::Coalesced { key: CoalescingKey::Node(peer), count } if peer == name => {
+                counts.push(count)
+            }
+            Event::NodeAdded(peer, _) |
+            Event::NodeLost(peer, _) if peer == name => panic!("Unexpected {:?}", event),
+            _ => (),
+        }
+    }
+    assert_eq!(counts, vec![2 * flaps]);
+}
+
+#[test]
+fn old_and_dual_wire_nodes_interoperate() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+
+    // Every other node accepts the next wire format version too, the rest only the base one.
+    let mut nodes = vec![TestNode::builder(&network)
+                             .first()
+                             .endpoint(Endpoint(0))
+                             .create()];
+    nodes[0].poll();
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    for i in 1..min_section_size {
+        let builder = TestNode::builder(&network)
+            .config(config.clone())
+            .endpoint(Endpoint(i));
+        let builder = if i % 2 == 1 {
+            builder.wire_versions(0, 1)
+        } else {
+            builder
+        };
+        nodes.push(builder.create());
+        poll_and_resend(&mut nodes, &mut []);
+    }
+    verify_invariant_for_all_nodes(&mut nodes);
+    for node in &nodes {
+        assert_eq!(node.routing_table().len(), min_section_size - 1);
+    }
+
+    // A client bootstrapping off a dual node can send requests into the network.
+    let client_config = Config::with_contacts(&[nodes[1].handle.endpoint()]);
+    let mut clients = vec![TestClient::new(&network, Some(client_config), None)];
+    let _ = poll_all(&mut nodes, &mut clients);
+    expect_next_event!(clients[0], Event::Connected);
+    let name: XorName = network.new_rng().gen();
+    let dst = Authority::NaeManager(name);
+    unwrap!(clients[0]
+                .inner
+                .send_get_request(dst, DataIdentifier::Immutable(name), MessageId::new()));
+    let _ = poll_all(&mut nodes, &mut clients);
+
+    // Nobody sent the new version before anyone else did, so all peers use the base one, and no
+    // section has upgraded.
+    let mut requests = 0;
+    for node in nodes.iter_mut() {
+        while let Ok(event) = node.inner.try_next_ev() {
+            match event {
+                Event::Request { .. } => requests += 1,
+                Event::WireUpgraded(version) => panic!("Upgraded to wire version {}.", version),
+                _ => (),
+            }
+        }
+    }
+    assert!(requests > 0);
+    for node in &nodes {
+        let versions = unwrap!(node.inner.diagnostics()).peer_wire_versions;
+        for name in node.routing_table().iter() {
+            assert_eq!(versions.get(name), Some(&0));
+        }
+        assert!(versions.values().all(|version| *version == 0));
+    }
+}
+
+#[test]
+fn connect_attempts_paced() {
+    let min_section_size = 8;
+    let max_in_flight = 2;
+    let spacing = Duration::from_secs(1);
+    let network = Network::new(min_section_size, None);
+    let mut nodes = vec![TestNode::builder(&network)
+                             .first()
+                             .endpoint(Endpoint(0))
+                             .connect_pacing(max_in_flight, spacing)
+                             .create()];
+    nodes[0].poll();
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+
+    for i in 1..(2 * min_section_size) {
+        nodes.push(TestNode::builder(&network)
+                       .config(config.clone())
+                       .endpoint(Endpoint(i))
+                       .connect_pacing(max_in_flight, spacing)
+                       .create());
+
+        // Poll one round at a time, checking the connection attempts in flight after each, until
+        // no messages are left and no connection attempts are queued or in flight.
+        let mut idle = false;
+        for _ in 0..1000 {
+            let mut handled_message = false;
+            for node in &mut nodes {
+                handled_message = node.poll() || handled_message;
+            }
+            let mut pending_connects = 0;
+            for node in &mut nodes {
+                if let Ok(diagnostics) = node.inner.diagnostics() {
+                    assert!(diagnostics.connects_in_flight <= max_in_flight,
+                            "{} has {} connection attempts in flight.",
+                            node.name(),
+                            diagnostics.connects_in_flight);
+                    pending_connects += diagnostics.connects_in_flight +
+                                        diagnostics.queued_connects;
+                }
+            }
+            if !handled_message && !nodes[0].handle.reset_message_sent() {
+                if pending_connects == 0 {
+                    idle = true;
+                    break;
+                }
+                FakeClock::advance_time(ACK_TIMEOUT_SECS * 1000 + 1);
+            }
+        }
+        assert!(idle, "Connection attempts still pending after 1000 rounds.");
+        poll_and_resend(&mut nodes, &mut []);
+    }
+
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn settle_waits_for_paced_connects() {
+    let min_section_size = 8;
+    let spacing_secs = 10;
+    let horizon = Duration::from_secs(1);
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    poll_and_resend(&mut nodes, &mut []);
+
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(Endpoint(min_section_size))
+                   .connect_pacing(1, Duration::from_secs(spacing_secs))
+                   .create());
+    let new_index = nodes.len() - 1;
+
+    // Without the clock passing the spacing, the queued connection attempts keep the new node
+    // busy, and the network doesn't settle.
+    match settle(&mut nodes, &mut [], horizon) {
+        Ok(()) => panic!("Settled with connection attempts still queued."),
+        Err(busy) => {
+            let new_name = nodes[new_index].name();
+            assert!(busy.iter()
+                        .any(|&(name, ref work)| name == new_name && work.queued_connects > 0),
+                    "{:?}",
+                    busy);
+        }
+    }
+
+    let mut rounds = 0;
+    while settle(&mut nodes, &mut [], horizon).is_err() {
+        rounds += 1;
+        assert!(rounds < 100, "Network didn't settle.");
+        FakeClock::advance_time(spacing_secs * 1000 + 1);
+    }
+    assert!(rounds > 0);
+    assert!(nodes[new_index].inner.is_idle(horizon));
+}
+
+#[test]
+fn ping_directly_connected_peers() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let name = nodes[1].name();
+
+    // A connected peer replies, and the round-trip time is measured against the mock clock.
+    let reply_rx = unwrap!(nodes[0].inner.ping_peer(name));
+    FakeClock::advance_time(5);
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(unwrap!(reply_rx.try_recv()), Duration::from_millis(5));
+    assert_eq!(1, unwrap!(nodes[1].inner.diagnostics()).pings_answered);
+
+    // A name we aren't connected to is refused immediately.
+    match nodes[0].inner.ping_peer(rng.gen()) {
+        Err(InterfaceError::NotConnected) => (),
+        result => panic!("Expected Err(InterfaceError::NotConnected), got {:?}", result),
+    }
+
+    // A flood of pings is only answered up to the rate limit.
+    FakeClock::advance_time(PING_WINDOW_SECS * 1000);
+    let reply_rxs = (0..(MAX_PINGS_PER_WINDOW + 3))
+        .map(|_| unwrap!(nodes[0].inner.ping_peer(name)))
+        .collect::<Vec<_>>();
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(MAX_PINGS_PER_WINDOW,
+               reply_rxs
+                   .iter()
+                   .filter(|reply_rx| reply_rx.try_recv().is_ok())
+                   .count());
+    let diagnostics = unwrap!(nodes[1].inner.diagnostics());
+    assert_eq!(MAX_PINGS_PER_WINDOW + 1, diagnostics.pings_answered);
+    assert_eq!(3, diagnostics.pings_throttled);
+
+    // Once the window has passed, pings are answered again.
+    FakeClock::advance_time(PING_WINDOW_SECS * 1000);
+    let reply_rx = unwrap!(nodes[0].inner.ping_peer(name));
+    let _ = poll_all(&mut nodes, &mut []);
+    assert!(reply_rx.try_recv().is_ok());
+}
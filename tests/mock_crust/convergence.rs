@@ -0,0 +1,363 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use super::{TestNode, add_node, create_connected_nodes, poll_all, poll_and_resend,
+            verify_invariant_for_all_nodes};
+use fake_clock::FakeClock;
+use rand::Rng;
+use routing::{Event, EventStream, Prefix, XOR_NAME_BITS, XOR_NAME_LEN, XorName, Xorable};
+use routing::mock_crust::{Config, Endpoint, Network};
+use std::collections::BTreeSet;
+use std::time::Duration;
+
+#[test]
+fn node_joins_across_bridged_networks() {
+    let min_section_size = 8;
+    let network_a = Network::new(min_section_size, None);
+    let network_b = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network_a, min_section_size);
+    network_a.bridge(&network_b);
+
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(&network_b).config(config).create());
+    poll_and_resend(&mut nodes, &mut []);
+
+    let index = nodes.len() - 1;
+    expect_any_event!(nodes[index], Event::Connected);
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn bridged_networks_converge() {
+    let min_section_size = 8;
+    let network_a = Network::new(min_section_size, None);
+    let network_b = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network_a, min_section_size);
+
+    // Form a second, isolated network on endpoints the first one doesn't use.
+    let mut other_nodes = vec![TestNode::builder(&network_b)
+                                   .first()
+                                   .endpoint(Endpoint(min_section_size))
+                                   .create()];
+    other_nodes[0].poll();
+    let config = Config::with_contacts(&[other_nodes[0].handle.endpoint()]);
+    for i in 1..min_section_size {
+        other_nodes.push(TestNode::builder(&network_b)
+                             .config(config.clone())
+                             .endpoint(Endpoint(min_section_size + i))
+                             .create());
+        poll_and_resend(&mut other_nodes, &mut []);
+    }
+    verify_invariant_for_all_nodes(&mut other_nodes);
+    assert!(other_nodes
+                .iter()
+                .all(|node| !node.routing_table().has(&nodes[0].name())));
+
+    // Once bridged, the nodes of the second network rejoin via the first one, one at a time.
+    network_a.bridge(&network_b);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    while let Some(node) = other_nodes.pop() {
+        drop(node);
+        nodes.push(TestNode::builder(&network_b)
+                       .config(config.clone())
+                       .create());
+        poll_and_resend(&mut nodes, &mut []);
+        let index = nodes.len() - 1;
+        expect_any_event!(nodes[index], Event::Connected);
+    }
+
+    // The routing tables have converged to a single network holding all the nodes.
+    assert_eq!(nodes.len(), 2 * min_section_size);
+    verify_invariant_for_all_nodes(&mut nodes);
+    let names: BTreeSet<XorName> = nodes.iter().map(TestNode::name).collect();
+    for node in nodes.iter() {
+        let our_section = node.routing_table().our_section();
+        assert!(our_section.iter().all(|name| names.contains(name)));
+        assert!(our_section.len() >= min_section_size);
+    }
+}
+
+// Advances the clock past the next convergence snapshot, and returns the number of
+// `Event::TableConverged` raised by the given node. Its other events are discarded.
+fn next_convergence_snapshot(nodes: &mut [TestNode], index: usize, interval: Duration) -> usize {
+    FakeClock::advance_time(interval.as_secs() * 1000 + 1);
+    let _ = poll_all(nodes, &mut []);
+    let mut converged = 0;
+    while let Ok(event) = nodes[index].try_next_ev() {
+        if let Event::TableConverged(snapshot) = event {
+            assert_eq!(snapshot.table_size, nodes.len() - 1);
+            converged += 1;
+        }
+    }
+    converged
+}
+
+#[test]
+fn table_convergence_tracked_through_growth_and_churn() {
+    let min_section_size = 8;
+    let interval = Duration::from_secs(200);
+    let stable_snapshots = 5;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = vec![TestNode::builder(&network)
+                             .first()
+                             .endpoint(Endpoint(0))
+                             .convergence_tracking(interval, stable_snapshots, min_section_size)
+                             .create()];
+    nodes[0].poll();
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+
+    // Grow the network one node at a time, with a snapshot after each one.
+    for index in 1..(min_section_size + 4) {
+        nodes.push(TestNode::builder(&network)
+                       .config(config.clone())
+                       .endpoint(Endpoint(index))
+                       .create());
+        poll_and_resend(&mut nodes, &mut []);
+        assert_eq!(next_convergence_snapshot(&mut nodes, 0, interval),
+                   0,
+                   "Converged while still growing.");
+    }
+
+    // While nodes only join, our close group can only get closer.
+    let metrics = unwrap!(nodes[0].inner.diagnostics()).convergence;
+    assert!(!metrics.converged);
+    assert_eq!(metrics.current.table_size, nodes.len() - 1);
+    let distances: Vec<_> = metrics
+        .history
+        .iter()
+        .filter_map(|snapshot| snapshot.close_group_distance)
+        .collect();
+    assert!(!distances.is_empty());
+    assert!(distances.windows(2).all(|pair| pair[0] >= pair[1]),
+            "{:?}",
+            distances);
+
+    // Once growth stops, the table converges, and the event fires only once.
+    let converged: Vec<_> = (0..stable_snapshots + 3)
+        .map(|_| next_convergence_snapshot(&mut nodes, 0, interval))
+        .collect();
+    assert_eq!(converged.iter().sum::<usize>(), 1, "{:?}", converged);
+    assert!(unwrap!(nodes[0].inner.diagnostics()).convergence.converged);
+
+    // Churn resets convergence, and it fires again once the table is stable.
+    let _ = nodes.pop();
+    poll_and_resend(&mut nodes, &mut []);
+    let metrics = unwrap!(nodes[0].inner.diagnostics()).convergence;
+    assert!(!metrics.converged);
+    assert_eq!(metrics.current.table_size, nodes.len() - 1);
+    let converged: Vec<_> = (0..stable_snapshots + 3)
+        .map(|_| next_convergence_snapshot(&mut nodes, 0, interval))
+        .collect();
+    assert_eq!(converged.iter().sum::<usize>(), 1, "{:?}", converged);
+}
+
+#[test]
+fn nodes_with_factory_ids_form_requested_sections() {
+    let min_section_size = 5;
+    let network = Network::new(min_section_size, None);
+    let prefixes = [Prefix::new(1, XorName([0; XOR_NAME_LEN])),
+                    Prefix::new(1, XorName([255; XOR_NAME_LEN]))];
+
+    // The factory's IDs only depend on the network's seed, the index and the prefix.
+    let id_factory = network.id_factory();
+    let pub_id = id_factory.public_id(3, &prefixes[1]);
+    assert_eq!(pub_id, id_factory.public_id(3, &prefixes[1]));
+    assert_ne!(pub_id, id_factory.public_id(4, &prefixes[1]));
+    assert!(prefixes[1].matches(pub_id.name()));
+
+    // Add eight nodes, the minimum split size, to each half, so that the network splits in two.
+    let mut nodes = Vec::new();
+    for index in 0..16 {
+        add_node(&network, &mut nodes, Some(prefixes[index % 2]));
+    }
+
+    let names = nodes.iter().map(TestNode::name).collect::<Vec<_>>();
+    for (index, node) in nodes.iter().enumerate() {
+        let expected_section = names
+            .iter()
+            .enumerate()
+            .filter(|&(name_index, _)| name_index % 2 == index % 2)
+            .map(|(_, name)| *name)
+            .collect::<BTreeSet<_>>();
+        assert_eq!(*node.routing_table().our_prefix(), prefixes[index % 2]);
+        assert_eq!(*node.routing_table().our_section(), expected_section);
+    }
+}
+
+#[test]
+fn nodes_agree_on_close_groups() {
+    let min_section_size = 5;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let prefixes = [Prefix::new(2, XorName([0; XOR_NAME_LEN])),
+                    Prefix::new(2, XorName([0b0100_0000; XOR_NAME_LEN])),
+                    Prefix::new(1, XorName([255; XOR_NAME_LEN]))];
+
+    // Form sections 00, 01 and 1 with eight nodes each. Section 1 fills up first, so that the
+    // network splits in two before section 0 splits again.
+    let mut sequence = Vec::new();
+    for index in 0..8 {
+        sequence.push(prefixes[index % 2]);
+        sequence.push(prefixes[2]);
+    }
+    for index in 0..8 {
+        sequence.push(prefixes[index % 2]);
+    }
+    let mut nodes = Vec::new();
+    for prefix in sequence {
+        add_node(&network, &mut nodes, Some(prefix));
+    }
+    for node in &nodes {
+        assert!(prefixes.contains(node.routing_table().our_prefix()));
+    }
+
+    // Each node knows every other one, so all nodes must compute the same groups. Addresses in
+    // section 1 are equally close to sections 00 and 01 by prefix, and a name with a flipped last
+    // bit is nearly tied with the original one.
+    let names = nodes.iter().map(TestNode::name).collect::<Vec<_>>();
+    let mut addresses = names
+        .iter()
+        .map(|name| name.with_flipped_bit(XOR_NAME_BITS - 1))
+        .collect::<Vec<_>>();
+    for prefix in &prefixes {
+        for _ in 0..4 {
+            addresses.push(prefix.substituted_in(rng.gen()));
+        }
+    }
+    for address in addresses {
+        let mut sorted_names = names.clone();
+        sorted_names.sort_by(|lhs, rhs| address.cmp_closeness(lhs, rhs));
+        for count in 1..(names.len() + 1) {
+            let expected = &sorted_names[..count];
+            for node in &nodes {
+                match node.inner.close_group(address, count) {
+                    Some(group) => assert_eq!(group, expected),
+                    None => assert!(!expected.contains(&node.name())),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn connection_audit_repairs_drift() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    let interval_secs = 10;
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(Endpoint(min_section_size))
+                   .connection_audit(Duration::from_secs(interval_secs),
+                                     Duration::from_secs(interval_secs / 2))
+                   .create());
+    poll_and_resend(&mut nodes, &mut []);
+    let auditor = nodes.len() - 1;
+    while nodes[auditor].inner.try_next_ev().is_ok() {}
+
+    // A connection Crust lost track of is reported by the next audit, and its routing table entry
+    // dropped by the one after the grace period.
+    let unconnected_name = nodes[1].name();
+    network.forget_connection(nodes[auditor].handle.endpoint(), nodes[1].handle.endpoint());
+    FakeClock::advance_time(interval_secs * 1000 + 1);
+    let _ = poll_all(&mut nodes, &mut []);
+    expect_any_event!(nodes[auditor],
+                      Event::ConnectionAudit(ref report)
+                          if report.pending.contains(&unconnected_name));
+    FakeClock::advance_time(interval_secs * 1000 + 1);
+    let _ = poll_all(&mut nodes, &mut []);
+    expect_any_event!(nodes[auditor],
+                      Event::ConnectionAudit(ref report)
+                          if report.dropped_entries == vec![unconnected_name]);
+    assert_eq!(1, unwrap!(nodes[auditor].inner.diagnostics()).audit_repairs);
+
+    // A live connection to a peer we forgot about is closed the same way.
+    let unknown_name = nodes[2].name();
+    nodes[auditor].inner.forget_peer(&unknown_name);
+    FakeClock::advance_time(interval_secs * 1000 + 1);
+    let _ = poll_all(&mut nodes, &mut []);
+    expect_any_event!(nodes[auditor],
+                      Event::ConnectionAudit(ref report)
+                          if report.pending.contains(&unknown_name));
+    FakeClock::advance_time(interval_secs * 1000 + 1);
+    let _ = poll_all(&mut nodes, &mut []);
+    expect_any_event!(nodes[auditor],
+                      Event::ConnectionAudit(ref report)
+                          if report.closed_connections == vec![unknown_name]);
+    assert_eq!(2, unwrap!(nodes[auditor].inner.diagnostics()).audit_repairs);
+}
+
+#[test]
+fn table_gossip_closes_blind_spots() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let interval_secs = 10;
+    let interval = Duration::from_secs(interval_secs);
+    let (fanout, sample_size) = (3, 6);
+
+    // Form the network with gossip enabled on every node.
+    let mut nodes = vec![TestNode::builder(&network)
+                             .first()
+                             .endpoint(Endpoint(0))
+                             .table_gossip(interval, fanout, sample_size)
+                             .create()];
+    nodes[0].poll();
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    for i in 1..(min_section_size + 4) {
+        nodes.push(TestNode::builder(&network)
+                       .config(config.clone())
+                       .endpoint(Endpoint(i))
+                       .table_gossip(interval, fanout, sample_size)
+                       .create());
+        poll_and_resend(&mut nodes, &mut []);
+    }
+    verify_invariant_for_all_nodes(&mut nodes);
+
+    // Then make two of them lose track of each other, without either noticing.
+    let (name_1, name_2) = (nodes[1].name(), nodes[2].name());
+    network.forget_connection(nodes[1].handle.endpoint(), nodes[2].handle.endpoint());
+    nodes[1].inner.forget_peer(&name_2);
+    nodes[2].inner.forget_peer(&name_1);
+    assert!(!nodes[1].routing_table().has(&name_2));
+    assert!(!nodes[2].routing_table().has(&name_1));
+
+    // Samples from their common peers let them find each other again within a few rounds.
+    let max_rounds = 10;
+    let mut rounds = 0;
+    while !nodes[1].routing_table().has(&name_2) || !nodes[2].routing_table().has(&name_1) {
+        assert!(rounds < max_rounds,
+                "Blind spot not closed after {} gossip rounds.",
+                max_rounds);
+        let sent_before: Vec<usize> = nodes
+            .iter_mut()
+            .map(|node| unwrap!(node.inner.diagnostics()).gossip_samples_sent)
+            .collect();
+        FakeClock::advance_time(interval_secs * 1000 + 1);
+        let _ = poll_all(&mut nodes, &mut []);
+        for (node, before) in nodes.iter_mut().zip(sent_before) {
+            let sent = unwrap!(node.inner.diagnostics()).gossip_samples_sent - before;
+            assert!(sent <= fanout, "{} samples sent in one round.", sent);
+        }
+        rounds += 1;
+    }
+    verify_invariant_for_all_nodes(&mut nodes);
+    assert!(nodes
+                .iter_mut()
+                .all(|node| unwrap!(node.inner.diagnostics()).gossip_samples_throttled == 0));
+}
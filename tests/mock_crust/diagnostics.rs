@@ -0,0 +1,152 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use super::{TestNode, create_connected_nodes, poll_all, wait_for, with_watchdog};
+use fake_clock::FakeClock;
+use routing::{Authority, DataIdentifier, Event, EventStream, MessageId, RingBufferSink, XorName};
+use routing::mock_crust::{Config, Endpoint, Network};
+use std::time::Duration;
+
+#[test]
+fn diagnostics() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    for node in &mut *nodes {
+        let diagnostics = unwrap!(node.inner.diagnostics());
+        assert!(diagnostics.incoming_filter_len > 0);
+        assert!(diagnostics.outgoing_filter_len > 0);
+        assert_eq!(0, diagnostics.incoming_filter_evictions);
+    }
+}
+
+#[test]
+fn relocation_requests_handled_once() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    let (hits, misses) = nodes
+        .iter_mut()
+        .map(|node| unwrap!(node.inner.diagnostics()))
+        .fold((0, 0), |(hits, misses), diagnostics| {
+            (hits + diagnostics.relocation_cache_hits,
+             misses + diagnostics.relocation_cache_misses)
+        });
+    assert_eq!(0, hits);
+    assert!(misses > 0);
+}
+
+#[test]
+fn expiring_caches_reported() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let caches = unwrap!(nodes[0].inner.diagnostics()).caches;
+    for label in &["banned_peers", "connection_cache", "identify_nonces"] {
+        assert!(caches.contains_key(*label), "{} not reported", label);
+    }
+
+    let ban_secs = 60;
+    unwrap!(nodes[0]
+                .inner
+                .ban_peer(nodes[1].name(), Duration::from_secs(ban_secs)));
+    let _ = poll_all(&mut nodes, &mut []);
+    let banned = unwrap!(nodes[0].inner.diagnostics()).caches["banned_peers"];
+    assert_eq!((1, 1, 0), (banned.len, banned.insertions, banned.expirations));
+
+    FakeClock::advance_time(ban_secs * 1000 + 1);
+    let banned = unwrap!(nodes[0].inner.diagnostics()).caches["banned_peers"];
+    assert_eq!((0, 1, 1), (banned.len, banned.insertions, banned.expirations));
+}
+
+#[test]
+#[should_panic(expected = "Endpoint(0) -> Endpoint(1): Blocked")]
+fn watchdog_dumps_state_of_hanging_test() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, 3);
+    network.enable_send_confirmations(true);
+    let endpoints: Vec<_> = nodes.iter().map(|node| node.handle.endpoint()).collect();
+    for &sender in &endpoints {
+        for &receiver in &endpoints {
+            if sender != receiver {
+                network.block_connection(sender, receiver);
+            }
+        }
+    }
+
+    // The request can't get through, so waiting for it would hang without the watchdog.
+    let _watchdog = with_watchdog(&network, 50, Duration::from_secs(60));
+    let src = Authority::ManagedNode(nodes[0].name());
+    let dst = Authority::ManagedNode(nodes[1].name());
+    let data_id = DataIdentifier::Immutable(XorName([1; 32]));
+    unwrap!(nodes[0]
+                .inner
+                .send_get_request(src, dst, data_id, MessageId::new()));
+    let _ = wait_for(&mut nodes, &mut [], |_, event| match *event {
+        Event::Request { .. } => true,
+        _ => false,
+    });
+}
+
+#[test]
+fn events_delivered_to_ring_buffer_sink() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    // With enough room, the sink receives all events, and none are left to read from the node.
+    let roomy_sink = RingBufferSink::new(100);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(Endpoint(nodes.len()))
+                   .event_sink(Box::new(roomy_sink.clone()))
+                   .create());
+    let _ = poll_all(&mut nodes, &mut []);
+    let events = roomy_sink.try_drain();
+    assert!(events.iter().any(|event| match *event {
+                                  Event::Connected => true,
+                                  _ => false,
+                              }));
+    assert!(events.iter().any(|event| match *event {
+                                  Event::NodeAdded(..) => true,
+                                  _ => false,
+                              }));
+    assert_eq!(0, roomy_sink.dropped());
+    assert!(roomy_sink.try_drain().is_empty());
+    let roomy_node = nodes.len() - 1;
+    expect_no_event!(nodes[roomy_node]);
+
+    // An undersized sink only keeps the latest events, and counts the ones it displaced.
+    let tight_sink = RingBufferSink::new(1);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(Endpoint(nodes.len()))
+                   .event_sink(Box::new(tight_sink.clone()))
+                   .create());
+    let _ = poll_all(&mut nodes, &mut []);
+    let events = tight_sink.try_drain();
+    assert_eq!(1, events.len());
+    if let Event::Connected = events[0] {
+        panic!("Expected the first event to be displaced.");
+    }
+    assert!(tight_sink.dropped() >= min_section_size as u64);
+}
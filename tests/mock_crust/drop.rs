@@ -15,9 +15,14 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
-use super::{TestNode, create_connected_nodes, poll_all, verify_invariant_for_all_nodes};
-use routing::{Event, EventStream};
-use routing::mock_crust::Network;
+use super::{TestNode, create_connected_nodes, create_connected_nodes_until_split, poll_all,
+            poll_and_resend, verify_invariant_for_all_nodes};
+use fake_clock::FakeClock;
+use rand::Rng;
+use routing::{Authority, DataIdentifier, Event, EventStream, Health, MessageId, Prefix, PublicId,
+              Request, XorName};
+use routing::mock_crust::{Config, Endpoint, Network};
+use routing::test_consts::RECOVERY_RETRY_SECS;
 
 // Drop node at index and verify its own section receives NodeLost.
 fn drop_node(nodes: &mut Vec<TestNode>, index: usize) {
@@ -69,3 +74,318 @@ fn node_restart() {
 
     expect_next_event!(nodes[0], Event::RestartRequired);
 }
+
+// Adds a node with disconnected recovery enabled to a network of `min_section_size + 1` nodes, and
+// cuts it off from everyone else. Returns its endpoint and the other nodes' endpoints.
+fn create_disconnected_node(network: &Network<PublicId>,
+                            min_section_size: usize)
+                            -> (Vec<TestNode>, Endpoint, Vec<Endpoint>) {
+    let mut nodes = create_connected_nodes(network, min_section_size + 1);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(network)
+                   .config(config)
+                   .endpoint(Endpoint(min_section_size + 1))
+                   .disconnected_recovery(1)
+                   .create());
+    poll_and_resend(&mut nodes, &mut []);
+    let index = nodes.len() - 1;
+    expect_any_event!(nodes[index], Event::Connected);
+    while nodes[index].try_next_ev().is_ok() {}
+
+    let endpoint = nodes[index].handle.endpoint();
+    let others: Vec<_> = nodes[..index]
+        .iter()
+        .map(|node| node.handle.endpoint())
+        .collect();
+    network.partition(&[endpoint], &others);
+    for peer in nodes[index].handle.connected_endpoints() {
+        network.lost_connection_or_panic(endpoint, peer);
+    }
+    let _ = poll_all(&mut nodes, &mut []);
+    expect_any_event!(nodes[index], Event::Disconnected);
+    assert!(nodes[index].inner.is_disconnected());
+    assert!(nodes[index].routing_table().is_empty());
+    (nodes, endpoint, others)
+}
+
+#[test]
+fn disconnected_node_rejoins() {
+    let min_section_size = 5;
+    let network = Network::new(min_section_size, None);
+    let (mut nodes, endpoint, others) = create_disconnected_node(&network, min_section_size);
+    let index = nodes.len() - 1;
+
+    // Requests are queued, up to the limit.
+    let src = Authority::ManagedNode(nodes[index].name());
+    let dst = Authority::ManagedNode(nodes[0].name());
+    let data_id = DataIdentifier::Immutable(XorName([1; 32]));
+    let msg_id = MessageId::new();
+    unwrap!(nodes[index]
+                .inner
+                .send_get_request(src, dst, data_id, msg_id));
+    assert!(nodes[index]
+                .inner
+                .send_get_request(src, dst, data_id, MessageId::new())
+                .is_err());
+    let _ = poll_all(&mut nodes, &mut []);
+    for node in &mut nodes[..index] {
+        while let Ok(event) = node.try_next_ev() {
+            if let Event::Request { .. } = event {
+                panic!("{} received a request from a disconnected node.", node.name());
+            }
+        }
+    }
+
+    // Once reachable again, the node rejoins via its contact on the next retry and sends the
+    // queued request.
+    network.heal_partition(&[endpoint], &others);
+    FakeClock::advance_time(RECOVERY_RETRY_SECS * 1000 + 1);
+    poll_and_resend(&mut nodes, &mut []);
+    expect_any_event!(nodes[index], Event::Reconnected);
+    assert!(!nodes[index].inner.is_disconnected());
+    expect_any_event!(nodes[0], Event::Request {
+        request: Request::Get(_, id),
+        src: Authority::ManagedNode(_),
+        ..
+    } if id == msg_id);
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn disconnected_node_claims_no_authority() {
+    let min_section_size = 5;
+    let network = Network::new(min_section_size, None);
+    let (mut nodes, endpoint, others) = create_disconnected_node(&network, min_section_size);
+    let index = nodes.len() - 1;
+    let name = nodes[index].name();
+    let auths = [Authority::ManagedNode(name),
+                 Authority::NaeManager(name),
+                 Authority::Section(name)];
+    assert!(auths
+                .iter()
+                .all(|auth| !nodes[index].inner.in_authority(auth)));
+
+    // While the node rejoins, it claims no authority even once its routing table is no longer
+    // empty, until it is connected to a quorum of its section again.
+    network.heal_partition(&[endpoint], &others);
+    FakeClock::advance_time(RECOVERY_RETRY_SECS * 1000 + 1);
+    while nodes.iter_mut().any(TestNode::poll) {
+        if nodes[index].inner.is_disconnected() {
+            assert!(auths
+                        .iter()
+                        .all(|auth| !nodes[index].inner.in_authority(auth)));
+        }
+    }
+    poll_and_resend(&mut nodes, &mut []);
+    expect_any_event!(nodes[index], Event::Reconnected);
+    assert!(!nodes[index].routing_table().is_empty());
+    assert!(auths
+                .iter()
+                .all(|auth| nodes[index].inner.in_authority(auth)));
+}
+
+// Returns the health changes raised by the node, as pairs of the health and connected count.
+fn health_changes(node: &mut TestNode) -> Vec<(Health, usize)> {
+    let mut changes = Vec::new();
+    while let Ok(event) = node.try_next_ev() {
+        if let Event::HealthChanged { health, connected, .. } = event {
+            changes.push((health, connected));
+        }
+    }
+    changes
+}
+
+#[test]
+fn health_changes_as_section_shrinks_and_recovers() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = vec![TestNode::builder(&network)
+                             .first()
+                             .endpoint(Endpoint(0))
+                             .health_events()
+                             .create()];
+    nodes[0].poll();
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    let mut next_endpoint = 1;
+    let mut add_node = |nodes: &mut Vec<TestNode>| {
+        nodes.push(TestNode::builder(&network)
+                       .config(config.clone())
+                       .endpoint(Endpoint(next_endpoint))
+                       .create());
+        next_endpoint += 1;
+        poll_and_resend(nodes, &mut []);
+    };
+
+    // Quorum of the min section size is reached at five nodes, full health at eight.
+    for _ in 1..min_section_size {
+        add_node(&mut nodes);
+    }
+    assert_eq!(health_changes(&mut nodes[0]),
+               vec![(Health::Degraded, 5), (Health::Healthy, 8)]);
+
+    for connected in (4..min_section_size).rev() {
+        let _ = nodes.pop();
+        poll_and_resend(&mut nodes, &mut []);
+        let expected = match connected {
+            7 => vec![(Health::Degraded, 7)],
+            4 => vec![(Health::Critical, 4)],
+            _ => vec![],
+        };
+        assert_eq!(health_changes(&mut nodes[0]), expected);
+    }
+
+    for connected in 5..(min_section_size + 1) {
+        add_node(&mut nodes);
+        let expected = match connected {
+            5 => vec![(Health::Degraded, 5)],
+            8 => vec![(Health::Healthy, 8)],
+            _ => vec![],
+        };
+        assert_eq!(health_changes(&mut nodes[0]), expected);
+    }
+}
+
+// Creates a section of `size` nodes with departure consensus enabled, and drains their events.
+fn create_departure_consensus_nodes(network: &Network<PublicId>, size: usize) -> Vec<TestNode> {
+    let mut nodes = vec![TestNode::builder(network)
+                             .first()
+                             .endpoint(Endpoint(0))
+                             .departure_consensus()
+                             .create()];
+    nodes[0].poll();
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    for index in 1..size {
+        nodes.push(TestNode::builder(network)
+                       .config(config.clone())
+                       .endpoint(Endpoint(index))
+                       .departure_consensus()
+                       .create());
+        poll_and_resend(&mut nodes, &mut []);
+    }
+    for node in &mut nodes {
+        while node.try_next_ev().is_ok() {}
+    }
+    nodes
+}
+
+// Drains the node's events and returns the names it declared departed.
+fn departures(node: &mut TestNode) -> Vec<XorName> {
+    let mut names = Vec::new();
+    while let Ok(event) = node.try_next_ev() {
+        if let Event::NodeDeparted(name) = event {
+            names.push(name);
+        }
+    }
+    names
+}
+
+#[test]
+fn connection_lost_by_one_peer_is_no_departure() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_departure_consensus_nodes(&network, min_section_size);
+    let (endpoint_0, endpoint_1) = (nodes[0].handle.endpoint(), nodes[1].handle.endpoint());
+    let name_0 = nodes[0].name();
+    let name_1 = nodes[1].name();
+
+    network.block_connection(endpoint_0, endpoint_1);
+    network.block_connection(endpoint_1, endpoint_0);
+    network.lost_connection_or_panic(endpoint_0, endpoint_1);
+    poll_and_resend(&mut nodes, &mut []);
+
+    // Both nodes lost each other, but nobody else agrees, so neither has departed.
+    expect_any_event!(nodes[0], Event::NodeLost(name, _) if name == name_1);
+    expect_any_event!(nodes[1], Event::NodeLost(name, _) if name == name_0);
+    for node in &mut nodes {
+        assert_eq!(departures(node), Vec::<XorName>::new());
+    }
+}
+
+#[test]
+fn crashed_node_departs_once_on_all_members() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_departure_consensus_nodes(&network, min_section_size);
+    let crashed = nodes.remove(min_section_size / 2);
+    let crashed_name = crashed.name();
+
+    drop(crashed);
+    poll_and_resend(&mut nodes, &mut []);
+
+    for node in &mut nodes {
+        assert_eq!(departures(node), vec![crashed_name]);
+        let caches = unwrap!(node.inner.diagnostics()).caches;
+        assert_eq!(caches["departure_observations"].len, 0);
+        assert_eq!(caches["departed_members"].len, 1);
+    }
+}
+
+// Adds a node bootstrapping off `nodes[0]`, relocated to the section opposite its proxy's, so
+// that the proxy doesn't become one of its routing table entries. Returns the section's prefix.
+fn add_node_relocated_away_from_proxy(network: &Network<PublicId>,
+                                      nodes: &mut Vec<TestNode>,
+                                      proxy_drop_threshold: Option<usize>)
+                                      -> Prefix<XorName> {
+    let mut rng = network.new_rng();
+    let proxy_prefix = *nodes[0].routing_table().our_prefix();
+    let target_prefix = proxy_prefix.with_flipped_bit(0).with_flipped_bit(1);
+    let relocation_name = target_prefix.substituted_in(rng.gen());
+    for node in nodes.iter_mut() {
+        node.inner.set_next_relocation_dst(relocation_name);
+        node.inner
+            .set_next_relocation_interval((target_prefix.lower_bound(),
+                                           target_prefix.upper_bound()));
+    }
+
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    let endpoint = Endpoint(nodes.len());
+    let builder = TestNode::builder(network).config(config).endpoint(endpoint);
+    let builder = match proxy_drop_threshold {
+        Some(entries) => builder.proxy_drop_threshold(entries),
+        None => builder,
+    };
+    nodes.push(builder.create());
+    poll_and_resend(nodes, &mut []);
+    target_prefix
+}
+
+#[test]
+fn proxy_dropped_once_established() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes_until_split(&network, vec![2, 2, 2, 2], false);
+    let target_prefix = add_node_relocated_away_from_proxy(&network, &mut nodes, None);
+
+    expect_any_event!(unwrap!(nodes.last_mut()), Event::ProxyDropped);
+    {
+        let joined_node = unwrap!(nodes.last());
+        assert!(target_prefix.matches(&joined_node.name()));
+        assert!(!nodes[0].handle.is_connected(&joined_node.handle));
+        assert!(!joined_node.handle.is_connected(&nodes[0].handle));
+        assert!(!joined_node.routing_table().has(&nodes[0].name()));
+    }
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn proxy_kept_below_drop_threshold() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes_until_split(&network, vec![2, 2, 2, 2], false);
+    let threshold = nodes.len() + 1;
+    let target_prefix = add_node_relocated_away_from_proxy(&network, &mut nodes, Some(threshold));
+
+    // The routing table can't reach the threshold, so the proxy connection is kept.
+    {
+        let joined_node = unwrap!(nodes.last_mut());
+        assert!(target_prefix.matches(&joined_node.name()));
+        assert!(joined_node.routing_table().len() < threshold);
+        while let Ok(event) = joined_node.inner.try_next_ev() {
+            if let Event::ProxyDropped = event {
+                panic!("Unexpected {:?}", event);
+            }
+        }
+    }
+    assert!(nodes[0].handle.is_connected(&unwrap!(nodes.last()).handle));
+    verify_invariant_for_all_nodes(&mut nodes);
+}
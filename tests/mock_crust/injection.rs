@@ -0,0 +1,575 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use super::{TestClient, TestNode, poll_all, poll_and_resend};
+use rand;
+use routing::{Authority, CryptoError, Data, DataIdentifier, Event, EventStream, FullId,
+              ImmutableData, InterfaceError, MessageId, Node, PublicId, Request, RoutingError,
+              signed_message_encodings};
+use routing::mock_crust::{self, Config, Endpoint, Network, fail_crypto_init, fail_signing,
+                          fail_verification};
+use routing::test_consts::MAX_PROTOCOL_VIOLATIONS;
+use routing::test_messages::TestMessage;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+// Creates a section whose first node, which is never relocated, uses `first_id`.
+fn create_nodes(network: &Network<PublicId>, first_id: FullId, size: usize) -> Vec<TestNode> {
+    let mut nodes = vec![TestNode::builder(network)
+                             .first()
+                             .full_id(first_id)
+                             .endpoint(Endpoint(0))
+                             .create()];
+    nodes[0].poll();
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    for i in 1..size {
+        nodes.push(TestNode::builder(network)
+                       .config(config.clone())
+                       .endpoint(Endpoint(i))
+                       .create());
+        poll_and_resend(&mut nodes, &mut []);
+    }
+    nodes
+}
+
+fn count_requests(node: &mut TestNode) -> usize {
+    let mut count = 0;
+    while let Ok(event) = node.try_next_ev() {
+        if let Event::Request { .. } = event {
+            count += 1;
+        }
+    }
+    count
+}
+
+// Returns the only error `node` encountered since the last call.
+fn take_single_error(node: &mut TestNode) -> RoutingError {
+    let mut errors = node.inner.take_message_errors();
+    assert_eq!(errors.len(), 1, "Unexpected errors {:?}", errors);
+    errors.remove(0)
+}
+
+fn get_request() -> Request {
+    Request::Get(DataIdentifier::Immutable(rand::random()), MessageId::new())
+}
+
+#[test]
+fn injected_messages() {
+    let min_section_size = 4;
+    let network = Network::new(min_section_size, None);
+    let sender_id = FullId::new();
+    let mut nodes = create_nodes(&network, sender_id.clone(), min_section_size + 1);
+    let sender_ep = nodes[0].handle.endpoint();
+    let receiver_name = nodes[1].name();
+    let _ = count_requests(&mut nodes[1]);
+    let _ = nodes[1].inner.take_message_errors();
+
+    let client_id = FullId::new();
+    let client = Authority::Client {
+        client_id: *client_id.public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let dst = Authority::ManagedNode(receiver_name);
+
+    // Bytes from an endpoint we aren't connected to are rejected.
+    let bytes = TestMessage::request(client, dst, get_request()).to_bytes(&client_id, &sender_id);
+    match nodes[1].inner.inject_message_for_test(Endpoint(1000), bytes.clone()) {
+        Err(InterfaceError::NotConnected) => (),
+        result => panic!("Unexpected result {:?}", result),
+    }
+
+    // A correctly signed request is delivered like one received via Crust.
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(count_requests(&mut nodes[1]), 1);
+    assert!(nodes[1].inner.take_message_errors().is_empty());
+
+    // A client request must be signed by the client.
+    let bytes = TestMessage::request(client, dst, get_request()).to_bytes(&sender_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(count_requests(&mut nodes[1]), 0);
+    match take_single_error(&mut nodes[1]) {
+        RoutingError::FailedSignature => (),
+        error => panic!("Unexpected error {:?}", error),
+    }
+
+    // The hop must be signed by the peer it arrived from.
+    let bytes = TestMessage::request(client, dst, get_request())
+        .to_bytes(&client_id, &FullId::new());
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(count_requests(&mut nodes[1]), 0);
+    match take_single_error(&mut nodes[1]) {
+        RoutingError::FailedSignature => (),
+        error => panic!("Unexpected error {:?}", error),
+    }
+
+    // Connection info requests aren't accepted from a client to a client manager.
+    let client_manager = Authority::ClientManager(receiver_name);
+    let bytes = TestMessage::connection_info_request(client, client_manager, *client_id.public_id())
+        .to_bytes(&client_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    match take_single_error(&mut nodes[1]) {
+        RoutingError::BadAuthority => (),
+        error => panic!("Unexpected error {:?}", error),
+    }
+
+    // Asking us to connect to ourselves is a protocol violation by the sender.
+    let violations = unwrap!(nodes[1].inner.diagnostics()).protocol_violations;
+    let src = Authority::ManagedNode(nodes[0].name());
+    let receiver_id = nodes[1].id();
+    let bytes = TestMessage::connection_info_request(src, dst, receiver_id)
+        .to_bytes(&sender_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    match take_single_error(&mut nodes[1]) {
+        RoutingError::InvalidPeer => (),
+        error => panic!("Unexpected error {:?}", error),
+    }
+    assert_eq!(unwrap!(nodes[1].inner.diagnostics()).protocol_violations,
+               violations + 1);
+}
+
+#[test]
+fn relayed_message_keeps_received_bytes() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let sender_id = FullId::new();
+    let mut nodes = create_nodes(&network, sender_id.clone(), min_section_size + 1);
+    let sender_ep = nodes[0].handle.endpoint();
+    let relay_ep = nodes[1].handle.endpoint();
+
+    // A large request from a client to the section, which the receiver relays to all the other
+    // members apart from the one it came from.
+    let client_id = FullId::new();
+    let client = Authority::Client {
+        client_id: *client_id.public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let dst = Authority::Section(nodes[1].name());
+    let data = Data::Immutable(ImmutableData::new(vec![7; 16 * 1024]));
+    let msg = TestMessage::request(client, dst, Request::Put(data, MessageId::new()));
+    let signed_bytes = msg.signed_bytes(&client_id);
+
+    let relayed = Rc::new(RefCell::new(Vec::new()));
+    let relayed_clone = relayed.clone();
+    network.set_packet_observer(move |packet| if let Some(payload) = packet.payload {
+                                    if packet.sender == relay_ep &&
+                                       payload.len() > signed_bytes.len() {
+                                        let identical = payload
+                                            .windows(signed_bytes.len())
+                                            .any(|window| window == &signed_bytes[..]);
+                                        relayed_clone
+                                            .borrow_mut()
+                                            .push((packet.receiver, identical));
+                                    }
+                                });
+
+    // The relay signs its own ack, which is only sent once the section's signatures are
+    // accumulated. It doesn't encode the request again, however many members it sends it to.
+    let bytes = msg.to_bytes(&client_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let encodings = signed_message_encodings();
+    let _ = nodes[1].poll();
+    assert_eq!(signed_message_encodings(), encodings);
+    let _ = poll_all(&mut nodes, &mut []);
+    network.clear_packet_observer();
+
+    // Every member received the request as the client encoded and signed it.
+    let receivers: BTreeSet<_> = relayed
+        .borrow()
+        .iter()
+        .map(|&(receiver, identical)| {
+                 assert!(identical, "Relayed request to {:?} was encoded anew.", receiver);
+                 receiver
+             })
+        .collect();
+    let expected: BTreeSet<_> = nodes[2..]
+        .iter()
+        .map(|node| node.handle.endpoint())
+        .collect();
+    assert_eq!(receivers, expected);
+}
+
+#[test]
+fn forged_request_cancellation_ignored() {
+    let min_section_size = 4;
+    let network = Network::new(min_section_size, None);
+    let sender_id = FullId::new();
+    let mut nodes = create_nodes(&network, sender_id.clone(), min_section_size + 1);
+    let sender_ep = nodes[0].handle.endpoint();
+    let _ = count_requests(&mut nodes[1]);
+    let _ = nodes[1].inner.take_message_errors();
+
+    let client_id = FullId::new();
+    let client = Authority::Client {
+        client_id: *client_id.public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let dst = Authority::ManagedNode(nodes[1].name());
+    let request = get_request();
+    let msg_id = request.message_id();
+    let bytes = TestMessage::request(client, dst, request).to_bytes(&client_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(count_requests(&mut nodes[1]), 1);
+
+    // A cancellation whose message ID isn't signed by the requesting client is rejected.
+    let bytes = TestMessage::cancel_request(client, dst, msg_id, &FullId::new())
+        .to_bytes(&client_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    match take_single_error(&mut nodes[1]) {
+        RoutingError::FailedSignature => (),
+        error => panic!("Unexpected error {:?}", error),
+    }
+    while let Ok(event) = nodes[1].try_next_ev() {
+        if let Event::RequestCancelled { .. } = event {
+            panic!("Unexpected event {:?}", event);
+        }
+    }
+
+    // The client's own cancellation is accepted.
+    let bytes = TestMessage::cancel_request(client, dst, msg_id, &client_id)
+        .to_bytes(&client_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    expect_any_event!(nodes[1], Event::RequestCancelled { message_id } if message_id == msg_id);
+}
+
+#[test]
+fn unrequested_connection_info_response_dropped() {
+    let min_section_size = 4;
+    let network = Network::new(min_section_size, None);
+    let sender_id = FullId::new();
+    let mut nodes = create_nodes(&network, sender_id.clone(), min_section_size + 1);
+    let sender_ep = nodes[0].handle.endpoint();
+    let _ = nodes[1].inner.take_message_errors();
+
+    // The connection info exchange while joining put both nodes into each other's tables.
+    assert!(nodes[0].routing_table().has(&nodes[1].name()));
+    assert!(nodes[1].routing_table().has(&nodes[0].name()));
+    let table_len = nodes[1].routing_table().len();
+
+    // A response from a peer we never sent a request to isn't acted on.
+    let stranger_id = FullId::new();
+    let src = Authority::ManagedNode(*stranger_id.public_id().name());
+    let dst = Authority::ManagedNode(nodes[1].name());
+    let bytes = TestMessage::connection_info_response(src, dst, *stranger_id.public_id())
+        .to_bytes(&stranger_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    match take_single_error(&mut nodes[1]) {
+        RoutingError::InvalidDestination => (),
+        error => panic!("Unexpected error {:?}", error),
+    }
+    assert_eq!(unwrap!(nodes[1].inner.diagnostics()).connects_in_flight, 0);
+    assert_eq!(nodes[1].routing_table().len(), table_len);
+}
+
+#[test]
+fn connection_info_request_from_client_is_violation() {
+    let min_section_size = 4;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_nodes(&network, FullId::new(), min_section_size + 1);
+    let config = Config::with_contacts(&[nodes[1].handle.endpoint()]);
+    let mut clients = vec![TestClient::new(&network, Some(config), None)];
+    poll_and_resend(&mut nodes, &mut clients);
+    expect_any_event!(clients[0], Event::Connected);
+    let _ = nodes[1].inner.take_message_errors();
+    let violations = unwrap!(nodes[1].inner.diagnostics()).protocol_violations;
+
+    // Our client asking to connect to us as a node is misdirected. It counts against the client,
+    // until the connection to it is dropped.
+    let client_id = clients[0].full_id.clone();
+    let client_ep = clients[0].handle.endpoint();
+    let src = Authority::Client {
+        client_id: *client_id.public_id(),
+        proxy_node_name: nodes[1].name(),
+    };
+    let dst = Authority::ManagedNode(nodes[1].name());
+    let receiver_id = nodes[1].id();
+    for count in 1..(MAX_PROTOCOL_VIOLATIONS + 1) {
+        assert!(nodes[1].handle.is_connected(&clients[0].handle));
+        let msg = TestMessage::sealed_connection_info_request(src,
+                                                              dst,
+                                                              &client_id,
+                                                              &receiver_id,
+                                                              client_ep);
+        let bytes = msg.to_bytes(&client_id, &client_id);
+        unwrap!(nodes[1].inner.inject_message_for_test(client_ep, bytes));
+        let _ = poll_all(&mut nodes, &mut clients);
+        match take_single_error(&mut nodes[1]) {
+            RoutingError::UnexpectedConnectionInfo => (),
+            error => panic!("Unexpected error {:?}", error),
+        }
+        assert_eq!(unwrap!(nodes[1].inner.diagnostics()).protocol_violations,
+                   violations + count);
+    }
+    assert!(!nodes[1].handle.is_connected(&clients[0].handle));
+}
+
+#[test]
+fn relocate_request_for_existing_name_rejected() {
+    let min_section_size = 4;
+    let network = Network::new(min_section_size, None);
+    let sender_id = FullId::new();
+    let mut nodes = create_nodes(&network, sender_id.clone(), min_section_size + 1);
+    let sender_ep = nodes[0].handle.endpoint();
+    let _ = nodes[1].inner.take_message_errors();
+    let relocation_misses = unwrap!(nodes[1].inner.diagnostics()).relocation_cache_misses;
+
+    // A routing node asking to be relocated again under the name it already holds is refused.
+    let src = Authority::Client {
+        client_id: *sender_id.public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let dst = Authority::Section(nodes[0].name());
+    let bytes = TestMessage::relocate_request(src, dst).to_bytes(&sender_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    match take_single_error(&mut nodes[1]) {
+        RoutingError::InvalidSource => (),
+        error => panic!("Unexpected error {:?}", error),
+    }
+    assert_eq!(unwrap!(nodes[1].inner.diagnostics()).relocation_cache_misses,
+               relocation_misses);
+}
+
+// Returns the sums of all nodes' relocation cache hits and misses, and relocate responses sent.
+fn relocation_totals(nodes: &mut [TestNode]) -> (usize, usize, usize) {
+    nodes
+        .iter_mut()
+        .map(|node| unwrap!(node.inner.diagnostics()))
+        .fold((0, 0, 0), |(hits, misses, responses), diagnostics| {
+            (hits + diagnostics.relocation_cache_hits,
+             misses + diagnostics.relocation_cache_misses,
+             responses + diagnostics.relocate_responses)
+        })
+}
+
+#[test]
+fn repeated_relocate_request_answered_again() {
+    let min_section_size = 4;
+    let network = Network::new(min_section_size, None);
+    let sender_id = FullId::new();
+    let mut nodes = create_nodes(&network, sender_id.clone(), min_section_size + 1);
+    let sender_ep = nodes[0].handle.endpoint();
+    let (hits, misses, responses) = relocation_totals(&mut nodes);
+
+    // A joining node whose proxy is `nodes[0]` asks to be relocated three times. The network
+    // consists of a single section, so every node takes part in relocating it.
+    let joining_id = FullId::new();
+    let src = Authority::Client {
+        client_id: *joining_id.public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let dst = Authority::Section(*joining_id.public_id().name());
+    for attempt in 1..4 {
+        let bytes = TestMessage::relocate_request(src, dst).to_bytes(&joining_id, &sender_id);
+        unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+        let _ = poll_all(&mut nodes, &mut []);
+
+        // Only the first request is assigned a relocated name. Every request is answered with
+        // the same response, which each member of the accepting section sends.
+        assert_eq!(relocation_totals(&mut nodes),
+                   (hits + (attempt - 1) * nodes.len(),
+                    misses + nodes.len(),
+                    responses + attempt * nodes.len()));
+    }
+}
+
+#[test]
+fn relocate_request_for_relocated_name_rejected() {
+    let min_section_size = 4;
+    let network = Network::new(min_section_size, None);
+    let sender_id = FullId::new();
+    let mut nodes = create_nodes(&network, sender_id.clone(), min_section_size + 1);
+    let sender_ep = nodes[0].handle.endpoint();
+
+    // The first joining node is relocated to the second one's name, which isn't in the network.
+    let first_id = FullId::new();
+    let second_id = FullId::new();
+    let second_name = *second_id.public_id().name();
+    nodes[1].inner.set_next_relocation_dst(second_name);
+    let src = Authority::Client {
+        client_id: *first_id.public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let dst = Authority::Section(*first_id.public_id().name());
+    let bytes = TestMessage::relocate_request(src, dst).to_bytes(&first_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    assert!(!nodes[1].routing_table().has(&second_name));
+    let _ = nodes[1].inner.take_message_errors();
+
+    // The second node can't be relocated while it is the first one's pending name.
+    let src = Authority::Client {
+        client_id: *second_id.public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let dst = Authority::Section(second_name);
+    let bytes = TestMessage::relocate_request(src, dst).to_bytes(&second_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    match take_single_error(&mut nodes[1]) {
+        RoutingError::InvalidSource => (),
+        error => panic!("Unexpected error {:?}", error),
+    }
+}
+
+#[test]
+fn signing_failure_drops_message() {
+    let min_section_size = 4;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_nodes(&network, FullId::new(), min_section_size + 1);
+    let src = Authority::ManagedNode(nodes[0].name());
+    let dst = Authority::ManagedNode(nodes[1].name());
+    let _ = count_requests(&mut nodes[1]);
+    while let Ok(_) = nodes[0].try_next_ev() {}
+    let _ = nodes[0].handle.reset_message_sent();
+
+    let data_id = DataIdentifier::Immutable(rand::random());
+    fail_signing(1);
+    unwrap!(nodes[0].inner.send_get_request(src, dst, data_id, MessageId::new()));
+    match nodes[0].try_next_ev() {
+        Ok(Event::SigningFailed {
+               src: failed_src,
+               dst: failed_dst,
+           }) => {
+            assert_eq!(failed_src, src);
+            assert_eq!(failed_dst, dst);
+        }
+        other => panic!("Expected Ok(Event::SigningFailed {{ .. }}), got {:?}", other),
+    }
+    assert!(!nodes[0].handle.reset_message_sent());
+
+    // The message isn't resent either.
+    poll_and_resend(&mut nodes, &mut []);
+    assert_eq!(count_requests(&mut nodes[1]), 0);
+
+    // Only the scripted operation fails.
+    unwrap!(nodes[0].inner.send_get_request(src, dst, data_id, MessageId::new()));
+    poll_and_resend(&mut nodes, &mut []);
+    assert_eq!(count_requests(&mut nodes[1]), 1);
+}
+
+#[test]
+fn verification_failure_is_bad_signature() {
+    let min_section_size = 4;
+    let network = Network::new(min_section_size, None);
+    let sender_id = FullId::new();
+    let mut nodes = create_nodes(&network, sender_id.clone(), min_section_size + 1);
+    let sender_ep = nodes[0].handle.endpoint();
+    let _ = count_requests(&mut nodes[1]);
+    let _ = nodes[1].inner.take_message_errors();
+
+    let client_id = FullId::new();
+    let client = Authority::Client {
+        client_id: *client_id.public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let dst = Authority::ManagedNode(nodes[1].name());
+
+    // A correctly signed request whose verification fails...
+    let bytes = TestMessage::request(client, dst, get_request()).to_bytes(&client_id, &sender_id);
+    fail_verification(1);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = nodes[1].poll();
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(count_requests(&mut nodes[1]), 0);
+    match take_single_error(&mut nodes[1]) {
+        RoutingError::FailedSignature => (),
+        error => panic!("Unexpected error {:?}", error),
+    }
+
+    // ... is handled exactly like one with a bad signature.
+    let bytes = TestMessage::request(client, dst, get_request())
+        .to_bytes(&client_id, &FullId::new());
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = nodes[1].poll();
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(count_requests(&mut nodes[1]), 0);
+    match take_single_error(&mut nodes[1]) {
+        RoutingError::FailedSignature => (),
+        error => panic!("Unexpected error {:?}", error),
+    }
+}
+
+#[test]
+fn crypto_init_failure() {
+    let min_section_size = 4;
+    fail_crypto_init(1);
+    match Network::<PublicId>::try_new(min_section_size, None) {
+        Err(CryptoError::InitFailed) => (),
+        Err(error) => panic!("Unexpected error {:?}", error),
+        Ok(_) => panic!("Unexpected success"),
+    }
+
+    let network = Network::new(min_section_size, None);
+    let handle = network.new_service_handle(None, None);
+    fail_crypto_init(1);
+    let result = mock_crust::make_current(&handle, || {
+        Node::builder().first(true).create(min_section_size)
+    });
+    match result {
+        Err(RoutingError::Crypto(CryptoError::InitFailed)) => (),
+        Err(error) => panic!("Unexpected error {:?}", error),
+        Ok(_) => panic!("Unexpected success"),
+    }
+}
+
+#[test]
+fn looping_message_dropped_at_hop_limit() {
+    let min_section_size = 4;
+    let network = Network::new(min_section_size, None);
+    let sender_id = FullId::new();
+    let mut nodes = create_nodes(&network, sender_id.clone(), min_section_size + 1);
+    let sender_ep = nodes[0].handle.endpoint();
+    let max_hop_count = unwrap!(nodes[1].inner.effective_config()).max_hop_count;
+    let drops = unwrap!(nodes[1].inner.diagnostics()).hop_limit_drops;
+    let _ = count_requests(&mut nodes[2]);
+
+    let client_id = FullId::new();
+    let client = Authority::Client {
+        client_id: *client_id.public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let dst = Authority::ManagedNode(nodes[2].name());
+
+    // A message caught in a routing loop has its hop count raised on each pass. It is relayed by
+    // `nodes[1]` as long as the count stays within the limit ...
+    let bytes = TestMessage::request(client, dst, get_request())
+        .with_route(0, max_hop_count - 1)
+        .to_bytes(&client_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(count_requests(&mut nodes[2]), 1);
+    assert_eq!(unwrap!(nodes[1].inner.diagnostics()).hop_limit_drops, drops);
+
+    // ... and dropped once relaying it again would exceed it.
+    let bytes = TestMessage::request(client, dst, get_request())
+        .with_route(0, max_hop_count)
+        .to_bytes(&client_id, &sender_id);
+    unwrap!(nodes[1].inner.inject_message_for_test(sender_ep, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(count_requests(&mut nodes[2]), 0);
+    assert_eq!(unwrap!(nodes[1].inner.diagnostics()).hop_limit_drops, drops + 1);
+}
@@ -0,0 +1,254 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use super::{TestNode, create_connected_clients, create_connected_nodes, poll_all, poll_and_resend,
+            verify_invariant_for_all_nodes};
+use fake_clock::FakeClock;
+use rand::Rng;
+use routing::{Authority, ConfigRefusal, ConnectionQuotas, DataIdentifier, EffectiveConfig, Event,
+              EventStream, InterfaceError, LiveConfig, MemoryStateStore, MessageId, PartialConfig,
+              QUORUM_DENOMINATOR, QUORUM_NUMERATOR, SnapshotRejection, StartupConfig, XorName};
+use routing::mock_crust::{Config, Network};
+use std::time::Duration;
+
+#[test]
+fn node_terminates_on_request() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let name = nodes[0].name();
+
+    // The node shuts down as soon as it is asked to, without being polled.
+    nodes[0].inner.terminate();
+    expect_any_event!(nodes[0], Event::Terminate);
+    for node in &nodes[1..] {
+        assert!(!node.handle.is_connected(&nodes[0].handle));
+    }
+    assert!(nodes[0].inner.diagnostics().is_err());
+
+    // Actions requested afterwards fail instead of waiting for a result forever.
+    let src = Authority::ManagedNode(name);
+    let dst = Authority::NaeManager(name);
+    let data_id = DataIdentifier::Immutable(network.new_rng().gen());
+    match nodes[0]
+              .inner
+              .send_get_request(src, dst, data_id, MessageId::new()) {
+        Err(InterfaceError::InvalidState) => (),
+        result => panic!("Unexpected result {:?}", result),
+    }
+
+    let mut terminated = nodes.remove(0);
+    assert!(!terminated.poll());
+    let _ = poll_all(&mut nodes, &mut []);
+    for node in &mut nodes {
+        expect_any_event!(node, Event::NodeLost(lost_name, _) if lost_name == name);
+    }
+}
+
+#[test]
+fn node_restored_from_saved_state() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    let store = MemoryStateStore::new();
+    let interval = Duration::from_secs(60);
+
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .state_persistence(Box::new(store.clone()), interval, true)
+                   .create());
+    poll_and_resend(&mut nodes, &mut []);
+    verify_invariant_for_all_nodes(&mut nodes);
+
+    // Snapshots are saved periodically once the node has joined, so they survive a crash.
+    FakeClock::advance_time(interval.as_secs() * 1000 + 1);
+    let _ = poll_all(&mut nodes, &mut []);
+    assert!(store.contents().is_some());
+
+    // A final one is saved when the node is terminated.
+    let mut saved = unwrap!(nodes.pop());
+    let saved_id = saved.id();
+    saved.inner.terminate();
+    drop(saved);
+    poll_and_resend(&mut nodes, &mut []);
+
+    // A new node using the same store starts with the saved keys and contacts, and rejoins by
+    // bootstrapping off the restored contacts even though it has none configured.
+    let seed_id = nodes[0].id();
+    nodes.push(TestNode::builder(&network)
+                   .config(Config::new())
+                   .state_persistence(Box::new(store.clone()), interval, true)
+                   .create());
+    assert_eq!(saved_id, unwrap!(nodes.last()).id());
+    expect_any_event!(unwrap!(nodes.last_mut()),
+                      Event::StateRestored(ref contacts) if contacts.contains(&seed_id));
+    poll_and_resend(&mut nodes, &mut []);
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn discarded_state_reported() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    let store = MemoryStateStore::new();
+    let interval = Duration::from_secs(60);
+
+    nodes.push(TestNode::builder(&network)
+                   .config(config.clone())
+                   .state_persistence(Box::new(store.clone()), interval, false)
+                   .create());
+    poll_and_resend(&mut nodes, &mut []);
+    drop(unwrap!(nodes.pop()));
+    poll_and_resend(&mut nodes, &mut []);
+    let snapshot = unwrap!(store.contents());
+
+    // A snapshot saved by a node with different settings is discarded.
+    let mut node = TestNode::builder(&network)
+        .config(config.clone())
+        .group_fanout(min_section_size)
+        .state_persistence(Box::new(store.clone()), interval, false)
+        .create();
+    expect_next_event!(node, Event::StateDiscarded(SnapshotRejection::ConfigMismatch));
+    drop(node);
+
+    // So is a corrupted one, and the node starts afresh.
+    store.set_contents(snapshot[..snapshot.len() / 2].to_vec());
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .state_persistence(Box::new(store.clone()), interval, false)
+                   .create());
+    expect_next_event!(unwrap!(nodes.last_mut()),
+                       Event::StateDiscarded(SnapshotRejection::Corrupt));
+    poll_and_resend(&mut nodes, &mut []);
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn client_terminates_on_request() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let mut clients = create_connected_clients(&network, &mut nodes, 1);
+
+    // The client's request reaches the network via its proxy.
+    let name: XorName = network.new_rng().gen();
+    let dst = Authority::NaeManager(name);
+    unwrap!(clients[0]
+                .inner
+                .send_get_request(dst, DataIdentifier::Immutable(name), MessageId::new()));
+    let _ = poll_all(&mut nodes, &mut clients);
+    let mut requests = 0;
+    for node in nodes.iter_mut() {
+        while let Ok(event) = node.inner.try_next_ev() {
+            if let Event::Request { .. } = event {
+                requests += 1;
+            }
+        }
+    }
+    assert!(requests > 0);
+
+    unwrap!(clients[0].inner.terminate());
+    expect_any_event!(clients[0], Event::Terminate);
+    for node in &nodes {
+        assert!(!clients[0].handle.is_connected(&node.handle));
+    }
+    let _ = poll_all(&mut nodes, &mut clients);
+    for node in &nodes {
+        assert_eq!(0, unwrap!(node.inner.diagnostics()).relayed_clients);
+    }
+}
+
+#[test]
+fn config_partially_updated_at_runtime() {
+    let min_section_size = 8;
+    let quorum = min_section_size * QUORUM_NUMERATOR / QUORUM_DENOMINATOR + 1;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    while let Ok(_) = nodes[0].inner.try_next_ev() {}
+
+    // Settings changed via other actions are part of the effective config.
+    let quotas = ConnectionQuotas {
+        clients: Some(2),
+        ..ConnectionQuotas::default()
+    };
+    unwrap!(nodes[0].inner.set_connection_quotas(quotas));
+    let before = unwrap!(nodes[0].inner.effective_config());
+    assert_eq!(before.connection_quotas, quotas);
+    assert_eq!(before.max_peers_per_subnet, None);
+
+    let new_quotas = ConnectionQuotas {
+        unidentified: Some(10),
+        ..quotas
+    };
+    let update = PartialConfig {
+        live: LiveConfig {
+            connection_quotas: Some(new_quotas),
+            group_fanout: Some(quorum - 1),
+            max_connects_in_flight: Some(Some(0)),
+            connect_spacing: Some(Duration::from_secs(2)),
+            max_peers_per_ip: Some(Some(5)),
+            max_peers_per_subnet: Some(Some(4)),
+            health_events: Some(true),
+            ..LiveConfig::default()
+        },
+        startup: StartupConfig {
+            filter_capacity: Some(10),
+            gossip_interval: Some(Duration::from_secs(1)),
+            ..StartupConfig::default()
+        },
+    };
+    unwrap!(nodes[0].inner.update_config(update));
+
+    match nodes[0].inner.try_next_ev() {
+        Ok(Event::ConfigRefused(refused)) => {
+            let fields: Vec<_> = refused.iter().map(|setting| setting.field).collect();
+            assert_eq!(fields,
+                       vec!["filter_capacity",
+                            "gossip_interval",
+                            "group_fanout",
+                            "max_connects_in_flight",
+                            "max_peers_per_ip",
+                            "max_peers_per_subnet"]);
+            for setting in &refused[..2] {
+                assert_eq!(setting.reason, ConfigRefusal::NotLive);
+            }
+            for setting in &refused[2..] {
+                match setting.reason {
+                    ConfigRefusal::Invalid(_) => (),
+                    ref reason => panic!("Unexpected reason {:?}", reason),
+                }
+            }
+        }
+        other => panic!("Expected Ok(Event::ConfigRefused(..)), got {:?}", other),
+    }
+
+    // Exactly the valid live settings have changed.
+    let expected = EffectiveConfig {
+        connection_quotas: new_quotas,
+        connect_spacing: Duration::from_secs(2),
+        health_events: true,
+        ..before
+    };
+    match nodes[0].inner.try_next_ev() {
+        Ok(Event::ConfigUpdated(config)) => assert_eq!(config, expected),
+        other => panic!("Expected Ok(Event::ConfigUpdated(..)), got {:?}", other),
+    }
+    assert_eq!(unwrap!(nodes[0].inner.effective_config()), expected);
+}
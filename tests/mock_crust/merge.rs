@@ -58,6 +58,7 @@ fn merge(prefix_lengths: Vec<usize>) {
                 match event {
                     Event::NodeAdded(..) |
                     Event::NodeLost(..) |
+                    Event::ProxyDropped |
                     Event::Tick => (),
                     Event::SectionMerge(prefix) => {
                         if prefix.bit_count() == 0 {
@@ -16,20 +16,27 @@
 // relating to use of the SAFE Network Software.
 
 mod accumulate;
+mod bootstrap;
 mod cache;
 mod churn;
+mod connect;
+mod convergence;
+mod diagnostics;
 mod drop;
+mod injection;
+mod lifecycle;
 mod merge;
+mod quota;
 mod requests;
 mod tunnel;
 mod utils;
 
-pub use self::utils::{Nodes, TestClient, TestNode, add_connected_nodes_until_split,
-                      create_connected_clients, create_connected_nodes,
-                      create_connected_nodes_until_split, gen_bytes, gen_immutable_data,
-                      gen_range, gen_range_except, poll_all, poll_and_resend,
-                      remove_nodes_which_failed_to_connect, sort_nodes_by_distance_to,
-                      verify_invariant_for_all_nodes};
+pub use self::utils::{LatencyReport, LatencyTracker, Nodes, TestClient, TestNode,
+                      add_connected_nodes_until_split, add_node, create_connected_clients,
+                      create_connected_nodes, create_connected_nodes_until_split, gen_bytes,
+                      gen_immutable_data, gen_range, gen_range_except, poll_all, poll_and_resend,
+                      remove_nodes_which_failed_to_connect, settle, sort_nodes_by_distance_to,
+                      verify_invariant_for_all_nodes, wait_for, wait_until_joined, with_watchdog};
 use routing::{Event, EventStream, Prefix, XOR_NAME_LEN, XorName};
 use routing::mock_crust::{Config, Endpoint, Network};
 
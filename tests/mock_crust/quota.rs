@@ -0,0 +1,363 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+use super::{TestClient, TestNode, create_connected_nodes, poll_all, poll_and_resend,
+            verify_invariant_for_all_nodes};
+use fake_clock::FakeClock;
+use rand::Rng;
+use routing::{BootstrapFailure, ConnectionQuotas, Event, EventStream, FullId, RefusalReason,
+              XorName, test_messages};
+use routing::mock_crust::{Config, Endpoint, Network, crust};
+use routing::test_consts::MAX_MALFORMED_MSG_STRIKES;
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::net::{IpAddr, Ipv4Addr};
+use std::rc::Rc;
+use std::time::Duration;
+
+#[test]
+fn disconnect_after_malformed_messages() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let endpoint = nodes[0].handle.endpoint();
+    let sender_id = nodes[1].id();
+    let sender_name = nodes[1].name();
+
+    // Alternate random garbage with a truncated direct message (just the enum variant tag).
+    let mut rng = network.new_rng();
+    for i in 0..MAX_MALFORMED_MSG_STRIKES {
+        assert!(nodes[0].handle.is_connected(&nodes[1].handle));
+        let bytes = if i % 2 == 0 {
+            rng.gen_iter().take(100).collect()
+        } else {
+            vec![0, 0, 0, 0]
+        };
+        let len = bytes.len();
+        network.send_crust_event_or_panic(endpoint, crust::Event::NewMessage(sender_id, bytes));
+        let _ = nodes[0].poll();
+        expect_any_event!(nodes[0],
+                          Event::MalformedMessage(pub_id, bytes_len)
+                              if pub_id == sender_id && bytes_len == len);
+    }
+
+    assert!(!nodes[0].handle.is_connected(&nodes[1].handle));
+    expect_any_event!(nodes[0], Event::NodeLost(name, _) if name == sender_name);
+    let diagnostics = unwrap!(nodes[0].inner.diagnostics());
+    assert_eq!(MAX_MALFORMED_MSG_STRIKES, diagnostics.malformed_msgs);
+}
+
+#[test]
+fn banned_peer_reconnects_once_ban_lapses() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let banned_name = nodes[1].name();
+    let ban_secs = 60;
+
+    unwrap!(nodes[0]
+                .inner
+                .ban_peer(banned_name, Duration::from_secs(ban_secs)));
+    expect_any_event!(nodes[0], Event::NodeLost(name, _) if name == banned_name);
+
+    // The banned node tries to reconnect, but is refused.
+    let _ = poll_all(&mut nodes, &mut []);
+    assert!(!nodes[0].handle.is_connected(&nodes[1].handle));
+    assert!(!nodes[0].routing_table().has(&banned_name));
+    assert_eq!(1, unwrap!(nodes[0].inner.diagnostics()).banned_peers);
+
+    // Once the ban has lapsed, the peer is reconnected and added back to the routing table.
+    FakeClock::advance_time(ban_secs * 1000 + 1);
+    assert_eq!(0, unwrap!(nodes[0].inner.diagnostics()).banned_peers);
+    poll_and_resend(&mut nodes, &mut []);
+    assert!(nodes[0].handle.is_connected(&nodes[1].handle));
+    assert!(nodes[0].routing_table().has(&banned_name));
+    assert!(nodes[1].routing_table().has(&nodes[0].name()));
+    expect_any_event!(nodes[0], Event::NodeAdded(name, _) if name == banned_name);
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn client_quota_adjusted_at_runtime() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let mut quotas = ConnectionQuotas {
+        clients: Some(2),
+        ..ConnectionQuotas::default()
+    };
+    unwrap!(nodes[0].inner.set_connection_quotas(quotas));
+
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    let mut clients = Vec::new();
+    for _ in 0..3 {
+        clients.push(TestClient::new(&network, Some(config.clone()), None));
+        let _ = poll_all(&mut nodes, &mut clients);
+    }
+    expect_next_event!(clients[0], Event::Connected);
+    expect_next_event!(clients[1], Event::Connected);
+    match clients[2].inner.try_next_ev() {
+        Ok(Event::BootstrapFailed(failures)) => {
+            assert_eq!(failures.len(), 1);
+            assert_eq!(failures[0].1, BootstrapFailure::Denied);
+        }
+        other => panic!("Expected Ok(Event::BootstrapFailed(..)), got {:?}", other),
+    }
+    expect_next_event!(clients[2], Event::Terminate);
+    let refused_name = *clients[2].full_id.public_id().name();
+    expect_any_event!(nodes[0],
+                      Event::PeerRefused(name, RefusalReason::ClientQuota) if name == refused_name);
+    assert_eq!(2, unwrap!(nodes[0].inner.diagnostics()).relayed_clients);
+
+    // After raising the quota, the refused client succeeds on retry.
+    quotas.clients = Some(3);
+    unwrap!(nodes[0].inner.set_connection_quotas(quotas));
+    let full_id = unwrap!(clients.pop()).full_id;
+    clients.push(TestClient::with_full_id(&network, Some(config), None, full_id));
+    let _ = poll_all(&mut nodes, &mut clients);
+    expect_next_event!(clients[2], Event::Connected);
+    assert_eq!(3, unwrap!(nodes[0].inner.diagnostics()).relayed_clients);
+}
+
+#[test]
+fn unidentified_quota_culls_oldest() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let endpoint = nodes[0].handle.endpoint();
+
+    // Peers which connect, but never identify themselves.
+    let names: Vec<XorName> = (0..4)
+        .map(|_| {
+                 let pub_id = *FullId::new().public_id();
+                 let event = crust::Event::BootstrapAccept(pub_id, crust::CrustUser::Client);
+                 network.send_crust_event_or_panic(endpoint, event);
+                 let _ = nodes[0].poll();
+                 FakeClock::advance_time(1000);
+                 *pub_id.name()
+             })
+        .collect();
+    assert_eq!(4, unwrap!(nodes[0].inner.diagnostics()).unidentified_connections);
+
+    // Lowering the quota drops all but the newest connection immediately.
+    let quotas = ConnectionQuotas {
+        unidentified: Some(1),
+        ..ConnectionQuotas::default()
+    };
+    unwrap!(nodes[0].inner.set_connection_quotas(quotas));
+    assert_eq!(1, unwrap!(nodes[0].inner.diagnostics()).unidentified_connections);
+    for name in &names[..3] {
+        assert!(nodes[0].inner.disconnect_peer(*name).is_err());
+    }
+
+    // Further connections are refused.
+    let pub_id = *FullId::new().public_id();
+    let event = crust::Event::BootstrapAccept(pub_id, crust::CrustUser::Client);
+    network.send_crust_event_or_panic(endpoint, event);
+    let _ = nodes[0].poll();
+    expect_any_event!(nodes[0],
+                      Event::PeerRefused(name, RefusalReason::UnidentifiedQuota)
+                          if name == *pub_id.name());
+    assert_eq!(1, unwrap!(nodes[0].inner.diagnostics()).unidentified_connections);
+}
+
+#[test]
+fn lost_unidentified_peer_forgotten() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let endpoint = nodes[0].handle.endpoint();
+    let pub_id = *FullId::new().public_id();
+
+    let event = crust::Event::BootstrapAccept(pub_id, crust::CrustUser::Client);
+    network.send_crust_event_or_panic(endpoint, event);
+    let _ = nodes[0].poll();
+    assert_eq!(1, unwrap!(nodes[0].inner.diagnostics()).unidentified_connections);
+
+    // Losing the connection before the peer identified itself leaves no trace of it.
+    network.send_crust_event_or_panic(endpoint, crust::Event::LostPeer(pub_id));
+    let _ = nodes[0].poll();
+    assert_eq!(0, unwrap!(nodes[0].inner.diagnostics()).unidentified_connections);
+    assert!(nodes[0].inner.disconnect_peer(*pub_id.name()).is_err());
+
+    // So it can connect again straight away.
+    let event = crust::Event::BootstrapAccept(pub_id, crust::CrustUser::Client);
+    network.send_crust_event_or_panic(endpoint, event);
+    let _ = nodes[0].poll();
+    assert_eq!(1, unwrap!(nodes[0].inner.diagnostics()).unidentified_connections);
+}
+
+#[test]
+fn reconnecting_peer_replaces_stale_entry() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let endpoint = nodes[0].handle.endpoint();
+    let quotas = ConnectionQuotas {
+        unidentified: Some(1),
+        ..ConnectionQuotas::default()
+    };
+    unwrap!(nodes[0].inner.set_connection_quotas(quotas));
+    while let Ok(_) = nodes[0].inner.try_next_ev() {}
+
+    let pub_id = *FullId::new().public_id();
+    let event = crust::Event::BootstrapAccept(pub_id, crust::CrustUser::Client);
+    network.send_crust_event_or_panic(endpoint, event);
+    let _ = nodes[0].poll();
+    assert_eq!(1, unwrap!(nodes[0].inner.diagnostics()).unidentified_connections);
+
+    // The same peer reconnecting before its stale entry expired takes that entry's place.
+    let event = crust::Event::BootstrapAccept(pub_id, crust::CrustUser::Client);
+    network.send_crust_event_or_panic(endpoint, event);
+    let _ = nodes[0].poll();
+    while let Ok(event) = nodes[0].inner.try_next_ev() {
+        if let Event::PeerRefused(..) = event {
+            panic!("Unexpected {:?}", event);
+        }
+    }
+    assert_eq!(1, unwrap!(nodes[0].inner.diagnostics()).unidentified_connections);
+
+    // A different peer is still refused while the quota is full.
+    let other_id = *FullId::new().public_id();
+    let event = crust::Event::BootstrapAccept(other_id, crust::CrustUser::Client);
+    network.send_crust_event_or_panic(endpoint, event);
+    let _ = nodes[0].poll();
+    expect_any_event!(nodes[0],
+                      Event::PeerRefused(name, RefusalReason::UnidentifiedQuota)
+                          if name == *other_id.name());
+    assert_eq!(1, unwrap!(nodes[0].inner.diagnostics()).unidentified_connections);
+}
+
+#[test]
+fn reconnecting_routing_peer_keeps_entry() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let endpoint = nodes[0].handle.endpoint();
+    let name = nodes[1].name();
+    assert!(nodes[0].routing_table().has(&name));
+
+    let event = crust::Event::BootstrapAccept(nodes[1].id(), crust::CrustUser::Node);
+    network.send_crust_event_or_panic(endpoint, event);
+    let _ = poll_all(&mut nodes, &mut []);
+    assert!(nodes[0].routing_table().has(&name));
+    assert_eq!(0, unwrap!(nodes[0].inner.diagnostics()).unidentified_connections);
+    verify_invariant_for_all_nodes(&mut nodes);
+}
+
+#[test]
+fn reconnecting_routing_peer_must_identify() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let full_id = FullId::new();
+    let pub_id = *full_id.public_id();
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .full_id(full_id.clone())
+                   .create());
+    poll_and_resend(&mut nodes, &mut []);
+    assert!(nodes[0].routing_table().has(pub_id.name()));
+
+    let endpoint = nodes[0].handle.endpoint();
+    let peer_endpoint = unwrap!(nodes.last()).handle.endpoint();
+    let nonces = Rc::new(RefCell::new(Vec::new()));
+    let nonces_clone = nonces.clone();
+    network.set_packet_observer(move |packet| if let Some(payload) = packet.payload {
+                                    if packet.sender == endpoint &&
+                                       packet.receiver == peer_endpoint {
+                                        if let Some(nonce) =
+                                            test_messages::identify_challenge_nonce(payload) {
+                                            nonces_clone.borrow_mut().push(nonce);
+                                        }
+                                    }
+                                });
+
+    // The peer reconnecting while in our routing table is challenged to identify itself, and
+    // keeps its entry once it has.
+    let event = crust::Event::BootstrapAccept(pub_id, crust::CrustUser::Node);
+    network.send_crust_event_or_panic(endpoint, event);
+    let _ = poll_all(&mut nodes, &mut []);
+    let nonce = unwrap!(nonces.borrow_mut().pop());
+    let bytes = test_messages::node_identify_bytes(&pub_id, &full_id, nonce);
+    unwrap!(nodes[0].inner.inject_message_for_test(peer_endpoint, bytes));
+    let _ = poll_all(&mut nodes, &mut []);
+    assert!(nodes[0].routing_table().has(pub_id.name()));
+    assert!(nodes[0].handle.is_connected(&unwrap!(nodes.last()).handle));
+
+    // A reconnection which signs the challenge with a different key is dropped without replacing
+    // the entry.
+    let event = crust::Event::BootstrapAccept(pub_id, crust::CrustUser::Node);
+    network.send_crust_event_or_panic(endpoint, event);
+    let _ = poll_all(&mut nodes, &mut []);
+    let nonce = unwrap!(nonces.borrow_mut().pop());
+    let bytes = test_messages::node_identify_bytes(&pub_id, &FullId::new(), nonce);
+    unwrap!(nodes[0].inner.inject_message_for_test(peer_endpoint, bytes));
+    let _ = nodes[0].poll();
+    assert!(nodes[0].routing_table().has(pub_id.name()));
+    assert!(!nodes[0].handle.is_connected(&unwrap!(nodes.last()).handle));
+}
+
+#[test]
+fn peers_per_ip_limited() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, 3);
+
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config.clone())
+                   .endpoint(Endpoint(3))
+                   .max_peers_per_ip(2)
+                   .create());
+    poll_and_resend(&mut nodes, &mut []);
+    let target_index = nodes.len() - 1;
+
+    // Five further nodes share an IP address, but the target node only accepts two of them.
+    let shared_ip = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    for i in 4..9 {
+        network.assign_ip(Endpoint(i), shared_ip);
+        nodes.push(TestNode::builder(&network)
+                       .config(config.clone())
+                       .endpoint(Endpoint(i))
+                       .create());
+        poll_and_resend(&mut nodes, &mut []);
+    }
+
+    let shared_ip_names: BTreeSet<XorName> = nodes[target_index + 1..]
+        .iter()
+        .map(TestNode::name)
+        .collect();
+    let accepted: BTreeSet<XorName> = nodes[target_index]
+        .routing_table()
+        .iter()
+        .filter(|name| shared_ip_names.contains(*name))
+        .cloned()
+        .collect();
+    assert_eq!(accepted.len(), 2);
+
+    let mut refused = BTreeSet::new();
+    while let Ok(event) = nodes[target_index].inner.try_next_ev() {
+        if let Event::PeerRefused(name, reason) = event {
+            assert_eq!(reason, RefusalReason::IpLimit(shared_ip));
+            let _ = refused.insert(name);
+        }
+    }
+    assert_eq!(refused,
+               shared_ip_names.difference(&accepted).cloned().collect());
+}
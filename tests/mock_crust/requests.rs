@@ -15,11 +15,26 @@
 // Please review the Licences for the specific language governing permissions and limitations
 // relating to use of the SAFE Network Software.
 
-use super::{create_connected_clients, create_connected_nodes, gen_bytes, gen_immutable_data,
-            poll_all};
-use routing::{Authority, Data, DataIdentifier, Event, EventStream, ImmutableData, MessageId,
-              Request, Response};
-use routing::mock_crust::Network;
+use super::{LatencyReport, LatencyTracker, TestClient, TestNode, create_connected_clients,
+            create_connected_nodes, gen_bytes, gen_immutable_data, poll_all, poll_and_resend,
+            verify_invariant_for_all_nodes};
+use fake_clock::FakeClock;
+use maidsafe_utilities::serialisation::{deserialise, serialise};
+use rand::Rng;
+use routing::{Authority, AuthorityKind, Data, DataIdentifier, Decision, DecisionRecord, Event,
+              EventMask, EventStream, FilterOutcome, ForwardingDecision, ForwardingReason, FullId,
+              ImmutableData, InputLog, InterfaceError, LiveConfig, MessageId, Node, PartialConfig,
+              ProcessingPhase, ProxyStrategy, PublicId, QUORUM_DENOMINATOR, QUORUM_NUMERATOR,
+              RecordedInput, Request, Response, RoutingDispatcher, RoutingError, WireKind,
+              XOR_NAME_LEN, XorName, decode_decision_log, inject_phase_cost};
+use routing::mock_crust::{self, Config, Delivery, Endpoint, Network, PacketKind};
+use routing::test_consts::{ACK_TIMEOUT_SECS, MAX_PROTOCOL_VIOLATIONS,
+                           MESSAGE_ID_RETRY_WINDOW_SECS, SLOW_MESSAGE_REPORT_INTERVAL_SECS};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::time::Duration;
+use std::sync::mpsc;
+use std::thread;
 
 #[test]
 fn successful_put_request() {
@@ -60,6 +75,46 @@ fn successful_put_request() {
     assert!(2 * request_received_count > min_section_size);
 }
 
+#[test]
+fn requests_sent_under_each_proxy_strategy() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size + 1);
+    let mut clients = create_connected_clients(&network, &mut nodes, 1);
+    let dst = Authority::ClientManager(clients[0].name());
+
+    for strategy in &[ProxyStrategy::LowestLatency,
+                      ProxyStrategy::RoundRobin,
+                      ProxyStrategy::FirstIdentified] {
+        unwrap!(clients[0].inner.set_proxy_strategy(*strategy));
+        let _ = poll_all(&mut nodes, &mut clients);
+
+        let data = gen_immutable_data(&mut rng, 1024);
+        let message_id = MessageId::new();
+        unwrap!(clients[0]
+                    .inner
+                    .send_put_request(dst, data.clone(), message_id));
+        let _ = poll_all(&mut nodes, &mut clients);
+
+        let mut request_received_count = 0;
+        for node in nodes.iter_mut().filter(|n| n.is_recipient(&dst)) {
+            while let Ok(event) = node.try_next_ev() {
+                if let Event::Request { request: Request::Put(ref immutable, ref id), .. } = event {
+                    if data == *immutable && message_id == *id {
+                        request_received_count += 1;
+                        break;
+                    }
+                }
+            }
+        }
+        assert!(2 * request_received_count > min_section_size,
+                "{:?}: request received by {} nodes",
+                strategy,
+                request_received_count);
+    }
+}
+
 #[test]
 fn successful_get_request() {
     let min_section_size = 8;
@@ -131,6 +186,64 @@ fn successful_get_request() {
     assert_eq!(response_received_count, 1);
 }
 
+#[test]
+fn get_round_trip_between_two_nodes() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, 2);
+    let requester = Authority::ManagedNode(nodes[0].name());
+    let holder = Authority::ManagedNode(nodes[1].name());
+    let data = gen_immutable_data(&mut rng, 1024);
+    let data_id = data.identifier();
+    let message_id = MessageId::new();
+
+    unwrap!(nodes[0]
+                .inner
+                .send_get_request(requester, holder, data_id, message_id));
+    let _ = poll_all(&mut nodes, &mut []);
+    expect_any_event!(nodes[1],
+                      Event::Request { request: Request::Get(ref id, msg_id), src, dst }
+                          if *id == data_id && msg_id == message_id && src == requester &&
+                             dst == holder);
+
+    unwrap!(nodes[1]
+                .inner
+                .send_get_success(holder, requester, data.clone(), message_id));
+    let _ = poll_all(&mut nodes, &mut []);
+    expect_any_event!(nodes[0],
+                      Event::Response { response: Response::GetSuccess(ref got, msg_id), src, dst }
+                          if *got == data && msg_id == message_id && src == holder &&
+                             dst == requester);
+}
+
+#[test]
+fn unrequested_response_dropped() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, 2);
+    let requester = Authority::ManagedNode(nodes[0].name());
+    let holder = Authority::ManagedNode(nodes[1].name());
+    let data = gen_immutable_data(&mut rng, 1024);
+    let _ = nodes[0].inner.take_message_errors();
+
+    // `nodes[0]` never sent a request with this ID, so the response is dropped.
+    unwrap!(nodes[1]
+                .inner
+                .send_get_success(holder, requester, data, MessageId::new()));
+    let _ = poll_all(&mut nodes, &mut []);
+    expect_no_event!(nodes[0]);
+    assert!(nodes[0]
+                .inner
+                .take_message_errors()
+                .iter()
+                .any(|error| match *error {
+                         RoutingError::UnknownMessageType => true,
+                         _ => false,
+                     }));
+}
+
 #[test]
 fn failed_get_request() {
     let min_section_size = 8;
@@ -268,3 +381,1213 @@ fn disconnect_on_get_request() {
         }
     }
 }
+
+#[test]
+fn client_never_joins_routing_table() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size + 1);
+    let mut clients = create_connected_clients(&network, &mut nodes, 1);
+
+    let data = gen_immutable_data(&mut rng, 1024);
+    let dst = Authority::NaeManager(*data.name());
+    let message_id = MessageId::new();
+
+    assert!(clients[0]
+                .inner
+                .send_get_request(dst, data.identifier(), message_id)
+                .is_ok());
+
+    let _ = poll_all(&mut nodes, &mut clients);
+
+    for node in nodes.iter_mut().filter(|n| n.is_recipient(&dst)) {
+        loop {
+            match node.try_next_ev() {
+                Ok(Event::Request { request: Request::Get(_, id), src, dst }) => {
+                    if message_id == id {
+                        unwrap!(node.inner.send_get_success(dst, src, data.clone(), id));
+                        break;
+                    }
+                }
+                Ok(_) => (),
+                _ => panic!("Event::Request not received"),
+            }
+        }
+    }
+
+    let _ = poll_all(&mut nodes, &mut clients);
+
+    expect_any_event!(clients[0],
+                      Event::Response { response: Response::GetSuccess(_, id), .. }
+                      if id == message_id);
+
+    let client_name = clients[0].name();
+    for node in nodes.iter() {
+        assert!(!node.routing_table().has(&client_name),
+                "Client {:?} found in the routing table of {:?}.",
+                client_name,
+                node.name());
+    }
+}
+
+#[test]
+fn get_response_requires_quorum() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size + 1);
+    let mut clients = create_connected_clients(&network, &mut nodes, 1);
+
+    let data = gen_immutable_data(&mut rng, 1024);
+    let bad_data = gen_immutable_data(&mut rng, 1024);
+    let dst = Authority::NaeManager(*data.name());
+    let message_id = MessageId::new();
+
+    assert!(clients[0]
+                .inner
+                .send_get_request(dst, data.identifier(), message_id)
+                .is_ok());
+
+    let _ = poll_all(&mut nodes, &mut clients);
+
+    // One member of the section answers with different data than all the others.
+    let mut is_first = true;
+    for node in nodes.iter_mut().filter(|n| n.is_recipient(&dst)) {
+        loop {
+            match node.try_next_ev() {
+                Ok(Event::Request { request: Request::Get(_, id), src, dst }) => {
+                    if message_id == id {
+                        let response_data = if is_first {
+                            bad_data.clone()
+                        } else {
+                            data.clone()
+                        };
+                        is_first = false;
+                        unwrap!(node.inner.send_get_success(dst, src, response_data, id));
+                        break;
+                    }
+                }
+                Ok(_) => (),
+                _ => panic!("Event::Request not received"),
+            }
+        }
+    }
+
+    let _ = poll_all(&mut nodes, &mut clients);
+
+    // Only the response agreed on by a quorum of the section is delivered, and only once.
+    let mut responses = Vec::new();
+    while let Ok(event) = clients[0].inner.try_next_ev() {
+        if let Event::Response { response: Response::GetSuccess(response_data, id), .. } = event {
+            assert_eq!(message_id, id);
+            responses.push(response_data);
+        }
+    }
+    assert_eq!(vec![data], responses);
+}
+
+// Returns the `MessageId`s of all `Put` requests the node has raised events for.
+fn put_request_ids(node: &mut TestNode) -> Vec<MessageId> {
+    let mut ids = Vec::new();
+    while let Ok(event) = node.try_next_ev() {
+        if let Event::Request { request: Request::Put(_, id), .. } = event {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+#[test]
+fn retried_request_keeps_message_id() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    let src = Authority::ManagedNode(nodes[0].name());
+    let dst = Authority::ManagedNode(nodes[1].name());
+    let data = gen_immutable_data(&mut rng, 1024);
+    let content = data.name().0;
+
+    // A retry within the window reuses the ID, so the recipient only handles the request once.
+    let message_id = nodes[0].inner.message_id_for(&content, None);
+    unwrap!(nodes[0]
+                .inner
+                .send_put_request(src, dst, data.clone(), message_id));
+    let _ = poll_all(&mut nodes, &mut []);
+    let retry_id = nodes[0].inner.message_id_for(&content, None);
+    assert_eq!(message_id, retry_id);
+    assert_ne!(message_id, nodes[0].inner.message_id_for(&content, Some(1)));
+    unwrap!(nodes[0]
+                .inner
+                .send_put_request(src, dst, data.clone(), retry_id));
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(put_request_ids(&mut nodes[1]), vec![message_id]);
+
+    // Once the window has elapsed, the same content is sent as a new request.
+    FakeClock::advance_time(MESSAGE_ID_RETRY_WINDOW_SECS * 1000 + 1);
+    let new_id = nodes[0].inner.message_id_for(&content, None);
+    assert_ne!(message_id, new_id);
+    unwrap!(nodes[0].inner.send_put_request(src, dst, data, new_id));
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(put_request_ids(&mut nodes[1]), vec![new_id]);
+}
+
+// Sends `data` in a `Put` request from `nodes[0]` to `nodes[1]`, with the ID assigned by
+// `message_id_for`. Returns the ID and the `Hop` messages recorded in `hops` meanwhile.
+fn put_with_retry_safe_id(nodes: &mut [TestNode],
+                          data: &ImmutableData,
+                          hops: &RefCell<Vec<Vec<u8>>>)
+                          -> (MessageId, Vec<Vec<u8>>) {
+    let src = Authority::ManagedNode(nodes[0].name());
+    let dst = Authority::ManagedNode(nodes[1].name());
+    let message_id = nodes[0].inner.message_id_for(&data.name().0, None);
+    unwrap!(nodes[0]
+                .inner
+                .send_put_request(src, dst, data.clone(), message_id));
+    let _ = poll_all(nodes, &mut []);
+    (message_id, hops.borrow_mut().drain(..).collect())
+}
+
+#[test]
+fn retried_request_resent_unchanged() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    let sender = nodes[0].handle.endpoint();
+    let recipient = nodes[1].handle.endpoint();
+    let hops = Rc::new(RefCell::new(Vec::new()));
+    let observed_hops = hops.clone();
+    network.set_packet_observer(move |packet| {
+        if packet.sender == sender && packet.receiver == recipient &&
+           packet.wire_kind == Some(WireKind::Hop) {
+            observed_hops
+                .borrow_mut()
+                .push(unwrap!(packet.payload).to_vec());
+        }
+    });
+    let data = gen_immutable_data(&mut rng, 1024);
+
+    let (message_id, first_hops) = put_with_retry_safe_id(&mut nodes, &data, &hops);
+    assert!(!first_hops.is_empty());
+    expect_any_event!(nodes[0], Event::MessageSent { msg_id, resent: false, .. }
+                      if msg_id == message_id);
+
+    // A retry within the window puts the same bytes on the wire, and is filtered by the recipient.
+    let (retry_id, retry_hops) = put_with_retry_safe_id(&mut nodes, &data, &hops);
+    assert_eq!(retry_id, message_id);
+    assert_eq!(retry_hops, first_hops);
+    expect_any_event!(nodes[0], Event::MessageSent { msg_id, resent: true, .. }
+                      if msg_id == message_id);
+    assert_eq!(put_request_ids(&mut nodes[1]), vec![message_id]);
+
+    // Once the window has elapsed, the same content is sent as a new message.
+    FakeClock::advance_time(MESSAGE_ID_RETRY_WINDOW_SECS * 1000 + 1);
+    let (new_id, new_hops) = put_with_retry_safe_id(&mut nodes, &data, &hops);
+    assert_ne!(new_id, message_id);
+    assert!(!new_hops.is_empty());
+    assert!(new_hops.iter().all(|hop| !first_hops.contains(hop)));
+    expect_any_event!(nodes[0], Event::MessageSent { msg_id, resent: false, .. }
+                      if msg_id == new_id);
+    assert_eq!(put_request_ids(&mut nodes[1]), vec![new_id]);
+    network.clear_packet_observer();
+}
+
+#[test]
+fn retry_safe_messages_evicted_at_capacity() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.insert(0,
+                 TestNode::builder(&network)
+                     .config(config)
+                     .message_id_retry_capacity(1)
+                     .create());
+    poll_and_resend(&mut nodes, &mut []);
+    verify_invariant_for_all_nodes(&mut nodes);
+    let hops = RefCell::new(Vec::new());
+
+    // Only the most recent retry-safe message is remembered.
+    let first_data = gen_immutable_data(&mut rng, 1024);
+    let (first_id, _) = put_with_retry_safe_id(&mut nodes, &first_data, &hops);
+    let second_data = gen_immutable_data(&mut rng, 1024);
+    let (second_id, _) = put_with_retry_safe_id(&mut nodes, &second_data, &hops);
+    let caches = unwrap!(nodes[0].inner.diagnostics()).caches;
+    for label in &["message_ids", "retry_safe_ids", "recent_sends"] {
+        assert_eq!((1, 1), (caches[*label].len, caches[*label].evictions));
+    }
+
+    // A retry of the evicted one is sent as a new message.
+    let (retry_id, _) = put_with_retry_safe_id(&mut nodes, &first_data, &hops);
+    assert_ne!(retry_id, first_id);
+    assert_eq!(put_request_ids(&mut nodes[1]),
+               vec![first_id, second_id, retry_id]);
+}
+
+#[test]
+fn client_claiming_wrong_proxy_dropped() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size + 1);
+    let mut clients = create_connected_clients(&network, &mut nodes, 1);
+    let dst = Authority::ClientManager(clients[0].name());
+
+    // A well-behaved client's request is relayed by its proxy.
+    let message_id = MessageId::new();
+    unwrap!(clients[0]
+                .inner
+                .send_put_request(dst, gen_immutable_data(&mut rng, 1024), message_id));
+    let _ = poll_all(&mut nodes, &mut clients);
+    for node in nodes.iter_mut().filter(|n| n.is_recipient(&dst)) {
+        assert_eq!(put_request_ids(node), vec![message_id]);
+    }
+
+    // Claiming another node as the proxy gets the requests dropped, until the proxy disconnects.
+    let other_name = nodes[1].name();
+    clients[0].inner.set_claimed_proxy_name(other_name);
+    for violations in 1..(MAX_PROTOCOL_VIOLATIONS + 1) {
+        assert!(clients[0].handle.is_connected(&nodes[0].handle));
+        unwrap!(clients[0]
+                    .inner
+                    .send_put_request(dst, gen_immutable_data(&mut rng, 1024), MessageId::new()));
+        let _ = poll_all(&mut nodes, &mut clients);
+        for node in nodes.iter_mut().filter(|n| n.is_recipient(&dst)) {
+            assert!(put_request_ids(node).is_empty());
+        }
+        assert_eq!(unwrap!(nodes[0].inner.diagnostics()).protocol_violations,
+                   violations);
+    }
+    assert!(!clients[0].handle.is_connected(&nodes[0].handle));
+}
+
+#[test]
+fn connection_info_request_claiming_our_name_is_violation() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    // A request to connect in the recipient's own name counts against the sender.
+    let victim_id = unwrap!(nodes[1].inner.id());
+    for violations in 1..(MAX_PROTOCOL_VIOLATIONS + 1) {
+        nodes[0]
+            .inner
+            .send_forged_connection_info_request(victim_id, *victim_id.name());
+        let _ = poll_all(&mut nodes, &mut []);
+        let diagnostics = unwrap!(nodes[1].inner.diagnostics());
+        assert_eq!(diagnostics.protocol_violations, violations);
+    }
+}
+
+#[test]
+fn connection_info_from_before_churn_is_dropped() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut nodes = create_connected_nodes(&network, min_section_size + 3);
+    let sender_id = unwrap!(nodes[0].inner.id());
+    let sender_ep = nodes[0].handle.endpoint();
+    let receiver_name = nodes[1].name();
+    let receiver_ep = nodes[1].handle.endpoint();
+
+    // Let the receiver learn the sender's current churn generation.
+    nodes[0]
+        .inner
+        .send_forged_connection_info_request(sender_id, receiver_name);
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(unwrap!(nodes[1].inner.diagnostics()).stale_msgs, 0);
+
+    // Hold a request on the link while two nodes of the section drop out.
+    network.hold_connection(sender_ep, receiver_ep);
+    nodes[0]
+        .inner
+        .send_forged_connection_info_request(sender_id, receiver_name);
+    for _ in 0..2 {
+        let _ = nodes.pop();
+        let _ = poll_all(&mut nodes, &mut []);
+    }
+
+    // On arrival, the request is older than the slack allows and is dropped.
+    network.release_connection(sender_ep, receiver_ep);
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(unwrap!(nodes[1].inner.diagnostics()).stale_msgs, 1);
+
+    // A request sent after the churn passes.
+    nodes[0]
+        .inner
+        .send_forged_connection_info_request(sender_id, receiver_name);
+    let _ = poll_all(&mut nodes, &mut []);
+    assert_eq!(unwrap!(nodes[1].inner.diagnostics()).stale_msgs, 1);
+}
+
+#[test]
+fn response_redirected_after_proxy_restart() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size + 2);
+    let config = Config::with_contacts(&[nodes[1].handle.endpoint()]);
+    let mut clients = vec![TestClient::new(&network, Some(config), None)];
+    let _ = poll_all(&mut nodes, &mut clients);
+    expect_next_event!(clients[0], Event::Connected);
+
+    let data = gen_immutable_data(&mut rng, 1024);
+    let dst = Authority::NaeManager(*data.name());
+    let message_id = MessageId::new();
+    unwrap!(clients[0]
+                .inner
+                .send_get_request(dst, data.identifier(), message_id));
+    let _ = poll_all(&mut nodes, &mut clients);
+
+    let mut requests = Vec::new();
+    for node in nodes.iter_mut().filter(|n| n.is_recipient(&dst)) {
+        loop {
+            match node.try_next_ev() {
+                Ok(Event::Request { request: Request::Get(_, id), src, dst }) => {
+                    if message_id == id {
+                        requests.push((node.name(), src, dst));
+                        break;
+                    }
+                }
+                Ok(_) => (),
+                _ => panic!("Event::Request not received"),
+            }
+        }
+    }
+
+    // The proxy node leaves, and the client reconnects with the same ID via another one.
+    drop(nodes.remove(1));
+    let _ = poll_all(&mut nodes, &mut clients);
+    expect_any_event!(clients[0], Event::Terminate);
+    let config = Config::with_contacts(&[nodes[1].handle.endpoint()]);
+    let full_id = clients[0].full_id.clone();
+    clients = vec![TestClient::with_full_id(&network, Some(config), None, full_id)];
+    let _ = poll_all(&mut nodes, &mut clients);
+    expect_next_event!(clients[0], Event::Connected);
+
+    // The response is still addressed via the old proxy node, but reaches the client.
+    for node in nodes.iter_mut() {
+        if let Some(&(_, src, dst)) = requests.iter().find(|request| request.0 == node.name()) {
+            unwrap!(node.inner
+                        .send_get_success(dst, src, data.clone(), message_id));
+        }
+    }
+    let _ = poll_all(&mut nodes, &mut clients);
+    expect_any_event!(clients[0],
+                      Event::Response { response: Response::GetSuccess(_, id), .. }
+                      if id == message_id);
+}
+
+#[test]
+fn response_relayed_to_client_or_reported_unreachable() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size + 1);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    let mut clients = vec![TestClient::new(&network, Some(config), None)];
+    let _ = poll_all(&mut nodes, &mut clients);
+    expect_next_event!(clients[0], Event::Connected);
+
+    // Node 1 responds to the client, which is only connected to its proxy node 0.
+    let src = Authority::ManagedNode(nodes[1].name());
+    let client = Authority::Client {
+        client_id: *clients[0].full_id.public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let data = gen_immutable_data(&mut rng, 1024);
+    let message_id = MessageId::new();
+    unwrap!(nodes[1]
+                .inner
+                .send_get_success(src, client, data.clone(), message_id));
+    let _ = poll_all(&mut nodes, &mut clients);
+    expect_any_event!(clients[0],
+                      Event::Response { response: Response::GetSuccess(ref got, id), .. }
+                          if *got == data && id == message_id);
+
+    // Once the client is gone, the proxy node reports it to the sender.
+    drop(clients.remove(0));
+    let _ = poll_all(&mut nodes, &mut clients);
+    unwrap!(nodes[1]
+                .inner
+                .send_get_success(src, client, data, MessageId::new()));
+    let _ = poll_all(&mut nodes, &mut clients);
+    expect_any_event!(nodes[1], Event::ClientUnreachable(auth) if auth == client);
+}
+
+#[test]
+fn cancelled_request_reported_to_recipients() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size + 1);
+    let mut clients = create_connected_clients(&network, &mut nodes, 1);
+    let client = Authority::Client {
+        client_id: *clients[0].full_id.public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let data = gen_immutable_data(&mut rng, 1024);
+    let dst = Authority::NaeManager(*data.name());
+    let message_id = MessageId::new();
+    unwrap!(clients[0]
+                .inner
+                .send_get_request(dst, data.identifier(), message_id));
+    let _ = poll_all(&mut nodes, &mut clients);
+    for node in nodes.iter_mut().filter(|node| node.is_recipient(&dst)) {
+        expect_any_event!(node, Event::Request { request: Request::Get(_, id), .. }
+                          if id == message_id);
+    }
+
+    // The recipients are told, and the request can't be cancelled twice.
+    unwrap!(clients[0].inner.cancel_request(message_id));
+    let _ = poll_all(&mut nodes, &mut clients);
+    for node in nodes.iter_mut().filter(|node| node.is_recipient(&dst)) {
+        expect_any_event!(node, Event::RequestCancelled { message_id: id } if id == message_id);
+    }
+    match clients[0].inner.cancel_request(message_id) {
+        Err(InterfaceError::UnknownRequest) => (),
+        result => panic!("Unexpected result {:?}", result),
+    }
+
+    // Responses sent anyway are dropped by the client.
+    for node in nodes.iter_mut().filter(|node| node.is_recipient(&dst)) {
+        unwrap!(node.inner
+                    .send_get_success(dst, client, data.clone(), message_id));
+    }
+    let _ = poll_all(&mut nodes, &mut clients);
+    while let Ok(event) = clients[0].inner.try_next_ev() {
+        if let Event::Response { .. } = event {
+            panic!("Unexpected response {:?}", event);
+        }
+    }
+}
+
+#[test]
+fn request_cancelled_before_delivery_dropped() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size + 1);
+    let mut clients = create_connected_clients(&network, &mut nodes, 1);
+    let client_endpoint = clients[0].handle.endpoint();
+    let proxy_endpoint = nodes[0].handle.endpoint();
+    let dst = Authority::ClientManager(clients[0].name());
+    let message_id = MessageId::new();
+
+    // The request is lost on its way to the proxy node.
+    network.blackhole_connection(client_endpoint, proxy_endpoint);
+    unwrap!(clients[0]
+                .inner
+                .send_put_request(dst, gen_immutable_data(&mut rng, 1024), message_id));
+    let _ = poll_all(&mut nodes, &mut clients);
+    network.unblackhole_connection(client_endpoint, proxy_endpoint);
+
+    // The cancellation reaches the recipients before the client resends the request, so they
+    // never deliver it.
+    unwrap!(clients[0].inner.cancel_request(message_id));
+    let _ = poll_all(&mut nodes, &mut clients);
+    FakeClock::advance_time(ACK_TIMEOUT_SECS * 1000 + 1);
+    let _ = poll_all(&mut nodes, &mut clients);
+    for node in nodes.iter_mut() {
+        while let Ok(event) = node.try_next_ev() {
+            match event {
+                Event::Request { request: Request::Put(_, id), .. } |
+                Event::RequestCancelled { message_id: id } if id == message_id => {
+                    panic!("Unexpected event {:?} at {}", event, node.name());
+                }
+                _ => (),
+            }
+        }
+        if node.is_recipient(&dst) {
+            let caches = unwrap!(node.inner.diagnostics()).caches;
+            assert_eq!(caches["cancelled_requests"].len, 1);
+            assert_eq!(caches["delivered_requests"].len, 0);
+        }
+    }
+}
+
+#[test]
+fn dispatcher_delivers_responses_to_requesting_threads() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let dispatcher = RoutingDispatcher::new();
+    let churn_rx = dispatcher.subscribe(EventMask::churn());
+
+    // Three threads each send a `Get` request via the first node, and wait for its response.
+    let src = Authority::ManagedNode(nodes[0].name());
+    let dst = Authority::ManagedNode(nodes[1].name());
+    let data = (0..3)
+        .map(|_| gen_immutable_data(&mut rng, 1024))
+        .collect::<Vec<_>>();
+    let (sent_tx, sent_rx) = mpsc::channel();
+    let threads = data.iter()
+        .map(|data| {
+            let handle = dispatcher.handle();
+            let sent_tx = sent_tx.clone();
+            let data_id = data.identifier();
+            thread::spawn(move || {
+                let message_id = MessageId::new();
+                let request = Request::Get(data_id, message_id);
+                let request_handle = handle.send_request(src, dst, request);
+                unwrap!(sent_tx.send(()));
+                (data_id, message_id, unwrap!(request_handle.wait()))
+            })
+        })
+        .collect::<Vec<_>>();
+    for _ in 0..threads.len() {
+        unwrap!(sent_rx.recv());
+    }
+
+    let _ = dispatcher.pump(&mut nodes[0].inner);
+    let _ = poll_all(&mut nodes, &mut []);
+    while let Ok(event) = nodes[1].try_next_ev() {
+        if let Event::Request { request: Request::Get(data_id, id), src, dst } = event {
+            let data = unwrap!(data.iter().find(|data| data.identifier() == data_id));
+            unwrap!(nodes[1].inner.send_get_success(dst, src, data.clone(), id));
+        }
+    }
+    let _ = poll_all(&mut nodes, &mut []);
+    let _ = dispatcher.pump(&mut nodes[0].inner);
+
+    for thread in threads {
+        match unwrap!(thread.join()) {
+            (data_id, message_id, Response::GetSuccess(data, id)) => {
+                assert_eq!(data_id, data.identifier());
+                assert_eq!(message_id, id);
+            }
+            (_, _, response) => panic!("Unexpected response {:?}", response),
+        }
+    }
+
+    // The churn subscriber is notified of a new node, and received none of the responses.
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    let endpoint = Endpoint(nodes.len());
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(endpoint)
+                   .create());
+    poll_and_resend(&mut nodes, &mut []);
+    let _ = dispatcher.pump(&mut nodes[0].inner);
+    let churn_events = churn_rx.try_iter().collect::<Vec<_>>();
+    assert!(churn_events
+                .iter()
+                .any(|event| match *event {
+                         Event::NodeAdded(..) => true,
+                         _ => false,
+                     }));
+    assert!(churn_events
+                .iter()
+                .all(|event| EventMask::churn().matches(event)));
+}
+
+#[test]
+fn dispatcher_pumps_client() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size + 1);
+    let mut clients = create_connected_clients(&network, &mut nodes, 1);
+    let dispatcher = RoutingDispatcher::new();
+
+    // The client always sends as itself, so the source given to the handle is ignored.
+    let data = gen_immutable_data(&mut rng, 1024);
+    let dst = Authority::NaeManager(*data.name());
+    let src = Authority::ManagedNode(nodes[0].name());
+    let message_id = MessageId::new();
+    let request_handle = dispatcher
+        .handle()
+        .send_request(src, dst, Request::Get(data.identifier(), message_id));
+    assert!(dispatcher.pump(&mut clients[0].inner));
+    let _ = poll_all(&mut nodes, &mut clients);
+
+    for node in nodes.iter_mut().filter(|node| node.is_recipient(&dst)) {
+        while let Ok(event) = node.try_next_ev() {
+            if let Event::Request { request: Request::Get(_, id), src, dst } = event {
+                assert!(src.is_client());
+                unwrap!(node.inner.send_get_success(dst, src, data.clone(), id));
+            }
+        }
+    }
+    let _ = poll_all(&mut nodes, &mut clients);
+    let _ = dispatcher.pump(&mut clients[0].inner);
+
+    match request_handle.try_response() {
+        Some(Response::GetSuccess(got, id)) => {
+            assert_eq!(got, data);
+            assert_eq!(id, message_id);
+        }
+        response => panic!("Unexpected response {:?}", response),
+    }
+}
+
+#[test]
+fn batch_reports_each_destination() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    let src = Authority::ManagedNode(nodes[0].name());
+    let mut requests = (1..5)
+        .map(|i| {
+                 let data = gen_immutable_data(&mut rng, 1024);
+                 let request = Request::Get(data.identifier(), MessageId::new());
+                 (Authority::ManagedNode(nodes[i].name()), request)
+             })
+        .collect::<Vec<_>>();
+    // A client which claims us as its proxy, but isn't connected to us, is unroutable.
+    let unroutable = Authority::Client {
+        client_id: *FullId::new().public_id(),
+        proxy_node_name: nodes[0].name(),
+    };
+    let data = gen_immutable_data(&mut rng, 1024);
+    requests.push((unroutable, Request::Get(data.identifier(), MessageId::new())));
+
+    unwrap!(nodes[0].inner.send_request_batch(src, requests.clone(), 7));
+    let _ = poll_all(&mut nodes, &mut []);
+
+    expect_any_event!(nodes[0], Event::BatchSent { batch_id: 7, ref succeeded, ref failed }
+        if succeeded.len() == 4 && failed == &vec![unroutable]);
+    while let Ok(event) = nodes[0].try_next_ev() {
+        if let Event::BatchSent { .. } = event {
+            panic!("Unexpected second {:?}", event);
+        }
+    }
+
+    for (i, &(dst, ref request)) in requests.iter().take(4).enumerate() {
+        let node = &mut nodes[i + 1];
+        expect_any_event!(node, Event::Request { request: ref received, dst: received_dst, .. }
+            if received == request && received_dst == dst);
+    }
+}
+
+#[test]
+fn slow_message_reports() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(Endpoint(min_section_size))
+                   .slow_message_reports(Duration::from_millis(50))
+                   .create());
+    let _ = poll_all(&mut nodes, &mut []);
+    let reporter = nodes.len() - 1;
+    let mut slow_reports = Vec::new();
+    while let Ok(event) = nodes[reporter].inner.try_next_ev() {
+        if let Event::SlowMessage(report) = event {
+            slow_reports.push(report);
+        }
+    }
+    assert!(slow_reports.is_empty());
+    let histograms = unwrap!(nodes[reporter].inner.diagnostics()).processing_histograms;
+    assert!(histograms.phase(ProcessingPhase::Decode)[0] > 0);
+    assert_eq!(histograms.phase(ProcessingPhase::Verify)[4], 0);
+
+    // Make signature verification take 100ms on every node.
+    let start = FakeClock::now();
+    inject_phase_cost(ProcessingPhase::Verify, 100);
+    let src = Authority::ManagedNode(nodes[0].name());
+    let dst = Authority::ManagedNode(nodes[reporter].name());
+    let data = gen_immutable_data(&mut rng, 1024);
+    unwrap!(nodes[0]
+                .inner
+                .send_put_request(src, dst, data, MessageId::new()));
+    let _ = poll_all(&mut nodes, &mut []);
+    inject_phase_cost(ProcessingPhase::Verify, 0);
+
+    while let Ok(event) = nodes[reporter].inner.try_next_ev() {
+        if let Event::SlowMessage(report) = event {
+            slow_reports.push(report);
+        }
+    }
+    assert!(!slow_reports.is_empty());
+    for report in &slow_reports {
+        assert_eq!(report.dominant_phase, ProcessingPhase::Verify);
+        assert!(report.total >= Duration::from_millis(100));
+    }
+    // Reports are rate limited.
+    let max_reports = start.elapsed().as_secs() / SLOW_MESSAGE_REPORT_INTERVAL_SECS + 1;
+    assert!(slow_reports.len() as u64 <= max_reports);
+    let histograms = unwrap!(nodes[reporter].inner.diagnostics()).processing_histograms;
+    assert!(histograms.phase(ProcessingPhase::Verify)[4] > 0);
+}
+
+// Returns the records added to the node's decision log since the last call, which returned
+// `seen` records in total.
+fn new_decision_records(node: &mut TestNode, seen: &mut usize) -> Vec<DecisionRecord> {
+    let records = unwrap!(decode_decision_log(&unwrap!(node.inner.decision_log())));
+    let new_records = records[*seen..].to_vec();
+    *seen = records.len();
+    new_records
+}
+
+#[test]
+fn decision_log_records_routing_decisions() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(Endpoint(min_section_size))
+                   .decision_log(10_000)
+                   .create());
+    let _ = poll_all(&mut nodes, &mut []);
+    let logger = nodes.len() - 1;
+    while nodes[logger].inner.try_next_ev().is_ok() {}
+    let mut seen = 0;
+    let _ = new_decision_records(&mut nodes[logger], &mut seen);
+    assert!(seen > 0);
+
+    // A request we send is forwarded straight to its recipient, as we are connected to it.
+    let src = Authority::ManagedNode(nodes[logger].name());
+    let dst = Authority::ManagedNode(nodes[1].name());
+    let data = gen_immutable_data(&mut rng, 1024);
+    unwrap!(nodes[logger]
+                .inner
+                .send_put_request(src, dst, data, MessageId::new()));
+    let _ = poll_all(&mut nodes, &mut []);
+    let records = new_decision_records(&mut nodes[logger], &mut seen);
+    let logger_name = nodes[logger].name();
+    let sent: Vec<_> = records
+        .iter()
+        .filter(|record| record.peer_is(&logger_name) && record.dst == AuthorityKind::ManagedNode)
+        .filter(|record| record.decision == Decision::Forwarded(1))
+        .collect();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].route, 0);
+    let sent_hash = sent[0].msg_hash;
+    assert_eq!(records
+                   .iter()
+                   .filter(|record| record.msg_hash == sent_hash)
+                   .count(),
+               1);
+    assert_eq!(put_request_ids(&mut nodes[1]).len(), 1);
+
+    // A request to us is delivered once. With our ack lost, its retry on the next route is only
+    // filtered.
+    network.blackhole_connection(nodes[logger].handle.endpoint(), nodes[0].handle.endpoint());
+    let src = Authority::ManagedNode(nodes[0].name());
+    let dst = Authority::ManagedNode(nodes[logger].name());
+    let data = gen_immutable_data(&mut rng, 1024);
+    unwrap!(nodes[0]
+                .inner
+                .send_put_request(src, dst, data, MessageId::new()));
+    let _ = poll_all(&mut nodes, &mut []);
+    FakeClock::advance_time(ACK_TIMEOUT_SECS * 1000 + 1);
+    let _ = poll_all(&mut nodes, &mut []);
+    network.unblackhole_connection(nodes[logger].handle.endpoint(), nodes[0].handle.endpoint());
+    assert_eq!(put_request_ids(&mut nodes[logger]).len(), 1);
+
+    let records = new_decision_records(&mut nodes[logger], &mut seen);
+    let sender_name = nodes[0].name();
+    let delivered: Vec<_> = records
+        .iter()
+        .filter(|record| record.peer_is(&sender_name) && record.decision == Decision::Delivered)
+        .collect();
+    assert_eq!(delivered.len(), 1);
+    let received_hash = delivered[0].msg_hash;
+    let received: Vec<_> = records
+        .iter()
+        .filter(|record| record.msg_hash == received_hash)
+        .map(|record| (record.decision, record.route))
+        .collect();
+    assert_eq!(received,
+               vec![(Decision::Received, 0),
+                    (Decision::Filtered(FilterOutcome::New), 0),
+                    (Decision::Delivered, 0),
+                    (Decision::Received, 1),
+                    (Decision::Filtered(FilterOutcome::KnownMessage), 1)]);
+    let first = unwrap!(records.iter().find(|record| record.msg_hash == received_hash));
+    let last = unwrap!(records.iter().rev().find(|record| record.msg_hash == received_hash));
+    assert!(last.elapsed - first.elapsed >= Duration::from_secs(ACK_TIMEOUT_SECS));
+}
+
+#[test]
+fn identical_payloads_held_once_while_unacknowledged() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let data_len = 1024 * 1024;
+    let data = gen_immutable_data(&mut rng, data_len);
+
+    // Send the same chunk to five peers, none of which receive it yet.
+    let recipients = 1..6;
+    for index in recipients.clone() {
+        network.blackhole_connection(nodes[0].handle.endpoint(), nodes[index].handle.endpoint());
+    }
+    let src = Authority::ManagedNode(nodes[0].name());
+    let mut msg_ids = Vec::new();
+    for index in recipients.clone() {
+        let dst = Authority::ManagedNode(nodes[index].name());
+        let msg_id = MessageId::new();
+        unwrap!(nodes[0]
+                    .inner
+                    .send_put_request(src, dst, data.clone(), msg_id));
+        msg_ids.push(msg_id);
+    }
+    let _ = poll_all(&mut nodes, &mut []);
+
+    // Only the last part of each message differs, as it contains the message ID.
+    let diagnostics = unwrap!(nodes[0].inner.diagnostics());
+    assert!(diagnostics.pooled_payload_bytes >= data_len);
+    assert!(diagnostics.pooled_payload_bytes < 2 * data_len);
+
+    for index in recipients.clone() {
+        network.unblackhole_connection(nodes[0].handle.endpoint(), nodes[index].handle.endpoint());
+    }
+    poll_and_resend(&mut nodes, &mut []);
+    for (index, msg_id) in recipients.zip(msg_ids) {
+        let mut received = false;
+        while let Ok(event) = nodes[index].try_next_ev() {
+            if let Event::Request { request: Request::Put(ref put_data, id), .. } = event {
+                received = received || (id == msg_id && *put_data == data);
+            }
+        }
+        assert!(received, "Node {} didn't receive the chunk.", index);
+    }
+    assert_eq!(unwrap!(nodes[0].inner.diagnostics()).pooled_payloads, 0);
+}
+
+// Sends get requests for ten different names from a client, one at a time, and measures how long
+// they take to reach their destination sections.
+fn get_request_latency(seed: [u32; 4], slow_link_period: Option<usize>) -> LatencyReport {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, Some(seed));
+    let mut nodes = create_connected_nodes(&network, min_section_size + 1);
+    let mut clients = create_connected_clients(&network, &mut nodes, 1);
+
+    let mut tracker = LatencyTracker::new(&network);
+    if let Some(period) = slow_link_period {
+        tracker.slow_link(clients[0].handle.endpoint(), nodes[0].handle.endpoint(), period);
+    }
+    for i in 0..10 {
+        let name = XorName([i; XOR_NAME_LEN]);
+        let msg_id = MessageId::new();
+        unwrap!(clients[0]
+                    .inner
+                    .send_get_request(Authority::NaeManager(name),
+                                      DataIdentifier::Immutable(name),
+                                      msg_id));
+        tracker.track(msg_id);
+        tracker.poll_all(&mut nodes, &mut clients);
+    }
+    tracker.latency_report()
+}
+
+#[test]
+fn latency_report_reflects_slow_links() {
+    let seed = [1, 2, 3, 4];
+    let report = get_request_latency(seed, None);
+    assert_eq!(report.lost, 0);
+    assert_eq!(report.samples.len(), 10);
+    assert_eq!(report, get_request_latency(seed, None));
+
+    let slow_report = get_request_latency(seed, Some(5));
+    assert_eq!(slow_report.lost, 0);
+    assert!(slow_report.steps_percentile(50) > report.steps_percentile(50));
+    assert!(slow_report.elapsed_percentile(100) > report.elapsed_percentile(100));
+    assert_eq!(slow_report, get_request_latency(seed, Some(5)));
+}
+
+#[test]
+fn group_fanout_is_capped() {
+    let min_section_size = 8;
+    let quorum = min_section_size * QUORUM_NUMERATOR / QUORUM_DENOMINATOR + 1;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+
+    // Every node caps the fan-out towards a group at a quorum.
+    let mut nodes = vec![TestNode::builder(&network)
+                             .first()
+                             .group_fanout(quorum)
+                             .create()];
+    let _ = nodes[0].poll();
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    for _ in 1..(min_section_size + 5) {
+        nodes.push(TestNode::builder(&network)
+                       .config(config.clone())
+                       .group_fanout(quorum)
+                       .create());
+        poll_and_resend(&mut nodes, &mut []);
+    }
+    verify_invariant_for_all_nodes(&mut nodes);
+
+    // The sender isn't a member of the destination group, so only the hop towards it is capped.
+    let index = nodes.len() - 1;
+    let mut dst_name: XorName = rng.gen();
+    while nodes[index]
+              .inner
+              .close_group(dst_name, min_section_size)
+              .is_some() {
+        dst_name = rng.gen();
+    }
+    let src = Authority::ManagedNode(nodes[index].name());
+    let dst = Authority::NaeManager(dst_name);
+    let sender = nodes[index].handle.endpoint();
+    let receivers: Vec<_> = nodes[..index]
+        .iter()
+        .map(|node| node.handle.endpoint())
+        .collect();
+    for &receiver in &receivers {
+        network.hold_connection(sender, receiver);
+    }
+    let data_id = gen_immutable_data(&mut rng, 8).identifier();
+    let message_id = MessageId::new();
+    unwrap!(nodes[index]
+                .inner
+                .send_get_request(src, dst, data_id, message_id));
+    let _ = nodes[index].poll();
+    let first_hops = receivers
+        .iter()
+        .filter(|&&receiver| {
+                    network
+                        .pending_packets(sender, receiver)
+                        .contains(&PacketKind::Message)
+                })
+        .count();
+    assert_eq!(first_hops, quorum);
+
+    // The members it skipped receive the request from the others, which pass it on to the whole
+    // group.
+    for &receiver in &receivers {
+        network.release_connection(sender, receiver);
+    }
+    poll_and_resend(&mut nodes, &mut []);
+    let mut request_received_count = 0;
+    for node in nodes[..index]
+            .iter_mut()
+            .filter(|node| node.is_recipient(&dst)) {
+        while let Ok(event) = node.try_next_ev() {
+            if let Event::Request { request: Request::Get(_, id), .. } = event {
+                if id == message_id {
+                    request_received_count += 1;
+                }
+            }
+        }
+    }
+    assert_eq!(request_received_count, min_section_size);
+}
+
+#[test]
+fn group_fanout_below_quorum_rejected() {
+    let min_section_size = 8;
+    let quorum = min_section_size * QUORUM_NUMERATOR / QUORUM_DENOMINATOR + 1;
+    let network = Network::new(min_section_size, None);
+    let handle = network.new_service_handle(None, None);
+    let result = mock_crust::make_current(&handle, || {
+        Node::builder()
+            .first(true)
+            .group_fanout(quorum - 1)
+            .create(min_section_size)
+    });
+    match result {
+        Err(RoutingError::InvalidConfig) => (),
+        Err(error) => panic!("Unexpected error: {:?}", error),
+        Ok(_) => panic!("Created a node with a group fan-out below quorum."),
+    }
+}
+
+// Returns the first decision reported for a message we sent to `dst`, and drops all others.
+fn originated_decision(decision_rx: &mpsc::Receiver<ForwardingDecision>,
+                       dst: &Authority<XorName>)
+                       -> ForwardingDecision {
+    let decisions: Vec<_> = decision_rx.try_iter().collect();
+    unwrap!(decisions
+                .into_iter()
+                .find(|decision| {
+                          decision.reason == ForwardingReason::Originated &&
+                          decision.destination == *dst
+                      }))
+}
+
+#[test]
+fn forwarding_decisions_only() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+
+    let (decision_tx, decision_rx) = mpsc::channel();
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    let endpoint = Endpoint(min_section_size);
+    nodes.push(TestNode::builder(&network)
+                   .config(config)
+                   .endpoint(endpoint)
+                   .forwarding_sink(Box::new(decision_tx))
+                   .create());
+    poll_and_resend(&mut nodes, &mut []);
+    let index = nodes.len() - 1;
+    let src = Authority::ManagedNode(nodes[index].name());
+    let dst = Authority::NaeManager(rng.gen());
+    let data_id = DataIdentifier::Immutable(rng.gen());
+    let _ = decision_rx.try_iter().count();
+
+    // Normally, the message is sent to the reported hops.
+    network.reset_traffic_stats();
+    unwrap!(nodes[index]
+                .inner
+                .send_get_request(src, dst, data_id, MessageId::new()));
+    let _ = poll_all(&mut nodes, &mut []);
+    let sent = originated_decision(&decision_rx, &dst);
+    assert!(!sent.chosen_hops.is_empty());
+    assert!(network.bytes_sent(endpoint) > 0);
+
+    // In decision-only mode, the same hops are reported, but nothing is sent.
+    let update = PartialConfig {
+        live: LiveConfig {
+            decisions_only: Some(true),
+            ..LiveConfig::default()
+        },
+        ..PartialConfig::default()
+    };
+    unwrap!(nodes[index].inner.update_config(update));
+    let _ = poll_all(&mut nodes, &mut []);
+    network.reset_traffic_stats();
+    unwrap!(nodes[index]
+                .inner
+                .send_get_request(src, dst, data_id, MessageId::new()));
+    let _ = poll_all(&mut nodes, &mut []);
+    let decided = originated_decision(&decision_rx, &dst);
+    assert_eq!(decided.chosen_hops, sent.chosen_hops);
+    assert_ne!(decided.message_hash, sent.message_hash);
+    assert_eq!(network.bytes_sent(endpoint), 0);
+}
+
+// Creates a lone seed node with the given keys and RNG seed, which logs its routing decisions.
+fn create_replayable_node(network: &Network<PublicId>,
+                          full_id: &FullId,
+                          rng_seed: [u32; 4],
+                          record_inputs: bool)
+                          -> TestNode {
+    let builder = TestNode::builder(network)
+        .first()
+        .full_id(full_id.clone())
+        .rng_seed(rng_seed)
+        .decision_log(1000);
+    if record_inputs {
+        builder.record_inputs().create()
+    } else {
+        builder.create()
+    }
+}
+
+#[test]
+fn input_log_replay() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let full_id = FullId::new();
+    let rng_seed = rng.gen();
+    let mut nodes = vec![create_replayable_node(&network, &full_id, rng_seed, true)];
+    let _ = poll_all(&mut nodes, &mut []);
+
+    let src = Authority::ManagedNode(nodes[0].name());
+    for _ in 0..3 {
+        let dst = Authority::NaeManager(rng.gen());
+        let data = gen_immutable_data(&mut rng, 1024);
+        unwrap!(nodes[0]
+                    .inner
+                    .send_put_request(src, dst, data, MessageId::new()));
+        let _ = poll_all(&mut nodes, &mut []);
+    }
+    FakeClock::advance_time(ACK_TIMEOUT_SECS * 1000 + 1);
+    let _ = poll_all(&mut nodes, &mut []);
+
+    let log = nodes[0].inner.take_input_log();
+    let recorded = unwrap!(nodes[0].inner.decision_log());
+    assert!(!unwrap!(decode_decision_log(&recorded)).is_empty());
+    let sends = log.records
+        .iter()
+        .filter(|record| match record.input {
+                    RecordedInput::NodeSendMessage { .. } => true,
+                    _ => false,
+                })
+        .count();
+    assert_eq!(sends, 3);
+
+    // The log survives serialisation, and replaying it against a fresh node with the same keys
+    // reproduces the decision log.
+    let log: InputLog = unwrap!(deserialise(&unwrap!(serialise(&log))));
+    let replay_network = Network::new(min_section_size, None);
+    let mut replayed = create_replayable_node(&replay_network, &full_id, rng_seed, false);
+    assert_eq!(replayed.inner.replay_input_log(&log), Ok(()));
+    assert_eq!(unwrap!(replayed.inner.decision_log()), recorded);
+
+    // A handler picking a destination the recorded run didn't, as one drawing from an unseeded
+    // RNG would, is reported at the step which handled it.
+    let mut perturbed = log.clone();
+    let step = unwrap!(perturbed
+                           .records
+                           .iter()
+                           .position(|record| match record.input {
+                                         RecordedInput::NodeSendMessage { .. } => true,
+                                         _ => false,
+                                     }));
+    if let RecordedInput::NodeSendMessage { ref mut dst, .. } = perturbed.records[step].input {
+        *dst = Authority::NaeManager(rng.gen());
+    }
+    let replay_network = Network::new(min_section_size, None);
+    let mut replayed = create_replayable_node(&replay_network, &full_id, rng_seed, false);
+    let divergence = match replayed.inner.replay_input_log(&perturbed) {
+        Err(divergence) => divergence,
+        Ok(()) => panic!("Replay didn't diverge."),
+    };
+    assert_eq!(divergence.step, step);
+    assert_eq!(divergence.expected, log.records[step].decisions);
+}
+
+#[test]
+fn duplicated_messages_are_filtered() {
+    let min_section_size = 8;
+    let network = Network::new(min_section_size, None);
+    let mut rng = network.new_rng();
+    let mut nodes = create_connected_nodes(&network, min_section_size);
+    let endpoints: Vec<_> = nodes.iter().map(|node| node.handle.endpoint()).collect();
+    for &sender in &endpoints {
+        for &receiver in endpoints.iter().filter(|&&receiver| receiver != sender) {
+            network.set_duplication(sender, receiver, 1.0);
+        }
+    }
+    let deliveries = Rc::new(Cell::new(0));
+    let deliveries_clone = deliveries.clone();
+    let (endpoint_0, endpoint_1) = (endpoints[0], endpoints[1]);
+    network.set_packet_observer(move |packet| if packet.sender == endpoint_0 &&
+                                                 packet.receiver == endpoint_1 &&
+                                                 packet.kind == PacketKind::Message &&
+                                                 packet.delivery == Delivery::Delivered {
+                                    deliveries_clone.set(deliveries_clone.get() + 1);
+                                });
+
+    let src = Authority::ManagedNode(nodes[0].name());
+    let dst = Authority::ManagedNode(nodes[1].name());
+    let data_id = gen_immutable_data(&mut rng, 8).identifier();
+    let msg_id = MessageId::new();
+    unwrap!(nodes[0].inner.send_get_request(src, dst, data_id, msg_id));
+    poll_and_resend(&mut nodes, &mut []);
+    network.clear_packet_observer();
+
+    // Every message reached the receiver twice, but the request is only raised once.
+    assert!(deliveries.get() >= 2 && deliveries.get() % 2 == 0);
+    let mut received_count = 0;
+    while let Ok(event) = nodes[1].try_next_ev() {
+        if let Event::Request { request: Request::Get(_, id), .. } = event {
+            if id == msg_id {
+                received_count += 1;
+            }
+        }
+    }
+    assert_eq!(received_count, 1);
+    verify_invariant_for_all_nodes(&mut nodes);
+}
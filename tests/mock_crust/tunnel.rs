@@ -69,7 +69,7 @@ fn lost_connection_and_unidirectional_block() {
     let mut nodes = create_connected_nodes(&network, min_section_size);
     verify_invariant_for_all_nodes(&mut nodes);
 
-    network.lost_connection(Endpoint(2), Endpoint(3));
+    network.lost_connection_or_panic(Endpoint(2), Endpoint(3));
     network.block_connection(Endpoint(2), Endpoint(3));
     network.delay_connection(Endpoint(3), Endpoint(2));
     poll_and_resend(&mut nodes, &mut []);
@@ -231,7 +231,7 @@ fn tunnel_client_connect_failure() {
     let mut nodes = create_connected_nodes(&network, min_section_size);
     let tunnel_node_index = unwrap!(locate_tunnel_node(&nodes, nodes[2].id(), nodes[3].id()));
 
-    network.send_crust_event(Endpoint(2), crust::Event::ConnectFailure(nodes[3].id()));
+    network.send_crust_event_or_panic(Endpoint(2), crust::Event::ConnectFailure(nodes[3].id()));
     let _ = poll_all(&mut nodes, &mut []);
     verify_invariant_for_all_nodes(&mut nodes);
     assert_eq!(tunnel_node_index,
@@ -288,7 +288,7 @@ fn tunnel_node_disrupted() {
     let mut nodes = create_connected_nodes(&network, min_section_size);
     let tunnel_node_index = unwrap!(locate_tunnel_node(&nodes, nodes[2].id(), nodes[3].id()));
 
-    network.lost_connection(Endpoint(2), Endpoint(tunnel_node_index));
+    network.lost_connection_or_panic(Endpoint(2), Endpoint(tunnel_node_index));
     poll_and_resend(&mut nodes, &mut []);
     verify_tunnel_switch(&mut nodes, tunnel_node_index, 2, 3);
     assert_ne!(tunnel_node_index,
@@ -307,7 +307,7 @@ fn tunnel_node_blocked() {
 
     network.block_connection(Endpoint(2), Endpoint(tunnel_node_index));
     network.block_connection(Endpoint(tunnel_node_index), Endpoint(2));
-    network.lost_connection(Endpoint(2), Endpoint(tunnel_node_index));
+    network.lost_connection_or_panic(Endpoint(2), Endpoint(tunnel_node_index));
     poll_and_resend(&mut nodes, &mut []);
     verify_tunnel_switch(&mut nodes, tunnel_node_index, 2, 3);
     assert_ne!(tunnel_node_index,
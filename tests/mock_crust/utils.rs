@@ -18,16 +18,20 @@
 use fake_clock::FakeClock;
 use itertools::Itertools;
 use rand::Rng;
-use routing::{Authority, Cache, Client, Data, DataIdentifier, Event, EventStream, FullId,
-              ImmutableData, Node, NullCache, Prefix, PublicId, Request, Response, RoutingTable,
-              XorName, Xorable, verify_network_invariant};
+use routing::{Authority, Cache, Client, Data, DataIdentifier, Event, EventSink, EventStream,
+              ForwardingSink, FullId, ImmutableData, JoinProgress, JoinProgressMask, MessageId,
+              Node, NodeBuilder, NullCache, PendingWork, Prefix, PublicId, Request, Response,
+              RoutingTable, StateStore, XorName, Xorable, decode_decision_log,
+              verify_network_invariant};
 use routing::mock_crust::{self, Config, Endpoint, Network, ServiceHandle};
 use routing::test_consts::{ACK_TIMEOUT_SECS, CONNECTING_PEER_TIMEOUT_SECS};
 use std::{cmp, thread};
 use std::cell::RefCell;
 use std::collections::{BTreeSet, HashMap};
 use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::mpsc::{RecvError, TryRecvError};
+use std::time::{Duration, Instant};
 
 // Various utilities. Since this is all internal stuff we're a bit lax about the doc.
 #[allow(missing_docs)]
@@ -39,6 +43,15 @@ const BALANCED_POLLING: bool = true;
 // anticipated upper limit for any test, and if hit is likely to indicate an infinite loop.
 const MAX_POLL_CALLS: usize = 1000;
 
+// Maximum number of rounds of polling in `settle`.
+const MAX_SETTLE_ROUNDS: usize = 10;
+
+// Mock clock time advanced by each round of polling in `LatencyTracker`.
+const LATENCY_STEP_MILLIS: u64 = 10;
+
+// Number of most recently processed packets included in the watchdog's dump.
+const WATCHDOG_TRACE_LEN: usize = 20;
+
 // -----  Random number generation  -----
 
 pub fn gen_range<T: Rng>(rng: &mut T, low: usize, high: usize) -> usize {
@@ -120,30 +133,9 @@ impl TestNode {
     pub fn builder(network: &Network<PublicId>) -> TestNodeBuilder {
         TestNodeBuilder {
             network: network,
-            first_node: false,
             config: None,
             endpoint: None,
-            cache: Box::new(NullCache),
-        }
-    }
-
-    pub fn new(network: &Network<PublicId>,
-               first_node: bool,
-               config: Option<Config>,
-               endpoint: Option<Endpoint>,
-               cache: Box<Cache>)
-               -> Self {
-        let handle = network.new_service_handle(config, endpoint);
-        let node = mock_crust::make_current(&handle, || {
-            unwrap!(Node::builder()
-                        .cache(cache)
-                        .first(first_node)
-                        .create(network.min_section_size()))
-        });
-
-        TestNode {
-            handle: handle,
-            inner: node,
+            node_builder: Node::builder(),
         }
     }
 
@@ -173,15 +165,14 @@ impl TestNode {
 
 pub struct TestNodeBuilder<'a> {
     network: &'a Network<PublicId>,
-    first_node: bool,
     config: Option<Config>,
     endpoint: Option<Endpoint>,
-    cache: Box<Cache>,
+    node_builder: NodeBuilder,
 }
 
 impl<'a> TestNodeBuilder<'a> {
     pub fn first(mut self) -> Self {
-        self.first_node = true;
+        self.node_builder = self.node_builder.first(true);
         self
     }
 
@@ -196,21 +187,152 @@ impl<'a> TestNodeBuilder<'a> {
     }
 
     pub fn cache(mut self, use_cache: bool) -> Self {
-        self.cache = if use_cache {
+        let cache: Box<Cache> = if use_cache {
             Box::new(TestCache::new())
         } else {
             Box::new(NullCache)
         };
+        self.node_builder = self.node_builder.cache(cache);
+        self
+    }
+
+    pub fn join_progress_events(mut self, mask: JoinProgressMask) -> Self {
+        self.node_builder = self.node_builder.join_progress_events(mask);
+        self
+    }
+
+    pub fn max_peers_per_ip(mut self, max: usize) -> Self {
+        self.node_builder = self.node_builder.max_peers_per_ip(max);
+        self
+    }
+
+    pub fn connect_pacing(mut self, max_in_flight: usize, spacing: Duration) -> Self {
+        self.node_builder = self.node_builder.connect_pacing(max_in_flight, spacing);
+        self
+    }
+
+    pub fn connection_audit(mut self, interval: Duration, grace: Duration) -> Self {
+        self.node_builder = self.node_builder.connection_audit(interval, grace);
+        self
+    }
+
+    pub fn decision_log(mut self, capacity: usize) -> Self {
+        self.node_builder = self.node_builder.decision_log(capacity);
+        self
+    }
+
+    pub fn slow_message_reports(mut self, threshold: Duration) -> Self {
+        self.node_builder = self.node_builder.slow_message_reports(threshold);
+        self
+    }
+
+    pub fn table_gossip(mut self, interval: Duration, fanout: usize, sample_size: usize) -> Self {
+        self.node_builder = self.node_builder.table_gossip(interval, fanout, sample_size);
+        self
+    }
+
+    pub fn disconnected_recovery(mut self, queue_limit: usize) -> Self {
+        self.node_builder = self.node_builder.disconnected_recovery(queue_limit);
+        self
+    }
+
+    pub fn message_id_retry_capacity(mut self, capacity: usize) -> Self {
+        self.node_builder = self.node_builder.message_id_retry_capacity(capacity);
+        self
+    }
+
+    pub fn group_fanout(mut self, max: usize) -> Self {
+        self.node_builder = self.node_builder.group_fanout(max);
+        self
+    }
+
+    pub fn proxy_drop_threshold(mut self, entries: usize) -> Self {
+        self.node_builder = self.node_builder.proxy_drop_threshold(entries);
+        self
+    }
 
+    pub fn convergence_tracking(mut self,
+                                interval: Duration,
+                                stable_snapshots: usize,
+                                min_table_size: usize)
+                                -> Self {
+        self.node_builder = self.node_builder
+            .convergence_tracking(interval, stable_snapshots, min_table_size);
+        self
+    }
+
+    pub fn startup_queue_capacity(mut self, capacity: usize) -> Self {
+        self.node_builder = self.node_builder.startup_queue_capacity(capacity);
+        self
+    }
+
+    pub fn wire_versions(mut self, oldest: u8, newest: u8) -> Self {
+        self.node_builder = self.node_builder.wire_versions(oldest, newest);
+        self
+    }
+
+    pub fn health_events(mut self) -> Self {
+        self.node_builder = self.node_builder.health_events();
+        self
+    }
+
+    pub fn departure_consensus(mut self) -> Self {
+        self.node_builder = self.node_builder.departure_consensus();
+        self
+    }
+
+    pub fn full_id(mut self, full_id: FullId) -> Self {
+        self.node_builder = self.node_builder.full_id(full_id);
+        self
+    }
+
+    pub fn rng_seed(mut self, seed: [u32; 4]) -> Self {
+        self.node_builder = self.node_builder.rng_seed(seed);
+        self
+    }
+
+    pub fn record_inputs(mut self) -> Self {
+        self.node_builder = self.node_builder.record_inputs();
+        self
+    }
+
+    pub fn event_sink(mut self, sink: Box<EventSink>) -> Self {
+        self.node_builder = self.node_builder.event_sink(sink);
+        self
+    }
+
+    pub fn event_coalescing(mut self, window: Duration) -> Self {
+        self.node_builder = self.node_builder.event_coalescing(window);
+        self
+    }
+
+    pub fn forwarding_sink(mut self, sink: Box<ForwardingSink>) -> Self {
+        self.node_builder = self.node_builder.forwarding_sink(sink);
+        self
+    }
+
+    pub fn state_persistence(mut self,
+                             store: Box<StateStore>,
+                             interval: Duration,
+                             include_keys: bool)
+                             -> Self {
+        self.node_builder = self.node_builder
+            .state_persistence(store, interval, include_keys);
         self
     }
 
     pub fn create(self) -> TestNode {
-        TestNode::new(self.network,
-                      self.first_node,
-                      self.config,
-                      self.endpoint,
-                      self.cache)
+        let handle = self.network.new_service_handle(self.config, self.endpoint);
+        let min_section_size = self.network.min_section_size();
+        let node_builder = self.node_builder;
+        let node = mock_crust::make_current(&handle, || {
+                                                unwrap!(node_builder.create(min_section_size))
+                                            });
+
+        TestNode {
+            handle: handle,
+            inner: node,
+        }
     }
 }
 
@@ -228,7 +350,14 @@ impl TestClient {
                config: Option<Config>,
                endpoint: Option<Endpoint>)
                -> Self {
-        let full_id = FullId::new();
+        Self::with_full_id(network, config, endpoint, FullId::new())
+    }
+
+    pub fn with_full_id(network: &Network<PublicId>,
+                        config: Option<Config>,
+                        endpoint: Option<Endpoint>,
+                        full_id: FullId)
+                        -> Self {
         let handle = network.new_service_handle(config, endpoint);
         let client = mock_crust::make_current(&handle, || {
             unwrap!(Client::new(Some(full_id.clone()), network.min_section_size()))
@@ -284,6 +413,7 @@ pub fn poll_all(nodes: &mut [TestNode], clients: &mut [TestClient]) -> bool {
     assert!(!nodes.is_empty());
     let mut result = false;
     for _ in 0..MAX_POLL_CALLS {
+        check_watchdog(nodes);
         let mut handled_message = false;
         if BALANCED_POLLING {
             // handle all current messages for each node in turn, then repeat (via outer loop):
@@ -320,6 +450,69 @@ pub fn poll_and_resend(nodes: &mut [TestNode], clients: &mut [TestClient]) {
     panic!("Polling has been called {} times.", MAX_POLL_CALLS);
 }
 
+/// Polls all nodes and clients until the network is quiet and every node is idle, i.e. has no
+/// pending work and no timeout due within `horizon`. Doesn't advance the clock, so if any node is
+/// still busy after `MAX_SETTLE_ROUNDS` rounds of polling, returns the names of the busy nodes and
+/// their pending work.
+pub fn settle(nodes: &mut [TestNode],
+              clients: &mut [TestClient],
+              horizon: Duration)
+              -> Result<(), Vec<(XorName, PendingWork)>> {
+    let mut busy = Vec::new();
+    for _ in 0..MAX_SETTLE_ROUNDS {
+        let _ = poll_all(nodes, clients);
+        busy = nodes
+            .iter()
+            .map(|node| (node.name(), node.inner.pending_work(horizon)))
+            .filter(|&(_, ref work)| !work.is_idle())
+            .collect();
+        if busy.is_empty() {
+            return Ok(());
+        }
+    }
+    Err(busy)
+}
+
+/// Polls all nodes and clients until `predicate(index, event)` returns `true` for an event raised
+/// by the node at `index` in `nodes`, and returns that index. Whenever the network is quiet, the
+/// clock is advanced to trigger resends. All other events raised by the nodes are discarded.
+pub fn wait_for<F>(nodes: &mut [TestNode], clients: &mut [TestClient], mut predicate: F) -> usize
+    where F: FnMut(usize, &Event) -> bool
+{
+    for _ in 0..MAX_POLL_CALLS {
+        if !poll_all(nodes, clients) {
+            FakeClock::advance_time(ACK_TIMEOUT_SECS * 1000 + 1);
+        }
+        for (index, node) in nodes.iter_mut().enumerate() {
+            while let Ok(event) = node.try_next_ev() {
+                if predicate(index, &event) {
+                    return index;
+                }
+            }
+        }
+    }
+    panic!("Polling has been called {} times.", MAX_POLL_CALLS);
+}
+
+/// Polls the network until the node at `index` reports `JoinProgress::JoinComplete`, and returns
+/// the join steps it reported on the way, including the final one. The node needs to have been
+/// built with `join_progress_events` covering at least `JoinProgressMask::completion()`. Events of
+/// the other nodes are discarded.
+pub fn wait_until_joined(nodes: &mut [TestNode],
+                         clients: &mut [TestClient],
+                         index: usize)
+                         -> Vec<JoinProgress> {
+    let mut steps = Vec::new();
+    let _ = wait_for(nodes, clients, |node_index, event| match *event {
+        Event::JoinProgress(step) if node_index == index => {
+            steps.push(step);
+            step == JoinProgress::JoinComplete
+        }
+        _ => false,
+    });
+    steps
+}
+
 /// Checks each of the last `count` members of `nodes` for a `Connected` event, and removes those
 /// which don't fire one. Returns the number of removed nodes.
 pub fn remove_nodes_which_failed_to_connect(nodes: &mut Vec<TestNode>, count: usize) -> usize {
@@ -387,6 +580,7 @@ pub fn create_connected_nodes_with_cache(network: &Network<PublicId>,
                 Event::NodeAdded(..) => node_added_count += 1,
                 Event::NodeLost(..) |
                 Event::SectionSplit(..) |
+                Event::ProxyDropped |
                 Event::RestartRequired |
                 Event::Tick => (),
                 event => panic!("Got unexpected event: {:?}", event),
@@ -517,6 +711,7 @@ pub fn add_connected_nodes_until_split(network: &Network<PublicId>,
             match event {
                 Event::NodeAdded(..) |
                 Event::NodeLost(..) |
+                Event::ProxyDropped |
                 Event::Tick |
                 Event::SectionSplit(..) => (),
                 event => panic!("Got unexpected event: {:?}", event),
@@ -528,6 +723,51 @@ pub fn add_connected_nodes_until_split(network: &Network<PublicId>,
     trace!("Created testnet comprising {:?}", prefixes);
 }
 
+// Adds a node started with the ID at index `nodes.len()` of the network's `IdFactory`, and polls
+// until it has joined. If `prefix` is given, both that ID and the name the node is relocated to lie
+// within it.
+pub fn add_node(network: &Network<PublicId>,
+                nodes: &mut Vec<TestNode>,
+                prefix: Option<Prefix<XorName>>) {
+    let index = nodes.len();
+    let full_id = network
+        .id_factory()
+        .full_id(index as u32, &prefix.unwrap_or_default());
+    if nodes.is_empty() {
+        nodes.push(TestNode::builder(network)
+                       .first()
+                       .endpoint(Endpoint(0))
+                       .full_id(full_id)
+                       .create());
+        nodes[0].poll();
+        return;
+    }
+
+    if let Some(prefix) = prefix {
+        let relocation_name = *full_id.public_id().name();
+        for node in nodes.iter_mut() {
+            node.inner.set_next_relocation_dst(relocation_name);
+            node.inner
+                .set_next_relocation_interval((prefix.lower_bound(), prefix.upper_bound()));
+        }
+    }
+
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    nodes.push(TestNode::builder(network)
+                   .config(config)
+                   .endpoint(Endpoint(index))
+                   .full_id(full_id)
+                   .create());
+    poll_and_resend(nodes, &mut []);
+    expect_any_event!(nodes[index], Event::Connected);
+    for node in nodes.iter_mut() {
+        node.inner.clear_next_relocation_dst();
+    }
+    if let Some(prefix) = prefix {
+        assert!(prefix.matches(&nodes[index].name()));
+    }
+}
+
 // Create `size` clients, all of whom are connected to `nodes[0]`.
 pub fn create_connected_clients(network: &Network<PublicId>,
                                 nodes: &mut [TestNode],
@@ -548,6 +788,310 @@ pub fn create_connected_clients(network: &Network<PublicId>,
 }
 
 
+// -----  Watchdog  -----
+
+thread_local! {
+    static WATCHDOG: RefCell<Option<Watchdog>> = RefCell::new(None);
+}
+
+struct Watchdog {
+    network: Network<PublicId>,
+    max_steps: usize,
+    max_wall_time: Duration,
+    steps: usize,
+    started: Instant,
+}
+
+/// Removes the watchdog installed via `with_watchdog` when dropped.
+pub struct WatchdogGuard(());
+
+impl Drop for WatchdogGuard {
+    fn drop(&mut self) {
+        WATCHDOG.with(|watchdog| *watchdog.borrow_mut() = None);
+    }
+}
+
+/// Makes `poll_all`, and the helpers built on it such as `poll_and_resend`, `settle` and
+/// `wait_for`, fail the test once they have polled `max_steps` rounds in total on this thread, or
+/// once `max_wall_time` has passed, until the returned guard is dropped.
+///
+/// The panic message contains a dump of the nodes' connections, idle status and last logged
+/// decisions, and of the network's queued packets, impaired links and last processed packets. The
+/// last decisions are only available for nodes built with a decision log. Recording of the
+/// network's trace is started, so it shouldn't otherwise be used while the watchdog is installed.
+pub fn with_watchdog(network: &Network<PublicId>,
+                     max_steps: usize,
+                     max_wall_time: Duration)
+                     -> WatchdogGuard {
+    network.start_recording();
+    let watchdog = Watchdog {
+        network: network.clone(),
+        max_steps: max_steps,
+        max_wall_time: max_wall_time,
+        steps: 0,
+        started: Instant::now(),
+    };
+    WATCHDOG.with(|cell| *cell.borrow_mut() = Some(watchdog));
+    WatchdogGuard(())
+}
+
+// Counts a round of polling, and panics with a dump if the installed watchdog has run out of steps
+// or time.
+fn check_watchdog(nodes: &mut [TestNode]) {
+    let fired = WATCHDOG.with(|cell| {
+        let mut cell = cell.borrow_mut();
+        let exceeded = match cell.as_mut() {
+            None => return None,
+            Some(watchdog) => {
+                watchdog.steps += 1;
+                if watchdog.steps > watchdog.max_steps {
+                    format!("{} steps", watchdog.max_steps)
+                } else if watchdog.started.elapsed() > watchdog.max_wall_time {
+                    format!("{:?} of wall time", watchdog.max_wall_time)
+                } else {
+                    return None;
+                }
+            }
+        };
+        // Uninstall the watchdog, so it doesn't fire again while the test unwinds.
+        cell.take().map(|watchdog| (watchdog.network, exceeded))
+    });
+    if let Some((network, exceeded)) = fired {
+        panic!("Watchdog fired after {}.\n{}",
+               exceeded,
+               watchdog_dump(&network, nodes));
+    }
+}
+
+// Describes the state of the nodes and the network. Each section is assembled separately, and is
+// replaced by a note if that panics, e.g. because the state is still borrowed.
+fn watchdog_dump(network: &Network<PublicId>, nodes: &mut [TestNode]) -> String {
+    let mut dump = String::new();
+    for (index, node) in nodes.iter_mut().enumerate() {
+        dump_section(&mut dump, &format!("Node {}", index), || {
+            let last_decision = node.inner
+                .decision_log()
+                .ok()
+                .and_then(|bytes| decode_decision_log(&bytes))
+                .and_then(|records| records.last().cloned());
+            format!("    {:?} at {:?}: {} connections, idle: {}, last decision: {:?}\n",
+                    node.inner.id().ok().map(|id| *id.name()),
+                    node.handle.endpoint(),
+                    node.handle.connection_count(),
+                    node.inner.is_idle(Duration::from_secs(0)),
+                    last_decision)
+        });
+    }
+    dump_section(&mut dump, "Queued packets", || {
+        let endpoints = network.endpoints();
+        let mut text = String::new();
+        for &sender in &endpoints {
+            for &receiver in &endpoints {
+                let packets = network.pending_packets(sender, receiver);
+                if !packets.is_empty() {
+                    text.push_str(&format!("    {:?} -> {:?}: {:?}\n", sender, receiver, packets));
+                }
+            }
+        }
+        text
+    });
+    dump_section(&mut dump, "Impaired links", || {
+        network
+            .impaired_links()
+            .into_iter()
+            .map(|(sender, receiver, impairment)| {
+                     format!("    {:?} -> {:?}: {:?}\n", sender, receiver, impairment)
+                 })
+            .collect()
+    });
+    dump_section(&mut dump,
+                 &format!("Last {} processed packets", WATCHDOG_TRACE_LEN),
+                 || {
+        let trace = network.take_trace();
+        trace[trace.len().saturating_sub(WATCHDOG_TRACE_LEN)..]
+            .iter()
+            .map(|entry| {
+                     format!("    {:?} -> {:?}: {:?}\n",
+                             entry.sender,
+                             entry.receiver,
+                             entry.kind)
+                 })
+            .collect()
+    });
+    dump
+}
+
+fn dump_section<F: FnOnce() -> String>(dump: &mut String, title: &str, section: F) {
+    dump.push_str(title);
+    dump.push_str(":\n");
+    match panic::catch_unwind(AssertUnwindSafe(section)) {
+        Ok(text) => dump.push_str(&text),
+        Err(_) => dump.push_str("    <unavailable>\n"),
+    }
+}
+
+
+// -----  Latency measurement  -----
+
+/// The end-to-end latency of a single message, as measured by `LatencyTracker`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LatencySample {
+    /// The number of polling rounds between sending the message and its first arrival.
+    pub steps: usize,
+    /// The mock clock time elapsed in between.
+    pub elapsed: Duration,
+}
+
+/// The latencies measured by a `LatencyTracker`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LatencyReport {
+    /// The samples of the messages which arrived, in the order they were sent.
+    pub samples: Vec<LatencySample>,
+    /// The number of tracked messages which never arrived.
+    pub lost: usize,
+}
+
+impl LatencyReport {
+    /// Returns the given percentile of the step counts, using the nearest-rank method.
+    pub fn steps_percentile(&self, percentile: usize) -> usize {
+        nearest_rank(self.samples.iter().map(|sample| sample.steps).collect(),
+                     percentile)
+    }
+
+    /// Returns the given percentile of the elapsed times, using the nearest-rank method.
+    pub fn elapsed_percentile(&self, percentile: usize) -> Duration {
+        nearest_rank(self.samples.iter().map(|sample| sample.elapsed).collect(),
+                     percentile)
+    }
+}
+
+fn nearest_rank<T: Copy + Ord>(mut values: Vec<T>, percentile: usize) -> T {
+    assert!(!values.is_empty() && percentile <= 100);
+    values.sort();
+    let rank = cmp::max(1, (percentile * values.len() + 99) / 100);
+    values[rank - 1]
+}
+
+/// Measures how many rounds of polling, and how much mock clock time, tracked messages take from
+/// being sent until the first `Event::Request` or `Event::Response` with their message ID is
+/// raised at any node or client.
+///
+/// Polls the nodes and clients in a fixed order, one round at a time, advancing the mock clock by
+/// a fixed amount per round. All events are consumed while polling.
+pub struct LatencyTracker {
+    network: Network<PublicId>,
+    step: usize,
+    /// The send step and time of each tracked message, in the order they were sent.
+    sent: Vec<(MessageId, usize, FakeClock)>,
+    arrived: HashMap<MessageId, LatencySample>,
+    /// Routes which only pass packets every given number of steps.
+    slow_links: Vec<(Endpoint, Endpoint, usize)>,
+}
+
+impl LatencyTracker {
+    pub fn new(network: &Network<PublicId>) -> Self {
+        LatencyTracker {
+            network: network.clone(),
+            step: 0,
+            sent: Vec::new(),
+            arrived: HashMap::new(),
+            slow_links: Vec::new(),
+        }
+    }
+
+    /// Makes the route from `sender` to `receiver` hold its packets back, only passing them on
+    /// every `period` steps.
+    pub fn slow_link(&mut self, sender: Endpoint, receiver: Endpoint, period: usize) {
+        assert!(period > 0);
+        self.network.hold_connection(sender, receiver);
+        self.slow_links.push((sender, receiver, period));
+    }
+
+    /// Starts measuring the latency of the message which was just sent with the given ID.
+    pub fn track(&mut self, msg_id: MessageId) {
+        self.sent.push((msg_id, self.step, FakeClock::now()));
+    }
+
+    /// Polls in rounds until the network is quiet and no packets are held back by slow links.
+    pub fn poll_all(&mut self, nodes: &mut [TestNode], clients: &mut [TestClient]) {
+        for _ in 0..MAX_POLL_CALLS {
+            let handled_message = self.poll_round(nodes, clients);
+            let held = self.slow_links
+                .iter()
+                .any(|&(sender, receiver, _)| {
+                         !self.network.pending_packets(sender, receiver).is_empty()
+                     });
+            if !handled_message && !held && !self.network.reset_message_sent() {
+                return;
+            }
+        }
+        panic!("Polling has been called {} times.", MAX_POLL_CALLS);
+    }
+
+    /// Returns the latencies measured so far.
+    pub fn latency_report(&self) -> LatencyReport {
+        let samples: Vec<_> = self.sent
+            .iter()
+            .filter_map(|&(ref msg_id, ..)| self.arrived.get(msg_id).cloned())
+            .collect();
+        LatencyReport {
+            lost: self.sent.len() - samples.len(),
+            samples: samples,
+        }
+    }
+
+    // Polls every node and then every client once, recording the arrival of tracked messages.
+    // Returns whether any of them handled a message.
+    fn poll_round(&mut self, nodes: &mut [TestNode], clients: &mut [TestClient]) -> bool {
+        self.step += 1;
+        FakeClock::advance_time(LATENCY_STEP_MILLIS);
+        for &(sender, receiver, period) in &self.slow_links {
+            if self.step % period == 0 {
+                self.network.release_connection(sender, receiver);
+            } else {
+                self.network.hold_connection(sender, receiver);
+            }
+        }
+        self.network.poll();
+
+        let mut handled_message = false;
+        for node in nodes.iter_mut() {
+            handled_message = node.poll() || handled_message;
+            while let Ok(event) = node.try_next_ev() {
+                self.record_arrival(&event);
+            }
+        }
+        for client in clients.iter_mut() {
+            handled_message = client.inner.poll() || handled_message;
+            while let Ok(event) = client.inner.try_next_ev() {
+                self.record_arrival(&event);
+            }
+        }
+        handled_message
+    }
+
+    fn record_arrival(&mut self, event: &Event) {
+        let msg_id = match *event {
+            Event::Request { ref request, .. } => request.message_id(),
+            Event::Response { ref response, .. } => response.message_id(),
+            _ => return,
+        };
+        if self.arrived.contains_key(&msg_id) {
+            return;
+        }
+        let step = self.step;
+        if let Some(&(_, sent_step, ref sent_at)) =
+            self.sent.iter().find(|&&(ref id, ..)| *id == msg_id) {
+            let sample = LatencySample {
+                steps: step - sent_step,
+                elapsed: sent_at.elapsed(),
+            };
+            let _ = self.arrived.insert(msg_id, sample);
+        }
+    }
+}
+
+
 // -----  Small misc functions  -----
 
 /// Sorts the given nodes by their distance to `name`. Note that this will call the `name()`
@@ -0,0 +1,268 @@
+// Copyright 2017 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under (1) the MaidSafe.net Commercial License,
+// version 1.0 or later, or (2) The General Public License (GPL), version 3, depending on which
+// licence you accepted on initial access to the Software (the "Licences").
+//
+// By contributing code to the SAFE Network Software, or to this project generally, you agree to be
+// bound by the terms of the MaidSafe Contributor Agreement.  This, along with the Licenses can be
+// found in the root directory of this project at LICENSE, COPYING and CONTRIBUTOR.
+//
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied.
+//
+// Please review the Licences for the specific language governing permissions and limitations
+// relating to use of the SAFE Network Software.
+
+//! A minimal end-to-end example of the public API, run against mock Crust: a small network of
+//! nodes runs an in-memory key-value "vault", and a client stores, retrieves and updates data.
+//!
+//! Unlike the `mock_crust_tests` suite, this only uses what downstream crates can use, so that
+//! changes to the public API surface show up here.
+
+#![cfg(feature = "use-mock-crust")]
+
+// For explanation of lint checks, run `rustc -W help` or see
+// https://github.com/maidsafe/QA/blob/master/Documentation/Rust%20Lint%20Checks.md
+#![forbid(bad_style, exceeding_bitshifts, mutable_transmutes, no_mangle_const_items,
+          unknown_crate_types, warnings)]
+#![deny(deprecated, improper_ctypes, missing_docs,
+        non_shorthand_field_patterns, overflowing_literals, plugin_as_library,
+        private_no_mangle_fns, private_no_mangle_statics, stable_features, unconditional_recursion,
+        unknown_lints, unsafe_code, unused, unused_allocation, unused_attributes,
+        unused_comparisons, unused_features, unused_parens, while_true)]
+#![warn(trivial_casts, trivial_numeric_casts, unused_extern_crates, unused_import_braces,
+        unused_qualifications, unused_results)]
+#![allow(box_pointers, fat_ptr_transmutes, missing_copy_implementations,
+         missing_debug_implementations, variant_size_differences)]
+
+extern crate fake_clock;
+extern crate routing;
+#[macro_use]
+extern crate unwrap;
+
+use fake_clock::FakeClock;
+use routing::{Authority, Client, Data, DataIdentifier, Event, EventStream, ImmutableData,
+              InterfaceError, MessageId, Node, PublicId, Request, Response, StructuredData,
+              XOR_NAME_LEN, XorName};
+use routing::mock_crust::{self, Config, Network, ServiceHandle};
+use routing::test_consts::{ACK_TIMEOUT_SECS, CONNECTING_PEER_TIMEOUT_SECS};
+use std::collections::{BTreeSet, HashMap};
+
+const MIN_SECTION_SIZE: usize = 8;
+const SEED: [u32; 4] = [1, 2, 3, 4];
+const MAX_POLL_ROUNDS: usize = 1000;
+const TYPE_TAG: u64 = 10_000;
+
+// A node together with its share of the vault.
+struct VaultNode {
+    handle: ServiceHandle<PublicId>,
+    node: Node,
+    store: HashMap<DataIdentifier, Data>,
+}
+
+impl VaultNode {
+    fn new(network: &Network<PublicId>, config: Option<Config>, first: bool) -> Self {
+        let handle = network.new_service_handle(config, None);
+        let node = mock_crust::make_current(&handle, || {
+            unwrap!(Node::builder().first(first).create(network.min_section_size()))
+        });
+        VaultNode {
+            handle: handle,
+            node: node,
+            store: HashMap::new(),
+        }
+    }
+
+    // Handles all pending events, answering requests from the store. Returns whether there were
+    // any events.
+    fn poll(&mut self) -> bool {
+        let mut handled = self.node.poll();
+        while let Ok(event) = self.node.try_next_ev() {
+            handled = true;
+            if let Event::Request { request, src, dst } = event {
+                self.handle_request(request, src, dst);
+            }
+        }
+        handled
+    }
+
+    fn handle_request(&mut self,
+                      request: Request,
+                      src: Authority<XorName>,
+                      dst: Authority<XorName>) {
+        // Responses are sent from the authority the request was addressed to.
+        let result = match request {
+            Request::Put(data, id) => {
+                let data_id = data.identifier();
+                if self.store.contains_key(&data_id) {
+                    self.node
+                        .send_put_failure(dst, src, data_id, b"exists".to_vec(), id)
+                } else {
+                    let _ = self.store.insert(data_id, data);
+                    self.node.send_put_success(dst, src, data_id, id)
+                }
+            }
+            Request::Get(data_id, id) => {
+                match self.store.get(&data_id).cloned() {
+                    Some(data) => self.node.send_get_success(dst, src, data, id),
+                    None => {
+                        self.node
+                            .send_get_failure(dst, src, data_id, b"missing".to_vec(), id)
+                    }
+                }
+            }
+            Request::Post(data, id) => {
+                let data_id = data.identifier();
+                if self.store.contains_key(&data_id) {
+                    let _ = self.store.insert(data_id, data);
+                    self.node.send_post_success(dst, src, data_id, id)
+                } else {
+                    self.node
+                        .send_post_failure(dst, src, data_id, b"missing".to_vec(), id)
+                }
+            }
+            _ => Ok(()),
+        };
+        unwrap!(result);
+    }
+}
+
+// Polls the nodes and the client until the network is quiet, and collects the client's
+// responses.
+fn poll_all(nodes: &mut [VaultNode], client: Option<&Client>) -> Vec<Response> {
+    let mut responses = Vec::new();
+    for _ in 0..MAX_POLL_ROUNDS {
+        let mut handled = false;
+        for node in nodes.iter_mut() {
+            handled = node.poll() || handled;
+        }
+        if let Some(client) = client {
+            handled = client.poll() || handled;
+            while let Ok(event) = client.try_next_ev() {
+                handled = true;
+                if let Event::Response { response, .. } = event {
+                    responses.push(response);
+                }
+            }
+        }
+        if !handled && !nodes[0].handle.reset_message_sent() {
+            return responses;
+        }
+    }
+    panic!("Polling has been called {} times.", MAX_POLL_ROUNDS);
+}
+
+// Like `poll_all`, but also lets the timers for resending and for giving up on peers fire.
+fn poll_and_resend(nodes: &mut [VaultNode], client: Option<&Client>) -> Vec<Response> {
+    let mut responses = poll_all(nodes, client);
+    FakeClock::advance_time(ACK_TIMEOUT_SECS * 1000 + 1);
+    responses.extend(poll_all(nodes, client));
+    FakeClock::advance_time(CONNECTING_PEER_TIMEOUT_SECS * 1000 + 1);
+    responses.extend(poll_all(nodes, client));
+    responses
+}
+
+// Sends a request via `send` and returns the single response it yields.
+fn request<F>(nodes: &mut [VaultNode], client: &Client, send: F) -> Response
+    where F: FnOnce(MessageId) -> Result<(), InterfaceError>
+{
+    let id = MessageId::new();
+    unwrap!(send(id));
+    let mut responses = poll_and_resend(nodes, Some(client));
+    assert_eq!(responses.len(), 1, "Unexpected responses: {:?}", responses);
+    let response = unwrap!(responses.pop());
+    assert_eq!(response.message_id(), id);
+    response
+}
+
+#[test]
+fn key_value_vault() {
+    let network = Network::new(MIN_SECTION_SIZE, Some(SEED));
+
+    let mut nodes = vec![VaultNode::new(&network, None, true)];
+    let config = Config::with_contacts(&[nodes[0].handle.endpoint()]);
+    for _ in 0..MIN_SECTION_SIZE {
+        nodes.push(VaultNode::new(&network, Some(config.clone()), false));
+        let _ = poll_and_resend(&mut nodes, None);
+    }
+    for node in &nodes {
+        assert_eq!(unwrap!(node.node.routing_table()).len(), MIN_SECTION_SIZE);
+    }
+
+    let handle = network.new_service_handle(Some(config), None);
+    let client =
+        mock_crust::make_current(&handle, || unwrap!(Client::new(None, MIN_SECTION_SIZE)));
+    let _ = poll_all(&mut nodes, Some(&client));
+
+    // Put immutable data, then read it back.
+    let data = Data::Immutable(ImmutableData::new(b"Hello, vault!".to_vec()));
+    let dst = Authority::NaeManager(*data.name());
+    match request(&mut nodes, &client, |id| client.send_put_request(dst, data.clone(), id)) {
+        Response::PutSuccess(data_id, _) => assert_eq!(data_id, data.identifier()),
+        response => panic!("Unexpected response: {:?}", response),
+    }
+    match request(&mut nodes,
+                  &client,
+                  |id| client.send_get_request(dst, data.identifier(), id)) {
+        Response::GetSuccess(got, _) => assert_eq!(got, data),
+        response => panic!("Unexpected response: {:?}", response),
+    }
+
+    // Putting it again fails.
+    match request(&mut nodes, &client, |id| client.send_put_request(dst, data.clone(), id)) {
+        Response::PutFailure { external_error_indicator, .. } => {
+            assert_eq!(external_error_indicator, b"exists".to_vec())
+        }
+        response => panic!("Unexpected response: {:?}", response),
+    }
+
+    // Structured data can only be updated once it exists.
+    let name = XorName([7; XOR_NAME_LEN]);
+    let dst = Authority::NaeManager(name);
+    let version_0 = Data::Structured(unwrap!(StructuredData::new(TYPE_TAG,
+                                                                 name,
+                                                                 0,
+                                                                 b"v0".to_vec(),
+                                                                 BTreeSet::new())));
+    let version_1 = Data::Structured(unwrap!(StructuredData::new(TYPE_TAG,
+                                                                 name,
+                                                                 1,
+                                                                 b"v1".to_vec(),
+                                                                 BTreeSet::new())));
+    match request(&mut nodes,
+                  &client,
+                  |id| client.send_post_request(dst, version_1.clone(), id)) {
+        Response::PostFailure { external_error_indicator, .. } => {
+            assert_eq!(external_error_indicator, b"missing".to_vec())
+        }
+        response => panic!("Unexpected response: {:?}", response),
+    }
+    match request(&mut nodes,
+                  &client,
+                  |id| client.send_put_request(dst, version_0.clone(), id)) {
+        Response::PutSuccess(..) => (),
+        response => panic!("Unexpected response: {:?}", response),
+    }
+    match request(&mut nodes,
+                  &client,
+                  |id| client.send_post_request(dst, version_1.clone(), id)) {
+        Response::PostSuccess(..) => (),
+        response => panic!("Unexpected response: {:?}", response),
+    }
+    match request(&mut nodes,
+                  &client,
+                  |id| client.send_get_request(dst, version_1.identifier(), id)) {
+        Response::GetSuccess(got, _) => assert_eq!(got, version_1),
+        response => panic!("Unexpected response: {:?}", response),
+    }
+
+    // Data that was never stored can't be retrieved.
+    let missing = DataIdentifier::Immutable(XorName([9; XOR_NAME_LEN]));
+    let dst = Authority::NaeManager(*missing.name());
+    match request(&mut nodes, &client, |id| client.send_get_request(dst, missing, id)) {
+        Response::GetFailure { data_id, .. } => assert_eq!(data_id, missing),
+        response => panic!("Unexpected response: {:?}", response),
+    }
+}